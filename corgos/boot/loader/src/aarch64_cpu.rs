@@ -0,0 +1,65 @@
+//! The aarch64 processor state for the kernel.
+//!
+//! The firmware vectors live in the boot services memory the kernel is free
+//! to reuse. The loader points `VBAR_EL1` to its own vectors that log the
+//! exception and the registers before panicking. Those live in the loader
+//! image, so the kernel must install its vectors before reclaiming the
+//! loader memory. The interrupts are masked and the generic timers are
+//! stopped so nothing arrives before the kernel is ready.
+
+use crate::aarch64_regs::access::Aarch64Register;
+use crate::aarch64_regs::CurrentEl;
+use crate::aarch64_regs::El;
+use exceptions::aarch64::Esr;
+use exceptions::aarch64::Exception;
+use exceptions::aarch64::ExceptionFrame;
+use exceptions::aarch64::ExceptionHandlers;
+use exceptions::aarch64::ExceptionKind;
+
+fn loader_fault(exception: Exception, frame: &mut ExceptionFrame, esr: Esr) {
+    if exception.kind == ExceptionKind::Synchronous && crate::gdb_stub::stop(frame) {
+        return;
+    }
+    log::error!("{exception:?}, {esr:x?}, {frame:#x?}");
+    panic!(
+        "Exception {:?} ({:?}) at {:#016x}, FAR {:#016x}",
+        exception.kind,
+        esr.class(),
+        frame.elr,
+        frame.far
+    );
+}
+
+static HANDLERS: ExceptionHandlers = ExceptionHandlers {
+    synchronous: loader_fault,
+    irq: loader_fault,
+    fiq: loader_fault,
+    serror: loader_fault,
+};
+
+/// Puts the processor into the state the kernel expects. Must be called
+/// after exiting the boot services as the firmware relies on its own
+/// exception vectors until then.
+pub fn prepare_for_kernel() {
+    // The loader does not rely on interrupts past this point.
+    crate::interrupts::mask();
+    crate::timer::stop_generic_timers();
+
+    let mut current_el = CurrentEl::new();
+    current_el.load();
+    if !matches!(current_el.el(), El::EL1) {
+        log::warn!(
+            "Running at {:?}, keeping the firmware exception vectors",
+            current_el.el()
+        );
+        return;
+    }
+
+    // SAFETY: running at EL1 with the boot services gone.
+    unsafe { exceptions::aarch64::install(&HANDLERS) };
+
+    log::info!(
+        "Installed the loader exception vectors at {:#016x}, interrupts masked",
+        exceptions::aarch64::vector_table()
+    );
+}
@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 
 use bitfield_struct::bitfield;
+pub use page_tables::aarch64::PageBlockEntry;
+pub use page_tables::aarch64::PageTableEntry;
 
 #[derive(Debug)]
 #[repr(u64)]
@@ -37,6 +39,27 @@ pub struct CurrentEl {
     _mbz1: u64,
 }
 
+/// `DAIF`, the debug, SError, IRQ, and FIQ exceptions are masked when set.
+#[bitfield(u64, default = false)]
+pub struct ExceptionMask {
+    #[bits(6)]
+    _mbz0: u64,
+    pub f: bool,
+    pub i: bool,
+    pub a: bool,
+    pub d: bool,
+    #[bits(54)]
+    _mbz1: u64,
+}
+
+/// `SPSel`, whether `SP_ELx` rather than `SP_EL0` is the stack pointer.
+#[bitfield(u64, default = false)]
+pub struct StackPointerSelect {
+    pub sp_elx: bool,
+    #[bits(63)]
+    _mbz0: u64,
+}
+
 #[bitfield(u64, default = false)]
 pub struct SystemControlEl1 {
     #[bits(1)]
@@ -258,6 +281,32 @@ pub struct MainIdEl1 {
     _mbz0: u64,
 }
 
+#[bitfield(u64, default = false)]
+pub struct MultiprocessorAffinityEl1 {
+    #[bits(8)]
+    pub aff0: u64,
+    #[bits(8)]
+    pub aff1: u64,
+    #[bits(8)]
+    pub aff2: u64,
+    pub mt: bool,
+    #[bits(5)]
+    _mbz0: u64,
+    pub u: bool,
+    _mbo0: bool,
+    #[bits(8)]
+    pub aff3: u64,
+    #[bits(24)]
+    _mbz1: u64,
+}
+
+impl MultiprocessorAffinityEl1 {
+    /// The affinity fields as laid out in the ACPI GICC structure.
+    pub fn affinity(&self) -> u64 {
+        self.into_bits() & 0xff_00ff_ffff
+    }
+}
+
 #[bitfield(u64, default = false)]
 pub struct ProcessorFeatures0El1 {
     #[bits(4)]
@@ -351,6 +400,10 @@ impl MemoryAttributeIndirectionEl1 {
     pub fn get_index(&self, a: MemoryAttributeEl1) -> Option<usize> {
         self.0.iter().position(|&x| x == a as u8)
     }
+
+    pub fn attribute(&self, index: usize) -> u8 {
+        self.0[index]
+    }
 }
 
 impl Default for MemoryAttributeIndirectionEl1 {
@@ -369,11 +422,11 @@ impl Default for MemoryAttributeIndirectionEl1 {
 }
 
 impl MemoryAttributeIndirectionEl1 {
-    const fn into_bits(self) -> u64 {
+    pub const fn into_bits(self) -> u64 {
         u64::from_le_bytes(self.0)
     }
 
-    const fn from_bits(bits: u64) -> Self {
+    pub const fn from_bits(bits: u64) -> Self {
         Self(bits.to_le_bytes())
     }
 }
@@ -936,57 +989,299 @@ pub struct MmFeatures4El1 {
 }
 
 #[bitfield(u64, default = false)]
-pub struct PageTableEntry {
-    pub valid: bool,
-    pub table: bool, // Use PageBlockEntry if `false`
-    #[bits(10)]
+pub struct IsaFeatures0El1 {
+    #[bits(4)]
     _mbz0: u64,
-    #[bits(35)]
-    pub next_table_pfn: u64,
-    #[bits(12)]
-    _mbz1: u64,
-    pub priv_x_never: bool,
-    pub user_x_never: bool,
-    // NoEffect = 0b00,
-    // PrivOnly = 0b01,
-    // ReadOnly = 0b10,
-    // PrivReadOnly = 0b11
-    #[bits(2)]
-    pub access_perm: u64,
-    pub non_secure: bool,
+    #[bits(4)]
+    pub aes: u64,
+    #[bits(4)]
+    pub sha1: u64,
+    #[bits(4)]
+    pub sha2: u64,
+    #[bits(4)]
+    pub crc32: u64,
+    #[bits(4)]
+    pub atomic: u64,
+    #[bits(4)]
+    pub tme: u64,
+    #[bits(4)]
+    pub rdm: u64,
+    #[bits(4)]
+    pub sha3: u64,
+    #[bits(4)]
+    pub sm3: u64,
+    #[bits(4)]
+    pub sm4: u64,
+    #[bits(4)]
+    pub dp: u64,
+    #[bits(4)]
+    pub fhm: u64,
+    #[bits(4)]
+    pub ts: u64,
+    #[bits(4)]
+    pub tlb: u64,
+    #[bits(4)]
+    pub rndr: u64,
 }
 
 #[bitfield(u64, default = false)]
-pub struct PageBlockEntry {
-    pub valid: bool,
-    pub page: bool,
-    #[bits(3)]
-    pub mair_idx: usize,
-    #[bits(1)]
+pub struct IsaFeatures1El1 {
+    #[bits(4)]
+    pub dpb: u64,
+    #[bits(4)]
+    pub apa: u64,
+    #[bits(4)]
+    pub api: u64,
+    #[bits(4)]
+    pub jscvt: u64,
+    #[bits(4)]
+    pub fcma: u64,
+    #[bits(4)]
+    pub lrcpc: u64,
+    #[bits(4)]
+    pub gpa: u64,
+    #[bits(4)]
+    pub gpi: u64,
+    #[bits(4)]
+    pub frintts: u64,
+    #[bits(4)]
+    pub sb: u64,
+    #[bits(4)]
+    pub specres: u64,
+    #[bits(4)]
+    pub bf16: u64,
+    #[bits(4)]
+    pub dgh: u64,
+    #[bits(4)]
+    pub i8mm: u64,
+    #[bits(4)]
+    pub xs: u64,
+    #[bits(4)]
+    pub ls64: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct IsaFeatures2El1 {
+    #[bits(4)]
+    pub wfxt: u64,
+    #[bits(4)]
+    pub rpres: u64,
+    #[bits(4)]
+    pub gpa3: u64,
+    #[bits(4)]
+    pub apa3: u64,
+    #[bits(4)]
+    pub mops: u64,
+    #[bits(4)]
+    pub bc: u64,
+    #[bits(4)]
+    pub pac_frac: u64,
+    #[bits(4)]
+    pub clrbhb: u64,
+    #[bits(4)]
+    pub sysreg_128: u64,
+    #[bits(4)]
+    pub sysinstr_128: u64,
+    #[bits(4)]
+    pub prfmslc: u64,
+    #[bits(4)]
+    _mbz0: u64,
+    #[bits(4)]
+    pub rprfm: u64,
+    #[bits(4)]
+    pub cssc: u64,
+    #[bits(4)]
+    pub lut: u64,
+    #[bits(4)]
+    pub ats1a: u64,
+}
+
+/// Whether the accesses to the SVE, the FP/SIMD, and the SME registers
+/// trap, the same encoding for the three.
+#[derive(Debug)]
+#[repr(u64)]
+pub enum AccessTrap {
+    /// Both EL0 and EL1 accesses trap.
+    All = 0b00,
+    /// Only EL0 accesses trap.
+    El0 = 0b01,
+    /// Both EL0 and EL1 accesses trap, same as `All`.
+    All2 = 0b10,
+    /// Nothing traps.
+    None = 0b11,
+}
+
+impl AccessTrap {
+    const fn into_bits(self) -> u64 {
+        self as u64
+    }
+
+    const fn from_bits(bits: u64) -> Self {
+        match bits {
+            0b00 => AccessTrap::All,
+            0b01 => AccessTrap::El0,
+            0b10 => AccessTrap::All2,
+            _ => AccessTrap::None,
+        }
+    }
+}
+
+#[bitfield(u64, default = false)]
+pub struct ArchFeatureAccessControlEl1 {
+    #[bits(16)]
     _mbz0: u64,
-    // PrivOnly = 0b00,
-    // ReadWrite = 0b01,
-    // PrivReadOnly = 0b10,
-    // ReadOnly = 0b11
     #[bits(2)]
-    pub access_perm: u64,
-    // NonShareable = 0b00,
-    // OuterShareable = 0b10,
-    // InnerShareable = 0b11
+    pub zen: AccessTrap,
     #[bits(2)]
-    pub share_perm: u64,
-    pub accessed: bool,
-    pub not_global: bool,
-    #[bits(35)]
-    pub address_pfn: u64,
-    #[bits(4)]
     _mbz1: u64,
-    pub dirty: bool,
-    pub contig: bool,
-    pub priv_x_never: bool,
-    pub user_x_never: bool,
-    #[bits(9)]
+    #[bits(2)]
+    pub fpen: AccessTrap,
+    #[bits(2)]
+    _mbz2: u64,
+    #[bits(2)]
+    pub smen: AccessTrap,
+    #[bits(2)]
+    _mbz3: u64,
+    pub tta: bool,
+    /// `E0POE`, `TAM` and `TCPAC` are for the features the loader does not
+    /// use, and trap or disable more when set.
+    #[bits(3)]
+    _mbz4: u64,
+    #[bits(32)]
+    _mbz5: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct CounterFrequencyEl0 {
+    #[bits(32)]
+    pub frequency: u64,
+    #[bits(32)]
+    _mbz0: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct VirtualCountEl0 {
+    #[bits(64)]
+    pub count: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct PhysicalCountEl0 {
+    #[bits(64)]
+    pub count: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct PhysicalTimerControlEl0 {
+    pub enable: bool,
+    /// Masks the timer interrupt, the status is still updated.
+    pub imask: bool,
+    /// Read-only, the timer condition is met.
+    pub istatus: bool,
+    #[bits(61)]
+    _mbz0: u64,
+}
+
+/// The ticks left until the timer condition is met, negative once it is.
+/// Writing sets the compare value to the count plus the value.
+#[bitfield(u64, default = false)]
+pub struct PhysicalTimerValueEl0 {
+    #[bits(32)]
+    pub value: i32,
+    #[bits(32)]
+    _mbz0: u64,
+}
+
+/// The timer condition is met when the count reaches the compare value.
+#[bitfield(u64, default = false)]
+pub struct PhysicalTimerCompareEl0 {
+    #[bits(64)]
+    pub compare: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct VirtualTimerControlEl0 {
+    pub enable: bool,
+    /// Masks the timer interrupt, the status is still updated.
+    pub imask: bool,
+    /// Read-only, the timer condition is met.
+    pub istatus: bool,
+    #[bits(61)]
+    _mbz0: u64,
+}
+
+/// The ticks left until the timer condition is met, negative once it is.
+/// Writing sets the compare value to the count plus the value.
+#[bitfield(u64, default = false)]
+pub struct VirtualTimerValueEl0 {
+    #[bits(32)]
+    pub value: i32,
+    #[bits(32)]
+    _mbz0: u64,
+}
+
+/// The timer condition is met when the virtual count reaches the compare
+/// value.
+#[bitfield(u64, default = false)]
+pub struct VirtualTimerCompareEl0 {
+    #[bits(64)]
+    pub compare: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct CounterKernelControlEl1 {
+    pub el0pcten: bool,
+    pub el0vcten: bool,
+    pub evnten: bool,
+    pub evntdir: bool,
+    #[bits(4)]
+    pub evnti: u64,
+    pub el0vten: bool,
+    pub el0pten: bool,
+    #[bits(7)]
+    _mbz0: u64,
+    pub evntis: bool,
+    #[bits(46)]
+    _mbz1: u64,
+}
+
+#[bitfield(u64, default = false)]
+pub struct MonitorDebugSystemControlEl1 {
+    pub ss: bool,
+    #[bits(5)]
+    _mbz0: u64,
+    pub err: bool,
+    #[bits(5)]
+    _mbz1: u64,
+    pub tdcc: bool,
+    pub kde: bool,
+    pub hde: bool,
+    pub mde: bool,
+    #[bits(3)]
     _mbz2: u64,
+    pub sc2: bool,
+    _mbz3: bool,
+    pub tda: bool,
+    #[bits(2)]
+    pub intdis: u64,
+    #[bits(2)]
+    _mbz4: u64,
+    pub txu: bool,
+    pub rxo: bool,
+    _mbz5: bool,
+    pub txfull: bool,
+    pub rxfull: bool,
+    #[bits(33)]
+    _mbz6: u64,
+}
+
+/// Write-only, reading it is undefined.
+#[bitfield(u64, default = false)]
+pub struct OsLockAccessEl1 {
+    /// Locks the debug registers against the external debugger.
+    pub oslk: bool,
+    #[bits(63)]
+    _mbz0: u64,
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -1021,7 +1316,62 @@ pub mod access {
         fn bits(&self) -> u64;
     }
 
-    macro_rules! impl_register_access {
+    /// The value has the reserved bits set, and has not been written.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ReservedBitsSet {
+        pub register: &'static str,
+        pub bits: u64,
+    }
+
+    /// The registers that can be written at any time. The read-only ones do
+    /// not implement this.
+    pub trait WritableRegister {
+        /// The bits that are reserved, and must be written as zero.
+        const RESERVED: u64;
+
+        fn store(&mut self);
+        /// Writes the register unless a reserved bit is set.
+        fn try_store(&mut self) -> Result<(), ReservedBitsSet>;
+    }
+
+    /// The registers that control the translation and the caches, writing
+    /// them changes how the running code sees the memory.
+    pub trait SystemControlRegister {
+        /// The bits that are reserved, and must be written as zero.
+        const RESERVED: u64;
+
+        fn store(&mut self, token: &SystemControlToken);
+        /// Writes the register unless a reserved bit is set.
+        fn try_store(&mut self, token: &SystemControlToken) -> Result<(), ReservedBitsSet>;
+    }
+
+    /// The permission to write a [`SystemControlRegister`].
+    pub struct SystemControlToken(());
+
+    impl SystemControlToken {
+        /// # Safety
+        ///
+        /// The values written keep the code running: the translation tables,
+        /// the memory attributes, and the caches stay consistent with the
+        /// memory the code and the stack are in.
+        pub unsafe fn new() -> Self {
+            Self(())
+        }
+    }
+
+    macro_rules! check_reserved {
+        ($register:ident, $val:expr) => {{
+            let bits = $val & Self::RESERVED;
+            if bits != 0 {
+                return Err(ReservedBitsSet {
+                    register: stringify!($register),
+                    bits,
+                });
+            }
+        }};
+    }
+
+    macro_rules! impl_register_load {
         ($register_type:ident, $register:ident) => {
             impl Aarch64Register for $register_type {
                 fn load(&mut self) {
@@ -1037,36 +1387,70 @@ pub mod access {
                     (*self).into_bits()
                 }
             }
+        };
+    }
+
+    macro_rules! impl_register_store {
+        ($register_type:ident, $register:ident, $reserved:expr) => {
+            impl WritableRegister for $register_type {
+                const RESERVED: u64 = $reserved;
 
-            impl $register_type {
-                pub fn store(&mut self) {
+                fn store(&mut self) {
                     let val: u64 = (*self).into_bits();
                     store_sys_reg!($register, val)
                 }
+
+                fn try_store(&mut self) -> Result<(), ReservedBitsSet> {
+                    check_reserved!($register, (*self).into_bits());
+                    self.store();
+                    Ok(())
+                }
             }
         };
     }
 
-    macro_rules! impl_register_access_ro {
-        ($register_type:ident, $register:ident) => {
-            impl Aarch64Register for $register_type {
-                fn load(&mut self) {
-                    let val: u64 = load_sys_reg!($register).into();
-                    *self = Self::from(val);
-                }
+    macro_rules! impl_register_access {
+        ($register_type:ident, $register:ident, $reserved:expr) => {
+            impl_register_load!($register_type, $register);
+            impl_register_store!($register_type, $register, $reserved);
+        };
+    }
 
-                fn name(&self) -> &'static str {
-                    stringify!($register)
+    macro_rules! impl_register_access_sys {
+        ($register_type:ident, $register:ident, $reserved:expr) => {
+            impl_register_load!($register_type, $register);
+
+            impl SystemControlRegister for $register_type {
+                const RESERVED: u64 = $reserved;
+
+                fn store(&mut self, _token: &SystemControlToken) {
+                    let val: u64 = (*self).into_bits();
+                    store_sys_reg!($register, val)
                 }
 
-                fn bits(&self) -> u64 {
-                    (*self).into()
+                fn try_store(&mut self, token: &SystemControlToken) -> Result<(), ReservedBitsSet> {
+                    check_reserved!($register, (*self).into_bits());
+                    self.store(token);
+                    Ok(())
                 }
             }
         };
     }
 
+    macro_rules! impl_register_access_ro {
+        ($register_type:ident, $register:ident) => {
+            impl_register_load!($register_type, $register);
+        };
+    }
+
+    macro_rules! impl_register_access_wo {
+        ($register_type:ident, $register:ident, $reserved:expr) => {
+            impl_register_store!($register_type, $register, $reserved);
+        };
+    }
+
     impl_register_access_ro!(MainIdEl1, MIDR_EL1);
+    impl_register_access_ro!(MultiprocessorAffinityEl1, MPIDR_EL1);
     impl_register_access_ro!(ProcessorFeatures0El1, ID_AA64PFR0_EL1);
     impl_register_access_ro!(ProcessorFeatures1El1, ID_AA64PFR1_EL1);
     impl_register_access_ro!(MmFeatures0El1, ID_AA64MMFR0_EL1);
@@ -1074,18 +1458,46 @@ pub mod access {
     impl_register_access_ro!(MmFeatures2El1, ID_AA64MMFR2_EL1);
     impl_register_access_ro!(MmFeatures3El1, ID_AA64MMFR3_EL1);
     impl_register_access_ro!(MmFeatures4El1, ID_AA64MMFR4_EL1);
+    impl_register_access_ro!(IsaFeatures0El1, ID_AA64ISAR0_EL1);
+    impl_register_access_ro!(IsaFeatures1El1, ID_AA64ISAR1_EL1);
+    impl_register_access_ro!(IsaFeatures2El1, ID_AA64ISAR2_EL1);
+    impl_register_access_ro!(CounterFrequencyEl0, CNTFRQ_EL0);
+    impl_register_access_ro!(VirtualCountEl0, CNTVCT_EL0);
+    impl_register_access_ro!(PhysicalCountEl0, CNTPCT_EL0);
 
     impl_register_access_ro!(CurrentEl, CurrentEL);
 
-    impl_register_access!(SystemControlEl1, SCTLR_EL1);
-    impl_register_access!(VectorBaseEl1, VBAR_EL1);
-    impl_register_access!(ExceptionLinkEl1, ELR_EL1);
-    impl_register_access!(ExceptionSyndromeEl1, ESR_EL1);
-    impl_register_access!(SavedProgramStateEl1, SPSR_EL1);
-    impl_register_access!(TranslationControlEl1, TCR_EL1);
-    impl_register_access!(TranslationBase0El1, TTBR0_EL1);
-    impl_register_access!(TranslationBase1El1, TTBR1_EL1);
-    impl_register_access!(MemoryAttributeIndirectionEl1, MAIR_EL1);
+    impl_register_access_sys!(SystemControlEl1, SCTLR_EL1, 0x0000_0004_0002_0000);
+    impl_register_access_sys!(TranslationControlEl1, TCR_EL1, 0xc000_0008_0000_0040);
+    impl_register_access_sys!(TranslationBase0El1, TTBR0_EL1, 0);
+    impl_register_access_sys!(TranslationBase1El1, TTBR1_EL1, 0);
+    impl_register_access_sys!(MemoryAttributeIndirectionEl1, MAIR_EL1, 0);
+    impl_register_access_sys!(StackPointerSelect, SPSel, 0xffff_ffff_ffff_fffe);
+
+    impl_register_access!(ExceptionMask, DAIF, 0xffff_ffff_ffff_fc3f);
+    impl_register_access!(VectorBaseEl1, VBAR_EL1, 0x7ff);
+    impl_register_access!(ExceptionLinkEl1, ELR_EL1, 0);
+    impl_register_access!(ExceptionSyndromeEl1, ESR_EL1, 0);
+    impl_register_access!(SavedProgramStateEl1, SPSR_EL1, 0xffff_ffff_ffff_fc20);
+    impl_register_access!(
+        ArchFeatureAccessControlEl1,
+        CPACR_EL1,
+        0xffff_ffff_eccc_ffff
+    );
+    impl_register_access!(CounterKernelControlEl1, CNTKCTL_EL1, 0xffff_ffff_fffd_fc00);
+    impl_register_access!(
+        MonitorDebugSystemControlEl1,
+        MDSCR_EL1,
+        0xffff_ffff_9317_0fbe
+    );
+    impl_register_access!(PhysicalTimerControlEl0, CNTP_CTL_EL0, 0xffff_ffff_ffff_fff8);
+    impl_register_access!(PhysicalTimerValueEl0, CNTP_TVAL_EL0, 0xffff_ffff_0000_0000);
+    impl_register_access!(PhysicalTimerCompareEl0, CNTP_CVAL_EL0, 0);
+    impl_register_access!(VirtualTimerControlEl0, CNTV_CTL_EL0, 0xffff_ffff_ffff_fff8);
+    impl_register_access!(VirtualTimerValueEl0, CNTV_TVAL_EL0, 0xffff_ffff_0000_0000);
+    impl_register_access!(VirtualTimerCompareEl0, CNTV_CVAL_EL0, 0);
+
+    impl_register_access_wo!(OsLockAccessEl1, OSLAR_EL1, 0xffff_ffff_ffff_fffe);
 
     #[macro_export]
     macro_rules! register {
@@ -1094,3 +1506,59 @@ pub mod access {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::access::SystemControlRegister;
+    use super::access::WritableRegister;
+    use super::*;
+
+    /// The fields that can be set cover all the bits but the reserved ones.
+    #[test]
+    fn reserved_bits() {
+        let cpacr = ArchFeatureAccessControlEl1::new()
+            .with_zen(AccessTrap::None)
+            .with_fpen(AccessTrap::None)
+            .with_smen(AccessTrap::None)
+            .with_tta(true);
+        assert_eq!(
+            cpacr.into_bits(),
+            !<ArchFeatureAccessControlEl1 as WritableRegister>::RESERVED
+        );
+
+        let daif = ExceptionMask::new()
+            .with_f(true)
+            .with_i(true)
+            .with_a(true)
+            .with_d(true);
+        assert_eq!(
+            daif.into_bits(),
+            !<ExceptionMask as WritableRegister>::RESERVED
+        );
+
+        let spsel = StackPointerSelect::new().with_sp_elx(true);
+        assert_eq!(
+            spsel.into_bits(),
+            !<StackPointerSelect as SystemControlRegister>::RESERVED
+        );
+
+        let cntv_ctl = VirtualTimerControlEl0::new()
+            .with_enable(true)
+            .with_imask(true)
+            .with_istatus(true);
+        assert_eq!(
+            cntv_ctl.into_bits(),
+            !<VirtualTimerControlEl0 as WritableRegister>::RESERVED
+        );
+    }
+
+    #[test]
+    fn cpacr_enables_fp() {
+        let cpacr = ArchFeatureAccessControlEl1::new().with_fpen(AccessTrap::None);
+        assert_eq!(cpacr.into_bits(), 0b11 << 20);
+        assert_eq!(
+            cpacr.into_bits() & <ArchFeatureAccessControlEl1 as WritableRegister>::RESERVED,
+            0
+        );
+    }
+}
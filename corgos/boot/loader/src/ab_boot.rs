@@ -0,0 +1,178 @@
+//! A/B kernel selection.
+//!
+//! The loader counts the boot attempts of the primary kernel in a UEFI
+//! variable. The kernel marks a successful boot by deleting the variable
+//! (or setting it to `0`) through the runtime services. If that has not
+//! happened within `max_boot_attempts` boots, the fallback kernel is
+//! selected until the counter is reset. The attempts are counted without
+//! a fallback kernel too: the kernel that has been attempted when the
+//! counter is found reset is remembered as the last good one, and is the
+//! fallback if none is configured.
+//!
+//! A kernel that hangs before resetting the counter has failed the boot
+//! once the machine is reset, by hand or by a watchdog of the board: the
+//! UEFI watchdog stops with the boot services, so it cannot catch that.
+//! After `recovery_boot_attempts` failed boots the loader stops trying
+//! either kernel and boots the `recovery` boot menu entry, e.g. a known
+//! good kernel with verbose logging, without showing the menu, and logs at
+//! `recovery_log_level` itself.
+
+use crate::settings;
+use crate::settings::Setting;
+use crate::settings::MAX_SETTING_SIZE;
+use boot_logger::BootLoaderConfig;
+use boot_logger::MAX_PATH_SIZE;
+use boot_logger::RECOVERY_ENTRY_NAME;
+
+fn read_boot_attempts() -> u8 {
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    settings::read(Setting::BootAttempts, &mut buf)
+        .and_then(|value| value.first().copied())
+        .unwrap_or_default()
+}
+
+/// The counter has been reset since the last attempt, so the kernel that
+/// was attempted has booted.
+fn record_last_good_kernel() {
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    if let Some(kernel) = settings::read(Setting::AttemptedKernel, &mut buf) {
+        settings::write(Setting::LastGoodKernel, kernel);
+        settings::delete(Setting::AttemptedKernel);
+    }
+}
+
+/// Falls back to the kernel that has booted successfully last time if
+/// there is no fallback kernel configured, and it is not the primary one.
+pub fn apply_last_good_kernel(config: &mut BootLoaderConfig) {
+    if config.kernel_b_str().is_some() {
+        return;
+    }
+
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    let Some(kernel) = settings::read_str(Setting::LastGoodKernel, &mut buf) else {
+        return;
+    };
+    if kernel == config.kernel_a_str() || kernel.len() >= MAX_PATH_SIZE {
+        return;
+    }
+
+    log::info!("The last good kernel '{kernel}' is the fallback");
+    config.kernel_b = [0; MAX_PATH_SIZE];
+    config.kernel_b[..kernel.len()].copy_from_slice(kernel.as_bytes());
+}
+
+/// Switches to the recovery menu entry after too many failed boots.
+/// Returns `true` if switched.
+pub fn apply_recovery(config: &mut BootLoaderConfig) -> bool {
+    let attempts = read_boot_attempts();
+    if !recovery_due(attempts, config.recovery_boot_attempts) {
+        return false;
+    }
+
+    let Some(entry) = config
+        .boot_entries
+        .iter()
+        .find(|entry| entry.is_used() && entry.name_str() == RECOVERY_ENTRY_NAME)
+        .copied()
+    else {
+        log::warn!(
+            "{attempts} failed boots, yet no '{RECOVERY_ENTRY_NAME}' boot menu entry to recover with"
+        );
+        return false;
+    };
+
+    config.log_level = config.recovery_log_level;
+    log::set_max_level(config.log_level);
+    log::warn!("{attempts} failed boots, booting the '{RECOVERY_ENTRY_NAME}' boot menu entry");
+
+    if !entry.kernel_str().is_empty() {
+        config.kernel_a = entry.kernel;
+    }
+    config.kernel_b = [0; MAX_PATH_SIZE];
+    config.cmdline = entry.cmdline;
+
+    true
+}
+
+/// Whether `attempts` failed boots call for the recovery entry.
+fn recovery_due(attempts: u8, recovery_boot_attempts: u8) -> bool {
+    recovery_boot_attempts != 0 && attempts >= recovery_boot_attempts
+}
+
+/// Whether the boot attempt after `attempts` failed ones goes to the
+/// fallback kernel, if there is one.
+fn fallback_due(attempts: u8, max_boot_attempts: u8) -> bool {
+    attempts >= max_boot_attempts
+}
+
+fn write_attempted_kernel(kernel: &str) {
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    if settings::read(Setting::AttemptedKernel, &mut buf) != Some(kernel.as_bytes()) {
+        settings::write(Setting::AttemptedKernel, kernel.as_bytes());
+    }
+}
+
+/// Picks the kernel image to boot, and accounts for the boot attempt,
+/// whichever kernel it goes to.
+pub fn select_kernel(config: &BootLoaderConfig) -> &str {
+    let kernel_a = config.kernel_a_str();
+    let attempts = read_boot_attempts();
+    if attempts == 0 {
+        record_last_good_kernel();
+    }
+
+    let kernel = match config.kernel_b_str() {
+        Some(kernel_b) if fallback_due(attempts, config.max_boot_attempts) => {
+            log::warn!(
+                "Kernel '{kernel_a}' did not boot successfully in {attempts} attempts, falling back to '{kernel_b}'"
+            );
+            kernel_b
+        }
+        Some(_) => {
+            log::info!(
+                "Booting kernel '{kernel_a}', attempt {} of {}",
+                attempts + 1,
+                config.max_boot_attempts
+            );
+            kernel_a
+        }
+        None => kernel_a,
+    };
+
+    settings::write(Setting::BootAttempts, &[attempts.saturating_add(1)]);
+    write_attempted_kernel(kernel);
+
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use boot_logger::DEFAULT_MAX_BOOT_ATTEMPTS;
+    use boot_logger::DEFAULT_RECOVERY_BOOT_ATTEMPTS;
+
+    #[test]
+    fn failed_boots_reach_recovery() {
+        let mut attempts = 0_u8;
+        let mut boots = [""; DEFAULT_RECOVERY_BOOT_ATTEMPTS as usize + 1];
+        for boot in &mut boots {
+            if recovery_due(attempts, DEFAULT_RECOVERY_BOOT_ATTEMPTS) {
+                *boot = "recovery";
+                break;
+            }
+            *boot = if fallback_due(attempts, DEFAULT_MAX_BOOT_ATTEMPTS) {
+                "b"
+            } else {
+                "a"
+            };
+            attempts = attempts.saturating_add(1);
+        }
+        assert_eq!(boots, ["a", "a", "a", "b", "b", "b", "recovery"]);
+    }
+
+    #[test]
+    fn no_recovery_if_disabled() {
+        assert!(!recovery_due(u8::MAX, 0));
+        assert!(recovery_due(u8::MAX, DEFAULT_RECOVERY_BOOT_ATTEMPTS));
+    }
+}
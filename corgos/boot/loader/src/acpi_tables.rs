@@ -0,0 +1,35 @@
+//! Access to the ACPI tables.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use acpi::AcpiHandler;
+use acpi::AcpiTables;
+use acpi::PhysicalMapping;
+use core::ptr::NonNull;
+
+/// The firmware identity-maps the memory before the boot services exit,
+/// so mapping a table is just taking its address.
+#[derive(Clone, Copy)]
+pub struct IdentityMapped;
+
+impl AcpiHandler for IdentityMapped {
+    unsafe fn map_physical_region<T>(
+        &self,
+        physical_address: usize,
+        size: usize,
+    ) -> PhysicalMapping<Self, T> {
+        let virtual_address =
+            NonNull::new(physical_address as *mut T).expect("Must not map the NULL page");
+        // SAFETY: the memory is identity-mapped.
+        unsafe { PhysicalMapping::new(physical_address, virtual_address, size, size, *self) }
+    }
+
+    fn unmap_physical_region<T>(_region: &PhysicalMapping<Self, T>) {}
+}
+
+/// The ACPI tables rooted at the already validated RSDP.
+pub fn acpi_tables(rsdp_addr: u64) -> AcpiTables<IdentityMapped> {
+    // SAFETY: the RSDP comes from the UEFI configuration table.
+    unsafe { AcpiTables::from_rsdp(IdentityMapped, rsdp_addr as usize) }
+        .or_fail(BootError::Acpi, "Cannot parse the ACPI tables")
+}
@@ -0,0 +1,124 @@
+//! The local APIC mode of the boot processor.
+//!
+//! The address of the xAPIC registers comes from `IA32_APIC_BASE`, and is
+//! compared with the one in the MADT. With `x2apic`, the loader switches
+//! the boot processor to the x2APIC mode after exiting the boot services,
+//! when the firmware no longer sends the IPIs, so that the kernel never
+//! has to map the xAPIC window; otherwise the loader maps it in the device
+//! window for the kernel. The secondary processors come up in the
+//! xAPIC mode after INIT, and the kernel switches them to the mode of the
+//! boot processor as they start.
+
+use crate::paging::PageTables;
+use crate::x86_64_regs::access::X86Register;
+use crate::x86_64_regs::ApicBase;
+use acpi::madt::Madt;
+use acpi::madt::MadtEntry;
+use acpi::AcpiHandler;
+use acpi::AcpiTables;
+use bootinfo::Apic;
+use bootinfo::ApicMode;
+use raw_cpuid::CpuId;
+
+fn mode(apic_base: ApicBase) -> ApicMode {
+    match (apic_base.en(), apic_base.extd()) {
+        (true, true) => ApicMode::X2Apic,
+        (true, false) => ApicMode::XApic,
+        (false, _) => ApicMode::Disabled,
+    }
+}
+
+/// The local APIC address from the MADT, `0` if there is no MADT.
+fn madt_base(tables: &AcpiTables<impl AcpiHandler>) -> u64 {
+    let Ok(madt) = tables.find_table::<Madt>() else {
+        log::warn!("No MADT, cannot find the local APIC address");
+        return 0;
+    };
+
+    let mut base = madt.local_apic_address as u64;
+    for entry in madt.entries() {
+        if let MadtEntry::LocalApicAddressOverride(e) = entry {
+            base = e.local_apic_address;
+        }
+    }
+    base
+}
+
+/// Records the local APIC mode and address of the boot processor.
+pub fn discover_apic(tables: &AcpiTables<impl AcpiHandler>, apic: &mut Apic) {
+    let mut apic_base = ApicBase::new();
+    apic_base.load();
+    *apic = Apic {
+        mode: mode(apic_base),
+        x2apic_supported: CpuId::new()
+            .get_feature_info()
+            .is_some_and(|info| info.has_x2apic()) as u32,
+        apic_base_msr: apic_base.into_bits(),
+        phys_base: apic_base.base_pfn() << 12,
+        madt_base: madt_base(tables),
+        ..Apic::default()
+    };
+
+    log::info!(
+        "Local APIC {:?} @ {:#016x}, x2APIC {}supported{}",
+        apic.mode,
+        apic.phys_base,
+        if apic.x2apic_supported != 0 {
+            ""
+        } else {
+            "not "
+        },
+        if apic_base.bsp() {
+            ", boot processor"
+        } else {
+            ""
+        }
+    );
+    if apic.madt_base != 0 && apic.madt_base != apic.phys_base {
+        log::warn!(
+            "The local APIC address in the MADT {:#016x} differs from IA32_APIC_BASE",
+            apic.madt_base
+        );
+    }
+}
+
+/// The size of the xAPIC register window.
+const XAPIC_REGISTERS_SIZE: u64 = 0x1000;
+
+/// Maps the xAPIC registers in the device window for the kernel to send
+/// the IPIs with, unless the local APIC is in the x2APIC mode already.
+pub fn map_apic(apic: &mut Apic, page_tables: &mut PageTables) {
+    if apic.mode != ApicMode::XApic || apic.phys_base == 0 {
+        return;
+    }
+    apic.virt_base = page_tables.map_device(apic.phys_base, XAPIC_REGISTERS_SIZE);
+    log::info!("Local APIC registers @ {:#016x}", apic.virt_base);
+}
+
+/// Switches the boot processor to the x2APIC mode. Must run with the
+/// interrupts disabled, after exiting the boot services.
+pub fn enable_x2apic(apic: &mut Apic) {
+    match apic.mode {
+        ApicMode::X2Apic => {
+            log::info!("The local APIC is in the x2APIC mode already");
+            return;
+        }
+        ApicMode::Disabled => {
+            log::warn!("The local APIC is disabled, not switching to the x2APIC mode");
+            return;
+        }
+        ApicMode::XApic if apic.x2apic_supported == 0 => {
+            log::warn!("The processor does not support the x2APIC mode");
+            return;
+        }
+        ApicMode::XApic => {}
+    }
+
+    let mut apic_base = ApicBase::from_bits(apic.apic_base_msr).with_extd(true);
+    apic_base.store();
+    apic_base.load();
+    apic.apic_base_msr = apic_base.into_bits();
+    apic.mode = mode(apic_base);
+    apic.x2apic_enabled_by_loader = (apic.mode == ApicMode::X2Apic) as u32;
+    log::info!("Local APIC switched to the {:?} mode", apic.mode);
+}
@@ -0,0 +1,191 @@
+//! The fatal boot errors.
+//!
+//! Each failure point of the loader has a code the error is reported with.
+//! On a machine in the field there is often no serial capture, so besides
+//! the log, the code is drawn in large digits on the screen for one to read
+//! it out or take a picture, and is stored in the [`BOOT_ERROR_VARIABLE`]
+//! UEFI variable for the next boot or the firmware shell to pick up.
+//!
+//! Once the boot services have been exited, there is no UEFI console to
+//! draw on, and the code goes to the log and the variable only. Once the
+//! runtime services have been switched to the kernel addresses, the loader
+//! cannot call them either, and the code goes to the log only.
+
+use crate::settings::CORGOS_VARIABLE_VENDOR;
+use core::fmt::Debug;
+use core::fmt::Write;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use uefi::proto::console::text::Color;
+use uefi::runtime;
+use uefi::runtime::VariableAttributes;
+use uefi::system;
+use uefi::CStr16;
+
+/// The code of the last boot error, a little-endian `u32`.
+pub const BOOT_ERROR_VARIABLE: &CStr16 = uefi::cstr16!("CorgOsBootError");
+
+static RUNTIME_SERVICES_SWITCHED: AtomicBool = AtomicBool::new(false);
+
+/// The rows of the hex digits, 3 pixels wide, the high bit on the left.
+const DIGIT_GLYPHS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+const GLYPH_WIDTH: usize = 3;
+/// The code is drawn as 4 hex digits.
+const CODE_DIGITS: usize = 4;
+
+/// Where the boot has failed. The high byte of the code is the area: the
+/// configuration, the file I/O, the kernel image, its verification, the
+/// memory, the handoff, and the processor.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootError {
+    /// A setting in the configuration is not valid.
+    ConfigInvalid = 0x0101,
+    /// The kernel image cannot be read.
+    KernelRead = 0x0201,
+    /// The kernel image is neither ELF nor PE, or is malformed.
+    KernelFormat = 0x0301,
+    /// The kernel image is built for another architecture.
+    KernelArch = 0x0302,
+    /// The kernel image has nothing to load, or is not page-aligned.
+    KernelLayout = 0x0303,
+    /// The kernel image cannot be relocated.
+    KernelRelocation = 0x0304,
+    /// The kernel image asks for a writable and executable mapping.
+    KernelWx = 0x0305,
+    /// The kernel image SHA-256 does not match.
+    KernelVerify = 0x0401,
+    /// The memory cannot be allocated.
+    OutOfMemory = 0x0501,
+    /// The memory map cannot be read.
+    MemoryMap = 0x0502,
+    /// The ACPI tables the kernel needs are missing or malformed.
+    Acpi = 0x0601,
+    /// The boot info for the kernel cannot be set up.
+    Handoff = 0x0602,
+    /// The processor lacks a feature the kernel requires.
+    CpuFeature = 0x0701,
+}
+
+impl BootError {
+    pub const fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::ConfigInvalid => "invalid configuration",
+            Self::KernelRead => "cannot read the kernel",
+            Self::KernelFormat => "bad kernel image format",
+            Self::KernelArch => "kernel for another architecture",
+            Self::KernelLayout => "bad kernel image layout",
+            Self::KernelRelocation => "cannot relocate the kernel",
+            Self::KernelWx => "writable and executable kernel mapping",
+            Self::KernelVerify => "kernel image corrupted",
+            Self::OutOfMemory => "out of memory",
+            Self::MemoryMap => "cannot get the memory map",
+            Self::Acpi => "bad ACPI tables",
+            Self::Handoff => "cannot prepare the kernel handoff",
+            Self::CpuFeature => "unsupported processor",
+        }
+    }
+
+    /// Reports the error on the screen, in the log, and in the UEFI variable,
+    /// and stops.
+    pub fn fail(self, details: core::fmt::Arguments<'_>) -> ! {
+        let code = self.code();
+        log::error!("Boot error {code:#06x}: {}: {details}", self.description());
+
+        if !RUNTIME_SERVICES_SWITCHED.load(Ordering::Acquire) {
+            store_code(code);
+        }
+        if boot_logger::boot_services_available() {
+            draw_code(code, self.description());
+        }
+
+        panic!("Boot error {code:#06x}: {details}");
+    }
+}
+
+/// Stops storing the code, the runtime services are at the kernel
+/// addresses now.
+pub fn runtime_services_switched() {
+    RUNTIME_SERVICES_SWITCHED.store(true, Ordering::Release);
+}
+
+fn store_code(code: u32) {
+    let attributes = VariableAttributes::NON_VOLATILE
+        | VariableAttributes::BOOTSERVICE_ACCESS
+        | VariableAttributes::RUNTIME_ACCESS;
+    if let Err(e) = runtime::set_variable(
+        BOOT_ERROR_VARIABLE,
+        &CORGOS_VARIABLE_VENDOR,
+        attributes,
+        &code.to_le_bytes(),
+    ) {
+        log::warn!("Could not store the boot error code: {e:?}");
+    }
+}
+
+/// Draws the code in large digits with the block characters.
+fn draw_code(code: u32, description: &str) {
+    system::with_stdout(|stdout| {
+        stdout.set_color(Color::White, Color::Red).ok();
+        stdout.clear().ok();
+        writeln!(stdout, "\n  CorgOS boot error\n").ok();
+
+        for row in 0..DIGIT_GLYPHS[0].len() {
+            stdout.write_str("  ").ok();
+            for digit in (0..CODE_DIGITS).rev() {
+                let glyph = DIGIT_GLYPHS[(code >> (4 * digit)) as usize & 0xf][row];
+                for column in (0..GLYPH_WIDTH).rev() {
+                    let pixel = if glyph & (1 << column) != 0 {
+                        "\u{2588}\u{2588}"
+                    } else {
+                        "  "
+                    };
+                    stdout.write_str(pixel).ok();
+                }
+                stdout.write_str("  ").ok();
+            }
+            stdout.write_str("\n").ok();
+        }
+
+        writeln!(stdout, "\n  {code:#06x}: {description}").ok();
+    });
+}
+
+/// Turns the failures into the boot errors.
+pub trait OrFail<T> {
+    /// The value, or reports `error` with `what` has failed, and stops.
+    fn or_fail(self, error: BootError, what: &str) -> T;
+}
+
+impl<T, E: Debug> OrFail<T> for Result<T, E> {
+    fn or_fail(self, error: BootError, what: &str) -> T {
+        self.unwrap_or_else(|e| error.fail(format_args!("{what}: {e:?}")))
+    }
+}
+
+impl<T> OrFail<T> for Option<T> {
+    fn or_fail(self, error: BootError, what: &str) -> T {
+        self.unwrap_or_else(|| error.fail(format_args!("{what}")))
+    }
+}
@@ -0,0 +1,213 @@
+//! Text boot menu.
+//!
+//! The menu is rendered on the UEFI console (that is the screen and the
+//! serial console the firmware has set up), and is controlled with the
+//! arrow keys on the console or on the serial port used for logging. The
+//! entry selected last time, or the first one, is booted when the timeout
+//! elapses unless a key has been pressed, so that the unattended boots
+//! still work. The watchdog is off while the menu waits for the user.
+
+use crate::keyboard::Keyboard;
+use crate::settings;
+use crate::settings::Setting;
+use crate::settings::MAX_SETTING_SIZE;
+use crate::watchdog;
+use boot_logger::BootLoaderConfig;
+use boot_logger::MAX_BOOT_ENTRIES;
+use boot_logger::MAX_CMDLINE_SIZE;
+use core::fmt::Write;
+use input::EditResult;
+use input::Key;
+use input::LineEditor;
+use uefi::system;
+
+/// One second in microseconds.
+const ONE_SECOND: usize = 1_000_000;
+
+/// The UEFI console as a `Write` for the echo.
+struct Stdout;
+
+impl Write for Stdout {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        system::with_stdout(|stdout| stdout.write_str(s))
+    }
+}
+
+struct Menu {
+    /// Indices of the used configuration entries.
+    entries: [usize; MAX_BOOT_ENTRIES],
+    entry_count: usize,
+    selected: usize,
+    cmdline: [u8; MAX_CMDLINE_SIZE],
+}
+
+impl Menu {
+    fn new(config: &BootLoaderConfig) -> Self {
+        let mut entries = [0; MAX_BOOT_ENTRIES];
+        let mut entry_count = 0;
+        for (index, _) in config
+            .boot_entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.is_used())
+        {
+            entries[entry_count] = index;
+            entry_count += 1;
+        }
+
+        let mut buf = [0_u8; MAX_SETTING_SIZE];
+        let last_entry = settings::read_str(Setting::LastMenuEntry, &mut buf);
+        let selected = entries[..entry_count]
+            .iter()
+            .position(|&index| Some(config.boot_entries[index].name_str()) == last_entry)
+            .unwrap_or_default();
+
+        let mut menu = Self {
+            entries,
+            entry_count,
+            selected,
+            cmdline: [0; MAX_CMDLINE_SIZE],
+        };
+        menu.reset_cmdline(config);
+        menu
+    }
+
+    fn reset_cmdline(&mut self, config: &BootLoaderConfig) {
+        let entry = &config.boot_entries[self.entries[self.selected]];
+        self.cmdline = entry.cmdline;
+    }
+
+    fn draw(&self, config: &BootLoaderConfig, seconds_left: Option<usize>) {
+        system::with_stdout(|stdout| {
+            stdout.clear().ok();
+            writeln!(stdout, "CorgOS boot menu\n").ok();
+            for (i, &index) in self.entries[..self.entry_count].iter().enumerate() {
+                let entry = &config.boot_entries[index];
+                let marker = if i == self.selected { '>' } else { ' ' };
+                writeln!(stdout, " {marker} {}. {}", i + 1, entry.name_str()).ok();
+            }
+            writeln!(
+                stdout,
+                "\ncmdline: {}",
+                boot_logger::zero_terminated_str(&self.cmdline)
+            )
+            .ok();
+            writeln!(
+                stdout,
+                "\nUp/Down to select, Enter to boot, 'e' to edit the command line"
+            )
+            .ok();
+            if let Some(seconds_left) = seconds_left {
+                writeln!(
+                    stdout,
+                    "Booting the selected entry in {seconds_left} seconds"
+                )
+                .ok();
+            }
+        });
+    }
+
+    /// Edits the command line with the [`LineEditor`] keys, Enter
+    /// accepts, Esc cancels.
+    fn edit_cmdline(&mut self, keyboard: &mut Keyboard) {
+        let mut cmdline = self.cmdline;
+        // The last byte stays zero.
+        let mut editor = LineEditor::new(&mut cmdline[..MAX_CMDLINE_SIZE - 1]);
+
+        Stdout.write_str("\nedit> ").ok();
+        editor.redraw(&mut Stdout);
+
+        loop {
+            let Some(event) = keyboard.wait_for_key(None) else {
+                continue;
+            };
+            match editor.handle(event, &mut Stdout) {
+                EditResult::Editing => {}
+                EditResult::Accepted => {
+                    self.cmdline = cmdline;
+                    return;
+                }
+                EditResult::Cancelled => return,
+            }
+        }
+    }
+}
+
+/// Shows the menu if there are boot entries configured, and applies the
+/// selected entry to the configuration.
+pub fn run_boot_menu(config: &mut BootLoaderConfig) {
+    if config.boot_entry_count() == 0 {
+        return;
+    }
+
+    let mut menu = Menu::new(config);
+    watchdog::disable();
+    let index = choose_entry(config, &mut menu);
+    watchdog::refresh();
+    let entry = config.boot_entries[index];
+    log::info!("Boot menu entry '{}' selected", entry.name_str());
+    remember_entry(entry.name_str());
+
+    if !entry.kernel_str().is_empty() {
+        config.kernel_a = entry.kernel;
+        config.kernel_b = [0; boot_logger::MAX_PATH_SIZE];
+    }
+    config.cmdline = menu.cmdline;
+}
+
+fn remember_entry(name: &str) {
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    if settings::read_str(Setting::LastMenuEntry, &mut buf) != Some(name) {
+        settings::write(Setting::LastMenuEntry, name.as_bytes());
+    }
+}
+
+fn choose_entry(config: &BootLoaderConfig, menu: &mut Menu) -> usize {
+    let mut keyboard = Keyboard::new();
+    if !keyboard.has_input() {
+        log::warn!("No console input and no serial port, booting the default menu entry");
+        return menu.entries[menu.selected];
+    }
+    let mut seconds_left = Some(config.menu_timeout_seconds);
+
+    let selected = loop {
+        menu.draw(config, seconds_left);
+
+        if seconds_left == Some(0) {
+            break menu.entries[menu.selected];
+        }
+
+        let Some(event) = keyboard.wait_for_key(seconds_left.map(|_| ONE_SECOND)) else {
+            seconds_left = seconds_left.map(|s| s.saturating_sub(1));
+            continue;
+        };
+
+        // Any key stops the countdown.
+        seconds_left = None;
+        match event.key {
+            Key::Up => {
+                menu.selected = menu.selected.saturating_sub(1);
+                menu.reset_cmdline(config);
+            }
+            Key::Down => {
+                menu.selected = core::cmp::min(menu.selected + 1, menu.entry_count - 1);
+                menu.reset_cmdline(config);
+            }
+            Key::Home => {
+                menu.selected = 0;
+                menu.reset_cmdline(config);
+            }
+            Key::End => {
+                menu.selected = menu.entry_count - 1;
+                menu.reset_cmdline(config);
+            }
+            Key::Enter => break menu.entries[menu.selected],
+            _ if event.printable() == Some('e') => menu.edit_cmdline(&mut keyboard),
+            _ => {}
+        }
+    };
+
+    system::with_stdout(|stdout| stdout.clear().ok());
+
+    selected
+}
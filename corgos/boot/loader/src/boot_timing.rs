@@ -0,0 +1,55 @@
+//! Where the loader spends the time.
+//!
+//! The stages are timed with the timestamp counter, that is cheap to read
+//! and keeps counting after exiting the boot services. The table is logged
+//! before the handoff, and passed to the kernel for its boot report.
+
+use crate::timer;
+use bootinfo::BootStage;
+use bootinfo::BootTimings;
+use bootinfo::BOOT_STAGE_COUNT;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+static LOADER_START: AtomicU64 = AtomicU64::new(0);
+static STAGES: [AtomicU64; BOOT_STAGE_COUNT] = [const { AtomicU64::new(0) }; BOOT_STAGE_COUNT];
+
+/// Starts the clock.
+pub fn start() {
+    LOADER_START.store(timer::counter(), Ordering::Relaxed);
+}
+
+/// Runs `f`, and adds the time it has taken to the stage.
+pub fn measure<T>(stage: BootStage, f: impl FnOnce() -> T) -> T {
+    let start = timer::counter();
+    let result = f();
+    STAGES[stage as usize].fetch_add(timer::counter() - start, Ordering::Relaxed);
+    result
+}
+
+/// Logs the table of the stages, and returns it for the kernel.
+pub fn report() -> BootTimings {
+    let mut timings = BootTimings {
+        counter_frequency: timer::timer().frequency,
+        loader_start: LOADER_START.load(Ordering::Relaxed),
+        loader_end: timer::counter(),
+        ..BootTimings::default()
+    };
+    for (ticks, stage) in timings.stages.iter_mut().zip(STAGES.iter()) {
+        *ticks = stage.load(Ordering::Relaxed);
+    }
+
+    let total = timings.loader_end - timings.loader_start;
+    log::info!("Boot timing, counter at {} Hz", timings.counter_frequency);
+    for stage in BootStage::ALL {
+        let ticks = timings.stage_ticks(stage);
+        log::info!(
+            "  {:<20}{:>10} us",
+            stage.name(),
+            timings.microseconds(ticks)
+        );
+    }
+    log::info!("  {:<20}{:>10} us", "total", timings.microseconds(total));
+
+    timings
+}
@@ -0,0 +1,53 @@
+//! Chainloading another EFI application.
+//!
+//! Useful for dual-boot setups, and to compare the firmware behavior
+//! without removing CorgOS from the boot order.
+
+use crate::files;
+use uefi::boot;
+use uefi::boot::LoadImageSource;
+use uefi::boot::MemoryType;
+use uefi::Status;
+
+/// Loads and starts the EFI application, returns its exit status when
+/// the application returns control.
+pub fn chainload(path: &str) -> Status {
+    log::info!("Chainloading '{path}'");
+
+    let image = match files::read_file(path, MemoryType::LOADER_DATA) {
+        Ok(image) => image,
+        Err(e) => {
+            log::error!("Cannot read '{path}': {e:?}");
+            return e.status();
+        }
+    };
+
+    let image_handle = boot::load_image(
+        boot::image_handle(),
+        LoadImageSource::FromBuffer {
+            buffer: image,
+            file_path: None,
+        },
+    );
+    // The firmware has made its own copy.
+    files::free_file(image);
+
+    let image_handle = match image_handle {
+        Ok(image_handle) => image_handle,
+        Err(e) => {
+            log::error!("Cannot load '{path}': {e:?}");
+            return e.status();
+        }
+    };
+
+    match boot::start_image(image_handle) {
+        Ok(()) => {
+            log::info!("'{path}' has returned");
+            Status::SUCCESS
+        }
+        Err(e) => {
+            log::error!("'{path}' has failed: {e:?}");
+            e.status()
+        }
+    }
+}
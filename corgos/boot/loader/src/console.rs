@@ -0,0 +1,52 @@
+//! Keeping the serial console available to the kernel, and finding the
+//! virtio console for the loader.
+
+use crate::device_tree;
+use crate::paging::PageTables;
+use boot_logger::LogDevice;
+use bootinfo::Console;
+use bootinfo::ConsoleKind;
+
+/// The size of the PL011 register block.
+const PL011_REGISTERS_SIZE: u64 = 0x1000;
+
+const COM1_IO_PORT: u16 = 0x3f8;
+const COM2_IO_PORT: u16 = 0x2f8;
+
+/// Maps the registers of the log device into the kernel address space.
+/// The virtio console is not handed off, its queues are in the loader.
+pub fn map_console(log_device: &LogDevice, page_tables: &mut PageTables) -> Console {
+    let console = match *log_device {
+        LogDevice::Pl011(phys_base) if cfg!(target_arch = "aarch64") => Console {
+            kind: ConsoleKind::Pl011,
+            phys_base,
+            virt_base: page_tables.map_device(phys_base, PL011_REGISTERS_SIZE),
+            ..Console::default()
+        },
+        LogDevice::Com1 if cfg!(target_arch = "x86_64") => Console {
+            kind: ConsoleKind::Uart16550Io,
+            io_port: COM1_IO_PORT,
+            ..Console::default()
+        },
+        LogDevice::Com2 if cfg!(target_arch = "x86_64") => Console {
+            kind: ConsoleKind::Uart16550Io,
+            io_port: COM2_IO_PORT,
+            ..Console::default()
+        },
+        _ => Console::default(),
+    };
+
+    log::info!("Kernel console {console:x?}");
+    console
+}
+
+/// The first virtio console among the virtio-mmio transports in the
+/// device tree the firmware has published. Runs before the logger is set
+/// up, nothing is logged.
+pub fn find_virtio_console() -> Option<u64> {
+    device_tree::find_device("virtio,mmio", |base_addr| {
+        // SAFETY: the transports are identity-mapped, and reading their
+        // identification registers has no side effects.
+        unsafe { poll_uart::probe_virtio_console(base_addr) }.is_ok()
+    })
+}
@@ -0,0 +1,135 @@
+//! The processor features the kernel cannot run without.
+//!
+//! The kernel assumes these and would fault somewhere deep in the early
+//! code without them, so the loader checks them upfront and names what is
+//! missing. Under a hypervisor the invariant TSC is often not advertised
+//! while the TSC is stable enough, so it is only reported there.
+
+use crate::boot_error::BootError;
+
+struct Feature {
+    name: &'static str,
+    present: bool,
+    /// Not having the feature stops the boot.
+    required: bool,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn features() -> [Feature; 5] {
+    use crate::x86_64_regs::access::X86Register;
+    use crate::x86_64_regs::Cr4;
+    use raw_cpuid::CpuId;
+
+    let mut cr4 = Cr4::new();
+    cr4.load();
+
+    let cpuid = CpuId::new();
+    let feature_info = cpuid.get_feature_info();
+    let extended_info = cpuid.get_extended_processor_and_feature_identifiers();
+    let hypervisor = feature_info
+        .as_ref()
+        .is_some_and(|info| info.has_hypervisor());
+
+    [
+        Feature {
+            name: "64-bit mode",
+            present: extended_info
+                .as_ref()
+                .is_some_and(|info| info.has_64bit_mode()),
+            required: true,
+        },
+        Feature {
+            name: "no-execute pages (NX)",
+            present: extended_info
+                .as_ref()
+                .is_some_and(|info| info.has_execute_disable()),
+            required: true,
+        },
+        Feature {
+            // The kernel page tables have 4 levels, and the firmware must
+            // not have switched to 5.
+            name: "4-level paging",
+            present: !cr4.la57(),
+            required: true,
+        },
+        Feature {
+            name: "SSE2",
+            present: feature_info.as_ref().is_some_and(|info| info.has_sse2()),
+            required: true,
+        },
+        Feature {
+            name: "invariant TSC",
+            present: cpuid
+                .get_advanced_power_mgmt_info()
+                .is_some_and(|info| info.has_invariant_tsc()),
+            required: !hypervisor,
+        },
+    ]
+}
+
+#[cfg(target_arch = "aarch64")]
+fn features() -> [Feature; 2] {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::*;
+    use uefi::boot;
+    use uefi::boot::MemoryType;
+    use uefi::mem::memory_map::MemoryMap;
+
+    let mut mmfr0 = MmFeatures0El1::new();
+    mmfr0.load();
+
+    let pa_bits = match mmfr0.pa_range() {
+        MmfPaRange::_32_bits_4GB => 32,
+        MmfPaRange::_36_bits_64GB => 36,
+        MmfPaRange::_40_bits_1TB => 40,
+        MmfPaRange::_42_bits_4TB => 42,
+        MmfPaRange::_44_bits_16TB => 44,
+        MmfPaRange::_48_bits_256TB => 48,
+        MmfPaRange::_52_bits_4PB => 52,
+        MmfPaRange::_56_bits_64PB => 56,
+    };
+    let memory_end = boot::memory_map(MemoryType::LOADER_DATA)
+        .map(|memory_map| {
+            memory_map
+                .entries()
+                .map(|entry| entry.phys_start + entry.page_count * 0x1000)
+                .max()
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+    log::info!("Physical address range {pa_bits} bits, the memory ends at {memory_end:#016x}");
+
+    [
+        Feature {
+            name: "4 KiB translation granule",
+            present: !matches!(mmfr0.t_gran4(), MmfTGran4KB::No),
+            required: true,
+        },
+        Feature {
+            name: "physical address range covering the memory",
+            present: memory_end <= 1 << pa_bits,
+            required: true,
+        },
+    ]
+}
+
+/// Stops the boot naming the first missing required feature.
+pub fn check_required_features() {
+    let mut missing = None;
+    for feature in features() {
+        match (feature.present, feature.required) {
+            (true, _) => log::info!("CPU feature: {}", feature.name),
+            (false, true) => {
+                log::error!("Missing required CPU feature: {}", feature.name);
+                missing.get_or_insert(feature.name);
+            }
+            (false, false) => log::warn!("Missing CPU feature: {}", feature.name),
+        }
+    }
+
+    if let Some(name) = missing {
+        BootError::CpuFeature.fail(format_args!(
+            "The processor does not support {name}, CorgOS cannot run on it"
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! Collecting what the kernel needs to start the secondary processors.
+//!
+//! The processors are enumerated from the ACPI MADT. On aarch64, the FADT
+//! tells if PSCI is implemented and which conduit to use, with the parking
+//! protocol as a fallback. On x86_64, the application processors are started
+//! with INIT-SIPI-SIPI, and that requires a page below 1 MiB which is
+//! reserved here as the kernel has no means to allocate one later.
+
+use acpi::madt::Madt;
+use acpi::madt::MadtEntry;
+use acpi::AcpiHandler;
+use acpi::AcpiTables;
+use bootinfo::CpuEnableMethod;
+use bootinfo::CpuInfo;
+use bootinfo::Cpus;
+use bootinfo::MAX_CPUS;
+#[cfg(target_arch = "aarch64")]
+use core::sync::atomic::AtomicU16;
+#[cfg(target_arch = "aarch64")]
+use core::sync::atomic::Ordering;
+#[cfg(target_arch = "aarch64")]
+use psci::Conduit;
+#[cfg(target_arch = "aarch64")]
+use psci::Psci;
+
+/// The processor is enabled.
+const MADT_CPU_ENABLED: u32 = 1 << 0;
+/// The processor can be enabled by the OS.
+#[cfg(target_arch = "x86_64")]
+const MADT_CPU_ONLINE_CAPABLE: u32 = 1 << 1;
+#[cfg(target_arch = "aarch64")]
+const MADT_CPU_ONLINE_CAPABLE: u32 = 1 << 3;
+
+fn add_cpu(cpus: &mut Cpus, hw_id: u64, uid: u32, flags: u32, parked_address: u64) {
+    if flags & (MADT_CPU_ENABLED | MADT_CPU_ONLINE_CAPABLE) == 0 {
+        return;
+    }
+    if cpus.count as usize >= MAX_CPUS {
+        log::warn!("Ignoring the processor {hw_id:#x}, more than {MAX_CPUS} processors");
+        return;
+    }
+
+    cpus.cpus[cpus.count as usize] = CpuInfo {
+        hw_id,
+        uid,
+        enabled: (flags & MADT_CPU_ENABLED != 0) as u32,
+        parked_address,
+        ..CpuInfo::default()
+    };
+    cpus.count += 1;
+}
+
+/// The hardware ID of the processor the caller runs on.
+#[cfg(target_arch = "x86_64")]
+pub fn current_cpu_hw_id() -> u64 {
+    use raw_cpuid::CpuId;
+
+    let cpuid = CpuId::new();
+    if let Some(topology) = cpuid
+        .get_extended_topology_info()
+        .and_then(|mut t| t.next())
+    {
+        return topology.x2apic_id() as u64;
+    }
+    cpuid
+        .get_feature_info()
+        .map(|f| f.initial_local_apic_id() as u64)
+        .unwrap_or_default()
+}
+
+/// The hardware ID of the processor the caller runs on.
+#[cfg(target_arch = "aarch64")]
+pub fn current_cpu_hw_id() -> u64 {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::MultiprocessorAffinityEl1;
+
+    let mut mpidr = MultiprocessorAffinityEl1::new();
+    mpidr.load();
+    mpidr.affinity()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn enable_method(_tables: &AcpiTables<impl AcpiHandler>, cpus: &mut Cpus) -> CpuEnableMethod {
+    use crate::memory_types;
+    use uefi::boot;
+    use uefi::boot::AllocateType;
+
+    // The SIPI vector is the page number, so the page must be below 1 MiB.
+    match boot::allocate_pages(
+        AllocateType::MaxAddress(0xf_ffff),
+        memory_types::AP_TRAMPOLINE,
+        bootinfo::AP_TRAMPOLINE_PAGES,
+    ) {
+        Ok(page) => {
+            cpus.ap_trampoline = page.as_ptr() as u64;
+            CpuEnableMethod::Sipi
+        }
+        Err(e) => {
+            log::warn!("Cannot allocate the SIPI trampoline pages: {e:?}");
+            CpuEnableMethod::None
+        }
+    }
+}
+
+/// The ARM boot architecture flags of the FADT, tell if the firmware
+/// implements PSCI and through which conduit.
+#[cfg(target_arch = "aarch64")]
+static ARM_BOOT_FLAGS: AtomicU16 = AtomicU16::new(0);
+
+#[cfg(target_arch = "aarch64")]
+fn detect_psci(tables: &AcpiTables<impl AcpiHandler>) {
+    use acpi::fadt::Fadt;
+
+    let Ok(flags) = tables.find_table::<Fadt>().map(|fadt| fadt.arm_boot_arch) else {
+        return;
+    };
+    let flags = (flags.implements_psci() as u16 * psci::ARM_BOOT_ARCH_PSCI_COMPLIANT)
+        | (flags.use_hvc_as_psci_conduit() as u16 * psci::ARM_BOOT_ARCH_PSCI_USE_HVC);
+    ARM_BOOT_FLAGS.store(flags, Ordering::Relaxed);
+
+    if let Some(psci) = psci() {
+        match psci.version() {
+            Ok(version) => log::info!("PSCI {version} via {:?}", psci.conduit()),
+            Err(e) => log::warn!("PSCI via {:?} is not usable: {e:?}", psci.conduit()),
+        }
+    }
+}
+
+/// The PSCI of the firmware, known after the processors are collected.
+#[cfg(target_arch = "aarch64")]
+pub fn psci() -> Option<Psci> {
+    Conduit::from_arm_boot_flags(ARM_BOOT_FLAGS.load(Ordering::Relaxed)).map(Psci::new)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn enable_method(_tables: &AcpiTables<impl AcpiHandler>, cpus: &mut Cpus) -> CpuEnableMethod {
+    match psci().map(|psci| psci.conduit()) {
+        Some(Conduit::Hvc) => CpuEnableMethod::PsciHvc,
+        Some(Conduit::Smc) => CpuEnableMethod::PsciSmc,
+        None if cpus.cpus().iter().any(|c| c.parked_address != 0) => {
+            CpuEnableMethod::ParkingProtocol
+        }
+        None => CpuEnableMethod::None,
+    }
+}
+
+/// Enumerates the processors, and prepares for starting them.
+pub fn collect_cpus(tables: &AcpiTables<impl AcpiHandler>, cpus: &mut Cpus) {
+    *cpus = Cpus {
+        boot_cpu_hw_id: current_cpu_hw_id(),
+        ..Cpus::default()
+    };
+
+    match tables.find_table::<Madt>() {
+        Ok(madt) => {
+            for entry in madt.entries() {
+                match entry {
+                    MadtEntry::LocalApic(e) => {
+                        add_cpu(cpus, e.apic_id as u64, e.processor_id as u32, e.flags, 0)
+                    }
+                    MadtEntry::LocalX2Apic(e) => {
+                        add_cpu(cpus, e.x2apic_id as u64, e.processor_uid, e.flags, 0)
+                    }
+                    MadtEntry::Gicc(e) => add_cpu(cpus, e.mpidr, e.processor_uid, e.flags, {
+                        e.parked_address
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        Err(e) => log::warn!("No MADT, cannot enumerate the processors: {e:?}"),
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    detect_psci(tables);
+    if cpus.count > 1 {
+        cpus.enable_method = enable_method(tables, cpus);
+    }
+
+    log::info!(
+        "Found {} processors, boot processor {:#x}, enable method {:?}",
+        cpus.count,
+        cpus.boot_cpu_hw_id,
+        cpus.enable_method
+    );
+    for cpu in cpus.cpus() {
+        log::debug!("Processor {cpu:x?}");
+    }
+}
@@ -0,0 +1,408 @@
+//! Pre-boot debug shell.
+//!
+//! A tiny command interpreter to poke at the firmware-specific issues
+//! without rebuilding and rebooting. It is entered when `debug_shell` is set
+//! in the configuration, or when [`HOTKEY`] has been pressed by the time the
+//! loader starts. The input comes from the UEFI console and from the serial
+//! port used for logging, the output goes where the log goes.
+//!
+//! The addresses are physical as the firmware identity-maps the memory.
+//! The dumps and the searches only read the memory in the UEFI memory map,
+//! but nothing stops one from reading or writing a register they should
+//! not.
+
+use crate::files;
+use crate::keyboard::Keyboard;
+use crate::memory_map;
+use crate::watchdog;
+use boot_logger::BootLoaderConfig;
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+use core::fmt::Write;
+use input::EditResult;
+use input::LineEditor;
+use meminspect::InspectError;
+use meminspect::Window;
+use memmap::RegionList;
+use uefi::boot;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::mem::memory_map::MemoryMapMut;
+use uefi::runtime;
+use uefi::runtime::ResetType;
+use uefi::Status;
+
+/// Enters the shell when pressed by the time the loader starts.
+pub const HOTKEY: char = 'd';
+
+const MAX_LINE_SIZE: usize = 128;
+const MAX_ARGS: usize = 4;
+const DEFAULT_HEXDUMP_SIZE: u64 = 256;
+/// The firmware maps have a few hundred entries at most.
+const MAX_REGIONS: usize = 512;
+
+const HELP: &str = "\
+help                              this text
+memmap                            the UEFI memory map
+hexdump <addr> [size]             dump the memory
+find <addr> <size> <pattern>      find the text, or the bytes as 0x<hex>
+read <addr> [8|16|32|64]          read a register, 32 bits by default
+write <addr> <value> [8|16|32|64] write a register, 32 bits by default
+config                            the configuration
+ls [dir]                          list the directory on the boot volume
+gdb                               stop in the GDB stub if it is on
+continue                          leave the shell and boot
+reset                             reset the machine
+";
+
+/// The log device as a `Write`.
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        boot_logger::write_str(s);
+        Ok(())
+    }
+}
+
+/// Whether [`HOTKEY`] is in the input buffer.
+pub fn hotkey_pressed() -> bool {
+    Keyboard::new()
+        .read_key()
+        .and_then(|event| event.printable())
+        == Some(HOTKEY)
+}
+
+/// Reads a line with the [`LineEditor`] keys, Esc gives an empty line.
+fn read_line<'a>(keyboard: &mut Keyboard, line: &'a mut [u8; MAX_LINE_SIZE]) -> &'a str {
+    line.fill(0);
+    let mut editor = LineEditor::new(line);
+    let len = loop {
+        let Some(event) = keyboard.wait_for_key(None) else {
+            continue;
+        };
+        match editor.handle(event, &mut Console) {
+            EditResult::Editing => {}
+            EditResult::Accepted => break editor.len(),
+            EditResult::Cancelled => break 0,
+        }
+    };
+    Console.write_str("\n").ok();
+
+    core::str::from_utf8(&line[..len]).unwrap_or_default()
+}
+
+/// Parses hex with `0x` or decimal.
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn memmap(out: &mut Console) {
+    let Ok(mut memory_map) = boot::memory_map(MemoryType::LOADER_DATA) else {
+        writeln!(out, "Cannot get the memory map").ok();
+        return;
+    };
+    memory_map.sort();
+
+    for desc in memory_map.entries() {
+        writeln!(
+            out,
+            "{:#018x}-{:#018x} {:>8} {:?} {:?}",
+            desc.phys_start,
+            desc.phys_start + desc.page_count * 0x1000 - 1,
+            desc.page_count,
+            desc.ty,
+            desc.att
+        )
+        .ok();
+    }
+}
+
+/// Calls `f` with the window of the memory in the UEFI memory map.
+fn with_window(
+    out: &mut Console,
+    f: impl FnOnce(&mut Console, &Window) -> Result<(), InspectError>,
+) {
+    let Ok(memory_map) = boot::memory_map(MemoryType::LOADER_DATA) else {
+        writeln!(out, "Cannot get the memory map").ok();
+        return;
+    };
+    let mut storage = [MemoryRegion {
+        start: 0,
+        pages: 0,
+        kind: MemoryKind::Reserved,
+        reserved: 0,
+        attributes: 0,
+    }; MAX_REGIONS];
+    let mut list = RegionList::new(&mut storage);
+    for entry in memory_map.entries() {
+        let region = memmap::from_uefi(
+            memory_map::memory_kind(entry.ty),
+            entry.phys_start,
+            entry.page_count,
+            entry.att.bits(),
+        );
+        if let Err(e) = region.and_then(|region| list.push(region)) {
+            writeln!(out, "Cannot use the memory map: {e:?}").ok();
+            return;
+        }
+    }
+
+    if let Err(e) = f(out, &Window::new(list.as_slice(), 0)) {
+        writeln!(out, "Cannot read the memory: {e:?}").ok();
+    }
+}
+
+fn hexdump(out: &mut Console, addr: u64, size: u64) {
+    if addr.checked_add(size).is_none() {
+        writeln!(out, "The range wraps around").ok();
+        return;
+    }
+    with_window(out, |out, window| {
+        for line in window.hex_lines(addr, size)? {
+            writeln!(out, "{line}").ok();
+        }
+        Ok(())
+    });
+}
+
+fn find(out: &mut Console, addr: u64, size: u64, pattern: &str) {
+    if addr.checked_add(size).is_none() {
+        writeln!(out, "The range wraps around").ok();
+        return;
+    }
+    let mut buf = [0; meminspect::MAX_PATTERN_SIZE];
+    let Some(pattern) = meminspect::parse_pattern(pattern, &mut buf) else {
+        writeln!(out, "Bad pattern '{pattern}'").ok();
+        return;
+    };
+    with_window(out, |out, window| {
+        match window.find(addr, size, pattern)? {
+            Some(found) => writeln!(out, "Found at {found:#018x}").ok(),
+            None => writeln!(out, "Not found").ok(),
+        };
+        Ok(())
+    });
+}
+
+#[derive(Clone, Copy)]
+enum Width {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl Width {
+    fn bytes(self) -> u64 {
+        match self {
+            Width::U8 => 1,
+            Width::U16 => 2,
+            Width::U32 => 4,
+            Width::U64 => 8,
+        }
+    }
+}
+
+/// The width of the register access, 32 bits if not given. The address
+/// must be aligned to it.
+fn register_width(out: &mut Console, addr: u64, width: Option<&str>) -> Option<Width> {
+    let width = match width.map(parse_number) {
+        None => Width::U32,
+        Some(Some(8)) => Width::U8,
+        Some(Some(16)) => Width::U16,
+        Some(Some(32)) => Width::U32,
+        Some(Some(64)) => Width::U64,
+        Some(_) => {
+            writeln!(out, "The width must be 8, 16, 32, or 64").ok();
+            return None;
+        }
+    };
+    if addr % width.bytes() != 0 {
+        writeln!(
+            out,
+            "The address {addr:#x} is not aligned to {} bytes",
+            width.bytes()
+        )
+        .ok();
+        return None;
+    }
+    Some(width)
+}
+
+fn read_register(addr: u64, width: Width) -> u64 {
+    // SAFETY: the user asked for it, the address is aligned.
+    unsafe {
+        match width {
+            Width::U8 => core::ptr::read_volatile(addr as *const u8) as u64,
+            Width::U16 => core::ptr::read_volatile(addr as *const u16) as u64,
+            Width::U32 => core::ptr::read_volatile(addr as *const u32) as u64,
+            Width::U64 => core::ptr::read_volatile(addr as *const u64),
+        }
+    }
+}
+
+fn write_register(addr: u64, value: u64, width: Width) {
+    // SAFETY: the user asked for it, the address is aligned.
+    unsafe {
+        match width {
+            Width::U8 => core::ptr::write_volatile(addr as *mut u8, value as u8),
+            Width::U16 => core::ptr::write_volatile(addr as *mut u16, value as u16),
+            Width::U32 => core::ptr::write_volatile(addr as *mut u32, value as u32),
+            Width::U64 => core::ptr::write_volatile(addr as *mut u64, value),
+        }
+    }
+}
+
+fn show_config(out: &mut Console, config: &BootLoaderConfig) {
+    writeln!(out, "revision: {}", config.revision_str()).ok();
+    writeln!(out, "payload: {:?}", config.payload).ok();
+    writeln!(out, "kernel_a: {}", config.kernel_a_str()).ok();
+    writeln!(out, "kernel_b: {:?}", config.kernel_b_str()).ok();
+    writeln!(out, "kernel_sha256: {:?}", config.kernel_sha256_str()).ok();
+    writeln!(out, "cmdline: {}", config.cmdline_str()).ok();
+    writeln!(out, "initrd: {:?}", config.initrd_str()).ok();
+    writeln!(out, "chainload: {:?}", config.chainload_str()).ok();
+    for module in config.modules.iter().filter(|m| m.is_used()) {
+        writeln!(
+            out,
+            "module.{}: {} {}",
+            module.name_str(),
+            module.path_str(),
+            module.cmdline_str()
+        )
+        .ok();
+    }
+    for entry in config.boot_entries.iter().filter(|e| e.is_used()) {
+        writeln!(
+            out,
+            "boot_entry: {} {} {}",
+            entry.name_str(),
+            entry.kernel_str(),
+            entry.cmdline_str()
+        )
+        .ok();
+    }
+    writeln!(out, "log_device: {:?}", config.log_device).ok();
+    writeln!(out, "log_level: {:?}", config.log_level).ok();
+    writeln!(out, "gdb_stub: {:x?}", config.gdb_stub).ok();
+    writeln!(
+        out,
+        "recovery_boot_attempts: {}, recovery_log_level: {:?}",
+        config.recovery_boot_attempts, config.recovery_log_level
+    )
+    .ok();
+    writeln!(out, "kaslr: {}", config.kaslr).ok();
+    writeln!(out, "load_below_4g: {}", config.load_below_4g).ok();
+    writeln!(out, "video_mode: {:?}", config.video_mode).ok();
+    writeln!(out, "framebuffer_console: {}", config.framebuffer_console).ok();
+    writeln!(out, "mp_probe: {}", config.mp_probe).ok();
+    writeln!(out, "pmu_profile: {}", config.pmu_profile).ok();
+    writeln!(out, "x2apic: {}", config.x2apic).ok();
+    writeln!(out, "persist_settings: {}", config.persist_settings).ok();
+    writeln!(out, "early_heap: {:#x}", config.early_heap_size).ok();
+    writeln!(out, "kernel_stack: {:#x}", config.kernel_stack_size).ok();
+    writeln!(
+        out,
+        "load_watchdog_seconds: {}, exit_watchdog_seconds: {}",
+        config.load_watchdog_seconds, config.exit_watchdog_seconds
+    )
+    .ok();
+    writeln!(out, "volume_label: {:?}", config.volume_label_str()).ok();
+    writeln!(
+        out,
+        "volume_partition_guid: {:?}",
+        config.volume_partition_guid_str()
+    )
+    .ok();
+}
+
+fn ls(out: &mut Console, path: &str) {
+    let result = files::list_dir(path, |info| {
+        let kind = if info.is_directory() { "<DIR>" } else { "" };
+        writeln!(
+            out,
+            "{:>12} {kind:5} {}",
+            info.file_size(),
+            info.file_name()
+        )
+        .ok();
+    });
+    if let Err(e) = result {
+        writeln!(out, "Cannot list '{path}': {e:?}").ok();
+    }
+}
+
+/// Runs the shell until `continue`.
+pub fn run(config: &BootLoaderConfig) {
+    // Sitting at the prompt must not reset the machine.
+    watchdog::start(0);
+
+    let mut out = Console;
+    writeln!(out, "CorgOS debug shell, 'help' for the commands").ok();
+
+    let mut keyboard = Keyboard::new();
+    let mut line = [0_u8; MAX_LINE_SIZE];
+    loop {
+        out.write_str("corgos> ").ok();
+
+        let mut args = [""; MAX_ARGS];
+        let mut arg_count = 0;
+        let mut words = read_line(&mut keyboard, &mut line).split_whitespace();
+        for (arg, word) in args.iter_mut().zip(&mut words) {
+            *arg = word;
+            arg_count += 1;
+        }
+        if words.next().is_some() {
+            writeln!(
+                out,
+                "Too many arguments, at most {MAX_ARGS} with the command"
+            )
+            .ok();
+            continue;
+        }
+        let numbers = {
+            let mut numbers = [None; MAX_ARGS];
+            for (number, arg) in numbers.iter_mut().zip(&args[..arg_count]) {
+                *number = parse_number(arg);
+            }
+            numbers
+        };
+
+        match (&args[..arg_count], &numbers[1..]) {
+            ([], _) => {}
+            (["help"], _) => {
+                out.write_str(HELP).ok();
+            }
+            (["memmap"], _) => memmap(&mut out),
+            (["hexdump", _], [Some(addr), ..]) => hexdump(&mut out, *addr, DEFAULT_HEXDUMP_SIZE),
+            (["hexdump", _, _], [Some(addr), Some(size), ..]) => hexdump(&mut out, *addr, *size),
+            (["find", _, _, pattern], [Some(addr), Some(size), _]) => {
+                find(&mut out, *addr, *size, pattern)
+            }
+            (["read", _, width @ ..], [Some(addr), ..]) if width.len() <= 1 => {
+                if let Some(width) = register_width(&mut out, *addr, width.first().copied()) {
+                    let value = read_register(*addr, width);
+                    writeln!(out, "{addr:#018x}: {value:#x}").ok();
+                }
+            }
+            (["write", _, _, width @ ..], [Some(addr), Some(value), ..]) => {
+                if let Some(width) = register_width(&mut out, *addr, width.first().copied()) {
+                    write_register(*addr, *value, width);
+                }
+            }
+            (["config"], _) => show_config(&mut out, config),
+            (["ls"], _) => ls(&mut out, ""),
+            (["ls", path], _) => ls(&mut out, path),
+            (["gdb"], _) => crate::gdb_stub::breakpoint(),
+            (["continue"], _) => break,
+            (["reset"], _) => runtime::reset(ResetType::WARM, Status::SUCCESS, None),
+            _ => {
+                writeln!(out, "Bad command or arguments, 'help' for the commands").ok();
+            }
+        }
+    }
+}
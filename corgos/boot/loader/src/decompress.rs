@@ -0,0 +1,93 @@
+//! The LZ4-compressed kernel images and boot modules.
+//!
+//! A file starting with an LZ4 frame is decompressed right after reading,
+//! anything else is taken as is. The frame must have the content size
+//! (`lz4 --content-size`) for the loader to allocate the memory up front.
+
+use crate::files;
+use crate::profiling;
+use crate::watchdog;
+use core::ptr::NonNull;
+use lz4::FrameDecoder;
+use lz4::Lz4Error;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+
+const PAGE_SIZE: usize = 0x1000;
+/// The watchdog is refreshed after decompressing this much of the input.
+const DECOMPRESS_CHUNK_SIZE: usize = 0x10_0000;
+
+#[derive(Debug)]
+pub enum DecompressError {
+    /// The frame does not say how large the content is.
+    NoContentSize,
+    OutOfMemory(uefi::Error),
+    Lz4(Lz4Error),
+}
+
+impl From<Lz4Error> for DecompressError {
+    fn from(e: Lz4Error) -> Self {
+        Self::Lz4(e)
+    }
+}
+
+/// Decompresses the file read by [`files::read_file`] into freshly
+/// allocated pages if it is compressed, and frees the compressed data.
+/// The data that is not compressed is returned as is. The result can be
+/// freed with [`files::free_file`].
+pub fn decompress_file(
+    path: &str,
+    data: &'static mut [u8],
+    memory_type: MemoryType,
+) -> Result<&'static mut [u8], DecompressError> {
+    if !lz4::is_frame(data) {
+        return Ok(data);
+    }
+
+    let result = profiling::profile(&profiling::DECOMPRESS, || decompress(data, memory_type));
+    files::free_file(data);
+    let output = result?;
+    log::info!("Decompressed '{path}' to {} bytes", output.len());
+
+    Ok(output)
+}
+
+fn decompress(data: &[u8], memory_type: MemoryType) -> Result<&'static mut [u8], DecompressError> {
+    let size = lz4::content_size(data)?.ok_or(DecompressError::NoContentSize)? as usize;
+    let output = boot::allocate_pages(
+        AllocateType::AnyPages,
+        memory_type,
+        size.div_ceil(PAGE_SIZE).max(1),
+    )
+    .map_err(DecompressError::OutOfMemory)?;
+    // SAFETY: the pages have just been allocated and hold `size` bytes.
+    let output = unsafe { core::slice::from_raw_parts_mut(output.as_ptr(), size) };
+
+    let result = {
+        let mut decoder = FrameDecoder::new(output);
+        data.chunks(DECOMPRESS_CHUNK_SIZE)
+            .try_for_each(|chunk| {
+                let result = decoder.feed(chunk);
+                watchdog::refresh();
+                result
+            })
+            .and_then(|()| decoder.finish())
+    };
+    // The frame has been checked to have the content size, the frames
+    // after it could only overflow the output.
+    match result {
+        Ok(_) => Ok(output),
+        Err(e) => {
+            free_output(output);
+            Err(e.into())
+        }
+    }
+}
+
+fn free_output(output: &'static mut [u8]) {
+    if let Some(ptr) = NonNull::new(output.as_mut_ptr()) {
+        // SAFETY: the pages were allocated by `decompress`, and the slice is consumed.
+        unsafe { boot::free_pages(ptr, output.len().div_ceil(PAGE_SIZE).max(1)) }.ok();
+    }
+}
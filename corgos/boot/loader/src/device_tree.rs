@@ -0,0 +1,33 @@
+//! The device tree the firmware publishes as a configuration table. The
+//! aarch64 firmware has it alongside or instead of the ACPI tables, the
+//! x86_64 one rarely has it.
+
+use fdt::Fdt;
+use uefi::system;
+use uefi_guids::config_tables::ConfigTables;
+use uefi_guids::EFI_DTB_TABLE_GUID;
+
+/// Calls `f` with the device tree, `None` if there is no valid one. Must
+/// be called while the boot services are available.
+pub fn with_device_tree<R>(f: impl FnOnce(&Fdt) -> Option<R>) -> Option<R> {
+    let dtb = system::with_config_table(|tables| {
+        // SAFETY: the boot services are active, the tables are identity-mapped.
+        let tables = unsafe { ConfigTables::new(tables) };
+        tables.find(&EFI_DTB_TABLE_GUID)
+    })?;
+    // SAFETY: the DTB stays in place while the boot services are active.
+    let fdt = unsafe { Fdt::from_ptr(dtb as *const u8) }.ok()?;
+    f(&fdt)
+}
+
+/// The first region of the first enabled node compatible with `compatible`
+/// that passes `probe`.
+pub fn find_device(compatible: &str, probe: impl Fn(u64) -> bool) -> Option<u64> {
+    with_device_tree(|fdt| {
+        fdt.compatible(compatible)
+            .filter(|node| node.is_enabled())
+            .filter_map(|node| node.reg()?.next())
+            .map(|region| region.address)
+            .find(|&address| probe(address))
+    })
+}
@@ -0,0 +1,194 @@
+//! Diagnostics dump to the boot volume.
+//!
+//! On the hardware without a serial port or a screen one can capture, the
+//! loader writes what it knows about the machine, and the log so far, to
+//! [`DIAGNOSTICS_PATH`] right before exiting the boot services. The dump
+//! starts with the time the real-time clock reads, if there is one, and
+//! lists the PCI devices with their BARs.
+
+use crate::files;
+use crate::pci;
+use crate::real_time_clock;
+use bootinfo::Pci;
+use bootinfo::Rtc;
+use core::cell::RefCell;
+use core::fmt::Write;
+use uefi::boot;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::mem::memory_map::MemoryMapMut;
+use uefi::proto::media::file::File;
+use uefi::proto::media::file::RegularFile;
+use uefi::system;
+use uefi_guids::GuidCategory;
+
+pub const DIAGNOSTICS_PATH: &str = "\\EFI\\corgos\\diag.txt";
+
+/// Writes the formatted text to the file, remembers the first error.
+struct FileWriter {
+    file: RegularFile,
+    result: uefi::Result,
+}
+
+impl FileWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.result.is_ok() {
+            self.result = self
+                .file
+                .write(bytes)
+                .map_err(|e| uefi::Error::from(e.status()));
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn write_memory_map(out: &mut FileWriter) {
+    let mut memory_map = match boot::memory_map(MemoryType::LOADER_DATA) {
+        Ok(memory_map) => memory_map,
+        Err(e) => {
+            writeln!(out, "Cannot get the memory map: {e:?}").ok();
+            return;
+        }
+    };
+    memory_map.sort();
+
+    writeln!(out, "Memory map, {} entries", memory_map.len()).ok();
+    for desc in memory_map.entries() {
+        writeln!(
+            out,
+            "{:#018x}-{:#018x} {:>8} {:?} {:?}",
+            desc.phys_start,
+            desc.phys_start + desc.page_count * 0x1000 - 1,
+            desc.page_count,
+            desc.ty,
+            desc.att
+        )
+        .ok();
+    }
+}
+
+/// The configuration tables grouped by the category.
+fn write_config_tables(out: &mut FileWriter) {
+    const CATEGORIES: [GuidCategory; 7] = [
+        GuidCategory::Acpi,
+        GuidCategory::Smbios,
+        GuidCategory::Memory,
+        GuidCategory::Hob,
+        GuidCategory::Debug,
+        GuidCategory::Firmware,
+        GuidCategory::Vendor,
+    ];
+
+    // The closure cannot mutate what it captures.
+    let out = RefCell::new(out);
+    system::with_config_table(|tables| {
+        let mut out = out.borrow_mut();
+        writeln!(out, "Configuration tables, {} entries", tables.len()).ok();
+        for category in CATEGORIES {
+            let mut in_category = tables
+                .iter()
+                .filter(|table| uefi_guids::get_uefi_table_category(&table.guid) == category)
+                .peekable();
+            if in_category.peek().is_some() {
+                writeln!(out, "{category:?}:").ok();
+            }
+            for table in in_category {
+                writeln!(
+                    out,
+                    "  {} @ {:#016x}: {}",
+                    table.guid,
+                    table.address as u64,
+                    uefi_guids::get_uefi_table_name(&table.guid)
+                )
+                .ok();
+            }
+        }
+    });
+}
+
+fn write_rsdp(out: &mut FileWriter, rsdp_addr: u64) {
+    // SAFETY: the RSDP has been validated while reporting the UEFI info.
+    let rsdp = unsafe { &*(rsdp_addr as *const acpi::rsdp::Rsdp) };
+    writeln!(out, "ACPI RSDP @ {rsdp_addr:#016x}").ok();
+    writeln!(out, "{rsdp:#x?}").ok();
+
+    // SAFETY: the loader has required the ACPI 2.0 RSDP, which is `length`
+    // bytes.
+    let bytes =
+        unsafe { core::slice::from_raw_parts(rsdp_addr as *const u8, rsdp.length() as usize) };
+    for line in meminspect::hex_lines(rsdp_addr, bytes) {
+        writeln!(out, "{line}").ok();
+    }
+}
+
+fn write_pci(out: &mut FileWriter, pci: &Pci) {
+    let Some(ecam) = pci::identity_mapped(pci) else {
+        writeln!(out, "No PCI Express configuration space").ok();
+        return;
+    };
+    writeln!(out, "PCI ECAM @ {:#016x}", pci.ecam_phys_base).ok();
+    for function in ecam.functions() {
+        writeln!(out, "  {function}").ok();
+        for bar in function.bars() {
+            writeln!(
+                out,
+                "    BAR{} {:?} @ {:#x}, {:#x} bytes{}",
+                bar.index,
+                bar.kind,
+                bar.address,
+                bar.size,
+                if bar.prefetchable {
+                    ", prefetchable"
+                } else {
+                    ""
+                }
+            )
+            .ok();
+        }
+    }
+}
+
+/// Writes the diagnostics to [`DIAGNOSTICS_PATH`].
+pub fn dump(rsdp_addr: u64, rtc: &Rtc, pci: &Pci) {
+    let file = match files::create_file(DIAGNOSTICS_PATH) {
+        Ok(file) => file,
+        Err(e) => {
+            log::warn!("Cannot create '{DIAGNOSTICS_PATH}': {e:?}");
+            return;
+        }
+    };
+    log::info!("Writing the diagnostics to '{DIAGNOSTICS_PATH}'");
+
+    let mut out = FileWriter {
+        file,
+        result: Ok(()),
+    };
+    match real_time_clock::read(rtc) {
+        Some(time) => writeln!(out, "Written at {time} by the {:?} clock\n", rtc.kind).ok(),
+        None => writeln!(out, "Written at an unknown time\n").ok(),
+    };
+    write_memory_map(&mut out);
+    out.write_str("\n").ok();
+    write_config_tables(&mut out);
+    out.write_str("\n").ok();
+    write_rsdp(&mut out, rsdp_addr);
+    out.write_str("\n").ok();
+    write_pci(&mut out, pci);
+    out.write_str("\nLog\n").ok();
+    // Nothing is logged while the ring is held.
+    boot_logger::with_log_ring(|older, newer| {
+        out.write_bytes(older);
+        out.write_bytes(newer);
+    });
+
+    let result = out.result.and_then(|_| out.file.flush());
+    if let Err(e) = result {
+        log::warn!("Cannot write '{DIAGNOSTICS_PATH}': {e:?}");
+    }
+}
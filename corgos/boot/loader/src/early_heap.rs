@@ -0,0 +1,53 @@
+//! The early kernel heap.
+//!
+//! The kernel global allocator needs memory before the kernel can parse the
+//! memory map and set up its page allocator, so the loader sets aside a
+//! physically contiguous chunk with its own memory type. The chunk is
+//! allocated below the end of the linear map, and that maps it read-write
+//! and not executable, so there is nothing else to map.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use crate::paging::LINEAR_MAP_BASE;
+use crate::paging::LINEAR_MAP_MAX_SIZE;
+use bootinfo::EarlyHeap;
+use uefi::boot;
+use uefi::boot::AllocateType;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Allocates the heap of `size` bytes rounded up to the page size, none if
+/// `size` is `0`.
+pub fn allocate_early_heap(size: usize) -> EarlyHeap {
+    if size == 0 {
+        log::info!("No early kernel heap");
+        return EarlyHeap::default();
+    }
+
+    let pages = size.div_ceil(PAGE_SIZE);
+    let heap = boot::allocate_pages(
+        AllocateType::MaxAddress(LINEAR_MAP_MAX_SIZE - 1),
+        memory_types::EARLY_HEAP,
+        pages,
+    )
+    .or_fail(
+        BootError::OutOfMemory,
+        "Cannot allocate the early kernel heap",
+    );
+
+    let phys_base = heap.as_ptr() as u64;
+    let early_heap = EarlyHeap {
+        phys_base,
+        virt_base: LINEAR_MAP_BASE + phys_base,
+        size: (pages * PAGE_SIZE) as u64,
+    };
+    log::info!(
+        "Early kernel heap @ {:#016x} -> {:#016x}, {:#x} bytes",
+        early_heap.virt_base,
+        early_heap.phys_base,
+        early_heap.size
+    );
+
+    early_heap
+}
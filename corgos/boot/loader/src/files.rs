@@ -0,0 +1,305 @@
+//! Reading files from the ESP, or from the network for the URLs.
+
+use crate::netboot;
+use crate::watchdog;
+use boot_logger::BootLoaderConfig;
+use boot_logger::MAX_PATH_SIZE;
+use core::ffi::c_void;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::Ordering;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::boot::OpenProtocolParams;
+use uefi::boot::SearchType;
+use uefi::proto::device_path::build;
+use uefi::proto::device_path::build::DevicePathBuilder;
+use uefi::proto::device_path::media::PartitionSignature;
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::device_path::DevicePathNodeEnum;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::File;
+use uefi::proto::media::file::FileAttribute;
+use uefi::proto::media::file::FileInfo;
+use uefi::proto::media::file::FileMode;
+use uefi::proto::media::file::FileSystemVolumeLabel;
+use uefi::proto::media::file::RegularFile;
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::CStr16;
+use uefi::Guid;
+use uefi::Handle;
+use uefi::Status;
+
+const PAGE_SIZE: usize = 0x1000;
+/// The watchdog is refreshed after reading this much.
+const READ_CHUNK_SIZE: usize = 0x10_0000;
+
+/// The volume chosen with [`set_volume`], null if none.
+static VOLUME: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The volume the files are read from, the first one unless another has
+/// been chosen.
+pub fn volume() -> uefi::Result<Handle> {
+    // SAFETY: only the valid handles are stored.
+    match unsafe { Handle::from_ptr(VOLUME.load(Ordering::Relaxed)) } {
+        Some(volume) => Ok(volume),
+        None => boot::get_handle_for_protocol::<SimpleFileSystem>(),
+    }
+}
+
+pub fn set_volume(volume: Handle) {
+    VOLUME.store(volume.as_ptr(), Ordering::Relaxed);
+}
+
+/// Finds the volume for which `matches` is true among all the simple file
+/// systems. The volume the loader has been loaded from is tried first.
+pub fn find_volume(mut matches: impl FnMut(Handle) -> bool) -> Option<Handle> {
+    let loader_volume = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle())
+        .ok()
+        .and_then(|loaded_image| loaded_image.device());
+    if let Some(loader_volume) = loader_volume {
+        if boot::test_protocol::<SimpleFileSystem>(OpenProtocolParams {
+            handle: loader_volume,
+            agent: boot::image_handle(),
+            controller: None,
+        })
+        .unwrap_or(false)
+            && matches(loader_volume)
+        {
+            return Some(loader_volume);
+        }
+    }
+
+    let volumes = boot::locate_handle_buffer(SearchType::from_proto::<SimpleFileSystem>()).ok()?;
+    volumes
+        .iter()
+        .copied()
+        .filter(|&volume| Some(volume) != loader_volume)
+        .find(|&volume| matches(volume))
+}
+
+/// Whether the file exists on the volume.
+pub fn has_file(volume: Handle, path: &CStr16) -> bool {
+    boot::open_protocol_exclusive::<SimpleFileSystem>(volume)
+        .and_then(|mut sfs| sfs.open_volume())
+        .and_then(|mut root| root.open(path, FileMode::Read, FileAttribute::empty()))
+        .is_ok()
+}
+
+fn has_label(volume: Handle, label: &str) -> bool {
+    let mut info_buf = [0_u8; 512];
+    boot::open_protocol_exclusive::<SimpleFileSystem>(volume)
+        .and_then(|mut sfs| sfs.open_volume())
+        .is_ok_and(|mut root| {
+            root.get_info::<FileSystemVolumeLabel>(&mut info_buf)
+                .is_ok_and(|info| {
+                    info.volume_label()
+                        .iter()
+                        .map(|&c| char::from(c))
+                        .eq(label.chars())
+                })
+        })
+}
+
+fn partition_guid(volume: Handle) -> Option<Guid> {
+    let device_path = boot::open_protocol_exclusive::<DevicePath>(volume).ok()?;
+    device_path
+        .node_iter()
+        .find_map(|node| match node.as_enum() {
+            Ok(DevicePathNodeEnum::MediaHardDrive(hard_drive)) => {
+                match hard_drive.partition_signature() {
+                    PartitionSignature::Guid(guid) => Some(guid),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+}
+
+/// Chooses the volume by the label or the partition GUID if configured,
+/// otherwise keeps the volume the configuration has been read from.
+pub fn select_volume(config: &BootLoaderConfig) {
+    let label = config.volume_label_str();
+    let guid = config.volume_partition_guid_str().and_then(|guid| {
+        let parsed = guid.parse::<Guid>().ok();
+        if parsed.is_none() {
+            log::warn!("Invalid partition GUID '{guid}'");
+        }
+        parsed
+    });
+
+    if label.is_some() || guid.is_some() {
+        let volume = find_volume(|volume| {
+            label.is_none_or(|label| has_label(volume, label))
+                && guid.is_none_or(|guid| partition_guid(volume) == Some(guid))
+        });
+        match volume {
+            Some(volume) => set_volume(volume),
+            None => log::warn!("No volume with label {label:?} and partition {guid:?}"),
+        }
+    }
+
+    match volume() {
+        Ok(volume) => {
+            let mut info_buf = [0_u8; 512];
+            let mut sfs = boot::open_protocol_exclusive::<SimpleFileSystem>(volume).ok();
+            let mut root = sfs.as_mut().and_then(|sfs| sfs.open_volume().ok());
+            let label = root
+                .as_mut()
+                .and_then(|root| root.get_info::<FileSystemVolumeLabel>(&mut info_buf).ok());
+            log::info!(
+                "Reading files from the volume {volume:?}, label '{}', partition {:?}",
+                label
+                    .map(|label| label.volume_label())
+                    .unwrap_or(uefi::cstr16!("")),
+                partition_guid(volume)
+            );
+        }
+        Err(e) => log::warn!("No volume to read files from: {e:?}"),
+    }
+}
+
+/// Builds the full device path of the file on the [`volume`] in `buf`.
+pub fn file_device_path<'a>(
+    path: &str,
+    buf: &'a mut [MaybeUninit<u8>],
+) -> uefi::Result<&'a DevicePath> {
+    let volume_path = boot::open_protocol_exclusive::<DevicePath>(volume()?)?;
+
+    // The file path node wants the UEFI separators.
+    let mut path_buf = [0_u16; MAX_PATH_SIZE + 1];
+    let mut len = 0;
+    for c in path.chars().take(MAX_PATH_SIZE) {
+        path_buf[len] = if c == '/' { '\\' as u16 } else { c as u16 };
+        len += 1;
+    }
+    let path_name = CStr16::from_u16_with_nul(&path_buf[..=len])
+        .map_err(|_| uefi::Error::from(Status::INVALID_PARAMETER))?;
+
+    let too_long = |_| uefi::Error::from(Status::BUFFER_TOO_SMALL);
+    let mut builder = DevicePathBuilder::with_buf(buf);
+    for node in volume_path.node_iter() {
+        builder = builder.push(&node).map_err(too_long)?;
+    }
+    builder
+        .push(&build::media::FilePath { path_name })
+        .map_err(too_long)?
+        .finalize()
+        .map_err(too_long)
+}
+
+/// Reads the whole file into freshly allocated pages. The pages are
+/// not freed unless [`free_file`] is called.
+pub fn read_file(path: &str, memory_type: MemoryType) -> uefi::Result<&'static mut [u8]> {
+    if netboot::is_network_path(path) {
+        return netboot::read_file(path, memory_type);
+    }
+
+    let mut sfs = boot::open_protocol_exclusive::<SimpleFileSystem>(volume()?)?;
+    let mut root = sfs.open_volume()?;
+
+    let mut path_buf = [0_u16; MAX_PATH_SIZE + 1];
+    let path = CStr16::from_str_with_buf(path, &mut path_buf)
+        .map_err(|_| uefi::Error::from(Status::INVALID_PARAMETER))?;
+    let mut file = root
+        .open(path, FileMode::Read, FileAttribute::empty())?
+        .into_regular_file()
+        .ok_or(uefi::Error::from(Status::INVALID_PARAMETER))?;
+
+    let file_size = {
+        let mut file_info_buf = [0u8; 512];
+        let file_info = file
+            .get_info::<FileInfo>(&mut file_info_buf)
+            .map_err(|e| uefi::Error::from(e.status()))?;
+        file_info.file_size() as usize
+    };
+
+    let data = boot::allocate_pages(
+        AllocateType::AnyPages,
+        memory_type,
+        file_size.div_ceil(PAGE_SIZE),
+    )?
+    .as_ptr();
+    let data = unsafe { core::slice::from_raw_parts_mut(data, file_size) };
+
+    for chunk in data.chunks_mut(READ_CHUNK_SIZE) {
+        let bytes_read = file
+            .read(chunk)
+            .map_err(|e| uefi::Error::from(e.status()))?;
+        if bytes_read != chunk.len() {
+            return Err(Status::END_OF_FILE.into());
+        }
+        watchdog::refresh();
+    }
+
+    Ok(data)
+}
+
+/// Calls `f` for each entry of the directory on the volume, the root
+/// directory if the path is empty.
+pub fn list_dir(path: &str, mut f: impl FnMut(&FileInfo)) -> uefi::Result {
+    let mut sfs = boot::open_protocol_exclusive::<SimpleFileSystem>(volume()?)?;
+    let root = sfs.open_volume()?;
+    let mut dir = if path.is_empty() {
+        root
+    } else {
+        let mut root = root;
+        let mut path_buf = [0_u16; MAX_PATH_SIZE + 1];
+        let path = CStr16::from_str_with_buf(path, &mut path_buf)
+            .map_err(|_| uefi::Error::from(Status::INVALID_PARAMETER))?;
+        root.open(path, FileMode::Read, FileAttribute::empty())?
+            .into_directory()
+            .ok_or(uefi::Error::from(Status::INVALID_PARAMETER))?
+    };
+
+    let mut info_buf = [0_u8; 512];
+    while let Some(info) = dir
+        .read_entry(&mut info_buf)
+        .map_err(|e| uefi::Error::from(e.status()))?
+    {
+        f(info);
+    }
+
+    Ok(())
+}
+
+/// Creates the file on the volume replacing the existing one, and the
+/// missing directories on its path.
+pub fn create_file(path: &str) -> uefi::Result<RegularFile> {
+    let mut sfs = boot::open_protocol_exclusive::<SimpleFileSystem>(volume()?)?;
+    let mut dir = sfs.open_volume()?;
+
+    let mut components = path.split(['/', '\\']).filter(|c| !c.is_empty()).peekable();
+    while let Some(component) = components.next() {
+        let mut name_buf = [0_u16; MAX_PATH_SIZE + 1];
+        let name = CStr16::from_str_with_buf(component, &mut name_buf)
+            .map_err(|_| uefi::Error::from(Status::INVALID_PARAMETER))?;
+
+        if components.peek().is_some() {
+            dir = dir
+                .open(name, FileMode::CreateReadWrite, FileAttribute::DIRECTORY)?
+                .into_directory()
+                .ok_or(uefi::Error::from(Status::INVALID_PARAMETER))?;
+        } else {
+            if let Ok(existing) = dir.open(name, FileMode::ReadWrite, FileAttribute::empty()) {
+                existing.delete()?;
+            }
+            return dir
+                .open(name, FileMode::CreateReadWrite, FileAttribute::empty())?
+                .into_regular_file()
+                .ok_or(uefi::Error::from(Status::INVALID_PARAMETER));
+        }
+    }
+
+    Err(Status::INVALID_PARAMETER.into())
+}
+
+/// Frees the pages allocated by [`read_file`].
+pub fn free_file(data: &'static mut [u8]) {
+    if let Some(ptr) = NonNull::new(data.as_mut_ptr()) {
+        // SAFETY: the pages were allocated by `read_file`, and the slice is consumed.
+        unsafe { boot::free_pages(ptr, data.len().div_ceil(PAGE_SIZE)) }.ok();
+    }
+}
@@ -0,0 +1,123 @@
+//! Enabling and trapping the FP/SIMD registers.
+//!
+//! The compiler emits the FP/SIMD instructions wherever it sees fit unless
+//! the target disables them, and those trap unless the access is enabled:
+//! with `CPACR_EL1.FPEN` at EL1, with `CR0.TS` and `CR0.EM` on x86_64 where
+//! the SSE state also needs `CR4.OSFXSR`, and `XCR0.SSE` if `xsave` is on.
+//! The loader code that needs the FP/SIMD registers runs under a [`FpGuard`]
+//! which traps the access again when dropped if it has been trapped before.
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::access::WritableRegister;
+    use crate::aarch64_regs::AccessTrap;
+    use crate::aarch64_regs::ArchFeatureAccessControlEl1;
+
+    fn cpacr() -> ArchFeatureAccessControlEl1 {
+        let mut cpacr = ArchFeatureAccessControlEl1::new();
+        cpacr.load();
+        cpacr
+    }
+
+    pub fn is_enabled() -> bool {
+        matches!(cpacr().fpen(), AccessTrap::None)
+    }
+
+    pub fn enable() {
+        cpacr().with_fpen(AccessTrap::None).store();
+    }
+
+    pub fn trap() {
+        cpacr().with_fpen(AccessTrap::All).store();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use crate::x86_64_regs::access::X86Register;
+    use crate::x86_64_regs::Cr0;
+    use crate::x86_64_regs::Cr4;
+    use crate::x86_64_regs::Xcr0;
+
+    fn cr0() -> Cr0 {
+        let mut cr0 = Cr0::new();
+        cr0.load();
+        cr0
+    }
+
+    fn cr4() -> Cr4 {
+        let mut cr4 = Cr4::new();
+        cr4.load();
+        cr4
+    }
+
+    pub fn is_enabled() -> bool {
+        let cr0 = cr0();
+        !cr0.ts() && !cr0.em() && cr4().osfxsr()
+    }
+
+    /// The SSE state is enabled for good, only `CR0.TS` is toggled later.
+    pub fn enable() {
+        cr0().with_ts(false).with_em(false).with_mp(true).store();
+
+        let mut cr4 = cr4();
+        if !cr4.osfxsr() || !cr4.osxmmexcpt() {
+            cr4.set_osfxsr(true);
+            cr4.set_osxmmexcpt(true);
+            cr4.store();
+        }
+        if cr4.osxsave() {
+            let mut xcr0 = Xcr0::new();
+            xcr0.load();
+            if !xcr0.sse() {
+                xcr0.with_x87(true).with_sse(true).store();
+            }
+        }
+    }
+
+    pub fn trap() {
+        cr0().with_ts(true).store();
+    }
+}
+
+/// Whether the FP/SIMD instructions can run without trapping.
+pub fn is_enabled() -> bool {
+    arch::is_enabled()
+}
+
+pub fn enable() {
+    arch::enable()
+}
+
+/// Makes the FP/SIMD instructions trap.
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub fn trap() {
+    arch::trap()
+}
+
+/// Enables the FP/SIMD access until dropped. Nothing needs it on x86_64,
+/// the UEFI target is soft-float.
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub struct FpGuard {
+    was_enabled: bool,
+}
+
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+impl FpGuard {
+    pub fn new() -> Self {
+        let was_enabled = is_enabled();
+        if !was_enabled {
+            enable();
+        }
+        Self { was_enabled }
+    }
+}
+
+impl Drop for FpGuard {
+    fn drop(&mut self) {
+        if !self.was_enabled {
+            trap();
+        }
+    }
+}
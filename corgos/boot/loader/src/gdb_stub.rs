@@ -0,0 +1,650 @@
+//! The GDB stub on a serial port of its own, `gdb_stub = com2` or
+//! `gdb_stub = "pl011@9040000"` in the configuration, see the `gdb_stub`
+//! crate. The loader stops for GDB to attach once it has logged the image
+//! base GDB needs to load the loader symbols at, and then on the
+//! breakpoints GDB inserts, after the single-steps, on the faults, and
+//! wherever [`breakpoint`] is called.
+//!
+//! While the boot services are active, the exceptions come through
+//! `EFI_DEBUG_SUPPORT_PROTOCOL`, the firmware saves the registers for the
+//! callback and restores them on return. After exiting the boot services,
+//! the loader exception handlers hand the exceptions over with [`stop`].
+//!
+//! The memory GDB asks for is looked up in the page tables first as GDB
+//! reads past the stack and follows the bogus frame pointers, and a fault
+//! in the stub would not come back. The breakpoints are written through
+//! the read-only mappings of the code: with `CR0.WP` cleared on x86_64,
+//! and by making the page writable for the time on aarch64.
+
+use ::gdb_stub::Connection;
+use ::gdb_stub::GdbStub;
+use ::gdb_stub::Memory;
+use ::gdb_stub::MemoryFault;
+use ::gdb_stub::Registers;
+use ::gdb_stub::Resume;
+use boot_logger::BootLoaderConfig;
+use boot_logger::GdbStubDevice;
+use boot_logger::LogDevice;
+use core::arch::asm;
+use poll_uart::BaudDivisor;
+use poll_uart::ComPort;
+use poll_uart::ComPortIo;
+use poll_uart::Pl011;
+use sync::Spinlock;
+use uefi::boot;
+use uefi::proto::debug::DebugSupport;
+use uefi::runtime;
+use uefi::runtime::ResetType;
+use uefi::Status;
+
+pub use arch::stop;
+
+static STUB: Spinlock<Option<GdbStub<Port>>> = Spinlock::new(None);
+
+/// The serial port GDB is on.
+enum Port {
+    Com(ComPort),
+    Pl011(Pl011),
+}
+
+impl Connection for Port {
+    fn read_byte(&mut self) -> u8 {
+        loop {
+            let byte = match self {
+                Port::Com(port) => port.try_receive_byte(),
+                Port::Pl011(uart) => uart.try_receive_byte(),
+            };
+            if let Some(byte) = byte {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self {
+            Port::Com(port) => port.send_byte(byte),
+            Port::Pl011(uart) => uart.send_byte(byte),
+        }
+    }
+}
+
+/// The memory as the page tables the firmware has set up map it.
+struct LoaderMemory;
+
+impl Memory for LoaderMemory {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), MemoryFault> {
+        for (addr, byte) in (addr..).zip(buf.iter_mut()) {
+            if !arch::is_mapped(addr) {
+                return Err(MemoryFault { addr });
+            }
+            // SAFETY: the address is mapped.
+            *byte = unsafe { (addr as *const u8).read_volatile() };
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), MemoryFault> {
+        for (addr, &byte) in (addr..).zip(data) {
+            arch::write_byte(addr, byte).ok_or(MemoryFault { addr })?;
+        }
+        arch::sync_instruction_cache(addr, data.len() as u64);
+        Ok(())
+    }
+}
+
+/// Sets the stub up on the configured port, and stops for GDB to attach.
+pub fn start(config: &BootLoaderConfig) {
+    let shares_log = match (config.gdb_stub, &config.log_device) {
+        (GdbStubDevice::Com1, LogDevice::Com1) | (GdbStubDevice::Com2, LogDevice::Com2) => true,
+        (GdbStubDevice::Pl011(stub), LogDevice::Pl011(log)) => stub == *log,
+        _ => false,
+    };
+    if shares_log {
+        log::warn!(
+            "The GDB stub cannot share {:x?} with the log",
+            config.gdb_stub
+        );
+        return;
+    }
+    let port = match config.gdb_stub {
+        GdbStubDevice::None => return,
+        GdbStubDevice::Com1 => Port::Com(ComPort::new(ComPortIo::Com1, BaudDivisor::Baud115200)),
+        GdbStubDevice::Com2 => Port::Com(ComPort::new(ComPortIo::Com2, BaudDivisor::Baud115200)),
+        GdbStubDevice::Pl011(base_addr) => Port::Pl011(Pl011::new(base_addr)),
+    };
+    *STUB.lock() = Some(GdbStub::new(port));
+
+    if let Err(e) = register_callbacks() {
+        log::warn!("Cannot take the exceptions over for the GDB stub: {e:?}");
+        *STUB.lock() = None;
+        return;
+    }
+    log::info!(
+        "GDB stub on {:x?}, waiting for GDB to attach",
+        config.gdb_stub
+    );
+    breakpoint();
+}
+
+fn register_callbacks() -> uefi::Result {
+    let handle = boot::get_handle_for_protocol::<DebugSupport>()?;
+    let mut debug_support = boot::open_protocol_exclusive::<DebugSupport>(handle)?;
+    for &(exception, _) in arch::EXCEPTIONS.iter() {
+        // SAFETY: the callback follows the protocol, and stays in the loader
+        // image until the kernel installs its own exception handlers.
+        unsafe {
+            debug_support.register_exception_callback(
+                0,
+                Some(arch::exception_callback),
+                exception,
+            )?
+        };
+    }
+    Ok(())
+}
+
+/// Stops in GDB if the stub is on.
+pub fn breakpoint() {
+    if STUB.lock().is_none() {
+        return;
+    }
+    // SAFETY: the stub takes the exception, and resumes after the
+    // instruction.
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        asm!("int3", options(nomem, nostack))
+    };
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        // GDB writes `brk #0`, anything else is stepped over on resuming.
+        asm!("brk #0xf000", options(nomem, nostack))
+    };
+}
+
+/// Serves GDB with the registers, `None` if the stub is off.
+fn enter<R: Registers>(signal: u8, registers: &mut R) -> Option<Resume> {
+    let Some(mut stub) = STUB.try_lock() else {
+        panic!("Exception in the GDB stub, signal {signal}");
+    };
+    let resume = stub.as_mut()?.stopped(signal, registers, &mut LoaderMemory);
+    if resume == Resume::Kill {
+        runtime::reset(ResetType::COLD, Status::ABORTED, None);
+    }
+    Some(resume)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::enter;
+    use crate::x86_64_regs::access::X86Register;
+    use crate::x86_64_regs::Cr0;
+    use crate::x86_64_regs::Cr3;
+    use crate::x86_64_regs::Cr4;
+    use crate::x86_64_regs::PageTableEntry;
+    use exceptions::x86_64::ExceptionFrame;
+    use gdb_stub::Resume;
+    use gdb_stub::X86_64Registers;
+    use uefi::proto::debug::ExceptionType;
+    use uefi::proto::debug::SystemContext;
+
+    /// `RFLAGS.TF`, the debug exception comes after the next instruction.
+    const TRAP_FLAG: u64 = 1 << 8;
+
+    /// The exceptions the stub takes while the boot services are active,
+    /// and their vectors.
+    pub const EXCEPTIONS: [(ExceptionType, u64); 6] = [
+        (ExceptionType::EXCEPT_X64_DIVIDE_ERROR, 0),
+        (ExceptionType::EXCEPT_X64_DEBUG, 1),
+        (ExceptionType::EXCEPT_X64_BREAKPOINT, 3),
+        (ExceptionType::EXCEPT_X64_INVALID_OPCODE, 6),
+        (ExceptionType::EXCEPT_X64_GP_FAULT, 13),
+        (ExceptionType::EXCEPT_X64_PAGE_FAULT, 14),
+    ];
+
+    /// `EFI_SYSTEM_CONTEXT_X64`
+    #[repr(C)]
+    struct SystemContextX64 {
+        exception_data: u64,
+        fx_save_state: [u8; 512],
+        dr0: u64,
+        dr1: u64,
+        dr2: u64,
+        dr3: u64,
+        dr6: u64,
+        dr7: u64,
+        cr0: u64,
+        cr1: u64,
+        cr2: u64,
+        cr3: u64,
+        cr4: u64,
+        cr8: u64,
+        rflags: u64,
+        ldtr: u64,
+        tr: u64,
+        gdtr: [u64; 2],
+        idtr: [u64; 2],
+        rip: u64,
+        gs: u64,
+        fs: u64,
+        es: u64,
+        ds: u64,
+        cs: u64,
+        ss: u64,
+        rdi: u64,
+        rsi: u64,
+        rbp: u64,
+        rsp: u64,
+        rbx: u64,
+        rdx: u64,
+        rcx: u64,
+        rax: u64,
+        r8: u64,
+        r9: u64,
+        r10: u64,
+        r11: u64,
+        r12: u64,
+        r13: u64,
+        r14: u64,
+        r15: u64,
+    }
+
+    pub unsafe extern "efiapi" fn exception_callback(
+        exception: ExceptionType,
+        context: SystemContext,
+    ) {
+        let Some(&(_, vector)) = EXCEPTIONS.iter().find(|&&(e, _)| e == exception) else {
+            return;
+        };
+        // SAFETY: the members of the union all point to the registers the
+        // firmware restores on return, this is the x64 one.
+        let context =
+            unsafe { &mut *core::mem::transmute::<SystemContext, *mut SystemContextX64>(context) };
+
+        let mut frame = ExceptionFrame {
+            cr2: context.cr2,
+            r15: context.r15,
+            r14: context.r14,
+            r13: context.r13,
+            r12: context.r12,
+            r11: context.r11,
+            r10: context.r10,
+            r9: context.r9,
+            r8: context.r8,
+            rbp: context.rbp,
+            rdi: context.rdi,
+            rsi: context.rsi,
+            rdx: context.rdx,
+            rcx: context.rcx,
+            rbx: context.rbx,
+            rax: context.rax,
+            vector,
+            error_code: context.exception_data,
+            rip: context.rip,
+            cs: context.cs,
+            rflags: context.rflags,
+            rsp: context.rsp,
+            ss: context.ss,
+        };
+        if !stop(&mut frame) {
+            return;
+        }
+        context.r15 = frame.r15;
+        context.r14 = frame.r14;
+        context.r13 = frame.r13;
+        context.r12 = frame.r12;
+        context.r11 = frame.r11;
+        context.r10 = frame.r10;
+        context.r9 = frame.r9;
+        context.r8 = frame.r8;
+        context.rbp = frame.rbp;
+        context.rdi = frame.rdi;
+        context.rsi = frame.rsi;
+        context.rdx = frame.rdx;
+        context.rcx = frame.rcx;
+        context.rbx = frame.rbx;
+        context.rax = frame.rax;
+        context.rip = frame.rip;
+        context.rflags = frame.rflags;
+        context.rsp = frame.rsp;
+        // The single-step status bit is sticky.
+        context.dr6 = 0;
+    }
+
+    fn signal(vector: u64) -> u8 {
+        match vector {
+            0 | 16 | 19 => gdb_stub::SIGFPE,
+            1 | 3 => gdb_stub::SIGTRAP,
+            2 => gdb_stub::SIGINT,
+            6 => gdb_stub::SIGILL,
+            17 => gdb_stub::SIGBUS,
+            _ => gdb_stub::SIGSEGV,
+        }
+    }
+
+    /// Stops in GDB with the registers in the frame, `false` if the stub
+    /// is off.
+    pub fn stop(frame: &mut ExceptionFrame) -> bool {
+        let mut registers = X86_64Registers {
+            rax: frame.rax,
+            rbx: frame.rbx,
+            rcx: frame.rcx,
+            rdx: frame.rdx,
+            rsi: frame.rsi,
+            rdi: frame.rdi,
+            rbp: frame.rbp,
+            rsp: frame.rsp,
+            r8: frame.r8,
+            r9: frame.r9,
+            r10: frame.r10,
+            r11: frame.r11,
+            r12: frame.r12,
+            r13: frame.r13,
+            r14: frame.r14,
+            r15: frame.r15,
+            rip: frame.rip,
+            eflags: frame.rflags as u32,
+            cs: frame.cs as u32,
+            ss: frame.ss as u32,
+            ..Default::default()
+        };
+        let Some(resume) = enter(signal(frame.vector), &mut registers) else {
+            return false;
+        };
+
+        frame.rax = registers.rax;
+        frame.rbx = registers.rbx;
+        frame.rcx = registers.rcx;
+        frame.rdx = registers.rdx;
+        frame.rsi = registers.rsi;
+        frame.rdi = registers.rdi;
+        frame.rbp = registers.rbp;
+        frame.rsp = registers.rsp;
+        frame.r8 = registers.r8;
+        frame.r9 = registers.r9;
+        frame.r10 = registers.r10;
+        frame.r11 = registers.r11;
+        frame.r12 = registers.r12;
+        frame.r13 = registers.r13;
+        frame.r14 = registers.r14;
+        frame.r15 = registers.r15;
+        frame.rip = registers.rip;
+        frame.rflags = match resume {
+            Resume::Step => registers.eflags as u64 | TRAP_FLAG,
+            _ => registers.eflags as u64 & !TRAP_FLAG,
+        };
+        true
+    }
+
+    /// Whether the address is mapped, and if writable through all the
+    /// levels.
+    fn lookup(addr: u64) -> Option<bool> {
+        let mut cr4 = Cr4::new();
+        cr4.load();
+        let levels = if cr4.la57() { 5 } else { 4 };
+        // The non-canonical addresses fault whatever the tables say.
+        let shift = 64 - (12 + 9 * levels);
+        if ((addr << shift) as i64 >> shift) as u64 != addr {
+            return None;
+        }
+
+        let mut cr3 = Cr3::new();
+        cr3.load();
+        let mut table = cr3.pml_pfn() << 12;
+        let mut writable = true;
+        for level in (0..levels).rev() {
+            let index = (addr >> (12 + 9 * level)) & 0x1ff;
+            // SAFETY: the firmware identity-maps the page tables.
+            let entry =
+                PageTableEntry::from(unsafe { (table as *const u64).add(index as usize).read() });
+            if !entry.present() {
+                return None;
+            }
+            writable &= entry.writable();
+            // PDPT and PD entries may map 1GiB and 2MiB pages.
+            if level == 0 || (level <= 2 && entry.page_size()) {
+                return Some(writable);
+            }
+            table = entry.address_pfn() << 12;
+        }
+        None
+    }
+
+    pub fn is_mapped(addr: u64) -> bool {
+        lookup(addr).is_some()
+    }
+
+    pub fn write_byte(addr: u64, byte: u8) -> Option<()> {
+        let writable = lookup(addr)?;
+        let mut cr0 = Cr0::new();
+        cr0.load();
+        // The supervisor writes ignore the read-only mappings then.
+        if !writable {
+            cr0.with_wp(false).store();
+        }
+        // SAFETY: the address is mapped.
+        unsafe { (addr as *mut u8).write_volatile(byte) };
+        if !writable {
+            cr0.store();
+        }
+        Some(())
+    }
+
+    /// The instruction fetches see the writes on x86_64.
+    pub fn sync_instruction_cache(_addr: u64, _len: u64) {}
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use super::enter;
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::access::WritableRegister;
+    use crate::aarch64_regs::MonitorDebugSystemControlEl1;
+    use crate::aarch64_regs::OsLockAccessEl1;
+    use crate::aarch64_regs::PageTableEntry;
+    use crate::aarch64_regs::TranslationBase0El1;
+    use core::arch::asm;
+    use exceptions::aarch64::ExceptionClass;
+    use exceptions::aarch64::ExceptionFrame;
+    use gdb_stub::Aarch64Registers;
+    use gdb_stub::Resume;
+    use uefi::proto::debug::ExceptionType;
+    use uefi::proto::debug::SystemContext;
+
+    /// `SPSR.SS`, the single-step is active-not-pending after returning.
+    const SPSR_SS: u64 = 1 << 21;
+    /// `SPSR.D`, the debug exceptions are masked.
+    const SPSR_D: u64 = 1 << 9;
+    /// `AP[2]` of the page and the block descriptors.
+    const AP_READ_ONLY: u64 = 1 << 7;
+
+    /// The exceptions the stub takes while the boot services are active,
+    /// the syndrome tells which one it is.
+    pub const EXCEPTIONS: [(ExceptionType, u64); 1] =
+        [(ExceptionType::EXCEPT_AARCH64_SYNCHRONOUS_EXCEPTIONS, 0)];
+
+    /// `EFI_SYSTEM_CONTEXT_AARCH64`
+    #[repr(C)]
+    struct SystemContextAarch64 {
+        /// `x29` is `FP`, `x30` is `LR`.
+        x: [u64; 31],
+        sp: u64,
+        v: [[u64; 2]; 32],
+        elr: u64,
+        spsr: u64,
+        fpsr: u64,
+        esr: u64,
+        far: u64,
+    }
+
+    pub unsafe extern "efiapi" fn exception_callback(
+        exception: ExceptionType,
+        context: SystemContext,
+    ) {
+        if exception != ExceptionType::EXCEPT_AARCH64_SYNCHRONOUS_EXCEPTIONS {
+            return;
+        }
+        // SAFETY: the members of the union all point to the registers the
+        // firmware restores on return, this is the AArch64 one.
+        let context = unsafe {
+            &mut *core::mem::transmute::<SystemContext, *mut SystemContextAarch64>(context)
+        };
+
+        let mut frame = ExceptionFrame {
+            x: context.x,
+            sp: context.sp,
+            elr: context.elr,
+            spsr: context.spsr,
+            esr: context.esr,
+            far: context.far,
+        };
+        if !stop(&mut frame) {
+            return;
+        }
+        context.x = frame.x;
+        context.sp = frame.sp;
+        context.elr = frame.elr;
+        context.spsr = frame.spsr;
+    }
+
+    /// Stops in GDB with the registers in the frame of a synchronous
+    /// exception, `false` if the stub is off.
+    pub fn stop(frame: &mut ExceptionFrame) -> bool {
+        let esr = frame.syndrome();
+        let signal = match esr.class() {
+            ExceptionClass::Brk
+            | ExceptionClass::Breakpoint
+            | ExceptionClass::SoftwareStep
+            | ExceptionClass::Watchpoint => gdb_stub::SIGTRAP,
+            ExceptionClass::Unknown => gdb_stub::SIGILL,
+            ExceptionClass::PcAlignment | ExceptionClass::SpAlignment => gdb_stub::SIGBUS,
+            ExceptionClass::FpException => gdb_stub::SIGFPE,
+            _ => gdb_stub::SIGSEGV,
+        };
+        let mut registers = Aarch64Registers {
+            x: frame.x,
+            sp: frame.sp,
+            pc: frame.elr,
+            cpsr: frame.spsr as u32,
+        };
+        let Some(resume) = enter(signal, &mut registers) else {
+            return false;
+        };
+
+        // The compiled-in breakpoints are stepped over, GDB puts the
+        // instructions back in place of its own.
+        if esr.class() == ExceptionClass::Brk
+            && esr.iss() & 0xffff != 0
+            && registers.pc == frame.elr
+        {
+            registers.pc += 4;
+        }
+        frame.x = registers.x;
+        frame.sp = registers.sp;
+        frame.elr = registers.pc;
+        frame.spsr = frame.spsr & !0xffff_ffff | registers.cpsr as u64;
+
+        let mut mdscr = MonitorDebugSystemControlEl1::new();
+        mdscr.load();
+        if resume == Resume::Step {
+            // Nothing is stepped with the OS lock set.
+            OsLockAccessEl1::new().with_oslk(false).store();
+            mdscr.with_ss(true).with_kde(true).store();
+            frame.spsr = (frame.spsr | SPSR_SS) & !SPSR_D;
+        } else {
+            mdscr.with_ss(false).store();
+            frame.spsr &= !SPSR_SS;
+        }
+        true
+    }
+
+    /// The page or the block descriptor mapping the address.
+    fn leaf_entry(addr: u64) -> Option<*mut u64> {
+        if addr >> 48 != 0 {
+            return None;
+        }
+        let mut ttbr0_el1 = TranslationBase0El1::new();
+        ttbr0_el1.load();
+        let mut table = ttbr0_el1.baddr();
+        for level in 0..4 {
+            let index = (addr >> (39 - 9 * level)) & 0x1ff;
+            let entry = (table as *mut u64).wrapping_add(index as usize);
+            // SAFETY: the firmware identity-maps the page tables.
+            let descriptor = unsafe { entry.read_volatile() };
+            if descriptor & 1 == 0 {
+                return None;
+            }
+            // The blocks are at the levels 1 and 2, the pages at 3.
+            if level == 3 || descriptor & 0b11 == 0b01 {
+                return (level > 0).then_some(entry);
+            }
+            table = PageTableEntry::from(descriptor).next_table_pfn() << 12;
+        }
+        None
+    }
+
+    fn flush_tlb(addr: u64) {
+        // SAFETY: invalidating the translations is always safe.
+        unsafe {
+            asm!(
+                "dsb ishst",
+                "tlbi vaae1is, {page}",
+                "dsb ish",
+                "isb",
+                page = in(reg) addr >> 12,
+                options(nostack)
+            )
+        };
+    }
+
+    pub fn is_mapped(addr: u64) -> bool {
+        leaf_entry(addr).is_some()
+    }
+
+    pub fn write_byte(addr: u64, byte: u8) -> Option<()> {
+        let entry = leaf_entry(addr)?;
+        // SAFETY: the firmware identity-maps the page tables.
+        let descriptor = unsafe { entry.read_volatile() };
+        let read_only = descriptor & AP_READ_ONLY != 0;
+        if read_only {
+            // SAFETY: the loader is the only one running.
+            unsafe { entry.write_volatile(descriptor & !AP_READ_ONLY) };
+            flush_tlb(addr);
+        }
+        // SAFETY: the address is mapped, and writable now.
+        unsafe { (addr as *mut u8).write_volatile(byte) };
+        if read_only {
+            // SAFETY: puts the descriptor back.
+            unsafe { entry.write_volatile(descriptor) };
+            flush_tlb(addr);
+        }
+        Some(())
+    }
+
+    /// Cleans the data cache and invalidates the instruction cache to the
+    /// point of unification for the range.
+    pub fn sync_instruction_cache(addr: u64, len: u64) {
+        let ctr_el0: u64;
+        // SAFETY: reading the cache type register has no side effects.
+        unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr_el0, options(nomem, nostack)) };
+        // The smallest lines in words.
+        let line_size = 4 << core::cmp::min(ctr_el0 & 0xf, (ctr_el0 >> 16) & 0xf);
+        let mut line = addr & !(line_size - 1);
+        while line < addr + len {
+            // SAFETY: the cache maintenance by the address does not change
+            // the memory.
+            unsafe {
+                asm!(
+                    "dc cvau, {line}",
+                    "dsb ish",
+                    "ic ivau, {line}",
+                    line = in(reg) line,
+                    options(nostack)
+                )
+            };
+            line += line_size;
+        }
+        // SAFETY: the barriers have no side effects.
+        unsafe { asm!("dsb ish", "isb", options(nomem, nostack)) };
+    }
+}
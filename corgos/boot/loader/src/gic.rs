@@ -0,0 +1,213 @@
+//! GICv3/v4 discovery.
+//!
+//! The distributor and the redistributor ranges come from the ACPI MADT.
+//! The redistributor of the boot processor is found by walking the frames
+//! and matching `GICR_TYPER.Affinity_Value` against MPIDR, and is woken up
+//! so that the kernel can take the timer interrupt without touching the
+//! power management of the GIC first. The distributor and that frame are
+//! mapped in the device window for the kernel.
+
+use crate::paging::PageTables;
+use crate::timer::Deadline;
+use acpi::madt::Madt;
+use acpi::madt::MadtEntry;
+use acpi::AcpiHandler;
+use acpi::AcpiTables;
+use bootinfo::Gic;
+use bootinfo::GicrRange;
+use bootinfo::MAX_GICR_RANGES;
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+
+/// Peripheral ID2, `ArchRev` is in the bits `[7:4]`.
+const GICD_PIDR2: Register<u32, ReadOnly> = Register::new(0xffe8);
+const GICR_PIDR2: Register<u32, ReadOnly> = Register::new(0xffe8);
+const GICR_TYPER: Register<u64, ReadOnly> = Register::new(0x0008);
+const GICR_WAKER: Register<u32, ReadWrite> = Register::new(0x0014);
+
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+/// The distributor registers, without the GICv4.1 virtual LPI ones.
+const GICD_SIZE: u64 = 0x1_0000;
+/// The RD_base and SGI_base frames.
+const GICR_FRAME_SIZE_V3: u64 = 0x2_0000;
+/// Plus VLPI_base and the reserved frame.
+const GICR_FRAME_SIZE_V4: u64 = 0x4_0000;
+
+/// How long the redistributor is given to wake up, in nanoseconds.
+const WAKE_TIMEOUT_NS: u64 = 10_000_000;
+
+fn registers(base: u64) -> RegisterBlock {
+    // SAFETY: the GIC registers are identity-mapped by the firmware.
+    unsafe { RegisterBlock::new(base) }
+}
+
+fn arch_rev(pidr2: u32) -> u32 {
+    (pidr2 >> 4) & 0xf
+}
+
+/// Converts the MPIDR affinity to the `Aff3.Aff2.Aff1.Aff0` layout of
+/// `GICR_TYPER[63:32]`.
+fn mpidr_to_typer_affinity(mpidr: u64) -> u64 {
+    ((mpidr >> 8) & 0xff00_0000) | (mpidr & 0xff_ffff)
+}
+
+/// Finds the redistributor frame of the processor with the given MPIDR.
+fn find_redistributor(ranges: &[GicrRange], mpidr: u64) -> Option<u64> {
+    let affinity = mpidr_to_typer_affinity(mpidr);
+    for range in ranges {
+        let mut frame = range.base;
+        while frame < range.base + range.length {
+            let regs = registers(frame);
+            if !matches!(arch_rev(regs.read(GICR_PIDR2)), 3 | 4) {
+                break;
+            }
+
+            let typer = regs.read(GICR_TYPER);
+            if typer >> 32 == affinity {
+                return Some(frame);
+            }
+            if typer & GICR_TYPER_LAST != 0 {
+                break;
+            }
+            frame += if typer & GICR_TYPER_VLPIS != 0 {
+                GICR_FRAME_SIZE_V4
+            } else {
+                GICR_FRAME_SIZE_V3
+            };
+        }
+    }
+
+    None
+}
+
+fn wake_up_redistributor(gicr_base: u64) -> bool {
+    let regs = registers(gicr_base);
+    regs.modify(GICR_WAKER, |waker| waker & !GICR_WAKER_PROCESSOR_SLEEP);
+
+    let deadline = Deadline::arm(WAKE_TIMEOUT_NS);
+    loop {
+        let awake = regs.read(GICR_WAKER) & GICR_WAKER_CHILDREN_ASLEEP == 0;
+        if awake || deadline.expired() {
+            deadline.disarm();
+            return awake;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Finds the GIC, and wakes up the redistributor of the boot processor if
+/// `wake_redistributor` is set.
+pub fn discover_gic(
+    tables: &AcpiTables<impl AcpiHandler>,
+    boot_mpidr: u64,
+    wake_redistributor: bool,
+    gic: &mut Gic,
+) {
+    *gic = Gic::default();
+
+    let Ok(madt) = tables.find_table::<Madt>() else {
+        log::warn!("No MADT, cannot find the GIC");
+        return;
+    };
+
+    // The redistributor frames of the processors, used if there are no
+    // discovery ranges in the MADT (the always-on power domain case).
+    let mut gicc_ranges = [GicrRange::default(); MAX_GICR_RANGES];
+    let mut gicc_range_count = 0;
+    for entry in madt.entries() {
+        match entry {
+            MadtEntry::Gicd(e) => {
+                gic.gicd_base = e.physical_base_address;
+                log::info!(
+                    "GICD @ {:#016x}, version {} in MADT",
+                    gic.gicd_base,
+                    e.gic_version
+                );
+            }
+            MadtEntry::GicRedistributor(e) => {
+                if gic.gicr_range_count as usize >= MAX_GICR_RANGES {
+                    log::warn!("Too many GIC redistributor ranges");
+                    continue;
+                }
+                gic.gicr_ranges[gic.gicr_range_count as usize] = GicrRange {
+                    base: e.discovery_range_base_address,
+                    length: e.discovery_range_length as u64,
+                };
+                gic.gicr_range_count += 1;
+            }
+            MadtEntry::Gicc(e) if e.gicr_base_address != 0 => {
+                if gicc_range_count < MAX_GICR_RANGES {
+                    gicc_ranges[gicc_range_count] = GicrRange {
+                        base: e.gicr_base_address,
+                        length: GICR_FRAME_SIZE_V4,
+                    };
+                    gicc_range_count += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    if gic.gicr_range_count == 0 {
+        gic.gicr_ranges = gicc_ranges;
+        gic.gicr_range_count = gicc_range_count as u32;
+    }
+
+    if gic.gicd_base == 0 {
+        log::warn!("No GIC distributor in MADT");
+        return;
+    }
+
+    let version = arch_rev(registers(gic.gicd_base).read(GICD_PIDR2));
+    if !matches!(version, 3 | 4) {
+        log::warn!("GIC architecture revision {version} is not supported, expected 3 or 4");
+        *gic = Gic::default();
+        return;
+    }
+    gic.version = version;
+
+    for range in gic.gicr_ranges() {
+        log::info!(
+            "GICR range @ {:#016x}, {:#x} bytes",
+            range.base,
+            range.length
+        );
+    }
+
+    let Some(boot_gicr_base) = find_redistributor(gic.gicr_ranges(), boot_mpidr) else {
+        log::warn!("No GIC redistributor found for the boot processor {boot_mpidr:#x}");
+        return;
+    };
+    gic.boot_gicr_base = boot_gicr_base;
+    log::info!("GICv{version}, boot processor GICR @ {boot_gicr_base:#016x}");
+
+    if wake_redistributor {
+        if wake_up_redistributor(boot_gicr_base) {
+            gic.boot_gicr_awake = 1;
+        } else {
+            log::warn!("The boot processor GIC redistributor did not wake up");
+        }
+    }
+}
+
+/// Maps the distributor and the redistributor frame of the boot processor
+/// in the device window.
+pub fn map_gic(gic: &mut Gic, page_tables: &mut PageTables) {
+    if gic.version == 0 {
+        return;
+    }
+    gic.gicd_virt_base = page_tables.map_device(gic.gicd_base, GICD_SIZE);
+    if gic.boot_gicr_base != 0 {
+        gic.boot_gicr_virt_base = page_tables.map_device(gic.boot_gicr_base, GICR_FRAME_SIZE_V3);
+    }
+    log::info!(
+        "GICD registers @ {:#016x}, boot processor GICR @ {:#016x}",
+        gic.gicd_virt_base,
+        gic.boot_gicr_virt_base
+    );
+}
@@ -0,0 +1,207 @@
+//! The data passed over to the kernel, and the jump to its entry.
+//!
+//! The kernel starts with its page tables installed, on the stack from
+//! the boot info, with the interrupts masked, and with the linear map
+//! address of the boot info in the first argument register: `rdi` on
+//! x86_64, `x0` on aarch64.
+//!
+//! On aarch64 the kernel tables go to `TTBR1_EL1`, and the firmware
+//! identity mapping in `TTBR0_EL1` keeps the loader running until the
+//! jump. On x86_64 `CR3` holds both halves, so the page with the code
+//! switching the tables is identity-mapped in the kernel tables as well.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use crate::paging::PageTables;
+use bootinfo::BootInfo;
+use uefi::boot;
+use uefi::boot::AllocateType;
+
+/// Allocates the [`BootInfo`] in the memory the kernel can find in the
+/// memory map and reclaim later.
+pub fn allocate_boot_info() -> &'static mut BootInfo {
+    let pages = core::mem::size_of::<BootInfo>().div_ceil(0x1000);
+    let boot_info = boot::allocate_pages(AllocateType::AnyPages, memory_types::BOOT_INFO, pages)
+        .or_fail(
+            BootError::Handoff,
+            "Cannot allocate pages for the boot info",
+        )
+        .cast::<BootInfo>();
+
+    // SAFETY: the pages have just been allocated, and are large enough.
+    // The kernel reclaims the tail of the last page with the rest, so that
+    // is zeroed, too.
+    unsafe {
+        boot_info.cast::<u8>().write_bytes(0, pages * 0x1000);
+        boot_info.write(BootInfo::default());
+        &mut *boot_info.as_ptr()
+    }
+}
+
+/// Checks the processor can take the kernel tables, and maps what the jump
+/// needs. The boot services must be available.
+pub fn prepare_handoff(page_tables: &mut PageTables) {
+    arch::prepare_handoff(page_tables);
+}
+
+/// Switches to the kernel page tables and stack, and jumps to the kernel
+/// entry.
+pub fn enter_kernel(boot_info: &'static BootInfo) -> ! {
+    // The kernel checks the same with no console to tell why it stops.
+    boot_info.validate().or_fail(
+        BootError::Handoff,
+        "The boot info does not pass the kernel checks",
+    );
+    let boot_info_virt = boot_info.paging.linear_map_base + boot_info as *const _ as u64;
+    log::info!(
+        "Entering the kernel @ {:#016x}, stack top {:#016x}, boot info @ {boot_info_virt:#016x}",
+        boot_info.kernel.entry,
+        boot_info.kernel_stack.top
+    );
+
+    // SAFETY: the tables map the kernel image, the stack, and the boot
+    // info, the interrupts are masked, and nothing returns here.
+    unsafe {
+        arch::enter_kernel(
+            boot_info_virt,
+            boot_info.kernel_stack.top,
+            boot_info.paging.root_table,
+            boot_info.kernel.entry,
+        )
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use crate::paging::MapAttributes;
+    use crate::paging::MapKind;
+    use crate::paging::PageTables;
+    use crate::x86_64_regs::access::X86Register;
+    use crate::x86_64_regs::Efer;
+
+    const PAGE_SIZE: u64 = 0x1000;
+
+    // Aligned so it does not cross a page, only the one page it is in is
+    // identity-mapped in the kernel tables.
+    core::arch::global_asm!(
+        ".balign 64",
+        ".global corgos_enter_kernel",
+        "corgos_enter_kernel:",
+        "    mov     rsp, rsi",
+        "    mov     cr3, rdx",
+        "    xor     ebp, ebp",
+        "    jmp     rcx",
+    );
+
+    extern "sysv64" {
+        /// Takes the boot info in `rdi` and keeps it there for the kernel.
+        fn corgos_enter_kernel(boot_info: u64, stack_top: u64, root_table: u64, entry: u64) -> !;
+    }
+
+    pub fn prepare_handoff(page_tables: &mut PageTables) {
+        let trampoline = corgos_enter_kernel as usize as u64 & !(PAGE_SIZE - 1);
+        page_tables.map(
+            trampoline,
+            trampoline,
+            PAGE_SIZE,
+            MapAttributes {
+                kind: MapKind::Normal,
+                writable: false,
+                executable: true,
+            },
+        );
+
+        // The kernel tables have the no-execute bit set in the data
+        // mappings, that is reserved unless enabled.
+        let mut efer = Efer::new();
+        efer.load();
+        if !efer.nxe() {
+            efer.with_nxe(true).store();
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The tables map the entry, the stack, and the boot info.
+    pub unsafe fn enter_kernel(boot_info: u64, stack_top: u64, root_table: u64, entry: u64) -> ! {
+        // SAFETY: guaranteed by the caller, and the trampoline page is
+        // identity-mapped by `prepare_handoff`.
+        unsafe { corgos_enter_kernel(boot_info, stack_top, root_table, entry) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::access::SystemControlRegister;
+    use crate::aarch64_regs::access::SystemControlToken;
+    use crate::aarch64_regs::CurrentEl;
+    use crate::aarch64_regs::El;
+    use crate::aarch64_regs::MemoryAttributeIndirectionEl1;
+    use crate::aarch64_regs::TranslationBase1El1;
+    use crate::aarch64_regs::TranslationControlEl1;
+    use crate::aarch64_regs::TranslationGranule1;
+    use crate::boot_error::BootError;
+    use crate::paging::PageTables;
+    use core::arch::asm;
+
+    /// 48 bits of the virtual address space for the four levels.
+    const T1SZ: u64 = 16;
+    /// Write-back, read and write allocate.
+    const CACHEABLE_WBWA: u64 = 0b01;
+    const INNER_SHAREABLE: u64 = 0b11;
+
+    pub fn prepare_handoff(_page_tables: &mut PageTables) {
+        let mut current_el = CurrentEl::new();
+        current_el.load();
+        if !matches!(current_el.el(), El::EL1) {
+            BootError::Handoff.fail(format_args!(
+                "Running at {:?}, the kernel needs EL1",
+                current_el.el()
+            ));
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The tables map the entry, the stack, and the boot info.
+    pub unsafe fn enter_kernel(boot_info: u64, stack_top: u64, root_table: u64, entry: u64) -> ! {
+        // SAFETY: the lower half with the loader stays mapped through
+        // TTBR0_EL1, and the memory attributes have the same indices as
+        // the firmware ones.
+        let token = unsafe { SystemControlToken::new() };
+        MemoryAttributeIndirectionEl1::from_bits(crate::paging::MAIR).store(&token);
+        let mut tcr = TranslationControlEl1::new();
+        tcr.load();
+        tcr.with_t1sz(T1SZ)
+            .with_a1(0)
+            .with_epd1(0)
+            .with_irgn1(CACHEABLE_WBWA)
+            .with_orgn1(CACHEABLE_WBWA)
+            .with_sh1(INNER_SHAREABLE)
+            .with_tg1(TranslationGranule1::_4KB)
+            .store(&token);
+        TranslationBase1El1::new()
+            .with_baddr(root_table)
+            .store(&token);
+
+        // SAFETY: drops the stale translations of the upper half, and jumps
+        // with the stack and the boot info guaranteed by the caller.
+        unsafe {
+            asm!(
+                "tlbi   vmalle1",
+                "dsb    nsh",
+                "isb",
+                "mov    sp, {stack_top}",
+                "mov    x29, xzr",
+                "mov    x30, xzr",
+                "br     {entry}",
+                stack_top = in(reg) stack_top,
+                entry = in(reg) entry,
+                in("x0") boot_info,
+                options(noreturn)
+            )
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Masking the interrupts for good before the code that must not be
+//! interrupted: switching the page tables, and jumping to the kernel. The
+//! critical sections mask them with the `sync` locks instead.
+//!
+//! On aarch64, all of `DAIF` is masked. On x86_64, `RFLAGS.IF` is cleared.
+//!
+//! The asm blocks are not `nomem`, so that the compiler does not move the
+//! memory accesses across them.
+
+/// Masks the interrupts for good.
+pub fn mask() {
+    // SAFETY: masking the exceptions has no effect on the memory.
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("msr daifset, #0xf", options(nostack))
+    };
+    // SAFETY: clearing the interrupt flag has no effect on the memory.
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!("cli", options(nostack))
+    };
+}
@@ -0,0 +1,139 @@
+//! Kernel address space layout randomization.
+//!
+//! The entropy comes from `EFI_RNG_PROTOCOL` if the firmware has it, and
+//! from the processor otherwise: `rdrand` (or the timestamp counter) on
+//! x86_64, and the virtual counter on aarch64. The latter is not much of
+//! a secret yet still moves the kernel around from boot to boot.
+
+use uefi::boot;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::proto::rng::Rng;
+
+/// The kernel is placed at 2 MiB boundaries so it can be mapped with
+/// large pages.
+pub const KERNEL_ALIGN: u64 = 0x20_0000;
+
+/// The kernel is slid within this window above the address it has been
+/// linked at.
+const VIRT_WINDOW_SIZE: u64 = 0x4000_0000;
+
+/// The highest physical address to place the kernel below.
+const PHYS_WINDOW_END: u64 = 0x1_0000_0000_0000;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+fn firmware_random_u64() -> Option<u64> {
+    let handle = boot::get_handle_for_protocol::<Rng>().ok()?;
+    let mut rng = boot::open_protocol_exclusive::<Rng>(handle).ok()?;
+    let mut buf = [0_u8; 8];
+    rng.get_rng(None, &mut buf).ok()?;
+
+    Some(u64::from_le_bytes(buf))
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpu_random_u64() -> u64 {
+    use raw_cpuid::CpuId;
+
+    if CpuId::new()
+        .get_feature_info()
+        .is_some_and(|f| f.has_rdrand())
+    {
+        for _ in 0..10 {
+            let mut value = 0;
+            // SAFETY: `rdrand` is supported as reported by CPUID.
+            if unsafe { core::arch::x86_64::_rdrand64_step(&mut value) } == 1 {
+                return value;
+            }
+        }
+    }
+
+    log::warn!("No rdrand, using the timestamp counter for KASLR");
+    // SAFETY: reading the timestamp counter has no side effects.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_random_u64() -> u64 {
+    let counter: u64;
+    // SAFETY: reading the virtual counter has no side effects.
+    unsafe {
+        core::arch::asm!("mrs {}, cntvct_el0", out(reg) counter, options(nomem, nostack));
+    }
+
+    // The low bits change the fastest, spread them over the whole value.
+    counter.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32)
+}
+
+/// A random number, not necessarily of the cryptographic quality.
+pub fn random_u64() -> u64 {
+    firmware_random_u64().unwrap_or_else(|| {
+        log::warn!("No EFI_RNG_PROTOCOL, using the processor for KASLR");
+        cpu_random_u64()
+    })
+}
+
+/// Picks a random aligned virtual base for the kernel of `image_size` bytes
+/// linked at `link_base`.
+pub fn random_virt_base(link_base: u64, image_size: u64) -> u64 {
+    let image_size = image_size.next_multiple_of(KERNEL_ALIGN);
+    let slots = VIRT_WINDOW_SIZE.saturating_sub(image_size) / KERNEL_ALIGN + 1;
+
+    link_base + (random_u64() % slots) * KERNEL_ALIGN
+}
+
+/// Allocates `image_size` bytes of memory of the `memory_type` at a random
+/// aligned physical address, ending at or below `phys_end`. Returns `None`
+/// if there is no free memory region large enough.
+pub fn allocate_random_phys(
+    image_size: u64,
+    memory_type: MemoryType,
+    phys_end: u64,
+) -> Option<u64> {
+    let image_size = image_size.next_multiple_of(PAGE_SIZE);
+    let phys_end = phys_end.min(PHYS_WINDOW_END);
+    let memory_map = boot::memory_map(MemoryType::LOADER_DATA).ok()?;
+
+    // The aligned start addresses within a region form a range, count them
+    // all and pick one.
+    let slots_in = |start: u64, pages: u64| -> Option<(u64, u64)> {
+        let end = core::cmp::min(start + pages * PAGE_SIZE, phys_end);
+        let first = start.max(KERNEL_ALIGN).next_multiple_of(KERNEL_ALIGN);
+        let last = end.checked_sub(image_size)? & !(KERNEL_ALIGN - 1);
+        (first <= last).then(|| (first, (last - first) / KERNEL_ALIGN + 1))
+    };
+    let free_regions = || {
+        memory_map
+            .entries()
+            .filter(|e| e.ty == MemoryType::CONVENTIONAL)
+            .filter_map(|e| slots_in(e.phys_start, e.page_count))
+    };
+
+    let total_slots: u64 = free_regions().map(|(_, slots)| slots).sum();
+    if total_slots == 0 {
+        return None;
+    }
+
+    let mut slot = random_u64() % total_slots;
+    let (first, _) = free_regions().find(|&(_, slots)| {
+        if slot < slots {
+            true
+        } else {
+            slot -= slots;
+            false
+        }
+    })?;
+    let phys_base = first + slot * KERNEL_ALIGN;
+    drop(memory_map);
+
+    // The memory map has been freed, and the region might have been split,
+    // yet the slot is still free as nothing has been allocated since.
+    boot::allocate_pages(
+        boot::AllocateType::Address(phys_base),
+        memory_type,
+        (image_size / PAGE_SIZE) as usize,
+    )
+    .ok()
+    .map(|ptr| ptr.as_ptr() as u64)
+}
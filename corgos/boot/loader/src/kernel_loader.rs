@@ -0,0 +1,567 @@
+//! Loading the kernel image.
+//!
+//! The kernel is a static PIE, either ELF or PE32+ told apart by the magic,
+//! and may be compressed with LZ4. The hash in the config is of the file as
+//! it is stored.
+//! Its loadable segments (or sections) are copied to one contiguous physical
+//! range keeping their relative placement, and the relocations are applied
+//! for the virtual base the kernel is going to run at. Without KASLR that is
+//! the address the kernel has been linked at, and the relocations are no-ops.
+//!
+//! The mappings follow the segment (or section) flags: text is RX, rodata
+//! is R, data and bss are RW and never executable. A segment asking to be
+//! both writable and executable is refused unless allowed in the config.
+//! The linker script leaves a page between the segments, and that page is
+//! not mapped to catch the stray accesses.
+//!
+//! Without KASLR the image goes to the lowest 2 MiB-aligned free memory
+//! above 4 GiB if there is any, leaving the low memory to the devices that
+//! cannot address more. Some firmware mishandles the memory up there, and
+//! `load_below_4g` keeps the image below 4 GiB, with or without KASLR.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::boot_timing;
+use crate::decompress;
+use crate::files;
+#[cfg(target_arch = "aarch64")]
+use crate::fpu::FpGuard;
+use crate::kaslr;
+use crate::kaslr::KERNEL_ALIGN;
+use crate::kernel_symbols;
+use crate::memory_types;
+use crate::paging::MapAttributes;
+use crate::paging::MapKind;
+use crate::paging::PageTables;
+use crate::profiling;
+use crate::tpm;
+use crate::watchdog;
+use bootinfo::BootStage;
+use bootinfo::KernelImage;
+use bootinfo::KernelSymbols;
+use bootinfo::KERNEL_IMAGE_BSS_ZEROED;
+use elf::endian::LittleEndian;
+use elf::ElfBytes;
+use pe_image::PeImage;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+
+#[cfg(target_arch = "x86_64")]
+const R_RELATIVE: u32 = elf::abi::R_X86_64_RELATIVE;
+#[cfg(target_arch = "aarch64")]
+const R_RELATIVE: u32 = elf::abi::R_AARCH64_RELATIVE;
+
+const PAGE_SIZE: u64 = 0x1000;
+const FOUR_GIB: u64 = 0x1_0000_0000;
+
+/// Loads the kernel image, places it randomly if `kaslr` is set, and maps
+/// it into `page_tables`. If `expected_sha256` is set, the image file must
+/// have that hash before decompression. The image file is measured into
+/// the TPM if there is one. The image is kept in the physical memory below
+/// 4 GiB if `below_4g` is set. The writable and executable mappings are
+/// refused unless `allow_wx` is set. The function symbols of an ELF image
+/// go to `symbols`.
+pub fn load_kernel(
+    kernel_path: &str,
+    expected_sha256: Option<&str>,
+    kaslr: bool,
+    below_4g: bool,
+    allow_wx: bool,
+    page_tables: &mut PageTables,
+    symbols: &mut KernelSymbols,
+) -> KernelImage {
+    log::info!("Loading kernel image '{kernel_path}'");
+    let data = boot_timing::measure(BootStage::KernelRead, || {
+        files::read_file(kernel_path, MemoryType::LOADER_DATA)
+    })
+    .or_fail(BootError::KernelRead, "Cannot read the kernel image");
+
+    log::info!("Kernel file size {} bytes", data.len());
+    if let Some(expected_sha256) = expected_sha256 {
+        boot_timing::measure(BootStage::KernelVerify, || {
+            verify_sha256(kernel_path, data, expected_sha256)
+        });
+    }
+    tpm::measure_kernel(kernel_path, data);
+
+    let data = decompress::decompress_file(kernel_path, data, MemoryType::LOADER_DATA).or_fail(
+        BootError::KernelFormat,
+        "Cannot decompress the kernel image",
+    );
+
+    let kernel = boot_timing::measure(BootStage::KernelLoad, || {
+        if PeImage::is_pe(data) {
+            load_kernel_from_pe(data, kaslr, below_4g, allow_wx, page_tables)
+        } else {
+            load_kernel_from_elf(data, kaslr, below_4g, allow_wx, page_tables, symbols)
+        }
+    });
+    files::free_file(data);
+
+    kernel
+}
+
+/// Hashes in chunks to keep the watchdog at bay on slow machines.
+const HASH_CHUNK_SIZE: usize = 0x10_0000;
+
+fn verify_sha256(kernel_path: &str, data: &[u8], expected_sha256: &str) {
+    let expected = digest::sha256::parse_hex_digest(expected_sha256).unwrap_or_else(|| {
+        BootError::ConfigInvalid.fail(format_args!(
+            "'kernel_sha256 = {expected_sha256}' must be 64 hex digits, refusing to boot"
+        ))
+    });
+
+    let actual = {
+        // The SHA instructions use the SIMD registers. The x86_64 target
+        // is soft-float, and hashes in software.
+        #[cfg(target_arch = "aarch64")]
+        let _fp = FpGuard::new();
+        let mut hasher = digest::Sha256::new();
+        for chunk in data.chunks(HASH_CHUNK_SIZE) {
+            hasher.update(chunk);
+            watchdog::refresh();
+        }
+        hasher.finalize()
+    };
+
+    if actual != expected {
+        log::error!("Kernel image '{kernel_path}' SHA-256 mismatch");
+        log::error!("  expected: {expected_sha256}");
+        log::error!("  actual:   {}", HexDigest(&actual));
+        BootError::KernelVerify.fail(format_args!(
+            "Kernel image '{kernel_path}' is corrupted, refusing to boot"
+        ));
+    }
+
+    log::info!("Kernel image SHA-256 verified: {}", HexDigest(&actual));
+}
+
+struct HexDigest<'a>(&'a [u8]);
+
+impl core::fmt::Display for HexDigest<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+/// The attributes of a kernel mapping, checks W^X.
+fn kernel_map_attributes(
+    virt: u64,
+    writable: bool,
+    executable: bool,
+    allow_wx: bool,
+) -> MapAttributes {
+    if writable && executable {
+        if !allow_wx {
+            BootError::KernelWx.fail(format_args!(
+                "The kernel asks for a writable and executable mapping at {virt:#016x}, \
+                refusing to boot (set allow_wx_kernel to override)"
+            ));
+        }
+        log::warn!("Mapping the kernel at {virt:#016x} writable and executable");
+    }
+
+    MapAttributes {
+        kind: MapKind::Normal,
+        writable,
+        executable,
+    }
+}
+
+/// Reports the mappings without an unmapped page before them.
+fn check_guard_page(virt: u64, start: u64, mapped_end: u64, first: bool) {
+    if !first && start <= mapped_end {
+        log::warn!("No guard page before the kernel mapping at {virt:#016x}");
+    }
+}
+
+fn load_kernel_from_elf(
+    elf_data: &[u8],
+    kaslr: bool,
+    below_4g: bool,
+    allow_wx: bool,
+    page_tables: &mut PageTables,
+    symbols: &mut KernelSymbols,
+) -> KernelImage {
+    let elf = ElfBytes::<LittleEndian>::minimal_parse(elf_data).or_fail(
+        BootError::KernelFormat,
+        "Cannot parse the kernel image as ELF",
+    );
+
+    #[cfg(target_arch = "aarch64")]
+    if elf.ehdr.e_machine != elf::abi::EM_AARCH64 {
+        BootError::KernelArch.fail(format_args!("Wrong kernel target arch, expected aarch64"));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if elf.ehdr.e_machine != elf::abi::EM_X86_64 {
+        BootError::KernelArch.fail(format_args!("Wrong kernel target arch, expected x86_64"));
+    }
+
+    let segments = elf.segments().or_fail(
+        BootError::KernelFormat,
+        "Cannot find segments in the ELF file",
+    );
+
+    // First pass: see where the image is linked and how large it is.
+    let mut link_base = u64::MAX;
+    let mut link_end = 0;
+    for ph in segments {
+        log::info!(
+            "Found segment of {} bytes ({} in the image), PA: {:#016x}, VA: {:#016x}",
+            ph.p_memsz,
+            ph.p_filesz,
+            ph.p_paddr,
+            ph.p_vaddr
+        );
+
+        if ph.p_type != elf::abi::PT_LOAD {
+            continue;
+        }
+        let end = ph.p_vaddr.checked_add(ph.p_memsz).or_fail(
+            BootError::KernelLayout,
+            "A segment wraps around the address space",
+        );
+        link_base = link_base.min(ph.p_vaddr & !(PAGE_SIZE - 1));
+        link_end = link_end.max(end);
+
+        log::info!("Will load the segment");
+    }
+    if link_base >= link_end {
+        BootError::KernelLayout.fail(format_args!("No loadable segments in the kernel"));
+    }
+
+    let size = (link_end - link_base)
+        .checked_next_multiple_of(PAGE_SIZE)
+        .or_fail(BootError::KernelLayout, "The kernel image is too large");
+    log::info!("Loaded image size will be {size} bytes, rounded up to 4KiB");
+
+    let (phys_base, virt_base, image) = place_image(link_base, size, kaslr, below_4g);
+    let virt_slide = virt_base - link_base;
+
+    // Second pass: load the code and data, and map it. A page shared by two
+    // segments gets the permissions of the first one.
+    let mut mapped_end = link_base;
+    for ph in segments {
+        if ph.p_type != elf::abi::PT_LOAD {
+            continue;
+        }
+        log::info!(
+            "Loading segment of {} bytes ({} in the image), PA: {:#016x}, VA: {:#016x}",
+            ph.p_memsz,
+            ph.p_filesz,
+            ph.p_paddr,
+            ph.p_vaddr
+        );
+
+        let src = ph
+            .p_offset
+            .checked_add(ph.p_filesz)
+            .and_then(|end| elf_data.get(ph.p_offset as usize..end as usize))
+            .or_fail(
+                BootError::KernelFormat,
+                "A segment is past the end of the ELF file",
+            );
+        let dst = (ph.p_vaddr - link_base) as usize;
+        let dst = image.get_mut(dst..dst + src.len()).or_fail(
+            BootError::KernelLayout,
+            "A segment has more bytes in the file than in the memory",
+        );
+        profiling::profile(&profiling::ELF_COPY, || dst.copy_from_slice(src));
+
+        let page_start = ph.p_vaddr & !(PAGE_SIZE - 1);
+        check_guard_page(
+            ph.p_vaddr + virt_slide,
+            page_start,
+            mapped_end,
+            mapped_end == link_base,
+        );
+        let start = page_start.max(mapped_end);
+        let end = (ph.p_vaddr + ph.p_memsz).next_multiple_of(PAGE_SIZE);
+        if start < end {
+            let attributes = kernel_map_attributes(
+                ph.p_vaddr + virt_slide,
+                ph.p_flags & elf::abi::PF_W != 0,
+                ph.p_flags & elf::abi::PF_X != 0,
+                allow_wx,
+            );
+            page_tables.map(
+                start + virt_slide,
+                phys_base + (start - link_base),
+                end - start,
+                attributes,
+            );
+            mapped_end = end;
+        }
+    }
+
+    apply_relocations(&elf, image, link_base, virt_slide);
+    *symbols = kernel_symbols::copy_symbols(&elf, virt_slide);
+
+    let entry = elf.ehdr.e_entry + virt_slide;
+    log::info!("Kernel entry point: {entry:#016x}");
+
+    KernelImage {
+        phys_base,
+        virt_base,
+        size,
+        entry,
+        virt_slide,
+        flags: KERNEL_IMAGE_BSS_ZEROED,
+    }
+}
+
+fn load_kernel_from_pe(
+    pe_data: &[u8],
+    kaslr: bool,
+    below_4g: bool,
+    allow_wx: bool,
+    page_tables: &mut PageTables,
+) -> KernelImage {
+    let pe = PeImage::parse(pe_data).or_fail(
+        BootError::KernelFormat,
+        "Cannot parse the kernel image as PE",
+    );
+
+    #[cfg(target_arch = "aarch64")]
+    if pe.machine != pe_image::IMAGE_FILE_MACHINE_ARM64 {
+        BootError::KernelArch.fail(format_args!("Wrong kernel target arch, expected aarch64"));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if pe.machine != pe_image::IMAGE_FILE_MACHINE_AMD64 {
+        BootError::KernelArch.fail(format_args!("Wrong kernel target arch, expected x86_64"));
+    }
+
+    let link_base = pe.image_base;
+    if link_base & (PAGE_SIZE - 1) != 0 {
+        BootError::KernelLayout.fail(format_args!(
+            "The kernel image base {link_base:#016x} is not page-aligned"
+        ));
+    }
+    let size = (pe.size_of_image as u64).next_multiple_of(PAGE_SIZE);
+    log::info!("Loaded image size will be {size} bytes, rounded up to 4KiB");
+
+    let (phys_base, virt_base, image) = place_image(link_base, size, kaslr, below_4g);
+    let virt_slide = virt_base - link_base;
+
+    pe.load(image).or_fail(
+        BootError::KernelFormat,
+        "Cannot load the kernel image sections",
+    );
+    let relocations = pe.relocate(image, virt_slide).or_fail(
+        BootError::KernelRelocation,
+        "Cannot relocate the kernel image",
+    );
+    log::info!("Applied {relocations} relocations");
+
+    // The headers are read-only, the sections are mapped as they ask.
+    // A page shared by two sections gets the permissions of the first one.
+    let headers_end = (pe.size_of_headers as u64).next_multiple_of(PAGE_SIZE);
+    page_tables.map(
+        virt_base,
+        phys_base,
+        headers_end,
+        MapAttributes {
+            kind: MapKind::Normal,
+            writable: false,
+            executable: false,
+        },
+    );
+    let mut mapped_end = headers_end;
+    for (index, section) in pe.sections().enumerate() {
+        let section = section.or_fail(
+            BootError::KernelFormat,
+            "Cannot parse the kernel image sections",
+        );
+        log::info!(
+            "Section {} of {} bytes, RVA: {:#x}",
+            boot_logger::zero_terminated_str(&section.name),
+            section.virtual_size,
+            section.virtual_address
+        );
+
+        let page_start = section.virtual_address as u64 & !(PAGE_SIZE - 1);
+        check_guard_page(
+            virt_base + section.virtual_address as u64,
+            page_start,
+            mapped_end,
+            index == 0,
+        );
+        let start = page_start.max(mapped_end);
+        let end = (section.virtual_address as u64
+            + section.virtual_size.max(section.size_of_raw_data) as u64)
+            .next_multiple_of(PAGE_SIZE)
+            .min(size);
+        if start < end {
+            let attributes = kernel_map_attributes(
+                virt_base + start,
+                section.is_writable(),
+                section.is_executable(),
+                allow_wx,
+            );
+            page_tables.map(
+                virt_base + start,
+                phys_base + start,
+                end - start,
+                attributes,
+            );
+            mapped_end = end;
+        }
+    }
+
+    let entry = virt_base + pe.address_of_entry_point as u64;
+    log::info!("Kernel entry point: {entry:#016x}");
+
+    KernelImage {
+        phys_base,
+        virt_base,
+        size,
+        entry,
+        virt_slide,
+        flags: KERNEL_IMAGE_BSS_ZEROED,
+    }
+}
+
+/// Allocates the memory for the image of `size` bytes linked at `link_base`,
+/// and picks the virtual base. Returns the physical and the virtual bases,
+/// and the zero-filled image memory.
+fn place_image(
+    link_base: u64,
+    size: u64,
+    kaslr: bool,
+    below_4g: bool,
+) -> (u64, u64, &'static mut [u8]) {
+    let phys_end = if below_4g { FOUR_GIB } else { u64::MAX };
+    let (phys_base, virt_base) = if kaslr {
+        let phys_base = kaslr::allocate_random_phys(size, memory_types::KERNEL_IMAGE, phys_end)
+            .unwrap_or_else(|| {
+                log::warn!("Cannot place the kernel at a random physical address");
+                allocate_fixed(size, below_4g)
+            });
+        (phys_base, kaslr::random_virt_base(link_base, size))
+    } else {
+        (allocate_fixed(size, below_4g), link_base)
+    };
+    log::info!(
+        "Kernel image PA: {phys_base:#016x}, VA: {virt_base:#016x}, slide: {:#x}",
+        virt_base - link_base
+    );
+
+    // SAFETY: the pages have just been allocated, and the memory is identity-mapped.
+    let image = unsafe { core::slice::from_raw_parts_mut(phys_base as *mut u8, size as usize) };
+    // Covers the BSS and the gaps between the segments.
+    image.fill(0);
+
+    (phys_base, virt_base, image)
+}
+
+/// Allocates the image memory below 4 GiB if `below_4g` is set, and above
+/// if possible otherwise.
+fn allocate_fixed(size: u64, below_4g: bool) -> u64 {
+    let pages = (size / PAGE_SIZE) as usize;
+    if below_4g {
+        log::info!("Placing the kernel below 4 GiB");
+        return boot::allocate_pages(
+            AllocateType::MaxAddress(FOUR_GIB - 1),
+            memory_types::KERNEL_IMAGE,
+            pages,
+        )
+        .or_fail(
+            BootError::OutOfMemory,
+            "Cannot allocate pages for the kernel below 4 GiB",
+        )
+        .as_ptr() as u64;
+    }
+
+    allocate_above_4g(size).unwrap_or_else(|| {
+        log::info!("No room for the kernel above 4 GiB, placing it anywhere");
+        boot::allocate_pages(AllocateType::AnyPages, memory_types::KERNEL_IMAGE, pages)
+            .or_fail(
+                BootError::OutOfMemory,
+                "Cannot allocate pages for the kernel",
+            )
+            .as_ptr() as u64
+    })
+}
+
+/// Allocates the image memory at the lowest aligned free address above
+/// 4 GiB. Returns `None` if there is no free memory region large enough.
+fn allocate_above_4g(size: u64) -> Option<u64> {
+    let memory_map = boot::memory_map(MemoryType::LOADER_DATA).ok()?;
+    let phys_base = memory_map
+        .entries()
+        .filter(|e| e.ty == MemoryType::CONVENTIONAL)
+        .filter_map(|e| {
+            let start = e.phys_start.max(FOUR_GIB).next_multiple_of(KERNEL_ALIGN);
+            let end = e.phys_start.checked_add(e.page_count * PAGE_SIZE)?;
+            (start.checked_add(size)? <= end).then_some(start)
+        })
+        .min()?;
+    drop(memory_map);
+
+    // As nothing has been allocated since the memory map was taken, the
+    // range is still free.
+    boot::allocate_pages(
+        AllocateType::Address(phys_base),
+        memory_types::KERNEL_IMAGE,
+        (size / PAGE_SIZE) as usize,
+    )
+    .ok()
+    .map(|ptr| ptr.as_ptr() as u64)
+}
+
+/// Applies the relative relocations for the kernel to run `virt_slide`
+/// bytes above the address it has been linked at. Any other relocation
+/// type means the kernel has not been linked as a static PIE.
+fn apply_relocations(
+    elf: &ElfBytes<'_, LittleEndian>,
+    image: &mut [u8],
+    link_base: u64,
+    virt_slide: u64,
+) {
+    let Some(section_headers) = elf.section_headers() else {
+        log::warn!("No section headers in the kernel image, not relocating");
+        return;
+    };
+
+    let mut relocations = 0;
+    for sh in section_headers
+        .iter()
+        .filter(|sh| sh.sh_type == elf::abi::SHT_RELA)
+    {
+        let relas = elf.section_data_as_relas(&sh).or_fail(
+            BootError::KernelRelocation,
+            "Cannot parse the kernel relocations",
+        );
+        for rela in relas {
+            if rela.r_type != R_RELATIVE {
+                BootError::KernelRelocation.fail(format_args!(
+                    "Unsupported relocation type {} at {:#016x}",
+                    rela.r_type, rela.r_offset
+                ));
+            }
+
+            let target = rela
+                .r_offset
+                .checked_sub(link_base)
+                .and_then(|offset| {
+                    let offset = usize::try_from(offset).ok()?;
+                    image.get_mut(offset..offset.checked_add(8)?)
+                })
+                .unwrap_or_else(|| {
+                    BootError::KernelRelocation.fail(format_args!(
+                        "Relocation at {:#016x} is outside the kernel image",
+                        rela.r_offset
+                    ))
+                });
+            let value = (rela.r_addend as u64).wrapping_add(virt_slide);
+            target.copy_from_slice(&value.to_le_bytes());
+            relocations += 1;
+        }
+    }
+
+    log::info!("Applied {relocations} relocations");
+}
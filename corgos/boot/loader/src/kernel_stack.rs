@@ -0,0 +1,51 @@
+//! The initial kernel stack.
+//!
+//! The kernel starts on a stack the loader allocates and maps at
+//! [`KERNEL_STACK_BASE`] above an unmapped guard page. An overflow in the
+//! early kernel code then faults on the guard page instead of silently
+//! corrupting whatever is below the stack.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use crate::paging::MapAttributes;
+use crate::paging::PageTables;
+use crate::paging::KERNEL_STACK_BASE;
+use bootinfo::KernelStack;
+use uefi::boot;
+use uefi::boot::AllocateType;
+
+const PAGE_SIZE: usize = 0x1000;
+/// Left unmapped below the stack.
+const GUARD_SIZE: u64 = PAGE_SIZE as u64;
+
+/// Allocates the stack of `size` bytes rounded up to the page size, and
+/// maps it for the kernel.
+pub fn allocate_kernel_stack(size: usize, page_tables: &mut PageTables) -> KernelStack {
+    let pages = size.div_ceil(PAGE_SIZE);
+    let stack = boot::allocate_pages(AllocateType::AnyPages, memory_types::KERNEL_STACK, pages)
+        .or_fail(BootError::OutOfMemory, "Cannot allocate the kernel stack");
+
+    let size = (pages * PAGE_SIZE) as u64;
+    let virt_base = KERNEL_STACK_BASE + GUARD_SIZE;
+    let kernel_stack = KernelStack {
+        phys_base: stack.as_ptr() as u64,
+        virt_base,
+        size,
+        top: virt_base + size,
+    };
+    page_tables.map(
+        kernel_stack.virt_base,
+        kernel_stack.phys_base,
+        size,
+        MapAttributes::DATA,
+    );
+    log::info!(
+        "Kernel stack @ {:#016x} -> {:#016x}, {:#x} bytes, guard page @ {KERNEL_STACK_BASE:#016x}",
+        kernel_stack.virt_base,
+        kernel_stack.phys_base,
+        kernel_stack.size
+    );
+
+    kernel_stack
+}
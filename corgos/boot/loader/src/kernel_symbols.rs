@@ -0,0 +1,106 @@
+//! The kernel symbols for the backtraces.
+//!
+//! The kernel panic handler should print symbolized backtraces from the
+//! very start, long before it could read its own image from a disk. The
+//! function symbols from `.symtab` are copied into a compact index sorted
+//! by the address, with the KASLR slide applied, so the kernel looks an
+//! address up with a binary search. A stripped image has no symbols, and
+//! the backtraces have the bare addresses then.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use crate::paging::LINEAR_MAP_BASE;
+use crate::paging::LINEAR_MAP_MAX_SIZE;
+use bootinfo::KernelSymbol;
+use bootinfo::KernelSymbols;
+use elf::endian::LittleEndian;
+use elf::symbol::Symbol;
+use elf::ElfBytes;
+use uefi::boot;
+use uefi::boot::AllocateType;
+
+const PAGE_SIZE: usize = 0x1000;
+
+fn is_function(symbol: &Symbol) -> bool {
+    symbol.st_symtype() == elf::abi::STT_FUNC && !symbol.is_undefined() && symbol.st_value != 0
+}
+
+/// Copies the function symbols of the kernel image linked `virt_slide`
+/// bytes below where it runs.
+pub fn copy_symbols(elf: &ElfBytes<'_, LittleEndian>, virt_slide: u64) -> KernelSymbols {
+    let (symtab, strtab) = match elf.symbol_table() {
+        Ok(Some(tables)) => tables,
+        Ok(None) => {
+            log::info!("No symbol table in the kernel image");
+            return KernelSymbols::default();
+        }
+        Err(e) => {
+            log::warn!("Cannot parse the kernel symbol table: {e:?}");
+            return KernelSymbols::default();
+        }
+    };
+
+    // First pass: how much room the symbols and the names take.
+    let mut count = 0;
+    let mut names_size = 0;
+    for symbol in symtab.iter().filter(is_function) {
+        let name = strtab.get(symbol.st_name as usize).unwrap_or_default();
+        count += 1;
+        names_size += name.len() + 1;
+    }
+    if count == 0 {
+        log::info!("No function symbols in the kernel image");
+        return KernelSymbols::default();
+    }
+
+    let names_offset = count * core::mem::size_of::<KernelSymbol>();
+    let size = (names_offset + names_size).next_multiple_of(PAGE_SIZE);
+    let memory = boot::allocate_pages(
+        AllocateType::MaxAddress(LINEAR_MAP_MAX_SIZE - 1),
+        memory_types::KERNEL_SYMBOLS,
+        size / PAGE_SIZE,
+    )
+    .or_fail(BootError::OutOfMemory, "Cannot allocate the kernel symbols");
+    let phys_base = memory.as_ptr() as u64;
+
+    // SAFETY: the pages have just been allocated, are identity-mapped, and
+    // the symbols are followed by the names.
+    let (symbols, names) = unsafe {
+        (
+            core::slice::from_raw_parts_mut(memory.as_ptr() as *mut KernelSymbol, count),
+            core::slice::from_raw_parts_mut(memory.as_ptr().add(names_offset), names_size),
+        )
+    };
+
+    // Second pass: copy them.
+    let mut name_offset = 0;
+    for (entry, symbol) in symbols.iter_mut().zip(symtab.iter().filter(is_function)) {
+        let name = strtab.get(symbol.st_name as usize).unwrap_or_default();
+        names[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+        names[name_offset + name.len()] = 0;
+        *entry = KernelSymbol {
+            address: symbol.st_value.wrapping_add(virt_slide),
+            size: symbol.st_size,
+            name_offset: name_offset as u32,
+            reserved: 0,
+        };
+        name_offset += name.len() + 1;
+    }
+    symbols.sort_unstable_by_key(|symbol| symbol.address);
+
+    let kernel_symbols = KernelSymbols {
+        phys_base,
+        virt_base: LINEAR_MAP_BASE + phys_base,
+        size: size as u64,
+        count: count as u64,
+        names_offset: names_offset as u64,
+    };
+    log::info!(
+        "{count} kernel symbols @ {:#016x}, {:#x} bytes",
+        kernel_symbols.phys_base,
+        kernel_symbols.size
+    );
+
+    kernel_symbols
+}
@@ -0,0 +1,254 @@
+//! Keyboard input for the boot menu and the debug shell.
+//!
+//! The keys come from the UEFI console, and from the serial port used for
+//! logging as the headless machines have no console input. The console is
+//! read through the Simple Text Input Ex protocol where the firmware has
+//! it, as only that one reports the modifier keys, and through the Simple
+//! Text Input protocol otherwise. The serial bytes are decoded with the
+//! [`SerialDecoder`], so both report the same [`KeyEvent`]s.
+
+use core::ffi::c_void;
+use input::Key;
+use input::KeyEvent;
+use input::Modifiers;
+use input::SerialDecoder;
+use uefi::boot;
+use uefi::boot::OpenProtocolAttributes;
+use uefi::boot::OpenProtocolParams;
+use uefi::boot::ScopedProtocol;
+use uefi::proto::console::text::Key as UefiKey;
+use uefi::proto::unsafe_protocol;
+use uefi::system;
+use uefi::table;
+use uefi::Handle;
+use uefi::Status;
+
+/// How often the keys are polled, in microseconds.
+const POLL_INTERVAL: usize = 10_000;
+/// How long the rest of an escape sequence is waited for before the Esc
+/// is taken to be the Escape key, in microseconds. The terminals send the
+/// sequences at once, the slow links deliver them within that.
+const ESCAPE_TIMEOUT: usize = 50_000;
+/// How often the rest of an escape sequence is polled, in microseconds.
+const SEQUENCE_POLL_INTERVAL: usize = 500;
+
+/// The scan codes of the UEFI specification, 12.3 Simple Text Input
+/// Protocol.
+const SCAN_UP: u16 = 0x01;
+const SCAN_DOWN: u16 = 0x02;
+const SCAN_RIGHT: u16 = 0x03;
+const SCAN_LEFT: u16 = 0x04;
+const SCAN_HOME: u16 = 0x05;
+const SCAN_END: u16 = 0x06;
+const SCAN_INSERT: u16 = 0x07;
+const SCAN_DELETE: u16 = 0x08;
+const SCAN_PAGE_UP: u16 = 0x09;
+const SCAN_PAGE_DOWN: u16 = 0x0a;
+const SCAN_F1: u16 = 0x0b;
+const SCAN_F12: u16 = 0x16;
+const SCAN_ESC: u16 = 0x17;
+const SCAN_F13: u16 = 0x68;
+const SCAN_F24: u16 = 0x73;
+
+const CHAR_BACKSPACE: u16 = 0x08;
+const CHAR_TAB: u16 = 0x09;
+const CHAR_CARRIAGE_RETURN: u16 = 0x0d;
+
+/// `KeyShiftState` is valid.
+const SHIFT_STATE_VALID: u32 = 0x8000_0000;
+const RIGHT_SHIFT_PRESSED: u32 = 0x0000_0001;
+const LEFT_SHIFT_PRESSED: u32 = 0x0000_0002;
+const RIGHT_CONTROL_PRESSED: u32 = 0x0000_0004;
+const LEFT_CONTROL_PRESSED: u32 = 0x0000_0008;
+const RIGHT_ALT_PRESSED: u32 = 0x0000_0010;
+const LEFT_ALT_PRESSED: u32 = 0x0000_0020;
+
+/// `EFI_KEY_DATA`
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+struct KeyData {
+    scan_code: u16,
+    unicode_char: u16,
+    key_shift_state: u32,
+    key_toggle_state: u8,
+}
+
+/// `EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL`, not in the `uefi` crate. Only
+/// reading the keys is used.
+#[repr(C)]
+#[unsafe_protocol("dd9e7534-7762-4698-8c14-f58517a625aa")]
+struct InputEx {
+    reset: unsafe extern "efiapi" fn(this: *mut Self, extended_verification: bool) -> Status,
+    read_key_stroke_ex: unsafe extern "efiapi" fn(this: *mut Self, key: *mut KeyData) -> Status,
+    wait_for_key_ex: *mut c_void,
+    set_state: *const c_void,
+    register_key_notify: *const c_void,
+    unregister_key_notify: *const c_void,
+}
+
+/// The protocol on the console input handle, that one combines all the
+/// keyboards and the terminals the firmware uses.
+fn console_input_ex() -> Option<ScopedProtocol<InputEx>> {
+    let system_table = table::system_table_raw()?;
+    // SAFETY: the system table is valid while the boot services are active.
+    let handle = unsafe { Handle::from_ptr(system_table.as_ref().stdin_handle)? };
+    // SAFETY: the exclusive access would disconnect the UEFI console from
+    // the keyboards, and the protocol is not uninstalled while the loader
+    // runs.
+    unsafe {
+        boot::open_protocol::<InputEx>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .ok()
+}
+
+fn modifiers(key_shift_state: u32) -> Modifiers {
+    let mut modifiers = Modifiers::NONE;
+    if key_shift_state & SHIFT_STATE_VALID == 0 {
+        return modifiers;
+    }
+    if key_shift_state & (RIGHT_SHIFT_PRESSED | LEFT_SHIFT_PRESSED) != 0 {
+        modifiers |= Modifiers::SHIFT;
+    }
+    if key_shift_state & (RIGHT_CONTROL_PRESSED | LEFT_CONTROL_PRESSED) != 0 {
+        modifiers |= Modifiers::CTRL;
+    }
+    if key_shift_state & (RIGHT_ALT_PRESSED | LEFT_ALT_PRESSED) != 0 {
+        modifiers |= Modifiers::ALT;
+    }
+    modifiers
+}
+
+/// The key the firmware reports, `None` for the keys with no [`Key`], and
+/// for the shift and the toggle keys alone.
+fn key_event(key_data: KeyData) -> Option<KeyEvent> {
+    let mut modifiers = modifiers(key_data.key_shift_state);
+    let key = match key_data.scan_code {
+        0 => match key_data.unicode_char {
+            0 => return None,
+            CHAR_CARRIAGE_RETURN => Key::Enter,
+            CHAR_BACKSPACE => Key::Backspace,
+            CHAR_TAB => Key::Tab,
+            // Some firmware report the letters with Ctrl as the control
+            // characters.
+            c @ 0x01..=0x1a => {
+                modifiers |= Modifiers::CTRL;
+                Key::Char(char::from(b'a' + c as u8 - 1))
+            }
+            c => Key::Char(char::from_u32(c.into())?),
+        },
+        SCAN_UP => Key::Up,
+        SCAN_DOWN => Key::Down,
+        SCAN_RIGHT => Key::Right,
+        SCAN_LEFT => Key::Left,
+        SCAN_HOME => Key::Home,
+        SCAN_END => Key::End,
+        SCAN_INSERT => Key::Insert,
+        SCAN_DELETE => Key::Delete,
+        SCAN_PAGE_UP => Key::PageUp,
+        SCAN_PAGE_DOWN => Key::PageDown,
+        SCAN_ESC => Key::Escape,
+        scan @ SCAN_F1..=SCAN_F12 => Key::Function((scan - SCAN_F1 + 1) as u8),
+        scan @ SCAN_F13..=SCAN_F24 => Key::Function((scan - SCAN_F13 + 13) as u8),
+        _ => return None,
+    };
+    Some(KeyEvent::with_modifiers(key, modifiers))
+}
+
+/// The UEFI console and the serial port.
+pub struct Keyboard {
+    input_ex: Option<ScopedProtocol<InputEx>>,
+    serial: SerialDecoder,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self {
+            input_ex: console_input_ex(),
+            serial: SerialDecoder::new(),
+        }
+    }
+
+    fn read_console(&mut self) -> Option<KeyEvent> {
+        let Some(input_ex) = self.input_ex.as_mut() else {
+            let key = system::with_stdin(|stdin| stdin.read_key().ok().flatten())?;
+            let key_data = match key {
+                UefiKey::Printable(c) => KeyData {
+                    unicode_char: c.into(),
+                    ..KeyData::default()
+                },
+                UefiKey::Special(scan_code) => KeyData {
+                    scan_code: scan_code.0,
+                    ..KeyData::default()
+                },
+            };
+            return key_event(key_data);
+        };
+
+        let mut key_data = KeyData::default();
+        let input_ex: &mut InputEx = input_ex;
+        // SAFETY: the protocol is open, and the key data is writable.
+        let status = unsafe { (input_ex.read_key_stroke_ex)(input_ex, &mut key_data) };
+        if status.is_success() {
+            key_event(key_data)
+        } else {
+            None
+        }
+    }
+
+    /// Waits a little for the rest of an escape sequence started.
+    fn read_serial(&mut self) -> Option<KeyEvent> {
+        let mut waited = 0;
+        loop {
+            if let Some(byte) = boot_logger::read_serial_byte() {
+                waited = 0;
+                if let Some(event) = self.serial.push(byte) {
+                    return Some(event);
+                }
+            } else if !self.serial.is_pending() {
+                return None;
+            } else if waited >= ESCAPE_TIMEOUT {
+                return self.serial.flush();
+            } else {
+                boot::stall(SEQUENCE_POLL_INTERVAL);
+                waited += SEQUENCE_POLL_INTERVAL;
+            }
+        }
+    }
+
+    /// Whether there is the console input or the serial port to read the
+    /// keys from.
+    pub fn has_input(&self) -> bool {
+        // SAFETY: the system table is valid while the boot services are
+        // active.
+        let stdin = table::system_table_raw()
+            .is_some_and(|system_table| unsafe { !system_table.as_ref().stdin.is_null() });
+        self.input_ex.is_some() || stdin || boot_logger::has_serial_input()
+    }
+
+    /// The key pressed, `None` if there is none in the input buffers.
+    pub fn read_key(&mut self) -> Option<KeyEvent> {
+        self.read_console().or_else(|| self.read_serial())
+    }
+
+    /// Waits for a key for up to `timeout` microseconds, or forever.
+    pub fn wait_for_key(&mut self, timeout: Option<usize>) -> Option<KeyEvent> {
+        let mut waited = 0;
+        loop {
+            if let Some(event) = self.read_key() {
+                return Some(event);
+            }
+            if timeout.is_some_and(|timeout| waited >= timeout) {
+                return None;
+            }
+            boot::stall(POLL_INTERVAL);
+            waited += POLL_INTERVAL;
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Booting Linux through its EFI stub.
+//!
+//! Both the arm64 `Image` and the x86 `bzImage` are PE images when built
+//! with `CONFIG_EFI_STUB`, and the stub does the rest of the respective boot
+//! protocol: builds the device tree or `boot_params`, loads the initrd given
+//! as `initrd=` on the command line, gets the memory map, and exits the boot
+//! services. The loader loads the image with the device path of the file so
+//! the stub finds the initrd on the same volume.
+
+use crate::files;
+use boot_logger::MAX_CMDLINE_SIZE;
+use boot_logger::MAX_PATH_SIZE;
+use core::mem::MaybeUninit;
+use pe_image::PeImage;
+use uefi::boot;
+use uefi::boot::LoadImageSource;
+use uefi::boot::MemoryType;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::Status;
+
+/// `ARM\x64` at this offset of the arm64 `Image` header.
+const ARM64_IMAGE_MAGIC_OFFSET: usize = 0x38;
+const ARM64_IMAGE_MAGIC: &[u8] = b"ARM\x64";
+/// `HdrS` at this offset of the x86 setup header.
+const X86_SETUP_HEADER_MAGIC_OFFSET: usize = 0x202;
+const X86_SETUP_HEADER_MAGIC: &[u8] = b"HdrS";
+
+const INITRD_OPTION: &str = " initrd=";
+const LOAD_OPTIONS_SIZE: usize = MAX_CMDLINE_SIZE + INITRD_OPTION.len() + MAX_PATH_SIZE + 1;
+const DEVICE_PATH_BUF_SIZE: usize = 1024;
+
+fn has_magic(image: &[u8], offset: usize, magic: &[u8]) -> bool {
+    image.get(offset..offset + magic.len()) == Some(magic)
+}
+
+/// The command line as UCS-2 for the loaded image options.
+fn load_options(cmdline: &str, initrd: Option<&str>, options: &mut [u16]) -> usize {
+    let initrd = initrd
+        .map(|initrd| [INITRD_OPTION, initrd])
+        .unwrap_or_default();
+
+    let mut len = 0;
+    for c in cmdline
+        .chars()
+        .chain(initrd.iter().flat_map(|s| s.chars()))
+        .take(options.len() - 1)
+    {
+        options[len] = if c.is_ascii() { c as u16 } else { b'?' as u16 };
+        len += 1;
+    }
+    options[len] = 0;
+
+    len + 1
+}
+
+/// Loads and starts the Linux kernel, returns the status if that fails.
+pub fn boot_linux(kernel_path: &str, initrd: Option<&str>, cmdline: &str) -> Status {
+    log::info!("Booting Linux '{kernel_path}', initrd {initrd:?}, cmdline '{cmdline}'");
+
+    let image = match files::read_file(kernel_path, MemoryType::LOADER_DATA) {
+        Ok(image) => image,
+        Err(e) => {
+            log::error!("Cannot read '{kernel_path}': {e:?}");
+            return e.status();
+        }
+    };
+
+    if has_magic(image, ARM64_IMAGE_MAGIC_OFFSET, ARM64_IMAGE_MAGIC) {
+        log::info!("arm64 Image");
+    } else if has_magic(image, X86_SETUP_HEADER_MAGIC_OFFSET, X86_SETUP_HEADER_MAGIC) {
+        log::info!("x86 bzImage");
+    }
+    if !PeImage::is_pe(image) {
+        log::error!("'{kernel_path}' has no EFI stub, only CONFIG_EFI_STUB kernels are supported");
+        files::free_file(image);
+        return Status::UNSUPPORTED;
+    }
+
+    let mut device_path_buf = [MaybeUninit::uninit(); DEVICE_PATH_BUF_SIZE];
+    let file_path = files::file_device_path(kernel_path, &mut device_path_buf)
+        .inspect_err(|e| log::warn!("No device path for '{kernel_path}', initrd won't load: {e:?}"))
+        .ok();
+
+    let image_handle = boot::load_image(
+        boot::image_handle(),
+        LoadImageSource::FromBuffer {
+            buffer: image,
+            file_path,
+        },
+    );
+    // The firmware has made its own copy.
+    files::free_file(image);
+
+    let image_handle = match image_handle {
+        Ok(image_handle) => image_handle,
+        Err(e) => {
+            log::error!("Cannot load '{kernel_path}': {e:?}");
+            return e.status();
+        }
+    };
+
+    // Must outlive the kernel EFI stub which reads it.
+    let mut options = [0_u16; LOAD_OPTIONS_SIZE];
+    let options_len = load_options(cmdline, initrd, &mut options);
+    match boot::open_protocol_exclusive::<LoadedImage>(image_handle) {
+        Ok(mut loaded_image) => {
+            // SAFETY: the options are valid until `start_image` returns, and
+            // the kernel does not return on success.
+            unsafe {
+                loaded_image.set_load_options(
+                    options.as_ptr().cast(),
+                    (options_len * core::mem::size_of::<u16>()) as u32,
+                )
+            };
+        }
+        Err(e) => {
+            log::error!("Cannot set the Linux command line: {e:?}");
+            return e.status();
+        }
+    }
+
+    match boot::start_image(image_handle) {
+        Ok(()) => {
+            log::error!("Linux '{kernel_path}' has returned");
+            Status::ABORTED
+        }
+        Err(e) => {
+            log::error!("Linux '{kernel_path}' has failed: {e:?}");
+            e.status()
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Where the loader is.
+//!
+//! The debuggers attached to the firmware find the symbols for the loaded
+//! images through the EFI Debug Image Info Table. Most firmware adds the
+//! images to it in `LoadImage`, some does not, so the loader makes sure it
+//! is there. The kernel is told where the loader is in the boot info to be
+//! able to symbolize the early crashes, and to reclaim the memory.
+
+use uefi::boot;
+use uefi::boot::MemoryType;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::system;
+use uefi::Handle;
+use uefi_guids::config_tables::ConfigTables;
+use uefi_guids::config_tables::DebugImageInfoNormal;
+use uefi_guids::config_tables::IMAGE_INFO_TYPE_NORMAL;
+
+/// Set in `UpdateStatus` while the table is being modified.
+const UPDATE_IN_PROGRESS: u32 = 1;
+/// Set in `UpdateStatus` once the table has been modified.
+const TABLE_MODIFIED: u32 = 2;
+
+/// Logs where the loader is, and makes sure it is in the debug table.
+pub fn report_loader_image() -> bootinfo::LoaderImage {
+    let image_handle = boot::image_handle();
+    let loaded_image = match boot::open_protocol_exclusive::<LoadedImage>(image_handle) {
+        Ok(loaded_image) => loaded_image,
+        Err(e) => {
+            log::warn!("Cannot open the loaded image protocol, the loader is not known: {e:?}");
+            return bootinfo::LoaderImage::default();
+        }
+    };
+    let (base, size) = loaded_image.info();
+    log::info!("Loader image @ {:#016x}, {size:#x} bytes", base as u64);
+
+    let loaded_image: *const LoadedImage = &*loaded_image;
+    if let Err(e) = add_debug_image_info(image_handle, loaded_image) {
+        log::warn!("Cannot add the loader to the debug image info table: {e:?}");
+    }
+
+    bootinfo::LoaderImage {
+        phys_base: base as u64,
+        size,
+    }
+}
+
+fn add_debug_image_info(image_handle: Handle, loaded_image: *const LoadedImage) -> uefi::Result {
+    let header = system::with_config_table(|tables| {
+        // SAFETY: the boot services are active, the tables are identity-mapped.
+        let tables = unsafe { ConfigTables::new(tables) };
+        tables.debug_image_info().map(|table| table.header())
+    });
+    let Some(header) = header else {
+        log::info!("No debug image info table");
+        return Ok(());
+    };
+
+    // SAFETY: the firmware has published the table, and nothing else runs
+    // while the loader modifies it.
+    let header = unsafe { &mut *header.as_ptr() };
+    let table_size = header.table_size as usize;
+    let table: &mut [*mut DebugImageInfoNormal] = if header.table.is_null() {
+        &mut []
+    } else {
+        // SAFETY: the table has `table_size` entries.
+        unsafe { core::slice::from_raw_parts_mut(header.table, table_size) }
+    };
+
+    let listed = table.iter().any(|&entry| {
+        // SAFETY: the non-NULL entries point to the image infos.
+        !entry.is_null() && unsafe { (*entry).image_handle } == image_handle
+    });
+    if listed {
+        log::info!("The loader is in the debug image info table");
+        return Ok(());
+    }
+
+    let entry_pool = boot::allocate_pool(
+        MemoryType::LOADER_DATA,
+        core::mem::size_of::<DebugImageInfoNormal>(),
+    )?;
+    let entry = entry_pool.as_ptr() as *mut DebugImageInfoNormal;
+    // SAFETY: the memory has just been allocated.
+    unsafe {
+        entry.write(DebugImageInfoNormal {
+            image_info_type: IMAGE_INFO_TYPE_NORMAL,
+            loaded_image,
+            image_handle,
+        })
+    };
+
+    // The debugger does not read the table while it is being updated.
+    // SAFETY: the header is valid.
+    unsafe {
+        core::ptr::write_volatile(
+            &mut header.update_status,
+            header.update_status | UPDATE_IN_PROGRESS,
+        )
+    };
+
+    let result = match table.iter_mut().find(|entry| entry.is_null()) {
+        Some(free) => {
+            *free = entry;
+            Ok(())
+        }
+        None => boot::allocate_pool(
+            MemoryType::LOADER_DATA,
+            (table_size + 1) * core::mem::size_of::<*mut DebugImageInfoNormal>(),
+        )
+        .map(|new_table| {
+            let new_table = new_table.as_ptr() as *mut *mut DebugImageInfoNormal;
+            // SAFETY: the new table has one more entry than the old one. The
+            // old one is left alone as the firmware owns it.
+            unsafe {
+                core::ptr::copy_nonoverlapping(table.as_ptr(), new_table, table_size);
+                new_table.add(table_size).write(entry);
+            }
+            header.table = new_table;
+            header.table_size += 1;
+        }),
+    };
+
+    // SAFETY: the header is valid.
+    unsafe {
+        core::ptr::write_volatile(
+            &mut header.update_status,
+            header.update_status & !UPDATE_IN_PROGRESS | TABLE_MODIFIED,
+        )
+    };
+
+    match result {
+        Ok(()) => log::info!("Added the loader to the debug image info table"),
+        // SAFETY: the entry has not been published.
+        Err(_) => unsafe { boot::free_pool(entry_pool) }.unwrap_or_default(),
+    }
+    result
+}
@@ -0,0 +1,55 @@
+//! How much of the stack the firmware has given the loader it uses.
+//!
+//! The stack is painted on entry and scanned before the handoff, see the
+//! `stack_usage` crate. The firmware does not say where the stack is, the
+//! UEFI specification only guarantees [`UEFI_MIN_STACK_SIZE`] bytes of it
+//! available to the image. The top is taken above the frame of `main` as
+//! the backtraces do, and the bottom that much below it, so the painting
+//! stays within what the specification guarantees.
+
+use crate::stack_trace::MAIN_FRAME_SLACK;
+use stack_usage::PaintedStack;
+
+/// The stack space the UEFI specification guarantees to the image.
+const UEFI_MIN_STACK_SIZE: u64 = 0x20000;
+
+static mut STACK: Option<PaintedStack> = None;
+
+/// Paints the stack below the frame of `main`.
+pub fn paint(main_fp: u64) {
+    let top = main_fp + MAIN_FRAME_SLACK;
+    let bottom = top - UEFI_MIN_STACK_SIZE;
+    // SAFETY: the specification guarantees the stack space, and nothing
+    // below the stack pointer is in use.
+    let stack = unsafe { PaintedStack::paint(bottom, top) };
+    // SAFETY: only the boot processor runs, and nothing reads it yet.
+    unsafe { *core::ptr::addr_of_mut!(STACK) = Some(stack) };
+}
+
+fn stack() -> Option<&'static PaintedStack> {
+    // SAFETY: written once on entry.
+    unsafe { (*core::ptr::addr_of!(STACK)).as_ref() }
+}
+
+/// Logs the high-water mark of the stack.
+pub fn report() {
+    let Some(stack) = stack() else {
+        return;
+    };
+    log::info!(
+        "Loader stack @ {:#016x}..{:#016x}: {}",
+        stack.bottom(),
+        stack.top(),
+        stack.usage()
+    );
+    if !stack.guard_intact() {
+        log::warn!("Loader stack guard is overwritten, the stack may have overflowed");
+    }
+}
+
+/// Logs the overwritten guard, the panic might be the result of that.
+pub fn check_guard() {
+    if stack().is_some_and(|stack| !stack.guard_intact()) {
+        log::error!("Loader stack guard is overwritten, the stack may have overflowed");
+    }
+}
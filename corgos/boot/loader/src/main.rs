@@ -2,31 +2,100 @@
 #![no_main]
 #![deny(unsafe_op_in_unsafe_fn)]
 
+#[cfg(target_arch = "aarch64")]
+mod aarch64_cpu;
 #[cfg(target_arch = "aarch64")]
 mod aarch64_regs;
+mod ab_boot;
+mod acpi_tables;
+#[cfg(target_arch = "x86_64")]
+mod apic;
+mod boot_error;
+mod boot_menu;
+mod boot_timing;
+mod chainload;
+mod console;
+mod cpu_features;
+mod cpus;
+mod debug_shell;
+mod decompress;
+mod device_tree;
+mod diagnostics;
+mod early_heap;
+mod files;
+mod fpu;
+mod gdb_stub;
+#[cfg(target_arch = "aarch64")]
+mod gic;
+mod handoff;
+mod interrupts;
+mod kaslr;
+mod kernel_loader;
+mod kernel_stack;
+mod kernel_symbols;
+mod keyboard;
+mod linux;
+mod loader_image;
+mod loader_stack;
+mod memory_map;
+mod memory_types;
+mod modules;
+mod mp_services;
+mod netboot;
+mod numa;
+mod page_bitmap_storage;
+mod page_walk;
+mod paging;
+mod pci;
+mod profiling;
+mod real_time_clock;
+mod register_snapshot;
+mod runtime_map;
+mod settings;
+mod smbios;
+mod stack_trace;
+mod timer;
+mod tpm;
+mod video;
+mod watchdog;
+#[cfg(target_arch = "x86_64")]
+mod x86_64_cpu;
+#[cfg(target_arch = "x86_64")]
+mod x86_64_regs;
 
+use boot_error::BootError;
+use boot_error::OrFail;
 use boot_logger::BootLoaderConfig;
+use boot_logger::ConfigProblems;
+use boot_logger::GdbStubDevice;
 use boot_logger::LogDevice;
+use boot_logger::PageTableWalk;
+use boot_logger::Payload;
+use boot_logger::RtcDevice;
+use boot_logger::VideoMode;
+use boot_logger::DEFAULT_LOAD_WATCHDOG_SECONDS;
+use boot_logger::MAX_BOOT_ENTRIES;
+use boot_logger::MAX_CMDLINE_SIZE;
+use boot_logger::MAX_MODULES;
+use bootinfo::BootStage;
 use core::arch::asm;
-use elf::endian::LittleEndian;
-use elf::ElfBytes;
 use log::LevelFilter;
+use register_snapshot::RegisterSnapshot;
 use uefi::boot;
-use uefi::boot::AllocateType;
 use uefi::mem::memory_map::MemoryMap;
 use uefi::mem::memory_map::MemoryMapMut;
-use uefi::proto::console::text::Input;
+use uefi::proto::loaded_image::LoadedImage;
 use uefi::proto::media::file::File;
 use uefi::proto::media::file::FileAttribute;
-use uefi::proto::media::file::FileInfo;
 use uefi::proto::media::file::FileMode;
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::runtime;
 use uefi::system;
-use uefi::table::boot::MemoryType;
 use uefi::table::runtime::ResetType;
 use uefi::CStr16;
 use uefi::Status;
+use uefi_guids::config_tables::ConfigTables;
+use uefi_guids::config_tables::Rsdp;
 
 /// The name of the configuration file in the ESP partition alongside the loader.
 #[cfg(target_arch = "x86_64")]
@@ -34,8 +103,12 @@ const CORGOS_INI: &CStr16 = uefi::cstr16!("corgos-boot-x86_64.ini");
 #[cfg(target_arch = "aarch64")]
 const CORGOS_INI: &CStr16 = uefi::cstr16!("corgos-boot-aarch64.ini");
 
-/// The name of the CorgOS kernel binary image.
-const CORGOS_KERNEL: &CStr16 = uefi::cstr16!("corgos");
+/// The per-machine configuration file is looked for first, it is
+/// `corgos-boot-<product>.ini` with the product name from SMBIOS.
+const MACHINE_INI_PREFIX: &str = "corgos-boot-";
+const MACHINE_INI_SUFFIX: &str = ".ini";
+const MAX_MACHINE_INI_SIZE: usize =
+    MACHINE_INI_PREFIX.len() + smbios::MAX_PRODUCT_NAME_SIZE + MACHINE_INI_SUFFIX.len() + 1;
 
 /// Upon panic, b"CORGBARF" is loaded into R8. R9 contains the address of the file name,
 /// R10 contains the line number in the least significant 32 bits, and the column number
@@ -46,8 +119,210 @@ const CORGOS_BARF: u64 = u64::from_le_bytes([0x46, 0x52, 0x41, 0x42, 0x47, 0x52,
 /// Timeout for the boot services.
 const WATCHDOG_TIMEOUT_CODE: u64 = CORGOS_BARF;
 
+/// The load options longer than that are truncated.
+const MAX_LOAD_OPTIONS_SIZE: usize = 1024;
+
+/// Sets the free text to the value with the escape sequences undone. The
+/// paths, names, and the rest are taken as they are, so that `\EFI\new`
+/// needs no doubled backslashes. A value that does not fit is counted as a
+/// problem, and the text is left as it was.
+fn set_text(buf: &mut [u8], value: &[u8], problems: &mut ConfigProblems) {
+    // No text is longer than the command line.
+    let mut text = [0_u8; MAX_CMDLINE_SIZE];
+    match ini_file::unescape(value, &mut text[..buf.len()]) {
+        Some(text) => boot_logger::set_zero_terminated(buf, text),
+        None => problems.values_too_long = problems.values_too_long.saturating_add(1),
+    }
+}
+
+/// Parses `<index>.<field>` of the `boot_entry.<index>.<field>` keys.
+fn parse_boot_entry(config: &mut BootLoaderConfig, index_field: &[u8], value: &[u8]) {
+    let Some(dot) = index_field.iter().position(|&c| c == b'.') else {
+        return;
+    };
+    let (index, field) = (&index_field[..dot], &index_field[dot + 1..]);
+    let Ok(index) = core::str::from_utf8(index)
+        .unwrap_or_default()
+        .parse::<usize>()
+    else {
+        return;
+    };
+    if index >= MAX_BOOT_ENTRIES {
+        return;
+    }
+
+    let entry = &mut config.boot_entries[index];
+    match field {
+        b"name" => set_text(&mut entry.name, value, &mut config.problems),
+        b"kernel" => boot_logger::set_zero_terminated(&mut entry.kernel, value),
+        b"cmdline" => set_text(&mut entry.cmdline, value, &mut config.problems),
+        _ => {}
+    }
+}
+
+/// Parses `module.<name> = path[,cmdline]`.
+fn parse_module(config: &mut BootLoaderConfig, name: &[u8], value: &[u8]) {
+    let Some(module) = config.modules.iter_mut().find(|m| !m.is_used()) else {
+        config.problems.modules_dropped = config.problems.modules_dropped.saturating_add(1);
+        return;
+    };
+
+    let (path, cmdline) = match value.iter().position(|&c| c == b',') {
+        Some(comma) => (&value[..comma], &value[comma + 1..]),
+        None => (value, b"".as_slice()),
+    };
+    boot_logger::set_zero_terminated(&mut module.name, name);
+    boot_logger::set_zero_terminated(&mut module.path, path.trim_ascii());
+    boot_logger::set_zero_terminated(&mut module.cmdline, cmdline.trim_ascii());
+}
+
+/// Parses `WIDTHxHEIGHT[xBPP]`, `max`, or `keep`, 32 bits per pixel if
+/// not given. None of the numbers can be zero.
+fn parse_video_mode(value: &[u8]) -> Option<VideoMode> {
+    match value {
+        b"keep" => return Some(VideoMode::Keep),
+        b"max" => return Some(VideoMode::Max),
+        _ => {}
+    }
+
+    let mut numbers = core::str::from_utf8(value)
+        .ok()?
+        .split('x')
+        .map(|number| number.parse::<usize>().ok().filter(|&number| number != 0));
+    let width = numbers.next()??;
+    let height = numbers.next()??;
+    let bits_per_pixel = numbers.next().unwrap_or(Some(32))?;
+    if numbers.next().is_some() {
+        return None;
+    }
+
+    Some(VideoMode::Resolution {
+        width,
+        height,
+        bits_per_pixel,
+    })
+}
+
+/// Parses `none`, `com1`, `com2`, or `pl011@<hex address>`.
+fn parse_gdb_stub(value: &[u8]) -> Option<GdbStubDevice> {
+    match value {
+        b"none" => Some(GdbStubDevice::None),
+        b"com1" => Some(GdbStubDevice::Com1),
+        b"com2" => Some(GdbStubDevice::Com2),
+        _ => {
+            let base_addr = value.strip_prefix(b"pl011@")?;
+            let base_addr = core::str::from_utf8(base_addr).ok()?;
+            Some(GdbStubDevice::Pl011(
+                u64::from_str_radix(base_addr, 16).ok()?,
+            ))
+        }
+    }
+}
+
+/// Parses `auto`, `none`, `cmos`, or `pl031@<hex address>`.
+fn parse_rtc(value: &[u8]) -> Option<RtcDevice> {
+    match value {
+        b"auto" => Some(RtcDevice::Auto),
+        b"none" => Some(RtcDevice::None),
+        b"cmos" => Some(RtcDevice::Cmos),
+        _ => {
+            let base_addr = value.strip_prefix(b"pl031@")?;
+            let base_addr = core::str::from_utf8(base_addr).ok()?;
+            Some(RtcDevice::Pl031(u64::from_str_radix(base_addr, 16).ok()?))
+        }
+    }
+}
+
+/// A size in bytes with an optional `K`, `M`, or `G` suffix, `KiB` and the
+/// like work too.
+fn parse_size(value: &[u8]) -> Option<usize> {
+    let value = core::str::from_utf8(value).ok()?;
+    let value = value.strip_suffix("iB").unwrap_or(value);
+    let (number, shift) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 10),
+        b'M' | b'm' => (&value[..value.len() - 1], 20),
+        b'G' | b'g' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    number.trim().parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+fn parse_log_level(value: &[u8]) -> Option<LevelFilter> {
+    match value {
+        b"info" => Some(LevelFilter::Info),
+        b"warn" => Some(LevelFilter::Warn),
+        b"error" => Some(LevelFilter::Error),
+        b"debug" => Some(LevelFilter::Debug),
+        b"trace" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+fn report_config_problems(problems: &ConfigProblems) {
+    if problems.values_too_long != 0 {
+        log::warn!(
+            "{} configuration values are too long, and have been ignored",
+            problems.values_too_long
+        );
+    }
+    if problems.modules_dropped != 0 {
+        log::warn!(
+            "{} modules are over the limit of {}, and have been ignored",
+            problems.modules_dropped,
+            MAX_MODULES
+        );
+    }
+    if problems.bad_crc32 {
+        log::error!("The configuration file does not match its crc32 line, and has been ignored");
+    }
+}
+
+/// Whether the configuration file matches its `crc32` line if it has one:
+/// the CRC32 in hex of the file without that line. Catches the files
+/// damaged on the way, e.g. from the boot server.
+fn config_crc32_matches(bytes: &[u8]) -> bool {
+    let mut crc = digest::Crc32::new();
+    let mut expected = None;
+    for line in bytes.split_inclusive(|&byte| byte == b'\n') {
+        let value = match ini_file::Parser::new(line).parse() {
+            Ok(Some(ini_file::KeyValue {
+                key: b"crc32",
+                value,
+            })) => Some(value),
+            // The `crc32` line without a value matches nothing.
+            Err(_) if line.trim_ascii_start().starts_with(b"crc32") => Some(b"".as_slice()),
+            _ => None,
+        };
+        match value {
+            Some(value) if expected.is_none() => expected = Some(value),
+            _ => crc.update(line),
+        }
+    }
+
+    match expected {
+        Some(expected) => {
+            core::str::from_utf8(expected)
+                .ok()
+                .and_then(|expected| u32::from_str_radix(expected, 16).ok())
+                == Some(crc.finalize())
+        }
+        None => true,
+    }
+}
+
 fn parse_config(bytes: &[u8]) -> Option<BootLoaderConfig> {
     let mut config = BootLoaderConfig::default();
+    if !config_crc32_matches(bytes) {
+        config.problems.bad_crc32 = true;
+        return Some(config);
+    }
+    apply_config(&mut config, bytes);
+
+    Some(config)
+}
+
+/// Applies the settings over the ones already in the configuration.
+fn apply_config(config: &mut BootLoaderConfig, bytes: &[u8]) {
     let mut parser = ini_file::Parser::new(bytes);
 
     while let Ok(Some(ini_file::KeyValue { key, value })) = parser.parse() {
@@ -57,6 +332,7 @@ fn parse_config(bytes: &[u8]) -> Option<BootLoaderConfig> {
                 b"com1" => config.log_device = LogDevice::Com1,
                 b"com2" => config.log_device = LogDevice::Com2,
                 b"stdout" => config.log_device = LogDevice::StdOut,
+                b"virtio_console" => config.log_device = LogDevice::VirtioConsole(0),
                 _ => {
                     // TODO: must be Device Tree or ACPI
                     if value.starts_with(b"pl011@") {
@@ -68,33 +344,89 @@ fn parse_config(bytes: &[u8]) -> Option<BootLoaderConfig> {
                         } else {
                             config.log_device = LogDevice::StdOut
                         }
+                    } else if value.starts_with(b"virtio_console@") {
+                        if let Ok(base_addr) = u64::from_str_radix(
+                            core::str::from_utf8(&value[b"virtio_console@".len()..])
+                                .unwrap_or_default(),
+                            16,
+                        ) {
+                            config.log_device = LogDevice::VirtioConsole(base_addr)
+                        } else {
+                            config.log_device = LogDevice::StdOut
+                        }
                     }
                 }
             },
-            b"log_level" => match value {
-                b"info" => config.log_level = LevelFilter::Info,
-                b"warn" => config.log_level = LevelFilter::Warn,
-                b"error" => config.log_level = LevelFilter::Error,
-                b"debug" => config.log_level = LevelFilter::Debug,
-                b"trace" => config.log_level = LevelFilter::Trace,
-                _ => continue,
+            b"log_level" => match parse_log_level(value) {
+                Some(log_level) => config.log_level = log_level,
+                None => continue,
+            },
+            b"recovery_log_level" => match parse_log_level(value) {
+                Some(log_level) => config.recovery_log_level = log_level,
+                None => continue,
             },
             b"log_source_path" => {
                 config.log_source_path =
                     value == b"yes" || value == b"on" || value == b"1" || value == b"true"
             }
-            b"wait_for_start" => {
-                config.wait_for_start =
+            b"gdb_stub" => match parse_gdb_stub(value) {
+                Some(gdb_stub) => config.gdb_stub = gdb_stub,
+                None => continue,
+            },
+            b"walk_page_tables" => {
+                config.walk_page_tables = if value == b"summary" {
+                    PageTableWalk::Summary
+                } else if value == b"yes" || value == b"on" || value == b"1" || value == b"true" {
+                    PageTableWalk::Entries
+                } else {
+                    PageTableWalk::Off
+                }
+            }
+            b"allow_wx_kernel" => {
+                config.allow_wx_kernel =
                     value == b"yes" || value == b"on" || value == b"1" || value == b"true"
             }
-            b"walk_page_tables" => {
-                config.walk_page_tables =
+            b"kaslr" => {
+                config.kaslr =
                     value == b"yes" || value == b"on" || value == b"1" || value == b"true"
             }
-            b"revision" => {
-                let len = core::cmp::min(value.len(), config.revision.len());
-                config.revision[..len].copy_from_slice(&value[..len])
+            b"framebuffer_console" => {
+                config.framebuffer_console =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
             }
+            b"load_below_4g" => {
+                config.load_below_4g =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"debug_shell" => {
+                config.debug_shell =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"dump_diagnostics" => {
+                config.dump_diagnostics =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"x2apic" => {
+                config.x2apic =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"persist_settings" => {
+                config.persist_settings =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"mp_probe" => {
+                config.mp_probe =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"pmu_profile" => {
+                config.pmu_profile =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"gic_wake_redistributor" => {
+                config.gic_wake_redistributor =
+                    value == b"yes" || value == b"on" || value == b"1" || value == b"true"
+            }
+            b"revision" => set_text(&mut config.revision, value, &mut config.problems),
             b"watchdog_seconds" => {
                 if let Ok(watchdog_seconds) =
                     core::str::from_utf8(value).unwrap_or_default().parse()
@@ -102,34 +434,205 @@ fn parse_config(bytes: &[u8]) -> Option<BootLoaderConfig> {
                     config.watchdog_seconds = Some(watchdog_seconds);
                 }
             }
+            b"load_watchdog_seconds" => {
+                if let Ok(load_watchdog_seconds) =
+                    core::str::from_utf8(value).unwrap_or_default().parse()
+                {
+                    config.load_watchdog_seconds = load_watchdog_seconds;
+                }
+            }
+            b"exit_watchdog_seconds" => {
+                if let Ok(exit_watchdog_seconds) =
+                    core::str::from_utf8(value).unwrap_or_default().parse()
+                {
+                    config.exit_watchdog_seconds = exit_watchdog_seconds;
+                }
+            }
+            b"net_retries" => {
+                if let Ok(net_retries) = core::str::from_utf8(value).unwrap_or_default().parse() {
+                    config.net_retries = net_retries;
+                }
+            }
+            b"kernel" | b"kernel_a" => {
+                boot_logger::set_zero_terminated(&mut config.kernel_a, value)
+            }
+            b"kernel_b" => boot_logger::set_zero_terminated(&mut config.kernel_b, value),
+            b"kernel_sha256" => boot_logger::set_zero_terminated(&mut config.kernel_sha256, value),
+            b"max_boot_attempts" => {
+                if let Ok(max_boot_attempts) =
+                    core::str::from_utf8(value).unwrap_or_default().parse()
+                {
+                    config.max_boot_attempts = max_boot_attempts;
+                }
+            }
+            b"recovery_boot_attempts" => {
+                if let Ok(recovery_boot_attempts) =
+                    core::str::from_utf8(value).unwrap_or_default().parse()
+                {
+                    config.recovery_boot_attempts = recovery_boot_attempts;
+                }
+            }
+            b"cmdline" => set_text(&mut config.cmdline, value, &mut config.problems),
+            b"payload" => match value {
+                b"corgos" => config.payload = Payload::CorgOs,
+                b"linux" => config.payload = Payload::Linux,
+                _ => continue,
+            },
+            b"early_heap" => match parse_size(value) {
+                Some(early_heap_size) => config.early_heap_size = early_heap_size,
+                None => continue,
+            },
+            b"kernel_stack" => match parse_size(value) {
+                Some(kernel_stack_size) if kernel_stack_size != 0 => {
+                    config.kernel_stack_size = kernel_stack_size
+                }
+                _ => continue,
+            },
+            b"rtc" => match parse_rtc(value) {
+                Some(rtc) => config.rtc = rtc,
+                None => continue,
+            },
+            b"video_mode" => match parse_video_mode(value) {
+                Some(video_mode) => config.video_mode = video_mode,
+                None => continue,
+            },
+            b"initrd" => boot_logger::set_zero_terminated(&mut config.initrd, value),
+            b"chainload" => boot_logger::set_zero_terminated(&mut config.chainload, value),
+            b"volume_label" => boot_logger::set_zero_terminated(&mut config.volume_label, value),
+            b"volume_partition_guid" => {
+                boot_logger::set_zero_terminated(&mut config.volume_partition_guid, value)
+            }
+            b"menu_timeout_seconds" => {
+                if let Ok(menu_timeout_seconds) =
+                    core::str::from_utf8(value).unwrap_or_default().parse()
+                {
+                    config.menu_timeout_seconds = menu_timeout_seconds;
+                }
+            }
+            _ if key.starts_with(b"boot_entry.") => {
+                parse_boot_entry(config, &key[b"boot_entry.".len()..], value)
+            }
+            _ if key.starts_with(b"module.") && key.len() > b"module.".len() => {
+                parse_module(config, &key[b"module.".len()..], value)
+            }
             _ => continue,
         }
     }
-
-    Some(config)
 }
 
-fn get_config() -> BootLoaderConfig {
-    let mut config = BootLoaderConfig::default();
-    if let Ok(fs_handle) = boot::get_handle_for_protocol::<SimpleFileSystem>() {
+/// Reads the configuration file from the first volume that has it, or
+/// from the boot server. A file that cannot be parsed gives the defaults.
+fn read_config(file_name: &CStr16) -> Option<BootLoaderConfig> {
+    let mut buf = [0_u8; 4096];
+    if let Some(fs_handle) = files::find_volume(|volume| files::has_file(volume, file_name)) {
+        files::set_volume(fs_handle);
         if let Ok(mut fs) = boot::open_protocol_exclusive::<SimpleFileSystem>(fs_handle) {
             if let Ok(mut root_directory) = fs.open_volume() {
                 if let Ok(file) =
-                    root_directory.open(CORGOS_INI, FileMode::Read, FileAttribute::empty())
+                    root_directory.open(file_name, FileMode::Read, FileAttribute::empty())
                 {
                     if let Some(mut file) = file.into_regular_file() {
-                        let mut buf = [0_u8; 4096];
                         let bytes_read: usize = file.read(&mut buf).unwrap_or_default();
-                        if let Some(file_config) = parse_config(&buf[..bytes_read]) {
-                            config = file_config;
-                        }
+                        return Some(parse_config(&buf[..bytes_read]).unwrap_or_default());
                     }
                 }
             }
         }
     }
 
-    config
+    let bytes_read = netboot::read_config(file_name, &mut buf)?;
+    Some(parse_config(&buf[..bytes_read]).unwrap_or_default())
+}
+
+/// The name of the configuration file for the machine of `product`.
+fn machine_config_name<'a>(
+    product: &str,
+    buf: &'a mut [u16; MAX_MACHINE_INI_SIZE],
+) -> Option<&'a CStr16> {
+    let mut len = 0;
+    for c in MACHINE_INI_PREFIX
+        .bytes()
+        .chain(product.bytes())
+        .chain(MACHINE_INI_SUFFIX.bytes())
+    {
+        *buf.get_mut(len)? = u16::from(c);
+        len += 1;
+    }
+    *buf.get_mut(len)? = 0;
+
+    CStr16::from_u16_with_nul(&buf[..=len]).ok()
+}
+
+/// Returns the configuration, and whether it is the one for the machine.
+fn get_config() -> (BootLoaderConfig, bool) {
+    let mut product_buf = [0_u8; smbios::MAX_PRODUCT_NAME_SIZE];
+    let mut name_buf = [0_u16; MAX_MACHINE_INI_SIZE];
+    let machine_config = smbios::product_name(&mut product_buf)
+        .and_then(|product| machine_config_name(product, &mut name_buf))
+        .and_then(read_config);
+    let per_machine = machine_config.is_some();
+
+    let mut config = machine_config
+        .or_else(|| read_config(CORGOS_INI))
+        .unwrap_or_default();
+    apply_load_options(&mut config);
+
+    (config, per_machine)
+}
+
+/// Applies the `key=value` settings from the load options of the loader,
+/// e.g. set in the firmware boot manager entry, over the configuration file.
+/// The values with whitespace are quoted as in the configuration file.
+fn apply_load_options(config: &mut BootLoaderConfig) {
+    let Ok(loaded_image) = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle())
+    else {
+        return;
+    };
+    let Some(load_options) = loaded_image.load_options_as_bytes() else {
+        return;
+    };
+    apply_ucs2_settings(config, load_options);
+}
+
+/// Applies the `key=value` settings from the UCS-2 load options, the words
+/// before the first setting are skipped.
+fn apply_ucs2_settings(config: &mut BootLoaderConfig, load_options: &[u8]) {
+    let mut buf = [0_u8; MAX_LOAD_OPTIONS_SIZE];
+    let mut len = 0;
+    for c in load_options
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .map(|&c| u16::from_le_bytes(c))
+        .take_while(|&c| c != 0)
+        .take(buf.len())
+    {
+        buf[len] = u8::try_from(c).ok().filter(u8::is_ascii).unwrap_or(b'?');
+        len += 1;
+    }
+
+    // The firmware or the shell may pass the image path before the settings.
+    let settings = &buf[..len];
+    let start = settings
+        .split(|c| c.is_ascii_whitespace())
+        .find(|word| word.contains(&b'='))
+        .map(|word| word.as_ptr() as usize - settings.as_ptr() as usize);
+    if let Some(start) = start {
+        apply_config(config, &settings[start..]);
+    }
+}
+
+fn report_machine(per_machine_config: bool) {
+    let mut product_buf = [0_u8; smbios::MAX_PRODUCT_NAME_SIZE];
+    match smbios::product_name(&mut product_buf) {
+        Some(product) if per_machine_config => {
+            log::info!(
+                "Machine '{product}', using '{MACHINE_INI_PREFIX}{product}{MACHINE_INI_SUFFIX}'"
+            )
+        }
+        Some(product) => log::info!("Machine '{product}', using '{CORGOS_INI}'"),
+        None => log::info!("No SMBIOS product name, using '{CORGOS_INI}'"),
+    }
 }
 
 fn report_boot_processor_info() {
@@ -138,14 +641,12 @@ fn report_boot_processor_info() {
         use raw_cpuid::CpuId;
 
         let cpuid = CpuId::new();
-        let cpu_vendor = cpuid
-            .get_vendor_info()
-            .expect("Must be able to get CPU vendor");
+        let cpu_vendor = cpuid.get_vendor_info();
         let brand_str = cpuid.get_processor_brand_string();
 
         log::info!(
             "Boot processor: {} {}",
-            cpu_vendor.as_str(),
+            cpu_vendor.as_ref().map_or("unknown vendor", |v| v.as_str()),
             if let Some(b) = &brand_str {
                 b.as_str()
             } else {
@@ -158,6 +659,42 @@ fn report_boot_processor_info() {
         } else {
             log::info!("No hypervisor detected (wasn't trying too hard though)");
         }
+
+        use crate::x86_64_regs::access::X86Register;
+        use crate::x86_64_regs::*;
+
+        let regs = [
+            register!(Cr0),
+            register!(Cr3),
+            register!(Cr4),
+            register!(Efer),
+            register!(ApicBase),
+            register!(Pat),
+            register!(MtrrCapabilities),
+            register!(MtrrDefaultType),
+        ];
+
+        for r in regs {
+            r.load();
+
+            let raw: u64 = r.bits();
+            let name = r.name();
+            log::info!("{name}\t{raw:#016x?}: {r:x?}");
+        }
+
+        let mut mtrr_cap = MtrrCapabilities::new();
+        mtrr_cap.load();
+        for index in 0..mtrr_cap.variable_count() as u32 {
+            let (base, mask) = access::variable_mtrr(index);
+            if mask.valid() {
+                log::info!(
+                    "MTRR{index}\t{:#016x} mask {:#016x}: {:?}",
+                    base.base_pfn() << 12,
+                    mask.mask_pfn() << 12,
+                    base.cache_type()
+                );
+            }
+        }
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -167,6 +704,7 @@ fn report_boot_processor_info() {
 
         let regs = [
             register!(MainIdEl1),
+            register!(MultiprocessorAffinityEl1),
             register!(ProcessorFeatures0El1),
             register!(ProcessorFeatures1El1),
             register!(MmFeatures0El1),
@@ -174,7 +712,12 @@ fn report_boot_processor_info() {
             register!(MmFeatures2El1),
             register!(MmFeatures3El1),
             register!(MmFeatures4El1),
+            register!(IsaFeatures0El1),
+            register!(IsaFeatures1El1),
+            register!(IsaFeatures2El1),
             register!(CurrentEl),
+            register!(ExceptionMask),
+            register!(StackPointerSelect),
             register!(SystemControlEl1),
             register!(VectorBaseEl1),
             register!(MemoryAttributeIndirectionEl1),
@@ -184,6 +727,14 @@ fn report_boot_processor_info() {
             register!(ExceptionLinkEl1),
             register!(ExceptionSyndromeEl1),
             register!(SavedProgramStateEl1),
+            register!(ArchFeatureAccessControlEl1),
+            register!(MonitorDebugSystemControlEl1),
+            register!(CounterFrequencyEl0),
+            register!(VirtualCountEl0),
+            register!(CounterKernelControlEl1),
+            register!(PhysicalTimerControlEl0),
+            register!(VirtualTimerControlEl0),
+            register!(VirtualTimerCompareEl0),
         ];
 
         for r in regs {
@@ -194,82 +745,19 @@ fn report_boot_processor_info() {
             log::info!("{name}\t{raw:#016x?}: {r:x?}");
         }
     }
-}
-
-fn walk_page_tables() {
-    #[cfg(target_arch = "aarch64")]
-    {
-        use crate::aarch64_regs::access::Aarch64Register;
-        use crate::aarch64_regs::*;
-
-        // Traverse page tables assuming 4K pages (check TCR!)
-
-        let mut ttbr0_el1 = TranslationBase0El1::new();
-        ttbr0_el1.load();
-
-        let lvl4_table =
-            unsafe { core::slice::from_raw_parts(ttbr0_el1.baddr() as *const PageTableEntry, 512) };
-
-        let lvl3_table = unsafe {
-            core::slice::from_raw_parts(
-                (lvl4_table[0].next_table_pfn() << 12) as *const PageBlockEntry,
-                512,
-            )
-        };
-
-        log::info!("{:x?}", lvl3_table[5]);
-
-        let mut dfs_stack = [(0u64, 0u64); 512];
-        let mut dfs_stack_top = 0;
-        dfs_stack[dfs_stack_top] = (0, ttbr0_el1.baddr() | 0b11);
-        dfs_stack_top += 1;
-
-        while dfs_stack_top > 0 {
-            dfs_stack_top -= 1;
-            let (level, entry) = dfs_stack[dfs_stack_top];
 
-            if entry & 1 == 0 {
-                // Not valid for hardware, skip. In general, might be valid when an OS is running
-                // for software PTEs and swapping.
-                continue;
-            }
-
-            assert!(entry & 0b11 == 0b11);
-
-            // This a table pointer.
-            let entry = PageTableEntry::from(entry);
-            let entry_raw = u64::from(entry);
-            log::info!("PTE {entry_raw:#x}: {entry:x?}");
-
-            // Assuming 4K pages (check TCR!)
-            let next_table_entries = unsafe {
-                core::slice::from_raw_parts((entry.next_table_pfn() << 12) as *const u64, 512)
-            };
-
-            for &entry in next_table_entries.iter().rev() {
-                if level >= 3 {
-                    // This is a block pointer (a leaf).
-                    let entry = PageBlockEntry::from(entry);
-                    let entry_raw = u64::from(entry);
-                    log::info!("PBE {entry_raw:#x}: {entry:x?}");
-                    continue;
-                }
-
-                if entry & 0b11 == 0b11 {
-                    dfs_stack[dfs_stack_top] = (level + 1, entry);
-                    dfs_stack_top += 1;
-                } else if entry & 1 == 1 {
-                    // This is a block pointer (a leaf).
-                    let entry = PageBlockEntry::from(entry);
-                    let entry_raw = u64::from(entry);
-                    log::info!("PBE {entry_raw:#x}: {entry:x?}");
-                }
-            }
+    log::info!(
+        "FP/SIMD access {}",
+        if fpu::is_enabled() {
+            "enabled"
+        } else {
+            "trapped"
         }
-    }
+    );
 }
 
-fn report_uefi_info() {
+/// Returns the address of the ACPI 2.0 RSDP.
+fn report_uefi_info() -> u64 {
     let fw_vendor = system::firmware_vendor();
     let fw_revision = system::firmware_revision();
     let uefi_revision = system::uefi_revision();
@@ -286,31 +774,30 @@ fn report_uefi_info() {
     );
 
     let rsdp = system::with_config_table(|tables| {
-        let mut rsdp_addr: Option<*const core::ffi::c_void> = None;
         for table in tables {
             let name = uefi_guids::get_uefi_table_name(&table.guid);
             log::info!(
-                "Table {} @ {:#016x}: {name}",
+                "Table {} @ {:#016x}: {name} ({:?})",
                 table.guid,
-                table.address as u64
+                table.address as u64,
+                uefi_guids::get_uefi_table_category(&table.guid)
             );
-            if table.guid == uefi_guids::EFI_ACPI20_TABLE_GUID {
-                rsdp_addr = Some(table.address);
-            }
         }
-        rsdp_addr
-    })
-    .expect("Must be able to locate ACPI 2.0 FADT");
 
-    let rsdp: *const acpi::rsdp::Rsdp = rsdp.cast();
-    let rsdp = unsafe {
-        rsdp.as_ref()
-            .expect("Must be a non-NULL point to ACPI 2.0 RSDP")
-    };
-    rsdp.validate().expect("Must have a valid ACPI 2.0 RSDP");
-    assert!(rsdp.revision() == 2, "Expected ACPI 2.0 RSDP");
+        // SAFETY: the boot services are active, the tables are identity-mapped.
+        let tables = unsafe { ConfigTables::new(tables) };
+        tables
+            .rsdp()
+            .map(|rsdp| (rsdp as *const Rsdp as u64, *rsdp))
+    });
+    let (rsdp_addr, rsdp) = rsdp.or_fail(BootError::Acpi, "Cannot locate a valid ACPI RSDP");
+    if rsdp.revision < 2 {
+        BootError::Acpi.fail(format_args!("Expected ACPI 2.0 RSDP"));
+    }
+
+    log::info!("ACPI 2.0 RSDP @ {rsdp_addr:#016x} {rsdp:x?}");
 
-    log::info!("ACPI 2.0 RSDP {rsdp:x?}");
+    rsdp_addr
 }
 
 fn arch_name() -> &'static str {
@@ -324,23 +811,15 @@ fn arch_name() -> &'static str {
     }
 }
 
+/// Waits for a key press on the UEFI console or on the serial port used
+/// for logging as the headless machines have no console input.
 fn boot_wait_for_key_press() {
-    let stdin = if let Some(stdin) = boot::get_handle_for_protocol::<Input>().ok() {
-        stdin
-    } else {
-        return;
-    };
-    let stdin = if let Some(stdin) = boot::open_protocol_exclusive::<Input>(stdin).ok() {
-        stdin
-    } else {
-        return;
-    };
-    let event = if let Some(event) = stdin.wait_for_key_event() {
-        event
-    } else {
+    let mut keyboard = keyboard::Keyboard::new();
+    if !keyboard.has_input() {
+        log::warn!("No console input and no serial port, not waiting for a key");
         return;
-    };
-    boot::wait_for_event(&mut [event]).ok();
+    }
+    keyboard.wait_for_key(None);
 }
 
 #[allow(dead_code)]
@@ -364,169 +843,12 @@ fn dead_loop() -> ! {
     }
 }
 
-// Write 0 to R9(X9) to break the loop.
-fn wait_for_start() {
-    #[cfg(target_arch = "x86_64")]
-    unsafe {
-        asm!(
-            r#"
-                1:
-                    cmpq    %r9, 0
-                    pause
-                    jne     1b
-            "#,
-            in("r9") 1,
-            options(att_syntax, nostack),
-        );
-    }
-    #[cfg(target_arch = "aarch64")]
-    unsafe {
-        asm!(
-            r#"
-            1:
-                    cmp     x9, 0
-                    yield
-                    bne     1b
-            "#,
-            in("x9") 1,
-            options(nostack),
-        );
-    }
-}
-
-fn load_kernel_from_elf() {
-    let sfs = boot::get_handle_for_protocol::<SimpleFileSystem>()
-        .expect("SimpleFileSystem must be available");
-    let mut sfs = boot::open_protocol_exclusive::<SimpleFileSystem>(sfs)
-        .expect("SimpleFileSystem must be opened");
-    let mut root = sfs.open_volume().expect("Failed to open root volume");
-
-    let kernel_file = root
-        .open(CORGOS_KERNEL, FileMode::Read, FileAttribute::empty())
-        .expect("Failed to open kernel image");
-
-    let mut kernel_file = kernel_file
-        .into_regular_file()
-        .expect("Failed to convert to a regular file");
-
-    let elf_data_size = {
-        let mut file_info_buf = [0u8; 512];
-        let file_info = kernel_file
-            .get_info::<FileInfo>(&mut file_info_buf)
-            .expect("Failed to get file info");
-
-        let file_size = file_info.file_size() as usize;
-        (file_size as usize + 0xFFF) & !0xFFF
-    };
-    assert!(elf_data_size & 0xFFF == 0);
-
-    log::info!("Kernel file size {elf_data_size} bytes, rounded up to 4KiB");
-
-    let elf_data = boot::allocate_pages(
-        AllocateType::AnyPages,
-        MemoryType::LOADER_DATA,
-        elf_data_size / 0x1000,
-    )
-    .expect("Failed to allocate pages to read the kernel image")
-    .as_ptr();
-    let elf_data = unsafe { core::slice::from_raw_parts_mut(elf_data, elf_data_size) };
-
-    kernel_file
-        .read(elf_data)
-        .expect("Cannot read the kernel image");
-    // Downgrade to immutable.
-    let elf_data = &elf_data[..elf_data_size];
-
-    let elf = ElfBytes::<LittleEndian>::minimal_parse(elf_data)
-        .expect("Cannot parse the kernel image as ELF");
-
-    #[cfg(target_arch = "aarch64")]
-    assert!(
-        elf.ehdr.e_machine == elf::abi::EM_AARCH64,
-        "Wrong kernel target arch, expected aarch64"
-    );
-
-    #[cfg(target_arch = "x86_64")]
-    assert!(
-        elf.ehdr.e_machine == elf::abi::EM_X86_64,
-        "Wrong kernel target arch, expected x86_64"
-    );
-
-    let mut loaded_size = 0;
-    let segments = elf
-        .segments()
-        .expect("Cannot find segments in the ELF file");
-
-    // First pass: see how much data is going to be loaded.
-    for ph in segments {
-        log::info!(
-            "Found segment of {} bytes ({} in the image), PA: {:#016x}, VA: {:#016x}",
-            ph.p_memsz,
-            ph.p_filesz,
-            ph.p_paddr,
-            ph.p_vaddr
-        );
-
-        if ph.p_type != elf::abi::PT_LOAD {
-            continue;
-        }
-        loaded_size += (ph.p_memsz + 0xFFF) & !0xFFF;
-
-        log::info!("Will load the segment");
-    }
-
-    assert!(loaded_size & 0xFFF == 0);
-
-    log::info!("Loaded image size will be {loaded_size} bytes, rounded up to 4KiB");
-
-    let loaded_data = boot::allocate_pages(
-        AllocateType::AnyPages,
-        MemoryType::LOADER_DATA, // TODO: Set some special memory type
-        (loaded_size / 0x1000) as usize,
-    )
-    .expect("Failed to allocate pages")
-    .as_ptr();
-    let _loaded_data =
-        unsafe { core::slice::from_raw_parts_mut(loaded_data, loaded_size as usize) };
-
-    // Second pass: load the code and data.
-    let mut _bytes_loaded = 0;
-    for ph in segments {
-        if ph.p_type != elf::abi::PT_LOAD {
-            continue;
-        }
-        log::info!(
-            "Loading segment of {} bytes ({} in the image), PA: {:#016x}, VA: {:#016x}",
-            ph.p_memsz,
-            ph.p_filesz,
-            ph.p_paddr,
-            ph.p_vaddr
-        );
-
-        // TODO: copy, round up to a page.
-
-        // if ph.p_filesz != 0 {
-        //     // Copy segment data to the allocated memory
-        //     let src_data = &elf_data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize];
-        //     dst.copy_from_slice(src_data);
-        // } else {
-        //     // If memory size is greater than file size, zero out the rest (clean BSS)
-        //     let zeroed_region = unsafe {
-        //         core::slice::from_raw_parts_mut(segment_address as *mut u8, ph.p_memsz as usize)
-        //     };
-        //     zeroed_region.fill(0);
-        // }
-    }
-
-    log::info!("Kernel entry point: {:#016x}", elf.ehdr.e_entry);
-
-    todo!("Map the kernel code and data approriately");
-}
-
 #[cfg_attr(target_os = "uefi", panic_handler)]
 #[cfg_attr(not(target_os = "uefi"), allow(dead_code))]
 fn panic(panic: &core::panic::PanicInfo<'_>) -> ! {
     log::error!("{panic}");
+    stack_trace::log_backtrace();
+    loader_stack::check_guard();
 
     let (_file_name_addr, _line_col) = if let Some(location) = panic.location() {
         (
@@ -544,6 +866,12 @@ fn panic(panic: &core::panic::PanicInfo<'_>) -> ! {
         // TODO: Might be divergent or cause a hardware failure.
         // TODO: detect if running under QEMU.
         smh.exit_host_failure();
+
+        #[cfg(target_arch = "aarch64")]
+        if let Some(psci) = cpus::psci() {
+            let e = psci.system_off();
+            log::error!("PSCI SYSTEM_OFF failed: {e:?}");
+        }
         log::error!("Hit `Ctrl+A X` if running under QEMU, and it is not exiting");
     }
 
@@ -586,41 +914,370 @@ extern "efiapi" fn __chkstk() {}
 
 #[uefi::entry]
 fn main() -> Status {
-    let config = get_config();
-    if config.wait_for_start {
-        wait_for_start();
+    let main_fp = backtrace::frame_pointer();
+    loader_stack::paint(main_fp);
+    stack_trace::init(main_fp);
+    boot_timing::start();
+    timer::calibrate();
+    let (mut config, per_machine_config) = boot_timing::measure(BootStage::Config, get_config);
+    if let LogDevice::VirtioConsole(0) = config.log_device {
+        config.log_device =
+            console::find_virtio_console().map_or(LogDevice::StdOut, LogDevice::VirtioConsole);
     }
     boot_logger::setup_logger(&config);
+    report_config_problems(&config.problems);
+    profiling::init(config.pmu_profile);
 
     log::info!(
         "Loading **CorgOS/{}**, \"{}\"",
         arch_name(),
         config.revision_str()
     );
+    report_machine(per_machine_config);
     report_boot_processor_info();
-    if config.walk_page_tables {
-        walk_page_tables();
-    }
-    report_uefi_info();
+    let entry_registers = RegisterSnapshot::capture_control("entry");
+    page_walk::walk_page_tables(config.walk_page_tables);
+    let rsdp_addr = report_uefi_info();
+    let loader_image = loader_image::report_loader_image();
+    gdb_stub::start(&config);
+    files::select_volume(&config);
 
     if let Some(watchdog_seconds) = config.watchdog_seconds {
-        boot::set_watchdog_timer(watchdog_seconds, WATCHDOG_TIMEOUT_CODE, None).unwrap();
+        if let Err(e) = boot::set_watchdog_timer(watchdog_seconds, WATCHDOG_TIMEOUT_CODE, None) {
+            log::warn!("Could not set the watchdog to {watchdog_seconds} seconds: {e:?}");
+        }
         log::info!(
             "Hit a key to exit loader, otherwise the system will reboot. Timeout {watchdog_seconds} seconds"
         );
+        if config.load_watchdog_seconds != DEFAULT_LOAD_WATCHDOG_SECONDS
+            || config.exit_watchdog_seconds != 0
+        {
+            log::warn!(
+                "watchdog_seconds is set, not loading: the load and exit watchdogs are not used"
+            );
+        }
 
         boot_wait_for_key_press();
         return Status::ABORTED;
     }
 
-    load_kernel_from_elf();
+    if config.debug_shell || debug_shell::hotkey_pressed() {
+        debug_shell::run(&config);
+    }
+    watchdog::start(config.load_watchdog_seconds);
+    settings::set_persistence(config.persist_settings);
+    ab_boot::apply_last_good_kernel(&mut config);
+    if !ab_boot::apply_recovery(&mut config) {
+        boot_menu::run_boot_menu(&mut config);
+    }
+    netboot::set_retries(config.net_retries);
+    let framebuffer = video::set_video_mode(config.video_mode);
+    if let Some(chainload_path) = config.chainload_str() {
+        return chainload::chainload(chainload_path);
+    }
+    if config.payload == Payload::Linux {
+        let kernel_path = ab_boot::select_kernel(&config);
+        return linux::boot_linux(kernel_path, config.initrd_str(), config.cmdline_str());
+    }
+
+    cpu_features::check_required_features();
+    let boot_info = handoff::allocate_boot_info();
+    if config.framebuffer_console {
+        boot_info.framebuffer_console = video::allocate_console_scratch(&framebuffer);
+        video::start_log_console(&framebuffer, &boot_info.framebuffer_console);
+    }
+    boot_info.loader = loader_image;
+    boot_info.timer = timer::timer();
+    log::info!("Timer {:?}", boot_info.timer);
+    let acpi_tables = acpi_tables::acpi_tables(rsdp_addr);
+    cpus::collect_cpus(&acpi_tables, &mut boot_info.cpus);
+    mp_services::cross_check(&mut boot_info.cpus, config.mp_probe);
+    numa::collect_numa(&acpi_tables, &mut boot_info.cpus, &mut boot_info.numa);
+    #[cfg(target_arch = "aarch64")]
+    gic::discover_gic(
+        &acpi_tables,
+        boot_info.cpus.boot_cpu_hw_id,
+        config.gic_wake_redistributor,
+        &mut boot_info.gic,
+    );
+    #[cfg(target_arch = "x86_64")]
+    apic::discover_apic(&acpi_tables, &mut boot_info.apic);
+    real_time_clock::discover_rtc(config.rtc, &acpi_tables, &mut boot_info.rtc);
+    tpm::discover_tpm(rsdp_addr, &mut boot_info.tpm);
+    pci::discover_pci(rsdp_addr, &mut boot_info.pci);
+
+    let mut page_tables = boot_timing::measure(BootStage::PageTables, || {
+        profiling::profile(&profiling::PAGE_TABLES, || {
+            let mut page_tables = paging::PageTables::new();
+            paging::map_physical_memory(&mut page_tables);
+            page_tables
+        })
+    });
+    boot_info.console = console::map_console(&config.log_device, &mut page_tables);
+    boot_info.framebuffer = video::map_framebuffer(framebuffer, &mut page_tables);
+    #[cfg(target_arch = "aarch64")]
+    gic::map_gic(&mut boot_info.gic, &mut page_tables);
+    #[cfg(target_arch = "x86_64")]
+    apic::map_apic(&mut boot_info.apic, &mut page_tables);
+    real_time_clock::map_rtc(&mut boot_info.rtc, &mut page_tables);
+    tpm::map_tpm(&mut boot_info.tpm, &mut page_tables);
+    pci::map_pci(&mut boot_info.pci, &mut page_tables);
+    boot_timing::measure(BootStage::PageTables, || {
+        profiling::profile(&profiling::PAGE_TABLES, || {
+            runtime_map::map_runtime_regions(&mut page_tables)
+        })
+    });
+
+    let kernel_path = ab_boot::select_kernel(&config);
+    boot_info.kernel = kernel_loader::load_kernel(
+        kernel_path,
+        config.kernel_sha256_str(),
+        config.kaslr,
+        config.load_below_4g,
+        config.allow_wx_kernel,
+        &mut page_tables,
+        &mut boot_info.kernel_symbols,
+    );
+
+    boot_timing::measure(BootStage::Modules, || {
+        modules::load_modules(&config.modules, &mut boot_info.modules)
+    });
+    boot_logger::set_zero_terminated(&mut boot_info.cmdline, config.cmdline_str().as_bytes());
+    boot_info.early_heap = early_heap::allocate_early_heap(config.early_heap_size);
+    boot_info.kernel_stack =
+        kernel_stack::allocate_kernel_stack(config.kernel_stack_size, &mut page_tables);
+    handoff::prepare_handoff(&mut page_tables);
 
-    let mut memory_map = unsafe { boot::exit_boot_services(MemoryType(0x70000000)) };
+    log::info!(
+        "Kernel page tables @ {:#016x}, {:?}",
+        page_tables.root(),
+        page_tables.stats()
+    );
+    boot_info.paging = bootinfo::Paging {
+        root_table: page_tables.root(),
+        linear_map_base: paging::LINEAR_MAP_BASE,
+        linear_map_size: paging::LINEAR_MAP_MAX_SIZE,
+        #[cfg(target_arch = "aarch64")]
+        mair: paging::MAIR,
+        #[cfg(target_arch = "x86_64")]
+        mair: 0,
+    };
+
+    if config.dump_diagnostics {
+        diagnostics::dump(rsdp_addr, &boot_info.rtc, &boot_info.pci);
+    }
+    let memory_regions = memory_map::allocate_regions();
+    let mut page_bitmap = page_bitmap_storage::allocate_page_bitmap();
+    watchdog::arm_for_exit(config.exit_watchdog_seconds);
+    boot_info.wall_clock = timer::wall_clock();
+
+    // SAFETY: nothing below uses the boot services.
+    let mut memory_map = boot_timing::measure(BootStage::ExitBootServices, || unsafe {
+        memory_map::exit_boot_services(memory_types::MEMORY_MAP)
+    });
     memory_map.sort();
-    log::info!("Memory map has {} entries", memory_map.entries().len());
-    for entry in memory_map.entries() {
-        log::info!("Memory map: {entry:x?}")
+    boot_info.memory_map = memory_map::convert(&memory_map, memory_regions);
+    log::info!(
+        "Memory map has {} entries, {} regions for the kernel",
+        memory_map.entries().len(),
+        boot_info.memory_map.count
+    );
+    // SAFETY: the regions have just been written, and are identity-mapped.
+    let regions = unsafe { boot_info.memory_map.regions() };
+    for region in regions {
+        log::info!("Memory map: {region:x?}")
+    }
+    page_bitmap_storage::build_page_bitmap(&mut page_bitmap, regions);
+    boot_info.page_bitmap = page_bitmap;
+
+    boot_info.efi = runtime_map::set_virtual_address_map(&memory_map);
+
+    boot_info.timings = boot_timing::report();
+    profiling::report();
+
+    #[cfg(target_arch = "aarch64")]
+    aarch64_cpu::prepare_for_kernel();
+    #[cfg(target_arch = "x86_64")]
+    x86_64_cpu::prepare_for_kernel();
+    #[cfg(target_arch = "x86_64")]
+    if config.x2apic {
+        apic::enable_x2apic(&mut boot_info.apic);
+    }
+    entry_registers.log_diff(&RegisterSnapshot::capture_control("handoff"));
+    loader_stack::report();
+
+    // The lines logged from here on do not make it to the kernel.
+    let (log_base, log_size, log_head, log_wrapped) = boot_logger::log_ring_state();
+    boot_info.loader_log = bootinfo::LoaderLog {
+        phys_base: log_base,
+        virt_base: paging::LINEAR_MAP_BASE + log_base,
+        size: log_size as u64,
+        head: log_head as u64,
+        wrapped: log_wrapped as u32,
+        reserved: 0,
+    };
+    boot_info.seal();
+    log::info!("Boot info @ {:#016x} sealed", boot_info as *const _ as u64);
+
+    handoff::enter_kernel(boot_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_suffixes() {
+        assert_eq!(parse_size(b"0"), Some(0));
+        assert_eq!(parse_size(b"4096"), Some(4096));
+        assert_eq!(parse_size(b"64K"), Some(64 << 10));
+        assert_eq!(parse_size(b"64k"), Some(64 << 10));
+        assert_eq!(parse_size(b"64KiB"), Some(64 << 10));
+        assert_eq!(parse_size(b"2 M"), Some(2 << 20));
+        assert_eq!(parse_size(b"2MiB"), Some(2 << 20));
+        assert_eq!(parse_size(b"1g"), Some(1 << 30));
+    }
+
+    #[test]
+    fn parse_size_overflow() {
+        assert_eq!(parse_size(b"18446744073709551615"), Some(usize::MAX));
+        assert_eq!(parse_size(b"18446744073709551616"), None);
+        assert_eq!(parse_size(b"17179869183G"), Some(17179869183 << 30));
+        assert_eq!(parse_size(b"17179869184G"), None);
+        assert_eq!(parse_size(b"18014398509481984K"), None);
     }
 
-    todo!("Transfer to the kernel");
+    #[test]
+    fn parse_size_bad_input() {
+        for value in [
+            b"".as_slice(),
+            b"K",
+            b"KiB",
+            b"12X",
+            b"1T",
+            b"-1",
+            b"1.5M",
+            b"0x1000",
+            b"\xff",
+        ] {
+            assert_eq!(parse_size(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn parse_video_mode_resolution() {
+        assert_eq!(parse_video_mode(b"keep"), Some(VideoMode::Keep));
+        assert_eq!(parse_video_mode(b"max"), Some(VideoMode::Max));
+        assert_eq!(
+            parse_video_mode(b"1024x768"),
+            Some(VideoMode::Resolution {
+                width: 1024,
+                height: 768,
+                bits_per_pixel: 32
+            })
+        );
+        assert_eq!(
+            parse_video_mode(b"800x600x16"),
+            Some(VideoMode::Resolution {
+                width: 800,
+                height: 600,
+                bits_per_pixel: 16
+            })
+        );
+    }
+
+    #[test]
+    fn parse_video_mode_malformed() {
+        for value in [
+            b"".as_slice(),
+            b"1024",
+            b"1024x",
+            b"x768",
+            b"1024x768x",
+            b"1024x768x32x1",
+            b"1024X768",
+            b"1024*768",
+            b"-1024x768",
+            b"0x600",
+            b"800x0",
+            b"800x600x0",
+            b"Max",
+        ] {
+            assert_eq!(parse_video_mode(value), None, "{value:?}");
+        }
+    }
+
+    /// The ASCII as the UCS-2 load options, zero-terminated.
+    fn ucs2<const N: usize>(s: &str) -> [u8; N] {
+        let mut options = [0; N];
+        for (i, c) in s.bytes().enumerate() {
+            options[2 * i] = c;
+        }
+        options
+    }
+
+    #[test]
+    fn load_options_override_config() {
+        let mut config = BootLoaderConfig::default();
+        apply_config(
+            &mut config,
+            b"log_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\ncmdline = \"quiet\"\n",
+        );
+        let options: [u8; 160] =
+            ucs2("\\EFI\\corgos\\loader.efi log_level=trace cmdline=\"console=ttyS0 debug\"");
+        apply_ucs2_settings(&mut config, &options);
+
+        assert_eq!(config.log_level, LevelFilter::Trace);
+        assert_eq!(config.cmdline_str(), "console=ttyS0 debug");
+        assert_eq!(config.kernel_a_str(), "\\EFI\\corgos\\kernel");
+    }
+
+    #[test]
+    fn load_options_without_settings() {
+        let mut config = BootLoaderConfig::default();
+        apply_config(&mut config, b"log_level = warn\n");
+        let options: [u8; 64] = ucs2("\\EFI\\corgos\\loader.efi debug");
+        apply_ucs2_settings(&mut config, &options);
+        assert_eq!(config.log_level, LevelFilter::Warn);
+
+        // Nothing after the terminating zero is taken.
+        let mut options: [u8; 64] = ucs2("log_level=error");
+        options[32..].copy_from_slice(&ucs2::<32>("log_level=debug"));
+        apply_ucs2_settings(&mut config, &options);
+        assert_eq!(config.log_level, LevelFilter::Error);
+    }
+
+    #[test]
+    fn config_crc32() {
+        // The CRC32 of the lines other than the `crc32` one is da55e062.
+        assert!(config_crc32_matches(
+            b"crc32 = da55e062\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n"
+        ));
+        assert!(config_crc32_matches(
+            b"log_level = info\ncrc32 = DA55E062\nkernel = \"\\EFI\\corgos\\kernel\"\n"
+        ));
+        // No `crc32` line, nothing to check.
+        assert!(config_crc32_matches(b"log_level = info\n"));
+    }
+
+    #[test]
+    fn config_crc32_mismatch() {
+        for bytes in [
+            // A digit off.
+            b"crc32 = da55e063\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n".as_slice(),
+            // A line changed.
+            b"crc32 = da55e062\nlog_level = warn\nkernel = \"\\EFI\\corgos\\kernel\"\n",
+            // A line gone.
+            b"crc32 = da55e062\nlog_level = info\n",
+            // Not hex.
+            b"crc32 = da55e0g2\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n",
+            // No value.
+            b"crc32 =\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n",
+            b"crc32\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n",
+            // The second `crc32` line is a part of the file.
+            b"crc32 = da55e062\ncrc32 = da55e062\nlog_level = info\nkernel = \"\\EFI\\corgos\\kernel\"\n",
+        ] {
+            assert!(!config_crc32_matches(bytes), "{bytes:?}");
+        }
+    }
 }
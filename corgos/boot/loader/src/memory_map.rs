@@ -0,0 +1,225 @@
+//! The memory map for the kernel.
+//!
+//! The UEFI memory map is converted to the [`bootinfo::MemoryRegion`]
+//! records so the kernel does not depend on the `uefi` crate, and does not
+//! need to know about the firmware memory types. The records are
+//! normalized with the `memmap` crate, so they are sorted and disjoint
+//! whatever the firmware reports. The storage is allocated
+//! before exiting the boot services with some slack as the map changes
+//! when the loader allocates memory, and there is no allocating afterwards.
+//! The same goes for the buffer the firmware memory map is read into when
+//! exiting the boot services.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+use bootinfo::MEMORY_MAP_VERSION;
+use memmap::RegionList;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryDescriptor;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::mem::memory_map::MemoryMapKey;
+use uefi::mem::memory_map::MemoryMapMeta;
+use uefi::mem::memory_map::MemoryMapRefMut;
+use uefi::table;
+use uefi::Status;
+use uefi::StatusExt;
+
+/// Entries the map may grow by between now and exiting the boot services.
+const MEMORY_MAP_SLACK: usize = 64;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Allocates the storage for the converted memory map.
+pub fn allocate_regions() -> &'static mut [MemoryRegion] {
+    let entry_count = boot::memory_map(MemoryType::LOADER_DATA)
+        .or_fail(BootError::MemoryMap, "Cannot get the memory map")
+        .len();
+    let capacity = entry_count + MEMORY_MAP_SLACK;
+    let pages = (capacity * core::mem::size_of::<MemoryRegion>()).div_ceil(PAGE_SIZE);
+    let regions = boot::allocate_pages(AllocateType::AnyPages, memory_types::BOOT_INFO, pages)
+        .or_fail(
+            BootError::OutOfMemory,
+            "Cannot allocate pages for the memory map",
+        )
+        .cast::<MemoryRegion>();
+
+    // SAFETY: the pages have just been allocated, and are large enough. The
+    // regions are written before being read.
+    unsafe { core::slice::from_raw_parts_mut(regions.as_ptr(), capacity) }
+}
+
+pub fn memory_kind(ty: MemoryType) -> MemoryKind {
+    memmap::uefi_memory_kind(ty.0).unwrap_or(match ty {
+        memory_types::KERNEL_IMAGE => MemoryKind::KernelImage,
+        memory_types::BOOT_INFO => MemoryKind::BootInfo,
+        memory_types::AP_TRAMPOLINE => MemoryKind::ApTrampoline,
+        memory_types::PAGE_TABLES => MemoryKind::PageTables,
+        memory_types::BOOT_MODULE => MemoryKind::BootModule,
+        memory_types::EARLY_HEAP => MemoryKind::EarlyHeap,
+        memory_types::KERNEL_STACK => MemoryKind::KernelStack,
+        memory_types::KERNEL_SYMBOLS => MemoryKind::KernelSymbols,
+        memory_types::FRAMEBUFFER_CONSOLE => MemoryKind::FramebufferConsole,
+        memory_types::PAGE_BITMAP => MemoryKind::PageBitmap,
+        memory_types::MEMORY_MAP => MemoryKind::LoaderReclaimable,
+        _ => MemoryKind::Reserved,
+    })
+}
+
+/// Converts the UEFI memory map into `regions`, and normalizes them.
+pub fn convert(
+    memory_map: &impl MemoryMap,
+    regions: &'static mut [MemoryRegion],
+) -> bootinfo::MemoryMap {
+    let mut list = RegionList::new(regions);
+    for entry in memory_map.entries() {
+        let region = memmap::from_uefi(
+            memory_kind(entry.ty),
+            entry.phys_start,
+            entry.page_count,
+            entry.att.bits(),
+        )
+        .unwrap_or_else(|e| {
+            BootError::MemoryMap.fail(format_args!("The memory map entry {entry:x?}: {e:?}"))
+        });
+        list.push(region)
+            .or_fail(BootError::MemoryMap, "The memory map has grown too much");
+    }
+    list.normalize()
+        .or_fail(BootError::MemoryMap, "The memory map has too many overlaps");
+
+    let regions = list.into_slice();
+    bootinfo::MemoryMap {
+        version: MEMORY_MAP_VERSION,
+        count: regions.len() as u32,
+        regions: regions.as_ptr() as u64,
+    }
+}
+
+/// How many times exiting the boot services is attempted.
+const EXIT_BOOT_SERVICES_ATTEMPTS: usize = 8;
+
+/// The buffer for the firmware memory map of `entry_count` entries and the
+/// slack. The firmware descriptors may be larger than `MemoryDescriptor`.
+fn allocate_map_buffer(memory_type: MemoryType, entry_count: usize) -> &'static mut [u8] {
+    let size = (entry_count + MEMORY_MAP_SLACK) * core::mem::size_of::<MemoryDescriptor>() * 2;
+    let buf = boot::allocate_pool(memory_type, size).or_fail(
+        BootError::OutOfMemory,
+        "Cannot allocate the memory map for exiting the boot services",
+    );
+    // SAFETY: the pool has just been allocated, the pool allocations are
+    // 8-byte aligned as the descriptors need.
+    unsafe { core::slice::from_raw_parts_mut(buf.as_ptr(), size) }
+}
+
+/// Reads the memory map into `buf`, and exits the boot services with its
+/// key. The error has the size of the map, the one needed if `buf` is too
+/// small.
+///
+/// # Safety
+///
+/// The boot services must be available.
+unsafe fn get_memory_map_and_exit(buf: &mut [u8]) -> uefi::Result<MemoryMapMeta, usize> {
+    let system_table = table::system_table_raw().or_fail(
+        BootError::MemoryMap,
+        "No system table to exit the boot services with",
+    );
+    // SAFETY: the boot services are available as guaranteed by the caller.
+    let boot_services = unsafe { &*system_table.as_ref().boot_services };
+
+    let mut meta = MemoryMapMeta {
+        map_size: buf.len(),
+        desc_size: 0,
+        map_key: MemoryMapKey::default(),
+        desc_version: 0,
+    };
+    let mut map_key = 0;
+    // SAFETY: the buffer is `map_size` bytes, and aligned by the caller.
+    unsafe {
+        (boot_services.get_memory_map)(
+            &mut meta.map_size,
+            buf.as_mut_ptr().cast::<MemoryDescriptor>(),
+            &mut map_key,
+            &mut meta.desc_size,
+            &mut meta.desc_version,
+        )
+    }
+    .to_result_with_err(|_| meta.map_size)?;
+
+    // SAFETY: the boot services are available as guaranteed by the caller.
+    let status =
+        unsafe { (boot_services.exit_boot_services)(boot::image_handle().as_ptr(), map_key) };
+    status.to_result_with(|| meta, |_| meta.map_size)
+}
+
+/// Exits the boot services, and returns the final memory map.
+///
+/// The firmware refuses to exit if the memory map has changed since it has
+/// been read, and then only the memory map may be read again. Some firmware
+/// changes the map behind the loader's back, e.g. while the log is written
+/// to the console, so the map is read and the exit is retried a few times
+/// with nothing in between. The buffer is allocated upfront with some slack,
+/// and grown if the map has outgrown it before the first exit: allocating
+/// is not allowed after that. The log stops going to the UEFI console
+/// before anything is logged about the exit.
+///
+/// # Safety
+///
+/// Nothing may use the boot services after this.
+pub unsafe fn exit_boot_services(memory_type: MemoryType) -> MemoryMapRefMut<'static> {
+    let entry_count = boot::memory_map(memory_type)
+        .or_fail(BootError::MemoryMap, "Cannot get the memory map")
+        .len();
+    // Never freed once the boot services are gone.
+    let mut buf = allocate_map_buffer(memory_type, entry_count);
+
+    let mut attempts = 0;
+    let mut exit_failed = false;
+    let meta = loop {
+        attempts += 1;
+        // SAFETY: the boot services are still available as the exit has
+        // failed.
+        match unsafe { get_memory_map_and_exit(buf) } {
+            Ok(meta) => break meta,
+            Err(e)
+                if e.status() == Status::BUFFER_TOO_SMALL
+                    && !exit_failed
+                    && attempts < EXIT_BOOT_SERVICES_ATTEMPTS =>
+            {
+                let entry_count = e.data() / core::mem::size_of::<MemoryDescriptor>();
+                let old_buf = core::ptr::NonNull::from(&mut *buf).cast::<u8>();
+                buf = allocate_map_buffer(memory_type, entry_count);
+                // SAFETY: the old buffer is from the pool, and is not used
+                // anymore.
+                unsafe { boot::free_pool(old_buf) }.unwrap_or_default();
+            }
+            Err(e)
+                if e.status() == Status::INVALID_PARAMETER
+                    && attempts < EXIT_BOOT_SERVICES_ATTEMPTS =>
+            {
+                exit_failed = true;
+            }
+            Err(e) => {
+                if exit_failed {
+                    // The firmware may have torn the console down.
+                    boot_logger::boot_services_exited();
+                }
+                BootError::MemoryMap.fail(format_args!(
+                    "Cannot exit the boot services after {attempts} attempts: {:?}",
+                    e.status()
+                ))
+            }
+        }
+    };
+    boot_logger::boot_services_exited();
+
+    if attempts > 1 {
+        log::warn!("Exited the boot services after {attempts} attempts");
+    }
+    MemoryMapRefMut::new(&mut buf[..meta.map_size], meta)
+        .or_fail(BootError::MemoryMap, "The final memory map is not valid")
+}
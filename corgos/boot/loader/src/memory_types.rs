@@ -0,0 +1,30 @@
+//! The OS loader memory types.
+//!
+//! UEFI reserves `0x80000000..=0xffffffff` for the OS loaders, so the
+//! kernel can find its own allocations in the memory map handed over to it.
+
+use uefi::boot::MemoryType;
+
+/// The loaded kernel image.
+pub const KERNEL_IMAGE: MemoryType = MemoryType(0x8000_0001);
+/// The [`bootinfo::BootInfo`] structure.
+pub const BOOT_INFO: MemoryType = MemoryType(0x8000_0002);
+/// The page for starting the secondary processors on x86_64.
+pub const AP_TRAMPOLINE: MemoryType = MemoryType(0x8000_0003);
+/// The kernel page tables.
+pub const PAGE_TABLES: MemoryType = MemoryType(0x8000_0004);
+/// The boot modules.
+pub const BOOT_MODULE: MemoryType = MemoryType(0x8000_0005);
+/// The early kernel heap.
+pub const EARLY_HEAP: MemoryType = MemoryType(0x8000_0006);
+/// The initial kernel stack.
+pub const KERNEL_STACK: MemoryType = MemoryType(0x8000_0007);
+/// The kernel symbols for the backtraces.
+pub const KERNEL_SYMBOLS: MemoryType = MemoryType(0x8000_0008);
+/// The early framebuffer console scratch memory.
+pub const FRAMEBUFFER_CONSOLE: MemoryType = MemoryType(0x8000_0009);
+/// The page bitmap for the kernel page allocator.
+pub const PAGE_BITMAP: MemoryType = MemoryType(0x8000_000a);
+/// The UEFI memory map read when exiting the boot services, the kernel
+/// gets it converted in the boot info.
+pub const MEMORY_MAP: MemoryType = MemoryType(0x8000_000b);
@@ -0,0 +1,54 @@
+//! Boot modules.
+//!
+//! The files listed as `module.<name> = path[,cmdline]` in the configuration
+//! are loaded each into its own allocation, and described to the kernel. The
+//! kernel finds them by name, no file system needed. The modules compressed
+//! with LZ4 are decompressed.
+
+use crate::decompress;
+use crate::files;
+use crate::memory_types;
+use boot_logger::BootModule;
+use bootinfo::Module;
+use bootinfo::Modules;
+
+/// Loads the configured modules. A module that cannot be read or
+/// decompressed is skipped.
+pub fn load_modules(config_modules: &[BootModule], modules: &mut Modules) {
+    *modules = Modules::default();
+
+    for config_module in config_modules.iter().filter(|m| m.is_used()) {
+        let name = config_module.name_str();
+        let path = config_module.path_str();
+        let data = match files::read_file(path, memory_types::BOOT_MODULE) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Cannot load module '{name}' from '{path}': {e:?}");
+                continue;
+            }
+        };
+        let data = match decompress::decompress_file(path, data, memory_types::BOOT_MODULE) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Cannot decompress module '{name}' from '{path}': {e:?}");
+                continue;
+            }
+        };
+
+        let module = &mut modules.modules[modules.count as usize];
+        *module = Module {
+            phys_base: data.as_ptr() as u64,
+            size: data.len() as u64,
+            ..Module::default()
+        };
+        boot_logger::set_zero_terminated(&mut module.name, &config_module.name);
+        boot_logger::set_zero_terminated(&mut module.cmdline, &config_module.cmdline);
+        modules.count += 1;
+
+        log::info!(
+            "Module '{name}' from '{path}' @ {:#016x}, {} bytes",
+            module.phys_base,
+            module.size
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Cross-checking the processors with the EFI MP Services.
+//!
+//! The processor list comes from the ACPI MADT, the firmware has its own
+//! from bringing the processors up. The two are compared, and the location
+//! and the health the firmware reports are passed to the kernel. With
+//! `mp_probe`, the firmware also runs a short probe on each processor that
+//! reads its hardware ID and revision, one processor at a time.
+
+use crate::cpus;
+use bootinfo::Cpus;
+use bootinfo::CPU_FLAG_HEALTHY;
+use bootinfo::CPU_FLAG_MP_REPORTED;
+use bootinfo::CPU_FLAG_PROBED;
+use core::ffi::c_void;
+use core::time::Duration;
+use uefi::boot;
+use uefi::proto::pi::mp::MpServices;
+
+/// How long the probe may take on all processors.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[cfg(target_arch = "x86_64")]
+fn hw_id(processor_id: u64) -> u64 {
+    processor_id
+}
+
+/// The MP Services report the whole MPIDR.
+#[cfg(target_arch = "aarch64")]
+fn hw_id(processor_id: u64) -> u64 {
+    processor_id & 0xff_00ff_ffff
+}
+
+/// The microcode revision.
+#[cfg(target_arch = "x86_64")]
+fn revision() -> u64 {
+    const IA32_BIOS_SIGN_ID: u32 = 0x8b;
+
+    let revision: u32;
+    // SAFETY: writing zero and executing CPUID leaf 1 makes the processor
+    // load the revision into the MSR, nothing else changes.
+    unsafe {
+        core::arch::asm!("wrmsr", in("ecx") IA32_BIOS_SIGN_ID, in("eax") 0, in("edx") 0, options(nomem, nostack));
+        core::arch::x86_64::__cpuid(1);
+        core::arch::asm!("rdmsr", in("ecx") IA32_BIOS_SIGN_ID, out("eax") _, out("edx") revision, options(nomem, nostack));
+    }
+    revision as u64
+}
+
+/// `MIDR_EL1`, the cores of a big.LITTLE system differ there.
+#[cfg(target_arch = "aarch64")]
+fn revision() -> u64 {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::MainIdEl1;
+
+    let mut midr = MainIdEl1::new();
+    midr.load();
+    midr.bits()
+}
+
+/// Records the revision of the processor the caller runs on.
+fn probe_current(cpus: &mut Cpus) {
+    let hw_id = cpus::current_cpu_hw_id();
+    let count = cpus.count as usize;
+    if let Some(cpu) = cpus.cpus[..count].iter_mut().find(|cpu| cpu.hw_id == hw_id) {
+        cpu.revision = revision();
+        cpu.flags |= CPU_FLAG_PROBED;
+    }
+}
+
+/// Runs on the application processors, must not call the boot services
+/// or log.
+extern "efiapi" fn probe(cpus: *mut c_void) {
+    // SAFETY: the processors run the probe one at a time while the boot
+    // processor waits for them.
+    probe_current(unsafe { &mut *(cpus as *mut Cpus) });
+}
+
+/// Compares the processors with what the MP Services report, and probes
+/// them if asked.
+pub fn cross_check(cpus: &mut Cpus, run_probe: bool) {
+    let mp = match boot::get_handle_for_protocol::<MpServices>()
+        .and_then(boot::open_protocol_exclusive::<MpServices>)
+    {
+        Ok(mp) => mp,
+        Err(e) => {
+            log::info!("No MP Services: {:?}", e.status());
+            return;
+        }
+    };
+
+    let Ok(count) = mp.get_number_of_processors() else {
+        log::warn!("MP Services cannot count the processors");
+        return;
+    };
+    log::info!(
+        "MP Services: {} processors, {} enabled",
+        count.total,
+        count.enabled
+    );
+
+    for number in 0..count.total {
+        let Ok(info) = mp.get_processor_info(number) else {
+            log::warn!("MP Services: no information on the processor {number}");
+            continue;
+        };
+        log::info!(
+            "MP processor {number}: {:#x}, package {} core {} thread {}{}{}{}",
+            info.processor_id,
+            info.location.package,
+            info.location.core,
+            info.location.thread,
+            if info.is_bsp() { ", boot" } else { "" },
+            if info.is_enabled() {
+                ", enabled"
+            } else {
+                ", disabled"
+            },
+            if info.is_healthy() {
+                ", healthy"
+            } else {
+                ", unhealthy"
+            }
+        );
+
+        let hw_id = hw_id(info.processor_id);
+        let cpu_count = cpus.count as usize;
+        let Some(cpu) = cpus.cpus[..cpu_count]
+            .iter_mut()
+            .find(|cpu| cpu.hw_id == hw_id)
+        else {
+            log::warn!("The processor {hw_id:#x} is not in the MADT");
+            continue;
+        };
+        cpu.flags |= CPU_FLAG_MP_REPORTED;
+        if info.is_healthy() {
+            cpu.flags |= CPU_FLAG_HEALTHY;
+        }
+        cpu.package = info.location.package;
+        cpu.core = info.location.core;
+        cpu.thread = info.location.thread;
+    }
+
+    for cpu in cpus
+        .cpus()
+        .iter()
+        .filter(|cpu| cpu.flags & CPU_FLAG_MP_REPORTED == 0)
+    {
+        log::warn!(
+            "The processor {:#x} is not reported by MP Services",
+            cpu.hw_id
+        );
+    }
+
+    if !run_probe {
+        return;
+    }
+
+    probe_current(cpus);
+    if count.enabled > 1 {
+        if let Err(e) = mp.startup_all_aps(
+            true,
+            probe,
+            cpus as *mut Cpus as *mut c_void,
+            None,
+            Some(PROBE_TIMEOUT),
+        ) {
+            log::warn!("The probe has failed on the application processors: {e:?}");
+        }
+    }
+
+    for cpu in cpus.cpus() {
+        if cpu.flags & CPU_FLAG_PROBED != 0 {
+            log::info!("Processor {:#x}: revision {:#x}", cpu.hw_id, cpu.revision);
+        } else if cpu.enabled != 0 {
+            log::warn!("Processor {:#x} has not run the probe", cpu.hw_id);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Downloading files over the network.
+//!
+//! The files are fetched over TFTP with the PXE Base Code protocol, that is
+//! what the firmware offers on the boards in the lab. The paths look like
+//! `tftp://10.0.0.1/corgos/kernel`, and `tftp:///corgos/kernel` uses the
+//! boot server the loader itself has been downloaded from. The firmware
+//! does not resolve the host names, and the UEFI HTTP protocol is not
+//! supported.
+//!
+//! When the loader has been booted from the network and there is no
+//! configuration file on the disks, the configuration file is downloaded
+//! from the directory of the loader on the boot server.
+
+use crate::watchdog;
+use boot_logger::MAX_PATH_SIZE;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::boot::ScopedProtocol;
+use uefi::proto::network::pxe::BaseCode;
+use uefi::proto::network::pxe::DhcpV4Packet;
+use uefi::proto::network::IpAddress;
+use uefi::CStr16;
+use uefi::CStr8;
+use uefi::Status;
+
+const PAGE_SIZE: usize = 0x1000;
+const TFTP_PREFIX: &str = "tftp://";
+const HTTP_PREFIX: &str = "http://";
+/// The pause between the download attempts, in microseconds.
+const RETRY_DELAY: usize = 1_000_000;
+
+static RETRIES: AtomicUsize = AtomicUsize::new(boot_logger::DEFAULT_NET_RETRIES);
+
+/// Sets how many times a download is attempted.
+pub fn set_retries(retries: usize) {
+    RETRIES.store(retries.max(1), Ordering::Relaxed);
+}
+
+/// Whether the path is a URL rather than a path on a disk.
+pub fn is_network_path(path: &str) -> bool {
+    path.starts_with(TFTP_PREFIX) || path.starts_with(HTTP_PREFIX)
+}
+
+fn parse_ipv4(host: &str) -> Option<[u8; 4]> {
+    let mut ip = [0_u8; 4];
+    let mut octets = host.split('.');
+    for octet in ip.iter_mut() {
+        *octet = octets.next()?.parse().ok()?;
+    }
+    octets.next().is_none().then_some(ip)
+}
+
+/// Splits `tftp://host/path` into the server address, if any, and the path.
+fn parse_url(url: &str) -> uefi::Result<(Option<[u8; 4]>, &str)> {
+    if url.starts_with(HTTP_PREFIX) {
+        log::error!("'{url}': HTTP is not supported, use {TFTP_PREFIX}");
+        return Err(Status::UNSUPPORTED.into());
+    }
+
+    let (host, path) = url
+        .strip_prefix(TFTP_PREFIX)
+        .and_then(|rest| rest.split_once('/'))
+        .ok_or(uefi::Error::from(Status::INVALID_PARAMETER))?;
+    if host.is_empty() {
+        return Ok((None, path));
+    }
+    match parse_ipv4(host) {
+        Some(ip) => Ok((Some(ip), path)),
+        None => {
+            log::error!("'{url}': the server must be an IPv4 address");
+            Err(Status::INVALID_PARAMETER.into())
+        }
+    }
+}
+
+/// The Base Code protocol with the DHCP done.
+fn base_code() -> uefi::Result<ScopedProtocol<BaseCode>> {
+    let handle = boot::get_handle_for_protocol::<BaseCode>()?;
+    let mut pxe = boot::open_protocol_exclusive::<BaseCode>(handle)?;
+    if !pxe.mode().started {
+        pxe.start(false)?;
+    }
+    if !pxe.mode().dhcp_ack_received {
+        log::info!("Configuring the network with DHCP");
+        pxe.dhcp(true)?;
+    }
+
+    Ok(pxe)
+}
+
+/// The DHCP reply naming the boot server and the boot file.
+fn boot_reply(pxe: &BaseCode) -> &DhcpV4Packet {
+    let mode = pxe.mode();
+    if mode.proxy_offer_received {
+        mode.proxy_offer.as_ref()
+    } else {
+        mode.dhcp_ack.as_ref()
+    }
+}
+
+fn tftp_name<'a>(path: &str, buf: &'a mut [u8; MAX_PATH_SIZE + 1]) -> uefi::Result<&'a CStr8> {
+    let len = path.len();
+    if len > MAX_PATH_SIZE {
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    buf[..len].copy_from_slice(path.as_bytes());
+    buf[len] = 0;
+    CStr8::from_bytes_with_nul(&buf[..=len]).map_err(|_| Status::INVALID_PARAMETER.into())
+}
+
+fn download(
+    pxe: &mut BaseCode,
+    server: &IpAddress,
+    name: &CStr8,
+    memory_type: MemoryType,
+) -> uefi::Result<&'static mut [u8]> {
+    let file_size = pxe.tftp_get_file_size(server, name)? as usize;
+    if file_size == 0 {
+        return Err(Status::END_OF_FILE.into());
+    }
+    log::info!("Downloading {file_size} bytes");
+
+    let pages = file_size.div_ceil(PAGE_SIZE);
+    let ptr = boot::allocate_pages(AllocateType::AnyPages, memory_type, pages)?;
+    // SAFETY: the pages have just been allocated.
+    let data = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), file_size) };
+
+    let result = pxe.tftp_read_file(server, name, Some(data));
+    watchdog::refresh();
+    match result {
+        Ok(bytes_read) if bytes_read as usize == file_size => Ok(data),
+        result => {
+            // SAFETY: the pages have been allocated above and are not used.
+            unsafe { boot::free_pages(ptr, pages) }.ok();
+            match result {
+                Ok(_) => Err(Status::END_OF_FILE.into()),
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+/// Downloads the whole file into freshly allocated pages, the pages can
+/// be freed with [`crate::files::free_file`].
+pub fn read_file(url: &str, memory_type: MemoryType) -> uefi::Result<&'static mut [u8]> {
+    let (server, path) = parse_url(url)?;
+    let mut name_buf = [0_u8; MAX_PATH_SIZE + 1];
+    let name = tftp_name(path, &mut name_buf)?;
+
+    let mut pxe = base_code()?;
+    let server = IpAddress::new_v4(server.unwrap_or(boot_reply(&pxe).bootp_si_addr));
+
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut attempt = 1;
+    loop {
+        log::info!("Fetching '{url}', attempt {attempt} of {retries}");
+        match download(&mut pxe, &server, name, memory_type) {
+            Ok(data) => {
+                log::info!("Fetched '{url}', {} bytes", data.len());
+                return Ok(data);
+            }
+            Err(e) if attempt < retries => {
+                log::warn!("Could not fetch '{url}': {e:?}, retrying");
+                boot::stall(RETRY_DELAY);
+                attempt += 1;
+            }
+            Err(e) => {
+                log::error!("Could not fetch '{url}': {e:?}");
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Downloads the configuration file from the directory of the loader on
+/// the boot server if the loader has been booted from the network.
+/// Returns the size of the file.
+pub fn read_config(file_name: &CStr16, buf: &mut [u8]) -> Option<usize> {
+    let handle = boot::get_handle_for_protocol::<BaseCode>().ok()?;
+    let mut pxe = boot::open_protocol_exclusive::<BaseCode>(handle).ok()?;
+    if !pxe.mode().dhcp_ack_received {
+        return None;
+    }
+
+    let reply = boot_reply(&pxe);
+    let server = IpAddress::new_v4(reply.bootp_si_addr);
+    let boot_file = boot_logger::zero_terminated_str(&reply.bootp_boot_file);
+    let dir = boot_file.rfind('/').map(|i| &boot_file[..=i]).unwrap_or("");
+
+    let mut path_buf = [0_u8; MAX_PATH_SIZE + 1];
+    let mut len = 0;
+    for c in dir
+        .bytes()
+        .chain(file_name.iter().map(|&c| u16::from(c) as u8))
+    {
+        *path_buf.get_mut(len)? = c;
+        len += 1;
+    }
+    let name = CStr8::from_bytes_with_nul(path_buf.get(..=len)?).ok()?;
+
+    let size = pxe.tftp_read_file(&server, name, Some(buf)).ok()?;
+    Some(size as usize)
+}
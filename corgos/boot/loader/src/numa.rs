@@ -0,0 +1,277 @@
+//! The NUMA topology from the ACPI SRAT and SLIT.
+//!
+//! The SRAT assigns the memory ranges and the processors to the proximity
+//! domains, and the SLIT has the relative distances between them. The
+//! domains are numbered densely as the nodes in the order they are found,
+//! so the kernel can size its per-node structures by the node count and
+//! partition the page bitmap along the node memory ranges from the start.
+//! The `acpi` crate does not parse these tables, hence the raw access.
+
+use acpi::sdt::SdtHeader;
+use acpi::sdt::Signature;
+use acpi::AcpiHandler;
+use acpi::AcpiTable;
+use acpi::AcpiTables;
+use acpi::PhysicalMapping;
+use bootinfo::Cpus;
+use bootinfo::Numa;
+use bootinfo::NumaMemoryRange;
+use bootinfo::MAX_NUMA_MEMORY_RANGES;
+use bootinfo::MAX_NUMA_NODES;
+use bootinfo::NUMA_MEMORY_HOT_PLUGGABLE;
+use bootinfo::NUMA_MEMORY_NON_VOLATILE;
+
+const SRAT_PROCESSOR_APIC: u8 = 0;
+const SRAT_MEMORY: u8 = 1;
+const SRAT_PROCESSOR_X2APIC: u8 = 2;
+const SRAT_GICC: u8 = 3;
+
+const SRAT_PROCESSOR_APIC_LENGTH: usize = 16;
+const SRAT_MEMORY_LENGTH: usize = 40;
+const SRAT_PROCESSOR_X2APIC_LENGTH: usize = 24;
+const SRAT_GICC_LENGTH: usize = 18;
+
+/// The entry is used, the same bit in all the SRAT entries.
+const SRAT_ENABLED: u32 = 1 << 0;
+
+/// The number of the localities comes after the header.
+const SLIT_ENTRIES_OFFSET: usize = core::mem::size_of::<SdtHeader>() + 8;
+
+#[repr(C, packed)]
+struct Srat {
+    header: SdtHeader,
+    reserved: [u8; 12],
+}
+
+// SAFETY: the layout is the one of the SRAT.
+unsafe impl AcpiTable for Srat {
+    const SIGNATURE: Signature = Signature::SRAT;
+
+    fn header(&self) -> &SdtHeader {
+        &self.header
+    }
+}
+
+#[repr(C, packed)]
+struct Slit {
+    header: SdtHeader,
+    locality_count: u64,
+}
+
+// SAFETY: the layout is the one of the SLIT.
+unsafe impl AcpiTable for Slit {
+    const SIGNATURE: Signature = Signature::SLIT;
+
+    fn header(&self) -> &SdtHeader {
+        &self.header
+    }
+}
+
+/// The whole table, its length has been validated with the checksum.
+fn table_bytes<H: AcpiHandler, T: AcpiTable>(table: &PhysicalMapping<H, T>) -> &[u8] {
+    let length = table.header().length as usize;
+    // SAFETY: the tables are identity-mapped, and the checksum covers
+    // `length` bytes.
+    unsafe { core::slice::from_raw_parts(table.virtual_start().as_ptr() as *const u8, length) }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from(read_u32(bytes, offset)) | u64::from(read_u32(bytes, offset + 4)) << 32
+}
+
+/// The SRAT entries as the type and the whole entry.
+fn srat_entries(bytes: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut offset = core::mem::size_of::<Srat>();
+    core::iter::from_fn(move || {
+        let header = bytes.get(offset..offset + 2)?;
+        let entry = bytes.get(offset..offset + header[1] as usize)?;
+        if entry.len() < 2 {
+            log::warn!("Malformed SRAT entry at {offset:#x}");
+            return None;
+        }
+        offset += entry.len();
+        Some((header[0], entry))
+    })
+}
+
+/// The node of the proximity domain, added if new.
+fn node(numa: &mut Numa, proximity_domain: u32) -> Option<u32> {
+    let count = numa.node_count as usize;
+    if let Some(node) = numa.proximity_domains[..count]
+        .iter()
+        .position(|&domain| domain == proximity_domain)
+    {
+        return Some(node as u32);
+    }
+    if count >= MAX_NUMA_NODES {
+        log::warn!(
+            "Ignoring the proximity domain {proximity_domain}, more than {MAX_NUMA_NODES} nodes"
+        );
+        return None;
+    }
+
+    numa.proximity_domains[count] = proximity_domain;
+    numa.node_count += 1;
+    Some(count as u32)
+}
+
+fn add_memory_range(numa: &mut Numa, entry: &[u8]) {
+    let flags = read_u32(entry, 28);
+    let base = read_u64(entry, 8);
+    let length = read_u64(entry, 16);
+    if flags & SRAT_ENABLED == 0 || length == 0 {
+        return;
+    }
+    let Some(node) = node(numa, read_u32(entry, 2)) else {
+        return;
+    };
+    if numa.memory_range_count as usize >= MAX_NUMA_MEMORY_RANGES {
+        log::warn!(
+            "Ignoring the NUMA memory @ {base:#016x}, more than {MAX_NUMA_MEMORY_RANGES} ranges"
+        );
+        return;
+    }
+
+    numa.memory_ranges[numa.memory_range_count as usize] = NumaMemoryRange {
+        base,
+        length,
+        node,
+        flags: flags & (NUMA_MEMORY_HOT_PLUGGABLE | NUMA_MEMORY_NON_VOLATILE),
+    };
+    numa.memory_range_count += 1;
+}
+
+/// Assigns the node to the processor matching by the hardware ID, or by
+/// the ACPI processor UID for the GICC entries.
+fn assign_cpu(
+    numa: &mut Numa,
+    cpus: &mut Cpus,
+    proximity_domain: u32,
+    hw_id: Option<u64>,
+    uid: Option<u32>,
+) {
+    let Some(node) = node(numa, proximity_domain) else {
+        return;
+    };
+    let count = cpus.count as usize;
+    if let Some(cpu) = cpus.cpus[..count]
+        .iter_mut()
+        .find(|cpu| Some(cpu.hw_id) == hw_id || Some(cpu.uid) == uid)
+    {
+        cpu.node = node;
+    }
+}
+
+fn parse_srat(srat: &[u8], cpus: &mut Cpus, numa: &mut Numa) {
+    for (kind, entry) in srat_entries(srat) {
+        match kind {
+            SRAT_PROCESSOR_APIC if entry.len() >= SRAT_PROCESSOR_APIC_LENGTH => {
+                if read_u32(entry, 4) & SRAT_ENABLED != 0 {
+                    let proximity_domain = u32::from(entry[2])
+                        | u32::from(entry[9]) << 8
+                        | u32::from(entry[10]) << 16
+                        | u32::from(entry[11]) << 24;
+                    assign_cpu(
+                        numa,
+                        cpus,
+                        proximity_domain,
+                        Some(u64::from(entry[3])),
+                        None,
+                    );
+                }
+            }
+            SRAT_MEMORY if entry.len() >= SRAT_MEMORY_LENGTH => add_memory_range(numa, entry),
+            SRAT_PROCESSOR_X2APIC if entry.len() >= SRAT_PROCESSOR_X2APIC_LENGTH => {
+                if read_u32(entry, 12) & SRAT_ENABLED != 0 {
+                    let x2apic_id = u64::from(read_u32(entry, 8));
+                    assign_cpu(numa, cpus, read_u32(entry, 4), Some(x2apic_id), None);
+                }
+            }
+            SRAT_GICC if entry.len() >= SRAT_GICC_LENGTH => {
+                if read_u32(entry, 10) & SRAT_ENABLED != 0 {
+                    let uid = read_u32(entry, 6);
+                    assign_cpu(numa, cpus, read_u32(entry, 2), None, Some(uid));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_slit(slit: &[u8], numa: &mut Numa) {
+    let locality_count = read_u64(slit, core::mem::size_of::<SdtHeader>()) as usize;
+    let entries_end = locality_count
+        .checked_mul(locality_count)
+        .and_then(|entries| entries.checked_add(SLIT_ENTRIES_OFFSET));
+    if entries_end.map_or(true, |end| slit.len() < end) {
+        log::warn!("The SLIT is too short for {locality_count} localities");
+        return;
+    }
+
+    let node_count = numa.node_count as usize;
+    let domains = numa.proximity_domains;
+    for (from, &from_domain) in domains[..node_count].iter().enumerate() {
+        for (to, &to_domain) in domains[..node_count].iter().enumerate() {
+            let (from_domain, to_domain) = (from_domain as usize, to_domain as usize);
+            if from_domain < locality_count && to_domain < locality_count {
+                numa.distances[from][to] =
+                    slit[SLIT_ENTRIES_OFFSET + from_domain * locality_count + to_domain];
+            }
+        }
+    }
+}
+
+/// Collects the NUMA nodes, their memory, and distances, and assigns the
+/// processors to the nodes.
+pub fn collect_numa(tables: &AcpiTables<impl AcpiHandler>, cpus: &mut Cpus, numa: &mut Numa) {
+    *numa = Numa::default();
+
+    let Ok(srat) = tables.find_table::<Srat>() else {
+        log::info!("No SRAT, a single NUMA node");
+        return;
+    };
+    numa.node_count = 0;
+    parse_srat(table_bytes(&srat), cpus, numa);
+    if numa.node_count == 0 {
+        log::warn!("The SRAT has no proximity domains, a single NUMA node");
+        *numa = Numa::default();
+        return;
+    }
+    let range_count = numa.memory_range_count as usize;
+    numa.memory_ranges[..range_count].sort_unstable_by_key(|range| range.base);
+
+    match tables.find_table::<Slit>() {
+        Ok(slit) => parse_slit(table_bytes(&slit), numa),
+        Err(_) => log::info!("No SLIT, assuming the default NUMA distances"),
+    }
+
+    for (node, domain) in numa.proximity_domains[..numa.node_count as usize]
+        .iter()
+        .enumerate()
+    {
+        let cpu_count = cpus
+            .cpus()
+            .iter()
+            .filter(|cpu| cpu.node == node as u32)
+            .count();
+        log::info!(
+            "NUMA node {node}: proximity domain {domain}, {cpu_count} processors, distances {:?}",
+            &numa.distances[node][..numa.node_count as usize]
+        );
+    }
+    for range in numa.memory_ranges() {
+        log::info!(
+            "NUMA memory {:#016x}-{:#016x}: node {}, flags {:#x}",
+            range.base,
+            range.base + range.length,
+            range.node,
+            range.flags
+        );
+    }
+}
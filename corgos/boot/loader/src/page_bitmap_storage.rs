@@ -0,0 +1,103 @@
+//! The page bitmap for the kernel page allocator.
+//!
+//! The storage is allocated before exiting the boot services, sized for
+//! the usable memory there is then, as much of it as the bitmap tracks. The
+//! allocations after that only take the usable memory away, so once the
+//! memory map is final, the bitmap is built over it: the pages of the
+//! usable regions are free, and the rest are allocated. The kernel takes
+//! the bitmap over through the linear map, and checks the checksum first.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_map;
+use crate::memory_types;
+use crate::paging::LINEAR_MAP_BASE;
+use crate::paging::LINEAR_MAP_MAX_SIZE;
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+use bootinfo::PageBitmapStorage;
+use page_bitmap::PageBitmap;
+use page_bitmap::MAX_MEMORY_SUPPORTED_BYTES;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Allocates the storage for the bitmap of the usable memory.
+pub fn allocate_page_bitmap() -> PageBitmapStorage {
+    let memory_map = boot::memory_map(MemoryType::LOADER_DATA)
+        .or_fail(BootError::MemoryMap, "Cannot get the memory map");
+    let Some(end) = memory_map
+        .entries()
+        .filter(|entry| memory_map::memory_kind(entry.ty) == MemoryKind::Usable)
+        .map(|entry| entry.phys_start + entry.page_count * PAGE_SIZE)
+        .max()
+    else {
+        BootError::OutOfMemory.fail(format_args!("No usable memory for the kernel"));
+    };
+    let max_memory = end.min(MAX_MEMORY_SUPPORTED_BYTES as u64);
+    if max_memory < end {
+        log::warn!("The page bitmap does not track the memory above {max_memory:#x}");
+    }
+
+    let size = page_bitmap::page_bitmap_storage_size(max_memory as usize);
+    let storage = boot::allocate_pages(
+        AllocateType::MaxAddress(LINEAR_MAP_MAX_SIZE - 1),
+        memory_types::PAGE_BITMAP,
+        size.div_ceil(PAGE_SIZE as usize),
+    )
+    .or_fail(BootError::OutOfMemory, "Cannot allocate the page bitmap");
+
+    let phys_base = storage.as_ptr() as u64;
+    log::info!(
+        "Page bitmap @ {:#016x} -> {phys_base:#016x}, {size:#x} bytes for {max_memory:#x} bytes",
+        LINEAR_MAP_BASE + phys_base
+    );
+    PageBitmapStorage {
+        phys_base,
+        virt_base: LINEAR_MAP_BASE + phys_base,
+        size: size as u64,
+        max_memory,
+        checksum: 0,
+        reserved: 0,
+    }
+}
+
+/// Builds the bitmap over the final memory map, and sets the checksum.
+pub fn build_page_bitmap(storage: &mut PageBitmapStorage, regions: &[MemoryRegion]) {
+    // SAFETY: the storage has been allocated for the bitmap, and the
+    // loader runs on the identity map.
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(storage.phys_base as *mut u8, storage.size as usize)
+    };
+    bytes.fill(0);
+
+    let max_pfn = storage.max_memory / PAGE_SIZE;
+    let mut allocated_pages = 0;
+    {
+        let mut bitmap = PageBitmap::from_storage(bytes, storage.max_memory as usize);
+        // The bitmap starts with everything free, take out the gaps
+        // between the usable regions.
+        let mut take_out = |bitmap: &mut PageBitmap, start_pfn: u64, end_pfn: u64| {
+            for pfn in start_pfn..end_pfn {
+                bitmap.allocate_page(pfn as usize).ok();
+                allocated_pages += 1;
+            }
+        };
+        let mut next_pfn = 0;
+        for range in memmap::page_ranges(regions, MemoryKind::Usable) {
+            take_out(&mut bitmap, next_pfn, range.start_pfn.min(max_pfn));
+            next_pfn = next_pfn.max(range.end_pfn.min(max_pfn));
+        }
+        take_out(&mut bitmap, next_pfn, max_pfn);
+    }
+
+    storage.checksum = digest::crc32(bytes);
+    log::info!(
+        "Page bitmap: {} free pages, checksum {:#010x}",
+        max_pfn - allocated_pages,
+        storage.checksum
+    );
+}
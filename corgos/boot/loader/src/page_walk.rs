@@ -0,0 +1,348 @@
+//! Walking the page tables the firmware has set up.
+//!
+//! The tables are assumed to use the 4K granule, and to be identity mapped.
+//! The walk either logs every entry, or coalesces the virtually contiguous
+//! pages with the same attributes into ranges and prints the totals per the
+//! attributes, that is a few dozen lines instead of tens of thousands.
+
+use boot_logger::PageTableWalk;
+
+const PAGE_SIZES: [(u64, &str); 3] = [(0x1000, "4KiB"), (0x20_0000, "2MiB"), (0x4000_0000, "1GiB")];
+/// The distinct attribute combinations in the totals.
+const MAX_ATTRIBUTE_CLASSES: usize = 16;
+
+/// The effective attributes of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Attributes {
+    writable: bool,
+    executable: bool,
+    user: bool,
+    memory: &'static str,
+}
+
+impl core::fmt::Display for Attributes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "R{}{}{} {}",
+            if self.writable { 'W' } else { '-' },
+            if self.executable { 'X' } else { '-' },
+            if self.user { 'U' } else { '-' },
+            self.memory
+        )
+    }
+}
+
+struct Range {
+    start: u64,
+    end: u64,
+    attributes: Attributes,
+    /// Bit `i` is set if there are pages of `PAGE_SIZES[i]`.
+    page_sizes: u8,
+}
+
+struct AttributeClass {
+    attributes: Attributes,
+    size: u64,
+    ranges: usize,
+}
+
+/// Coalesces the pages into ranges, the pages are added in the ascending
+/// order of the virtual addresses.
+struct Summary {
+    range: Option<Range>,
+    classes: [Option<AttributeClass>; MAX_ATTRIBUTE_CLASSES],
+}
+
+impl Summary {
+    fn new() -> Self {
+        Self {
+            range: None,
+            classes: [const { None }; MAX_ATTRIBUTE_CLASSES],
+        }
+    }
+
+    fn add(&mut self, virt: u64, size: u64, attributes: Attributes) {
+        let page_size = PAGE_SIZES
+            .iter()
+            .position(|&(s, _)| s == size)
+            .map_or(0, |i| 1 << i);
+
+        if let Some(range) = self.range.as_mut() {
+            if range.end == virt && range.attributes == attributes {
+                range.end = virt + size;
+                range.page_sizes |= page_size;
+                return;
+            }
+        }
+
+        self.flush();
+        self.range = Some(Range {
+            start: virt,
+            end: virt + size,
+            attributes,
+            page_sizes: page_size,
+        });
+    }
+
+    fn flush(&mut self) {
+        let Some(range) = self.range.take() else {
+            return;
+        };
+
+        let mut sizes = [""; PAGE_SIZES.len()];
+        let mut size_count = 0;
+        for (i, &(_, name)) in PAGE_SIZES.iter().enumerate() {
+            if range.page_sizes & (1 << i) != 0 {
+                sizes[size_count] = name;
+                size_count += 1;
+            }
+        }
+        log::info!(
+            "{:#x}-{:#x} {}, {:?} pages",
+            range.start,
+            range.end - 1,
+            range.attributes,
+            &sizes[..size_count]
+        );
+
+        let size = range.end - range.start;
+        let class = self.classes.iter_mut().find(|class| {
+            class
+                .as_ref()
+                .is_none_or(|class| class.attributes == range.attributes)
+        });
+        match class {
+            Some(Some(class)) => {
+                class.size += size;
+                class.ranges += 1;
+            }
+            Some(class) => {
+                *class = Some(AttributeClass {
+                    attributes: range.attributes,
+                    size,
+                    ranges: 1,
+                })
+            }
+            None => log::warn!(
+                "Too many attribute classes, {} not counted",
+                range.attributes
+            ),
+        }
+    }
+
+    fn finish(mut self) {
+        self.flush();
+        for class in self.classes.iter().flatten() {
+            log::info!(
+                "{}: {} KiB in {} ranges",
+                class.attributes,
+                class.size / 1024,
+                class.ranges
+            );
+        }
+    }
+}
+
+/// Reports the page pointed to by a leaf entry.
+fn page(
+    summary: &mut Option<Summary>,
+    name: &str,
+    entry: impl core::fmt::Debug,
+    entry_raw: u64,
+    virt: u64,
+    size: u64,
+    attributes: Attributes,
+) {
+    match summary {
+        Some(summary) => summary.add(virt, size, attributes),
+        None => log::info!("{name} {entry_raw:#x}: {entry:x?}"),
+    }
+}
+
+pub fn walk_page_tables(walk: PageTableWalk) {
+    let mut summary = match walk {
+        PageTableWalk::Off => return,
+        PageTableWalk::Entries => None,
+        PageTableWalk::Summary => Some(Summary::new()),
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        use crate::x86_64_regs::access::X86Register;
+        use crate::x86_64_regs::*;
+
+        const LEVEL_NAMES: [&str; 5] = ["PML5E", "PML4E", "PDPTE", "PDE", "PTE"];
+        const PRESENT: u64 = 1;
+
+        let mut cr3 = Cr3::new();
+        cr3.load();
+        let mut cr4 = Cr4::new();
+        cr4.load();
+        let levels = if cr4.la57() { 5 } else { 4 };
+        let virt_bits = 12 + 9 * levels as u32;
+        log::info!("CR3 {:#x}: {cr3:x?}, {levels}-level paging", u64::from(cr3));
+
+        let mut pat = Pat::new();
+        pat.load();
+        let memory = |pat_index: usize| pat.entry(pat_index).short_name();
+
+        // The tables on the path from the top one: the table, the next
+        // entry in it, the virtual address it maps, and the attributes
+        // accumulated from the upper levels: writable, user, and no execute.
+        let top_level = LEVEL_NAMES.len() - levels;
+        let mut path = [(0u64, 0usize, 0u64, (true, true, false)); LEVEL_NAMES.len()];
+        path[0] = (cr3.pml_pfn() << 12, 0, 0, (true, true, false));
+        let mut depth = 0;
+
+        loop {
+            let (table, index, virt, (writable, user, no_execute)) = path[depth];
+            if index == 512 {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+                continue;
+            }
+            path[depth].1 += 1;
+
+            let entry = unsafe { (table as *const u64).add(index).read() };
+            if entry & PRESENT == 0 {
+                continue;
+            }
+
+            let level = top_level + depth;
+            let shift = 12 + 9 * (LEVEL_NAMES.len() - 1 - level) as u32;
+            let virt = virt | (index as u64) << shift;
+            let entry = PageTableEntry::from(entry);
+            let entry_raw = u64::from(entry);
+            let inherited = (
+                writable && entry.writable(),
+                user && entry.user(),
+                no_execute || entry.no_execute(),
+            );
+
+            // PDPT and PD entries may map 1GiB and 2MiB pages.
+            let is_page = level == LEVEL_NAMES.len() - 1 || (level >= 2 && entry.page_size());
+            if is_page {
+                let pat_bit = if level == LEVEL_NAMES.len() - 1 {
+                    entry.page_size()
+                } else {
+                    entry.address_pfn() & 1 != 0
+                };
+                let pat_index = (pat_bit as usize) << 2
+                    | (entry.cache_disable() as usize) << 1
+                    | entry.write_through() as usize;
+                // Sign-extend to the canonical address.
+                let virt = ((virt << (64 - virt_bits)) as i64 >> (64 - virt_bits)) as u64;
+                page(
+                    &mut summary,
+                    LEVEL_NAMES[level],
+                    entry,
+                    entry_raw,
+                    virt,
+                    1 << shift,
+                    Attributes {
+                        writable: inherited.0,
+                        executable: !inherited.2,
+                        user: inherited.1,
+                        memory: memory(pat_index),
+                    },
+                );
+                continue;
+            }
+
+            if summary.is_none() {
+                log::info!("{} {entry_raw:#x}: {entry:x?}", LEVEL_NAMES[level]);
+            }
+            depth += 1;
+            path[depth] = (entry.address_pfn() << 12, 0, virt, inherited);
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        use crate::aarch64_regs::access::Aarch64Register;
+        use crate::aarch64_regs::*;
+
+        // Traverse page tables assuming 4K pages (check TCR!)
+
+        let mut ttbr0_el1 = TranslationBase0El1::new();
+        ttbr0_el1.load();
+        let mut mair_el1 = MemoryAttributeIndirectionEl1::new();
+        mair_el1.load();
+        let memory = |mair_idx: usize| match mair_el1.attribute(mair_idx) {
+            0x00 => "Device-nGnRnE",
+            0x04 => "Device-nGnRE",
+            0x08 => "Device-nGRE",
+            0x0c => "Device-GRE",
+            0x44 => "Normal NC",
+            0xbb => "Normal WT",
+            0xff => "Normal WB",
+            _ => "Normal",
+        };
+
+        // The tables on the path from the top one: the table, the next
+        // entry in it, and the virtual address it maps.
+        let mut path = [(0u64, 0usize, 0u64); 4];
+        path[0] = (ttbr0_el1.baddr(), 0, 0);
+        let mut level = 0;
+
+        loop {
+            let (table, index, virt) = path[level];
+            if index == 512 {
+                if level == 0 {
+                    break;
+                }
+                level -= 1;
+                continue;
+            }
+            path[level].1 += 1;
+
+            let entry = unsafe { (table as *const u64).add(index).read() };
+            if entry & 1 == 0 {
+                // Not valid for hardware, skip. In general, might be valid when an OS is running
+                // for software PTEs and swapping.
+                continue;
+            }
+
+            let shift = 39 - 9 * level as u32;
+            let virt = virt | (index as u64) << shift;
+
+            if level < 3 && entry & 0b11 == 0b11 {
+                // This a table pointer.
+                let entry = PageTableEntry::from(entry);
+                let entry_raw = u64::from(entry);
+                if summary.is_none() {
+                    log::info!("PTE {entry_raw:#x}: {entry:x?}");
+                }
+                level += 1;
+                path[level] = (entry.next_table_pfn() << 12, 0, virt);
+                continue;
+            }
+
+            // This is a block or a page pointer (a leaf).
+            let entry = PageBlockEntry::from(entry);
+            let entry_raw = u64::from(entry);
+            page(
+                &mut summary,
+                "PBE",
+                entry,
+                entry_raw,
+                virt,
+                1 << shift,
+                Attributes {
+                    // RW at EL1 for 0b00 and 0b01.
+                    writable: entry.access_perm() & 0b10 == 0,
+                    executable: !entry.priv_x_never(),
+                    user: entry.access_perm() & 0b01 != 0,
+                    memory: memory(entry.mair_idx()),
+                },
+            );
+        }
+    }
+
+    if let Some(summary) = summary {
+        summary.finish();
+    }
+}
@@ -0,0 +1,280 @@
+//! The kernel page tables.
+//!
+//! The tables use the 4 KiB granule and four levels on both architectures,
+//! and are built in the memory allocated from the firmware while the boot
+//! services are available. The mappings use the largest block the
+//! alignment of both addresses and the remaining size allow: 1 GiB where
+//! the processor supports that, then 2 MiB, then 4 KiB pages at the edges.
+//!
+//! The higher half starts with the linear map of the physical memory at
+//! [`LINEAR_MAP_BASE`], and the kernel image is mapped where it has been
+//! linked (plus the KASLR slide).
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use page_tables::BlockSize;
+use page_tables::MapError;
+use page_tables::MapStats;
+use page_tables::Table;
+use page_tables::TableAllocator;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::mem::memory_map::MemoryMapMut;
+
+/// The linear map of the physical memory, below the kernel image.
+pub const LINEAR_MAP_BASE: u64 = 0xffff_8000_0000_0000;
+/// Spans up to the kernel image link address.
+pub const LINEAR_MAP_MAX_SIZE: u64 = 0x100_0000_0000;
+/// The initial kernel stack is mapped here, above a guard page, and below
+/// the runtime services window.
+pub const KERNEL_STACK_BASE: u64 = 0xffff_fc00_0000_0000;
+/// The device registers are mapped in this window by [`PageTables::map_device`].
+pub const DEVICE_MAP_BASE: u64 = 0xffff_fe00_0000_0000;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// The memory type the mapping is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    /// Normal cacheable memory.
+    Normal,
+    /// Device memory, not cached, no speculation.
+    Device,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MapAttributes {
+    pub kind: MapKind,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl MapAttributes {
+    pub const DATA: Self = Self {
+        kind: MapKind::Normal,
+        writable: true,
+        executable: false,
+    };
+    pub const DEVICE: Self = Self {
+        kind: MapKind::Device,
+        writable: true,
+        executable: false,
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::MapAttributes;
+    use super::MapKind;
+    use page_tables::x86_64;
+    use page_tables::BlockSize;
+
+    pub use page_tables::x86_64::PageTableBuilder;
+
+    pub fn largest_block() -> BlockSize {
+        use raw_cpuid::CpuId;
+
+        if CpuId::new()
+            .get_extended_processor_and_feature_identifiers()
+            .is_some_and(|f| f.has_1gib_pages())
+        {
+            BlockSize::Block1G
+        } else {
+            BlockSize::Block2M
+        }
+    }
+
+    pub fn attributes(attributes: MapAttributes) -> x86_64::MapAttributes {
+        let base = match attributes.kind {
+            MapKind::Normal => x86_64::MapAttributes::DATA,
+            MapKind::Device => x86_64::MapAttributes::DEVICE,
+        };
+        x86_64::MapAttributes {
+            writable: attributes.writable,
+            executable: attributes.executable,
+            ..base
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use super::MapAttributes;
+    use super::MapKind;
+    use page_tables::aarch64;
+    use page_tables::BlockSize;
+
+    pub use page_tables::aarch64::PageTableBuilder;
+
+    /// The attribute indices are the same as in EDK2 so the identity
+    /// mapping of the firmware stays valid with the same `MAIR_EL1`.
+    pub const MAIR_DEVICE_NGNRNE_INDEX: u8 = 0;
+    pub const MAIR_NORMAL_WB_INDEX: u8 = 3;
+    pub const MAIR: u64 = 0xff_bb_44_00;
+
+    pub fn largest_block() -> BlockSize {
+        BlockSize::Block1G
+    }
+
+    pub fn attributes(attributes: MapAttributes) -> aarch64::MapAttributes {
+        let base = match attributes.kind {
+            MapKind::Normal => aarch64::MapAttributes::data(MAIR_NORMAL_WB_INDEX),
+            MapKind::Device => aarch64::MapAttributes::device(MAIR_DEVICE_NGNRNE_INDEX),
+        };
+        aarch64::MapAttributes {
+            writable: attributes.writable,
+            executable: attributes.executable,
+            ..base
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub use arch::MAIR;
+
+/// The tables are allocated from the firmware, and are identity-mapped
+/// while the boot services are available.
+struct FirmwareTableAllocator;
+
+impl TableAllocator for FirmwareTableAllocator {
+    fn allocate_table(&mut self) -> Option<u64> {
+        let table =
+            boot::allocate_pages(AllocateType::AnyPages, memory_types::PAGE_TABLES, 1).ok()?;
+        // SAFETY: the page has just been allocated, and is identity-mapped.
+        unsafe { table.as_ptr().write_bytes(0, PAGE_SIZE as usize) };
+
+        Some(table.as_ptr() as u64)
+    }
+
+    fn table(&mut self, phys: u64) -> &mut Table {
+        // SAFETY: the tables are allocated by `allocate_table`, and are identity-mapped.
+        unsafe { &mut *(phys as *mut Table) }
+    }
+}
+
+pub struct PageTables {
+    root: u64,
+    largest_block: BlockSize,
+    stats: MapStats,
+    /// The next free address in the device window.
+    next_device_virt: u64,
+}
+
+impl PageTables {
+    pub fn new() -> Self {
+        let mut allocator = FirmwareTableAllocator;
+        let builder = arch::PageTableBuilder::new(&mut allocator)
+            .or_fail(BootError::OutOfMemory, "Cannot allocate a page table");
+
+        Self {
+            root: builder.root(),
+            largest_block: arch::largest_block(),
+            stats: builder.stats(),
+            next_device_virt: DEVICE_MAP_BASE,
+        }
+    }
+
+    /// The physical address of the root table.
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    pub fn stats(&self) -> MapStats {
+        self.stats
+    }
+
+    /// Maps `size` bytes at `virt` to `phys`. The addresses and the size
+    /// must be page-aligned.
+    pub fn map(&mut self, virt: u64, phys: u64, size: u64, attributes: MapAttributes) {
+        let mut allocator = FirmwareTableAllocator;
+        let mut builder = arch::PageTableBuilder::from_root(self.root, &mut allocator)
+            .with_largest_block(self.largest_block);
+        let result = builder.map(virt, phys, size, arch::attributes(attributes));
+        self.stats += builder.stats();
+
+        match result {
+            Ok(()) => {}
+            Err(MapError::OutOfMemory) => {
+                BootError::OutOfMemory.fail(format_args!("Cannot allocate a page table"))
+            }
+            Err(MapError::Unaligned) => {
+                panic!("Unaligned mapping {virt:#016x} -> {phys:#016x}, {size:#x} bytes")
+            }
+            Err(MapError::AlreadyMapped(virt)) => panic!("{virt:#016x} is already mapped"),
+        }
+    }
+
+    /// Maps the device registers at `phys` in the device window, and returns
+    /// the virtual address of `phys`.
+    pub fn map_device(&mut self, phys: u64, size: u64) -> u64 {
+        let start = phys & !(PAGE_SIZE - 1);
+        let size = (phys + size - start).next_multiple_of(PAGE_SIZE);
+        let virt = self.next_device_virt;
+
+        self.map(virt, start, size, MapAttributes::DEVICE);
+        self.next_device_virt += size;
+
+        virt + (phys - start)
+    }
+}
+
+fn is_device_memory(ty: MemoryType) -> bool {
+    matches!(
+        ty,
+        MemoryType::MMIO | MemoryType::MMIO_PORT_SPACE | MemoryType::UNUSABLE
+    )
+}
+
+/// Maps the physical memory from the firmware memory map at [`LINEAR_MAP_BASE`].
+/// The adjacent regions of the same kind are merged so that the large pages
+/// can be used across the region boundaries.
+pub fn map_physical_memory(page_tables: &mut PageTables) {
+    let mut memory_map = boot::memory_map(MemoryType::LOADER_DATA)
+        .or_fail(BootError::MemoryMap, "Cannot get the memory map");
+    memory_map.sort();
+
+    let mut run: Option<(u64, u64, MapKind)> = None;
+    let map_run = |page_tables: &mut PageTables, (start, end, kind): (u64, u64, MapKind)| {
+        let end = end.min(LINEAR_MAP_MAX_SIZE);
+        if start < end {
+            let attributes = match kind {
+                MapKind::Normal => MapAttributes::DATA,
+                MapKind::Device => MapAttributes::DEVICE,
+            };
+            page_tables.map(LINEAR_MAP_BASE + start, start, end - start, attributes);
+        }
+    };
+
+    for entry in memory_map.entries() {
+        let (start, end) = (
+            entry.phys_start,
+            entry.phys_start + entry.page_count * PAGE_SIZE,
+        );
+        let kind = if is_device_memory(entry.ty) {
+            MapKind::Device
+        } else {
+            MapKind::Normal
+        };
+        run = match run {
+            Some((run_start, run_end, run_kind)) if run_end == start && run_kind == kind => {
+                Some((run_start, end, kind))
+            }
+            Some(previous) => {
+                map_run(page_tables, previous);
+                Some((start, end, kind))
+            }
+            None => Some((start, end, kind)),
+        };
+    }
+    if let Some(last) = run {
+        map_run(page_tables, last);
+    }
+
+    log::info!(
+        "Mapped the physical memory at {LINEAR_MAP_BASE:#016x}, {:?}",
+        page_tables.stats()
+    );
+}
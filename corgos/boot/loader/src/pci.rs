@@ -0,0 +1,80 @@
+//! The PCI Express configuration space for the kernel. The ECAM window of
+//! the segment 0 from the MCFG table is handed over, mapped for the kernel,
+//! and the devices on it are logged, see the `pci` crate. The machines
+//! with more segments are rare, and those are left out.
+
+use crate::paging::PageTables;
+use ::acpi_tables::AcpiTables;
+use ::pci::Ecam;
+use bootinfo::Pci;
+
+/// The window as the loader sees it, identity-mapped by the firmware.
+pub fn identity_mapped(pci: &Pci) -> Option<Ecam> {
+    if pci.ecam_phys_base == 0 {
+        return None;
+    }
+    // SAFETY: the firmware identity-maps the ECAM window while the boot
+    // services are active.
+    Some(unsafe { Ecam::new(pci.ecam_phys_base, pci.segment, pci.start_bus, pci.end_bus) })
+}
+
+/// Finds the ECAM window in the MCFG table, and logs the devices.
+pub fn discover_pci(rsdp_addr: u64, pci: &mut Pci) {
+    *pci = Pci::default();
+    // SAFETY: the RSDP comes from the UEFI configuration table, and the
+    // tables are identity-mapped.
+    let tables = match unsafe { AcpiTables::from_rsdp(rsdp_addr, 0) } {
+        Ok(tables) => tables,
+        Err(e) => {
+            log::warn!("Cannot look for the MCFG table: {e:?}");
+            return;
+        }
+    };
+    let Some(mcfg) = tables.mcfg() else {
+        log::info!("No MCFG table, no PCI Express configuration space");
+        return;
+    };
+    let Some(entry) = mcfg.entries().find(|entry| entry.segment == 0) else {
+        log::warn!("No ECAM window for the PCI segment 0");
+        return;
+    };
+    if entry.start_bus > entry.end_bus {
+        log::warn!("Bad ECAM window {entry:x?}");
+        return;
+    }
+
+    *pci = Pci {
+        ecam_phys_base: entry.base,
+        segment: entry.segment,
+        start_bus: entry.start_bus,
+        end_bus: entry.end_bus,
+        ..Pci::default()
+    };
+    log::info!(
+        "PCI ECAM @ {:#016x}, buses {:02x}..={:02x}",
+        entry.base,
+        entry.start_bus,
+        entry.end_bus
+    );
+    if let Some(ecam) = identity_mapped(pci) {
+        for function in ecam.functions() {
+            log::info!("  {function}");
+            for bar in function.bars() {
+                log::debug!("    {bar:x?}");
+            }
+        }
+    }
+}
+
+/// Maps the configuration space of the buses into the kernel address space.
+pub fn map_pci(pci: &mut Pci, page_tables: &mut PageTables) {
+    if pci.ecam_phys_base == 0 {
+        return;
+    }
+    let start_offset = pci.start_bus as u64 * ::pci::BUS_CONFIG_SIZE;
+    let virt = page_tables.map_device(
+        pci.ecam_phys_base + start_offset,
+        Ecam::window_size(pci.start_bus, pci.end_bus),
+    );
+    pci.ecam_virt_base = virt - start_offset;
+}
@@ -0,0 +1,62 @@
+//! The cycles and the instructions the hot paths of the loader take, with
+//! `pmu_profile = yes` in the configuration.
+//!
+//! [`boot_timing`](crate::boot_timing) tells how long the stages take,
+//! this tells why, e.g. the copy stalling on the memory at a low IPC or
+//! the decompression running more instructions than it should. The paths
+//! are measured with the performance counters, see the `pmu` crate, and
+//! the totals are logged before the handoff. Nothing is measured if the
+//! processor or the hypervisor does not have the counters.
+
+use pmu::Hotspot;
+use pmu::Pmu;
+
+/// Copying the kernel segments into the image.
+pub static ELF_COPY: Hotspot = Hotspot::new("elf copy");
+/// Decompressing the files.
+pub static DECOMPRESS: Hotspot = Hotspot::new("decompress");
+/// Building the kernel page tables.
+pub static PAGE_TABLES: Hotspot = Hotspot::new("page tables");
+
+static HOTSPOTS: [&Hotspot; 3] = [&ELF_COPY, &DECOMPRESS, &PAGE_TABLES];
+
+static mut PMU: Option<Pmu> = None;
+
+fn pmu() -> Option<&'static Pmu> {
+    // SAFETY: written once in `init`.
+    unsafe { (*core::ptr::addr_of!(PMU)).as_ref() }
+}
+
+/// Starts the counters if asked to.
+pub fn init(enabled: bool) {
+    if !enabled {
+        return;
+    }
+    let Some(pmu) = Pmu::enable() else {
+        log::warn!("No performance counters, not profiling");
+        return;
+    };
+    log::info!("Profiling with the performance counters");
+    // SAFETY: only the boot processor runs, and nothing reads it yet.
+    unsafe { *core::ptr::addr_of_mut!(PMU) = Some(pmu) };
+}
+
+/// Runs `f`, and adds the counts to `hotspot` if profiling.
+pub fn profile<T>(hotspot: &'static Hotspot, f: impl FnOnce() -> T) -> T {
+    let Some(pmu) = pmu() else {
+        return f();
+    };
+    let _scope = pmu.scope(hotspot);
+    f()
+}
+
+/// Logs the totals of the hot paths.
+pub fn report() {
+    if pmu().is_none() {
+        return;
+    }
+    log::info!("Profile:");
+    for hotspot in HOTSPOTS {
+        log::info!("  {}", hotspot.totals());
+    }
+}
@@ -0,0 +1,110 @@
+//! The real-time clock for the kernel, which cannot call the EFI `GetTime`
+//! without the firmware mappings. The PL031 is looked up in the device
+//! tree, the CMOS clock is there on x86_64 unless the FADT says to use the
+//! ACPI time and alarm device instead. The loader reads the clock, too, to
+//! timestamp the diagnostics.
+
+use crate::device_tree;
+use crate::paging::PageTables;
+use acpi::fadt::Fadt;
+use acpi::AcpiHandler;
+use acpi::AcpiTables;
+use boot_logger::RtcDevice;
+use bootinfo::Rtc;
+use bootinfo::RtcKind;
+use rtc::Cmos;
+use rtc::DateTime;
+use rtc::Pl031;
+
+/// The size of the PL031 register block.
+const PL031_REGISTERS_SIZE: u64 = 0x1000;
+
+fn cmos(tables: &AcpiTables<impl AcpiHandler>) -> Rtc {
+    let Ok(fadt) = tables.find_table::<Fadt>() else {
+        log::info!("No FADT, assuming the CMOS clock has no century register");
+        return Rtc {
+            kind: RtcKind::Cmos,
+            ..Rtc::default()
+        };
+    };
+    let iapc_boot_arch = fadt.iapc_boot_arch;
+    if iapc_boot_arch.use_time_and_alarm_namespace_for_rtc() {
+        log::info!("The FADT says there is no CMOS clock");
+        return Rtc::default();
+    }
+    Rtc {
+        kind: RtcKind::Cmos,
+        century_register: fadt.century,
+        ..Rtc::default()
+    }
+}
+
+fn pl031(phys_base: u64) -> Rtc {
+    // SAFETY: the device is identity-mapped while the boot services are
+    // active, and the ID registers have no side effects.
+    let pl031 = unsafe { Pl031::new(phys_base) };
+    if !pl031.is_present() {
+        log::warn!("No PL031 at {phys_base:#x}");
+        return Rtc::default();
+    }
+    if !pl031.is_started() {
+        log::warn!("The PL031 at {phys_base:#x} is stopped");
+        return Rtc::default();
+    }
+    Rtc {
+        kind: RtcKind::Pl031,
+        phys_base,
+        ..Rtc::default()
+    }
+}
+
+/// Finds the clock as configured.
+pub fn discover_rtc(device: RtcDevice, tables: &AcpiTables<impl AcpiHandler>, rtc: &mut Rtc) {
+    *rtc = match device {
+        RtcDevice::None => Rtc::default(),
+        RtcDevice::Cmos | RtcDevice::Auto if cfg!(target_arch = "x86_64") => cmos(tables),
+        RtcDevice::Cmos => {
+            log::warn!("No CMOS clock on {}", crate::arch_name());
+            Rtc::default()
+        }
+        RtcDevice::Pl031(phys_base) => pl031(phys_base),
+        RtcDevice::Auto => {
+            match device_tree::find_device("arm,pl031", |phys_base| {
+                // SAFETY: as in `pl031`.
+                unsafe { Pl031::new(phys_base) }.is_present()
+            }) {
+                Some(phys_base) => pl031(phys_base),
+                None => {
+                    log::info!("No PL031 in the device tree");
+                    Rtc::default()
+                }
+            }
+        }
+    };
+
+    match read(rtc) {
+        Some(time) => log::info!("RTC {:?}, {time}", rtc.kind),
+        None if rtc.kind != RtcKind::None => log::warn!("Cannot read the RTC {rtc:x?}"),
+        None => {}
+    }
+}
+
+/// Maps the registers of the clock into the kernel address space.
+pub fn map_rtc(rtc: &mut Rtc, page_tables: &mut PageTables) {
+    if rtc.kind == RtcKind::Pl031 {
+        rtc.virt_base = page_tables.map_device(rtc.phys_base, PL031_REGISTERS_SIZE);
+    }
+}
+
+/// The time the clock reads, with the loader identity-mapped.
+pub fn read(rtc: &Rtc) -> Option<DateTime> {
+    match rtc.kind {
+        RtcKind::None => None,
+        // SAFETY: the registers are identity-mapped, and `discover_rtc`
+        // has checked there is a PL031 there.
+        RtcKind::Pl031 => Some(unsafe { Pl031::new(rtc.phys_base) }.read()),
+        // SAFETY: the firmware uses the CMOS only in the runtime services,
+        // and those are not called meanwhile.
+        RtcKind::Cmos => unsafe { Cmos::new(rtc.century_register) }.read(),
+    }
+}
@@ -0,0 +1,144 @@
+//! Snapshots of the system registers to tell from the boot log what has
+//! changed between two points of the boot: what the firmware has changed
+//! while the loader was running, or what the loader itself has set up for
+//! the kernel.
+
+#[cfg(target_arch = "aarch64")]
+use crate::aarch64_regs::access::Aarch64Register as Register;
+#[cfg(target_arch = "x86_64")]
+use crate::x86_64_regs::access::X86Register as Register;
+
+const MAX_REGISTERS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterValue {
+    pub name: &'static str,
+    pub bits: u64,
+}
+
+/// A register that has a different value in the later snapshot.
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegisterSnapshot {
+    label: &'static str,
+    values: [RegisterValue; MAX_REGISTERS],
+    count: usize,
+}
+
+impl RegisterSnapshot {
+    /// Loads the registers, the ones past [`MAX_REGISTERS`] are skipped.
+    pub fn capture(label: &'static str, registers: &mut [&mut dyn Register]) -> Self {
+        let mut snapshot = Self {
+            label,
+            values: [RegisterValue { name: "", bits: 0 }; MAX_REGISTERS],
+            count: 0,
+        };
+        for (register, value) in registers.iter_mut().zip(snapshot.values.iter_mut()) {
+            register.load();
+            *value = RegisterValue {
+                name: register.name(),
+                bits: register.bits(),
+            };
+            snapshot.count += 1;
+        }
+        if registers.len() > MAX_REGISTERS {
+            log::warn!(
+                "Snapshot {label}: only {MAX_REGISTERS} of {} registers captured",
+                registers.len()
+            );
+        }
+        snapshot
+    }
+
+    /// The registers the loader and the firmware may change on the way to
+    /// the kernel.
+    #[cfg(target_arch = "x86_64")]
+    pub fn capture_control(label: &'static str) -> Self {
+        use crate::register;
+        use crate::x86_64_regs::access::X86Register;
+        use crate::x86_64_regs::*;
+
+        Self::capture(
+            label,
+            &mut [
+                register!(Cr0),
+                register!(Cr3),
+                register!(Cr4),
+                register!(Efer),
+                register!(ApicBase),
+                register!(Pat),
+                register!(MtrrDefaultType),
+            ],
+        )
+    }
+
+    /// The registers the loader and the firmware may change on the way to
+    /// the kernel.
+    #[cfg(target_arch = "aarch64")]
+    pub fn capture_control(label: &'static str) -> Self {
+        use crate::aarch64_regs::access::Aarch64Register;
+        use crate::aarch64_regs::*;
+        use crate::register;
+
+        Self::capture(
+            label,
+            &mut [
+                register!(SystemControlEl1),
+                register!(VectorBaseEl1),
+                register!(MemoryAttributeIndirectionEl1),
+                register!(TranslationControlEl1),
+                register!(TranslationBase0El1),
+                register!(TranslationBase1El1),
+                register!(ArchFeatureAccessControlEl1),
+                register!(MonitorDebugSystemControlEl1),
+                register!(CounterKernelControlEl1),
+                register!(PhysicalTimerControlEl0),
+                register!(VirtualTimerControlEl0),
+            ],
+        )
+    }
+
+    pub fn values(&self) -> &[RegisterValue] {
+        &self.values[..self.count]
+    }
+
+    /// The registers in both snapshots whose values differ.
+    pub fn diff<'a>(&'a self, later: &'a Self) -> impl Iterator<Item = RegisterChange> + 'a {
+        self.values().iter().filter_map(|before| {
+            later
+                .values()
+                .iter()
+                .find(|after| after.name == before.name && after.bits != before.bits)
+                .map(|after| RegisterChange {
+                    name: before.name,
+                    before: before.bits,
+                    after: after.bits,
+                })
+        })
+    }
+
+    pub fn log_diff(&self, later: &Self) {
+        let mut changed = 0;
+        for change in self.diff(later) {
+            log::info!(
+                "{} -> {}: {}\t{:#016x} -> {:#016x}, bits {:#x}",
+                self.label,
+                later.label,
+                change.name,
+                change.before,
+                change.after,
+                change.before ^ change.after
+            );
+            changed += 1;
+        }
+        if changed == 0 {
+            log::info!("{} -> {}: no registers changed", self.label, later.label);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Virtual addressing for the UEFI runtime services.
+//!
+//! The runtime regions are mapped into the kernel page tables at
+//! [`RUNTIME_MAP_BASE`] plus their physical address while the boot services
+//! are still around to allocate the page tables. The runtime regions do not
+//! change when exiting the boot services, so `SetVirtualAddressMap` is then
+//! called with exactly these addresses.
+//!
+//! After that the firmware expects to be called at the new addresses which
+//! are mapped only in the kernel page tables, so the loader must not use the
+//! runtime services past [`set_virtual_address_map`].
+//!
+//! Some firmware does not support all the runtime services after exiting
+//! the boot services, and calling those may fault. The firmware reports the
+//! supported ones in `EFI_RT_PROPERTIES_TABLE`, and that is passed on to the
+//! kernel. Without the table all the services are assumed supported.
+
+use crate::boot_error;
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::paging::MapAttributes;
+use crate::paging::MapKind;
+use crate::paging::PageTables;
+use bootinfo::Efi;
+use bootinfo::EFI_RT_SUPPORTED_ALL;
+use bootinfo::EFI_RT_SUPPORTED_SET_VIRTUAL_ADDRESS_MAP;
+use uefi::boot;
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::MemoryAttribute;
+use uefi::mem::memory_map::MemoryDescriptor;
+use uefi::mem::memory_map::MemoryMap;
+use uefi::runtime;
+use uefi::system;
+use uefi::table;
+
+/// The runtime regions are mapped at this address plus their physical address.
+pub const RUNTIME_MAP_BASE: u64 = 0xffff_fd00_0000_0000;
+/// The size of the window, the runtime regions must be below that.
+const RUNTIME_MAP_SIZE: u64 = 0x100_0000_0000;
+
+/// The most runtime regions passed to `SetVirtualAddressMap`.
+const MAX_RUNTIME_REGIONS: usize = 128;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// `EFI_RT_PROPERTIES_TABLE`.
+#[repr(C)]
+struct RtPropertiesTable {
+    version: u16,
+    length: u16,
+    runtime_services_supported: u32,
+}
+
+fn is_runtime(entry: &MemoryDescriptor) -> bool {
+    entry.att.contains(MemoryAttribute::RUNTIME)
+}
+
+fn runtime_virt(phys: u64) -> u64 {
+    assert!(
+        phys < RUNTIME_MAP_SIZE,
+        "Runtime region {phys:#016x} is out of the runtime window"
+    );
+    RUNTIME_MAP_BASE + phys
+}
+
+/// Maps the runtime regions into the kernel page tables.
+pub fn map_runtime_regions(page_tables: &mut PageTables) {
+    let memory_map = boot::memory_map(MemoryType::LOADER_DATA)
+        .or_fail(BootError::MemoryMap, "Cannot get the memory map");
+
+    for entry in memory_map.entries().filter(|e| is_runtime(e)) {
+        let attributes = match entry.ty {
+            MemoryType::RUNTIME_SERVICES_CODE => MapAttributes {
+                kind: MapKind::Normal,
+                writable: true,
+                executable: true,
+            },
+            MemoryType::MMIO | MemoryType::MMIO_PORT_SPACE => MapAttributes::DEVICE,
+            _ => MapAttributes::DATA,
+        };
+
+        log::info!(
+            "Runtime region {:?} @ {:#016x}, {} pages",
+            entry.ty,
+            entry.phys_start,
+            entry.page_count
+        );
+        page_tables.map(
+            runtime_virt(entry.phys_start),
+            entry.phys_start,
+            entry.page_count * PAGE_SIZE,
+            attributes,
+        );
+    }
+}
+
+/// The supported runtime services from `EFI_RT_PROPERTIES_TABLE`, `None`
+/// if there is no table.
+fn rt_properties() -> Option<u32> {
+    let table = system::with_config_table(|tables| {
+        tables
+            .iter()
+            .find(|table| table.guid == uefi_guids::EFI_RT_PROPERTIES_TABLE_GUID)
+            .map(|table| table.address as *const RtPropertiesTable)
+    })?;
+
+    // SAFETY: the table is in the runtime memory, and is identity-mapped.
+    let table = unsafe { table.as_ref() }?;
+    log::info!(
+        "EFI_RT_PROPERTIES_TABLE version {}, length {}, supported runtime services {:#x}",
+        table.version,
+        table.length,
+        table.runtime_services_supported
+    );
+    Some(table.runtime_services_supported)
+}
+
+/// What the kernel gets when the runtime services cannot be switched to the
+/// virtual addresses.
+fn no_runtime_services(rt_properties: Option<u32>) -> Efi {
+    Efi {
+        runtime_map_base: RUNTIME_MAP_BASE,
+        rt_properties_table: rt_properties.is_some() as u32,
+        ..Efi::default()
+    }
+}
+
+/// Switches the runtime services to the virtual addresses if the firmware
+/// supports that, and returns what the kernel can use. Must be called after
+/// exiting the boot services with the final memory map.
+pub fn set_virtual_address_map(memory_map: &impl MemoryMap) -> Efi {
+    let rt_properties = rt_properties();
+    let supported = rt_properties.unwrap_or(EFI_RT_SUPPORTED_ALL);
+    if supported & EFI_RT_SUPPORTED_SET_VIRTUAL_ADDRESS_MAP == 0 {
+        log::warn!("The firmware does not support SetVirtualAddressMap, no runtime services");
+        return no_runtime_services(rt_properties);
+    }
+
+    let mut runtime_map = [MemoryDescriptor::default(); MAX_RUNTIME_REGIONS];
+    let mut count = 0;
+    for entry in memory_map.entries().filter(|e| is_runtime(e)) {
+        if count == MAX_RUNTIME_REGIONS {
+            log::warn!("More than {MAX_RUNTIME_REGIONS} runtime regions, no runtime services");
+            return no_runtime_services(rt_properties);
+        }
+        runtime_map[count] = MemoryDescriptor {
+            virt_start: runtime_virt(entry.phys_start),
+            ..*entry
+        };
+        count += 1;
+    }
+
+    let Some(system_table) = table::system_table_raw() else {
+        log::warn!("No system table, no runtime services");
+        return no_runtime_services(rt_properties);
+    };
+    let system_table_virt = runtime_virt(system_table.as_ptr() as u64);
+
+    // SAFETY: the map describes all runtime regions, and the loader does not
+    // touch the runtime services after this.
+    if let Err(e) = unsafe {
+        runtime::set_virtual_address_map(&mut runtime_map[..count], system_table_virt as *const _)
+    } {
+        log::warn!("Cannot set the virtual address map, no runtime services: {e:?}");
+        return no_runtime_services(rt_properties);
+    }
+    boot_error::runtime_services_switched();
+
+    // SAFETY: the system table is identity-mapped, and the firmware has
+    // converted the pointers in it to the virtual addresses.
+    let runtime_services = unsafe { system_table.as_ref() }.runtime_services as u64;
+    log::info!(
+        "Runtime services switched to virtual addressing, {count} regions, system table @ {system_table_virt:#016x}, runtime services @ {runtime_services:#016x}"
+    );
+
+    Efi {
+        system_table: system_table_virt,
+        runtime_map_base: RUNTIME_MAP_BASE,
+        runtime_services,
+        runtime_services_supported: supported,
+        rt_properties_table: rt_properties.is_some() as u32,
+    }
+}
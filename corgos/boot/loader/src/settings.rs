@@ -0,0 +1,132 @@
+//! The settings the loader keeps across the boots in UEFI variables.
+//!
+//! The values end with a checksum byte that makes the sum of all the bytes
+//! zero. A value torn by a power loss, or written by someone else, fails
+//! the check and reads as no value, and the variable is deleted so it does
+//! not get in the way again. A single zero byte is a valid empty value, so
+//! the kernel can reset the boot attempt counter by writing `0`.
+//!
+//! Some boards wear their flash or corrupt the variable store on frequent
+//! writes, `persist_settings = no` turns the writes off there.
+
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use uefi::guid;
+use uefi::runtime;
+use uefi::runtime::VariableAttributes;
+use uefi::runtime::VariableVendor;
+use uefi::CStr16;
+use uefi::Status;
+
+/// The vendor GUID for the CorgOS UEFI variables.
+pub const CORGOS_VARIABLE_VENDOR: VariableVendor =
+    VariableVendor(guid!("c0a6b007-5e1d-4a3b-8f2c-6b0d1e2f3a4b"));
+
+/// The longest value with the checksum.
+pub const MAX_SETTING_SIZE: usize = 256;
+
+static PERSIST: AtomicBool = AtomicBool::new(true);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    /// The boot attempts of the primary kernel so far, a `u8`.
+    BootAttempts,
+    /// The kernel image the counted attempts are made with.
+    AttemptedKernel,
+    /// The kernel image that has booted successfully last time.
+    LastGoodKernel,
+    /// The name of the boot menu entry selected last time.
+    LastMenuEntry,
+}
+
+impl Setting {
+    fn name(self) -> &'static CStr16 {
+        match self {
+            Self::BootAttempts => uefi::cstr16!("CorgOsBootAttempts"),
+            Self::AttemptedKernel => uefi::cstr16!("CorgOsAttemptedKernel"),
+            Self::LastGoodKernel => uefi::cstr16!("CorgOsLastGoodKernel"),
+            Self::LastMenuEntry => uefi::cstr16!("CorgOsLastMenuEntry"),
+        }
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0_u8, |sum, &byte| sum.wrapping_add(byte))
+        .wrapping_neg()
+}
+
+/// Turns the writes on or off.
+pub fn set_persistence(enabled: bool) {
+    PERSIST.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        log::info!("The settings are not persisted");
+    }
+}
+
+/// Reads the value into `buf`, `None` if there is none or it is corrupted.
+pub fn read(setting: Setting, buf: &mut [u8; MAX_SETTING_SIZE]) -> Option<&[u8]> {
+    let size = match runtime::get_variable(setting.name(), &CORGOS_VARIABLE_VENDOR, buf) {
+        Ok((data, _)) => data.len(),
+        Err(e) if e.status() == Status::NOT_FOUND => return None,
+        Err(e) => {
+            log::warn!("Cannot read the setting {setting:?}: {:?}", e.status());
+            return None;
+        }
+    };
+
+    match buf[..size].split_last() {
+        Some((&sum, value)) if checksum(value) == sum => Some(&buf[..size - 1]),
+        _ => {
+            log::warn!("The setting {setting:?} is corrupted, discarding it");
+            delete(setting);
+            None
+        }
+    }
+}
+
+/// Reads a string value, `None` if there is none or it is not UTF-8.
+pub fn read_str(setting: Setting, buf: &mut [u8; MAX_SETTING_SIZE]) -> Option<&str> {
+    read(setting, buf).and_then(|value| core::str::from_utf8(value).ok())
+}
+
+/// Writes the value unless the persistence is off.
+pub fn write(setting: Setting, value: &[u8]) {
+    if !PERSIST.load(Ordering::Relaxed) {
+        return;
+    }
+    if value.len() >= MAX_SETTING_SIZE {
+        log::warn!("The value of the setting {setting:?} is too long");
+        return;
+    }
+
+    let mut buf = [0_u8; MAX_SETTING_SIZE];
+    buf[..value.len()].copy_from_slice(value);
+    buf[value.len()] = checksum(value);
+
+    let attributes = VariableAttributes::NON_VOLATILE
+        | VariableAttributes::BOOTSERVICE_ACCESS
+        | VariableAttributes::RUNTIME_ACCESS;
+    if let Err(e) = runtime::set_variable(
+        setting.name(),
+        &CORGOS_VARIABLE_VENDOR,
+        attributes,
+        &buf[..=value.len()],
+    ) {
+        log::warn!("Could not store the setting {setting:?}: {:?}", e.status());
+    }
+}
+
+/// Deletes the value unless the persistence is off.
+pub fn delete(setting: Setting) {
+    if !PERSIST.load(Ordering::Relaxed) {
+        return;
+    }
+
+    match runtime::delete_variable(setting.name(), &CORGOS_VARIABLE_VENDOR) {
+        Ok(()) => {}
+        Err(e) if e.status() == Status::NOT_FOUND => {}
+        Err(e) => log::warn!("Could not delete the setting {setting:?}: {:?}", e.status()),
+    }
+}
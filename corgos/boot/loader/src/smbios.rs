@@ -0,0 +1,157 @@
+//! The machine identity from the SMBIOS tables.
+//!
+//! One ESP image serves several board models in the lab, and each model
+//! may need its own UART base or video mode. The product name from the
+//! System Information structure (Type 1) picks the configuration file for
+//! the model. The name is sanitized to be usable in a file name on any
+//! file system: the ASCII letters and digits are kept in the lower case,
+//! and the runs of anything else become a single `-`, so `Standard PC
+//! (Q35 + ICH9, 2009)` turns into `standard-pc-q35-ich9-2009`.
+
+use uefi::system;
+
+const SMBIOS2_ANCHOR: &[u8] = b"_SM_";
+const SMBIOS3_ANCHOR: &[u8] = b"_SM3_";
+const SMBIOS2_ENTRY_POINT_SIZE: usize = 0x1f;
+const SMBIOS3_ENTRY_POINT_SIZE: usize = 0x18;
+
+const TYPE_SYSTEM_INFORMATION: u8 = 1;
+const TYPE_END_OF_TABLE: u8 = 127;
+/// The offset of the product name string number in the Type 1 structure.
+const SYSTEM_PRODUCT_NAME: usize = 5;
+
+/// The longest sanitized product name.
+pub const MAX_PRODUCT_NAME_SIZE: usize = 64;
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from(read_u16(bytes, offset)) | u32::from(read_u16(bytes, offset + 2)) << 16
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from(read_u32(bytes, offset)) | u64::from(read_u32(bytes, offset + 4)) << 32
+}
+
+/// The structure table, the 64-bit entry point is preferred.
+fn structure_table() -> Option<&'static [u8]> {
+    let (entry_point, smbios3) = system::with_config_table(|tables| {
+        let find = |guid| {
+            tables
+                .iter()
+                .find(|table| table.guid == guid)
+                .map(|table| table.address as u64)
+        };
+        find(uefi_guids::EFI_SMBIOS3_TABLE_GUID)
+            .map(|address| (address, true))
+            .or_else(|| find(uefi_guids::EFI_SMBIOS_TABLE_GUID).map(|address| (address, false)))
+    })?;
+
+    let (address, size) = if smbios3 {
+        // SAFETY: the entry point is identity-mapped.
+        let entry = unsafe {
+            core::slice::from_raw_parts(entry_point as *const u8, SMBIOS3_ENTRY_POINT_SIZE)
+        };
+        if !entry.starts_with(SMBIOS3_ANCHOR) {
+            log::warn!("Bad SMBIOS 3 entry point @ {entry_point:#016x}");
+            return None;
+        }
+        (read_u64(entry, 0x10), read_u32(entry, 0x0c) as usize)
+    } else {
+        // SAFETY: the entry point is identity-mapped.
+        let entry = unsafe {
+            core::slice::from_raw_parts(entry_point as *const u8, SMBIOS2_ENTRY_POINT_SIZE)
+        };
+        if !entry.starts_with(SMBIOS2_ANCHOR) {
+            log::warn!("Bad SMBIOS entry point @ {entry_point:#016x}");
+            return None;
+        }
+        (
+            u64::from(read_u32(entry, 0x18)),
+            read_u16(entry, 0x16) as usize,
+        )
+    };
+    if address == 0 || size == 0 {
+        return None;
+    }
+
+    // SAFETY: the table is identity-mapped, and the firmware reports its
+    // size, the maximum one for SMBIOS 3.
+    Some(unsafe { core::slice::from_raw_parts(address as *const u8, size) })
+}
+
+/// The structures as the type, the formatted area, and the strings area
+/// without the terminating empty string.
+fn structures(table: &[u8]) -> impl Iterator<Item = (u8, &[u8], &[u8])> {
+    let mut offset = 0;
+    core::iter::from_fn(move || {
+        let header = table.get(offset..offset + 4)?;
+        let (kind, length) = (header[0], header[1] as usize);
+        if length < header.len() {
+            log::warn!("Malformed SMBIOS structure at {offset:#x}");
+            return None;
+        }
+        let formatted = table.get(offset..offset + length)?;
+
+        // The strings area ends with two zero bytes even if it is empty.
+        let strings_start = offset + length;
+        let strings_size = table
+            .get(strings_start..)?
+            .windows(2)
+            .position(|pair| pair == [0, 0])?;
+        let strings = &table[strings_start..strings_start + strings_size];
+
+        offset = if kind == TYPE_END_OF_TABLE {
+            table.len()
+        } else {
+            strings_start + strings_size + 2
+        };
+        Some((kind, formatted, strings))
+    })
+}
+
+/// The string by its number, starting from `1`.
+fn string(strings: &[u8], number: u8) -> Option<&[u8]> {
+    strings
+        .split(|&c| c == 0)
+        .nth(usize::from(number).checked_sub(1)?)
+}
+
+/// Keeps the ASCII letters and digits in the lower case, and replaces the
+/// runs of anything else with `-`. Returns `None` if nothing is left.
+fn sanitize<'a>(name: &[u8], buf: &'a mut [u8; MAX_PRODUCT_NAME_SIZE]) -> Option<&'a str> {
+    let mut len = 0;
+    for &c in name {
+        let c = if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else if len == 0 || buf[len - 1] == b'-' {
+            continue;
+        } else {
+            b'-'
+        };
+        if len == buf.len() {
+            break;
+        }
+        buf[len] = c;
+        len += 1;
+    }
+    while len > 0 && buf[len - 1] == b'-' {
+        len -= 1;
+    }
+
+    // Only the ASCII characters have been copied.
+    (len != 0).then(|| core::str::from_utf8(&buf[..len]).unwrap_or_default())
+}
+
+/// The sanitized product name of the machine, `None` if the firmware does
+/// not report it.
+pub fn product_name(buf: &mut [u8; MAX_PRODUCT_NAME_SIZE]) -> Option<&str> {
+    let table = structure_table()?;
+    let (_, formatted, strings) =
+        structures(table).find(|&(kind, _, _)| kind == TYPE_SYSTEM_INFORMATION)?;
+    let name = string(strings, *formatted.get(SYSTEM_PRODUCT_NAME)?)?;
+
+    sanitize(name, buf)
+}
@@ -0,0 +1,58 @@
+//! The backtraces of the loader panics.
+//!
+//! The loader runs on the stack the firmware has given it, and the frames
+//! of the firmware are below `main` on it. The walk is bounded by the
+//! frame pointer of `main` and by the loader image, so it stops at `main`
+//! and does not wander into the firmware frames. The addresses are logged
+//! along with the offsets into the image to look them up in the PE file.
+//!
+//! The kernel runs on its own stack with the frame pointer cleared, so the
+//! kernel backtraces do not reach in here either.
+
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use uefi::boot;
+use uefi::proto::loaded_image::LoadedImage;
+
+/// The Win64 prologues may point the frame pointer below the frame record
+/// in a large frame, by 128 bytes at most. The record of `main` is above
+/// its frame pointer then, this is to keep it within the bounds.
+pub const MAIN_FRAME_SLACK: u64 = 0x100;
+
+static STACK_END: AtomicU64 = AtomicU64::new(0);
+static IMAGE_BASE: AtomicU64 = AtomicU64::new(0);
+static IMAGE_END: AtomicU64 = AtomicU64::new(0);
+
+/// Takes the frame pointer of `main`, and where the loader image is.
+pub fn init(main_fp: u64) {
+    STACK_END.store(main_fp + MAIN_FRAME_SLACK, Ordering::Relaxed);
+
+    let Ok(loaded_image) = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle())
+    else {
+        return;
+    };
+    let (base, size) = loaded_image.info();
+    IMAGE_BASE.store(base as u64, Ordering::Relaxed);
+    IMAGE_END.store(base as u64 + size, Ordering::Relaxed);
+}
+
+/// Logs the return addresses from the caller outwards.
+#[inline(always)]
+pub fn log_backtrace() {
+    let fp = backtrace::frame_pointer();
+    let stack_end = STACK_END.load(Ordering::Relaxed);
+    if stack_end == 0 {
+        return;
+    }
+    let image = IMAGE_BASE.load(Ordering::Relaxed)..IMAGE_END.load(Ordering::Relaxed);
+
+    log::error!("Backtrace:");
+    // SAFETY: the stack from the current frame up to `main` is mapped.
+    let frames = unsafe { backtrace::Frames::new(fp, fp..stack_end) }.code(image.clone());
+    for (index, address) in frames.enumerate() {
+        log::error!(
+            "  #{index:<2} {address:#018x} (loader+{:#x})",
+            address - image.start
+        );
+    }
+}
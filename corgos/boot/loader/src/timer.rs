@@ -0,0 +1,230 @@
+//! The free-running timer and its frequency.
+//!
+//! The kernel keeps time with the TSC on x86_64 and with the virtual counter
+//! on aarch64. Finding out the frequency takes a calibration against another
+//! clock with the interrupts off unless the processor reports it, and the
+//! loader has the UEFI `Stall` service to calibrate against, so the kernel
+//! gets the frequency in the boot info.
+//!
+//! The kernel gets the time of day from the firmware as well, together with
+//! the counter value it has been read at.
+//!
+//! On aarch64, the virtual timer of the generic timer compares against the
+//! same counter, so the deadlines in nanoseconds are armed with it instead
+//! of polling a device as a clock.
+
+use bootinfo::Timer;
+use bootinfo::TimerFrequencySource;
+use bootinfo::WallClock;
+use bootinfo::WALL_CLOCK_UNSPECIFIED_TIME_ZONE;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+/// How long the TSC frequency is measured for, in microseconds.
+#[cfg(target_arch = "x86_64")]
+const CALIBRATION_INTERVAL: usize = 10_000;
+
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+static SOURCE: AtomicU32 = AtomicU32::new(TimerFrequencySource::None as u32);
+static INVARIANT: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_arch = "x86_64")]
+pub fn counter() -> u64 {
+    // SAFETY: reading the timestamp counter has no side effects.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn counter() -> u64 {
+    let counter: u64;
+    // SAFETY: reading the virtual counter has no side effects.
+    unsafe {
+        core::arch::asm!("isb; mrs {}, cntvct_el0", out(reg) counter, options(nomem, nostack));
+    }
+    counter
+}
+
+/// The TSC frequency from CPUID if the processor or the hypervisor reports
+/// it, measured against the boot services stall otherwise.
+#[cfg(target_arch = "x86_64")]
+fn frequency() -> (u64, TimerFrequencySource, bool) {
+    use raw_cpuid::CpuId;
+
+    let cpuid = CpuId::new();
+    let invariant = cpuid
+        .get_advanced_power_mgmt_info()
+        .is_some_and(|info| info.has_invariant_tsc());
+
+    if let Some(frequency) = cpuid.get_tsc_info().and_then(|info| info.tsc_frequency()) {
+        return (frequency, TimerFrequencySource::Cpuid, invariant);
+    }
+    if let Some(frequency_khz) = cpuid
+        .get_hypervisor_info()
+        .and_then(|info| info.tsc_frequency())
+        .filter(|&frequency_khz| frequency_khz != 0)
+    {
+        return (
+            frequency_khz as u64 * 1000,
+            TimerFrequencySource::Hypervisor,
+            invariant,
+        );
+    }
+
+    let start = counter();
+    uefi::boot::stall(CALIBRATION_INTERVAL);
+    let frequency = (counter() - start) * (1_000_000 / CALIBRATION_INTERVAL as u64);
+    (frequency, TimerFrequencySource::Calibrated, invariant)
+}
+
+/// The architectural counter runs at a fixed frequency.
+#[cfg(target_arch = "aarch64")]
+fn frequency() -> (u64, TimerFrequencySource, bool) {
+    use crate::aarch64_regs::access::Aarch64Register;
+    use crate::aarch64_regs::CounterFrequencyEl0;
+
+    let mut cntfrq = CounterFrequencyEl0::new();
+    cntfrq.load();
+    (
+        cntfrq.frequency(),
+        TimerFrequencySource::Architectural,
+        true,
+    )
+}
+
+/// The counter ticks in `nanoseconds` at `frequency`, rounded up.
+#[cfg(any(test, target_arch = "aarch64"))]
+pub const fn ticks_from_ns(nanoseconds: u64, frequency: u64) -> u64 {
+    (nanoseconds as u128 * frequency as u128).div_ceil(1_000_000_000) as u64
+}
+
+/// A one-shot deadline on the virtual timer of the generic timer. The timer
+/// interrupt stays masked, the deadline is polled for.
+#[cfg(target_arch = "aarch64")]
+pub struct Deadline;
+
+#[cfg(target_arch = "aarch64")]
+impl Deadline {
+    /// Arms the virtual timer `nanoseconds` from now, the frequency must
+    /// have been found out.
+    pub fn arm(nanoseconds: u64) -> Self {
+        use crate::aarch64_regs::access::WritableRegister;
+        use crate::aarch64_regs::VirtualTimerCompareEl0;
+        use crate::aarch64_regs::VirtualTimerControlEl0;
+
+        let compare = counter() + ticks_from_ns(nanoseconds, FREQUENCY.load(Ordering::Relaxed));
+        VirtualTimerCompareEl0::new().with_compare(compare).store();
+        VirtualTimerControlEl0::new()
+            .with_enable(true)
+            .with_imask(true)
+            .store();
+        Self
+    }
+
+    pub fn expired(&self) -> bool {
+        use crate::aarch64_regs::access::Aarch64Register;
+        use crate::aarch64_regs::VirtualTimerControlEl0;
+
+        let mut control = VirtualTimerControlEl0::new();
+        control.load();
+        control.istatus()
+    }
+
+    pub fn disarm(self) {
+        stop_virtual_timer();
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn stop_virtual_timer() {
+    use crate::aarch64_regs::access::WritableRegister;
+    use crate::aarch64_regs::VirtualTimerControlEl0;
+
+    VirtualTimerControlEl0::new().with_imask(true).store();
+}
+
+/// Stops both the physical and the virtual timers the firmware may have
+/// left armed so they do not fire before the kernel is ready.
+#[cfg(target_arch = "aarch64")]
+pub fn stop_generic_timers() {
+    use crate::aarch64_regs::access::WritableRegister;
+    use crate::aarch64_regs::PhysicalTimerControlEl0;
+
+    PhysicalTimerControlEl0::new().with_imask(true).store();
+    stop_virtual_timer();
+}
+
+/// Finds out the frequency, must be called while the boot services are
+/// available.
+pub fn calibrate() {
+    let (frequency, source, invariant) = frequency();
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+    SOURCE.store(source as u32, Ordering::Relaxed);
+    INVARIANT.store(invariant, Ordering::Relaxed);
+}
+
+pub fn timer() -> Timer {
+    let source = match SOURCE.load(Ordering::Relaxed) {
+        1 => TimerFrequencySource::Architectural,
+        2 => TimerFrequencySource::Cpuid,
+        3 => TimerFrequencySource::Hypervisor,
+        4 => TimerFrequencySource::Calibrated,
+        _ => TimerFrequencySource::None,
+    };
+
+    Timer {
+        frequency: FREQUENCY.load(Ordering::Relaxed),
+        source,
+        invariant: INVARIANT.load(Ordering::Relaxed) as u32,
+    }
+}
+
+/// Reads the time of day and the counter, must be called while the boot
+/// services are available.
+pub fn wall_clock() -> WallClock {
+    let time = match uefi::runtime::get_time() {
+        Ok(time) => time,
+        Err(e) => {
+            log::warn!("Cannot read the time of day: {:?}", e.status());
+            return WallClock::default();
+        }
+    };
+    let counter = counter();
+    log::info!("Time of day {time}, counter {counter}");
+
+    WallClock {
+        year: time.year(),
+        month: time.month(),
+        day: time.day(),
+        hour: time.hour(),
+        minute: time.minute(),
+        second: time.second(),
+        daylight: time.daylight().bits(),
+        nanosecond: time.nanosecond(),
+        time_zone: time.time_zone().unwrap_or(WALL_CLOCK_UNSPECIFIED_TIME_ZONE),
+        reserved: 0,
+        counter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ticks_from_ns;
+
+    #[test]
+    fn ticks() {
+        assert_eq!(ticks_from_ns(0, 62_500_000), 0);
+        assert_eq!(ticks_from_ns(1_000_000_000, 62_500_000), 62_500_000);
+        // 16 ns per tick, a part of one is rounded up.
+        assert_eq!(ticks_from_ns(16, 62_500_000), 1);
+        assert_eq!(ticks_from_ns(17, 62_500_000), 2);
+        assert_eq!(ticks_from_ns(1, 1_000_000_000), 1);
+        // No overflow for the long deadlines at the high frequencies.
+        assert_eq!(ticks_from_ns(u64::MAX, 1_000_000_000), u64::MAX);
+        assert_eq!(
+            ticks_from_ns(3_600_000_000_000, 4_000_000_000),
+            14_400_000_000_000
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! The TPM 2.0. While the boot services are there, the kernel image is
+//! measured with `EFI_TCG2_PROTOCOL`, which logs the event as well. After
+//! exiting them the kernel talks to the TPM itself, so the interface from
+//! the TPM2 table is handed over, mapped for the kernel. The firmware TPMs
+//! with the CRB buffers outside of the registers are not supported then.
+
+use crate::device_tree;
+use crate::paging::PageTables;
+use ::acpi_tables::tpm2::TPM2_START_CRB;
+use ::acpi_tables::tpm2::TPM2_START_CRB_ACPI;
+use ::acpi_tables::tpm2::TPM2_START_CRB_SMC;
+use ::acpi_tables::tpm2::TPM2_START_TIS;
+use ::acpi_tables::AcpiTables;
+use ::tpm::Crb;
+use ::tpm::Interface;
+use ::tpm::Tis;
+use ::tpm::LOCALITY_SIZE;
+use ::tpm::TIS_PC_BASE;
+use bootinfo::Tpm;
+use bootinfo::TpmInterface;
+use core::mem::MaybeUninit;
+use uefi::boot;
+use uefi::proto::tcg::v2::HashLogExtendEventFlags;
+use uefi::proto::tcg::v2::PcrEventInputs;
+use uefi::proto::tcg::v2::Tcg;
+use uefi::proto::tcg::EventType;
+use uefi::proto::tcg::PcrIndex;
+
+/// The PCR for the kernel image, as the other loaders use.
+const KERNEL_PCR: PcrIndex = PcrIndex(9);
+
+/// The event is the path of the kernel image.
+const MAX_EVENT_SIZE: usize = 512;
+
+/// Extends the PCR with the hash of the kernel image file, and logs the
+/// event. Nothing is done without `EFI_TCG2_PROTOCOL`.
+pub fn measure_kernel(kernel_path: &str, data: &[u8]) {
+    let Ok(handle) = boot::get_handle_for_protocol::<Tcg>() else {
+        log::info!("No EFI_TCG2_PROTOCOL, the kernel image is not measured");
+        return;
+    };
+    let Ok(mut tcg) = boot::open_protocol_exclusive::<Tcg>(handle) else {
+        log::warn!("Cannot open EFI_TCG2_PROTOCOL");
+        return;
+    };
+
+    let mut buffer = [MaybeUninit::uninit(); MAX_EVENT_SIZE];
+    let path = &kernel_path.as_bytes()[..kernel_path.len().min(MAX_EVENT_SIZE / 2)];
+    let Ok(event) = PcrEventInputs::new_in_buffer(&mut buffer, KERNEL_PCR, EventType::IPL, path)
+    else {
+        log::warn!("Cannot build the TCG2 event for '{kernel_path}'");
+        return;
+    };
+    match tcg.hash_log_extend_event(HashLogExtendEventFlags::empty(), data, event) {
+        Ok(()) => log::info!("Measured the kernel image into PCR {}", KERNEL_PCR.0),
+        Err(e) => log::warn!("Cannot measure the kernel image: {e:?}"),
+    }
+}
+
+/// The FIFO interface is not always in the TPM2 table.
+fn tis_base() -> Option<u64> {
+    if cfg!(target_arch = "x86_64") {
+        Some(TIS_PC_BASE)
+    } else {
+        device_tree::find_device("tcg,tpm-tis-mmio", |_| true)
+    }
+}
+
+/// Checks the TPM answers on the identity-mapped registers.
+fn probe(interface: impl Interface) -> bool {
+    let mut tpm = ::tpm::Tpm::new(interface);
+    let mut random = [0; 8];
+    match tpm.get_random(&mut random) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("The TPM does not answer: {e:?}");
+            false
+        }
+    }
+}
+
+/// Finds the TPM interface in the TPM2 table.
+pub fn discover_tpm(rsdp_addr: u64, tpm: &mut Tpm) {
+    *tpm = Tpm::default();
+    // SAFETY: the RSDP comes from the UEFI configuration table, and the
+    // tables are identity-mapped.
+    let tables = match unsafe { AcpiTables::from_rsdp(rsdp_addr, 0) } {
+        Ok(tables) => tables,
+        Err(e) => {
+            log::warn!("Cannot look for the TPM2 table: {e:?}");
+            return;
+        }
+    };
+    let Some(tpm2) = tables.tpm2() else {
+        log::info!("No TPM2 table");
+        return;
+    };
+
+    let (interface, phys_base) = match tpm2.start_method() {
+        TPM2_START_CRB | TPM2_START_CRB_ACPI | TPM2_START_CRB_SMC => (
+            TpmInterface::Crb,
+            tpm2.control_area() & !(LOCALITY_SIZE - 1),
+        ),
+        TPM2_START_TIS => match tpm2.control_area() {
+            0 => match tis_base() {
+                Some(phys_base) => (TpmInterface::Tis, phys_base),
+                None => {
+                    log::warn!("No TPM FIFO interface in the device tree");
+                    return;
+                }
+            },
+            phys_base => (TpmInterface::Tis, phys_base),
+        },
+        start_method => {
+            log::warn!("TPM start method {start_method} is not supported");
+            return;
+        }
+    };
+
+    let present = match interface {
+        // SAFETY: the registers are identity-mapped while the boot
+        // services are active.
+        TpmInterface::Crb => probe(unsafe { Crb::new(phys_base, phys_base, LOCALITY_SIZE) }),
+        // SAFETY: as above.
+        TpmInterface::Tis => probe(unsafe { Tis::new(phys_base) }),
+        TpmInterface::None => false,
+    };
+    if present {
+        *tpm = Tpm {
+            interface,
+            phys_base,
+            size: LOCALITY_SIZE,
+            ..Tpm::default()
+        };
+        log::info!("TPM 2.0 {interface:?} @ {phys_base:#016x}");
+    }
+}
+
+/// Maps the registers of the TPM into the kernel address space.
+pub fn map_tpm(tpm: &mut Tpm, page_tables: &mut PageTables) {
+    if tpm.interface != TpmInterface::None {
+        tpm.virt_base = page_tables.map_device(tpm.phys_base, tpm.size);
+    }
+}
@@ -0,0 +1,262 @@
+//! Setting the display mode, and handing the framebuffer to the kernel.
+//!
+//! The mode is set through the Graphics Output Protocol before the payload
+//! is started so that CorgOS, Linux, and the chainloaded applications all
+//! get it. Without the protocol, e.g. on the headless boards, the loader
+//! carries on with no framebuffer.
+//!
+//! The kernel gets scratch memory next to the framebuffer for its early
+//! console: drawing into the write-combined or uncached framebuffer and
+//! reading it back for scrolling is slow, so the console draws into the
+//! shadow buffer and copies the changed lines out. The loader logs through
+//! the same memory until the kernel takes over.
+
+use crate::boot_error::BootError;
+use crate::boot_error::OrFail;
+use crate::memory_types;
+use crate::paging::PageTables;
+use crate::paging::LINEAR_MAP_BASE;
+use crate::paging::LINEAR_MAP_MAX_SIZE;
+use boot_logger::VideoMode;
+use bootinfo::Framebuffer;
+use bootinfo::FramebufferConsole;
+use uefi::boot;
+use uefi::boot::AllocateType;
+use uefi::boot::OpenProtocolAttributes;
+use uefi::boot::OpenProtocolParams;
+use uefi::boot::ScopedProtocol;
+use uefi::proto::console::gop::GraphicsOutput;
+use uefi::proto::console::gop::Mode;
+use uefi::proto::console::gop::ModeInfo;
+use uefi::proto::console::gop::PixelFormat;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Enough for 512 glyphs of 32x32 pixels at one bit per pixel.
+const CONSOLE_FONT_SIZE: u64 = 0x1_0000;
+
+fn graphics_output() -> uefi::Result<ScopedProtocol<GraphicsOutput>> {
+    let handle = boot::get_handle_for_protocol::<GraphicsOutput>()?;
+    // SAFETY: the exclusive access would disconnect the UEFI console from
+    // the display, and the protocol is not uninstalled while the loader runs.
+    unsafe {
+        boot::open_protocol::<GraphicsOutput>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+}
+
+/// `0` if the mode has no framebuffer.
+fn bits_per_pixel(info: &ModeInfo) -> usize {
+    match info.pixel_format() {
+        PixelFormat::Rgb | PixelFormat::Bgr => 32,
+        PixelFormat::Bitmask => info.pixel_bitmask().map_or(0, |mask| {
+            (u32::BITS - (mask.red | mask.green | mask.blue | mask.reserved).leading_zeros())
+                as usize
+        }),
+        PixelFormat::BltOnly => 0,
+    }
+}
+
+fn log_mode(prefix: &str, info: &ModeInfo) {
+    let (width, height) = info.resolution();
+    log::info!(
+        "{prefix} {width}x{height}x{}, {:?}, stride {}",
+        bits_per_pixel(info),
+        info.pixel_format(),
+        info.stride()
+    );
+}
+
+fn best_mode(gop: &GraphicsOutput, video_mode: VideoMode) -> Option<Mode> {
+    let modes = gop.modes().filter(|mode| bits_per_pixel(mode.info()) != 0);
+    let area = |mode: &Mode| {
+        let (width, height) = mode.info().resolution();
+        width * height
+    };
+
+    match video_mode {
+        VideoMode::Keep => None,
+        VideoMode::Max => modes.max_by_key(|mode| (area(mode), bits_per_pixel(mode.info()))),
+        VideoMode::Resolution {
+            width,
+            height,
+            bits_per_pixel: bpp,
+        } => modes
+            .filter(|mode| {
+                let (mode_width, mode_height) = mode.info().resolution();
+                mode_width <= width && mode_height <= height
+            })
+            .max_by_key(|mode| (area(mode), bits_per_pixel(mode.info()) == bpp)),
+    }
+}
+
+/// Sets the display mode, and returns the framebuffer to map for the kernel.
+pub fn set_video_mode(video_mode: VideoMode) -> Framebuffer {
+    let mut gop = match graphics_output() {
+        Ok(gop) => gop,
+        Err(e) => {
+            log::info!("No graphics output: {:?}", e.status());
+            return Framebuffer::default();
+        }
+    };
+
+    for mode in gop.modes() {
+        let (width, height) = mode.info().resolution();
+        log::debug!(
+            "Video mode {width}x{height}x{}",
+            bits_per_pixel(mode.info())
+        );
+    }
+    log_mode("Current video mode", &gop.current_mode_info());
+
+    match best_mode(&gop, video_mode) {
+        Some(mode) if *mode.info() != gop.current_mode_info() => {
+            if let Err(e) = gop.set_mode(&mode) {
+                log::warn!("Cannot set the video mode: {:?}", e.status());
+            }
+        }
+        Some(_) => {}
+        None if video_mode != VideoMode::Keep => {
+            log::warn!("No video mode matches {video_mode:?}, keeping the current one");
+        }
+        None => {}
+    }
+
+    let info = gop.current_mode_info();
+    log_mode("Video mode", &info);
+    let bits_per_pixel = bits_per_pixel(&info);
+    if bits_per_pixel == 0 {
+        log::info!("The video mode has no framebuffer");
+        return Framebuffer::default();
+    }
+
+    let (width, height) = info.resolution();
+    let mask = info.pixel_bitmask().unwrap_or_default();
+    let mut frame_buffer = gop.frame_buffer();
+    let framebuffer = Framebuffer {
+        pixel_format: match info.pixel_format() {
+            PixelFormat::Rgb => bootinfo::PixelFormat::Rgb,
+            PixelFormat::Bgr => bootinfo::PixelFormat::Bgr,
+            _ => bootinfo::PixelFormat::Bitmask,
+        },
+        bits_per_pixel: bits_per_pixel as u32,
+        width: width as u32,
+        height: height as u32,
+        stride: info.stride() as u32,
+        red_mask: mask.red,
+        green_mask: mask.green,
+        blue_mask: mask.blue,
+        reserved_mask: mask.reserved,
+        phys_base: frame_buffer.as_mut_ptr() as u64,
+        size: frame_buffer.size() as u64,
+        ..Framebuffer::default()
+    };
+    log::info!(
+        "Framebuffer @ {:#016x}, {:#x} bytes",
+        framebuffer.phys_base,
+        framebuffer.size
+    );
+
+    framebuffer
+}
+
+/// Maps the framebuffer into the kernel address space.
+pub fn map_framebuffer(framebuffer: Framebuffer, page_tables: &mut PageTables) -> Framebuffer {
+    if framebuffer.size == 0 {
+        return framebuffer;
+    }
+
+    let framebuffer = Framebuffer {
+        virt_base: page_tables.map_device(framebuffer.phys_base, framebuffer.size),
+        ..framebuffer
+    };
+    log::info!("Kernel framebuffer {framebuffer:x?}");
+    framebuffer
+}
+
+/// Logs to the framebuffer with the shadow buffer in the `scratch` memory,
+/// both identity-mapped. Nothing is drawn for the formats the console
+/// cannot do.
+pub fn start_log_console(framebuffer: &Framebuffer, scratch: &FramebufferConsole) {
+    let Some(format) = fbcon::PixelFormat::from_boot_info(framebuffer) else {
+        return;
+    };
+    if scratch.shadow_size < framebuffer.size {
+        return;
+    }
+
+    // SAFETY: the scratch memory has just been allocated, and holds the
+    // shadow buffer of the framebuffer size.
+    let shadow = unsafe {
+        core::slice::from_raw_parts_mut(
+            scratch.phys_base as *mut u32,
+            scratch.shadow_size as usize / core::mem::size_of::<u32>(),
+        )
+    };
+    // SAFETY: the firmware has identity-mapped the framebuffer, and the
+    // loader draws there through the console only.
+    let console = unsafe {
+        fbcon::Console::new(
+            fbcon::Framebuffer {
+                base_addr: framebuffer.phys_base,
+                width: framebuffer.width as usize,
+                height: framebuffer.height as usize,
+                stride: framebuffer.stride as usize,
+                format,
+            },
+            shadow,
+            fbcon::Psf2Font::default_font(),
+        )
+    };
+    match console {
+        Some(console) => boot_logger::set_framebuffer_console(console),
+        None => log::warn!("The framebuffer is too small for the console"),
+    }
+}
+
+/// Allocates the scratch memory for the early console on `framebuffer`,
+/// none if there is no framebuffer.
+pub fn allocate_console_scratch(framebuffer: &Framebuffer) -> FramebufferConsole {
+    if framebuffer.size == 0 {
+        return FramebufferConsole::default();
+    }
+
+    let shadow_size = framebuffer.size;
+    let font_offset = shadow_size.next_multiple_of(PAGE_SIZE);
+    let size = font_offset + CONSOLE_FONT_SIZE;
+    let scratch = boot::allocate_pages(
+        AllocateType::MaxAddress(LINEAR_MAP_MAX_SIZE - 1),
+        memory_types::FRAMEBUFFER_CONSOLE,
+        (size / PAGE_SIZE) as usize,
+    )
+    .or_fail(
+        BootError::OutOfMemory,
+        "Cannot allocate the framebuffer console memory",
+    );
+    // SAFETY: the pages have just been allocated, and the memory is identity-mapped.
+    unsafe { core::ptr::write_bytes(scratch.as_ptr(), 0, size as usize) };
+
+    let phys_base = scratch.as_ptr() as u64;
+    let console = FramebufferConsole {
+        phys_base,
+        virt_base: LINEAR_MAP_BASE + phys_base,
+        size,
+        shadow_size,
+        font_offset,
+        font_size: CONSOLE_FONT_SIZE,
+    };
+    log::info!(
+        "Framebuffer console memory @ {:#016x} -> {:#016x}, {:#x} bytes",
+        console.virt_base,
+        console.phys_base,
+        console.size
+    );
+
+    console
+}
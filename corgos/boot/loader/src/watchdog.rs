@@ -0,0 +1,51 @@
+//! The UEFI watchdog while loading.
+//!
+//! The firmware resets the machine if the watchdog expires before the boot
+//! services are exited. Reading a large kernel from slow media can take
+//! long enough for that, so the long operations call [`refresh`] to re-arm
+//! the watchdog with the full timeout.
+
+use crate::WATCHDOG_TIMEOUT_CODE;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use uefi::boot;
+
+static LOAD_TIMEOUT_SECONDS: AtomicUsize =
+    AtomicUsize::new(boot_logger::DEFAULT_LOAD_WATCHDOG_SECONDS);
+
+fn set(timeout_seconds: usize) {
+    if let Err(e) = boot::set_watchdog_timer(timeout_seconds, WATCHDOG_TIMEOUT_CODE, None) {
+        log::warn!("Could not set the watchdog to {timeout_seconds} seconds: {e:?}");
+    }
+}
+
+/// Arms the watchdog for loading, `0` disables it.
+pub fn start(timeout_seconds: usize) {
+    LOAD_TIMEOUT_SECONDS.store(timeout_seconds, Ordering::Relaxed);
+    set(timeout_seconds);
+
+    if timeout_seconds == 0 {
+        log::info!("Watchdog disabled while loading");
+    } else {
+        log::info!("Watchdog armed for {timeout_seconds} seconds while loading");
+    }
+}
+
+/// Disables the watchdog while waiting for the user, [`refresh`] re-arms it.
+pub fn disable() {
+    set(0);
+}
+
+/// Re-arms the watchdog with the loading timeout.
+pub fn refresh() {
+    let timeout_seconds = LOAD_TIMEOUT_SECONDS.load(Ordering::Relaxed);
+    if timeout_seconds != 0 {
+        set(timeout_seconds);
+    }
+}
+
+/// Sets the watchdog right before exiting the boot services, `0` disables it.
+pub fn arm_for_exit(timeout_seconds: usize) {
+    set(timeout_seconds);
+    log::info!("Watchdog set to {timeout_seconds} seconds for exiting the boot services");
+}
@@ -0,0 +1,123 @@
+//! The x86_64 processor state for the kernel.
+//!
+//! The firmware leaves its own GDT and IDT installed, and those live in
+//! the boot services memory the kernel is free to reuse. The loader swaps
+//! them for its own: flat 64-bit code and data descriptors with a TSS, and
+//! an IDT that logs the exception and the registers before panicking.
+//! These cover the loader until the jump to the kernel. They live in the
+//! loader image, which the kernel tables do not map, so the kernel entry
+//! loads its own tables first thing. The interrupts are disabled and the
+//! legacy PIC is masked so no stray vector arrives before the kernel is
+//! ready.
+
+use core::arch::asm;
+use exceptions::x86_64::ExceptionFrame;
+use exceptions::x86_64::GlobalDescriptorTable;
+use exceptions::x86_64::IdtGate;
+use exceptions::x86_64::InterruptDescriptorTable;
+use exceptions::x86_64::SegmentDescriptor;
+use exceptions::x86_64::SegmentSelector;
+use exceptions::x86_64::TaskStateSegment;
+use exceptions::x86_64::EXCEPTION_COUNT;
+
+/// The null descriptor, the code, the data, and the two entries of the TSS.
+const GDT_ENTRIES: usize = 5;
+/// The double fault runs on its own stack as the one the loader has been
+/// running on may be what caused it.
+const DOUBLE_FAULT_VECTOR: u8 = 8;
+const DOUBLE_FAULT_IST: u8 = 1;
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+const PIC1_DATA: u16 = 0x21;
+const PIC2_DATA: u16 = 0xa1;
+
+#[repr(C, align(16))]
+struct FaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: FaultStack = FaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut GDT: GlobalDescriptorTable<GDT_ENTRIES> = GlobalDescriptorTable::new();
+static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+
+fn loader_fault(frame: &mut ExceptionFrame) {
+    if crate::gdb_stub::stop(frame) {
+        return;
+    }
+    log::error!("{frame:#x?}");
+    panic!(
+        "Exception {} (error code {:#x}) at {:#016x}, CR2 {:#016x}",
+        frame.vector, frame.error_code, frame.rip, frame.cr2
+    );
+}
+
+fn mask_legacy_pic() {
+    // SAFETY: writing the interrupt mask registers of the 8259 has no side
+    // effects beyond masking the interrupts, and is harmless without a PIC.
+    unsafe {
+        asm!("out dx, al", in("dx") PIC1_DATA, in("al") 0xff_u8, options(nomem, nostack));
+        asm!("out dx, al", in("dx") PIC2_DATA, in("al") 0xff_u8, options(nomem, nostack));
+    }
+}
+
+/// Builds the GDT with the TSS, and loads both. Returns the code selector
+/// for the IDT gates.
+fn load_gdt() -> SegmentSelector {
+    // SAFETY: the interrupts are disabled, and nothing else accesses the
+    // stack, the TSS, or the GDT.
+    let (stack, tss, gdt) = unsafe {
+        (
+            &*core::ptr::addr_of!(DOUBLE_FAULT_STACK),
+            &mut *core::ptr::addr_of_mut!(TSS),
+            &mut *core::ptr::addr_of_mut!(GDT),
+        )
+    };
+    let stack_top = stack.0.as_ptr_range().end as u64;
+    tss.interrupt_stacks[DOUBLE_FAULT_IST as usize - 1] = stack_top;
+
+    let code = gdt.add(SegmentDescriptor::kernel_code());
+    let data = gdt.add(SegmentDescriptor::kernel_data());
+    let tss = gdt.add_tss(tss);
+
+    // SAFETY: the GDT and the TSS are static, and the selectors refer to
+    // their descriptors.
+    unsafe {
+        exceptions::x86_64::load_gdt(gdt, code, data);
+        exceptions::x86_64::load_tss(tss);
+    }
+    code
+}
+
+fn load_idt(code: SegmentSelector) {
+    exceptions::x86_64::set_fault_handler(loader_fault);
+
+    // SAFETY: the interrupts are disabled, and nothing else accesses the IDT.
+    let idt = unsafe { &mut *core::ptr::addr_of_mut!(IDT) };
+    for vector in 0..EXCEPTION_COUNT as u8 {
+        let gate = IdtGate::interrupt(exceptions::x86_64::fault_stub(vector), code);
+        let gate = if vector == DOUBLE_FAULT_VECTOR {
+            gate.with_ist(DOUBLE_FAULT_IST)
+        } else {
+            gate
+        };
+        idt.set(vector, gate);
+    }
+
+    // SAFETY: the IDT is static, and its gates point to the fault stubs.
+    unsafe { exceptions::x86_64::load_idt(idt) };
+}
+
+/// Puts the processor into the state the kernel expects. Must be called
+/// after exiting the boot services as the firmware relies on its own
+/// interrupt handlers until then.
+pub fn prepare_for_kernel() {
+    // The loader does not rely on interrupts past this point.
+    crate::interrupts::mask();
+    // SAFETY: clearing the direction flag is what the ABI expects anyway.
+    unsafe { asm!("cld", options(nomem, nostack)) };
+
+    mask_legacy_pic();
+    let code = load_gdt();
+    load_idt(code);
+
+    log::info!("Loaded the loader GDT, TSS, and IDT, legacy PIC masked");
+}
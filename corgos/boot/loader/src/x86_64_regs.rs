@@ -0,0 +1,494 @@
+#![allow(dead_code)]
+
+use bitfield_struct::bitfield;
+
+pub const IA32_APIC_BASE: u32 = 0x1b;
+pub const IA32_MTRRCAP: u32 = 0xfe;
+pub const IA32_MTRR_PHYSBASE0: u32 = 0x200;
+pub const IA32_MTRR_PHYSMASK0: u32 = 0x201;
+pub const IA32_PAT: u32 = 0x277;
+pub const IA32_MTRR_DEF_TYPE: u32 = 0x2ff;
+pub const IA32_EFER: u32 = 0xc000_0080;
+
+#[bitfield(u64)]
+pub struct Cr0 {
+    pub pe: bool,
+    pub mp: bool,
+    pub em: bool,
+    pub ts: bool,
+    pub et: bool,
+    pub ne: bool,
+    #[bits(10)]
+    _reserved0: u64,
+    pub wp: bool,
+    _reserved1: bool,
+    pub am: bool,
+    #[bits(10)]
+    _reserved2: u64,
+    pub nw: bool,
+    pub cd: bool,
+    pub pg: bool,
+    #[bits(32)]
+    _reserved3: u64,
+}
+
+#[bitfield(u64)]
+pub struct Cr3 {
+    #[bits(3)]
+    _ignored0: u64,
+    pub write_through: bool,
+    pub cache_disable: bool,
+    #[bits(7)]
+    _ignored1: u64,
+    #[bits(40)]
+    pub pml_pfn: u64,
+    #[bits(12)]
+    _mbz0: u64,
+}
+
+#[bitfield(u64)]
+pub struct Cr4 {
+    pub vme: bool,
+    pub pvi: bool,
+    pub tsd: bool,
+    pub de: bool,
+    pub pse: bool,
+    pub pae: bool,
+    pub mce: bool,
+    pub pge: bool,
+    pub pce: bool,
+    pub osfxsr: bool,
+    pub osxmmexcpt: bool,
+    pub umip: bool,
+    pub la57: bool,
+    pub vmxe: bool,
+    pub smxe: bool,
+    _reserved0: bool,
+    pub fsgsbase: bool,
+    pub pcide: bool,
+    pub osxsave: bool,
+    pub kl: bool,
+    pub smep: bool,
+    pub smap: bool,
+    pub pke: bool,
+    pub cet: bool,
+    pub pks: bool,
+    pub uintr: bool,
+    #[bits(38)]
+    _reserved1: u64,
+}
+
+#[bitfield(u64)]
+pub struct Efer {
+    pub sce: bool,
+    #[bits(7)]
+    _reserved0: u64,
+    pub lme: bool,
+    _reserved1: bool,
+    pub lma: bool,
+    pub nxe: bool,
+    pub svme: bool,
+    pub lmsle: bool,
+    pub ffxsr: bool,
+    pub tce: bool,
+    #[bits(48)]
+    _reserved2: u64,
+}
+
+#[bitfield(u64)]
+pub struct ApicBase {
+    #[bits(8)]
+    _reserved0: u64,
+    pub bsp: bool,
+    _reserved1: bool,
+    /// The x2APIC mode, together with `en`.
+    pub extd: bool,
+    pub en: bool,
+    #[bits(40)]
+    pub base_pfn: u64,
+    #[bits(12)]
+    _reserved2: u64,
+}
+
+/// The index of `XCR0` for `xgetbv` and `xsetbv`.
+pub const XCR0: u32 = 0;
+
+/// The state components `xsave` manages, and the instructions using them
+/// are enabled for. Accessible with `CR4.OSXSAVE` set.
+#[bitfield(u64)]
+pub struct Xcr0 {
+    /// Must be set.
+    pub x87: bool,
+    pub sse: bool,
+    pub avx: bool,
+    pub bndreg: bool,
+    pub bndcsr: bool,
+    pub opmask: bool,
+    pub zmm_hi256: bool,
+    pub hi16_zmm: bool,
+    _reserved0: bool,
+    pub pkru: bool,
+    #[bits(7)]
+    _reserved1: u64,
+    pub tilecfg: bool,
+    pub tiledata: bool,
+    #[bits(45)]
+    _reserved2: u64,
+}
+
+/// The memory types of the PAT entries and of the MTRRs, `UC-` is only
+/// valid in the PAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CacheType {
+    Uncacheable = 0,
+    WriteCombining = 1,
+    Reserved2 = 2,
+    Reserved3 = 3,
+    WriteThrough = 4,
+    WriteProtected = 5,
+    WriteBack = 6,
+    UncachedMinus = 7,
+}
+
+impl CacheType {
+    const fn into_bits(self) -> u64 {
+        self as u64
+    }
+
+    const fn from_bits(bits: u64) -> Self {
+        match bits & 0x7 {
+            0 => Self::Uncacheable,
+            1 => Self::WriteCombining,
+            2 => Self::Reserved2,
+            3 => Self::Reserved3,
+            4 => Self::WriteThrough,
+            5 => Self::WriteProtected,
+            6 => Self::WriteBack,
+            _ => Self::UncachedMinus,
+        }
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Self::Uncacheable => "UC",
+            Self::WriteCombining => "WC",
+            Self::WriteThrough => "WT",
+            Self::WriteProtected => "WP",
+            Self::WriteBack => "WB",
+            Self::UncachedMinus => "UC-",
+            Self::Reserved2 | Self::Reserved3 => "reserved",
+        }
+    }
+}
+
+/// The page attribute table, the entry is picked by the `PAT`, `PCD`, and
+/// `PWT` bits of the page table entry.
+#[bitfield(u64)]
+pub struct Pat {
+    #[bits(3)]
+    pub pa0: CacheType,
+    #[bits(5)]
+    _reserved0: u64,
+    #[bits(3)]
+    pub pa1: CacheType,
+    #[bits(5)]
+    _reserved1: u64,
+    #[bits(3)]
+    pub pa2: CacheType,
+    #[bits(5)]
+    _reserved2: u64,
+    #[bits(3)]
+    pub pa3: CacheType,
+    #[bits(5)]
+    _reserved3: u64,
+    #[bits(3)]
+    pub pa4: CacheType,
+    #[bits(5)]
+    _reserved4: u64,
+    #[bits(3)]
+    pub pa5: CacheType,
+    #[bits(5)]
+    _reserved5: u64,
+    #[bits(3)]
+    pub pa6: CacheType,
+    #[bits(5)]
+    _reserved6: u64,
+    #[bits(3)]
+    pub pa7: CacheType,
+    #[bits(5)]
+    _reserved7: u64,
+}
+
+impl Pat {
+    pub fn entry(&self, index: usize) -> CacheType {
+        CacheType::from_bits(self.into_bits().to_le_bytes()[index] as u64)
+    }
+}
+
+#[bitfield(u64)]
+pub struct MtrrCapabilities {
+    #[bits(8)]
+    pub variable_count: u64,
+    pub fixed: bool,
+    _reserved0: bool,
+    pub write_combining: bool,
+    pub smrr: bool,
+    pub prmrr: bool,
+    #[bits(51)]
+    _reserved1: u64,
+}
+
+#[bitfield(u64)]
+pub struct MtrrDefaultType {
+    #[bits(3)]
+    pub default_type: CacheType,
+    #[bits(7)]
+    _reserved0: u64,
+    pub fixed_enable: bool,
+    pub enable: bool,
+    #[bits(52)]
+    _reserved1: u64,
+}
+
+#[bitfield(u64)]
+pub struct MtrrPhysBase {
+    #[bits(3)]
+    pub cache_type: CacheType,
+    #[bits(9)]
+    _reserved0: u64,
+    #[bits(40)]
+    pub base_pfn: u64,
+    #[bits(12)]
+    _reserved1: u64,
+}
+
+#[bitfield(u64)]
+pub struct MtrrPhysMask {
+    #[bits(11)]
+    _reserved0: u64,
+    pub valid: bool,
+    #[bits(40)]
+    pub mask_pfn: u64,
+    #[bits(12)]
+    _reserved1: u64,
+}
+
+/// An entry of any level: PML5, PML4, PDPT, PD, or PT.
+#[bitfield(u64)]
+pub struct PageTableEntry {
+    pub present: bool,
+    pub writable: bool,
+    pub user: bool,
+    pub write_through: bool,
+    pub cache_disable: bool,
+    pub accessed: bool,
+    /// Only in the entries mapping a page.
+    pub dirty: bool,
+    /// Maps a 1GiB (PDPT) or a 2MiB (PD) page, that is `PAT` in a PT entry.
+    pub page_size: bool,
+    /// Only in the entries mapping a page.
+    pub global: bool,
+    #[bits(3)]
+    pub available0: u64,
+    /// Bit 0 is `PAT` for the large pages.
+    #[bits(40)]
+    pub address_pfn: u64,
+    #[bits(7)]
+    pub available1: u64,
+    #[bits(4)]
+    pub protection_key: u64,
+    pub no_execute: bool,
+}
+
+pub mod access {
+    use super::*;
+
+    #[macro_export]
+    macro_rules! load_cr {
+        ($cr:ident) => {{
+            let reg_val: u64;
+            unsafe {
+                core::arch::asm!(concat!("mov {}, ", stringify!($cr)), out(reg) reg_val, options(nomem, nostack));
+            }
+            reg_val
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! store_cr {
+        ($cr:ident, $val:expr) => {{
+            let val: u64 = $val;
+            unsafe {
+                core::arch::asm!(concat!("mov ", stringify!($cr), ", {}"), in(reg) val, options(nostack));
+            }
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! load_msr {
+        ($msr:expr) => {{
+            let (lo, hi): (u32, u32);
+            unsafe {
+                core::arch::asm!("rdmsr", in("ecx") $msr, out("eax") lo, out("edx") hi, options(nomem, nostack));
+            }
+            u64::from(hi) << 32 | u64::from(lo)
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! store_msr {
+        ($msr:expr, $val:expr) => {{
+            let val: u64 = $val;
+            unsafe {
+                core::arch::asm!("wrmsr", in("ecx") $msr, in("eax") val as u32, in("edx") (val >> 32) as u32, options(nostack));
+            }
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! load_xcr {
+        ($xcr:expr) => {{
+            let (lo, hi): (u32, u32);
+            unsafe {
+                core::arch::asm!("xgetbv", in("ecx") $xcr, out("eax") lo, out("edx") hi, options(nomem, nostack));
+            }
+            u64::from(hi) << 32 | u64::from(lo)
+        }};
+    }
+
+    #[macro_export]
+    macro_rules! store_xcr {
+        ($xcr:expr, $val:expr) => {{
+            let val: u64 = $val;
+            unsafe {
+                core::arch::asm!("xsetbv", in("ecx") $xcr, in("eax") val as u32, in("edx") (val >> 32) as u32, options(nostack));
+            }
+        }};
+    }
+
+    pub trait X86Register: core::fmt::Debug {
+        fn load(&mut self);
+        fn name(&self) -> &'static str;
+        fn bits(&self) -> u64;
+    }
+
+    macro_rules! impl_cr_access {
+        ($register_type:ident, $name:literal, $cr:ident) => {
+            impl X86Register for $register_type {
+                fn load(&mut self) {
+                    *self = Self::from_bits(load_cr!($cr));
+                }
+
+                fn name(&self) -> &'static str {
+                    $name
+                }
+
+                fn bits(&self) -> u64 {
+                    (*self).into_bits()
+                }
+            }
+
+            impl $register_type {
+                pub fn store(&mut self) {
+                    store_cr!($cr, (*self).into_bits())
+                }
+            }
+        };
+    }
+
+    macro_rules! impl_msr_access {
+        ($register_type:ident, $msr:ident) => {
+            impl X86Register for $register_type {
+                fn load(&mut self) {
+                    *self = Self::from_bits(load_msr!($msr));
+                }
+
+                fn name(&self) -> &'static str {
+                    stringify!($msr)
+                }
+
+                fn bits(&self) -> u64 {
+                    (*self).into_bits()
+                }
+            }
+
+            impl $register_type {
+                pub fn store(&mut self) {
+                    store_msr!($msr, (*self).into_bits())
+                }
+            }
+        };
+    }
+
+    macro_rules! impl_msr_access_ro {
+        ($register_type:ident, $msr:ident) => {
+            impl X86Register for $register_type {
+                fn load(&mut self) {
+                    *self = Self::from_bits(load_msr!($msr));
+                }
+
+                fn name(&self) -> &'static str {
+                    stringify!($msr)
+                }
+
+                fn bits(&self) -> u64 {
+                    (*self).into_bits()
+                }
+            }
+        };
+    }
+
+    macro_rules! impl_xcr_access {
+        ($register_type:ident, $xcr:ident) => {
+            impl X86Register for $register_type {
+                fn load(&mut self) {
+                    *self = Self::from_bits(load_xcr!($xcr));
+                }
+
+                fn name(&self) -> &'static str {
+                    stringify!($xcr)
+                }
+
+                fn bits(&self) -> u64 {
+                    (*self).into_bits()
+                }
+            }
+
+            impl $register_type {
+                pub fn store(&mut self) {
+                    store_xcr!($xcr, (*self).into_bits())
+                }
+            }
+        };
+    }
+
+    impl_cr_access!(Cr0, "CR0", cr0);
+    impl_cr_access!(Cr3, "CR3", cr3);
+    impl_cr_access!(Cr4, "CR4", cr4);
+
+    impl_msr_access!(Efer, IA32_EFER);
+    impl_msr_access!(ApicBase, IA32_APIC_BASE);
+    impl_msr_access!(Pat, IA32_PAT);
+    impl_msr_access!(MtrrDefaultType, IA32_MTRR_DEF_TYPE);
+
+    impl_msr_access_ro!(MtrrCapabilities, IA32_MTRRCAP);
+
+    impl_xcr_access!(Xcr0, XCR0);
+
+    /// The variable range MTRR pair `index`, there are `variable_count` of
+    /// them.
+    pub fn variable_mtrr(index: u32) -> (MtrrPhysBase, MtrrPhysMask) {
+        (
+            MtrrPhysBase::from_bits(load_msr!(IA32_MTRR_PHYSBASE0 + 2 * index)),
+            MtrrPhysMask::from_bits(load_msr!(IA32_MTRR_PHYSMASK0 + 2 * index)),
+        )
+    }
+
+    #[macro_export]
+    macro_rules! register {
+        ($reg:ident) => {
+            &mut $reg::new() as &mut dyn X86Register
+        };
+    }
+}
@@ -5,20 +5,208 @@
 //! While UEFI offers the serial output, that works only when
 //! the boot services are still active. Due to this, a UART with
 //! polling is used.
+//!
+//! The virtio console is for the machines with no UART at a stable
+//! address, its queues are in the loader image.
+//!
+//! Once the loader has a framebuffer, the log goes there, too, unless the
+//! UEFI console is drawing on it.
 
 use core::fmt::Write;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
 
-use conquer_once::spin::OnceCell;
 use log::LevelFilter;
 use poll_uart::BaudDivisor;
 use poll_uart::ComPort;
 use poll_uart::ComPortIo;
 use poll_uart::Pl011;
+use poll_uart::VirtioConsole;
+use poll_uart::VirtioConsoleQueues;
+use sync::OnceCell;
+use sync::Spinlock;
 use uefi::boot;
 use uefi::proto::console::text::Output;
 use uefi::table;
 
 pub const MAX_REVISION_SIZE: usize = 64;
+pub const MAX_PATH_SIZE: usize = 128;
+pub const MAX_NAME_SIZE: usize = 64;
+pub const MAX_CMDLINE_SIZE: usize = 256;
+pub const MAX_BOOT_ENTRIES: usize = 8;
+pub const MAX_MODULES: usize = 8;
+/// SHA-256 in hex digits.
+pub const MAX_SHA256_HEX_SIZE: usize = 64;
+/// A GUID as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+pub const MAX_GUID_SIZE: usize = 36;
+
+/// The name of the CorgOS kernel binary image used when nothing is configured.
+pub const DEFAULT_KERNEL_PATH: &str = "corgos";
+/// How many times the primary kernel may try to boot before the fallback is used.
+pub const DEFAULT_MAX_BOOT_ATTEMPTS: u8 = 3;
+/// How many failed boots make the loader boot the recovery entry, after
+/// both the primary and the fallback kernel have had their attempts.
+pub const DEFAULT_RECOVERY_BOOT_ATTEMPTS: u8 = 2 * DEFAULT_MAX_BOOT_ATTEMPTS;
+/// The name of the boot menu entry booted for recovery.
+pub const RECOVERY_ENTRY_NAME: &str = "recovery";
+/// How long the boot menu waits for a key press.
+pub const DEFAULT_MENU_TIMEOUT_SECONDS: usize = 5;
+/// The same as the firmware arms the watchdog with before starting the loader.
+pub const DEFAULT_LOAD_WATCHDOG_SECONDS: usize = 300;
+/// The size of the in-memory log.
+pub const LOG_RING_SIZE: usize = 0x10000;
+/// How many times a network download is attempted.
+pub const DEFAULT_NET_RETRIES: usize = 3;
+/// The kernel heap for the small objects until it can grow from the page
+/// allocator.
+pub const DEFAULT_EARLY_HEAP_SIZE: usize = 16 << 20;
+/// The initial kernel stack size.
+pub const DEFAULT_KERNEL_STACK_SIZE: usize = 64 << 10;
+
+/// A boot menu entry.
+#[derive(Debug, Clone, Copy)]
+pub struct BootEntry {
+    /// The name shown in the menu, the entry is unused if empty.
+    pub name: [u8; MAX_NAME_SIZE],
+    /// The kernel image, the configured kernel is used if empty.
+    pub kernel: [u8; MAX_PATH_SIZE],
+    /// The kernel command line.
+    pub cmdline: [u8; MAX_CMDLINE_SIZE],
+}
+
+impl Default for BootEntry {
+    fn default() -> Self {
+        Self {
+            name: [0; MAX_NAME_SIZE],
+            kernel: [0; MAX_PATH_SIZE],
+            cmdline: [0; MAX_CMDLINE_SIZE],
+        }
+    }
+}
+
+impl BootEntry {
+    pub fn is_used(&self) -> bool {
+        self.name[0] != 0
+    }
+
+    pub fn name_str(&self) -> &str {
+        zero_terminated_str(&self.name)
+    }
+
+    pub fn kernel_str(&self) -> &str {
+        zero_terminated_str(&self.kernel)
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        zero_terminated_str(&self.cmdline)
+    }
+}
+
+/// What the page table walk logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageTableWalk {
+    Off,
+    /// Every entry.
+    Entries,
+    /// The ranges of pages with the same attributes, and the totals.
+    Summary,
+}
+
+/// The display mode the loader sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoMode {
+    /// The mode the firmware has set.
+    Keep,
+    /// The highest resolution.
+    Max,
+    /// The closest mode not exceeding the resolution, preferring the
+    /// bits per pixel.
+    Resolution {
+        width: usize,
+        height: usize,
+        bits_per_pixel: usize,
+    },
+}
+
+/// What the loader boots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payload {
+    CorgOs,
+    /// A Linux kernel with the EFI stub.
+    Linux,
+}
+
+/// The real-time clock handed to the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcDevice {
+    /// The PL031 from the device tree on aarch64, the CMOS clock on x86_64
+    /// unless the FADT says there is none.
+    Auto,
+    None,
+    Cmos,
+    /// A PL031 at the address.
+    Pl031(u64),
+}
+
+/// The serial port of the GDB stub in the loader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdbStubDevice {
+    None,
+    Com1,
+    Com2,
+    /// A PL011 at the address.
+    Pl011(u64),
+}
+
+/// A file loaded for the kernel alongside it.
+#[derive(Debug, Clone, Copy)]
+pub struct BootModule {
+    /// The name the kernel looks the module up by, the module is unused if empty.
+    pub name: [u8; MAX_NAME_SIZE],
+    pub path: [u8; MAX_PATH_SIZE],
+    /// The module command line.
+    pub cmdline: [u8; MAX_CMDLINE_SIZE],
+}
+
+impl Default for BootModule {
+    fn default() -> Self {
+        Self {
+            name: [0; MAX_NAME_SIZE],
+            path: [0; MAX_PATH_SIZE],
+            cmdline: [0; MAX_CMDLINE_SIZE],
+        }
+    }
+}
+
+impl BootModule {
+    pub fn is_used(&self) -> bool {
+        self.name[0] != 0
+    }
+
+    pub fn name_str(&self) -> &str {
+        zero_terminated_str(&self.name)
+    }
+
+    pub fn path_str(&self) -> &str {
+        zero_terminated_str(&self.path)
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        zero_terminated_str(&self.cmdline)
+    }
+}
+
+/// What was wrong with the configuration, it is read before there is a
+/// logger to tell.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigProblems {
+    /// The text values that did not fit, the settings keep what they had.
+    pub values_too_long: u8,
+    /// The modules past [`MAX_MODULES`], not loaded.
+    pub modules_dropped: u8,
+    /// The file did not match its `crc32` line, and has been ignored.
+    pub bad_crc32: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct BootLoaderConfig {
@@ -30,12 +218,89 @@ pub struct BootLoaderConfig {
     pub log_level: LevelFilter,
     /// Log source line and path.
     pub log_source_path: bool,
-    /// Wait at the entry point until `x9` or `r9` are set to `0`.
-    pub wait_for_start: bool,
-    /// Walk the page tables, and dump the page table entries.
-    pub walk_page_tables: bool,
-    /// TImeout in seconds for the UEFI watchdog.
+    /// The serial port for GDB, the loader stops early on for it to attach.
+    pub gdb_stub: GdbStubDevice,
+    /// Walk the page tables, and dump the page table entries or the summary.
+    pub walk_page_tables: PageTableWalk,
+    /// TImeout in seconds for the UEFI watchdog. If set, the loader only
+    /// waits for a key press and exits, so [`Self::load_watchdog_seconds`]
+    /// and [`Self::exit_watchdog_seconds`] are not used.
     pub watchdog_seconds: Option<usize>,
+    /// The primary kernel image.
+    pub kernel_a: [u8; MAX_PATH_SIZE],
+    /// The fallback kernel image, A/B boot is disabled if empty.
+    pub kernel_b: [u8; MAX_PATH_SIZE],
+    /// Boot attempts of the primary kernel before falling back.
+    pub max_boot_attempts: u8,
+    /// Failed boots before booting the recovery menu entry, `0` never.
+    pub recovery_boot_attempts: u8,
+    /// The log level when booting the recovery menu entry.
+    pub recovery_log_level: LevelFilter,
+    /// The kernel command line.
+    pub cmdline: [u8; MAX_CMDLINE_SIZE],
+    /// Boot menu entries, the menu is shown if there is at least one.
+    pub boot_entries: [BootEntry; MAX_BOOT_ENTRIES],
+    /// Timeout in seconds before the default menu entry is booted.
+    pub menu_timeout_seconds: usize,
+    /// An EFI application to start instead of the kernel.
+    pub chainload: [u8; MAX_PATH_SIZE],
+    /// Randomize the physical and the virtual base of the kernel.
+    pub kaslr: bool,
+    /// Keep the kernel image in the physical memory below 4 GiB.
+    pub load_below_4g: bool,
+    /// Map the kernel segments asking for that writable and executable.
+    pub allow_wx_kernel: bool,
+    /// Wake up the GIC redistributor of the boot processor on aarch64.
+    pub gic_wake_redistributor: bool,
+    /// Timeout in seconds for the UEFI watchdog while loading, re-armed
+    /// during the long operations. `0` disables the watchdog. Not used if
+    /// [`Self::watchdog_seconds`] is set.
+    pub load_watchdog_seconds: usize,
+    /// Timeout in seconds for the UEFI watchdog set right before exiting
+    /// the boot services. `0` disables the watchdog. Not used if
+    /// [`Self::watchdog_seconds`] is set.
+    pub exit_watchdog_seconds: usize,
+    /// The expected SHA-256 of the kernel image in hex, not checked if empty.
+    pub kernel_sha256: [u8; MAX_SHA256_HEX_SIZE],
+    /// The files to load for the kernel.
+    pub modules: [BootModule; MAX_MODULES],
+    /// What to boot.
+    pub payload: Payload,
+    /// The initial RAM disk for Linux.
+    pub initrd: [u8; MAX_PATH_SIZE],
+    /// How many times a download over the network is attempted.
+    pub net_retries: usize,
+    /// The label of the volume to read the files from.
+    pub volume_label: [u8; MAX_NAME_SIZE],
+    /// The GUID of the partition to read the files from.
+    pub volume_partition_guid: [u8; MAX_GUID_SIZE],
+    /// Enter the debug shell before booting.
+    pub debug_shell: bool,
+    /// Write the diagnostics to the boot volume before exiting the boot services.
+    pub dump_diagnostics: bool,
+    /// The display mode to set.
+    pub video_mode: VideoMode,
+    /// Run a probe on every processor through the EFI MP Services.
+    pub mp_probe: bool,
+    /// Switch the local APIC of the boot processor to the x2APIC mode on
+    /// x86_64.
+    pub x2apic: bool,
+    /// Keep the boot attempts, the last good kernel, and the last menu
+    /// entry in the UEFI variables.
+    pub persist_settings: bool,
+    /// The size of the early kernel heap in bytes, `0` for none.
+    pub early_heap_size: usize,
+    /// Reserve the scratch memory for the early framebuffer console of the
+    /// kernel, and log to the framebuffer through it.
+    pub framebuffer_console: bool,
+    /// The size of the initial kernel stack in bytes.
+    pub kernel_stack_size: usize,
+    /// The real-time clock for the kernel, and for the diagnostics timestamp.
+    pub rtc: RtcDevice,
+    /// Profile the hot paths with the performance counters.
+    pub pmu_profile: bool,
+    /// Logged once the logger is set up.
+    pub problems: ConfigProblems,
 }
 
 impl Default for BootLoaderConfig {
@@ -45,21 +310,134 @@ impl Default for BootLoaderConfig {
             log_device: LogDevice::StdOut,
             log_level: LevelFilter::Trace,
             log_source_path: false,
-            wait_for_start: false,
-            walk_page_tables: false,
+            gdb_stub: GdbStubDevice::None,
+            walk_page_tables: PageTableWalk::Off,
             watchdog_seconds: None,
+            kernel_a: [0; MAX_PATH_SIZE],
+            kernel_b: [0; MAX_PATH_SIZE],
+            max_boot_attempts: DEFAULT_MAX_BOOT_ATTEMPTS,
+            recovery_boot_attempts: DEFAULT_RECOVERY_BOOT_ATTEMPTS,
+            recovery_log_level: LevelFilter::Trace,
+            cmdline: [0; MAX_CMDLINE_SIZE],
+            boot_entries: [BootEntry::default(); MAX_BOOT_ENTRIES],
+            menu_timeout_seconds: DEFAULT_MENU_TIMEOUT_SECONDS,
+            chainload: [0; MAX_PATH_SIZE],
+            kaslr: false,
+            load_below_4g: false,
+            allow_wx_kernel: false,
+            gic_wake_redistributor: true,
+            load_watchdog_seconds: DEFAULT_LOAD_WATCHDOG_SECONDS,
+            exit_watchdog_seconds: 0,
+            kernel_sha256: [0; MAX_SHA256_HEX_SIZE],
+            modules: [BootModule::default(); MAX_MODULES],
+            payload: Payload::CorgOs,
+            initrd: [0; MAX_PATH_SIZE],
+            net_retries: DEFAULT_NET_RETRIES,
+            volume_label: [0; MAX_NAME_SIZE],
+            volume_partition_guid: [0; MAX_GUID_SIZE],
+            debug_shell: false,
+            dump_diagnostics: false,
+            video_mode: VideoMode::Keep,
+            mp_probe: false,
+            x2apic: false,
+            persist_settings: true,
+            early_heap_size: DEFAULT_EARLY_HEAP_SIZE,
+            framebuffer_console: true,
+            kernel_stack_size: DEFAULT_KERNEL_STACK_SIZE,
+            rtc: RtcDevice::Auto,
+            pmu_profile: false,
+            problems: ConfigProblems::default(),
         }
     }
 }
 
+/// Stores `value` as a zero-terminated string, truncating if needed.
+pub fn set_zero_terminated(buf: &mut [u8], value: &[u8]) {
+    let len = core::cmp::min(value.len(), buf.len());
+    buf.fill(0);
+    buf[..len].copy_from_slice(&value[..len])
+}
+
+/// The string up to the first zero byte, empty if that is not valid UTF-8.
+pub fn zero_terminated_str(buf: &[u8]) -> &str {
+    let len = buf.iter().position(|&x| x == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).unwrap_or_default()
+}
+
 impl BootLoaderConfig {
     pub fn revision_str(&self) -> &str {
-        let len = self
-            .revision
-            .iter()
-            .position(|&x| x == 0)
-            .unwrap_or(self.revision.len());
-        unsafe { core::str::from_utf8_unchecked(&self.revision[..len]) }
+        zero_terminated_str(&self.revision)
+    }
+
+    pub fn kernel_a_str(&self) -> &str {
+        let kernel_a = zero_terminated_str(&self.kernel_a);
+        if kernel_a.is_empty() {
+            DEFAULT_KERNEL_PATH
+        } else {
+            kernel_a
+        }
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        zero_terminated_str(&self.cmdline)
+    }
+
+    pub fn boot_entry_count(&self) -> usize {
+        self.boot_entries.iter().filter(|e| e.is_used()).count()
+    }
+
+    pub fn kernel_sha256_str(&self) -> Option<&str> {
+        let kernel_sha256 = zero_terminated_str(&self.kernel_sha256);
+        if kernel_sha256.is_empty() {
+            None
+        } else {
+            Some(kernel_sha256)
+        }
+    }
+
+    pub fn initrd_str(&self) -> Option<&str> {
+        let initrd = zero_terminated_str(&self.initrd);
+        if initrd.is_empty() {
+            None
+        } else {
+            Some(initrd)
+        }
+    }
+
+    pub fn volume_label_str(&self) -> Option<&str> {
+        let volume_label = zero_terminated_str(&self.volume_label);
+        if volume_label.is_empty() {
+            None
+        } else {
+            Some(volume_label)
+        }
+    }
+
+    pub fn volume_partition_guid_str(&self) -> Option<&str> {
+        let volume_partition_guid = zero_terminated_str(&self.volume_partition_guid);
+        if volume_partition_guid.is_empty() {
+            None
+        } else {
+            Some(volume_partition_guid)
+        }
+    }
+
+    pub fn chainload_str(&self) -> Option<&str> {
+        let chainload = zero_terminated_str(&self.chainload);
+        if chainload.is_empty() {
+            None
+        } else {
+            Some(chainload)
+        }
+    }
+
+    pub fn kernel_b_str(&self) -> Option<&str> {
+        let kernel_b = zero_terminated_str(&self.kernel_b);
+        if kernel_b.is_empty() {
+            None
+        } else {
+            Some(kernel_b)
+        }
     }
 }
 
@@ -69,6 +447,64 @@ pub enum LogOutput {
     Stdout,
     Com(ComPort),
     Pl(Pl011),
+    Virtio(VirtioConsole),
+}
+
+/// Keeps the most recent log output regardless of the log device.
+struct LogRing {
+    buf: [u8; LOG_RING_SIZE],
+    /// Where the next byte goes.
+    head: usize,
+    wrapped: bool,
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LOG_RING_SIZE],
+            head: 0,
+            wrapped: false,
+        }
+    }
+}
+
+impl Write for LogRing {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for &byte in s.as_bytes() {
+            self.buf[self.head] = byte;
+            self.head += 1;
+            if self.head == self.buf.len() {
+                self.head = 0;
+                self.wrapped = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+static LOG_RING: Spinlock<LogRing> = Spinlock::new(LogRing::new());
+
+/// Calls `f` with the log ring contents from the oldest to the newest,
+/// that takes two slices when the ring has wrapped around.
+pub fn with_log_ring<R>(f: impl FnOnce(&[u8], &[u8]) -> R) -> R {
+    let ring = LOG_RING.lock();
+    if ring.wrapped {
+        f(&ring.buf[ring.head..], &ring.buf[..ring.head])
+    } else {
+        f(&ring.buf[..ring.head], &[])
+    }
+}
+
+/// Where the log ring is, its size, where the next byte goes, and whether
+/// it has wrapped around. Lets the kernel keep the loader log.
+pub fn log_ring_state() -> (u64, usize, usize, bool) {
+    let ring = LOG_RING.lock();
+    (
+        ring.buf.as_ptr() as u64,
+        ring.buf.len(),
+        ring.head,
+        ring.wrapped,
+    )
 }
 
 /// Single-thread logger
@@ -79,7 +515,7 @@ pub struct BootLogger {
 }
 
 impl BootLogger {
-    fn write(&self, output: &mut dyn Write, record: &log::Record) {
+    fn write(&self, output: &mut dyn Write, record: &log::Record, line_end: &str) {
         output
             .write_fmt(format_args!(
                 "[{:7}][{}",
@@ -97,41 +533,50 @@ impl BootLogger {
                 .ok();
         }
         output.write_fmt(format_args!("] {}", record.args())).ok();
-        if matches!(
-            self.output,
-            Some(LogOutput::Com(_)) | Some(LogOutput::Pl(_))
-        ) {
-            output.write_str("\r\n").ok();
-        }
+        output.write_str(line_end).ok();
     }
 }
 
-unsafe impl Send for BootLogger {}
-unsafe impl Sync for BootLogger {}
-
 impl log::Log for BootLogger {
     fn enabled(&self, _metadata: &log::Metadata) -> bool {
         true
     }
 
     fn log(&self, record: &log::Record) {
+        // Not to deadlock when logging while the ring is being read.
+        if let Some(mut ring) = LOG_RING.try_lock() {
+            self.write(&mut *ring, record, "\n");
+        }
+
         match &self.output {
             None => {}
             Some(LogOutput::Stdout) => {
-                if table::system_table_raw().is_some() {
-                    // Boot services are still acive.
+                if boot_services_available() {
                     let stdout =
                         boot::get_handle_for_protocol::<Output>().expect("can get stdout handle");
                     let mut stdout =
                         boot::open_protocol_exclusive::<Output>(stdout).expect("can open stdout");
-                    self.write(&mut *stdout, record);
+                    self.write(&mut *stdout, record, "");
                 }
             }
             Some(LogOutput::Com(mut serial_port)) => {
-                self.write(&mut serial_port, record);
+                self.write(&mut serial_port, record, "\r\n");
             }
             Some(LogOutput::Pl(mut pl011_dev)) => {
-                self.write(&mut pl011_dev, record);
+                self.write(&mut pl011_dev, record, "\r\n");
+            }
+            Some(LogOutput::Virtio(mut virtio_console)) => {
+                self.write(&mut virtio_console, record, "\r\n");
+            }
+        }
+
+        let firmware_console =
+            matches!(self.output, Some(LogOutput::Stdout)) && boot_services_available();
+        if self.output.is_some() && !firmware_console {
+            if let Some(mut console) = FRAMEBUFFER_CONSOLE.try_lock() {
+                if let Some(console) = console.as_mut() {
+                    self.write(console, record, "\n");
+                }
             }
         }
     }
@@ -146,9 +591,32 @@ pub enum LogDevice {
     Com1,
     Com2,
     Pl011(u64),
+    /// The virtio-mmio transport of a console, `0` to look for one in
+    /// the device tree.
+    VirtioConsole(u64),
 }
 
 static BOOT_LOGGER: OnceCell<BootLogger> = OnceCell::uninit();
+static VIRTIO_CONSOLE_QUEUES: Spinlock<VirtioConsoleQueues> =
+    Spinlock::new(VirtioConsoleQueues::new());
+static FRAMEBUFFER_CONSOLE: Spinlock<Option<fbcon::Console<'static>>> = Spinlock::new(None);
+static BOOT_SERVICES_EXITED: AtomicBool = AtomicBool::new(false);
+
+/// Stops using the UEFI console, the boot services have been exited. The
+/// system table stays for the runtime services, so it cannot tell that.
+pub fn boot_services_exited() {
+    BOOT_SERVICES_EXITED.store(true, Ordering::Release);
+}
+
+/// Whether the UEFI console can be used.
+pub fn boot_services_available() -> bool {
+    !BOOT_SERVICES_EXITED.load(Ordering::Acquire) && table::system_table_raw().is_some()
+}
+
+/// Logs to the framebuffer console from now on, too.
+pub fn set_framebuffer_console(console: fbcon::Console<'static>) {
+    *FRAMEBUFFER_CONSOLE.lock() = Some(console);
+}
 
 pub fn setup_logger(config: &BootLoaderConfig) {
     let stdout_logger = || {
@@ -188,6 +656,16 @@ pub fn setup_logger(config: &BootLoaderConfig) {
                     stdout_logger()
                 }
             }
+            LogDevice::VirtioConsole(base_addr) => {
+                // SAFETY: the queues are given to this console only, and
+                // the loader runs identity-mapped.
+                let virtio_console =
+                    unsafe { VirtioConsole::new(base_addr, &VIRTIO_CONSOLE_QUEUES) };
+                match virtio_console {
+                    Ok(virtio_console) => Some(LogOutput::Virtio(virtio_console)),
+                    Err(_) => stdout_logger(),
+                }
+            }
             LogDevice::Null => None,
         };
 
@@ -199,4 +677,71 @@ pub fn setup_logger(config: &BootLoaderConfig) {
 
     log::set_logger(logger).unwrap();
     log::set_max_level(config.log_level);
+
+    if let LogDevice::VirtioConsole(base_addr) = config.log_device {
+        if matches!(logger.output, Some(LogOutput::Stdout)) {
+            log::warn!("No virtio console at {base_addr:#x}, logging to stdout");
+        }
+    }
+}
+
+/// Reads a byte from the serial port used for logging if one has been
+/// received. Lets the headless machines answer the interactive prompts.
+pub fn read_serial_byte() -> Option<u8> {
+    match BOOT_LOGGER.get()?.output.as_ref()? {
+        LogOutput::Com(serial_port) => serial_port.try_receive_byte(),
+        LogOutput::Pl(pl011_dev) => pl011_dev.try_receive_byte(),
+        LogOutput::Virtio(virtio_console) => {
+            let mut virtio_console = *virtio_console;
+            virtio_console.try_receive_byte()
+        }
+        LogOutput::Stdout => None,
+    }
+}
+
+/// Whether the log device is a serial port [`read_serial_byte`] can read.
+pub fn has_serial_input() -> bool {
+    BOOT_LOGGER
+        .get()
+        .and_then(|logger| logger.output.as_ref())
+        .is_some_and(|output| !matches!(output, LogOutput::Stdout))
+}
+
+/// Writes the text to the log device as is, the UEFI console is used if
+/// logging is disabled. Lets the interactive prompts reach the same place
+/// as the log.
+pub fn write_str(s: &str) {
+    let output = BOOT_LOGGER.get().and_then(|logger| logger.output.as_ref());
+    match output {
+        Some(LogOutput::Com(serial_port)) => {
+            let mut serial_port = *serial_port;
+            write_serial(&mut serial_port, s);
+        }
+        Some(LogOutput::Pl(pl011_dev)) => {
+            let mut pl011_dev = *pl011_dev;
+            write_serial(&mut pl011_dev, s);
+        }
+        Some(LogOutput::Virtio(virtio_console)) => {
+            let mut virtio_console = *virtio_console;
+            write_serial(&mut virtio_console, s);
+        }
+        Some(LogOutput::Stdout) | None => {
+            if boot_services_available() {
+                let stdout =
+                    boot::get_handle_for_protocol::<Output>().expect("can get stdout handle");
+                let mut stdout =
+                    boot::open_protocol_exclusive::<Output>(stdout).expect("can open stdout");
+                stdout.write_str(s).ok();
+            }
+        }
+    }
+}
+
+fn write_serial(output: &mut dyn Write, s: &str) {
+    for (i, line) in s.split('\n').enumerate() {
+        if i != 0 {
+            output.write_str("\r\n").ok();
+        }
+        output.write_str(line).ok();
+    }
 }
@@ -0,0 +1,110 @@
+//! The physical and the virtual addresses as distinct types, so that one
+//! cannot be passed where the other is expected, and the translations
+//! between them go through [`crate::linear_map`].
+
+use core::fmt;
+use core::ops::Add;
+use core::ops::Sub;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PhysAddr(u64);
+
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VirtAddr(u64);
+
+impl PhysAddr {
+    pub const fn new(addr: u64) -> Self {
+        Self(addr)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// The number of the 4 KiB page the address is in.
+    pub const fn pfn(self) -> u64 {
+        self.0 / PAGE_SIZE
+    }
+
+    pub const fn from_pfn(pfn: u64) -> Self {
+        Self(pfn * PAGE_SIZE)
+    }
+
+    pub const fn is_page_aligned(self) -> bool {
+        self.0.is_multiple_of(PAGE_SIZE)
+    }
+}
+
+impl VirtAddr {
+    pub const fn new(addr: u64) -> Self {
+        Self(addr)
+    }
+
+    pub fn from_ptr<T>(ptr: *const T) -> Self {
+        Self(ptr as u64)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub const fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    pub const fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+
+    pub const fn is_page_aligned(self) -> bool {
+        self.0.is_multiple_of(PAGE_SIZE)
+    }
+}
+
+macro_rules! address_ops {
+    ($addr:ident) => {
+        impl Add<u64> for $addr {
+            type Output = Self;
+
+            fn add(self, offset: u64) -> Self {
+                Self(self.0 + offset)
+            }
+        }
+
+        impl Sub<u64> for $addr {
+            type Output = Self;
+
+            fn sub(self, offset: u64) -> Self {
+                Self(self.0 - offset)
+            }
+        }
+
+        /// The distance between the addresses.
+        impl Sub for $addr {
+            type Output = u64;
+
+            fn sub(self, other: Self) -> u64 {
+                self.0 - other.0
+            }
+        }
+
+        impl fmt::Debug for $addr {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, concat!(stringify!($addr), "({:#x})"), self.0)
+            }
+        }
+
+        impl fmt::LowerHex for $addr {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::LowerHex::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+address_ops!(PhysAddr);
+address_ops!(VirtAddr);
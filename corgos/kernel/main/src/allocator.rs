@@ -0,0 +1,125 @@
+//! The kernel global allocator.
+//!
+//! The page-sized allocations come from the page allocator through the
+//! linear map, everything else from the heap on the early heap region the
+//! loader has set aside. Until [`init`] runs, all allocations fail.
+
+use crate::address::PhysAddr;
+use crate::address::VirtAddr;
+use crate::boot_info::BootInfo;
+use crate::heap::Heap;
+use crate::linear_map;
+use crate::page_allocator::PageAllocator;
+use crate::page_allocator::PAGE_SIZE;
+use crate::params;
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use page_tables::Table;
+use page_tables::TableAllocator;
+use sync::IrqSpinlock;
+
+struct KernelAllocator {
+    heap: IrqSpinlock<Heap>,
+    pages: IrqSpinlock<Option<PageAllocator>>,
+}
+
+impl KernelAllocator {
+    /// The allocations the heap would waste the most on.
+    fn wants_page(layout: Layout) -> bool {
+        layout.size() > PAGE_SIZE / 2 && layout.size() <= PAGE_SIZE && layout.align() <= PAGE_SIZE
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if Self::wants_page(layout) {
+            if let Some(pages) = self.pages.lock().as_mut() {
+                if let Some(phys) = pages.allocate_page() {
+                    return linear_map::phys_to_virt(phys).as_mut_ptr();
+                }
+            }
+        }
+        self.heap.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut heap = self.heap.lock();
+        if heap.contains(ptr) {
+            // SAFETY: the memory has come from the heap.
+            unsafe { heap.deallocate(ptr, layout) };
+            return;
+        }
+        drop(heap);
+
+        match self.pages.lock().as_mut() {
+            Some(pages) => pages.free_page(linear_map::virt_to_phys(VirtAddr::from_ptr(ptr))),
+            None => panic!("Freeing {ptr:p} that has not been allocated"),
+        }
+    }
+}
+
+// The host tests keep the system allocator.
+#[cfg_attr(not(test), global_allocator)]
+static ALLOCATOR: KernelAllocator = KernelAllocator {
+    heap: IrqSpinlock::new(Heap::empty()),
+    pages: IrqSpinlock::new(None),
+};
+
+/// Calls `f` with the page allocator, none if there is no page allocator.
+pub fn with_page_allocator<R>(f: impl FnOnce(&mut PageAllocator) -> R) -> Option<R> {
+    ALLOCATOR.pages.lock().as_mut().map(f)
+}
+
+/// Allocates the page tables from the page allocator, and writes them
+/// through the linear map.
+pub struct PageTableAllocator;
+
+impl TableAllocator for PageTableAllocator {
+    fn allocate_table(&mut self) -> Option<u64> {
+        let phys = with_page_allocator(PageAllocator::allocate_page)??;
+        self.table(phys.as_u64()).fill(0);
+        Some(phys.as_u64())
+    }
+
+    fn table(&mut self, phys: u64) -> &mut Table {
+        // SAFETY: the linear map covers the memory of the page allocator,
+        // and the page holds a table.
+        unsafe { &mut *linear_map::phys_to_virt(PhysAddr::new(phys)).as_mut_ptr() }
+    }
+}
+
+/// Sets up the page allocator on the page bitmap the loader has handed
+/// over, and the heap on the early heap. Needs [`linear_map::init`] and
+/// [`params::init`] to have run.
+pub fn init(boot_info: &BootInfo) {
+    // SAFETY: the loader has set aside the bitmap storage for the kernel,
+    // and has built the bitmap over the memory map.
+    match unsafe {
+        PageAllocator::adopt(
+            &boot_info.page_bitmap,
+            &boot_info.numa,
+            params::get().mem_limit,
+        )
+    } {
+        Ok(pages) => {
+            log::info!(
+                "Page allocator: {} free pages, bitmap of {:#x} bytes",
+                pages.free_pages(),
+                boot_info.page_bitmap.size
+            );
+            *ALLOCATOR.pages.lock() = Some(pages);
+        }
+        Err(e) => log::error!("No page allocator: {e:?}"),
+    }
+
+    let early_heap = boot_info.early_heap;
+    if early_heap.size == 0 {
+        log::warn!("No early heap, the kernel cannot allocate the small objects");
+        return;
+    }
+    // SAFETY: the loader has set aside the early heap for the kernel alone,
+    // and mapped it read-write.
+    let heap = unsafe { Heap::new(early_heap.virt_base as usize, early_heap.size as usize) };
+    log::info!("Kernel heap: {:#x} bytes", heap.free().0);
+    *ALLOCATOR.heap.lock() = heap;
+}
@@ -0,0 +1,108 @@
+//! What the architecture-specific start code provides to the rest of the
+//! kernel. The kernel is generic over [`Arch`], and the start crate of each
+//! architecture implements it, so a new architecture brings its own start
+//! crate and nothing else forks.
+//!
+//! There is only one processor of each kind in the system, so the
+//! operations are associated functions rather than methods.
+
+use crate::boot_info::BootInfo;
+
+/// The console the early messages go to.
+pub trait Console {
+    fn write_str(s: &str);
+}
+
+pub trait Interrupts {
+    /// Unmasks the interrupts on the current processor.
+    ///
+    /// # Safety
+    ///
+    /// The handlers for the interrupts that may arrive are installed.
+    unsafe fn enable();
+    fn disable();
+    fn are_enabled() -> bool;
+}
+
+/// Why the timer interrupt has not started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The timer frequency is not known, or out of range.
+    NoFrequency,
+    /// The interrupt controller is not found, not mapped, or not usable.
+    NoInterruptController,
+}
+
+/// The free-running counter, its frequency is in [`bootinfo::Timer`], and
+/// the timer interrupt.
+pub trait Timer {
+    fn ticks() -> u64;
+    /// Starts the timer interrupt of the current processor at `hz`, each
+    /// one calls [`crate::time::tick`]. Leaves the interrupts masked.
+    fn start_periodic(boot_info: &BootInfo, hz: u32) -> Result<(), TimerError>;
+}
+
+pub trait Mmu {
+    /// The physical address of the root table the kernel half of the
+    /// address space is translated with.
+    fn root_table() -> u64;
+    /// Invalidates the translations of the page on all the processors.
+    /// There is no TLB shootdown on x86_64, and the kernel changes its
+    /// translations only before [`crate::smp::start_secondary_cpus`].
+    fn flush_page(virt: u64);
+    /// Invalidates all the non-global translations on all the processors,
+    /// with the same restriction as [`Mmu::flush_page`].
+    fn flush_all();
+    /// Writes the byte at `virt` back to itself, and tells whether that
+    /// has not faulted.
+    ///
+    /// # Safety
+    ///
+    /// The address is mapped for reading, and nothing else writes there.
+    unsafe fn is_writable(virt: u64) -> bool;
+}
+
+/// Why a secondary processor has not started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartCpuError {
+    /// The boot info has no way to start the secondary processors.
+    NoEnableMethod,
+    /// No memory for the stack or the startup data.
+    OutOfMemory,
+    /// The firmware or the interrupt controller has refused.
+    Refused,
+    /// The processor has not reported in time.
+    NoResponse,
+}
+
+pub trait Arch {
+    const NAME: &'static str;
+
+    type Console: Console;
+    type Interrupts: Interrupts;
+    type Timer: Timer;
+    type Mmu: Mmu;
+
+    /// Sets up the per-CPU data of all the processors, and installs the
+    /// one of the boot processor. Runs once the allocator is ready.
+    fn init_per_cpu(boot_info: &BootInfo);
+    /// Starts the secondary processor at the index into the boot info
+    /// ones, on the stack at `stack_top`. The processor enters
+    /// [`crate::smp::secondary_main`], the start code of the architecture
+    /// has set up its translations to be the same as of the caller.
+    fn start_cpu(boot_info: &BootInfo, cpu_id: u32, stack_top: u64) -> Result<(), StartCpuError>;
+    /// Installs the per-CPU data and the exception handlers of the
+    /// secondary processor it runs on.
+    fn init_secondary_cpu(cpu_id: u32);
+    /// The index of the current processor into the boot info ones.
+    fn cpu_id() -> u32;
+    /// Stops the processor until an interrupt or another wakeup, see
+    /// [`crate::idle`] for the loops around it.
+    fn halt();
+    /// Asks QEMU to exit with the success or the failure code, for the
+    /// integration tests. Returns if not running under QEMU.
+    fn exit_qemu(success: bool);
+    /// The time the real-time clock in the boot info reads, `None` if
+    /// there is none the architecture has, or it cannot be read.
+    fn read_rtc(boot_info: &BootInfo) -> Option<rtc::DateTime>;
+}
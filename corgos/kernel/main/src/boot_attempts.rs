@@ -0,0 +1,105 @@
+//! Tells the loader the kernel has booted. The loader counts the boot
+//! attempts of the primary kernel in the `CorgOsBootAttempts` UEFI variable,
+//! and falls back to another kernel and then to the recovery entry once
+//! there have been too many. The kernel deletes the variable through the
+//! runtime services when it has initialized, so only the boots that fail
+//! before that count.
+
+use crate::boot_info::BootInfo;
+use bootinfo::EFI_RT_SUPPORTED_SET_VARIABLE;
+
+/// The vendor GUID of the CorgOS variables, the same as the loader's
+/// `CORGOS_VARIABLE_VENDOR`, in the `EFI_GUID` layout.
+const CORGOS_VARIABLE_VENDOR: [u8; 16] = [
+    0x07, 0xb0, 0xa6, 0xc0, 0x1d, 0x5e, 0x3b, 0x4a, 0x8f, 0x2c, 0x6b, 0x0d, 0x1e, 0x2f, 0x3a, 0x4b,
+];
+
+const BOOT_ATTEMPTS_VARIABLE: [u16; 19] = ucs2("CorgOsBootAttempts");
+
+const EFI_NOT_FOUND: usize = 1 << (usize::BITS - 1) | 14;
+
+/// The zero-terminated UCS-2 of the ASCII string.
+const fn ucs2<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    assert!(bytes.len() + 1 == N);
+    let mut name = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii());
+        name[i] = bytes[i] as u16;
+        i += 1;
+    }
+    name
+}
+
+type SetVariable = unsafe extern "efiapi" fn(
+    name: *const u16,
+    vendor: *const [u8; 16],
+    attributes: u32,
+    data_size: usize,
+    data: *const u8,
+) -> usize;
+
+/// `EFI_RUNTIME_SERVICES` up to `SetVariable`.
+#[repr(C)]
+struct RuntimeServices {
+    header: [u8; 24],
+    /// From `GetTime` to `GetNextVariableName`.
+    services: [usize; 8],
+    set_variable: SetVariable,
+}
+
+/// Resets the boot attempt counter, unless the firmware cannot set the
+/// variables after exiting the boot services.
+pub fn mark_booted(boot_info: &BootInfo) {
+    let efi = boot_info.efi;
+    if efi.runtime_services == 0 || !efi.is_supported(EFI_RT_SUPPORTED_SET_VARIABLE) {
+        log::warn!("No SetVariable, the boot attempts are not reset");
+        return;
+    }
+
+    // SAFETY: the loader has switched the runtime services to the virtual
+    // addresses in the runtime window of the kernel page tables, and the
+    // kernel makes no other runtime service calls. The variable is deleted
+    // with no attributes and no data.
+    let status = unsafe {
+        let runtime_services = &*(efi.runtime_services as *const RuntimeServices);
+        (runtime_services.set_variable)(
+            BOOT_ATTEMPTS_VARIABLE.as_ptr(),
+            &CORGOS_VARIABLE_VENDOR,
+            0,
+            0,
+            core::ptr::null(),
+        )
+    };
+    match status {
+        0 | EFI_NOT_FOUND => log::info!("Reset the boot attempts"),
+        status => log::warn!("Cannot reset the boot attempts: status {status:#x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendor_guid_layout() {
+        let digits = "c0a6b007-5e1d-4a3b-8f2c-6b0d1e2f3a4b".replace('-', "");
+        let hex: [u8; 16] =
+            core::array::from_fn(|i| u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).unwrap());
+
+        let mut expected = hex;
+        expected[..4].reverse();
+        expected[4..6].reverse();
+        expected[6..8].reverse();
+        assert_eq!(CORGOS_VARIABLE_VENDOR, expected);
+    }
+
+    #[test]
+    fn variable_name() {
+        let name: [u16; 4] = ucs2("Abc");
+        assert_eq!(name, [b'A' as u16, b'b' as u16, b'c' as u16, 0]);
+        assert_eq!(BOOT_ATTEMPTS_VARIABLE[0], b'C' as u16);
+        assert_eq!(BOOT_ATTEMPTS_VARIABLE[18], 0);
+    }
+}
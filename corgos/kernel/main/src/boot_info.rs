@@ -0,0 +1,75 @@
+//! The boot info the loader passes to the kernel.
+//!
+//! The loader leaves the boot info in the memory of its own type in the
+//! memory map, so it stays valid until the kernel reclaims that memory.
+//! The kernel checks it before looking at anything else in it.
+
+use bootinfo::BootInfoError;
+use bootinfo::Cpus;
+use bootinfo::KernelImage;
+use bootinfo::Modules;
+use core::ops::Deref;
+
+/// The validated boot info, borrowed for as long as its memory is not
+/// reclaimed.
+#[derive(Clone, Copy)]
+pub struct BootInfo<'a> {
+    info: &'a bootinfo::BootInfo,
+}
+
+impl<'a> BootInfo<'a> {
+    /// # Safety
+    ///
+    /// The pointer is the one the loader has passed, and the memory is
+    /// mapped and not reclaimed for `'a`.
+    pub unsafe fn from_ptr(ptr: *const bootinfo::BootInfo) -> Result<Self, BootInfoError> {
+        if ptr.is_null() {
+            return Err(BootInfoError::Null);
+        }
+        if !ptr.is_aligned() {
+            return Err(BootInfoError::Misaligned(ptr as u64));
+        }
+
+        // SAFETY: checked the magic before trusting the size, the rest is
+        // guaranteed by the caller.
+        let magic = unsafe { core::ptr::addr_of!((*ptr).magic).read() };
+        if magic != bootinfo::BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(magic));
+        }
+        // SAFETY: guaranteed by the caller, the bytes are checked before
+        // they are taken for the structure.
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                ptr.cast::<u8>(),
+                core::mem::size_of::<bootinfo::BootInfo>(),
+            )
+        };
+        let info = bootinfo::BootInfo::from_bytes(bytes)?;
+
+        Ok(Self { info })
+    }
+
+    pub fn kernel(&self) -> &'a KernelImage {
+        &self.info.kernel
+    }
+
+    pub fn cpus(&self) -> &'a Cpus {
+        &self.info.cpus
+    }
+
+    pub fn modules(&self) -> &'a Modules {
+        &self.info.modules
+    }
+
+    pub fn raw(&self) -> &'a bootinfo::BootInfo {
+        self.info
+    }
+}
+
+impl Deref for BootInfo<'_> {
+    type Target = bootinfo::BootInfo;
+
+    fn deref(&self) -> &Self::Target {
+        self.info
+    }
+}
@@ -0,0 +1,242 @@
+//! The heap for the small objects: a first-fit free list sorted by the
+//! address, the adjacent free blocks are merged on free.
+//!
+//! The blocks are multiples of [`BLOCK_ALIGN`] at the addresses aligned to
+//! it, so splitting a block never leaves a piece too small to track.
+
+use core::alloc::Layout;
+
+/// Fits a [`FreeBlock`].
+pub const BLOCK_ALIGN: usize = 16;
+
+#[repr(C)]
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const _: () = assert!(core::mem::size_of::<FreeBlock>() <= BLOCK_ALIGN);
+
+pub struct Heap {
+    /// The lowest free block.
+    head: *mut FreeBlock,
+    start: usize,
+    end: usize,
+}
+
+// SAFETY: the heap owns its memory, and is used under a lock.
+unsafe impl Send for Heap {}
+
+impl Heap {
+    pub const fn empty() -> Self {
+        Self {
+            head: core::ptr::null_mut(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The memory is mapped read-write, and not used by anything else.
+    pub unsafe fn new(start: usize, size: usize) -> Self {
+        let end = (start + size) & !(BLOCK_ALIGN - 1);
+        let start = start.next_multiple_of(BLOCK_ALIGN);
+        let mut heap = Self {
+            head: core::ptr::null_mut(),
+            start,
+            end,
+        };
+        if start < end {
+            // SAFETY: guaranteed by the caller.
+            unsafe { heap.insert(start, end - start) };
+        }
+        heap
+    }
+
+    pub fn contains(&self, ptr: *mut u8) -> bool {
+        (self.start..self.end).contains(&(ptr as usize))
+    }
+
+    fn block_layout(layout: Layout) -> (usize, usize) {
+        (
+            layout.size().max(1).next_multiple_of(BLOCK_ALIGN),
+            layout.align().max(BLOCK_ALIGN),
+        )
+    }
+
+    /// Links the free block in, merging it with the neighbors.
+    ///
+    /// # Safety
+    ///
+    /// The block is in the heap, aligned, and not in the list.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = core::ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            // SAFETY: the list has only the free blocks of the heap.
+            next = unsafe { (*next).next };
+        }
+
+        let block = addr as *mut FreeBlock;
+        // SAFETY: guaranteed by the caller, and the list has only the free
+        // blocks of the heap.
+        unsafe {
+            block.write(FreeBlock { size, next });
+            if !next.is_null() && addr + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+            if prev.is_null() {
+                self.head = block;
+            } else if prev as usize + (*prev).size == addr {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::block_layout(layout);
+
+        let mut prev: *mut FreeBlock = core::ptr::null_mut();
+        let mut block = self.head;
+        while !block.is_null() {
+            // SAFETY: the list has only the free blocks of the heap.
+            let FreeBlock {
+                size: block_size,
+                next,
+            } = unsafe { block.read() };
+            let block_start = block as usize;
+            let block_end = block_start + block_size;
+            let start = block_start.next_multiple_of(align);
+
+            if start + size <= block_end {
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    // SAFETY: as above.
+                    unsafe { (*prev).next = next };
+                }
+                // SAFETY: the pieces around the allocation are free, and
+                // aligned as both the block and the size are.
+                unsafe {
+                    if start > block_start {
+                        self.insert(block_start, start - block_start);
+                    }
+                    if start + size < block_end {
+                        self.insert(start + size, block_end - start - size);
+                    }
+                }
+                return start as *mut u8;
+            }
+
+            prev = block;
+            block = next;
+        }
+
+        core::ptr::null_mut()
+    }
+
+    /// # Safety
+    ///
+    /// The memory has been allocated from this heap with the same layout.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::block_layout(layout);
+        // SAFETY: guaranteed by the caller.
+        unsafe { self.insert(ptr as usize, size) };
+    }
+
+    /// The free bytes, and the largest free block.
+    pub fn free(&self) -> (usize, usize) {
+        let mut total = 0;
+        let mut largest = 0;
+        let mut block = self.head;
+        while !block.is_null() {
+            // SAFETY: the list has only the free blocks of the heap.
+            let FreeBlock { size, next } = unsafe { block.read() };
+            total += size;
+            largest = largest.max(size);
+            block = next;
+        }
+        (total, largest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C, align(4096))]
+    struct Memory([u8; 4096]);
+
+    fn heap(memory: &mut Memory) -> Heap {
+        // SAFETY: the test owns the memory.
+        unsafe { Heap::new(memory.0.as_mut_ptr() as usize, memory.0.len()) }
+    }
+
+    #[test]
+    fn alloc_free() {
+        let mut memory = Memory([0; 4096]);
+        let mut heap = heap(&mut memory);
+        assert_eq!(heap.free(), (4096, 4096));
+
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let aligned = Layout::from_size_align(100, 256).unwrap();
+        let a = heap.allocate(small);
+        let b = heap.allocate(aligned);
+        let c = heap.allocate(small);
+        assert!([a, b, c].iter().all(|p| heap.contains(*p)));
+        assert_eq!(b as usize % 256, 0);
+        assert_eq!(heap.free().0, 4096 - 32 - 112 - 32);
+
+        // SAFETY: allocated above with these layouts.
+        unsafe {
+            heap.deallocate(b, aligned);
+            heap.deallocate(a, small);
+            heap.deallocate(c, small);
+        }
+        assert_eq!(heap.free(), (4096, 4096));
+
+        assert!(heap
+            .allocate(Layout::from_size_align(4097, 8).unwrap())
+            .is_null());
+        assert!(!heap
+            .allocate(Layout::from_size_align(4096, 8).unwrap())
+            .is_null());
+        assert!(heap.allocate(small).is_null());
+    }
+
+    #[test]
+    fn realloc() {
+        let mut memory = Memory([0; 4096]);
+        let mut heap = heap(&mut memory);
+
+        // As `GlobalAlloc::realloc` does: allocate, copy and free.
+        let old_layout = Layout::from_size_align(32, 8).unwrap();
+        let old = heap.allocate(old_layout);
+        let pinned = heap.allocate(old_layout);
+        // SAFETY: the blocks are allocated and do not overlap.
+        unsafe {
+            old.write_bytes(0xa5, 32);
+            let new_layout = Layout::from_size_align(512, 8).unwrap();
+            let new = heap.allocate(new_layout);
+            assert!(!new.is_null());
+            new.copy_from_nonoverlapping(old, 32);
+            heap.deallocate(old, old_layout);
+            assert!(core::slice::from_raw_parts(new, 32)
+                .iter()
+                .all(|&b| b == 0xa5));
+
+            // The freed block is reused first.
+            assert_eq!(heap.allocate(old_layout), old);
+            heap.deallocate(old, old_layout);
+            heap.deallocate(new, new_layout);
+            heap.deallocate(pinned, old_layout);
+        }
+        assert_eq!(heap.free(), (4096, 4096));
+    }
+}
@@ -0,0 +1,69 @@
+//! What the processors do with nothing to run: [`idle`] sleeps until the
+//! next interrupt, over and over, and counts the wakeups and the time
+//! spent asleep; [`park_cpu`] masks the interrupts and sleeps for good,
+//! after a panic or a fatal exception.
+//!
+//! Both halt the processor rather than spin, so an idle or a dead virtual
+//! processor does not keep a host core busy.
+
+use crate::arch::Arch;
+use crate::arch::Interrupts;
+use crate::arch::Timer;
+use bootinfo::MAX_CPUS;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+/// The accounting of one processor, only it writes there.
+struct IdleCounters {
+    wakeups: AtomicU64,
+    idle_ticks: AtomicU64,
+}
+
+static COUNTERS: [IdleCounters; MAX_CPUS] = [const {
+    IdleCounters {
+        wakeups: AtomicU64::new(0),
+        idle_ticks: AtomicU64::new(0),
+    }
+}; MAX_CPUS];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdleStats {
+    /// How many times the processor has woken up in the idle loop.
+    pub wakeups: u64,
+    /// The timer ticks spent halted in the idle loop.
+    pub idle_ticks: u64,
+}
+
+pub fn stats(cpu_id: u32) -> IdleStats {
+    COUNTERS
+        .get(cpu_id as usize)
+        .map(|counters| IdleStats {
+            wakeups: counters.wakeups.load(Ordering::Relaxed),
+            idle_ticks: counters.idle_ticks.load(Ordering::Relaxed),
+        })
+        .unwrap_or_default()
+}
+
+/// The idle loop of the current processor. Needs the per-CPU data.
+pub fn idle<A: Arch>() -> ! {
+    let counters = &COUNTERS[A::cpu_id() as usize];
+    loop {
+        let start = A::Timer::ticks();
+        A::halt();
+        let end = A::Timer::ticks();
+
+        counters.wakeups.fetch_add(1, Ordering::Relaxed);
+        counters
+            .idle_ticks
+            .fetch_add(end.wrapping_sub(start), Ordering::Relaxed);
+    }
+}
+
+/// Stops the current processor for good. Does not touch anything but the
+/// processor, so works at any point of the boot.
+pub fn park_cpu<A: Arch>() -> ! {
+    A::Interrupts::disable();
+    loop {
+        A::halt();
+    }
+}
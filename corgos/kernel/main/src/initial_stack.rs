@@ -0,0 +1,45 @@
+//! How much of the initial stack the boot processor uses.
+//!
+//! The start crate paints the stack it has entered on with [`paint`]
+//! before anything else runs deep, and [`report`] logs the high-water mark
+//! once the kernel has initialized, see the `stack_usage` crate. The panic
+//! handler asks [`guard_intact`] whether the stack has reached its bottom.
+
+use core::ops::Range;
+use stack_usage::PaintedStack;
+use sync::OnceCell;
+
+static STACK: OnceCell<PaintedStack> = OnceCell::uninit();
+
+/// Paints the stack the boot processor runs on.
+///
+/// # Safety
+///
+/// The stack is mapped, and the boot processor is running on it.
+pub unsafe fn paint(stack: Range<u64>) {
+    // SAFETY: guaranteed by the caller.
+    let painted = unsafe { PaintedStack::paint(stack.start, stack.end) };
+    let _ = STACK.set(painted);
+}
+
+/// Logs the high-water mark of the initial stack.
+pub fn report() {
+    let Some(stack) = STACK.get() else {
+        return;
+    };
+    log::info!(
+        "Initial stack @ {:#016x}..{:#016x}: {}",
+        stack.bottom(),
+        stack.top(),
+        stack.usage()
+    );
+    if !stack.guard_intact() {
+        log::warn!("Initial stack guard is overwritten, the stack may have overflowed");
+    }
+}
+
+/// Whether the guard at the bottom of the initial stack is there, `None`
+/// if the stack is not painted.
+pub fn guard_intact() -> Option<bool> {
+    STACK.get().map(PaintedStack::guard_intact)
+}
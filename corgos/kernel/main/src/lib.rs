@@ -0,0 +1,86 @@
+//! The architecture-independent part of the kernel. The start crate of the
+//! architecture sets up enough to run Rust code, validates the boot info,
+//! and calls [`kernel_main`] with its [`Arch`] implementation.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod address;
+pub mod allocator;
+pub mod arch;
+pub mod boot_attempts;
+pub mod boot_info;
+mod heap;
+pub mod idle;
+pub mod initial_stack;
+pub mod linear_map;
+pub mod logger;
+pub mod page_allocator;
+pub mod params;
+pub mod pci;
+pub mod self_test;
+pub mod smp;
+pub mod time;
+pub mod tpm;
+pub mod wall_clock;
+
+pub use address::PhysAddr;
+pub use address::VirtAddr;
+pub use arch::Arch;
+pub use boot_info::BootInfo;
+
+use arch::Interrupts;
+use arch::Mmu;
+use arch::Timer;
+
+pub fn kernel_main<A: Arch>(boot_info: BootInfo<'static>) -> ! {
+    log::info!(
+        "Kernel on {} at {:#016x}, boot info version {}",
+        A::NAME,
+        boot_info.kernel().virt_base,
+        boot_info.version
+    );
+    log::debug!("{:?}", params::get());
+
+    linear_map::init(&boot_info);
+    allocator::init(&boot_info);
+    A::init_per_cpu(&boot_info);
+    logger::init_per_cpu::<A>();
+
+    log::info!(
+        "Processor {}, interrupts {}, root table {:#016x}",
+        A::cpu_id(),
+        if A::Interrupts::are_enabled() {
+            "enabled"
+        } else {
+            "masked"
+        },
+        A::Mmu::root_table()
+    );
+    // In 128 bits, the milliticks of a 1 GHz counter overflow 64 bits in
+    // about 200 days.
+    let ticks = u128::from(A::Timer::ticks());
+    if let Some(ms) = (ticks * 1000).checked_div(boot_info.timer.frequency.into()) {
+        log::info!("{ms} ms since the reset");
+    }
+
+    time::init::<A>(&boot_info);
+    wall_clock::init::<A>(&boot_info);
+    tpm::init(&boot_info);
+    pci::init(&boot_info);
+
+    let self_test_passed = !params::get().self_test || self_test::run::<A>();
+    smp::start_secondary_cpus::<A>(&boot_info);
+    initial_stack::report();
+    if self_test_passed {
+        boot_attempts::mark_booted(&boot_info);
+    }
+
+    if params::get().qemu_exit {
+        log::info!("Kernel initialized, exiting QEMU");
+        A::exit_qemu(self_test_passed);
+    }
+    log::info!("Kernel initialized, idling");
+    idle::idle::<A>()
+}
@@ -0,0 +1,89 @@
+//! The linear map of the physical memory the loader has set up: all the
+//! memory from the memory map is mapped at the same offset in the higher
+//! half, so the kernel reaches any physical page without mapping it.
+//!
+//! The debug builds check the translated addresses against the window
+//! and the memory map, catching the physical addresses that are garbage or
+//! were never mapped.
+
+use crate::address::PhysAddr;
+use crate::address::VirtAddr;
+use crate::boot_info::BootInfo;
+use bootinfo::MemoryRegion;
+
+struct LinearMap {
+    base: u64,
+    size: u64,
+    regions: &'static [MemoryRegion],
+}
+
+static mut LINEAR_MAP: LinearMap = LinearMap {
+    base: 0,
+    size: 0,
+    regions: &[],
+};
+
+fn linear_map() -> &'static LinearMap {
+    // SAFETY: written once by `init` before the other processors start.
+    unsafe { &*core::ptr::addr_of!(LINEAR_MAP) }
+}
+
+/// Takes the window from the boot info, and the memory map through it.
+pub fn init(boot_info: &BootInfo<'static>) {
+    let paging = boot_info.paging;
+    let memory_map = boot_info.memory_map;
+    let regions = if memory_map.count == 0 {
+        &[]
+    } else {
+        // SAFETY: the loader has placed the memory map in the memory the
+        // linear map covers, and it stays there for as long as the boot
+        // info.
+        unsafe {
+            core::slice::from_raw_parts(
+                (paging.linear_map_base + memory_map.regions) as *const MemoryRegion,
+                memory_map.count as usize,
+            )
+        }
+    };
+
+    // SAFETY: only the boot processor runs.
+    unsafe {
+        *core::ptr::addr_of_mut!(LINEAR_MAP) = LinearMap {
+            base: paging.linear_map_base,
+            size: paging.linear_map_size,
+            regions,
+        }
+    };
+}
+
+/// The memory map the loader has handed over, read through the linear map.
+pub fn memory_map() -> &'static [MemoryRegion] {
+    linear_map().regions
+}
+
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    let map = linear_map();
+    debug_assert!(map.base != 0, "The linear map is not set up");
+    debug_assert!(
+        phys.as_u64() < map.size,
+        "{phys:?} is outside of the linear map"
+    );
+    debug_assert!(
+        map.regions.is_empty()
+            || map
+                .regions
+                .iter()
+                .any(|region| (region.start..region.end()).contains(&phys.as_u64())),
+        "{phys:?} is not in the memory map"
+    );
+    VirtAddr::new(map.base + phys.as_u64())
+}
+
+pub fn virt_to_phys(virt: VirtAddr) -> PhysAddr {
+    let map = linear_map();
+    debug_assert!(
+        (map.base..map.base + map.size).contains(&virt.as_u64()),
+        "{virt:?} is outside of the linear map"
+    );
+    PhysAddr::new(virt.as_u64() - map.base)
+}
@@ -0,0 +1,224 @@
+//! The kernel logger.
+//!
+//! Mirrors `boot_logger` so the log reads the same across the handoff: the
+//! records are formatted the same way, and go to the serial console of the
+//! architecture and to an in-memory ring that starts with what the loader
+//! has logged.
+//!
+//! Each processor formats the record into a line buffer of its own, and
+//! writes the whole line to the sinks under their locks, so the lines of
+//! the processors do not interleave. The locks mask the interrupts, so
+//! only a record logged from an exception on the same processor finds the
+//! line buffer busy, and goes to the sinks unbuffered. The sinks may be
+//! busy then too, and the record is dropped from them rather than waited
+//! for.
+//!
+//! The level is the `log_level` kernel parameter, see [`crate::params`].
+
+use crate::arch::Arch;
+use crate::arch::Console;
+use crate::boot_info::BootInfo;
+use crate::params;
+use bootinfo::MAX_CPUS;
+use core::fmt::Write;
+use sync::IrqSpinlock;
+
+/// The same as the loader has.
+const LOG_RING_SIZE: usize = 0x10000;
+/// The longer lines are cut.
+const LINE_SIZE: usize = 256;
+
+/// Keeps the most recent log output.
+struct LogRing {
+    buf: [u8; LOG_RING_SIZE],
+    /// Where the next byte goes.
+    head: usize,
+    wrapped: bool,
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LOG_RING_SIZE],
+            head: 0,
+            wrapped: false,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buf[self.head] = byte;
+            self.head += 1;
+            if self.head == self.buf.len() {
+                self.head = 0;
+                self.wrapped = true;
+            }
+        }
+    }
+}
+
+impl Write for LogRing {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+struct LineBuffer {
+    buf: [u8; LINE_SIZE],
+    len: usize,
+}
+
+impl LineBuffer {
+    fn as_str(&self) -> &str {
+        let line = &self.buf[..self.len];
+        // A cut line may end in the middle of a character.
+        match core::str::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => core::str::from_utf8(&line[..e.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+}
+
+impl Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = s.len().min(LINE_SIZE - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Adapts the console of the architecture to `core::fmt::Write`.
+struct ConsoleSink(fn(&str));
+
+impl Write for ConsoleSink {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+static LOG_RING: IrqSpinlock<LogRing> = IrqSpinlock::new(LogRing::new());
+static CONSOLE: IrqSpinlock<Option<ConsoleSink>> = IrqSpinlock::new(None);
+static LINES: [IrqSpinlock<LineBuffer>; MAX_CPUS] = [const {
+    IrqSpinlock::new(LineBuffer {
+        buf: [0; LINE_SIZE],
+        len: 0,
+    })
+}; MAX_CPUS];
+
+/// Only the boot processor runs until [`init_per_cpu`].
+static mut CPU_ID: fn() -> u32 = || 0;
+
+fn cpu_id() -> u32 {
+    // SAFETY: written once before the other processors start.
+    let cpu_id = unsafe { *core::ptr::addr_of!(CPU_ID) };
+    cpu_id()
+}
+
+/// Calls `f` with the log ring contents from the oldest to the newest,
+/// that takes two slices when the ring has wrapped around.
+pub fn with_log_ring<R>(f: impl FnOnce(&[u8], &[u8]) -> R) -> R {
+    let ring = LOG_RING.lock();
+    if ring.wrapped {
+        f(&ring.buf[ring.head..], &ring.buf[..ring.head])
+    } else {
+        f(&ring.buf[..ring.head], &[])
+    }
+}
+
+struct KernelLogger;
+
+impl KernelLogger {
+    fn write(&self, output: &mut dyn Write, record: &log::Record, line_end: &str) {
+        output
+            .write_fmt(format_args!(
+                "[{:7}][{}] {}",
+                record.level(),
+                record.module_path().unwrap_or_default(),
+                record.args()
+            ))
+            .ok();
+        output.write_str(line_end).ok();
+    }
+
+    fn write_line(&self, line: &str) {
+        // Not to deadlock when logging while the ring is being read.
+        if let Some(mut ring) = LOG_RING.try_lock() {
+            ring.write_bytes(line.as_bytes());
+            ring.write_bytes(b"\n");
+        }
+        if let Some(console) = CONSOLE.lock().as_mut() {
+            console.write_str(line).ok();
+            console.write_str("\r\n").ok();
+        }
+    }
+}
+
+impl log::Log for KernelLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match LINES.get(cpu_id() as usize).and_then(IrqSpinlock::try_lock) {
+            Some(mut line) => {
+                line.len = 0;
+                self.write(&mut *line, record, "");
+                self.write_line(line.as_str());
+            }
+            None => {
+                if let Some(mut ring) = LOG_RING.try_lock() {
+                    self.write(&mut *ring, record, "\n");
+                }
+                // The exception may have come in the middle of writing to
+                // the console, waiting for it would never end.
+                if let Some(mut console) = CONSOLE.try_lock() {
+                    if let Some(console) = console.as_mut() {
+                        self.write(console, record, "\r\n");
+                    }
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: KernelLogger = KernelLogger;
+
+/// Keeps the loader log in the ring, and sends the `log` records to the
+/// console of the architecture and the ring. Needs [`params::init`] to have
+/// run.
+pub fn init<A: Arch>(boot_info: &BootInfo) {
+    let loader_log = boot_info.loader_log;
+    // SAFETY: the loader log is in the memory the linear map covers, and
+    // nothing has reclaimed the loader memory yet.
+    let (older, newer) = unsafe { loader_log.contents() };
+    {
+        let mut ring = LOG_RING.lock();
+        ring.write_bytes(older);
+        ring.write_bytes(newer);
+    }
+    *CONSOLE.lock() = Some(ConsoleSink(A::Console::write_str));
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(params::get().log_level);
+    }
+    params::log_problems(boot_info);
+    log::debug!(
+        "Kept {:#x} bytes of the loader log",
+        older.len() + newer.len()
+    );
+}
+
+/// Switches the line buffers to the per-CPU ones.
+pub fn init_per_cpu<A: Arch>() {
+    // SAFETY: only the boot processor runs.
+    unsafe { *core::ptr::addr_of_mut!(CPU_ID) = A::cpu_id };
+}
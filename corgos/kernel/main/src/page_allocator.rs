@@ -0,0 +1,305 @@
+//! The physical page allocator: the page bitmap the loader has built over
+//! the memory map, and handed over.
+//!
+//! The kernel takes the bitmap over where the linear map has its storage,
+//! after checking the checksum, so the memory the loader has marked as
+//! allocated is never handed out. The pages above what the bitmap tracks
+//! are left alone.
+//!
+//! The bitmap has a domain per NUMA node, with the memory between the nodes
+//! in the SRAT going to the node below it. A page is taken from the node
+//! asked for first, then from the others.
+
+use crate::address;
+use crate::address::PhysAddr;
+use crate::linear_map;
+use bootinfo::Numa;
+use bootinfo::PageBitmapStorage;
+use bootinfo::MAX_NUMA_NODES;
+use page_bitmap::page_bitmap_storage_size;
+use page_bitmap::PageBitMapError;
+use page_bitmap::PageBitmap;
+use page_bitmap::PageBitmapDomain;
+use page_bitmap::MAX_MEMORY_SUPPORTED_BYTES;
+
+pub const PAGE_SIZE: usize = address::PAGE_SIZE as usize;
+
+/// The domain of each NUMA node, the ones past the node count are empty.
+static mut DOMAINS: [PageBitmapDomain; MAX_NUMA_NODES] = [PageBitmapDomain {
+    start_pfn: 0,
+    end_pfn: 0,
+}; MAX_NUMA_NODES];
+
+#[derive(Debug, Clone, Copy)]
+pub enum PageAllocatorError {
+    /// The loader has handed over no bitmap.
+    NoBitmap,
+    /// The tracked memory is not whole pages, or is too large.
+    BadMaxMemory(u64),
+    /// The storage is not of the size for the tracked memory.
+    BadSize(u64),
+    /// The CRC32 of the storage is not the one the loader has computed.
+    BadChecksum {
+        computed: u32,
+        expected: u32,
+    },
+    Bitmap(PageBitMapError),
+    /// The bitmap and the count of the free pages disagree.
+    FreePages {
+        counted: usize,
+        expected: usize,
+    },
+}
+
+pub struct PageAllocator {
+    bitmap: PageBitmap<'static>,
+    free_pages: usize,
+}
+
+impl PageAllocator {
+    /// Takes over the bitmap the loader has handed over, splits it into
+    /// the NUMA nodes, and allocates the memory above `limit` in it so that
+    /// it is never used.
+    ///
+    /// # Safety
+    ///
+    /// The linear map covers the storage, nothing else uses it, and the
+    /// pages free in the bitmap are free.
+    pub unsafe fn adopt(
+        storage: &PageBitmapStorage,
+        numa: &Numa,
+        limit: Option<u64>,
+    ) -> Result<Self, PageAllocatorError> {
+        if storage.size == 0 {
+            return Err(PageAllocatorError::NoBitmap);
+        }
+        let max_memory = storage.max_memory;
+        if max_memory == 0
+            || max_memory > MAX_MEMORY_SUPPORTED_BYTES as u64
+            || !max_memory.is_multiple_of(address::PAGE_SIZE)
+        {
+            return Err(PageAllocatorError::BadMaxMemory(max_memory));
+        }
+        let max_memory = max_memory as usize;
+        if storage.size != page_bitmap_storage_size(max_memory) as u64 {
+            return Err(PageAllocatorError::BadSize(storage.size));
+        }
+
+        let base = linear_map::phys_to_virt(PhysAddr::new(storage.phys_base)).as_mut_ptr::<u8>();
+        // SAFETY: guaranteed by the caller, the size is checked.
+        let bytes = unsafe { core::slice::from_raw_parts(base, storage.size as usize) };
+        let computed = digest::crc32(bytes);
+        if computed != storage.checksum {
+            return Err(PageAllocatorError::BadChecksum {
+                computed,
+                expected: storage.checksum,
+            });
+        }
+
+        // SAFETY: the storage holds the bitmap for `max_memory`, as the
+        // size and the checksum say, the rest is guaranteed by the caller.
+        let mut bitmap = unsafe { PageBitmap::from_ptr(base, max_memory) };
+        bitmap.check().map_err(PageAllocatorError::Bitmap)?;
+        let max_pfn = max_memory / PAGE_SIZE;
+        let mut free_pages = max_pfn - bitmap.allocated_pages(0, max_pfn);
+
+        if let Some(limit) = limit.filter(|&limit| limit < max_memory as u64) {
+            log::info!("Not using the memory above {limit:#x}");
+            let limit_pfn = (limit / address::PAGE_SIZE) as usize;
+            for pfn in limit_pfn..max_pfn {
+                if bitmap.allocate_page(pfn).is_ok() {
+                    free_pages -= 1;
+                }
+            }
+        }
+
+        let node_domains = node_domains(numa, max_pfn).unwrap_or_else(|| {
+            log::warn!("The NUMA nodes interleave, the memory is one domain");
+            let mut domains = [PageBitmapDomain {
+                start_pfn: 0,
+                end_pfn: 0,
+            }; MAX_NUMA_NODES];
+            domains[0].end_pfn = max_pfn;
+            domains
+        });
+        // SAFETY: the allocator is built once, before anything reads the
+        // domains.
+        let domains = unsafe {
+            let domains = &mut *core::ptr::addr_of_mut!(DOMAINS);
+            *domains = node_domains;
+            &domains[..(numa.node_count as usize).clamp(1, MAX_NUMA_NODES)]
+        };
+        for (node, domain) in domains.iter().enumerate() {
+            log::info!(
+                "NUMA node {node}: pages {:#x}..{:#x}",
+                domain.start_pfn,
+                domain.end_pfn
+            );
+        }
+        bitmap.set_domains(domains);
+
+        Ok(Self { bitmap, free_pages })
+    }
+
+    /// The physical address of a free page.
+    pub fn allocate_page(&mut self) -> Option<PhysAddr> {
+        self.allocate_page_on(0)
+    }
+
+    /// The physical address of a free page, on the NUMA node if it has one.
+    pub fn allocate_page_on(&mut self, node: usize) -> Option<PhysAddr> {
+        let domains = self.bitmap.domains().len();
+        let pfn = core::iter::once(node)
+            .chain((0..domains).filter(|&other| other != node))
+            .find_map(|domain| self.bitmap.find_free_page_in_domain(domain))?;
+        self.bitmap.allocate_page(pfn).ok()?;
+        self.free_pages -= 1;
+        Some(PhysAddr::from_pfn(pfn as u64))
+    }
+
+    pub fn free_page(&mut self, phys: PhysAddr) {
+        match self.bitmap.free_page(phys.pfn() as usize) {
+            Ok(()) => self.free_pages += 1,
+            Err(e) => panic!("Freeing the page {phys:#x}: {e:?}"),
+        }
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.free_pages
+    }
+
+    /// Checks the levels of the bitmap against each other, and the free
+    /// pages in the bitmap against the count.
+    pub fn check(&self) -> Result<(), PageAllocatorError> {
+        self.bitmap.check().map_err(PageAllocatorError::Bitmap)?;
+
+        let max_pfn = self.bitmap.max_memory() / PAGE_SIZE;
+        let counted = max_pfn - self.bitmap.allocated_pages(0, max_pfn);
+        if counted != self.free_pages {
+            return Err(PageAllocatorError::FreePages {
+                counted,
+                expected: self.free_pages,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The pages of each NUMA node: from the start of its lowest memory range
+/// to the start of the next node, the first node from page `0` and the
+/// last one to `max_pfn`. The nodes without memory get no pages. None if
+/// the nodes interleave.
+fn node_domains(numa: &Numa, max_pfn: usize) -> Option<[PageBitmapDomain; MAX_NUMA_NODES]> {
+    let mut domains = [PageBitmapDomain {
+        start_pfn: 0,
+        end_pfn: 0,
+    }; MAX_NUMA_NODES];
+    let node_count = (numa.node_count as usize).clamp(1, MAX_NUMA_NODES);
+
+    // The span of the memory of each node.
+    let mut spans = [None::<(usize, usize)>; MAX_NUMA_NODES];
+    for range in numa.memory_ranges() {
+        let node = range.node as usize;
+        let start = (range.base / address::PAGE_SIZE) as usize;
+        let end = range
+            .base
+            .saturating_add(range.length)
+            .div_ceil(address::PAGE_SIZE) as usize;
+        if node >= node_count || start >= max_pfn || start == end {
+            continue;
+        }
+        let end = end.min(max_pfn);
+        spans[node] = Some(match spans[node] {
+            Some((lo, hi)) => (lo.min(start), hi.max(end)),
+            None => (start, end),
+        });
+    }
+
+    let mut nodes = [0; MAX_NUMA_NODES];
+    let mut count = 0;
+    for (node, span) in spans.iter().enumerate() {
+        if span.is_some() {
+            nodes[count] = node;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        domains[0].end_pfn = max_pfn;
+        return Some(domains);
+    }
+    let nodes = &mut nodes[..count];
+    nodes.sort_unstable_by_key(|&node| spans[node]);
+
+    for (index, &node) in nodes.iter().enumerate() {
+        let (start, end) = spans[node]?;
+        let next_start = match nodes.get(index + 1) {
+            Some(&next) => spans[next]?.0,
+            None => max_pfn,
+        };
+        if end > next_start {
+            return None;
+        }
+        domains[node] = PageBitmapDomain {
+            start_pfn: if index == 0 { 0 } else { start },
+            end_pfn: next_start,
+        };
+    }
+    Some(domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use bootinfo::NumaMemoryRange;
+
+    const PAGE: u64 = address::PAGE_SIZE;
+
+    fn numa(node_count: u32, ranges: &[(u64, u64, u32)]) -> Numa {
+        let mut numa = Numa {
+            node_count,
+            memory_range_count: ranges.len() as u32,
+            ..Default::default()
+        };
+        for (slot, &(base, length, node)) in numa.memory_ranges.iter_mut().zip(ranges) {
+            *slot = NumaMemoryRange {
+                base: base * PAGE,
+                length: length * PAGE,
+                node,
+                flags: 0,
+            };
+        }
+        numa
+    }
+
+    fn spans(domains: &[PageBitmapDomain]) -> Vec<(usize, usize)> {
+        domains.iter().map(|d| (d.start_pfn, d.end_pfn)).collect()
+    }
+
+    #[test]
+    fn no_srat() {
+        let domains = node_domains(&Numa::default(), 1000).unwrap();
+        assert_eq!(spans(&domains[..2]), [(0, 1000), (0, 0)]);
+    }
+
+    #[test]
+    fn a_domain_per_node() {
+        // Node 1 is below node 0, node 2 has two ranges, node 3 has no
+        // memory, and node 2 goes past the tracked memory.
+        let numa = numa(
+            4,
+            &[(16, 100, 1), (200, 50, 0), (300, 10, 2), (400, 1000, 2)],
+        );
+        let domains = node_domains(&numa, 1000).unwrap();
+        assert_eq!(
+            spans(&domains[..4]),
+            [(200, 300), (0, 200), (300, 1000), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn interleaved_nodes() {
+        let numa = numa(2, &[(0, 100, 0), (100, 100, 1), (200, 100, 0)]);
+        assert!(node_domains(&numa, 1000).is_none());
+    }
+}
@@ -0,0 +1,245 @@
+//! The kernel parameters from the command line in the boot info, tokenized
+//! the same way as the loader configuration, see `ini_file`. A flag is a
+//! key by itself or with a `yes`/`on`/`1`/`true` value, the `no`/`off`/
+//! `0`/`false` value clears it:
+//!
+//! ```ignore
+//! log_level=debug self_test nosmp mem=512M
+//! ```
+//!
+//! The parameters are parsed in [`init`] before the logger and the
+//! allocator exist, and the keys the kernel does not know, or the values it
+//! cannot parse, are logged by [`log_problems`] once there is a logger.
+
+use crate::boot_info::BootInfo;
+use log::LevelFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelParams {
+    /// `log_level=<off|error|warn|info|debug|trace>`.
+    pub log_level: LevelFilter,
+    /// `self_test`, run the boot-time self tests.
+    pub self_test: bool,
+    /// `nosmp`, leave the secondary processors parked.
+    pub nosmp: bool,
+    /// `qemu_exit`, ask QEMU to exit once the kernel has initialized, with
+    /// the failure code if a self test has failed.
+    pub qemu_exit: bool,
+    /// `mem=<size>`, do not use the memory above that, with an optional
+    /// `K`, `M`, or `G` suffix. Zero is not a valid limit.
+    pub mem_limit: Option<u64>,
+}
+
+impl KernelParams {
+    pub const DEFAULT: Self = Self {
+        log_level: LevelFilter::Info,
+        self_test: false,
+        nosmp: false,
+        qemu_exit: false,
+        mem_limit: None,
+    };
+
+    /// Parses the command line over the defaults, calls `on_problem` for
+    /// each parameter it skips. Stops at the first syntax error.
+    pub fn parse<'a>(cmdline: &'a str, mut on_problem: impl FnMut(ParamError<'a>)) -> Self {
+        let mut params = Self::DEFAULT;
+        let mut parser = ini_file::Parser::with_flags(cmdline.as_bytes());
+        loop {
+            match parser.parse() {
+                Ok(Some(ini_file::KeyValue { key, value })) => {
+                    // The literals are ASCII, the quoted values are cut
+                    // at the quotes of a valid string.
+                    let key = core::str::from_utf8(key).unwrap_or_default();
+                    let value = core::str::from_utf8(value).unwrap_or_default();
+                    if let Err(e) = params.apply(key, value) {
+                        on_problem(e);
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    on_problem(ParamError::Syntax(e));
+                    break;
+                }
+            }
+        }
+        params
+    }
+
+    fn apply<'a>(&mut self, key: &'a str, value: &'a str) -> Result<(), ParamError<'a>> {
+        let bad_value = || ParamError::BadValue { key, value };
+        match key {
+            "log_level" => self.log_level = parse_log_level(value).ok_or_else(bad_value)?,
+            "self_test" => self.self_test = parse_flag(value).ok_or_else(bad_value)?,
+            "nosmp" => self.nosmp = parse_flag(value).ok_or_else(bad_value)?,
+            "qemu_exit" => self.qemu_exit = parse_flag(value).ok_or_else(bad_value)?,
+            "mem" => {
+                let limit = parse_size(value).filter(|&size| size != 0);
+                self.mem_limit = Some(limit.ok_or_else(bad_value)?);
+            }
+            _ => return Err(ParamError::UnknownKey(key)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for KernelParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamError<'a> {
+    UnknownKey(&'a str),
+    BadValue { key: &'a str, value: &'a str },
+    Syntax(ini_file::Error),
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "" | "yes" | "on" | "1" | "true" => Some(true),
+        "no" | "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_log_level(value: &str) -> Option<LevelFilter> {
+    match value {
+        "off" => Some(LevelFilter::Off),
+        "error" => Some(LevelFilter::Error),
+        "warn" => Some(LevelFilter::Warn),
+        "info" => Some(LevelFilter::Info),
+        "debug" => Some(LevelFilter::Debug),
+        "trace" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// A size in bytes with an optional `K`, `M`, or `G` suffix.
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, shift) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 10),
+        b'M' | b'm' => (&value[..value.len() - 1], 20),
+        b'G' | b'g' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+static mut PARAMS: KernelParams = KernelParams::DEFAULT;
+
+/// Parses the kernel command line. Does not log, nor allocate.
+pub fn init(boot_info: &BootInfo) {
+    let params = KernelParams::parse(boot_info.cmdline_str(), |_| {});
+    // SAFETY: only the boot processor runs, and nothing has read the
+    // parameters yet.
+    unsafe { *core::ptr::addr_of_mut!(PARAMS) = params };
+}
+
+/// The kernel parameters, the defaults until [`init`].
+pub fn get() -> &'static KernelParams {
+    // SAFETY: written once before the other processors start.
+    unsafe { &*core::ptr::addr_of!(PARAMS) }
+}
+
+/// Logs the parameters [`init`] has skipped.
+pub fn log_problems(boot_info: &BootInfo) {
+    KernelParams::parse(boot_info.cmdline_str(), |problem| match problem {
+        ParamError::UnknownKey(key) => log::warn!("Unknown kernel parameter {key:?}"),
+        ParamError::BadValue { key, value } => {
+            log::warn!("Bad value {value:?} of the kernel parameter {key:?}")
+        }
+        ParamError::Syntax(e) => {
+            log::warn!("Kernel command line: {e:?}, ignoring the rest")
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn parse(cmdline: &str) -> (KernelParams, Vec<ParamError<'_>>) {
+        let mut problems = Vec::new();
+        let params = KernelParams::parse(cmdline, |problem| problems.push(problem));
+        (params, problems)
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(parse(""), (KernelParams::DEFAULT, Vec::new()));
+    }
+
+    #[test]
+    fn all_params() {
+        let (params, problems) = parse("log_level=debug self_test nosmp=on qemu_exit=1 mem=512M");
+        assert_eq!(problems, []);
+        assert_eq!(
+            params,
+            KernelParams {
+                log_level: LevelFilter::Debug,
+                self_test: true,
+                nosmp: true,
+                qemu_exit: true,
+                mem_limit: Some(512 << 20),
+            }
+        );
+
+        let (params, problems) = parse("self_test nosmp self_test=no nosmp=false");
+        assert_eq!(problems, []);
+        assert_eq!(params, KernelParams::DEFAULT);
+    }
+
+    #[test]
+    fn problems() {
+        let (params, problems) = parse("frobnicate log_level=loud mem=0 mem=12X nosmp");
+        assert_eq!(
+            problems,
+            [
+                ParamError::UnknownKey("frobnicate"),
+                ParamError::BadValue {
+                    key: "log_level",
+                    value: "loud"
+                },
+                ParamError::BadValue {
+                    key: "mem",
+                    value: "0"
+                },
+                ParamError::BadValue {
+                    key: "mem",
+                    value: "12X"
+                },
+            ]
+        );
+        assert_eq!(
+            params,
+            KernelParams {
+                nosmp: true,
+                ..KernelParams::DEFAULT
+            }
+        );
+    }
+
+    #[test]
+    fn stops_at_syntax_error() {
+        let (params, problems) = parse("nosmp log_level=\"debug self_test");
+        assert!(matches!(problems[..], [ParamError::Syntax(_)]));
+        assert!(params.nosmp);
+        assert!(!params.self_test);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64k"), Some(64 << 10));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("3G"), Some(3 << 30));
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("-1M"), None);
+        assert_eq!(parse_size("1T"), None);
+        assert_eq!(parse_size("17179869184G"), None);
+    }
+}
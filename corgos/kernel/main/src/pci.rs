@@ -0,0 +1,45 @@
+//! The PCI Express configuration space the loader has mapped, see the
+//! `pci` crate. The drivers find their devices with [`find`], by the
+//! vendor and the device IDs, or the class.
+
+use crate::boot_info::BootInfo;
+use ::pci::Ecam;
+use ::pci::Function;
+use ::pci::Header;
+use sync::OnceCell;
+
+static ECAM: OnceCell<Ecam> = OnceCell::uninit();
+
+/// Takes the ECAM window from the boot info, and logs the devices.
+pub fn init(boot_info: &BootInfo) {
+    let pci = boot_info.pci;
+    if pci.ecam_virt_base == 0 {
+        log::info!("No PCI Express configuration space");
+        return;
+    }
+    // SAFETY: the loader has mapped the configuration space of the buses.
+    let ecam = unsafe { Ecam::new(pci.ecam_virt_base, pci.segment, pci.start_bus, pci.end_bus) };
+    let ecam = ECAM.get_or_init(|| ecam);
+
+    let mut count = 0;
+    for function in ecam.functions() {
+        log::debug!("PCI {function}");
+        count += 1;
+    }
+    log::info!(
+        "PCI ECAM @ {:#016x}, buses {:02x}..={:02x}, {count} functions",
+        pci.ecam_phys_base,
+        pci.start_bus,
+        pci.end_bus
+    );
+}
+
+/// The functions whose header matches.
+pub fn find(
+    mut matches: impl FnMut(&Header) -> bool,
+) -> impl Iterator<Item = Function<'static, Ecam>> {
+    ECAM.get()
+        .into_iter()
+        .flat_map(Ecam::functions)
+        .filter(move |function| matches(&function.header()))
+}
@@ -0,0 +1,138 @@
+//! The boot-time self tests, run with `self_test` on the kernel command
+//! line. They check what the kernel takes from the loader on trust, so a
+//! mapping or a memory map bug shows at the boot rather than as a random
+//! corruption later: the page allocator agrees with itself, a sample of
+//! the free pages holds what is written there, and the kernel text is not
+//! writable.
+
+use crate::address::PhysAddr;
+use crate::allocator;
+use crate::arch::Arch;
+use crate::arch::Mmu;
+use crate::linear_map;
+use crate::page_allocator::PAGE_SIZE;
+use alloc::vec::Vec;
+
+/// How many free pages are pattern-tested.
+const SAMPLE_PAGES: usize = 64;
+
+const PATTERNS: [u64; 4] = [
+    0x5555_5555_5555_5555,
+    0xaaaa_aaaa_aaaa_aaaa,
+    0x0000_0000_0000_0000,
+    0xffff_ffff_ffff_ffff,
+];
+
+fn page_bitmap() -> Result<(), &'static str> {
+    match allocator::with_page_allocator(|pages| pages.check()) {
+        None => Err("no page allocator"),
+        Some(Err(e)) => {
+            log::error!("Page allocator: {e:?}");
+            Err("inconsistent page allocator")
+        }
+        Some(Ok(())) => Ok(()),
+    }
+}
+
+/// Writes each pattern and then the address of each word to the page,
+/// and reads them back.
+fn pattern_test_page(phys: PhysAddr) -> Result<(), PhysAddr> {
+    let words = linear_map::phys_to_virt(phys).as_mut_ptr::<u64>();
+    let word_count = PAGE_SIZE / core::mem::size_of::<u64>();
+    let word_addr = |i: usize| phys + (i * core::mem::size_of::<u64>()) as u64;
+
+    let check = |value: &dyn Fn(usize) -> u64| {
+        for i in 0..word_count {
+            // SAFETY: the page is allocated to the test, and mapped.
+            unsafe { words.add(i).write_volatile(value(i)) };
+        }
+        for i in 0..word_count {
+            // SAFETY: as above.
+            if unsafe { words.add(i).read_volatile() } != value(i) {
+                return Err(word_addr(i));
+            }
+        }
+        Ok(())
+    };
+
+    for pattern in PATTERNS {
+        check(&|_| pattern)?;
+    }
+    check(&|i| word_addr(i).as_u64())
+}
+
+fn free_pages() -> Result<(), &'static str> {
+    let pages: Vec<PhysAddr> = allocator::with_page_allocator(|pages| {
+        core::iter::from_fn(|| pages.allocate_page())
+            .take(SAMPLE_PAGES)
+            .collect()
+    })
+    .ok_or("no page allocator")?;
+
+    let result = pages.iter().try_for_each(|&phys| pattern_test_page(phys));
+    allocator::with_page_allocator(|allocator| {
+        for &phys in &pages {
+            allocator.free_page(phys);
+        }
+    });
+
+    match result {
+        Ok(()) if pages.is_empty() => Err("no free pages"),
+        Ok(()) => {
+            log::debug!("Pattern-tested {} pages", pages.len());
+            Ok(())
+        }
+        Err(addr) => {
+            log::error!("Memory at {addr:?} does not hold the pattern");
+            Err("bad free page")
+        }
+    }
+}
+
+type Test = fn() -> Result<(), &'static str>;
+
+static mut PROBE_DATA: u8 = 0;
+
+fn text_not_writable<A: Arch>() -> Result<(), &'static str> {
+    let text = text_not_writable::<A> as *const () as u64;
+    let data = core::ptr::addr_of!(PROBE_DATA) as u64;
+
+    // SAFETY: the probe only writes the bytes back, the kernel text does
+    // not change, and nothing else uses the probe data.
+    let (text_writable, data_writable) =
+        unsafe { (A::Mmu::is_writable(text), A::Mmu::is_writable(data)) };
+    if !data_writable {
+        return Err("data not writable, the probe does not work");
+    }
+    if text_writable {
+        log::error!("The kernel text at {text:#016x} is writable");
+        return Err("text writable");
+    }
+    Ok(())
+}
+
+/// Runs the tests, and reports the results. Needs the allocator. Returns
+/// whether all the tests have passed.
+pub fn run<A: Arch>() -> bool {
+    let tests: [(&str, Test); 3] = [
+        ("page bitmap", page_bitmap),
+        ("free pages", free_pages),
+        ("W^X", text_not_writable::<A>),
+    ];
+
+    let mut failed = 0;
+    for (name, test) in tests {
+        match test() {
+            Ok(()) => log::info!("Self test {name}: passed"),
+            Err(e) => {
+                log::error!("Self test {name}: failed, {e}");
+                failed += 1;
+            }
+        }
+    }
+    log::info!(
+        "Self tests: {} passed, {failed} failed",
+        tests.len() - failed
+    );
+    failed == 0
+}
@@ -0,0 +1,122 @@
+//! Starting the secondary processors.
+//!
+//! The boot processor starts them one at a time with [`Arch::start_cpu`],
+//! each on a stack of its own from the heap, and waits for each to report
+//! in from [`secondary_main`] before starting the next one, as the startup
+//! data the architecture code hands over is shared. The started processors
+//! wait at a barrier until the boot processor has been through all of
+//! them, and then go idle.
+//!
+//! With `nosmp` on the kernel command line, the secondary processors are
+//! left where the firmware has parked them.
+
+use crate::arch::Arch;
+use crate::arch::StartCpuError;
+use crate::boot_info::BootInfo;
+use crate::idle;
+use crate::params;
+use crate::time;
+use core::alloc::Layout;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+const SECONDARY_STACK_SIZE: usize = 0x8000;
+/// How long the boot processor waits for a secondary one to report in.
+const START_TIMEOUT_US: u64 = 100_000;
+
+/// The boot processor is online from the start.
+static ONLINE: AtomicU32 = AtomicU32::new(1);
+/// Lets the secondary processors past the barrier.
+static RELEASE: AtomicBool = AtomicBool::new(false);
+
+/// The number of the processors running the kernel.
+pub fn online_cpus() -> u32 {
+    ONLINE.load(Ordering::Acquire)
+}
+
+fn start_cpu<A: Arch>(boot_info: &BootInfo, cpu_id: u32) -> Result<(), StartCpuError> {
+    let layout = Layout::from_size_align(SECONDARY_STACK_SIZE, 16).unwrap();
+    // SAFETY: the layout is not zero-sized.
+    let stack = unsafe { alloc::alloc::alloc(layout) };
+    if stack.is_null() {
+        return Err(StartCpuError::OutOfMemory);
+    }
+    let stack_top = stack as u64 + SECONDARY_STACK_SIZE as u64;
+
+    let online = online_cpus();
+    if let Err(e) = A::start_cpu(boot_info, cpu_id, stack_top) {
+        // SAFETY: the processor has not started, nothing uses the stack.
+        unsafe { alloc::alloc::dealloc(stack, layout) };
+        return Err(e);
+    }
+
+    // Polls in the small steps so that a fast processor is not kept
+    // waiting for the whole timeout. The stack is leaked if the processor
+    // does not report, it may still come up.
+    for _ in 0..START_TIMEOUT_US / 100 {
+        if online_cpus() != online {
+            return Ok(());
+        }
+        time::delay_us::<A>(boot_info, 100);
+    }
+    Err(StartCpuError::NoResponse)
+}
+
+/// Starts the secondary processors, and lets them go idle. Needs the
+/// per-CPU data and the allocator.
+pub fn start_secondary_cpus<A: Arch>(boot_info: &BootInfo) {
+    let cpus = boot_info.cpus().cpus();
+    if cpus.len() <= 1 {
+        return;
+    }
+    if params::get().nosmp {
+        log::info!("nosmp, not starting {} processors", cpus.len() - 1);
+        return;
+    }
+
+    let boot_cpu_id = A::cpu_id();
+    for (cpu_id, cpu) in cpus.iter().enumerate() {
+        if cpu_id as u32 == boot_cpu_id {
+            continue;
+        }
+        if cpu.enabled == 0 {
+            log::debug!("Processor {cpu_id} ({:#x}) is not enabled", cpu.hw_id);
+            continue;
+        }
+
+        match start_cpu::<A>(boot_info, cpu_id as u32) {
+            Ok(()) => {}
+            Err(StartCpuError::NoEnableMethod) => {
+                log::warn!("No way to start the secondary processors");
+                break;
+            }
+            Err(e) => {
+                log::error!(
+                    "Processor {cpu_id} ({:#x}) has not started: {e:?}",
+                    cpu.hw_id
+                );
+                // The late one would find the startup data changed.
+                if e == StartCpuError::NoResponse {
+                    break;
+                }
+            }
+        }
+    }
+
+    RELEASE.store(true, Ordering::Release);
+    log::info!("{} of {} processors online", online_cpus(), cpus.len());
+}
+
+/// Where the secondary processors enter the kernel, with the translations
+/// of the boot processor and the interrupts masked.
+pub fn secondary_main<A: Arch>(cpu_id: u32) -> ! {
+    A::init_secondary_cpu(cpu_id);
+    log::info!("Processor {} online", A::cpu_id());
+
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    while !RELEASE.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+    idle::idle::<A>()
+}
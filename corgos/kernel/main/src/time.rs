@@ -0,0 +1,64 @@
+//! The time base of the kernel. The timer interrupt of the boot processor
+//! comes [`TICK_HZ`] times a second, and advances the tick counter that
+//! [`uptime`] is counted in. The secondary processors do not take the
+//! timer interrupt.
+//!
+//! [`delay_us`] spins on the free-running counter instead, so it works
+//! before the timer interrupt starts, and with the interrupts masked.
+
+use crate::arch::Arch;
+use crate::arch::Interrupts;
+use crate::arch::Timer;
+use crate::boot_info::BootInfo;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+pub const TICK_HZ: u32 = 100;
+/// Errs on the long side when the boot info has no timer frequency.
+const FALLBACK_TIMER_FREQUENCY: u64 = 4_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Called from the timer interrupt of the boot processor, once for each
+/// period that has passed.
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The timer interrupts since [`init`], monotonic.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// The time since [`init`], in the whole ticks.
+pub fn uptime() -> Duration {
+    Duration::from_nanos(ticks() * (1_000_000_000 / TICK_HZ as u64))
+}
+
+/// Spins for at least `us` microseconds.
+pub fn delay_us<A: Arch>(boot_info: &BootInfo, us: u64) {
+    let frequency = match boot_info.timer.frequency {
+        0 => FALLBACK_TIMER_FREQUENCY,
+        frequency => frequency,
+    };
+    let ticks = frequency.saturating_mul(us) / 1_000_000;
+    let start = A::Timer::ticks();
+    while A::Timer::ticks().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
+
+/// Starts the timer interrupt on the boot processor, and unmasks the
+/// interrupts. The kernel runs on without the ticks if that fails.
+pub fn init<A: Arch>(boot_info: &BootInfo) {
+    match A::Timer::start_periodic(boot_info, TICK_HZ) {
+        Ok(()) => {
+            log::info!("Timer interrupt at {TICK_HZ} Hz");
+            // SAFETY: the start code of the architecture has installed the
+            // handler of the timer interrupt, the other sources are masked.
+            unsafe { A::Interrupts::enable() };
+        }
+        Err(e) => log::warn!("No timer interrupt: {e:?}"),
+    }
+}
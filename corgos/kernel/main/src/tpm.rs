@@ -0,0 +1,74 @@
+//! The TPM 2.0 the loader has found, see the `tpm` crate. The kernel
+//! extends the PCRs for what it runs with itself, starting with its
+//! command line in [`CMDLINE_PCR`] at [`init`]; the loader has measured
+//! the kernel image with the firmware.
+
+use crate::boot_info::BootInfo;
+use ::tpm::Crb;
+use ::tpm::Interface;
+use ::tpm::Tis;
+use ::tpm::Tpm;
+use ::tpm::TpmError;
+use bootinfo::TpmInterface;
+use sync::IrqSpinlock;
+
+/// The PCR for the kernel command line, as the other kernels use.
+pub const CMDLINE_PCR: u32 = 12;
+
+/// One of the interfaces the loader hands over.
+enum Registers {
+    Crb(Crb),
+    Tis(Tis),
+}
+
+impl Interface for Registers {
+    fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        match self {
+            Registers::Crb(crb) => crb.execute(command, response),
+            Registers::Tis(tis) => tis.execute(command, response),
+        }
+    }
+}
+
+static TPM: IrqSpinlock<Option<Tpm<Registers>>> = IrqSpinlock::new(None);
+
+/// Takes the TPM from the boot info, and measures the command line.
+pub fn init(boot_info: &BootInfo) {
+    let tpm = boot_info.tpm;
+    let registers = match tpm.interface {
+        TpmInterface::None => {
+            log::info!("No TPM");
+            return;
+        }
+        TpmInterface::Crb => {
+            // SAFETY: the loader has mapped the registers of the locality 0.
+            Registers::Crb(unsafe { Crb::new(tpm.virt_base, tpm.phys_base, tpm.size) })
+        }
+        // SAFETY: as above.
+        TpmInterface::Tis => Registers::Tis(unsafe { Tis::new(tpm.virt_base) }),
+    };
+    *TPM.lock() = Some(Tpm::new(registers));
+    log::info!("TPM 2.0 {:?} @ {:#016x}", tpm.interface, tpm.phys_base);
+
+    let cmdline = boot_info.cmdline_str();
+    match pcr_extend(CMDLINE_PCR, &digest::sha256(cmdline.as_bytes())) {
+        Ok(()) => log::info!("Measured the command line into PCR {CMDLINE_PCR}"),
+        Err(e) => log::warn!("Cannot measure the command line: {e:?}"),
+    }
+}
+
+/// Extends the SHA-256 bank of the PCR with the digest.
+pub fn pcr_extend(pcr: u32, sha256: &[u8; 32]) -> Result<(), TpmError> {
+    match TPM.lock().as_mut() {
+        Some(tpm) => tpm.pcr_extend(pcr, sha256),
+        None => Err(TpmError::NotPresent),
+    }
+}
+
+/// Fills the buffer with the random bytes from the TPM.
+pub fn get_random(bytes: &mut [u8]) -> Result<(), TpmError> {
+    match TPM.lock().as_mut() {
+        Some(tpm) => tpm.get_random(bytes),
+        None => Err(TpmError::NotPresent),
+    }
+}
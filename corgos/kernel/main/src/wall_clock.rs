@@ -0,0 +1,79 @@
+//! The wall-clock time in UTC. The time of day the loader has read from
+//! the firmware is the base, and the RTC is read instead if the firmware
+//! could not tell the time: the EFI `GetTime` is not called, it needs the
+//! firmware mappings. The time moves on with the free-running counter
+//! from the base.
+
+use crate::arch::Arch;
+use crate::arch::Timer;
+use crate::boot_info::BootInfo;
+use bootinfo::WallClock;
+use bootinfo::WALL_CLOCK_UNSPECIFIED_TIME_ZONE;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use rtc::DateTime;
+
+/// The seconds since the epoch at [`BASE_COUNTER`], `0` if the time is
+/// not known.
+static BASE_SECONDS: AtomicU64 = AtomicU64::new(0);
+static BASE_COUNTER: AtomicU64 = AtomicU64::new(0);
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// The seconds since the epoch in UTC the firmware time is, `None` if it
+/// is all zeroes or invalid. The time with no time zone is taken as UTC.
+fn firmware_seconds(wall_clock: &WallClock) -> Option<u64> {
+    let seconds = DateTime {
+        year: wall_clock.year,
+        month: wall_clock.month,
+        day: wall_clock.day,
+        hour: wall_clock.hour,
+        minute: wall_clock.minute,
+        second: wall_clock.second,
+    }
+    .to_unix_seconds()?;
+
+    match wall_clock.time_zone {
+        WALL_CLOCK_UNSPECIFIED_TIME_ZONE => Some(seconds),
+        minutes => seconds.checked_add_signed(-(minutes as i64 * 60)),
+    }
+}
+
+/// Takes the base time from the firmware or the RTC.
+pub fn init<A: Arch>(boot_info: &BootInfo) {
+    FREQUENCY.store(boot_info.timer.frequency, Ordering::Relaxed);
+
+    let (source, seconds, counter) = match firmware_seconds(&boot_info.wall_clock) {
+        Some(seconds) => ("the firmware", seconds, boot_info.wall_clock.counter),
+        None => match A::read_rtc(boot_info).and_then(|time| time.to_unix_seconds()) {
+            Some(seconds) => ("the RTC", seconds, A::Timer::ticks()),
+            None => {
+                log::warn!("The time of day is not known");
+                return;
+            }
+        },
+    };
+    BASE_COUNTER.store(counter, Ordering::Relaxed);
+    BASE_SECONDS.store(seconds, Ordering::Release);
+
+    if let Some(now) = now::<A>() {
+        log::info!("Wall-clock time {now} UTC from {source}");
+    }
+}
+
+/// The seconds since the epoch, `None` if the time is not known.
+pub fn unix_seconds<A: Arch>() -> Option<u64> {
+    let seconds = match BASE_SECONDS.load(Ordering::Acquire) {
+        0 => return None,
+        seconds => seconds,
+    };
+    let elapsed = A::Timer::ticks()
+        .wrapping_sub(BASE_COUNTER.load(Ordering::Relaxed))
+        .checked_div(FREQUENCY.load(Ordering::Relaxed))
+        .unwrap_or(0);
+    Some(seconds + elapsed)
+}
+
+/// The time of day in UTC, `None` if it is not known.
+pub fn now<A: Arch>() -> Option<DateTime> {
+    unix_seconds::<A>().map(DateTime::from_unix_seconds)
+}
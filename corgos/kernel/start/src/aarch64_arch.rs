@@ -0,0 +1,175 @@
+//! The `kernel_main` architecture layer on AArch64: the early console,
+//! the IRQ mask in DAIF, the virtual counter and timer, the TTBR1
+//! translations, and starting the secondary processors.
+
+use crate::aarch64_interrupts;
+use crate::aarch64_smp;
+use crate::early_exceptions;
+use crate::per_cpu;
+use crate::qemu;
+use kernel_main::arch;
+use kernel_main::arch::StartCpuError;
+use kernel_main::arch::TimerError;
+use kernel_main::BootInfo;
+
+/// Reads the system register by its name.
+macro_rules! read_sysreg {
+    ($reg:literal) => {{
+        let value: u64;
+        // SAFETY: the callers read the registers that have no side effects
+        // on reading.
+        unsafe {
+            core::arch::asm!(concat!("mrs {}, ", $reg), out(reg) value, options(nomem, nostack))
+        };
+        value
+    }};
+}
+pub(crate) use read_sysreg;
+
+pub struct Aarch64;
+
+pub struct Console;
+
+impl arch::Console for Console {
+    fn write_str(s: &str) {
+        crate::early_console::print(format_args!("{s}"));
+    }
+}
+
+/// The DAIF.I bit.
+const DAIF_IRQ: u64 = 1 << 7;
+
+pub struct Interrupts;
+
+// Not `nomem`, the compiler must not move the memory accesses across
+// unmasking or masking the IRQs.
+impl arch::Interrupts for Interrupts {
+    unsafe fn enable() {
+        // SAFETY: the caller has installed the handlers.
+        unsafe { core::arch::asm!("msr daifclr, #2", options(nostack)) };
+    }
+
+    fn disable() {
+        // SAFETY: masking the IRQs has no effect on the memory.
+        unsafe { core::arch::asm!("msr daifset, #2", options(nostack)) };
+    }
+
+    fn are_enabled() -> bool {
+        let daif: u64;
+        // SAFETY: reading DAIF has no side effects.
+        unsafe { core::arch::asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack)) };
+        daif & DAIF_IRQ == 0
+    }
+}
+
+pub struct Timer;
+
+impl arch::Timer for Timer {
+    fn ticks() -> u64 {
+        let ticks: u64;
+        // SAFETY: the loader has left the virtual counter readable at EL1,
+        // the barrier keeps the read from being speculated early.
+        unsafe {
+            core::arch::asm!("isb", "mrs {}, cntvct_el0", out(reg) ticks, options(nomem, nostack))
+        };
+        ticks
+    }
+
+    fn start_periodic(boot_info: &BootInfo, hz: u32) -> Result<(), TimerError> {
+        aarch64_interrupts::start_periodic(boot_info, hz)
+    }
+}
+
+/// TTBR1_EL1.BADDR, without the ASID and the CnP bit.
+const TTBR_BADDR_MASK: u64 = 0x0000_ffff_ffff_fffe;
+
+pub struct Mmu;
+
+impl arch::Mmu for Mmu {
+    fn root_table() -> u64 {
+        let ttbr1: u64;
+        // SAFETY: reading TTBR1_EL1 has no side effects.
+        unsafe { core::arch::asm!("mrs {}, ttbr1_el1", out(reg) ttbr1, options(nomem, nostack)) };
+        ttbr1 & TTBR_BADDR_MASK
+    }
+
+    fn flush_page(virt: u64) {
+        // SAFETY: invalidating the translations has no effect on the memory
+        // contents, the barriers order it after the table updates.
+        unsafe {
+            core::arch::asm!(
+                "dsb ishst",
+                "tlbi vaae1is, {}",
+                "dsb ish",
+                "isb",
+                in(reg) (virt >> 12) & 0x0fff_ffff_ffff,
+                options(nostack)
+            )
+        };
+    }
+
+    fn flush_all() {
+        // SAFETY: as for `flush_page`.
+        unsafe {
+            core::arch::asm!(
+                "dsb ishst",
+                "tlbi vmalle1is",
+                "dsb ish",
+                "isb",
+                options(nostack)
+            )
+        };
+    }
+
+    unsafe fn is_writable(virt: u64) -> bool {
+        // SAFETY: guaranteed by the caller.
+        unsafe { early_exceptions::probe_write(virt) }
+    }
+}
+
+impl arch::Arch for Aarch64 {
+    const NAME: &'static str = "aarch64";
+
+    type Console = Console;
+    type Interrupts = Interrupts;
+    type Timer = Timer;
+    type Mmu = Mmu;
+
+    fn init_per_cpu(boot_info: &BootInfo) {
+        per_cpu::init(boot_info);
+    }
+
+    fn start_cpu(boot_info: &BootInfo, cpu_id: u32, stack_top: u64) -> Result<(), StartCpuError> {
+        aarch64_smp::start_cpu(boot_info, cpu_id, stack_top)
+    }
+
+    fn init_secondary_cpu(cpu_id: u32) {
+        early_exceptions::install_secondary();
+        // SAFETY: runs once on the processor, the boot one has set up the
+        // blocks before starting it.
+        unsafe { per_cpu::install(cpu_id as usize) };
+    }
+
+    fn cpu_id() -> u32 {
+        per_cpu::cpu_id()
+    }
+
+    fn halt() {
+        // SAFETY: waiting for an interrupt has no effect on the memory. A
+        // pending one ends the wait even when masked.
+        unsafe { core::arch::asm!("wfi", options(nomem, nostack)) };
+    }
+
+    fn exit_qemu(success: bool) {
+        qemu::exit(success);
+    }
+
+    fn read_rtc(boot_info: &BootInfo) -> Option<rtc::DateTime> {
+        if boot_info.rtc.kind != bootinfo::RtcKind::Pl031 || boot_info.rtc.virt_base == 0 {
+            return None;
+        }
+        // SAFETY: the loader has mapped the registers with the device
+        // attributes.
+        Some(unsafe { rtc::Pl031::new(boot_info.rtc.virt_base) }.read())
+    }
+}
@@ -0,0 +1,152 @@
+//! The timer interrupt on AArch64: the virtual timer of the boot processor
+//! through the GICv3 CPU interface in the system registers, and the
+//! redistributor of the boot processor. The loader has mapped the
+//! distributor and that redistributor, and has usually woken it up.
+
+use crate::aarch64_arch::read_sysreg;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use kernel_main::arch::TimerError;
+use kernel_main::time;
+use kernel_main::BootInfo;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+
+/// The PPI of the EL1 virtual timer.
+const VIRTUAL_TIMER_INTID: u32 = 27;
+/// The INTIDs from here on are special, nothing to acknowledge.
+const SPECIAL_INTID_MIN: u32 = 1020;
+const INTID_MASK: u64 = 0xff_ffff;
+
+const GICD_CTLR: Register<u32, ReadWrite> = Register::new(0x0000);
+const GICD_CTLR_ENABLE_GRP1A: u32 = 1 << 1;
+const GICD_CTLR_ARE_NS: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+
+const GICR_WAKER: Register<u32, ReadWrite> = Register::new(0x0014);
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+/// The SGI_base frame follows RD_base.
+const GICR_SGI_BASE: u64 = 0x1_0000;
+const GICR_IGROUPR0: Register<u32, ReadWrite> = Register::new(GICR_SGI_BASE + 0x0080);
+const GICR_ISENABLER0: Register<u32, ReadWrite> = Register::new(GICR_SGI_BASE + 0x0100);
+/// A byte for each INTID.
+const GICR_IPRIORITYR: Register<u8, ReadWrite> = Register::new(GICR_SGI_BASE + 0x0400);
+
+const ICC_SRE_SRE: u64 = 1 << 0;
+/// Lets all the priorities through.
+const ICC_PMR_ALL: u64 = 0xff;
+const TIMER_PRIORITY: u8 = 0x80;
+const CNTV_CTL_ENABLE: u64 = 1 << 0;
+
+const SPIN_LIMIT: usize = 1_000_000;
+
+/// The counter ticks of a period.
+static INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+macro_rules! write_sysreg {
+    ($reg:literal, $value:expr) => {{
+        let value: u64 = $value;
+        // SAFETY: the GIC CPU interface and the timer registers do not
+        // change the memory contents.
+        unsafe {
+            core::arch::asm!(concat!("msr ", $reg, ", {}"), "isb", in(reg) value, options(nostack))
+        };
+    }};
+}
+
+fn spin_until(mut done: impl FnMut() -> bool) -> Result<(), TimerError> {
+    for _ in 0..SPIN_LIMIT {
+        if done() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(TimerError::NoInterruptController)
+}
+
+/// Sets up the GIC for the timer PPI of the boot processor.
+fn init_gic(gicd: RegisterBlock, gicr: RegisterBlock, awake: bool) -> Result<(), TimerError> {
+    if !awake {
+        gicr.modify(GICR_WAKER, |waker| waker & !GICR_WAKER_PROCESSOR_SLEEP);
+        spin_until(|| gicr.read(GICR_WAKER) & GICR_WAKER_CHILDREN_ASLEEP == 0)?;
+    }
+
+    // EL2 may not let EL1 use the system register interface.
+    write_sysreg!("icc_sre_el1", read_sysreg!("icc_sre_el1") | ICC_SRE_SRE);
+    if read_sysreg!("icc_sre_el1") & ICC_SRE_SRE == 0 {
+        return Err(TimerError::NoInterruptController);
+    }
+    write_sysreg!("icc_pmr_el1", ICC_PMR_ALL);
+    write_sysreg!("icc_bpr1_el1", 0);
+    write_sysreg!("icc_igrpen1_el1", 1);
+
+    gicd.modify(GICD_CTLR, |ctlr| {
+        ctlr | GICD_CTLR_ARE_NS | GICD_CTLR_ENABLE_GRP1A
+    });
+    spin_until(|| gicd.read(GICD_CTLR) & GICD_CTLR_RWP == 0)?;
+
+    let bit = 1 << VIRTUAL_TIMER_INTID;
+    gicr.modify(GICR_IGROUPR0, |igroupr| igroupr | bit);
+    gicr.write(
+        GICR_IPRIORITYR.at(VIRTUAL_TIMER_INTID as u64),
+        TIMER_PRIORITY,
+    );
+    gicr.write(GICR_ISENABLER0, bit);
+    Ok(())
+}
+
+/// Starts the virtual timer of the boot processor, see the module docs.
+pub fn start_periodic(boot_info: &BootInfo, hz: u32) -> Result<(), TimerError> {
+    let gic = &boot_info.gic;
+    if gic.gicd_virt_base == 0 || gic.boot_gicr_virt_base == 0 {
+        return Err(TimerError::NoInterruptController);
+    }
+    let interval = boot_info.timer.frequency / hz as u64;
+    if interval == 0 {
+        return Err(TimerError::NoFrequency);
+    }
+
+    // SAFETY: the loader has mapped the GIC registers in the device window.
+    let (gicd, gicr) = unsafe {
+        (
+            RegisterBlock::new(gic.gicd_virt_base),
+            RegisterBlock::new(gic.boot_gicr_virt_base),
+        )
+    };
+    init_gic(gicd, gicr, gic.boot_gicr_awake != 0)?;
+
+    INTERVAL.store(interval, Ordering::Relaxed);
+    write_sysreg!("cntv_cval_el0", read_sysreg!("cntvct_el0") + interval);
+    write_sysreg!("cntv_ctl_el0", CNTV_CTL_ENABLE);
+    Ok(())
+}
+
+/// Called from the IRQ vectors.
+pub fn handle_irq() {
+    let iar: u64;
+    // SAFETY: acknowledging the interrupt has no effect on the memory, it
+    // is ended below.
+    unsafe { core::arch::asm!("mrs {}, icc_iar1_el1", out(reg) iar, options(nomem, nostack)) };
+    let intid = (iar & INTID_MASK) as u32;
+    if intid >= SPECIAL_INTID_MIN {
+        return;
+    }
+
+    if intid == VIRTUAL_TIMER_INTID {
+        // Catches up by the whole periods, so the ticks keep in step with
+        // the counter when an interrupt comes late.
+        let interval = INTERVAL.load(Ordering::Relaxed);
+        let now = read_sysreg!("cntvct_el0");
+        let mut deadline = read_sysreg!("cntv_cval_el0");
+        while deadline <= now {
+            deadline += interval;
+            time::tick();
+        }
+        write_sysreg!("cntv_cval_el0", deadline);
+    }
+    // Nothing else is enabled, the EOI keeps a stray one from blocking the
+    // lower priorities.
+    write_sysreg!("icc_eoir1_el1", intid as u64);
+}
@@ -0,0 +1,163 @@
+//! Starting the secondary processors on AArch64 with PSCI `CPU_ON`.
+//!
+//! The firmware starts the processor at `corgos_secondary_entry` in
+//! `start-aarch64.S` at its physical address, with the MMU off and the
+//! physical address of the startup data in `x0`. The entry loads the
+//! translation registers of the boot processor from the startup data, with
+//! TTBR0 pointing to the tables that map the entry at its physical address,
+//! turns the MMU on, and branches to `kernel_start_secondary` in the kernel
+//! half.
+
+use crate::aarch64_arch::read_sysreg;
+use core::ptr::addr_of;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use kernel_main::allocator::PageTableAllocator;
+use kernel_main::arch::StartCpuError;
+use kernel_main::BootInfo;
+use page_tables::aarch64::MapAttributes;
+use page_tables::aarch64::PageTableBuilder;
+use page_tables::PAGE_SIZE;
+use psci::Conduit;
+use psci::Psci;
+
+extern "C" {
+    fn corgos_secondary_entry();
+}
+
+/// What `corgos_secondary_entry` takes from the kernel, read with the MMU
+/// off.
+#[repr(C)]
+pub struct SecondaryStart {
+    pub mair: u64,
+    pub tcr: u64,
+    pub ttbr0: u64,
+    pub ttbr1: u64,
+    pub sctlr: u64,
+    pub cpacr: u64,
+    pub stack_top: u64,
+    /// `kernel_start_secondary`.
+    pub entry: u64,
+    pub cpu_id: u64,
+}
+
+static mut START: SecondaryStart = SecondaryStart {
+    mair: 0,
+    tcr: 0,
+    ttbr0: 0,
+    ttbr1: 0,
+    sctlr: 0,
+    cpacr: 0,
+    stack_top: 0,
+    entry: 0,
+    cpu_id: 0,
+};
+
+/// The tables mapping the entry at its physical address, built once.
+static IDENTITY_ROOT: AtomicU64 = AtomicU64::new(0);
+
+/// The TTBR0 fields of TCR_EL1: T0SZ, EPD0, IRGN0, ORGN0, SH0, and TG0.
+const TCR_TTBR0_MASK: u64 = 0xffff;
+const TCR_T1SZ_SHIFT: u64 = 16;
+const TCR_TSZ_MASK: u64 = 0x3f;
+/// Walks through TTBR0 in the inner-shareable write-back memory, with the
+/// 4 KiB granule.
+const TCR_TTBR0_WALK: u64 = 0b01 << 8 | 0b01 << 10 | 0b11 << 12;
+/// The normal write-back memory in MAIR_EL1.
+const MAIR_NORMAL_WRITE_BACK: u8 = 0xff;
+
+/// Cleans the data cache lines of the range to the point of coherency, for
+/// the processor reading it with the MMU off.
+fn clean_to_poc(start: u64, size: u64) {
+    let ctr = read_sysreg!("ctr_el0");
+    let line = 4 << ((ctr >> 16) & 0xf);
+    let mut addr = start & !(line - 1);
+    while addr < start + size {
+        // SAFETY: cleaning does not change the memory contents.
+        unsafe { core::arch::asm!("dc cvac, {}", in(reg) addr, options(nostack)) };
+        addr += line;
+    }
+    // SAFETY: a barrier has no effect on the memory contents.
+    unsafe { core::arch::asm!("dsb sy", options(nostack)) };
+}
+
+/// The tables for TTBR0 that map the entry at its physical address. The
+/// secondary processor walks them with the MMU on, and the walks are
+/// coherent with the caches as TCR_EL1 has them cacheable.
+fn identity_root(boot_info: &BootInfo, entry_phys: u64) -> Result<u64, StartCpuError> {
+    let root = IDENTITY_ROOT.load(Ordering::Acquire);
+    if root != 0 {
+        return Ok(root);
+    }
+
+    let mair_index = boot_info
+        .paging
+        .mair
+        .to_le_bytes()
+        .iter()
+        .position(|&attribute| attribute == MAIR_NORMAL_WRITE_BACK)
+        .ok_or(StartCpuError::NoEnableMethod)? as u8;
+    let attributes = MapAttributes {
+        writable: false,
+        executable: true,
+        ..MapAttributes::data(mair_index)
+    };
+
+    // The entry may straddle a page boundary.
+    let page = entry_phys & !(PAGE_SIZE - 1);
+    let mut allocator = PageTableAllocator;
+    let mut builder =
+        PageTableBuilder::new(&mut allocator).map_err(|_| StartCpuError::OutOfMemory)?;
+    builder
+        .map(page, page, 2 * PAGE_SIZE, attributes)
+        .map_err(|_| StartCpuError::OutOfMemory)?;
+    let root = builder.root();
+
+    IDENTITY_ROOT.store(root, Ordering::Release);
+    Ok(root)
+}
+
+/// Starts the processor with PSCI `CPU_ON`, see the module docs.
+pub fn start_cpu(boot_info: &BootInfo, cpu_id: u32, stack_top: u64) -> Result<(), StartCpuError> {
+    let cpus = boot_info.cpus();
+    let conduit = match cpus.enable_method {
+        bootinfo::CpuEnableMethod::PsciSmc => Conduit::Smc,
+        bootinfo::CpuEnableMethod::PsciHvc => Conduit::Hvc,
+        _ => return Err(StartCpuError::NoEnableMethod),
+    };
+    let hw_id = cpus.cpus()[cpu_id as usize].hw_id;
+
+    let kernel = boot_info.kernel();
+    let to_phys = |virt: u64| virt - kernel.virt_base + kernel.phys_base;
+    let entry_phys = to_phys(corgos_secondary_entry as *const () as u64);
+    let ttbr0 = identity_root(boot_info, entry_phys)?;
+
+    // TTBR0 has the same number of levels as TTBR1, the tables are built
+    // the same way.
+    let tcr = read_sysreg!("tcr_el1");
+    let t1sz = (tcr >> TCR_T1SZ_SHIFT) & TCR_TSZ_MASK;
+    let start = SecondaryStart {
+        mair: read_sysreg!("mair_el1"),
+        tcr: (tcr & !TCR_TTBR0_MASK) | t1sz | TCR_TTBR0_WALK,
+        ttbr0,
+        ttbr1: read_sysreg!("ttbr1_el1"),
+        sctlr: read_sysreg!("sctlr_el1"),
+        cpacr: read_sysreg!("cpacr_el1"),
+        stack_top,
+        entry: crate::kernel_start_secondary as *const () as u64,
+        cpu_id: cpu_id as u64,
+    };
+    // SAFETY: the previous processor has reported in, nothing reads the
+    // startup data.
+    unsafe { addr_of_mut!(START).write(start) };
+    let start_virt = addr_of!(START) as u64;
+    clean_to_poc(start_virt, core::mem::size_of::<SecondaryStart>() as u64);
+
+    Psci::new(conduit)
+        .cpu_on(hw_id, entry_phys, to_phys(start_virt))
+        .map_err(|e| {
+            log::error!("CPU_ON {hw_id:#x}: {e:?}");
+            StartCpuError::Refused
+        })
+}
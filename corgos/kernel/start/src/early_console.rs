@@ -0,0 +1,160 @@
+//! The consoles the loader has handed over, for the messages before the
+//! kernel has its own drivers: the serial console the loader has been
+//! logging to, and the framebuffer. Nothing is re-probed, the boot info
+//! says where the registers and the framebuffer are mapped.
+//!
+//! The console polls the UART, and is not synchronized, so only one
+//! processor may use it. The kernel logger writes there, too, under its
+//! lock.
+
+use bootinfo::BootInfo;
+use bootinfo::Console;
+use bootinfo::ConsoleKind;
+use bootinfo::Framebuffer;
+use bootinfo::FramebufferConsole;
+use core::fmt::Write;
+use poll_uart::BaudDivisor;
+use poll_uart::ComPort;
+use poll_uart::ComPortIo;
+use poll_uart::Pl011;
+
+enum Uart {
+    None,
+    Pl011(Pl011),
+    Com(ComPort),
+}
+
+impl Uart {
+    fn new(console: &Console) -> Self {
+        match console.kind {
+            ConsoleKind::Pl011 if console.virt_base != 0 => {
+                Uart::Pl011(Pl011::new(console.virt_base))
+            }
+            ConsoleKind::Uart16550Io => match com_port(console.io_port) {
+                Some(port) => Uart::Com(ComPort::new(port, BaudDivisor::Baud115200)),
+                None => Uart::None,
+            },
+            _ => Uart::None,
+        }
+    }
+}
+
+impl Write for Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        match self {
+            Uart::None => Ok(()),
+            Uart::Pl011(uart) => uart.write_str(s),
+            Uart::Com(uart) => uart.write_str(s),
+        }
+    }
+}
+
+/// Writes to the UART and the framebuffer, whichever are there.
+pub struct EarlyConsole {
+    uart: Uart,
+    framebuffer: Option<fbcon::Console<'static>>,
+}
+
+impl Write for EarlyConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.uart.write_str(s)?;
+        if let Some(framebuffer) = &mut self.framebuffer {
+            framebuffer.write_str(s)?;
+        }
+        Ok(())
+    }
+}
+
+static mut CONSOLE: EarlyConsole = EarlyConsole {
+    uart: Uart::None,
+    framebuffer: None,
+};
+
+fn com_port(io_port: u16) -> Option<ComPortIo> {
+    [
+        ComPortIo::Com1,
+        ComPortIo::Com2,
+        ComPortIo::Com3,
+        ComPortIo::Com4,
+    ]
+    .into_iter()
+    .find(|&port| port as u16 == io_port)
+}
+
+/// The text console on the `framebuffer`, drawing into the shadow buffer
+/// in the `scratch` memory. None if either is missing or the pixel format
+/// is not supported.
+fn framebuffer_console(
+    framebuffer: &Framebuffer,
+    scratch: &FramebufferConsole,
+) -> Option<fbcon::Console<'static>> {
+    let format = fbcon::PixelFormat::from_boot_info(framebuffer)?;
+    if framebuffer.virt_base == 0
+        || scratch.virt_base == 0
+        || scratch.shadow_size < framebuffer.size
+    {
+        return None;
+    }
+
+    // SAFETY: the loader has mapped the shadow buffer of the framebuffer
+    // size for the console alone.
+    let shadow = unsafe {
+        core::slice::from_raw_parts_mut(
+            scratch.virt_base as *mut u32,
+            scratch.shadow_size as usize / core::mem::size_of::<u32>(),
+        )
+    };
+    // SAFETY: the loader has mapped the framebuffer in the device window,
+    // and the early console is the only one drawing there.
+    unsafe {
+        fbcon::Console::new(
+            fbcon::Framebuffer {
+                base_addr: framebuffer.virt_base,
+                width: framebuffer.width as usize,
+                height: framebuffer.height as usize,
+                stride: framebuffer.stride as usize,
+                format,
+            },
+            shadow,
+            fbcon::Psf2Font::default_font(),
+        )
+    }
+}
+
+/// Sets up the consoles the loader has described, nothing is printed
+/// without any.
+pub fn init(boot_info: &BootInfo) {
+    let console = EarlyConsole {
+        uart: Uart::new(&boot_info.console),
+        framebuffer: framebuffer_console(&boot_info.framebuffer, &boot_info.framebuffer_console),
+    };
+
+    // SAFETY: only the boot processor runs, and nothing prints yet.
+    unsafe { *core::ptr::addr_of_mut!(CONSOLE) = console };
+}
+
+/// The console for the code that needs `core::fmt::Write`.
+///
+/// # Safety
+///
+/// Only the boot processor prints, and the reference is not kept.
+pub unsafe fn console() -> &'static mut EarlyConsole {
+    // SAFETY: guaranteed by the caller.
+    unsafe { &mut *core::ptr::addr_of_mut!(CONSOLE) }
+}
+
+pub fn print(args: core::fmt::Arguments) {
+    // SAFETY: only the boot processor runs, and the exceptions it takes
+    // while printing halt.
+    unsafe { console() }.write_fmt(args).ok();
+}
+
+#[macro_export]
+macro_rules! early_println {
+    () => {
+        $crate::early_console::print(format_args!("\r\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::early_console::print(format_args!("{}\r\n", format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,301 @@
+//! The exception handlers for the time before the kernel has its own.
+//!
+//! The loader handlers live in the loader image, and print through the
+//! firmware-era logger. These ones print the register frame, what the
+//! processor has reported about the exception, the faulting address, and
+//! the top of the stack over the early console, and halt. That turns the
+//! early bugs into a crash report instead of a triple fault or a hang. On
+//! x86_64 the entry has loaded the tables that only halt before these are
+//! installed, as the kernel tables do not map the loader ones.
+//!
+//! The interrupts go through the same vectors to the interrupt controller
+//! code of the architecture.
+
+fn halt() -> ! {
+    kernel_main::idle::park_cpu::<crate::CurrentArch>()
+}
+
+extern "C" {
+    fn corgos_probe_write(address: u64) -> u64;
+    fn corgos_probe_write_access();
+    fn corgos_probe_write_fault();
+}
+
+/// Where to resume if the faulting instruction is expected to fault.
+fn fixup(pc: u64) -> Option<u64> {
+    (pc == corgos_probe_write_access as *const () as u64)
+        .then_some(corgos_probe_write_fault as *const () as u64)
+}
+
+/// Writes the byte at `address` back, false if that has faulted.
+///
+/// # Safety
+///
+/// The address is mapped for reading, and writing the byte back does not
+/// race with anything.
+pub unsafe fn probe_write(address: u64) -> bool {
+    // SAFETY: guaranteed by the caller, the handlers recover from the write
+    // fault.
+    unsafe { corgos_probe_write(address) == 0 }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use crate::early_println;
+    use exceptions::aarch64::Esr;
+    use exceptions::aarch64::Exception;
+    use exceptions::aarch64::ExceptionFrame;
+    use exceptions::aarch64::ExceptionHandlers;
+    use exceptions::aarch64::ExceptionKind;
+
+    fn early_fault(exception: Exception, frame: &mut ExceptionFrame, esr: Esr) {
+        if exception.kind == ExceptionKind::Synchronous && esr.is_abort() {
+            if let Some(pc) = super::fixup(frame.elr) {
+                frame.elr = pc;
+                return;
+            }
+        }
+
+        early_println!(
+            "Exception {:?} from {:?}, {:?}",
+            exception.kind,
+            exception.origin,
+            esr.class()
+        );
+        early_println!(
+            "ESR {:#016x}, FAR {:#016x}{}",
+            frame.esr,
+            frame.far,
+            if esr.far_valid() { "" } else { " (not valid)" }
+        );
+        early_println!(
+            "ELR {:#016x}, SPSR {:#016x}, SP {:#016x}",
+            frame.elr,
+            frame.spsr,
+            frame.sp
+        );
+        for (row, regs) in frame.x.chunks(4).enumerate() {
+            for (i, reg) in regs.iter().enumerate() {
+                crate::early_console::print(format_args!("x{:<2} {reg:#018x}  ", row * 4 + i));
+            }
+            early_println!();
+        }
+        crate::panic::dump_stack(frame.sp);
+
+        super::halt();
+    }
+
+    fn irq(_: Exception, _: &mut ExceptionFrame, _: Esr) {
+        crate::aarch64_interrupts::handle_irq();
+    }
+
+    static HANDLERS: ExceptionHandlers = ExceptionHandlers {
+        synchronous: early_fault,
+        irq,
+        fiq: early_fault,
+        serror: early_fault,
+    };
+
+    pub fn install() {
+        // SAFETY: the kernel runs at EL1, and the loader vectors are not
+        // needed any longer.
+        unsafe { exceptions::aarch64::install(&HANDLERS) };
+    }
+
+    pub fn install_secondary() {
+        install();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use crate::early_println;
+    use alloc::boxed::Box;
+    use exceptions::x86_64::ExceptionFrame;
+    use exceptions::x86_64::GlobalDescriptorTable;
+    use exceptions::x86_64::IdtGate;
+    use exceptions::x86_64::InterruptDescriptorTable;
+    use exceptions::x86_64::SegmentDescriptor;
+    use exceptions::x86_64::SegmentSelector;
+    use exceptions::x86_64::TaskStateSegment;
+    use exceptions::x86_64::EXCEPTION_COUNT;
+
+    /// The null descriptor, the code, the data, and the two entries of
+    /// the TSS.
+    const GDT_ENTRIES: usize = 5;
+    /// The double fault runs on its own stack as the kernel stack may be
+    /// what caused it.
+    const DOUBLE_FAULT_VECTOR: u8 = 8;
+    const DOUBLE_FAULT_IST: u8 = 1;
+    const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+    const PAGE_FAULT_VECTOR: u64 = 14;
+
+    #[repr(C, align(16))]
+    struct FaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+    static mut DOUBLE_FAULT_STACK: FaultStack = FaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+    static mut TSS: TaskStateSegment = TaskStateSegment::new();
+    static mut GDT: GlobalDescriptorTable<GDT_ENTRIES> = GlobalDescriptorTable::new();
+    static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+    /// The code and the data selectors, for the interrupt gates.
+    static mut SELECTORS: (SegmentSelector, SegmentSelector) =
+        (SegmentSelector::new(), SegmentSelector::new());
+
+    fn early_fault(frame: &mut ExceptionFrame) {
+        if frame.vector >= EXCEPTION_COUNT as u64 {
+            crate::x86_64_interrupts::handle_interrupt(frame.vector as u8);
+            return;
+        }
+        if frame.vector == PAGE_FAULT_VECTOR {
+            if let Some(pc) = super::fixup(frame.rip) {
+                frame.rip = pc;
+                return;
+            }
+        }
+
+        early_println!(
+            "Exception {}, error code {:#x}, CR2 {:#016x}",
+            frame.vector,
+            frame.error_code,
+            frame.cr2
+        );
+        early_println!(
+            "RIP {:#016x}, CS {:#06x}, RFLAGS {:#016x}",
+            frame.rip,
+            frame.cs,
+            frame.rflags
+        );
+        early_println!("RSP {:#016x}, SS {:#06x}", frame.rsp, frame.ss);
+        early_println!(
+            "RAX {:#016x}  RBX {:#016x}  RCX {:#016x}  RDX {:#016x}",
+            frame.rax,
+            frame.rbx,
+            frame.rcx,
+            frame.rdx
+        );
+        early_println!(
+            "RSI {:#016x}  RDI {:#016x}  RBP {:#016x}  R8  {:#016x}",
+            frame.rsi,
+            frame.rdi,
+            frame.rbp,
+            frame.r8
+        );
+        early_println!(
+            "R9  {:#016x}  R10 {:#016x}  R11 {:#016x}  R12 {:#016x}",
+            frame.r9,
+            frame.r10,
+            frame.r11,
+            frame.r12
+        );
+        early_println!(
+            "R13 {:#016x}  R14 {:#016x}  R15 {:#016x}",
+            frame.r13,
+            frame.r14,
+            frame.r15
+        );
+        crate::panic::dump_stack(frame.rsp);
+
+        super::halt();
+    }
+
+    pub fn install() {
+        // SAFETY: the interrupts are masked, and nothing else accesses the
+        // stack, the TSS, the GDT, or the IDT.
+        let (stack, tss, gdt, idt) = unsafe {
+            (
+                &*core::ptr::addr_of!(DOUBLE_FAULT_STACK),
+                &mut *core::ptr::addr_of_mut!(TSS),
+                &mut *core::ptr::addr_of_mut!(GDT),
+                &mut *core::ptr::addr_of_mut!(IDT),
+            )
+        };
+        tss.interrupt_stacks[DOUBLE_FAULT_IST as usize - 1] = stack.0.as_ptr_range().end as u64;
+
+        let code = gdt.add(SegmentDescriptor::kernel_code());
+        let data = gdt.add(SegmentDescriptor::kernel_data());
+        let tss = gdt.add_tss(tss);
+        // SAFETY: as above.
+        unsafe { *core::ptr::addr_of_mut!(SELECTORS) = (code, data) };
+
+        exceptions::x86_64::set_fault_handler(early_fault);
+        for vector in 0..EXCEPTION_COUNT as u8 {
+            let gate = IdtGate::interrupt(exceptions::x86_64::fault_stub(vector), code);
+            let gate = if vector == DOUBLE_FAULT_VECTOR {
+                gate.with_ist(DOUBLE_FAULT_IST)
+            } else {
+                gate
+            };
+            idt.set(vector, gate);
+        }
+
+        // SAFETY: the tables are static, the selectors refer to their
+        // descriptors, and the gates point to the fault stubs.
+        unsafe {
+            exceptions::x86_64::load_gdt(gdt, code, data);
+            exceptions::x86_64::load_tss(tss);
+            exceptions::x86_64::load_idt(idt);
+        }
+    }
+
+    /// Points the gate of the interrupt `vector` to its stub.
+    pub fn install_interrupt(vector: u8) {
+        assert!(vector as usize >= EXCEPTION_COUNT);
+        // SAFETY: `install` has set up the tables, the interrupts are
+        // masked, and the gate is written before the vector is unmasked.
+        unsafe {
+            let (code, _) = *core::ptr::addr_of!(SELECTORS);
+            let gate = IdtGate::interrupt(exceptions::x86_64::fault_stub(vector), code);
+            (*core::ptr::addr_of_mut!(IDT)).set(vector, gate);
+        }
+    }
+
+    /// What a secondary processor has of its own: loading the TSS marks
+    /// its descriptor busy, so each processor has a TSS and a GDT with the
+    /// descriptor for it, and a stack for the double fault.
+    struct SecondaryTables {
+        stack: FaultStack,
+        tss: TaskStateSegment,
+        gdt: GlobalDescriptorTable<GDT_ENTRIES>,
+    }
+
+    /// The secondary processors share the IDT, and have the same selectors
+    /// in their GDTs. Needs the allocator.
+    pub fn install_secondary() {
+        let SecondaryTables { stack, tss, gdt } = Box::leak(Box::new(SecondaryTables {
+            stack: FaultStack([0; DOUBLE_FAULT_STACK_SIZE]),
+            tss: TaskStateSegment::new(),
+            gdt: GlobalDescriptorTable::new(),
+        }));
+        tss.interrupt_stacks[DOUBLE_FAULT_IST as usize - 1] = stack.0.as_ptr_range().end as u64;
+
+        let code = gdt.add(SegmentDescriptor::kernel_code());
+        let data = gdt.add(SegmentDescriptor::kernel_data());
+        let tss = gdt.add_tss(tss);
+        // SAFETY: `install` has set up the IDT on the boot processor, and
+        // nothing changes it any longer. The GDT and the TSS are never
+        // freed.
+        unsafe {
+            exceptions::x86_64::load_gdt(gdt, code, data);
+            exceptions::x86_64::load_tss(tss);
+            exceptions::x86_64::load_idt(&*core::ptr::addr_of!(IDT));
+        }
+    }
+}
+
+/// Replaces the loader handlers, which live in the memory the kernel is
+/// going to reclaim.
+pub fn install() {
+    arch::install();
+}
+
+/// Installs the handlers on a secondary processor, before anything else
+/// as that reloads the segment registers on x86_64.
+pub fn install_secondary() {
+    arch::install_secondary();
+}
+
+/// Routes the interrupt `vector` to the interrupt controller code.
+#[cfg(target_arch = "x86_64")]
+pub fn install_interrupt(vector: u8) {
+    arch::install_interrupt(vector);
+}
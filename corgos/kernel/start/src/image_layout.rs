@@ -1,29 +1,30 @@
-#![allow(dead_code)]
-
 extern "C" {
-    fn _page_tables_start();
-    fn _page_tables_end();
+    fn _bss_start();
+    fn _bss_end();
 }
 
-pub fn page_tables_phys_start() -> usize {
-    _page_tables_start as usize
+/// The entry zeroes the BSS in 16-byte steps, so it is 16-byte aligned
+/// and sized.
+pub fn bss() -> &'static [u64] {
+    let s = _bss_start as usize;
+    let e = _bss_end as usize;
+    unsafe { core::slice::from_raw_parts(s as *const u64, (e - s) / 8) }
 }
 
-pub fn page_tables_phys_end() -> usize {
-    _page_tables_end as usize
+extern "C" {
+    fn _stack_top();
+    fn _stack_bot();
 }
 
-pub fn page_tables_area() -> &'static mut [u8] {
-    let s = page_tables_phys_start();
-    let e = page_tables_phys_end();
-    unsafe { core::slice::from_raw_parts_mut(s as *mut u8, e - s) }
+/// The stack in the image, the entry runs on it when the loader has not
+/// set up one. `_stack_top` is the lowest address.
+pub fn stack() -> core::ops::Range<usize> {
+    _stack_top as usize.._stack_bot as usize
 }
 
 extern "C" {
     fn _base();
     fn _end();
-    fn _image_size();
-    fn _payload_start();
 }
 
 pub fn base() -> usize {
@@ -33,11 +34,3 @@ pub fn base() -> usize {
 pub fn end() -> usize {
     _end as usize
 }
-
-pub fn size() -> usize {
-    _image_size as usize
-}
-
-pub fn payload_start() -> usize {
-    _payload_start as usize
-}
@@ -1,32 +1,125 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64_arch;
+#[cfg(target_arch = "aarch64")]
+mod aarch64_interrupts;
+#[cfg(target_arch = "aarch64")]
+mod aarch64_smp;
+mod early_console;
+mod early_exceptions;
 mod image_layout;
+mod panic;
+mod per_cpu;
+mod qemu;
+#[cfg(target_arch = "x86_64")]
+mod x86_64_arch;
+#[cfg(target_arch = "x86_64")]
+mod x86_64_interrupts;
+#[cfg(target_arch = "x86_64")]
+mod x86_64_smp;
+
+use core::ops::Range;
+use kernel_main::BootInfo;
+
+#[cfg(target_arch = "aarch64")]
+type CurrentArch = aarch64_arch::Aarch64;
+#[cfg(target_arch = "x86_64")]
+type CurrentArch = x86_64_arch::X86_64;
 
+/// Called by `kernel_entry` with the BSS zeroed and on the kernel stack.
+/// The loader passes the linear map address of the boot info in the first
+/// argument register, see `handoff` in the loader.
 #[no_mangle]
-pub extern "C" fn kernel_start() -> ! {
-    todo!("Kernel stub");
+extern "C" fn kernel_start(boot_info: *const bootinfo::BootInfo) -> ! {
+    // Catches the loader or the entry leaving garbage in the BSS.
+    #[cfg(debug_assertions)]
+    if let Some(offset) = image_layout::bss().iter().position(|&word| word != 0) {
+        panic!("The BSS is not zeroed at offset {:#x}", offset * 8);
+    }
+
+    // Halts on a fault even with no console to report it.
+    early_exceptions::install();
+
+    // SAFETY: the loader has passed the boot info, and its memory is not
+    // reclaimed by anything yet.
+    let boot_info = match unsafe { BootInfo::from_ptr(boot_info) } {
+        Ok(boot_info) => boot_info,
+        Err(e) => panic!("Invalid boot info: {e:?}"),
+    };
+    // SAFETY: the entry has switched to the stack, and it is mapped.
+    unsafe { kernel_main::initial_stack::paint(kernel_stack(&boot_info)) };
+    early_console::init(&boot_info);
+    kernel_main::params::init(&boot_info);
+    kernel_main::logger::init::<CurrentArch>(&boot_info);
+    qemu::init(&boot_info);
+    panic::init(&boot_info);
+
+    kernel_main::kernel_main::<CurrentArch>(boot_info)
+}
+
+/// The stack the loader has set up, or the one in the image if it has not.
+fn kernel_stack(boot_info: &BootInfo) -> Range<u64> {
+    let kernel_stack = boot_info.kernel_stack;
+    if kernel_stack.size != 0 {
+        kernel_stack.virt_base..kernel_stack.virt_base + kernel_stack.size
+    } else {
+        let stack = image_layout::stack();
+        stack.start as u64..stack.end as u64
+    }
 }
 
+/// Called by the start code of the architecture on a secondary processor,
+/// with the translations of the boot processor and on its own stack.
 #[no_mangle]
-extern "C" fn rust_eh_personality() {
-    core::hint::spin_loop();
+extern "C" fn kernel_start_secondary(cpu_id: u32) -> ! {
+    kernel_main::smp::secondary_main::<CurrentArch>(cpu_id)
 }
 
+// Never called, the kernel aborts on panics.
 #[no_mangle]
-extern "C" fn rust_eh_unwind_resume(_: &i8) {
-    core::hint::spin_loop();
+extern "C" fn rust_eh_personality() {
+    kernel_main::idle::park_cpu::<CurrentArch>()
 }
 
-#[cfg_attr(feature = "kernel_build", panic_handler)]
-#[cfg_attr(not(feature = "kernel_build"), allow(unused))]
-fn panic_handler(_pi: &core::panic::PanicInfo<'_>) -> ! {
-    core::hint::spin_loop();
-    unsafe { core::hint::unreachable_unchecked() }
+#[no_mangle]
+extern "C" fn rust_eh_unwind_resume(_: &i8) {
+    kernel_main::idle::park_cpu::<CurrentArch>()
 }
 
 #[cfg(target_arch = "aarch64")]
-core::arch::global_asm!(include_str!("start-aarch64.S"));
+core::arch::global_asm!(
+    include_str!("start-aarch64.S"),
+    boot_info_kernel_flags = const core::mem::offset_of!(bootinfo::BootInfo, kernel.flags),
+    boot_info_stack_top = const core::mem::offset_of!(bootinfo::BootInfo, kernel_stack.top),
+    kernel_image_bss_zeroed_bit = const bootinfo::KERNEL_IMAGE_BSS_ZEROED.trailing_zeros(),
+    secondary_start_mair = const core::mem::offset_of!(aarch64_smp::SecondaryStart, mair),
+    secondary_start_tcr = const core::mem::offset_of!(aarch64_smp::SecondaryStart, tcr),
+    secondary_start_ttbr0 = const core::mem::offset_of!(aarch64_smp::SecondaryStart, ttbr0),
+    secondary_start_ttbr1 = const core::mem::offset_of!(aarch64_smp::SecondaryStart, ttbr1),
+    secondary_start_sctlr = const core::mem::offset_of!(aarch64_smp::SecondaryStart, sctlr),
+    secondary_start_cpacr = const core::mem::offset_of!(aarch64_smp::SecondaryStart, cpacr),
+    secondary_start_stack_top = const core::mem::offset_of!(aarch64_smp::SecondaryStart, stack_top),
+    secondary_start_entry = const core::mem::offset_of!(aarch64_smp::SecondaryStart, entry),
+    secondary_start_cpu_id = const core::mem::offset_of!(aarch64_smp::SecondaryStart, cpu_id),
+);
 
 #[cfg(target_arch = "x86_64")]
-core::arch::global_asm!(include_str!("start-x86_64.S"));
+core::arch::global_asm!(
+    include_str!("start-x86_64.S"),
+    boot_info_kernel_flags = const core::mem::offset_of!(bootinfo::BootInfo, kernel.flags),
+    boot_info_stack_top = const core::mem::offset_of!(bootinfo::BootInfo, kernel_stack.top),
+    kernel_image_bss_zeroed_bit = const bootinfo::KERNEL_IMAGE_BSS_ZEROED.trailing_zeros(),
+    ap_params_size = const core::mem::size_of::<x86_64_smp::TrampolineParams>(),
+    ap_params_startup_cr3 = const core::mem::offset_of!(x86_64_smp::TrampolineParams, startup_cr3),
+    ap_params_cr0 = const core::mem::offset_of!(x86_64_smp::TrampolineParams, cr0),
+    ap_params_cr3 = const core::mem::offset_of!(x86_64_smp::TrampolineParams, cr3),
+    ap_params_cr4 = const core::mem::offset_of!(x86_64_smp::TrampolineParams, cr4),
+    ap_params_efer = const core::mem::offset_of!(x86_64_smp::TrampolineParams, efer),
+    ap_params_entry = const core::mem::offset_of!(x86_64_smp::TrampolineParams, entry),
+    ap_params_stack_top = const core::mem::offset_of!(x86_64_smp::TrampolineParams, stack_top),
+    ap_params_cpu_id = const core::mem::offset_of!(x86_64_smp::TrampolineParams, cpu_id),
+);
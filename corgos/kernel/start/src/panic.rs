@@ -0,0 +1,129 @@
+//! The kernel panic handler: prints the message, the backtrace, and the
+//! top of the stack over the early console, asks QEMU to exit if running
+//! there, and parks the processor.
+//!
+//! The backtrace follows the frame records the kernel is built to keep
+//! (`-C force-frame-pointers=yes`) within the kernel stack, see the
+//! `backtrace` crate. The entry clears the frame pointer, so the chain ends
+//! at `kernel_start` and does not go on to the loader stack. The return
+//! addresses are symbolized with the symbol table the loader has passed,
+//! and printed raw if there is none.
+//!
+//! The early exception handlers dump the stack the same way.
+
+use crate::early_println;
+use crate::image_layout;
+use crate::qemu;
+use crate::CurrentArch;
+use bootinfo::KernelSymbols;
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+use core::ops::Range;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use kernel_main::idle::park_cpu;
+use kernel_main::BootInfo;
+
+/// The bytes of the stack to dump.
+const STACK_DUMP_SIZE: u64 = 0x100;
+const PAGE_SIZE: u64 = 0x1000;
+
+struct PanicState {
+    symbols: KernelSymbols,
+    /// Where the frame records may be.
+    stack: Range<u64>,
+}
+
+static mut STATE: Option<PanicState> = None;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Takes what the backtraces and the exit need from the boot info. The
+/// panics before that only park the processor, there is no console to
+/// report them to yet.
+pub fn init(boot_info: &BootInfo) {
+    let state = PanicState {
+        symbols: boot_info.kernel_symbols,
+        stack: crate::kernel_stack(boot_info),
+    };
+    // SAFETY: only the boot processor runs, and nothing panics concurrently.
+    unsafe { *core::ptr::addr_of_mut!(STATE) = Some(state) };
+}
+
+fn print_frame(index: usize, address: u64, symbols: &KernelSymbols) {
+    // The return address is past the call, and may be past the end of the
+    // function that does not return.
+    // SAFETY: the loader has placed the symbols in the memory the linear
+    // map covers, and nothing has reclaimed it.
+    match unsafe { symbols.lookup(symbols.virt_base, address - 1) } {
+        Some((name, offset)) => {
+            early_println!("  #{index:<2} {address:#018x} {name}+{:#x}", offset + 1)
+        }
+        None => early_println!("  #{index:<2} {address:#018x}"),
+    }
+}
+
+fn backtrace(state: &PanicState) {
+    early_println!("Backtrace:");
+
+    let fp = backtrace::frame_pointer();
+    let code = image_layout::base() as u64..image_layout::end() as u64;
+    // SAFETY: the kernel stack is mapped.
+    let frames = unsafe { backtrace::Frames::new(fp, state.stack.clone()) }.code(code);
+    for (index, return_address) in frames.enumerate() {
+        print_frame(index, return_address, &state.symbols);
+    }
+}
+
+/// Dumps the stack from `sp` up, as much of it as is in the kernel stack.
+pub fn dump_stack(sp: u64) {
+    // SAFETY: written once before anything may fault with the console up.
+    let Some(state) = (unsafe { &*core::ptr::addr_of!(STATE) }) else {
+        return;
+    };
+
+    // The stack as the only region of the window, at no offset as it is
+    // not in the linear map.
+    let stack = MemoryRegion {
+        start: state.stack.start,
+        pages: (state.stack.end - state.stack.start) / PAGE_SIZE,
+        kind: MemoryKind::KernelStack,
+        reserved: 0,
+        attributes: 0,
+    };
+    let window = meminspect::Window::new(core::slice::from_ref(&stack), 0);
+    let size = STACK_DUMP_SIZE.min(state.stack.end.saturating_sub(sp));
+    match window.hex_lines(sp, size) {
+        Ok(lines) => {
+            early_println!("Stack:");
+            for line in lines {
+                early_println!("  {line}");
+            }
+        }
+        Err(e) => early_println!("Stack pointer {sp:#018x} is not in the kernel stack: {e:?}"),
+    };
+}
+
+#[cfg_attr(feature = "kernel_build", panic_handler)]
+#[cfg_attr(not(feature = "kernel_build"), allow(unused))]
+fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
+    // A panic while reporting one would likely panic again.
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        park_cpu::<CurrentArch>();
+    }
+
+    // SAFETY: written once before anything may panic with the console up.
+    let Some(state) = (unsafe { &*core::ptr::addr_of!(STATE) }) else {
+        park_cpu::<CurrentArch>();
+    };
+
+    early_println!("Kernel panic: {info}");
+    if kernel_main::initial_stack::guard_intact() == Some(false) {
+        early_println!("Initial stack guard is overwritten, the stack may have overflowed");
+    }
+    backtrace(state);
+    dump_stack(backtrace::frame_pointer());
+
+    qemu::exit(false);
+    park_cpu::<CurrentArch>()
+}
@@ -0,0 +1,186 @@
+//! The per-CPU data.
+//!
+//! Each processor gets a [`PerCpu`] block of its own, and points a register
+//! to it: `TPIDR_EL1` on aarch64, `GS_BASE` on x86_64. The block starts
+//! with its own address, so getting to it takes one or two instructions and
+//! no lock, and the processor index has a faster path still, see
+//! [`cpu_id`]. The blocks are allocated for all the processors the loader
+//! has reported, the secondary ones call [`install`] once they run.
+//!
+//! The fields are read with [`per_cpu!`](crate::per_cpu). Those changed
+//! after the set up must be safe to share as the other processors may read
+//! them, too.
+
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use kernel_main::BootInfo;
+
+#[repr(C, align(64))]
+pub struct PerCpu {
+    /// The block itself, read through the register.
+    this: *const PerCpu,
+    /// The index into the processors of the boot info.
+    pub cpu_id: u32,
+    /// The NUMA node.
+    pub node: u32,
+    /// MPIDR affinity on aarch64, local APIC ID on x86_64.
+    pub hw_id: u64,
+}
+
+// SAFETY: the processors only read the fields shared with the others.
+unsafe impl Sync for PerCpu {}
+
+/// The fast paths rely on the layout.
+const _: () = assert!(core::mem::offset_of!(PerCpu, this) == 0);
+const CPU_ID_OFFSET: usize = core::mem::offset_of!(PerCpu, cpu_id);
+
+static BLOCKS: AtomicPtr<PerCpu> = AtomicPtr::new(core::ptr::null_mut());
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The field of the block of the current processor, or of the processor
+/// with the index.
+#[macro_export]
+macro_rules! per_cpu {
+    ($field:ident) => {
+        &$crate::per_cpu::this_cpu().$field
+    };
+    ($field:ident, $cpu:expr) => {
+        &$crate::per_cpu::cpu($cpu)
+            .expect("No such processor")
+            .$field
+    };
+}
+
+/// The block of the current processor.
+#[inline]
+pub fn this_cpu() -> &'static PerCpu {
+    let this: *const PerCpu;
+    // SAFETY: the register points to a block since `init` or `install`
+    // has run on this processor, and the block starts with its address.
+    unsafe {
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("mrs {}, tpidr_el1", out(reg) this, options(nomem, nostack, preserves_flags));
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("mov {}, gs:[0]", out(reg) this, options(readonly, nostack, preserves_flags));
+        &*this
+    }
+}
+
+/// The index of the current processor.
+#[inline]
+pub fn cpu_id() -> u32 {
+    let cpu_id: u32;
+    // SAFETY: as in `this_cpu`.
+    unsafe {
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!(
+            "mrs {tmp}, tpidr_el1",
+            "ldr {cpu_id:w}, [{tmp}, #{offset}]",
+            tmp = out(reg) _,
+            cpu_id = lateout(reg) cpu_id,
+            offset = const CPU_ID_OFFSET,
+            options(readonly, nostack, preserves_flags)
+        );
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!(
+            "mov {:e}, gs:[{offset}]",
+            out(reg) cpu_id,
+            offset = const CPU_ID_OFFSET,
+            options(readonly, nostack, preserves_flags)
+        );
+    }
+    cpu_id
+}
+
+pub fn count() -> usize {
+    COUNT.load(Ordering::Acquire)
+}
+
+/// The block of the processor with the index, none before `init` or if
+/// there is no such processor.
+pub fn cpu(cpu_id: usize) -> Option<&'static PerCpu> {
+    let blocks = BLOCKS.load(Ordering::Acquire);
+    if blocks.is_null() || cpu_id >= count() {
+        return None;
+    }
+    // SAFETY: the blocks are never freed, and the index is in range.
+    Some(unsafe { &*blocks.add(cpu_id) })
+}
+
+/// Points the register of the current processor to its block.
+///
+/// # Safety
+///
+/// Runs on the processor with the index, and nothing else uses the
+/// register.
+pub unsafe fn install(cpu_id: usize) {
+    let block = cpu(cpu_id).expect("No such processor") as *const PerCpu as u64;
+    // SAFETY: guaranteed by the caller.
+    unsafe {
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("msr tpidr_el1, {}", in(reg) block, options(nomem, nostack, preserves_flags));
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!(
+            "wrmsr",
+            in("ecx") 0xc000_0101_u32,
+            in("eax") block as u32,
+            in("edx") (block >> 32) as u32,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// Allocates the blocks for the processors the loader has reported, and
+/// installs the one of the boot processor. Needs the allocator.
+pub fn init(boot_info: &BootInfo) {
+    let cpus = boot_info.cpus();
+    let boot_cpu_id = cpus
+        .cpus()
+        .iter()
+        .position(|cpu| cpu.hw_id == cpus.boot_cpu_hw_id)
+        .unwrap_or_else(|| {
+            log::warn!(
+                "The boot processor {:#x} is not in the processor list",
+                cpus.boot_cpu_hw_id
+            );
+            0
+        });
+
+    let mut blocks = Vec::with_capacity(cpus.cpus().len().max(1));
+    for (cpu_id, cpu) in cpus.cpus().iter().enumerate() {
+        blocks.push(PerCpu {
+            this: core::ptr::null(),
+            cpu_id: cpu_id as u32,
+            node: cpu.node,
+            hw_id: cpu.hw_id,
+        });
+    }
+    if blocks.is_empty() {
+        blocks.push(PerCpu {
+            this: core::ptr::null(),
+            cpu_id: 0,
+            node: 0,
+            hw_id: cpus.boot_cpu_hw_id,
+        });
+    }
+
+    let blocks = blocks.leak();
+    for block in blocks.iter_mut() {
+        block.this = block;
+    }
+    COUNT.store(blocks.len(), Ordering::Release);
+    BLOCKS.store(blocks.as_mut_ptr(), Ordering::Release);
+
+    // SAFETY: runs on the boot processor, and nothing has used the
+    // register yet.
+    unsafe { install(boot_cpu_id) };
+    log::info!(
+        "Per-CPU blocks for {} processors, the boot one is {} ({:#x}) on node {}",
+        count(),
+        cpu_id(),
+        per_cpu!(hw_id),
+        per_cpu!(node)
+    );
+}
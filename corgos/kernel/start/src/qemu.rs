@@ -0,0 +1,68 @@
+//! Asking QEMU to exit, for the panics and the integration tests. Needs
+//! `-semihosting` or `isa-debug-exit` on the QEMU command line, see
+//! `run.py`. Elsewhere, the exit is not attempted.
+
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use kernel_main::BootInfo;
+
+static UNDER_QEMU: AtomicBool = AtomicBool::new(false);
+
+pub fn init(boot_info: &BootInfo) {
+    UNDER_QEMU.store(under_qemu(boot_info), Ordering::Relaxed);
+}
+
+/// QEMU with KVM says KVM in the hypervisor CPUID leaf, and so do the
+/// other VMMs on KVM, so QEMU is told by the signature of its `fw_cfg`
+/// device. The ports are only probed under a hypervisor, and
+/// `isa-debug-exit` is only looked for under QEMU: the port is the GPIO
+/// one on some chipsets.
+#[cfg(target_arch = "x86_64")]
+fn under_qemu(_boot_info: &BootInfo) -> bool {
+    use core::arch::asm;
+    use raw_cpuid::CpuId;
+
+    const FW_CFG_SELECTOR: u16 = 0x510;
+    const FW_CFG_DATA: u16 = 0x511;
+    const FW_CFG_SIGNATURE: u16 = 0;
+
+    if CpuId::new().get_hypervisor_info().is_none() {
+        return false;
+    }
+
+    let mut signature = [0_u8; 4];
+    // SAFETY: the selector and the data ports of `fw_cfg` are not used by
+    // anything else in the virtual machines.
+    unsafe {
+        asm!("out dx, ax", in("dx") FW_CFG_SELECTOR, in("ax") FW_CFG_SIGNATURE, options(nomem, nostack));
+        for byte in &mut signature {
+            asm!("in al, dx", in("dx") FW_CFG_DATA, out("al") *byte, options(nomem, nostack));
+        }
+    }
+    signature == *b"QEMU"
+}
+
+/// There is no safe probe for the semihosting, the trap is an undefined
+/// instruction without a debugger or QEMU to catch it. The PL011 of the
+/// QEMU `virt` machine is the tell.
+#[cfg(target_arch = "aarch64")]
+fn under_qemu(boot_info: &BootInfo) -> bool {
+    const QEMU_VIRT_PL011: u64 = 0x0900_0000;
+
+    boot_info.console.kind == bootinfo::ConsoleKind::Pl011
+        && boot_info.console.phys_base == QEMU_VIRT_PL011
+}
+
+/// Exits with the success or the failure code, returns if not under QEMU.
+/// QEMU exits with 15 or 1 for `isa-debug-exit`, and with 0 or 1 for the
+/// semihosting.
+pub fn exit(success: bool) {
+    if !UNDER_QEMU.load(Ordering::Relaxed) {
+        return;
+    }
+    if success {
+        semihosting::Semihosting.exit_host_success();
+    } else {
+        semihosting::Semihosting.exit_host_failure();
+    }
+}
@@ -0,0 +1,180 @@
+//! The `kernel_main` architecture layer on x86_64: the early console,
+//! RFLAGS.IF, the TSC and the local APIC timer, the CR3 translations, and
+//! starting the secondary processors.
+
+use crate::early_exceptions;
+use crate::per_cpu;
+use crate::qemu;
+use crate::x86_64_interrupts;
+use crate::x86_64_smp;
+use kernel_main::arch;
+use kernel_main::arch::StartCpuError;
+use kernel_main::arch::TimerError;
+use kernel_main::smp;
+use kernel_main::BootInfo;
+
+pub struct X86_64;
+
+pub struct Console;
+
+impl arch::Console for Console {
+    fn write_str(s: &str) {
+        crate::early_console::print(format_args!("{s}"));
+    }
+}
+
+/// Reads an MSR, the callers only read those the processor has.
+pub fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    // SAFETY: see above, reading has no side effects.
+    unsafe {
+        core::arch::asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack))
+    };
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes an MSR the processor has, the callers only write those that do
+/// not change the memory safety.
+pub fn write_msr(msr: u32, value: u64) {
+    // SAFETY: see above.
+    unsafe {
+        core::arch::asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack)
+        )
+    };
+}
+
+/// RFLAGS.IF.
+const RFLAGS_IF: u64 = 1 << 9;
+
+pub struct Interrupts;
+
+// Not `nomem`, the compiler must not move the memory accesses across
+// enabling or disabling the interrupts.
+impl arch::Interrupts for Interrupts {
+    unsafe fn enable() {
+        // SAFETY: the caller has installed the handlers.
+        unsafe { core::arch::asm!("sti", options(nostack)) };
+    }
+
+    fn disable() {
+        // SAFETY: masking the interrupts has no effect on the memory.
+        unsafe { core::arch::asm!("cli", options(nostack)) };
+    }
+
+    fn are_enabled() -> bool {
+        let rflags: u64;
+        // SAFETY: the push is popped right back.
+        unsafe { core::arch::asm!("pushfq", "pop {}", out(reg) rflags, options(nomem)) };
+        rflags & RFLAGS_IF != 0
+    }
+}
+
+pub struct Timer;
+
+impl arch::Timer for Timer {
+    fn ticks() -> u64 {
+        // SAFETY: the TSC is readable at CPL 0.
+        unsafe { core::arch::x86_64::_rdtsc() }
+    }
+
+    fn start_periodic(boot_info: &BootInfo, hz: u32) -> Result<(), TimerError> {
+        x86_64_interrupts::start_periodic(boot_info, hz)
+    }
+}
+
+/// The physical address bits of CR3, without the PCID and the flags.
+const CR3_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+pub struct Mmu;
+
+impl arch::Mmu for Mmu {
+    fn root_table() -> u64 {
+        let cr3: u64;
+        // SAFETY: reading CR3 has no side effects.
+        unsafe { core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack)) };
+        cr3 & CR3_ADDRESS_MASK
+    }
+
+    // The secondary processors idle with the interrupts masked, and take
+    // no shootdown IPIs, so the translations only change before they
+    // start.
+    fn flush_page(virt: u64) {
+        assert_eq!(smp::online_cpus(), 1, "No TLB shootdown on x86_64");
+        // SAFETY: invalidating a translation has no effect on the memory
+        // contents.
+        unsafe { core::arch::asm!("invlpg [{}]", in(reg) virt, options(nostack)) };
+    }
+
+    fn flush_all() {
+        assert_eq!(smp::online_cpus(), 1, "No TLB shootdown on x86_64");
+        // SAFETY: reloading CR3 with its own value only drops the
+        // non-global translations.
+        unsafe {
+            core::arch::asm!(
+                "mov {tmp}, cr3",
+                "mov cr3, {tmp}",
+                tmp = out(reg) _,
+                options(nostack)
+            )
+        };
+    }
+
+    unsafe fn is_writable(virt: u64) -> bool {
+        // SAFETY: guaranteed by the caller.
+        unsafe { early_exceptions::probe_write(virt) }
+    }
+}
+
+impl arch::Arch for X86_64 {
+    const NAME: &'static str = "x86_64";
+
+    type Console = Console;
+    type Interrupts = Interrupts;
+    type Timer = Timer;
+    type Mmu = Mmu;
+
+    fn init_per_cpu(boot_info: &BootInfo) {
+        per_cpu::init(boot_info);
+    }
+
+    fn start_cpu(boot_info: &BootInfo, cpu_id: u32, stack_top: u64) -> Result<(), StartCpuError> {
+        x86_64_smp::start_cpu(boot_info, cpu_id, stack_top)
+    }
+
+    fn init_secondary_cpu(cpu_id: u32) {
+        x86_64_smp::init_apic();
+        early_exceptions::install_secondary();
+        // SAFETY: runs once on the processor, the boot one has set up the
+        // blocks before starting it.
+        unsafe { per_cpu::install(cpu_id as usize) };
+    }
+
+    fn cpu_id() -> u32 {
+        per_cpu::cpu_id()
+    }
+
+    fn halt() {
+        // SAFETY: halting has no effect on the memory. With the interrupts
+        // masked, only an NMI or an INIT ends it.
+        unsafe { core::arch::asm!("hlt", options(nomem, nostack)) };
+    }
+
+    fn exit_qemu(success: bool) {
+        qemu::exit(success);
+    }
+
+    fn read_rtc(boot_info: &BootInfo) -> Option<rtc::DateTime> {
+        if boot_info.rtc.kind != bootinfo::RtcKind::Cmos {
+            return None;
+        }
+        // SAFETY: the loader has found the CMOS clock, and the kernel has
+        // no other use for the ports. The reads are not serialized across
+        // the processors, only the boot one reads the clock.
+        unsafe { rtc::Cmos::new(boot_info.rtc.century_register) }.read()
+    }
+}
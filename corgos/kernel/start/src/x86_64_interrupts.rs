@@ -0,0 +1,184 @@
+//! The timer interrupt on x86_64: the local APIC timer of the boot
+//! processor, in the TSC-deadline mode if the processor has it, and in the
+//! periodic mode calibrated against the TSC otherwise. The legacy PICs are
+//! masked, so only the local APIC interrupts arrive.
+//!
+//! The registers are the MSRs in the x2APIC mode, and in the window the
+//! loader has mapped in the xAPIC mode.
+
+use crate::early_exceptions;
+use crate::x86_64_arch::read_msr;
+use crate::x86_64_arch::write_msr;
+use bootinfo::ApicMode;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use kernel_main::arch::TimerError;
+use kernel_main::time;
+use kernel_main::BootInfo;
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Readable;
+use mmio::Register;
+use mmio::RegisterBlock;
+use mmio::Writable;
+use mmio::WriteOnly;
+use raw_cpuid::CpuId;
+
+pub const TIMER_VECTOR: u8 = 0x20;
+/// The local APIC does not expect an EOI for it.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+const APIC_EOI: Register<u32, WriteOnly> = Register::new(0x0b0);
+const APIC_SVR: Register<u32, ReadWrite> = Register::new(0x0f0);
+const APIC_LVT_TIMER: Register<u32, ReadWrite> = Register::new(0x320);
+const APIC_TIMER_INITIAL_COUNT: Register<u32, ReadWrite> = Register::new(0x380);
+const APIC_TIMER_CURRENT_COUNT: Register<u32, ReadOnly> = Register::new(0x390);
+const APIC_TIMER_DIVIDE: Register<u32, ReadWrite> = Register::new(0x3e0);
+
+const SVR_APIC_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 0b01 << 17;
+const LVT_TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// The x2APIC registers are the MSRs from here, at the xAPIC offset
+/// divided by 16.
+const X2APIC_MSR_BASE: u32 = 0x800;
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+
+const PIC1_DATA: u16 = 0x21;
+const PIC2_DATA: u16 = 0xa1;
+
+/// How long the local APIC timer counts against the TSC.
+const CALIBRATION_US: u64 = 10_000;
+
+static X2APIC: AtomicBool = AtomicBool::new(false);
+/// The xAPIC window, unused in the x2APIC mode.
+static XAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+/// The TSC ticks of a period in the TSC-deadline mode, `0` in the periodic
+/// mode.
+static DEADLINE_INTERVAL: AtomicU64 = AtomicU64::new(0);
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+fn x2apic_msr<A>(reg: Register<u32, A>) -> u32 {
+    X2APIC_MSR_BASE + (reg.offset() >> 4) as u32
+}
+
+fn xapic() -> RegisterBlock {
+    // SAFETY: the loader has mapped the registers in the device window.
+    unsafe { RegisterBlock::new(XAPIC_BASE.load(Ordering::Relaxed)) }
+}
+
+fn read<A: Readable>(reg: Register<u32, A>) -> u32 {
+    if X2APIC.load(Ordering::Relaxed) {
+        return read_msr(x2apic_msr(reg)) as u32;
+    }
+    xapic().read(reg)
+}
+
+fn write<A: Writable>(reg: Register<u32, A>, value: u32) {
+    if X2APIC.load(Ordering::Relaxed) {
+        return write_msr(x2apic_msr(reg), value as u64);
+    }
+    xapic().write(reg, value)
+}
+
+fn rdtsc() -> u64 {
+    // SAFETY: the TSC is readable at CPL 0.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// The firmware may have left the PIC interrupts routed through LINT0.
+fn mask_legacy_pics() {
+    // SAFETY: masking all the lines of the PICs has no effect on the
+    // memory.
+    unsafe {
+        core::arch::asm!("out dx, al", in("dx") PIC1_DATA, in("al") 0xff_u8, options(nomem, nostack));
+        core::arch::asm!("out dx, al", in("dx") PIC2_DATA, in("al") 0xff_u8, options(nomem, nostack));
+    }
+}
+
+/// Counts the local APIC timer ticks of a period against the TSC.
+fn calibrate(boot_info: &BootInfo, hz: u32) -> Result<u32, TimerError> {
+    write(APIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(APIC_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
+    write(APIC_TIMER_INITIAL_COUNT, u32::MAX);
+    time::delay_us::<crate::CurrentArch>(boot_info, CALIBRATION_US);
+    let counted = u32::MAX - read(APIC_TIMER_CURRENT_COUNT);
+    write(APIC_TIMER_INITIAL_COUNT, 0);
+
+    let per_second = counted as u64 * (1_000_000 / CALIBRATION_US);
+    match u32::try_from(per_second / hz as u64) {
+        Ok(0) | Err(_) => Err(TimerError::NoFrequency),
+        Ok(count) => Ok(count),
+    }
+}
+
+/// Starts the local APIC timer of the boot processor, see the module docs.
+pub fn start_periodic(boot_info: &BootInfo, hz: u32) -> Result<(), TimerError> {
+    let apic = &boot_info.apic;
+    match apic.mode {
+        ApicMode::X2Apic => X2APIC.store(true, Ordering::Relaxed),
+        ApicMode::XApic if apic.virt_base != 0 => {
+            XAPIC_BASE.store(apic.virt_base, Ordering::Relaxed)
+        }
+        _ => return Err(TimerError::NoInterruptController),
+    }
+    let interval = boot_info.timer.frequency / hz as u64;
+    if interval == 0 {
+        return Err(TimerError::NoFrequency);
+    }
+
+    mask_legacy_pics();
+    early_exceptions::install_interrupt(TIMER_VECTOR);
+    early_exceptions::install_interrupt(SPURIOUS_VECTOR);
+    write(APIC_SVR, SVR_APIC_ENABLE | SPURIOUS_VECTOR as u32);
+
+    let tsc_deadline = CpuId::new()
+        .get_feature_info()
+        .is_some_and(|info| info.has_tsc_deadline());
+    if tsc_deadline {
+        DEADLINE_INTERVAL.store(interval, Ordering::Relaxed);
+        write(APIC_LVT_TIMER, LVT_TIMER_TSC_DEADLINE | TIMER_VECTOR as u32);
+        // Orders the LVT write before the deadline in the xAPIC mode.
+        // SAFETY: a fence has no effect on the memory contents.
+        unsafe { core::arch::asm!("mfence", options(nostack)) };
+        let deadline = rdtsc() + interval;
+        NEXT_DEADLINE.store(deadline, Ordering::Relaxed);
+        write_msr(IA32_TSC_DEADLINE, deadline);
+        log::debug!("Local APIC timer in the TSC-deadline mode, {interval} TSC ticks");
+    } else {
+        let count = calibrate(boot_info, hz)?;
+        write(APIC_LVT_TIMER, LVT_TIMER_PERIODIC | TIMER_VECTOR as u32);
+        write(APIC_TIMER_INITIAL_COUNT, count);
+        log::debug!("Local APIC timer in the periodic mode, {count} ticks / 16");
+    }
+    Ok(())
+}
+
+/// Called from the stubs for the vectors above the exceptions.
+pub fn handle_interrupt(vector: u8) {
+    match vector {
+        SPURIOUS_VECTOR => return,
+        TIMER_VECTOR => match DEADLINE_INTERVAL.load(Ordering::Relaxed) {
+            0 => time::tick(),
+            interval => {
+                // Catches up by the whole periods, so the ticks keep in
+                // step with the TSC when an interrupt comes late.
+                let now = rdtsc();
+                let mut deadline = NEXT_DEADLINE.load(Ordering::Relaxed);
+                while deadline <= now {
+                    deadline += interval;
+                    time::tick();
+                }
+                NEXT_DEADLINE.store(deadline, Ordering::Relaxed);
+                write_msr(IA32_TSC_DEADLINE, deadline);
+            }
+        },
+        // Nothing else is unmasked, the EOI below keeps a stray one from
+        // blocking the lower priorities.
+        _ => {}
+    }
+    write(APIC_EOI, 0);
+}
@@ -0,0 +1,237 @@
+//! Starting the secondary processors on x86_64 with INIT-SIPI-SIPI.
+//!
+//! The processors start in the real mode at the trampoline the loader has
+//! reserved below 1 MiB. The kernel copies the trampoline code from
+//! `start-x86_64.S` to the first page, and builds the startup tables in the
+//! pages after it: the first 2 MiB at their physical addresses, and the
+//! kernel half with the entries of the kernel root table. The trampoline
+//! switches to the long mode with those, and jumps to `corgos_ap_entry`,
+//! that loads the control registers of the boot processor and calls
+//! `kernel_start_secondary`. The processors come out of INIT in the xAPIC
+//! mode, and [`init_apic`] switches them to the x2APIC mode if the boot
+//! processor is in it, as there is no xAPIC window mapped then.
+
+use crate::x86_64_arch::read_msr;
+use crate::x86_64_arch::write_msr;
+use bootinfo::Apic;
+use bootinfo::ApicMode;
+use bootinfo::CpuEnableMethod;
+use bootinfo::AP_TRAMPOLINE_PAGES;
+use core::ops::Range;
+use core::ptr::addr_of;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use kernel_main::arch::Arch;
+use kernel_main::arch::Mmu;
+use kernel_main::arch::StartCpuError;
+use kernel_main::linear_map;
+use kernel_main::time;
+use kernel_main::BootInfo;
+use kernel_main::PhysAddr;
+use page_tables::x86_64::MapAttributes;
+use page_tables::x86_64::PageTableBuilder;
+use page_tables::BlockSize;
+use page_tables::Table;
+use page_tables::TableAllocator;
+use page_tables::ENTRIES_PER_TABLE;
+use page_tables::PAGE_SIZE;
+
+extern "C" {
+    static corgos_ap_trampoline: u8;
+    static corgos_ap_trampoline_end: u8;
+    static corgos_ap_params: u8;
+    fn corgos_ap_entry();
+}
+
+/// What the trampoline and `corgos_ap_entry` take from the kernel, at
+/// `corgos_ap_params` in the copy of the trampoline.
+#[repr(C)]
+pub struct TrampolineParams {
+    /// The startup tables, below 1 MiB.
+    pub startup_cr3: u64,
+    pub cr0: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub efer: u64,
+    /// `corgos_ap_entry`.
+    pub entry: u64,
+    pub stack_top: u64,
+    pub cpu_id: u64,
+}
+
+const IA32_EFER: u32 = 0xc000_0080;
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const X2APIC_ICR: u32 = 0x830;
+
+const XAPIC_ICR_LOW: u64 = 0x300;
+const XAPIC_ICR_HIGH: u64 = 0x310;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+/// INIT, asserted.
+const ICR_INIT: u32 = 0x4500;
+/// Start-up, asserted, with the page number of the trampoline.
+const ICR_STARTUP: u32 = 0x4600;
+
+const INIT_DELAY_US: u64 = 10_000;
+const STARTUP_DELAY_US: u64 = 200;
+
+/// The identity map of the startup tables, covers the trampoline.
+const IDENTITY_MAP_SIZE: u64 = 2 << 20;
+const KERNEL_HALF: Range<usize> = ENTRIES_PER_TABLE / 2..ENTRIES_PER_TABLE;
+
+/// The boot processor is in the x2APIC mode.
+static X2APIC: AtomicBool = AtomicBool::new(false);
+
+/// The startup tables come from the trampoline pages after the code.
+struct TrampolineTables {
+    next: u64,
+    end: u64,
+}
+
+impl TableAllocator for TrampolineTables {
+    fn allocate_table(&mut self) -> Option<u64> {
+        if self.next == self.end {
+            return None;
+        }
+        let phys = self.next;
+        self.next += PAGE_SIZE;
+        self.table(phys).fill(0);
+        Some(phys)
+    }
+
+    fn table(&mut self, phys: u64) -> &mut Table {
+        // SAFETY: the loader has reserved the trampoline pages for the
+        // kernel, and the linear map covers them.
+        unsafe { &mut *linear_map::phys_to_virt(PhysAddr::new(phys)).as_mut_ptr() }
+    }
+}
+
+fn control_registers() -> (u64, u64, u64) {
+    let (cr0, cr3, cr4): (u64, u64, u64);
+    // SAFETY: reading the control registers has no side effects.
+    unsafe {
+        core::arch::asm!(
+            "mov {cr0}, cr0",
+            "mov {cr3}, cr3",
+            "mov {cr4}, cr4",
+            cr0 = out(reg) cr0,
+            cr3 = out(reg) cr3,
+            cr4 = out(reg) cr4,
+            options(nomem, nostack)
+        )
+    };
+    (cr0, cr3, cr4)
+}
+
+/// Sends the IPI with the delivery mode and the vector in `command`.
+fn send_ipi(apic: &Apic, destination: u32, command: u32) -> Result<(), StartCpuError> {
+    match apic.mode {
+        ApicMode::X2Apic => write_msr(X2APIC_ICR, (destination as u64) << 32 | command as u64),
+        ApicMode::XApic if apic.virt_base != 0 => {
+            let icr_low = (apic.virt_base + XAPIC_ICR_LOW) as *mut u32;
+            let icr_high = (apic.virt_base + XAPIC_ICR_HIGH) as *mut u32;
+            // SAFETY: the loader has mapped the registers, and only the
+            // boot processor sends the IPIs.
+            unsafe {
+                icr_high.write_volatile(destination << 24);
+                icr_low.write_volatile(command);
+                while icr_low.read_volatile() & ICR_DELIVERY_PENDING != 0 {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+        _ => return Err(StartCpuError::Refused),
+    }
+    Ok(())
+}
+
+/// Copies the trampoline, and builds the startup tables. Returns the
+/// copy of the parameters, and the root of the startup tables.
+fn prepare_trampoline(page: u64) -> Result<(*mut TrampolineParams, u64), StartCpuError> {
+    // SAFETY: the symbols delimit the trampoline in the image.
+    let (code, params_offset) = unsafe {
+        let start = addr_of!(corgos_ap_trampoline);
+        let len = addr_of!(corgos_ap_trampoline_end) as usize - start as usize;
+        let params_offset = addr_of!(corgos_ap_params) as usize - start as usize;
+        (core::slice::from_raw_parts(start, len), params_offset)
+    };
+    let trampoline = linear_map::phys_to_virt(PhysAddr::new(page)).as_mut_ptr::<u8>();
+    // SAFETY: the loader has reserved the page for the trampoline, and no
+    // processor runs there as the previous one has reported in.
+    unsafe { trampoline.copy_from_nonoverlapping(code.as_ptr(), code.len()) };
+
+    let mut tables = TrampolineTables {
+        next: page + PAGE_SIZE,
+        end: page + AP_TRAMPOLINE_PAGES as u64 * PAGE_SIZE,
+    };
+    let mut builder = PageTableBuilder::new(&mut tables)
+        .map_err(|_| StartCpuError::OutOfMemory)?
+        .with_largest_block(BlockSize::Block2M);
+    let attributes = MapAttributes {
+        executable: true,
+        global: false,
+        ..MapAttributes::DATA
+    };
+    builder
+        .map(0, 0, IDENTITY_MAP_SIZE, attributes)
+        .map_err(|_| StartCpuError::OutOfMemory)?;
+    let startup_root = builder.root();
+
+    let kernel_root = *tables.table(<crate::CurrentArch as Arch>::Mmu::root_table());
+    tables.table(startup_root)[KERNEL_HALF].copy_from_slice(&kernel_root[KERNEL_HALF]);
+
+    // SAFETY: the parameters are 8-byte aligned in the trampoline, and the
+    // trampoline is page-aligned.
+    let params = unsafe { trampoline.add(params_offset).cast() };
+    Ok((params, startup_root))
+}
+
+/// Starts the processor with INIT-SIPI-SIPI, see the module docs.
+pub fn start_cpu(boot_info: &BootInfo, cpu_id: u32, stack_top: u64) -> Result<(), StartCpuError> {
+    let cpus = boot_info.cpus();
+    let page = cpus.ap_trampoline;
+    if cpus.enable_method != CpuEnableMethod::Sipi || page == 0 {
+        return Err(StartCpuError::NoEnableMethod);
+    }
+    let hw_id = cpus.cpus()[cpu_id as usize].hw_id as u32;
+
+    let (params, startup_cr3) = prepare_trampoline(page)?;
+    let (cr0, cr3, cr4) = control_registers();
+    // SAFETY: points into the trampoline, see above.
+    unsafe {
+        params.write(TrampolineParams {
+            startup_cr3,
+            cr0,
+            cr3,
+            cr4,
+            efer: read_msr(IA32_EFER),
+            entry: corgos_ap_entry as *const () as u64,
+            stack_top,
+            cpu_id: cpu_id as u64,
+        })
+    };
+
+    let apic = &boot_info.apic;
+    X2APIC.store(apic.mode == ApicMode::X2Apic, Ordering::Relaxed);
+    send_ipi(apic, hw_id, ICR_INIT)?;
+    time::delay_us::<crate::CurrentArch>(boot_info, INIT_DELAY_US);
+    let vector = (page / PAGE_SIZE) as u32;
+    for _ in 0..2 {
+        send_ipi(apic, hw_id, ICR_STARTUP | vector)?;
+        time::delay_us::<crate::CurrentArch>(boot_info, STARTUP_DELAY_US);
+    }
+    Ok(())
+}
+
+/// Puts the local APIC of the secondary processor in the mode of the boot
+/// processor, see the module docs.
+pub fn init_apic() {
+    if X2APIC.load(Ordering::Relaxed) {
+        let apic_base = read_msr(IA32_APIC_BASE);
+        write_msr(
+            IA32_APIC_BASE,
+            apic_base | APIC_BASE_ENABLE | APIC_BASE_X2APIC_ENABLE,
+        );
+    }
+}
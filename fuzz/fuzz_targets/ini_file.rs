@@ -0,0 +1,120 @@
+//! Feeds arbitrary bytes to the parser of the configuration files and the
+//! kernel command line.
+
+#![no_main]
+
+use ini_file::Error;
+use ini_file::KeyValue;
+use ini_file::Location;
+use ini_file::Parser;
+use libfuzzer_sys::fuzz_target;
+
+/// The `&str` input counts the characters for each one it looks at.
+const MAX_STR_INPUT: usize = 0x1000;
+
+fn is_key_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || c == b'-'
+}
+
+/// The location is where the input says it is, not past its end.
+fn check_location(input: &[u8], location: Location) {
+    let pos = location.pos();
+    assert!(pos <= input.len(), "{location:?} is past the end");
+    let before = &input[..pos];
+    let lines = before.iter().filter(|&&c| c == b'\n').count();
+    let line_start = before
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .map_or(0, |i| i + 1);
+    assert_eq!(location.line(), lines + 1, "{location:?}");
+    assert_eq!(location.col(), pos - line_start + 1, "{location:?}");
+}
+
+fn check_within(input: &[u8], part: &[u8]) {
+    let input_range = input.as_ptr_range();
+    let part_range = part.as_ptr_range();
+    assert!(input_range.start <= part_range.start && part_range.end <= input_range.end);
+}
+
+/// The pairs up to the first error, and the error.
+type Parsed<'a> = (Vec<(&'a [u8], &'a [u8])>, Option<Error>);
+
+/// Parses up to the end or the first error as the loader and the kernel
+/// do, checks that the parser moves forward, and returns the pairs.
+fn parse_all(input: &[u8], flags: bool) -> Parsed<'_> {
+    let mut parser = if flags {
+        Parser::with_flags(input)
+    } else {
+        Parser::new(input)
+    };
+    let mut pairs = Vec::new();
+    let mut last = parser.location();
+    loop {
+        let result = parser.parse();
+        let location = parser.location();
+        check_location(input, location);
+        assert!(location.pos() >= last.pos(), "{last:?} -> {location:?}");
+
+        match result {
+            Ok(None) => return (pairs, None),
+            Ok(Some(KeyValue { key, value })) => {
+                assert!(location.pos() > last.pos(), "No progress at {last:?}");
+                check_within(input, key);
+                check_within(input, value);
+                assert!(key.first().is_some_and(|c| c.is_ascii_alphabetic()));
+                assert!(key.iter().all(|&c| is_key_char(c)));
+                // Only the parser with the flags takes the escaped quotes.
+                assert!(flags || !value.contains(&b'"'));
+                assert!(!value.contains(&b'\n'));
+                pairs.push((key, value));
+            }
+            Err(
+                e @ (Error::ExpectedKey(at)
+                | Error::ExpectedValue(at)
+                | Error::ExpectedAssign(at)
+                | Error::UnexpectedToken(at)
+                | Error::UnmatchedQuote(at)
+                | Error::InvalidKeyName(at)),
+            ) => {
+                check_location(input, at);
+                // The quote not closed on its line does not take the lines
+                // after it.
+                if let Error::UnmatchedQuote(_) = e {
+                    assert!(input.get(location.pos()).is_none_or(|&c| c == b'\n'));
+                }
+                return (pairs, Some(e));
+            }
+        }
+        last = location;
+    }
+}
+
+/// The same as [`parse_all`] for the `&str` input, the pairs as bytes.
+fn parse_all_str(input: &str, flags: bool) -> Parsed<'_> {
+    let mut parser = if flags {
+        Parser::with_flags(input)
+    } else {
+        Parser::new(input)
+    };
+    let mut pairs = Vec::new();
+    loop {
+        match parser.parse() {
+            Ok(None) => return (pairs, None),
+            Ok(Some(KeyValue { key, value })) => pairs.push((key.as_bytes(), value.as_bytes())),
+            Err(e) => return (pairs, Some(e)),
+        }
+    }
+}
+
+fuzz_target!(|input: &[u8]| {
+    for flags in [false, true] {
+        let parsed = parse_all(input, flags);
+
+        // The `&str` input is only handled for ASCII, and must parse the
+        // same as the bytes.
+        if input.is_ascii() && input.len() <= MAX_STR_INPUT {
+            let input = core::str::from_utf8(input).expect("ASCII");
+            assert_eq!(parse_all_str(input, flags), parsed);
+        }
+    }
+});
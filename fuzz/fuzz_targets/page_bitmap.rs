@@ -0,0 +1,165 @@
+//! Drives the page bitmap with random allocations, frees, and reservations,
+//! and checks it against a plain array of the allocated pages.
+//!
+//! The input starts with the number of the pages and the domain bounds,
+//! then each four bytes are an operation. `find_free_page` is left out,
+//! it is not finished, see `cttz`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use page_bitmap::page_bitmap_level_size;
+use page_bitmap::PageBitMapError;
+use page_bitmap::PageBitmap;
+use page_bitmap::PageBitmapDomain;
+
+const PAGE_SIZE: usize = 4096;
+const LEVELS: usize = 8;
+/// 64 MiB, enough for a few levels and quick to check in full.
+const MAX_PAGES: usize = 0x4000;
+const MAX_DOMAINS: usize = 3;
+
+/// What the bitmap is checked against.
+struct Model {
+    allocated: Vec<bool>,
+}
+
+impl Model {
+    fn first_free(&self, domain: &PageBitmapDomain) -> Option<usize> {
+        (domain.start_pfn..domain.end_pfn).find(|&pfn| !self.allocated[pfn])
+    }
+
+    fn count(&self, start_pfn: usize, end_pfn: usize) -> usize {
+        self.allocated[start_pfn..end_pfn]
+            .iter()
+            .filter(|&&allocated| allocated)
+            .count()
+    }
+}
+
+fn take_u16(input: &mut &[u8]) -> Option<usize> {
+    let (bytes, rest) = input.split_first_chunk::<2>()?;
+    *input = rest;
+    Some(u16::from_le_bytes(*bytes) as usize)
+}
+
+fn take_u8(input: &mut &[u8]) -> Option<usize> {
+    let (&byte, rest) = input.split_first()?;
+    *input = rest;
+    Some(byte as usize)
+}
+
+/// The domains split the pages at the sorted bounds, some pages may be
+/// left without a domain in between.
+fn domains(input: &mut &[u8], pages: usize) -> Option<Vec<PageBitmapDomain>> {
+    let count = take_u8(input)? % (MAX_DOMAINS + 1);
+    let mut bounds = Vec::with_capacity(count * 2);
+    for _ in 0..count * 2 {
+        bounds.push(take_u16(input)? % (pages + 1));
+    }
+    bounds.sort_unstable();
+    Some(
+        bounds
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|&[start_pfn, end_pfn]| PageBitmapDomain { start_pfn, end_pfn })
+            .collect(),
+    )
+}
+
+fn check_all(bitmap: &PageBitmap, model: &Model, pages: usize) {
+    bitmap.check().expect("The levels agree");
+    for pfn in 0..pages {
+        assert_eq!(
+            bitmap.is_page_allocated(pfn),
+            model.allocated[pfn],
+            "Page {pfn}"
+        );
+    }
+    assert_eq!(bitmap.allocated_pages(0, pages), model.count(0, pages));
+}
+
+fuzz_target!(|input: &[u8]| {
+    let mut input = input;
+    let Some(pages) = take_u16(&mut input).map(|n| n % MAX_PAGES + 1) else {
+        return;
+    };
+    let Some(domains) = domains(&mut input, pages) else {
+        return;
+    };
+
+    let max_memory = pages * PAGE_SIZE;
+    let sizes = page_bitmap_level_size(max_memory);
+    let mut storage: Vec<Vec<u8>> = sizes.iter().map(|&size| vec![0; size + 1]).collect();
+    let levels: [&mut [u8]; LEVELS] = storage
+        .iter_mut()
+        .map(|level| level.as_mut_slice())
+        .collect::<Vec<_>>()
+        .try_into()
+        .expect("One slice per level");
+    let mut bitmap = PageBitmap::new(levels, max_memory, || None);
+    bitmap.set_domains(&domains);
+    let mut model = Model {
+        allocated: vec![false; pages],
+    };
+
+    for &[op, pfn_low, pfn_high, extra] in input.as_chunks::<4>().0 {
+        let pfn = u16::from_le_bytes([pfn_low, pfn_high]) as usize % pages;
+        let extra = extra as usize;
+        match op % 5 {
+            0 => {
+                let result = bitmap.allocate_page(pfn);
+                assert_eq!(
+                    matches!(result, Err(PageBitMapError::AlreadyAllocated)),
+                    model.allocated[pfn]
+                );
+                assert!(result.is_ok() || model.allocated[pfn]);
+                model.allocated[pfn] = true;
+            }
+            1 => {
+                let result = bitmap.free_page(pfn);
+                assert_eq!(
+                    matches!(result, Err(PageBitMapError::NotAllocated)),
+                    !model.allocated[pfn]
+                );
+                assert!(result.is_ok() || !model.allocated[pfn]);
+                model.allocated[pfn] = false;
+            }
+            2 => {
+                // Reserves the range the way the kernel takes out the
+                // memory it cannot use, the allocated pages stay so.
+                for pfn in pfn..(pfn + extra * 8).min(pages) {
+                    bitmap.allocate_page(pfn).ok();
+                    model.allocated[pfn] = true;
+                }
+            }
+            3 => {
+                let domain = extra % (domains.len() + 1);
+                let found = bitmap.find_free_page_in_domain(domain);
+                let expected = domains.get(domain).and_then(|d| model.first_free(d));
+                assert_eq!(found, expected, "Domain {domain}");
+                if let Some(pfn) = found {
+                    bitmap.allocate_page(pfn).expect("The page is free");
+                    model.allocated[pfn] = true;
+                }
+            }
+            _ => {
+                let end_pfn = (pfn + extra * 8).min(pages);
+                assert_eq!(
+                    bitmap.allocated_pages(pfn, end_pfn),
+                    model.count(pfn, end_pfn)
+                );
+                assert_eq!(
+                    bitmap.domain_of(pfn),
+                    domains
+                        .iter()
+                        .position(|d| (d.start_pfn..d.end_pfn).contains(&pfn))
+                );
+            }
+        }
+        assert_eq!(bitmap.is_page_allocated(pfn), model.allocated[pfn]);
+    }
+
+    check_all(&bitmap, &model, pages);
+});
@@ -39,6 +39,7 @@ ARCH_CONFIG = {
 }
 
 EFI_DIR = f"{PWD}/esp"
+GDB_STUB_PORT = 1235
 OVMF_DIR = f"{PWD}/ovmf"
 
 
@@ -73,7 +74,7 @@ def copy_files(arch, release):
     shutil.copy(f"{kernel_build_dir}/kernel_start", f"{EFI_DIR}/corgos")
 
 
-def write_boot_ini(arch):
+def write_boot_ini(arch, load_below_4g, gdb_stub):
     logger.info(f"Writing boot.ini for architecture {arch}")
     config = ARCH_CONFIG[arch]
     revision, branch, dirty, date = get_git_info()
@@ -82,8 +83,9 @@ def write_boot_ini(arch):
         ini_file.write(f'revision = "{revision}{dirty} {date}, branch \'{branch}\'"\n')
         ini_file.write(f'log_device = {config["log_device"]}\n')
         ini_file.write('log_level = trace\n')
-        ini_file.write('wait_for_start = false\n')
+        ini_file.write(f'gdb_stub = {"com2" if gdb_stub else "none"}\n')
         ini_file.write('walk_page_tables = false\n')
+        ini_file.write(f'load_below_4g = {"on" if load_below_4g else "off"}\n')
 
 
 def get_arch_name_normalized(arch_name):
@@ -130,17 +132,20 @@ def build_project(arch, release):
         raise
 
 
-def run_qemu(arch, accel, release):
+def run_qemu(arch, accel, release, load_below_4g, gdb_stub):
     logger.info(f"Running QEMU for {arch}, release: {release}")
     config = ARCH_CONFIG[arch]
 
     setup_directories()
     copy_files(arch, release)
-    write_boot_ini(arch)
+    write_boot_ini(arch, load_below_4g, gdb_stub)
 
     cpu = "host" if accel else config['cpu'];
     semihosting = "" if accel else config['semihosting']
     accel_option = get_accelerator(arch, accel)
+    # GDB talks to the stub in the loader on the second serial port.
+    char1 = f"socket,id=char1,host=localhost,port={GDB_STUB_PORT},server=on,wait=off" if gdb_stub \
+        else "file,path=serial2.log,id=char1"
     qemu_command = f"""
         qemu-system-{arch}
             -nodefaults -s
@@ -154,7 +159,7 @@ def run_qemu(arch, accel, release):
             -chardev file,id=fwdebug,path=fw.log
             -serial chardev:char0
             -mon chardev=char0
-            -chardev file,path=serial2.log,id=char1
+            -chardev {char1}
             -serial chardev:char1
             -drive format=raw,file=fat:rw:{EFI_DIR}
             -drive if=pflash,format=raw,file={OVMF_DIR}/{os.path.basename(config['ovmf_code'])},readonly=on
@@ -177,6 +182,9 @@ def main():
     parser.add_argument('--accel', action='store_true', help="Enable hardware acceleration")
     parser.add_argument('--build-only', action='store_true', help="Only build, do not run QEMU")
     parser.add_argument('-b', '--build-all', action='store_true', help="Build for all architectures, do not run QEMU")
+    parser.add_argument('--load-below-4g', action='store_true', help="Keep the kernel below 4 GiB, it goes above by default")
+    parser.add_argument('--gdb-stub', action='store_true',
+                        help=f"Stop in the loader for GDB on localhost:{GDB_STUB_PORT}, x86_64 only")
     parser.add_argument('-a', '--arch', choices=['x86_64', 'aarch64'], help="Target architecture (x86_64 or aarch64)")
     args = parser.parse_args()
 
@@ -187,7 +195,7 @@ def main():
             if args.arch:
                 build_project(args.arch, args.release)
                 if not args.build_only:
-                    run_qemu(args.arch, args.accel, args.release)
+                    run_qemu(args.arch, args.accel, args.release, args.load_below_4g, args.gdb_stub)
             else:
                 logger.error("Please specify an architecture or use --all")
     except Exception as e:
@@ -0,0 +1,110 @@
+//! The Fixed ACPI Description Table.
+
+use crate::read_u16;
+use crate::read_u32;
+use crate::read_u64;
+use crate::read_u8;
+use crate::AcpiError;
+use crate::GenericAddress;
+use crate::Table;
+
+/// The length of the ACPI 1.0 FADT, the later fields are read only if the
+/// table is long enough.
+const FADT_V1_LENGTH: usize = 116;
+
+const DSDT: usize = 40;
+const SCI_INTERRUPT: usize = 46;
+const IAPC_BOOT_ARCH: usize = 109;
+const FLAGS: usize = 112;
+const RESET_REGISTER: usize = 116;
+const RESET_VALUE: usize = 128;
+const ARM_BOOT_ARCH: usize = 129;
+const MINOR_VERSION: usize = 131;
+const X_DSDT: usize = 140;
+
+/// The platform has no legacy hardware, as the ARM machines.
+pub const FADT_HW_REDUCED_ACPI: u32 = 1 << 20;
+/// The reset register is there.
+pub const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+/// The legacy devices on the LPC or ISA bus, in the IA-PC boot
+/// architecture flags.
+pub const IAPC_BOOT_ARCH_LEGACY_DEVICES: u16 = 1 << 0;
+/// The keyboard controller at the ports 0x60 and 0x64.
+pub const IAPC_BOOT_ARCH_8042: u16 = 1 << 1;
+pub const IAPC_BOOT_ARCH_VGA_NOT_PRESENT: u16 = 1 << 2;
+pub const IAPC_BOOT_ARCH_CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Fadt<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"FACP";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, FADT_V1_LENGTH)?,
+        })
+    }
+
+    fn has(&self, offset: usize, size: usize) -> bool {
+        self.table.bytes.len() >= offset + size
+    }
+
+    /// The 64-bit address if there is one, the 32-bit one otherwise.
+    pub fn dsdt_address(&self) -> u64 {
+        match self
+            .has(X_DSDT, 8)
+            .then(|| read_u64(self.table.bytes, X_DSDT))
+        {
+            Some(x_dsdt) if x_dsdt != 0 => x_dsdt,
+            _ => read_u32(self.table.bytes, DSDT) as u64,
+        }
+    }
+
+    pub fn sci_interrupt(&self) -> u16 {
+        read_u16(self.table.bytes, SCI_INTERRUPT)
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(self.table.bytes, FLAGS)
+    }
+
+    pub fn is_hw_reduced(&self) -> bool {
+        self.flags() & FADT_HW_REDUCED_ACPI != 0
+    }
+
+    /// The `IAPC_BOOT_ARCH_*` flags, ACPI 2.0 and later.
+    pub fn iapc_boot_arch(&self) -> u16 {
+        read_u16(self.table.bytes, IAPC_BOOT_ARCH)
+    }
+
+    /// The register and the value to write to reset the machine, if the
+    /// flags say it is there.
+    pub fn reset(&self) -> Option<(GenericAddress, u8)> {
+        (self.has(RESET_VALUE, 1) && self.flags() & FADT_RESET_REG_SUPPORTED != 0).then(|| {
+            (
+                GenericAddress::read(self.table.bytes, RESET_REGISTER),
+                read_u8(self.table.bytes, RESET_VALUE),
+            )
+        })
+    }
+
+    /// The ARM boot architecture flags of ACPI 5.1 and later, for PSCI.
+    pub fn arm_boot_arch(&self) -> Option<u16> {
+        self.has(ARM_BOOT_ARCH, 2)
+            .then(|| read_u16(self.table.bytes, ARM_BOOT_ARCH))
+    }
+
+    /// The version as `(major, minor)`, the major one is the table
+    /// revision.
+    pub fn version(&self) -> (u8, u8) {
+        let minor = match self.has(MINOR_VERSION, 1) {
+            true => read_u8(self.table.bytes, MINOR_VERSION) & 0xf,
+            false => 0,
+        };
+        (self.table.header.revision, minor)
+    }
+}
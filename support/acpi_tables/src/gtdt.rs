@@ -0,0 +1,85 @@
+//! The Generic Timer Description Table.
+
+use crate::read_u32;
+use crate::read_u64;
+use crate::AcpiError;
+use crate::Table;
+
+const GTDT_LENGTH: usize = 96;
+
+const CNT_CONTROL_BASE: usize = 36;
+const SECURE_EL1: usize = 48;
+const NON_SECURE_EL1: usize = 56;
+const VIRTUAL_EL1: usize = 64;
+const NON_SECURE_EL2: usize = 72;
+const CNT_READ_BASE: usize = 80;
+const VIRTUAL_EL2: usize = 96;
+
+/// Edge-triggered, level-triggered otherwise.
+pub const GTDT_TIMER_EDGE: u32 = 1 << 0;
+pub const GTDT_TIMER_ACTIVE_LOW: u32 = 1 << 1;
+/// Keeps running in the low-power states.
+pub const GTDT_TIMER_ALWAYS_ON: u32 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerInterrupt {
+    /// The PPI, `0` if the timer is not there.
+    pub gsiv: u32,
+    /// `GTDT_TIMER_*`.
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Gtdt<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Gtdt<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"GTDT";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, GTDT_LENGTH)?,
+        })
+    }
+
+    fn timer(&self, offset: usize) -> TimerInterrupt {
+        TimerInterrupt {
+            gsiv: read_u32(self.table.bytes, offset),
+            flags: read_u32(self.table.bytes, offset + 4),
+        }
+    }
+
+    /// The physical address of the `CNTControlBase` frame, all ones if
+    /// there is none.
+    pub fn cnt_control_base(&self) -> u64 {
+        read_u64(self.table.bytes, CNT_CONTROL_BASE)
+    }
+
+    /// The physical address of the `CNTReadBase` frame, all ones if there
+    /// is none.
+    pub fn cnt_read_base(&self) -> u64 {
+        read_u64(self.table.bytes, CNT_READ_BASE)
+    }
+
+    pub fn secure_el1(&self) -> TimerInterrupt {
+        self.timer(SECURE_EL1)
+    }
+
+    pub fn non_secure_el1(&self) -> TimerInterrupt {
+        self.timer(NON_SECURE_EL1)
+    }
+
+    pub fn virtual_el1(&self) -> TimerInterrupt {
+        self.timer(VIRTUAL_EL1)
+    }
+
+    pub fn non_secure_el2(&self) -> TimerInterrupt {
+        self.timer(NON_SECURE_EL2)
+    }
+
+    /// Of the revision 3 and later.
+    pub fn virtual_el2(&self) -> Option<TimerInterrupt> {
+        (self.table.bytes.len() >= VIRTUAL_EL2 + 8).then(|| self.timer(VIRTUAL_EL2))
+    }
+}
@@ -0,0 +1,397 @@
+//! The ACPI tables the boot path needs, read in place without allocating.
+//!
+//! [`AcpiTables::from_rsdp`] checks the RSDP, and the XSDT, or the RSDT
+//! with the ACPI 1.0 firmware. [`AcpiTables::find`] and [`AcpiTables::tables`]
+//! return the tables with the valid checksums only, and the typed views
+//! check the signature and the length of the fixed part:
+//!
+//! - [`Madt`]: the interrupt controllers and the processors,
+//! - [`Fadt`]: the boot architecture flags and the DSDT,
+//! - [`Spcr`]: the serial console of the firmware,
+//! - [`Gtdt`]: the interrupts of the generic timer,
+//! - [`Mcfg`]: the PCI Express configuration space,
+//! - [`Srat`]: the proximity domains of the processors and the memory,
+//! - [`Tpm2`]: the interface of the TPM.
+//!
+//! The sub-structures of the MADT and the SRAT come from the iterators that
+//! stop at the first malformed one, and the ones of the kinds not described
+//! here are passed through as the bytes.
+//!
+//! The tables are reached at their physical address plus an offset: `0`
+//! for the identity map of the firmware, or the base of the linear map in
+//! the kernel.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod fadt;
+pub mod gtdt;
+pub mod madt;
+pub mod mcfg;
+pub mod spcr;
+pub mod srat;
+mod tests;
+pub mod tpm2;
+
+pub use fadt::Fadt;
+pub use gtdt::Gtdt;
+pub use gtdt::TimerInterrupt;
+pub use madt::Madt;
+pub use madt::MadtEntries;
+pub use madt::MadtEntry;
+pub use mcfg::Mcfg;
+pub use mcfg::McfgEntry;
+pub use spcr::Spcr;
+pub use srat::Srat;
+pub use srat::SratEntries;
+pub use srat::SratEntry;
+pub use tpm2::Tpm2;
+
+pub const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// The part of the RSDP the ACPI 1.0 checksum covers.
+pub const RSDP_V1_SIZE: usize = 20;
+pub const RSDP_V2_SIZE: usize = 36;
+pub const SDT_HEADER_SIZE: usize = 36;
+
+pub const XSDT_SIGNATURE: &[u8; 4] = b"XSDT";
+pub const RSDT_SIGNATURE: &[u8; 4] = b"RSDT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The signature of the RSDP or of a table does not match.
+    BadSignature,
+    /// The bytes do not sum up to zero.
+    BadChecksum,
+    /// A table is shorter than its header, or than its fixed part.
+    Truncated,
+    /// Neither the XSDT nor the RSDT is there.
+    NoRootTable,
+}
+
+pub(crate) fn read_u8(data: &[u8], offset: usize) -> u8 {
+    data.get(offset).copied().unwrap_or_default()
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or_default()
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or_default()
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+fn array<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data.get(offset..offset + N)
+        .and_then(|b| b.try_into().ok())
+        .unwrap_or([0; N])
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// The address of a register block, the reads above use zeros for the
+/// missing bytes so the callers check the lengths before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GenericAddress {
+    /// `0` for the memory, `1` for the I/O ports.
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SIZE: usize = 12;
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+
+    pub(crate) fn read(data: &[u8], offset: usize) -> Self {
+        Self {
+            address_space: read_u8(data, offset),
+            bit_width: read_u8(data, offset + 1),
+            bit_offset: read_u8(data, offset + 2),
+            access_size: read_u8(data, offset + 3),
+            address: read_u64(data, offset + 4),
+        }
+    }
+}
+
+/// The header all the tables start with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    fn parse(data: &[u8]) -> Result<Self, AcpiError> {
+        let bytes = data.get(..SDT_HEADER_SIZE).ok_or(AcpiError::Truncated)?;
+        Ok(Self {
+            signature: array(bytes, 0),
+            length: read_u32(bytes, 4),
+            revision: bytes[8],
+            oem_id: array(bytes, 10),
+            oem_table_id: array(bytes, 16),
+            oem_revision: read_u32(bytes, 24),
+            creator_id: read_u32(bytes, 28),
+            creator_revision: read_u32(bytes, 32),
+        })
+    }
+}
+
+/// A table with a valid checksum, the bytes are exactly `header.length`.
+#[derive(Debug, Clone, Copy)]
+pub struct Table<'a> {
+    pub header: SdtHeader,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Table<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, AcpiError> {
+        let header = SdtHeader::parse(data)?;
+        let bytes = data
+            .get(..header.length as usize)
+            .filter(|bytes| bytes.len() >= SDT_HEADER_SIZE)
+            .ok_or(AcpiError::Truncated)?;
+        if !checksum_ok(bytes) {
+            return Err(AcpiError::BadChecksum);
+        }
+        Ok(Self { header, bytes })
+    }
+
+    /// The bytes after the header.
+    pub fn body(&self) -> &'a [u8] {
+        &self.bytes[SDT_HEADER_SIZE..]
+    }
+
+    /// For the typed views: the signature matches, and the table has at
+    /// least `min_length` bytes.
+    pub(crate) fn expect(self, signature: &[u8; 4], min_length: usize) -> Result<Self, AcpiError> {
+        if &self.header.signature != signature {
+            return Err(AcpiError::BadSignature);
+        }
+        if self.bytes.len() < min_length {
+            return Err(AcpiError::Truncated);
+        }
+        Ok(self)
+    }
+}
+
+/// The sub-structures that start with the type and the length bytes, as in
+/// the MADT and the SRAT.
+#[derive(Debug, Clone)]
+pub(crate) struct Subtables<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Subtables<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Subtables<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = *self.data.get(1)? as usize;
+        if length < 2 || length > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+        let (entry, rest) = self.data.split_at(length);
+        self.data = rest;
+        Some((entry[0], entry))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rsdp {
+    pub oem_id: [u8; 6],
+    /// `0` for ACPI 1.0, `2` for the later ones with the XSDT.
+    pub revision: u8,
+    pub rsdt_address: u32,
+    /// `0` for ACPI 1.0.
+    pub xsdt_address: u64,
+}
+
+impl Rsdp {
+    /// Checks the signature, and the checksums for the revision.
+    pub fn new(data: &[u8]) -> Result<Self, AcpiError> {
+        let v1 = data.get(..RSDP_V1_SIZE).ok_or(AcpiError::Truncated)?;
+        if &v1[..8] != RSDP_SIGNATURE {
+            return Err(AcpiError::BadSignature);
+        }
+        if !checksum_ok(v1) {
+            return Err(AcpiError::BadChecksum);
+        }
+        let revision = v1[15];
+        let mut rsdp = Self {
+            oem_id: array(v1, 9),
+            revision,
+            rsdt_address: read_u32(v1, 16),
+            xsdt_address: 0,
+        };
+        if revision >= 2 {
+            let v2 = data.get(..RSDP_V2_SIZE).ok_or(AcpiError::Truncated)?;
+            let length = read_u32(v2, 20) as usize;
+            let extended = data
+                .get(..length.max(RSDP_V2_SIZE))
+                .ok_or(AcpiError::Truncated)?;
+            if !checksum_ok(extended) {
+                return Err(AcpiError::BadChecksum);
+            }
+            rsdp.xsdt_address = read_u64(v2, 24);
+        }
+        Ok(rsdp)
+    }
+}
+
+/// The tables rooted at the XSDT or the RSDT.
+#[derive(Debug, Clone, Copy)]
+pub struct AcpiTables<'a> {
+    pub rsdp: Rsdp,
+    pub root: Table<'a>,
+    /// The physical addresses of the tables are 8 bytes in the XSDT, and 4
+    /// bytes in the RSDT.
+    entry_size: usize,
+    phys_offset: u64,
+}
+
+impl<'a> AcpiTables<'a> {
+    /// Checks the RSDP and the root table, see the crate docs.
+    ///
+    /// # Safety
+    ///
+    /// The RSDP is at `rsdp_phys`, and the firmware tables are readable at
+    /// their physical addresses plus `phys_offset` and stay unchanged for
+    /// `'a`.
+    pub unsafe fn from_rsdp(rsdp_phys: u64, phys_offset: u64) -> Result<Self, AcpiError> {
+        let rsdp_virt = (rsdp_phys + phys_offset) as *const u8;
+        // SAFETY: the caller guarantees there is an RSDP, the first part
+        // of it is always there.
+        let v1 = unsafe { core::slice::from_raw_parts(rsdp_virt, RSDP_V1_SIZE) };
+        let size = if v1[15] < 2 {
+            RSDP_V1_SIZE
+        } else {
+            // SAFETY: as above, the revision 2 has the extended part with
+            // the length.
+            let v2 = unsafe { core::slice::from_raw_parts(rsdp_virt, RSDP_V2_SIZE) };
+            (read_u32(v2, 20) as usize).max(RSDP_V2_SIZE)
+        };
+        // SAFETY: the length comes from the RSDP.
+        let rsdp = Rsdp::new(unsafe { core::slice::from_raw_parts(rsdp_virt, size) })?;
+
+        let (root_phys, signature, entry_size) = match rsdp {
+            Rsdp { xsdt_address, .. } if xsdt_address != 0 => (xsdt_address, XSDT_SIGNATURE, 8),
+            Rsdp { rsdt_address, .. } if rsdt_address != 0 => {
+                (rsdt_address as u64, RSDT_SIGNATURE, 4)
+            }
+            _ => return Err(AcpiError::NoRootTable),
+        };
+        // SAFETY: the caller guarantees the tables are readable.
+        let root = unsafe { Self::table_at(root_phys, phys_offset) }?.expect(signature, 0)?;
+        Ok(Self {
+            rsdp,
+            root,
+            entry_size,
+            phys_offset,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// A table is at `phys`, see [`Self::from_rsdp`].
+    unsafe fn table_at(phys: u64, phys_offset: u64) -> Result<Table<'a>, AcpiError> {
+        let virt = (phys + phys_offset) as *const u8;
+        // SAFETY: the caller guarantees there is a table, the header is
+        // always there.
+        let header =
+            SdtHeader::parse(unsafe { core::slice::from_raw_parts(virt, SDT_HEADER_SIZE) })?;
+        // SAFETY: the length comes from the header.
+        Table::new(unsafe { core::slice::from_raw_parts(virt, header.length as usize) })
+    }
+
+    /// The physical addresses of the tables in the root table.
+    pub fn addresses(&self) -> impl Iterator<Item = u64> + 'a {
+        let entry_size = self.entry_size;
+        self.root
+            .body()
+            .chunks_exact(entry_size)
+            .map(move |entry| match entry_size {
+                8 => read_u64(entry, 0),
+                _ => read_u32(entry, 0) as u64,
+            })
+            .filter(|&address| address != 0)
+    }
+
+    /// The tables with the valid checksums.
+    pub fn tables(&self) -> impl Iterator<Item = Table<'a>> + 'a {
+        let phys_offset = self.phys_offset;
+        self.addresses().filter_map(move |phys| {
+            // SAFETY: the root table points to the tables, see
+            // `from_rsdp`.
+            unsafe { Self::table_at(phys, phys_offset) }.ok()
+        })
+    }
+
+    /// The first table with the signature.
+    pub fn find(&self, signature: &[u8; 4]) -> Option<Table<'a>> {
+        self.tables()
+            .find(|table| &table.header.signature == signature)
+    }
+
+    /// The table at the physical address outside of the root table, as
+    /// the DSDT from the FADT.
+    ///
+    /// # Safety
+    ///
+    /// A table is at `phys`, see [`Self::from_rsdp`].
+    pub unsafe fn table(&self, phys: u64) -> Result<Table<'a>, AcpiError> {
+        // SAFETY: the caller guarantees there is a table.
+        unsafe { Self::table_at(phys, self.phys_offset) }
+    }
+
+    pub fn madt(&self) -> Option<Madt<'a>> {
+        Madt::new(self.find(Madt::SIGNATURE)?).ok()
+    }
+
+    pub fn fadt(&self) -> Option<Fadt<'a>> {
+        Fadt::new(self.find(Fadt::SIGNATURE)?).ok()
+    }
+
+    pub fn spcr(&self) -> Option<Spcr<'a>> {
+        Spcr::new(self.find(Spcr::SIGNATURE)?).ok()
+    }
+
+    pub fn gtdt(&self) -> Option<Gtdt<'a>> {
+        Gtdt::new(self.find(Gtdt::SIGNATURE)?).ok()
+    }
+
+    pub fn mcfg(&self) -> Option<Mcfg<'a>> {
+        Mcfg::new(self.find(Mcfg::SIGNATURE)?).ok()
+    }
+
+    pub fn srat(&self) -> Option<Srat<'a>> {
+        Srat::new(self.find(Srat::SIGNATURE)?).ok()
+    }
+
+    pub fn tpm2(&self) -> Option<Tpm2<'a>> {
+        Tpm2::new(self.find(Tpm2::SIGNATURE)?).ok()
+    }
+}
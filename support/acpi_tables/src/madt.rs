@@ -0,0 +1,238 @@
+//! The Multiple APIC Description Table.
+
+use crate::read_u16;
+use crate::read_u32;
+use crate::read_u64;
+use crate::read_u8;
+use crate::AcpiError;
+use crate::Subtables;
+use crate::Table;
+use crate::SDT_HEADER_SIZE;
+
+const MADT_ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+
+const LOCAL_APIC: u8 = 0x0;
+const IO_APIC: u8 = 0x1;
+const INTERRUPT_SOURCE_OVERRIDE: u8 = 0x2;
+const LOCAL_APIC_NMI: u8 = 0x4;
+const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 0x5;
+const LOCAL_X2APIC: u8 = 0x9;
+const GICC: u8 = 0xb;
+const GICD: u8 = 0xc;
+const GIC_MSI_FRAME: u8 = 0xd;
+const GICR: u8 = 0xe;
+const GIC_ITS: u8 = 0xf;
+
+/// The processor is enabled, in the flags of the processor entries.
+pub const MADT_CPU_ENABLED: u32 = 1 << 0;
+/// The dual 8259 PICs are there, in the flags of the table.
+pub const MADT_PCAT_COMPAT: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Madt<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Madt<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"APIC";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, MADT_ENTRIES_OFFSET)?,
+        })
+    }
+
+    /// The 32-bit address of the local APIC, see
+    /// [`MadtEntry::LocalApicAddressOverride`].
+    pub fn local_apic_address(&self) -> u32 {
+        read_u32(self.table.bytes, SDT_HEADER_SIZE)
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(self.table.bytes, SDT_HEADER_SIZE + 4)
+    }
+
+    pub fn entries(&self) -> MadtEntries<'a> {
+        MadtEntries {
+            subtables: Subtables::new(&self.table.bytes[MADT_ENTRIES_OFFSET..]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtEntry<'a> {
+    LocalApic {
+        uid: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        gsi_base: u32,
+    },
+    InterruptSourceOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: u16,
+    },
+    LocalApicNmi {
+        /// `0xff` for all the processors.
+        uid: u8,
+        flags: u16,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        uid: u32,
+    },
+    Gicc {
+        cpu_interface_number: u32,
+        uid: u32,
+        flags: u32,
+        parking_protocol_version: u32,
+        performance_interrupt: u32,
+        parked_address: u64,
+        physical_base: u64,
+        gicv_base: u64,
+        gich_base: u64,
+        vgic_maintenance_interrupt: u32,
+        /// `0` if the redistributors are in the [`MadtEntry::Gicr`]
+        /// ranges.
+        gicr_base: u64,
+        mpidr: u64,
+    },
+    Gicd {
+        id: u32,
+        physical_base: u64,
+        /// `1`-`4`, or `0` to read it from the distributor.
+        version: u8,
+    },
+    GicMsiFrame {
+        id: u32,
+        physical_base: u64,
+        flags: u32,
+        spi_count: u16,
+        spi_base: u16,
+    },
+    Gicr {
+        discovery_range_base: u64,
+        discovery_range_length: u32,
+    },
+    GicIts {
+        id: u32,
+        physical_base: u64,
+    },
+    /// Of the other types, or shorter than expected.
+    Other {
+        kind: u8,
+        bytes: &'a [u8],
+    },
+}
+
+impl<'a> MadtEntry<'a> {
+    fn parse(kind: u8, e: &'a [u8]) -> Self {
+        let length = match kind {
+            LOCAL_APIC => 8,
+            IO_APIC => 12,
+            INTERRUPT_SOURCE_OVERRIDE => 10,
+            LOCAL_APIC_NMI => 6,
+            LOCAL_APIC_ADDRESS_OVERRIDE => 12,
+            LOCAL_X2APIC => 16,
+            GICC => 76,
+            GICD => 24,
+            GIC_MSI_FRAME => 24,
+            GICR => 16,
+            GIC_ITS => 20,
+            _ => usize::MAX,
+        };
+        if e.len() < length {
+            return Self::Other { kind, bytes: e };
+        }
+        match kind {
+            LOCAL_APIC => Self::LocalApic {
+                uid: e[2],
+                apic_id: e[3],
+                flags: read_u32(e, 4),
+            },
+            IO_APIC => Self::IoApic {
+                id: e[2],
+                address: read_u32(e, 4),
+                gsi_base: read_u32(e, 8),
+            },
+            INTERRUPT_SOURCE_OVERRIDE => Self::InterruptSourceOverride {
+                bus: e[2],
+                source: e[3],
+                gsi: read_u32(e, 4),
+                flags: read_u16(e, 8),
+            },
+            LOCAL_APIC_NMI => Self::LocalApicNmi {
+                uid: e[2],
+                flags: read_u16(e, 3),
+                lint: e[5],
+            },
+            LOCAL_APIC_ADDRESS_OVERRIDE => Self::LocalApicAddressOverride {
+                address: read_u64(e, 4),
+            },
+            LOCAL_X2APIC => Self::LocalX2Apic {
+                x2apic_id: read_u32(e, 4),
+                flags: read_u32(e, 8),
+                uid: read_u32(e, 12),
+            },
+            GICC => Self::Gicc {
+                cpu_interface_number: read_u32(e, 4),
+                uid: read_u32(e, 8),
+                flags: read_u32(e, 12),
+                parking_protocol_version: read_u32(e, 16),
+                performance_interrupt: read_u32(e, 20),
+                parked_address: read_u64(e, 24),
+                physical_base: read_u64(e, 32),
+                gicv_base: read_u64(e, 40),
+                gich_base: read_u64(e, 48),
+                vgic_maintenance_interrupt: read_u32(e, 56),
+                gicr_base: read_u64(e, 60),
+                mpidr: read_u64(e, 68),
+            },
+            GICD => Self::Gicd {
+                id: read_u32(e, 4),
+                physical_base: read_u64(e, 8),
+                version: read_u8(e, 20),
+            },
+            GIC_MSI_FRAME => Self::GicMsiFrame {
+                id: read_u32(e, 4),
+                physical_base: read_u64(e, 8),
+                flags: read_u32(e, 16),
+                spi_count: read_u16(e, 20),
+                spi_base: read_u16(e, 22),
+            },
+            GICR => Self::Gicr {
+                discovery_range_base: read_u64(e, 4),
+                discovery_range_length: read_u32(e, 12),
+            },
+            GIC_ITS => Self::GicIts {
+                id: read_u32(e, 4),
+                physical_base: read_u64(e, 8),
+            },
+            _ => Self::Other { kind, bytes: e },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MadtEntries<'a> {
+    subtables: Subtables<'a>,
+}
+
+impl<'a> Iterator for MadtEntries<'a> {
+    type Item = MadtEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kind, bytes) = self.subtables.next()?;
+        Some(MadtEntry::parse(kind, bytes))
+    }
+}
@@ -0,0 +1,50 @@
+//! The PCI Express memory-mapped configuration space table.
+
+use crate::read_u16;
+use crate::read_u64;
+use crate::AcpiError;
+use crate::Table;
+use crate::SDT_HEADER_SIZE;
+
+/// 8 bytes reserved after the header.
+const MCFG_ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+const MCFG_ENTRY_SIZE: usize = 16;
+
+/// The ECAM window of a segment for a range of the buses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McfgEntry {
+    /// The physical address of the configuration space of the bus `0`,
+    /// even if `start_bus` is not `0`.
+    pub base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mcfg<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Mcfg<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"MCFG";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, MCFG_ENTRIES_OFFSET)?,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> + 'a {
+        self.table.bytes[MCFG_ENTRIES_OFFSET..]
+            .as_chunks::<MCFG_ENTRY_SIZE>()
+            .0
+            .iter()
+            .map(|e| McfgEntry {
+                base: read_u64(e, 0),
+                segment: read_u16(e, 8),
+                start_bus: e[10],
+                end_bus: e[11],
+            })
+    }
+}
@@ -0,0 +1,97 @@
+//! The Serial Port Console Redirection table.
+
+use crate::read_u32;
+use crate::read_u8;
+use crate::AcpiError;
+use crate::GenericAddress;
+use crate::Table;
+
+/// The revision 2 layout, the later fields are read only if the table is
+/// long enough.
+const SPCR_V2_LENGTH: usize = 80;
+
+const INTERFACE_TYPE: usize = 36;
+const BASE_ADDRESS: usize = 40;
+const INTERRUPT_TYPE: usize = 52;
+const IRQ: usize = 53;
+const GSI: usize = 54;
+const BAUD_RATE: usize = 58;
+const UART_CLOCK_FREQUENCY: usize = 76;
+const PRECISE_BAUD_RATE: usize = 80;
+
+/// The interface types, as in the DBG2 table.
+pub const SPCR_16550: u8 = 0x00;
+pub const SPCR_16450: u8 = 0x01;
+pub const SPCR_PL011: u8 = 0x03;
+/// Only 32-bit accesses.
+pub const SPCR_SBSA_32BIT: u8 = 0x0d;
+pub const SPCR_SBSA: u8 = 0x0e;
+pub const SPCR_BCM2835: u8 = 0x10;
+/// The register width and the access size are in the base address.
+pub const SPCR_16550_GAS: u8 = 0x12;
+
+/// The interrupt of the console is a GIC one, in the interrupt type.
+pub const SPCR_INTERRUPT_GIC: u8 = 1 << 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Spcr<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Spcr<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"SPCR";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, SPCR_V2_LENGTH)?,
+        })
+    }
+
+    /// One of `SPCR_*`.
+    pub fn interface_type(&self) -> u8 {
+        read_u8(self.table.bytes, INTERFACE_TYPE)
+    }
+
+    pub fn base_address(&self) -> GenericAddress {
+        GenericAddress::read(self.table.bytes, BASE_ADDRESS)
+    }
+
+    pub fn interrupt_type(&self) -> u8 {
+        read_u8(self.table.bytes, INTERRUPT_TYPE)
+    }
+
+    /// The PC-AT IRQ for the 8259 PICs.
+    pub fn irq(&self) -> u8 {
+        read_u8(self.table.bytes, IRQ)
+    }
+
+    pub fn gsi(&self) -> u32 {
+        read_u32(self.table.bytes, GSI)
+    }
+
+    /// `None` if the firmware leaves the rate as it is.
+    pub fn baud_rate(&self) -> Option<u32> {
+        if self.table.bytes.len() >= PRECISE_BAUD_RATE + 4 {
+            match read_u32(self.table.bytes, PRECISE_BAUD_RATE) {
+                0 => {}
+                rate => return Some(rate),
+            }
+        }
+        match read_u8(self.table.bytes, BAUD_RATE) {
+            3 => Some(9600),
+            4 => Some(19200),
+            6 => Some(57600),
+            7 => Some(115200),
+            _ => None,
+        }
+    }
+
+    /// The clock of the UART in Hz, of the revision 3 and later.
+    pub fn uart_clock_frequency(&self) -> Option<u32> {
+        match read_u32(self.table.bytes, UART_CLOCK_FREQUENCY) {
+            0 => None,
+            _ if self.table.header.revision < 3 => None,
+            frequency => Some(frequency),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The System Resource Affinity Table.
+
+use crate::read_u32;
+use crate::read_u64;
+use crate::AcpiError;
+use crate::Subtables;
+use crate::Table;
+use crate::SDT_HEADER_SIZE;
+
+/// 12 bytes reserved after the header.
+const SRAT_ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 12;
+
+const PROCESSOR_APIC: u8 = 0;
+const MEMORY: u8 = 1;
+const PROCESSOR_X2APIC: u8 = 2;
+const GICC: u8 = 3;
+const GIC_ITS: u8 = 4;
+
+/// The entry is used, the same bit in all the entries.
+pub const SRAT_ENABLED: u32 = 1 << 0;
+pub const SRAT_MEMORY_HOT_PLUGGABLE: u32 = 1 << 1;
+pub const SRAT_MEMORY_NON_VOLATILE: u32 = 1 << 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Srat<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Srat<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"SRAT";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, SRAT_ENTRIES_OFFSET)?,
+        })
+    }
+
+    pub fn entries(&self) -> SratEntries<'a> {
+        SratEntries {
+            subtables: Subtables::new(&self.table.bytes[SRAT_ENTRIES_OFFSET..]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SratEntry<'a> {
+    ProcessorApic {
+        /// Assembled from the low byte and the high three bytes.
+        proximity_domain: u32,
+        apic_id: u8,
+        flags: u32,
+        clock_domain: u32,
+    },
+    Memory {
+        proximity_domain: u32,
+        base: u64,
+        length: u64,
+        flags: u32,
+    },
+    ProcessorX2Apic {
+        proximity_domain: u32,
+        x2apic_id: u32,
+        flags: u32,
+        clock_domain: u32,
+    },
+    Gicc {
+        proximity_domain: u32,
+        /// The ACPI processor UID, as in the MADT GICC entry.
+        uid: u32,
+        flags: u32,
+        clock_domain: u32,
+    },
+    GicIts {
+        proximity_domain: u32,
+        its_id: u32,
+    },
+    /// Of the other types, or shorter than expected.
+    Other {
+        kind: u8,
+        bytes: &'a [u8],
+    },
+}
+
+impl<'a> SratEntry<'a> {
+    fn parse(kind: u8, e: &'a [u8]) -> Self {
+        let length = match kind {
+            PROCESSOR_APIC => 16,
+            MEMORY => 40,
+            PROCESSOR_X2APIC => 24,
+            GICC => 18,
+            GIC_ITS => 12,
+            _ => usize::MAX,
+        };
+        if e.len() < length {
+            return Self::Other { kind, bytes: e };
+        }
+        match kind {
+            PROCESSOR_APIC => Self::ProcessorApic {
+                proximity_domain: u32::from_le_bytes([e[2], e[9], e[10], e[11]]),
+                apic_id: e[3],
+                flags: read_u32(e, 4),
+                clock_domain: read_u32(e, 12),
+            },
+            MEMORY => Self::Memory {
+                proximity_domain: read_u32(e, 2),
+                base: read_u64(e, 8),
+                length: read_u64(e, 16),
+                flags: read_u32(e, 28),
+            },
+            PROCESSOR_X2APIC => Self::ProcessorX2Apic {
+                proximity_domain: read_u32(e, 4),
+                x2apic_id: read_u32(e, 8),
+                flags: read_u32(e, 12),
+                clock_domain: read_u32(e, 16),
+            },
+            GICC => Self::Gicc {
+                proximity_domain: read_u32(e, 2),
+                uid: read_u32(e, 6),
+                flags: read_u32(e, 10),
+                clock_domain: read_u32(e, 14),
+            },
+            GIC_ITS => Self::GicIts {
+                proximity_domain: read_u32(e, 2),
+                its_id: read_u32(e, 8),
+            },
+            _ => Self::Other { kind, bytes: e },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SratEntries<'a> {
+    subtables: Subtables<'a>,
+}
+
+impl<'a> Iterator for SratEntries<'a> {
+    type Item = SratEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kind, bytes) = self.subtables.next()?;
+        Some(SratEntry::parse(kind, bytes))
+    }
+}
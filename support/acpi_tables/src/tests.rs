@@ -0,0 +1,380 @@
+#![cfg(test)]
+
+use crate::fadt::FADT_HW_REDUCED_ACPI;
+use crate::spcr::SPCR_PL011;
+use crate::tpm2::TPM2_START_CRB;
+use crate::AcpiError;
+use crate::AcpiTables;
+use crate::GenericAddress;
+use crate::MadtEntry;
+use crate::McfgEntry;
+use crate::Rsdp;
+use crate::SratEntry;
+use crate::Table;
+use crate::TimerInterrupt;
+use crate::Tpm2;
+
+fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn fix_checksum(bytes: &mut [u8], range: core::ops::Range<usize>, at: usize) {
+    bytes[at] = 0;
+    let sum = bytes[range].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes[at] = sum.wrapping_neg();
+}
+
+/// The header with the checksum, then the body.
+fn table(signature: &[u8; 4], revision: u8, body: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0; 36];
+    bytes.extend_from_slice(body);
+    let length = bytes.len();
+    put(&mut bytes, 0, signature);
+    put(&mut bytes, 4, &(length as u32).to_le_bytes());
+    bytes[8] = revision;
+    put(&mut bytes, 10, b"CORGOS");
+    fix_checksum(&mut bytes, 0..length, 9);
+    bytes
+}
+
+fn rsdp(revision: u8, rsdt: u32, xsdt: u64) -> Vec<u8> {
+    let mut bytes = vec![0; 36];
+    put(&mut bytes, 0, b"RSD PTR ");
+    put(&mut bytes, 9, b"CORGOS");
+    bytes[15] = revision;
+    put(&mut bytes, 16, &rsdt.to_le_bytes());
+    put(&mut bytes, 20, &36u32.to_le_bytes());
+    put(&mut bytes, 24, &xsdt.to_le_bytes());
+    fix_checksum(&mut bytes, 0..20, 8);
+    fix_checksum(&mut bytes, 0..36, 32);
+    bytes
+}
+
+fn madt() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+    body.extend_from_slice(&1u32.to_le_bytes());
+    // Local APIC, UID 1, ID 2, enabled.
+    body.extend_from_slice(&[0, 8, 1, 2, 1, 0, 0, 0]);
+    // I/O APIC 3 at 0xfec00000 from the GSI 0.
+    body.extend_from_slice(&[1, 12, 3, 0, 0, 0, 0xc0, 0xfe, 0, 0, 0, 0]);
+    // GICD at 0x8000000, version 3.
+    let mut gicd = [0; 24];
+    gicd[..2].copy_from_slice(&[0xc, 24]);
+    put(&mut gicd, 8, &0x800_0000u64.to_le_bytes());
+    gicd[20] = 3;
+    body.extend_from_slice(&gicd);
+    // GICC, UID 7, MPIDR 0x100.
+    let mut gicc = [0; 80];
+    gicc[..2].copy_from_slice(&[0xb, 80]);
+    put(&mut gicc, 8, &7u32.to_le_bytes());
+    put(&mut gicc, 12, &1u32.to_le_bytes());
+    put(&mut gicc, 68, &0x100u64.to_le_bytes());
+    body.extend_from_slice(&gicc);
+    // Unknown type.
+    body.extend_from_slice(&[0x80, 3, 0xaa]);
+    // Malformed, ends the entries.
+    body.extend_from_slice(&[0x0, 0, 0, 0]);
+    body.extend_from_slice(&[0, 8, 9, 9, 1, 0, 0, 0]);
+    table(b"APIC", 5, &body)
+}
+
+fn fadt() -> Vec<u8> {
+    let mut body = vec![0; 276 - 36];
+    put(&mut body, 40 - 36, &0x1000u32.to_le_bytes());
+    put(&mut body, 112 - 36, &FADT_HW_REDUCED_ACPI.to_le_bytes());
+    put(&mut body, 129 - 36, &3u16.to_le_bytes());
+    body[131 - 36] = 3;
+    put(&mut body, 140 - 36, &0x2_0000_0000u64.to_le_bytes());
+    table(b"FACP", 6, &body)
+}
+
+fn spcr() -> Vec<u8> {
+    let mut body = vec![0; 88 - 36];
+    body[0] = SPCR_PL011;
+    put(&mut body, 4, &[0, 32, 0, 3]);
+    put(&mut body, 8, &0x900_0000u64.to_le_bytes());
+    body[52 - 36] = 1 << 3;
+    put(&mut body, 54 - 36, &33u32.to_le_bytes());
+    body[58 - 36] = 7;
+    put(&mut body, 76 - 36, &24_000_000u32.to_le_bytes());
+    table(b"SPCR", 4, &body)
+}
+
+fn gtdt() -> Vec<u8> {
+    let mut body = vec![0; 104 - 36];
+    put(&mut body, 0, &u64::MAX.to_le_bytes());
+    put(&mut body, 56 - 36, &30u32.to_le_bytes());
+    put(&mut body, 64 - 36, &27u32.to_le_bytes());
+    put(&mut body, 68 - 36, &4u32.to_le_bytes());
+    put(&mut body, 96 - 36, &28u32.to_le_bytes());
+    table(b"GTDT", 3, &body)
+}
+
+fn mcfg() -> Vec<u8> {
+    let mut body = vec![0; 8];
+    body.extend_from_slice(&0xb000_0000u64.to_le_bytes());
+    body.extend_from_slice(&[0, 0, 0, 0xff, 0, 0, 0, 0]);
+    table(b"MCFG", 1, &body)
+}
+
+fn srat() -> Vec<u8> {
+    let mut body = vec![0; 12];
+    // Processor APIC 2 in the domain 0x0403_0201.
+    let mut apic = [0; 16];
+    apic[..4].copy_from_slice(&[0, 16, 0x01, 2]);
+    put(&mut apic, 4, &1u32.to_le_bytes());
+    apic[9..12].copy_from_slice(&[0x02, 0x03, 0x04]);
+    body.extend_from_slice(&apic);
+    // Memory 0x1_0000_0000, 1 GiB, in the domain 1.
+    let mut memory = [0; 40];
+    memory[..2].copy_from_slice(&[1, 40]);
+    put(&mut memory, 2, &1u32.to_le_bytes());
+    put(&mut memory, 8, &0x1_0000_0000u64.to_le_bytes());
+    put(&mut memory, 16, &0x4000_0000u64.to_le_bytes());
+    put(&mut memory, 28, &3u32.to_le_bytes());
+    body.extend_from_slice(&memory);
+    // A GICC entry that is too short.
+    body.extend_from_slice(&[3, 6, 0, 0, 0, 0]);
+    table(b"SRAT", 3, &body)
+}
+
+fn tpm2() -> Vec<u8> {
+    let mut body = vec![0; 64 - 36];
+    put(&mut body, 40 - 36, &0xfed4_0040u64.to_le_bytes());
+    put(&mut body, 48 - 36, &TPM2_START_CRB.to_le_bytes());
+    table(b"TPM2", 4, &body)
+}
+
+/// The root table pointing to the tables, the tables must outlive it.
+fn root(signature: &[u8; 4], tables: &[&Vec<u8>], entry_size: usize) -> Vec<u8> {
+    let body: Vec<u8> = tables
+        .iter()
+        .flat_map(|table| (table.as_ptr() as u64).to_le_bytes()[..entry_size].to_vec())
+        .collect();
+    table(signature, 1, &body)
+}
+
+#[test]
+fn test_rsdp() {
+    let mut bytes = rsdp(2, 0x1000, 0x2000);
+    let parsed = Rsdp::new(&bytes).unwrap();
+    assert_eq!(parsed.revision, 2);
+    assert_eq!(parsed.rsdt_address, 0x1000);
+    assert_eq!(parsed.xsdt_address, 0x2000);
+    assert_eq!(&parsed.oem_id, b"CORGOS");
+    assert_eq!(Rsdp::new(&bytes[..20]).unwrap_err(), AcpiError::Truncated);
+
+    bytes[30] ^= 1;
+    assert_eq!(Rsdp::new(&bytes).unwrap_err(), AcpiError::BadChecksum);
+    bytes[0] = b'X';
+    assert_eq!(Rsdp::new(&bytes).unwrap_err(), AcpiError::BadSignature);
+
+    let v1 = rsdp(0, 0x1000, 0);
+    assert_eq!(Rsdp::new(&v1[..20]).unwrap().xsdt_address, 0);
+}
+
+#[test]
+fn test_table() {
+    let mut bytes = mcfg();
+    let table = Table::new(&bytes).unwrap();
+    assert_eq!(&table.header.signature, b"MCFG");
+    assert_eq!(table.header.length as usize, bytes.len());
+    assert_eq!(table.body().len(), bytes.len() - 36);
+    assert_eq!(
+        Table::new(&bytes[..bytes.len() - 1]).unwrap_err(),
+        AcpiError::Truncated
+    );
+    bytes[40] ^= 1;
+    assert_eq!(Table::new(&bytes).unwrap_err(), AcpiError::BadChecksum);
+}
+
+#[test]
+fn test_xsdt() {
+    let (madt, fadt, spcr) = (madt(), fadt(), spcr());
+    let mut broken = gtdt();
+    broken[40] ^= 1;
+    let xsdt = root(b"XSDT", &[&madt, &broken, &fadt, &spcr], 8);
+    let rsdp = rsdp(2, 0, xsdt.as_ptr() as u64);
+
+    // SAFETY: the tables are in the vectors above.
+    let tables = unsafe { AcpiTables::from_rsdp(rsdp.as_ptr() as u64, 0) }.unwrap();
+    assert_eq!(&tables.root.header.signature, b"XSDT");
+    assert_eq!(tables.addresses().count(), 4);
+    let signatures: Vec<_> = tables.tables().map(|t| t.header.signature).collect();
+    assert_eq!(signatures, [*b"APIC", *b"FACP", *b"SPCR"]);
+    assert!(tables.gtdt().is_none());
+    assert!(tables.find(b"SSDT").is_none());
+
+    let madt = tables.madt().unwrap();
+    assert_eq!(madt.local_apic_address(), 0xfee0_0000);
+    assert_eq!(madt.flags(), 1);
+    let entries: Vec<_> = madt.entries().collect();
+    assert_eq!(entries.len(), 5);
+    assert_eq!(
+        entries[0],
+        MadtEntry::LocalApic {
+            uid: 1,
+            apic_id: 2,
+            flags: 1
+        }
+    );
+    assert_eq!(
+        entries[1],
+        MadtEntry::IoApic {
+            id: 3,
+            address: 0xfec0_0000,
+            gsi_base: 0
+        }
+    );
+    assert_eq!(
+        entries[2],
+        MadtEntry::Gicd {
+            id: 0,
+            physical_base: 0x800_0000,
+            version: 3
+        }
+    );
+    assert!(matches!(
+        entries[3],
+        MadtEntry::Gicc {
+            uid: 7,
+            flags: 1,
+            mpidr: 0x100,
+            ..
+        }
+    ));
+    assert_eq!(
+        entries[4],
+        MadtEntry::Other {
+            kind: 0x80,
+            bytes: &[0x80, 3, 0xaa]
+        }
+    );
+
+    let fadt = tables.fadt().unwrap();
+    assert!(fadt.is_hw_reduced());
+    assert_eq!(fadt.arm_boot_arch(), Some(3));
+    assert_eq!(fadt.version(), (6, 3));
+    assert_eq!(fadt.dsdt_address(), 0x2_0000_0000);
+    assert_eq!(fadt.reset(), None);
+
+    let spcr = tables.spcr().unwrap();
+    assert_eq!(spcr.interface_type(), SPCR_PL011);
+    assert_eq!(
+        spcr.base_address(),
+        GenericAddress {
+            address_space: GenericAddress::SYSTEM_MEMORY,
+            bit_width: 32,
+            bit_offset: 0,
+            access_size: 3,
+            address: 0x900_0000
+        }
+    );
+    assert_eq!(spcr.gsi(), 33);
+    assert_eq!(spcr.baud_rate(), Some(115200));
+    assert_eq!(spcr.uart_clock_frequency(), Some(24_000_000));
+}
+
+#[test]
+fn test_rsdt() {
+    // The tables at their "physical" offsets in one buffer, reached with
+    // its address as the offset.
+    let tables = [gtdt(), mcfg(), srat()];
+    let mut offsets = Vec::new();
+    let mut memory = vec![0; 64];
+    for table in &tables {
+        offsets.push(memory.len() as u32);
+        memory.extend_from_slice(table);
+    }
+    let body: Vec<u8> = offsets.iter().flat_map(|o| o.to_le_bytes()).collect();
+    let rsdt_offset = memory.len() as u32;
+    memory.extend_from_slice(&table(b"RSDT", 1, &body));
+    put(&mut memory, 0, &rsdp(0, rsdt_offset, 0)[..20]);
+
+    // SAFETY: the tables are in the buffer above.
+    let tables = unsafe { AcpiTables::from_rsdp(0, memory.as_ptr() as u64) }.unwrap();
+    assert_eq!(tables.rsdp.revision, 0);
+    assert_eq!(&tables.root.header.signature, b"RSDT");
+    assert_eq!(
+        tables.addresses().collect::<Vec<_>>(),
+        offsets.iter().map(|&o| o as u64).collect::<Vec<_>>()
+    );
+    assert_eq!(tables.tables().count(), 3);
+    assert!(tables.srat().is_some());
+}
+
+#[test]
+fn test_gtdt_mcfg_srat() {
+    let (gtdt, mcfg, srat) = (gtdt(), mcfg(), srat());
+    let xsdt = root(b"XSDT", &[&gtdt, &mcfg, &srat], 8);
+    let rsdp = rsdp(2, 0, xsdt.as_ptr() as u64);
+    // SAFETY: the tables are in the vectors above.
+    let tables = unsafe { AcpiTables::from_rsdp(rsdp.as_ptr() as u64, 0) }.unwrap();
+
+    let gtdt = tables.gtdt().unwrap();
+    assert_eq!(gtdt.cnt_control_base(), u64::MAX);
+    assert_eq!(gtdt.non_secure_el1(), TimerInterrupt { gsiv: 30, flags: 0 });
+    assert_eq!(gtdt.virtual_el1(), TimerInterrupt { gsiv: 27, flags: 4 });
+    assert_eq!(gtdt.virtual_el2().unwrap().gsiv, 28);
+
+    let mcfg = tables.mcfg().unwrap();
+    assert_eq!(
+        mcfg.entries().collect::<Vec<_>>(),
+        [McfgEntry {
+            base: 0xb000_0000,
+            segment: 0,
+            start_bus: 0,
+            end_bus: 0xff
+        }]
+    );
+
+    let srat = tables.srat().unwrap();
+    let entries: Vec<_> = srat.entries().collect();
+    assert_eq!(
+        entries[..2],
+        [
+            SratEntry::ProcessorApic {
+                proximity_domain: 0x0403_0201,
+                apic_id: 2,
+                flags: 1,
+                clock_domain: 0
+            },
+            SratEntry::Memory {
+                proximity_domain: 1,
+                base: 0x1_0000_0000,
+                length: 0x4000_0000,
+                flags: 3
+            }
+        ]
+    );
+    assert!(matches!(entries[2], SratEntry::Other { kind: 3, .. }));
+}
+
+#[test]
+fn test_tpm2() {
+    let tpm2 = tpm2();
+    let xsdt = root(b"XSDT", &[&tpm2], 8);
+    let rsdp = rsdp(2, 0, xsdt.as_ptr() as u64);
+    // SAFETY: the tables are in the vectors above.
+    let tables = unsafe { AcpiTables::from_rsdp(rsdp.as_ptr() as u64, 0) }.unwrap();
+
+    let tpm2 = tables.tpm2().unwrap();
+    assert_eq!(tpm2.platform_class(), 0);
+    assert_eq!(tpm2.control_area(), 0xfed4_0040);
+    assert_eq!(tpm2.start_method(), TPM2_START_CRB);
+
+    let short = table(b"TPM2", 4, &[0; 12]);
+    assert_eq!(
+        Tpm2::new(Table::new(&short).unwrap()).unwrap_err(),
+        AcpiError::Truncated
+    );
+}
+
+#[test]
+fn test_no_root_table() {
+    let rsdp = rsdp(0, 0, 0);
+    // SAFETY: the RSDP is in the vector above, and points nowhere.
+    let error = unsafe { AcpiTables::from_rsdp(rsdp.as_ptr() as u64, 0) }.unwrap_err();
+    assert_eq!(error, AcpiError::NoRootTable);
+}
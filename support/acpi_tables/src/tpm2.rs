@@ -0,0 +1,53 @@
+//! The Trusted Platform Module 2 table, of the TCG ACPI Specification.
+
+use crate::read_u16;
+use crate::read_u32;
+use crate::read_u64;
+use crate::AcpiError;
+use crate::Table;
+
+/// Up to the start method, its parameters follow.
+const TPM2_LENGTH: usize = 52;
+
+const PLATFORM_CLASS: usize = 36;
+const CONTROL_AREA: usize = 40;
+const START_METHOD: usize = 48;
+
+/// The FIFO interface, the registers at `0xfed4_0000` on the PC platforms.
+pub const TPM2_START_TIS: u32 = 6;
+pub const TPM2_START_CRB: u32 = 7;
+/// The CRB, and an ACPI method to start the commands.
+pub const TPM2_START_CRB_ACPI: u32 = 8;
+/// The CRB, and the SMC call to start the commands.
+pub const TPM2_START_CRB_SMC: u32 = 11;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tpm2<'a> {
+    pub table: Table<'a>,
+}
+
+impl<'a> Tpm2<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"TPM2";
+
+    pub fn new(table: Table<'a>) -> Result<Self, AcpiError> {
+        Ok(Self {
+            table: table.expect(Self::SIGNATURE, TPM2_LENGTH)?,
+        })
+    }
+
+    /// `0` for the client, `1` for the server platforms.
+    pub fn platform_class(&self) -> u16 {
+        read_u16(self.table.bytes, PLATFORM_CLASS)
+    }
+
+    /// The physical address of the CRB control area, in the registers of
+    /// the locality 0 for the CRB. `0` or the FIFO base otherwise.
+    pub fn control_area(&self) -> u64 {
+        read_u64(self.table.bytes, CONTROL_AREA)
+    }
+
+    /// One of `TPM2_START_*`.
+    pub fn start_method(&self) -> u32 {
+        read_u32(self.table.bytes, START_METHOD)
+    }
+}
@@ -0,0 +1,144 @@
+//! Walking the frame records for the backtraces in the panic handlers.
+//!
+//! With `-C force-frame-pointers=yes` every function keeps a frame record
+//! on the stack, and the frame pointer register points at it:
+//!
+//! Offset Name
+//! ----------------------------------------------------------------------
+//! 0      The frame pointer of the caller
+//! 8      The return address into the caller
+//!
+//! That is `x29`/`x30` on aarch64 as AAPCS64 lays out, and `rbp` and the
+//! return address `call` has pushed on x86_64 as `push rbp; mov rbp, rsp`
+//! leaves them. The Win64 prologues LLVM emits for the UEFI targets may
+//! point `rbp` into the middle of a large frame instead, so the records
+//! are not trusted: each one is to be aligned and inside the stack, the
+//! return address to be in the code if its range is known, and the caller
+//! frame to be above the current one. The walk stops at the first record
+//! that is not, and the backtrace is shorter rather than made up.
+//!
+//! The stack bounds are what keeps the walk from following the chain
+//! across a stack switch. The kernel entry clears the frame pointer before
+//! calling into Rust, so the chain ends there, and the records on the
+//! firmware stack the loader has run on are outside of the kernel stack
+//! anyway.
+//!
+//! The addresses are reported raw, symbolizing them is up to the caller.
+//!
+//! Example:
+//! ```ignore
+//! let fp = backtrace::frame_pointer();
+//! // SAFETY: the stack is mapped.
+//! let frames = unsafe { backtrace::Frames::new(fp, stack) }.code(image);
+//! for (index, address) in frames.enumerate() {
+//!     log::error!("  #{index:<2} {address:#018x}");
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod tests;
+
+use core::ops::Range;
+
+/// The frames to walk at most.
+pub const MAX_FRAMES: usize = 32;
+
+/// The size of the frame record, and the alignment of the stack.
+const FRAME_RECORD_SIZE: u64 = 16;
+
+/// The frame pointer of the function this is inlined into.
+#[inline(always)]
+pub fn frame_pointer() -> u64 {
+    let fp: u64;
+    #[cfg(target_arch = "aarch64")]
+    // SAFETY: reading a register has no side effects.
+    unsafe {
+        core::arch::asm!("mov {}, x29", out(reg) fp, options(nomem, nostack))
+    };
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: reading a register has no side effects.
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack))
+    };
+    fp
+}
+
+/// The return addresses of the frames from the innermost one outwards.
+#[derive(Debug, Clone)]
+pub struct Frames {
+    fp: u64,
+    stack: Range<u64>,
+    code: Option<Range<u64>>,
+    remaining: usize,
+}
+
+impl Frames {
+    /// Walks the records from the one `fp` points at, up to
+    /// [`MAX_FRAMES`] of them.
+    ///
+    /// # Safety
+    ///
+    /// The `stack` is to be mapped and readable.
+    pub unsafe fn new(fp: u64, stack: Range<u64>) -> Self {
+        Self {
+            fp,
+            stack,
+            code: None,
+            remaining: MAX_FRAMES,
+        }
+    }
+
+    /// Stops at the first return address outside of the code.
+    pub fn code(mut self, code: Range<u64>) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Walks `frames` at most.
+    pub fn limit(mut self, frames: usize) -> Self {
+        self.remaining = frames;
+        self
+    }
+
+    fn record_in_stack(&self, fp: u64) -> bool {
+        fp.is_multiple_of(FRAME_RECORD_SIZE)
+            && fp >= self.stack.start
+            && fp
+                .checked_add(FRAME_RECORD_SIZE)
+                .is_some_and(|end| end <= self.stack.end)
+    }
+
+    fn in_code(&self, address: u64) -> bool {
+        self.code
+            .as_ref()
+            .is_none_or(|code| code.contains(&address))
+    }
+}
+
+impl Iterator for Frames {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 || !self.record_in_stack(self.fp) {
+            return None;
+        }
+
+        // SAFETY: the frame record is in the stack, which is readable.
+        let [caller_fp, return_address] = unsafe { (self.fp as *const [u64; 2]).read() };
+        if return_address == 0 || !self.in_code(return_address) {
+            self.remaining = 0;
+            return None;
+        }
+
+        // The stack grows down, the caller frames are above. Anything else
+        // is a corrupted record or a loop.
+        self.remaining = if caller_fp > self.fp {
+            self.remaining - 1
+        } else {
+            0
+        };
+        self.fp = caller_fp;
+        Some(return_address)
+    }
+}
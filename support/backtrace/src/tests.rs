@@ -0,0 +1,121 @@
+#![cfg(test)]
+
+use super::*;
+
+#[repr(C, align(16))]
+struct Stack([u64; 32]);
+
+impl Stack {
+    fn new() -> Box<Self> {
+        Box::new(Self([0; 32]))
+    }
+
+    fn address(&self, slot: usize) -> u64 {
+        &self.0[slot] as *const u64 as u64
+    }
+
+    fn range(&self) -> Range<u64> {
+        self.address(0)..self.address(0) + size_of::<Self>() as u64
+    }
+
+    fn record(&mut self, slot: usize, caller_fp: u64, return_address: u64) {
+        self.0[slot] = caller_fp;
+        self.0[slot + 1] = return_address;
+    }
+
+    fn frames(&self, slot: usize) -> Frames {
+        // SAFETY: the stack is a live allocation.
+        unsafe { Frames::new(self.address(slot), self.range()) }
+    }
+}
+
+/// The records in the slots 2, 6, 12, the outermost has no caller.
+fn chain() -> Box<Stack> {
+    let mut stack = Stack::new();
+    let (middle, outer) = (stack.address(6), stack.address(12));
+    stack.record(2, middle, 0x1010);
+    stack.record(6, outer, 0x1020);
+    stack.record(12, 0, 0x1030);
+    stack
+}
+
+#[test]
+fn walks_the_chain() {
+    let stack = chain();
+    assert_eq!(
+        stack.frames(2).collect::<Vec<_>>(),
+        [0x1010, 0x1020, 0x1030]
+    );
+    assert_eq!(stack.frames(6).collect::<Vec<_>>(), [0x1020, 0x1030]);
+}
+
+#[test]
+fn stops_at_the_stack_bounds() {
+    let mut stack = chain();
+    // The outermost caller is on another stack, as the firmware's is.
+    let elsewhere = stack.address(0) + 0x10_0000;
+    stack.record(12, elsewhere, 0x1030);
+    assert_eq!(
+        stack.frames(2).collect::<Vec<_>>(),
+        [0x1010, 0x1020, 0x1030]
+    );
+
+    // The record straddling the end.
+    // SAFETY: the stack is a live allocation.
+    let frames = unsafe { Frames::new(stack.address(2), stack.address(0)..stack.address(3)) };
+    assert_eq!(frames.count(), 0);
+
+    // SAFETY: nothing is read outside of the stack.
+    let frames = unsafe { Frames::new(u64::MAX - 0xf, stack.range()) };
+    assert_eq!(frames.count(), 0);
+}
+
+#[test]
+fn stops_at_the_bad_records() {
+    // Misaligned.
+    let mut stack = chain();
+    stack.record(6, stack.address(13), 0x1020);
+    assert_eq!(stack.frames(2).collect::<Vec<_>>(), [0x1010, 0x1020]);
+
+    // No return address.
+    let mut stack = chain();
+    stack.record(6, stack.address(12), 0);
+    assert_eq!(stack.frames(2).collect::<Vec<_>>(), [0x1010]);
+
+    // The caller below, and a loop.
+    let mut stack = chain();
+    stack.record(6, stack.address(2), 0x1020);
+    assert_eq!(stack.frames(2).collect::<Vec<_>>(), [0x1010, 0x1020]);
+    let mut stack = chain();
+    stack.record(6, stack.address(6), 0x1020);
+    assert_eq!(stack.frames(2).collect::<Vec<_>>(), [0x1010, 0x1020]);
+}
+
+#[test]
+fn stops_outside_of_the_code() {
+    let stack = chain();
+    assert_eq!(
+        stack.frames(2).code(0x1000..0x1030).collect::<Vec<_>>(),
+        [0x1010, 0x1020]
+    );
+    assert_eq!(stack.frames(2).code(0x2000..0x3000).count(), 0);
+}
+
+#[test]
+fn limit() {
+    let stack = chain();
+    assert_eq!(
+        stack.frames(2).limit(2).collect::<Vec<_>>(),
+        [0x1010, 0x1020]
+    );
+    assert_eq!(stack.frames(2).limit(0).count(), 0);
+
+    // A long chain ends at the record with no return address.
+    let mut stack = Stack::new();
+    for slot in (0..30).step_by(2) {
+        let caller = stack.address(slot + 2);
+        stack.record(slot, caller, 0x1000 + slot as u64);
+    }
+    assert_eq!(stack.frames(0).count(), 15);
+    assert_eq!(stack.frames(0).limit(4).count(), 4);
+}
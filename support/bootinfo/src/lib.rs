@@ -0,0 +1,1149 @@
+//! The loader to kernel handoff structures.
+//!
+//! The loader fills in [`BootInfo`] and passes its address to the kernel
+//! in the first argument register of the C calling convention, `x0` on
+//! aarch64 and `rdi` on x86_64. Both sides are built from this crate so the
+//! layout is shared, and the structures are `#[repr(C)]` so that is stable.
+//! They derive the `zerocopy` traits, so there is no padding in them, and
+//! the kernel reads them with [`BootInfo::from_bytes`] that refuses the
+//! values outside of the enums.
+//!
+//! The two may still come from different builds, so [`BootInfo`] starts
+//! with the magic, the version and the size, and the loader
+//! [seals](BootInfo::seal) it with a checksum the kernel
+//! [validates](BootInfo::validate). Any change to the layout bumps
+//! [`BOOT_INFO_VERSION`]; the layout snapshot tests fail until the
+//! snapshot and the version are updated together.
+
+#![cfg_attr(not(test), no_std)]
+
+mod tests;
+
+use zerocopy::ConvertError;
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+use zerocopy::TryFromBytes;
+
+/// b"CORGBOOT"
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"CORGBOOT");
+pub const BOOT_INFO_VERSION: u32 = 11;
+
+/// The version of the [`MemoryRegion`] layout and the kinds.
+pub const MEMORY_MAP_VERSION: u32 = 1;
+
+/// Write-back cacheable memory.
+pub const MEMORY_ATTRIBUTE_WRITE_BACK: u64 = 1 << 0;
+/// Write-through cacheable memory.
+pub const MEMORY_ATTRIBUTE_WRITE_THROUGH: u64 = 1 << 1;
+/// Write-combining memory.
+pub const MEMORY_ATTRIBUTE_WRITE_COMBINE: u64 = 1 << 2;
+/// Uncacheable memory.
+pub const MEMORY_ATTRIBUTE_UNCACHEABLE: u64 = 1 << 3;
+/// Non-volatile memory.
+pub const MEMORY_ATTRIBUTE_NON_VOLATILE: u64 = 1 << 4;
+
+/// The most boot modules the loader passes.
+pub const MAX_MODULES: usize = 8;
+pub const MAX_MODULE_NAME_SIZE: usize = 64;
+pub const MAX_MODULE_CMDLINE_SIZE: usize = 256;
+
+/// The most processors the loader reports.
+pub const MAX_CPUS: usize = 256;
+
+/// The pages below 1 MiB at [`Cpus::ap_trampoline`]: the SIPI trampoline
+/// code, and the page tables it switches to the long mode with.
+pub const AP_TRAMPOLINE_PAGES: usize = 4;
+
+/// The kernel command line, zero-terminated.
+pub const MAX_KERNEL_CMDLINE_SIZE: usize = 256;
+
+/// Where the loader image is, the memory can be reclaimed once the kernel
+/// no longer uses anything the loader has set up.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct LoaderImage {
+    /// Physical address of the first byte.
+    pub phys_base: u64,
+    /// The size of the image in bytes.
+    pub size: u64,
+}
+
+/// The loader has zeroed the whole image before loading it, the BSS
+/// included.
+pub const KERNEL_IMAGE_BSS_ZEROED: u64 = 1 << 0;
+
+/// Where the kernel image has been placed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct KernelImage {
+    /// Physical address of the first loaded byte.
+    pub phys_base: u64,
+    /// Virtual address of the first loaded byte.
+    pub virt_base: u64,
+    /// The size of the loaded image in bytes, a multiple of 4 KiB.
+    pub size: u64,
+    /// The virtual address of the entry point.
+    pub entry: u64,
+    /// The difference between the virtual base and the one the kernel
+    /// has been linked at, `0` unless KASLR is enabled. Needed to symbolize
+    /// the addresses.
+    pub virt_slide: u64,
+    /// `KERNEL_IMAGE_*`
+    pub flags: u64,
+}
+
+/// A kernel function.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct KernelSymbol {
+    /// The virtual address, with the KASLR slide applied.
+    pub address: u64,
+    /// `0` if not known.
+    pub size: u64,
+    /// The offset of the zero-terminated name in the names.
+    pub name_offset: u32,
+    pub reserved: u32,
+}
+
+/// The function symbols from the kernel image for symbolizing the
+/// backtraces, all zeroes if the image has none. The [`KernelSymbol`]
+/// array sorted by the address comes first, and the names follow it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct KernelSymbols {
+    pub phys_base: u64,
+    /// In the linear map of the physical memory.
+    pub virt_base: u64,
+    pub size: u64,
+    /// The number of the symbols.
+    pub count: u64,
+    /// The offset of the names from the base.
+    pub names_offset: u64,
+}
+
+impl KernelSymbols {
+    /// # Safety
+    ///
+    /// `base` must be [`KernelSymbols::phys_base`] or
+    /// [`KernelSymbols::virt_base`], and must be accessible.
+    pub unsafe fn symbols(&self, base: u64) -> &[KernelSymbol] {
+        if self.count == 0 {
+            return &[];
+        }
+        // SAFETY: guaranteed by the caller.
+        unsafe { core::slice::from_raw_parts(base as *const KernelSymbol, self.count as usize) }
+    }
+
+    /// The name of the function `address` is in, and the offset into it.
+    ///
+    /// # Safety
+    ///
+    /// The same as for [`KernelSymbols::symbols`].
+    pub unsafe fn lookup(&self, base: u64, address: u64) -> Option<(&str, u64)> {
+        // SAFETY: guaranteed by the caller.
+        let symbols = unsafe { self.symbols(base) };
+        let symbol = &symbols[symbols
+            .partition_point(|symbol| symbol.address <= address)
+            .checked_sub(1)?];
+        let offset = address - symbol.address;
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+
+        let name = base + self.names_offset + symbol.name_offset as u64;
+        // SAFETY: the names are zero-terminated, and follow the symbols.
+        let name = unsafe { core::ffi::CStr::from_ptr(name as *const core::ffi::c_char) };
+        Some((name.to_str().unwrap_or_default(), offset))
+    }
+}
+
+/// The most GIC redistributor discovery ranges the loader reports.
+pub const MAX_GICR_RANGES: usize = 8;
+
+/// How the secondary processors are started.
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum CpuEnableMethod {
+    /// No known method, the kernel runs on the boot processor only.
+    #[default]
+    None = 0,
+    /// `CPU_ON` of PSCI through `smc`.
+    PsciSmc = 1,
+    /// `CPU_ON` of PSCI through `hvc`.
+    PsciHvc = 2,
+    /// The ACPI parking protocol, the mailbox addresses are in
+    /// [`CpuInfo::parked_address`].
+    ParkingProtocol = 3,
+    /// INIT-SIPI-SIPI with the trampoline at [`Cpus::ap_trampoline`].
+    Sipi = 4,
+}
+
+/// The EFI MP Services have reported the processor.
+pub const CPU_FLAG_MP_REPORTED: u32 = 1 << 0;
+/// The EFI MP Services report the processor as healthy.
+pub const CPU_FLAG_HEALTHY: u32 = 1 << 1;
+/// The loader has run on the processor, see [`CpuInfo::revision`].
+pub const CPU_FLAG_PROBED: u32 = 1 << 2;
+
+/// A processor that is enabled or can be brought online.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct CpuInfo {
+    /// MPIDR affinity on aarch64, local APIC ID on x86_64.
+    pub hw_id: u64,
+    /// The ACPI processor UID.
+    pub uid: u32,
+    /// `1` if enabled, `0` if only online capable.
+    pub enabled: u32,
+    /// The parking protocol mailbox, `0` if none.
+    pub parked_address: u64,
+    /// `CPU_FLAG_*`
+    pub flags: u32,
+    /// The location as reported by the EFI MP Services.
+    pub package: u32,
+    pub core: u32,
+    pub thread: u32,
+    /// Read on the processor itself if [`CPU_FLAG_PROBED`]: the microcode
+    /// revision on x86_64, `MIDR_EL1` on aarch64.
+    pub revision: u64,
+    /// The NUMA node, an index into [`Numa::proximity_domains`], `0`
+    /// without the SRAT.
+    pub node: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Cpus {
+    pub enable_method: CpuEnableMethod,
+    /// The number of used entries in [`Cpus::cpus`].
+    pub count: u32,
+    /// The hardware ID of the processor the loader ran on.
+    pub boot_cpu_hw_id: u64,
+    /// [`AP_TRAMPOLINE_PAGES`] below 1 MiB reserved for the SIPI
+    /// trampoline, `0` if none.
+    pub ap_trampoline: u64,
+    pub cpus: [CpuInfo; MAX_CPUS],
+}
+
+impl Default for Cpus {
+    fn default() -> Self {
+        Self {
+            enable_method: CpuEnableMethod::None,
+            count: 0,
+            boot_cpu_hw_id: 0,
+            ap_trampoline: 0,
+            cpus: [CpuInfo::default(); MAX_CPUS],
+        }
+    }
+}
+
+impl Cpus {
+    pub fn cpus(&self) -> &[CpuInfo] {
+        &self.cpus[..self.count as usize]
+    }
+}
+
+/// A GIC redistributor discovery range.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct GicrRange {
+    pub base: u64,
+    pub length: u64,
+}
+
+/// The aarch64 interrupt controller, all zeroes on x86_64 or if not found.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Gic {
+    /// The architecture revision from `GICD_PIDR2`, `3` or `4`.
+    pub version: u32,
+    /// The number of used entries in [`Gic::gicr_ranges`].
+    pub gicr_range_count: u32,
+    /// The distributor base address.
+    pub gicd_base: u64,
+    pub gicr_ranges: [GicrRange; MAX_GICR_RANGES],
+    /// The redistributor frame of the boot processor, `0` if not found.
+    pub boot_gicr_base: u64,
+    /// `1` if the loader has woken up the boot processor redistributor.
+    pub boot_gicr_awake: u32,
+    pub reserved: u32,
+    /// The distributor in the device window, `0` if not mapped.
+    pub gicd_virt_base: u64,
+    /// The redistributor frame of the boot processor in the device window,
+    /// `0` if not mapped.
+    pub boot_gicr_virt_base: u64,
+}
+
+impl Gic {
+    pub fn gicr_ranges(&self) -> &[GicrRange] {
+        &self.gicr_ranges[..self.gicr_range_count as usize]
+    }
+}
+
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum ApicMode {
+    /// The local APIC is disabled or not found.
+    #[default]
+    Disabled = 0,
+    /// The registers are memory-mapped at [`Apic::phys_base`].
+    XApic = 1,
+    /// The registers are the MSRs, the MMIO window is not used.
+    X2Apic = 2,
+}
+
+/// The x86_64 local APIC of the boot processor, all zeroes on aarch64.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Apic {
+    /// The mode at the handoff.
+    pub mode: ApicMode,
+    /// `1` if the processor supports the x2APIC mode.
+    pub x2apic_supported: u32,
+    /// `1` if the loader has switched the local APIC to the x2APIC mode.
+    pub x2apic_enabled_by_loader: u32,
+    pub reserved: u32,
+    /// The raw `IA32_APIC_BASE` MSR after the setup.
+    pub apic_base_msr: u64,
+    /// The physical address of the xAPIC registers from `IA32_APIC_BASE`.
+    pub phys_base: u64,
+    /// The address from the MADT, with the override entry applied, `0` if
+    /// there is no MADT.
+    pub madt_base: u64,
+    /// The xAPIC registers in the device window, `0` if not mapped.
+    pub virt_base: u64,
+}
+
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum ConsoleKind {
+    /// No serial console, the loader logged to the UEFI console or nowhere.
+    #[default]
+    None = 0,
+    /// PL011 at [`Console::virt_base`].
+    Pl011 = 1,
+    /// 16550 in the I/O port space at [`Console::io_port`].
+    Uart16550Io = 2,
+}
+
+/// The serial console the loader has been logging to.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Console {
+    pub kind: ConsoleKind,
+    pub io_port: u16,
+    pub reserved: u16,
+    /// The physical address of the registers, `0` for the I/O ports.
+    pub phys_base: u64,
+    /// Where the registers are mapped in the kernel address space with
+    /// the device memory attributes, `0` for the I/O ports.
+    pub virt_base: u64,
+}
+
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum PixelFormat {
+    /// No framebuffer.
+    #[default]
+    None = 0,
+    /// 32 bits per pixel, red in the lowest byte.
+    Rgb = 1,
+    /// 32 bits per pixel, blue in the lowest byte.
+    Bgr = 2,
+    /// Described by the masks in [`Framebuffer`].
+    Bitmask = 3,
+}
+
+/// The linear framebuffer of the display mode the loader has set.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Framebuffer {
+    pub pixel_format: PixelFormat,
+    pub bits_per_pixel: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The number of pixels per scan line, may be more than the width.
+    pub stride: u32,
+    pub reserved: u32,
+    /// The masks of the color bits for [`PixelFormat::Bitmask`].
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
+    /// The physical address of the framebuffer.
+    pub phys_base: u64,
+    /// Where the framebuffer is mapped in the kernel address space with
+    /// the device memory attributes.
+    pub virt_base: u64,
+    /// The size of the framebuffer in bytes.
+    pub size: u64,
+}
+
+/// Scratch memory for the early framebuffer console, so the kernel can
+/// double-buffer it before its allocator exists. The shadow buffer of the
+/// framebuffer size and layout comes first, and the font area follows at
+/// a page boundary. Zero-filled, mapped read-write and not executable. All
+/// zeroes if there is no framebuffer or the console is not configured.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct FramebufferConsole {
+    pub phys_base: u64,
+    /// In the linear map of the physical memory.
+    pub virt_base: u64,
+    /// The size of the whole region.
+    pub size: u64,
+    /// The size of the shadow buffer at the start of the region.
+    pub shadow_size: u64,
+    /// The offset of the font area from the start of the region.
+    pub font_offset: u64,
+    pub font_size: u64,
+}
+
+/// Where the timer frequency comes from.
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum TimerFrequencySource {
+    /// Not known.
+    #[default]
+    None = 0,
+    /// `CNTFRQ_EL0` set by the firmware.
+    Architectural = 1,
+    /// CPUID leaf `0x15`.
+    Cpuid = 2,
+    /// The hypervisor CPUID leaf `0x40000010`.
+    Hypervisor = 3,
+    /// Measured against the UEFI `Stall` service.
+    Calibrated = 4,
+}
+
+/// The free-running timer: TSC on x86_64, the virtual counter on aarch64.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Timer {
+    /// The ticks per second, `0` if not known.
+    pub frequency: u64,
+    pub source: TimerFrequencySource,
+    /// `1` if the frequency does not change with the power states.
+    pub invariant: u32,
+}
+
+/// [`WallClock::time_zone`] of the local time with no time zone.
+pub const WALL_CLOCK_UNSPECIFIED_TIME_ZONE: i16 = 0x07ff;
+/// The time is adjusted for the daylight saving time.
+pub const WALL_CLOCK_ADJUST_DAYLIGHT: u8 = 1 << 0;
+/// The time is in the daylight saving time.
+pub const WALL_CLOCK_IN_DAYLIGHT: u8 = 1 << 1;
+
+/// The time of day from the firmware read right before exiting the boot
+/// services, and the [`Timer`] counter read right after it, so the kernel
+/// can keep the wall-clock time without an RTC driver. All zeroes if the
+/// firmware could not tell the time.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct WallClock {
+    /// `1900`-`9999`.
+    pub year: u16,
+    /// `1`-`12`.
+    pub month: u8,
+    /// `1`-`31`.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// `WALL_CLOCK_ADJUST_DAYLIGHT` and `WALL_CLOCK_IN_DAYLIGHT`.
+    pub daylight: u8,
+    pub nanosecond: u32,
+    /// The offset from UTC in minutes, the local time is UTC plus that, or
+    /// [`WALL_CLOCK_UNSPECIFIED_TIME_ZONE`].
+    pub time_zone: i16,
+    pub reserved: u16,
+    pub counter: u64,
+}
+
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum RtcKind {
+    /// No real-time clock the kernel can read.
+    #[default]
+    None = 0,
+    /// PL031 at [`Rtc::virt_base`].
+    Pl031 = 1,
+    /// The CMOS clock at the I/O ports `0x70` and `0x71`.
+    Cmos = 2,
+}
+
+/// The real-time clock for the kernel to read the time of day when the
+/// [`WallClock`] is all zeroes, or to check it later. The EFI `GetTime`
+/// is not usable then, it needs the firmware mappings.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Rtc {
+    pub kind: RtcKind,
+    /// The CMOS register with the century from the FADT, `0` if none.
+    pub century_register: u8,
+    pub reserved: [u8; 3],
+    /// The physical address of the registers, `0` for the CMOS.
+    pub phys_base: u64,
+    /// Where the registers are mapped in the kernel address space with
+    /// the device memory attributes, `0` for the CMOS.
+    pub virt_base: u64,
+}
+
+#[repr(u32)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout,
+)]
+pub enum TpmInterface {
+    /// No TPM 2.0 the kernel can reach.
+    #[default]
+    None = 0,
+    /// The FIFO interface at [`Tpm::virt_base`].
+    Tis = 1,
+    /// The Command Response Buffer at [`Tpm::virt_base`].
+    Crb = 2,
+}
+
+/// The TPM 2.0 for the kernel to extend the PCRs with, `EFI_TCG2_PROTOCOL`
+/// is gone with the boot services.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Tpm {
+    pub interface: TpmInterface,
+    pub reserved: u32,
+    /// The physical address of the registers of the locality 0.
+    pub phys_base: u64,
+    /// Where the registers are mapped in the kernel address space with
+    /// the device memory attributes.
+    pub virt_base: u64,
+    /// The size of the mapping.
+    pub size: u64,
+}
+
+/// The PCI Express configuration space of the segment 0 from the MCFG
+/// table, all zeroes if there is none.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Pci {
+    /// The physical address of the configuration space of the bus `0`,
+    /// even if `start_bus` is not `0`.
+    pub ecam_phys_base: u64,
+    /// Where the bus `0` would be in the kernel address space, only the
+    /// buses `start_bus..=end_bus` are mapped with the device memory
+    /// attributes.
+    pub ecam_virt_base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+    pub reserved: u32,
+}
+
+/// The number of the [`BootStage`]s.
+pub const BOOT_STAGE_COUNT: usize = 7;
+
+/// A phase of the loader [`BootTimings`] are measured for.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    /// Finding and reading the configuration file.
+    Config = 0,
+    /// Reading the kernel image file.
+    KernelRead = 1,
+    /// Checking the SHA-256 of the kernel image.
+    KernelVerify = 2,
+    /// Placing the kernel segments and mapping them.
+    KernelLoad = 3,
+    /// Reading the boot modules.
+    Modules = 4,
+    /// Building the page tables for the kernel.
+    PageTables = 5,
+    /// Exiting the boot services.
+    ExitBootServices = 6,
+}
+
+impl BootStage {
+    pub const ALL: [Self; BOOT_STAGE_COUNT] = [
+        Self::Config,
+        Self::KernelRead,
+        Self::KernelVerify,
+        Self::KernelLoad,
+        Self::Modules,
+        Self::PageTables,
+        Self::ExitBootServices,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::KernelRead => "kernel read",
+            Self::KernelVerify => "kernel verify",
+            Self::KernelLoad => "kernel load",
+            Self::Modules => "modules",
+            Self::PageTables => "page tables",
+            Self::ExitBootServices => "exit boot services",
+        }
+    }
+}
+
+/// How long the loader has spent, in the ticks of the timestamp counter:
+/// TSC on x86_64, the virtual counter on aarch64.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct BootTimings {
+    /// The ticks per second, `0` if not known.
+    pub counter_frequency: u64,
+    /// The counter when the loader has started.
+    pub loader_start: u64,
+    /// The counter right before the handoff.
+    pub loader_end: u64,
+    /// The ticks spent in each [`BootStage`].
+    pub stages: [u64; BOOT_STAGE_COUNT],
+}
+
+impl BootTimings {
+    pub fn stage_ticks(&self, stage: BootStage) -> u64 {
+        self.stages[stage as usize]
+    }
+
+    /// Converts the ticks to microseconds, `0` if the frequency is not known.
+    pub fn microseconds(&self, ticks: u64) -> u64 {
+        if self.counter_frequency == 0 {
+            return 0;
+        }
+        (ticks as u128 * 1_000_000 / self.counter_frequency as u128) as u64
+    }
+}
+
+/// The page tables the loader has built for the kernel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Paging {
+    /// The physical address of the root (level 4) table.
+    pub root_table: u64,
+    /// Where the physical memory is mapped.
+    pub linear_map_base: u64,
+    /// The size of the linear map window.
+    pub linear_map_size: u64,
+    /// `MAIR_EL1` the attribute indices refer to on aarch64, `0` on x86_64.
+    pub mair: u64,
+}
+
+/// What the memory is used for.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub enum MemoryKind {
+    /// Free for the kernel to use.
+    Usable = 0,
+    /// Must not be touched.
+    Reserved = 1,
+    /// The kernel image.
+    KernelImage = 2,
+    /// The boot information and the data it points to, including the
+    /// memory map.
+    BootInfo = 3,
+    /// The kernel page tables.
+    PageTables = 4,
+    /// The page allocator bitmap.
+    PageBitmap = 5,
+    /// The loader image, can be reused once the kernel has installed its
+    /// own descriptor tables.
+    LoaderReclaimable = 6,
+    /// The ACPI tables, can be reused once they have been parsed.
+    AcpiReclaimable = 7,
+    /// The ACPI firmware memory.
+    AcpiNvs = 8,
+    /// The device registers.
+    Mmio = 9,
+    /// The UEFI runtime services code and data.
+    Runtime = 10,
+    /// The pages reserved for starting the secondary processors.
+    ApTrampoline = 11,
+    /// A boot module.
+    BootModule = 12,
+    /// The early kernel heap, see [`EarlyHeap`].
+    EarlyHeap = 13,
+    /// The initial kernel stack, see [`KernelStack`].
+    KernelStack = 14,
+    /// The kernel symbols, see [`KernelSymbols`].
+    KernelSymbols = 15,
+    /// The early framebuffer console scratch memory, see
+    /// [`FramebufferConsole`].
+    FramebufferConsole = 16,
+    /// The persistent memory, for a driver to use as storage.
+    Persistent = 17,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct MemoryRegion {
+    /// The physical address, 4 KiB aligned.
+    pub start: u64,
+    /// The number of 4 KiB pages.
+    pub pages: u64,
+    pub kind: MemoryKind,
+    pub reserved: u32,
+    /// `MEMORY_ATTRIBUTE_*`
+    pub attributes: u64,
+}
+
+impl MemoryRegion {
+    pub fn end(&self) -> u64 {
+        self.start + self.pages * 0x1000
+    }
+}
+
+/// The physical memory as seen by the loader when it has exited
+/// the boot services. The regions are sorted by address, and the
+/// adjacent ones of the same kind and attributes are merged.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct MemoryMap {
+    /// [`MEMORY_MAP_VERSION`]
+    pub version: u32,
+    /// The number of regions.
+    pub count: u32,
+    /// The physical address of the [`MemoryRegion`] array.
+    pub regions: u64,
+}
+
+impl MemoryMap {
+    /// # Safety
+    ///
+    /// `regions` must point to `count` regions, and must be accessible
+    /// at that address.
+    pub unsafe fn regions(&self) -> &[MemoryRegion] {
+        if self.count == 0 {
+            return &[];
+        }
+        // SAFETY: guaranteed by the caller.
+        unsafe {
+            core::slice::from_raw_parts(self.regions as *const MemoryRegion, self.count as usize)
+        }
+    }
+}
+
+/// Physically contiguous memory for the kernel allocator to start with
+/// before the kernel has parsed the memory map, mapped read-write and not
+/// executable. All zeroes if not configured.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct EarlyHeap {
+    pub phys_base: u64,
+    /// In the linear map of the physical memory.
+    pub virt_base: u64,
+    pub size: u64,
+}
+
+/// The page bitmap the loader has built over the memory map for the kernel
+/// page allocator, see the `page_bitmap` crate. The pages of the regions
+/// other than [`MemoryKind::Usable`] are allocated in it. All zeroes if
+/// there is none.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct PageBitmapStorage {
+    pub phys_base: u64,
+    /// In the linear map of the physical memory.
+    pub virt_base: u64,
+    /// The size of the storage, all of it is the bitmap.
+    pub size: u64,
+    /// The bitmap tracks the memory below this address.
+    pub max_memory: u64,
+    /// The CRC32 of the storage.
+    pub checksum: u32,
+    pub reserved: u32,
+}
+
+/// The stack the kernel starts on, with an unmapped guard page below it so
+/// that an overflow faults instead of corrupting the memory.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct KernelStack {
+    pub phys_base: u64,
+    /// The lowest mapped address, the guard page is right below.
+    pub virt_base: u64,
+    pub size: u64,
+    /// The initial stack pointer, 16 bytes aligned.
+    pub top: u64,
+}
+
+/// The in-memory log of the loader, for the kernel to keep the whole boot
+/// log. All zeroes if there is none.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct LoaderLog {
+    pub phys_base: u64,
+    /// In the linear map of the physical memory.
+    pub virt_base: u64,
+    pub size: u64,
+    /// Where the next byte would go.
+    pub head: u64,
+    /// `1` if the log has wrapped around, and the whole buffer is valid.
+    pub wrapped: u32,
+    pub reserved: u32,
+}
+
+impl LoaderLog {
+    /// The log from the oldest to the newest byte, that takes two slices
+    /// when the log has wrapped around.
+    ///
+    /// # Safety
+    ///
+    /// The log at [`LoaderLog::virt_base`] is mapped, and not reclaimed.
+    pub unsafe fn contents(&self) -> (&[u8], &[u8]) {
+        if self.size == 0 || self.head > self.size {
+            return (&[], &[]);
+        }
+        // SAFETY: guaranteed by the caller.
+        let buf =
+            unsafe { core::slice::from_raw_parts(self.virt_base as *const u8, self.size as usize) };
+        let (newer, older) = buf.split_at(self.head as usize);
+        if self.wrapped != 0 {
+            (older, newer)
+        } else {
+            (newer, &[])
+        }
+    }
+}
+
+/// A file the loader has loaded for the kernel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Module {
+    /// Zero-terminated.
+    pub name: [u8; MAX_MODULE_NAME_SIZE],
+    /// Zero-terminated.
+    pub cmdline: [u8; MAX_MODULE_CMDLINE_SIZE],
+    /// The physical address of the contents, 4 KiB aligned.
+    pub phys_base: u64,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+impl Default for Module {
+    fn default() -> Self {
+        Self {
+            name: [0; MAX_MODULE_NAME_SIZE],
+            cmdline: [0; MAX_MODULE_CMDLINE_SIZE],
+            phys_base: 0,
+            size: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Modules {
+    /// The number of used entries in [`Modules::modules`].
+    pub count: u32,
+    pub reserved: u32,
+    pub modules: [Module; MAX_MODULES],
+}
+
+impl Modules {
+    pub fn modules(&self) -> &[Module] {
+        &self.modules[..self.count as usize]
+    }
+}
+
+/// The runtime services the firmware supports after exiting the boot
+/// services, the `EFI_RT_SUPPORTED_*` bits of `EFI_RT_PROPERTIES_TABLE`.
+pub const EFI_RT_SUPPORTED_GET_TIME: u32 = 1 << 0;
+pub const EFI_RT_SUPPORTED_SET_TIME: u32 = 1 << 1;
+pub const EFI_RT_SUPPORTED_GET_WAKEUP_TIME: u32 = 1 << 2;
+pub const EFI_RT_SUPPORTED_SET_WAKEUP_TIME: u32 = 1 << 3;
+pub const EFI_RT_SUPPORTED_GET_VARIABLE: u32 = 1 << 4;
+pub const EFI_RT_SUPPORTED_GET_NEXT_VARIABLE_NAME: u32 = 1 << 5;
+pub const EFI_RT_SUPPORTED_SET_VARIABLE: u32 = 1 << 6;
+pub const EFI_RT_SUPPORTED_SET_VIRTUAL_ADDRESS_MAP: u32 = 1 << 7;
+pub const EFI_RT_SUPPORTED_CONVERT_POINTER: u32 = 1 << 8;
+pub const EFI_RT_SUPPORTED_GET_NEXT_HIGH_MONOTONIC_COUNT: u32 = 1 << 9;
+pub const EFI_RT_SUPPORTED_RESET_SYSTEM: u32 = 1 << 10;
+pub const EFI_RT_SUPPORTED_UPDATE_CAPSULE: u32 = 1 << 11;
+pub const EFI_RT_SUPPORTED_QUERY_CAPSULE_CAPABILITIES: u32 = 1 << 12;
+pub const EFI_RT_SUPPORTED_QUERY_VARIABLE_INFO: u32 = 1 << 13;
+/// All the runtime services, as assumed without `EFI_RT_PROPERTIES_TABLE`.
+pub const EFI_RT_SUPPORTED_ALL: u32 = (1 << 14) - 1;
+
+/// The UEFI runtime services, switched to the virtual addressing.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Efi {
+    /// The virtual address of the system table, `0` if not available.
+    pub system_table: u64,
+    /// The runtime regions are mapped at this address plus their physical
+    /// address.
+    pub runtime_map_base: u64,
+    /// The virtual address of the runtime services table, `0` if not
+    /// available.
+    pub runtime_services: u64,
+    /// The `EFI_RT_SUPPORTED_*` services that are safe to call, none if
+    /// the runtime services are not available.
+    pub runtime_services_supported: u32,
+    /// Whether the firmware has `EFI_RT_PROPERTIES_TABLE`, all the services
+    /// are assumed supported otherwise.
+    pub rt_properties_table: u32,
+}
+
+impl Efi {
+    pub fn is_supported(&self, service: u32) -> bool {
+        self.runtime_services_supported & service == service
+    }
+}
+
+/// The most NUMA nodes the loader reports.
+pub const MAX_NUMA_NODES: usize = 16;
+/// The most NUMA memory ranges the loader reports.
+pub const MAX_NUMA_MEMORY_RANGES: usize = 32;
+/// The distance of a node to itself in the SLIT.
+pub const NUMA_LOCAL_DISTANCE: u8 = 10;
+
+/// The memory range can be hot-plugged.
+pub const NUMA_MEMORY_HOT_PLUGGABLE: u32 = 1 << 1;
+/// The memory range is non-volatile.
+pub const NUMA_MEMORY_NON_VOLATILE: u32 = 1 << 2;
+
+/// The memory of a NUMA node from the SRAT.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct NumaMemoryRange {
+    pub base: u64,
+    pub length: u64,
+    /// An index into [`Numa::proximity_domains`].
+    pub node: u32,
+    /// `NUMA_MEMORY_*`
+    pub flags: u32,
+}
+
+/// The NUMA topology, a single node if there is no SRAT. The processors
+/// have their node in [`CpuInfo::node`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct Numa {
+    /// The number of used entries in [`Numa::proximity_domains`].
+    pub node_count: u32,
+    /// The number of used entries in [`Numa::memory_ranges`].
+    pub memory_range_count: u32,
+    /// The ACPI proximity domain of each node.
+    pub proximity_domains: [u32; MAX_NUMA_NODES],
+    /// Sorted by the base address.
+    pub memory_ranges: [NumaMemoryRange; MAX_NUMA_MEMORY_RANGES],
+    /// The relative distances between the nodes from the SLIT, with
+    /// [`NUMA_LOCAL_DISTANCE`] to the node itself. Without the SLIT, the
+    /// other nodes are twice as far.
+    pub distances: [[u8; MAX_NUMA_NODES]; MAX_NUMA_NODES],
+}
+
+impl Default for Numa {
+    fn default() -> Self {
+        let mut distances = [[2 * NUMA_LOCAL_DISTANCE; MAX_NUMA_NODES]; MAX_NUMA_NODES];
+        for (node, row) in distances.iter_mut().enumerate() {
+            row[node] = NUMA_LOCAL_DISTANCE;
+        }
+
+        Self {
+            node_count: 1,
+            memory_range_count: 0,
+            proximity_domains: [0; MAX_NUMA_NODES],
+            memory_ranges: [NumaMemoryRange::default(); MAX_NUMA_MEMORY_RANGES],
+            distances,
+        }
+    }
+}
+
+impl Numa {
+    pub fn memory_ranges(&self) -> &[NumaMemoryRange] {
+        &self.memory_ranges[..self.memory_range_count as usize]
+    }
+
+    /// The node the physical address belongs to, if any range covers it.
+    pub fn node_of(&self, phys: u64) -> Option<u32> {
+        self.memory_ranges()
+            .iter()
+            .find(|range| range.base <= phys && phys - range.base < range.length)
+            .map(|range| range.node)
+    }
+
+    pub fn distance(&self, from: u32, to: u32) -> u8 {
+        self.distances[from as usize][to as usize]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, TryFromBytes, IntoBytes, Immutable, KnownLayout)]
+pub struct BootInfo {
+    /// [`BOOT_INFO_MAGIC`]
+    pub magic: u64,
+    /// [`BOOT_INFO_VERSION`]
+    pub version: u32,
+    /// The size of this structure.
+    pub size: u32,
+    /// The CRC32 of this structure with the checksum taken as zero.
+    pub checksum: u32,
+    pub reserved: u32,
+    pub kernel: KernelImage,
+    pub cpus: Cpus,
+    pub gic: Gic,
+    pub paging: Paging,
+    pub console: Console,
+    pub efi: Efi,
+    pub memory_map: MemoryMap,
+    pub modules: Modules,
+    pub loader: LoaderImage,
+    pub framebuffer: Framebuffer,
+    pub timings: BootTimings,
+    pub timer: Timer,
+    pub apic: Apic,
+    pub numa: Numa,
+    pub early_heap: EarlyHeap,
+    pub kernel_stack: KernelStack,
+    pub kernel_symbols: KernelSymbols,
+    pub framebuffer_console: FramebufferConsole,
+    pub wall_clock: WallClock,
+    pub rtc: Rtc,
+    pub loader_log: LoaderLog,
+    pub tpm: Tpm,
+    pub pci: Pci,
+    pub page_bitmap: PageBitmapStorage,
+    /// Zero-terminated.
+    pub cmdline: [u8; MAX_KERNEL_CMDLINE_SIZE],
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: core::mem::size_of::<Self>() as u32,
+            checksum: 0,
+            reserved: 0,
+            kernel: KernelImage::default(),
+            cpus: Cpus::default(),
+            gic: Gic::default(),
+            paging: Paging::default(),
+            console: Console::default(),
+            efi: Efi::default(),
+            memory_map: MemoryMap::default(),
+            modules: Modules::default(),
+            loader: LoaderImage::default(),
+            framebuffer: Framebuffer::default(),
+            timings: BootTimings::default(),
+            timer: Timer::default(),
+            apic: Apic::default(),
+            numa: Numa::default(),
+            early_heap: EarlyHeap::default(),
+            kernel_stack: KernelStack::default(),
+            kernel_symbols: KernelSymbols::default(),
+            framebuffer_console: FramebufferConsole::default(),
+            wall_clock: WallClock::default(),
+            rtc: Rtc::default(),
+            loader_log: LoaderLog::default(),
+            tpm: Tpm::default(),
+            pci: Pci::default(),
+            page_bitmap: PageBitmapStorage::default(),
+            cmdline: [0; MAX_KERNEL_CMDLINE_SIZE],
+        }
+    }
+}
+
+// The header stays where it is in all the versions, so that the kernel
+// tells the boot info of another version from garbage.
+const _: () = assert!(core::mem::offset_of!(BootInfo, magic) == 0);
+const _: () = assert!(core::mem::offset_of!(BootInfo, version) == 8);
+const _: () = assert!(core::mem::offset_of!(BootInfo, size) == 12);
+const _: () = assert!(core::mem::offset_of!(BootInfo, checksum) == 16);
+
+/// Why the boot info is not valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootInfoError {
+    Null,
+    Misaligned(u64),
+    BadMagic(u64),
+    UnsupportedVersion(u32),
+    SizeMismatch(u32),
+    /// The CRC32 computed.
+    BadChecksum(u32),
+    /// An enum field holds a value that is not one of its variants.
+    InvalidValue,
+}
+
+impl BootInfo {
+    /// The boot info at the start of `bytes`, checked with
+    /// [`BootInfo::validate`]. The header is checked first as the rest may
+    /// be of another version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<&Self, BootInfoError> {
+        let too_short = BootInfoError::SizeMismatch(bytes.len() as u32);
+        let (magic, rest) = u64::read_from_prefix(bytes).map_err(|_| too_short)?;
+        if magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(magic));
+        }
+        let (version, _) = u32::read_from_prefix(rest).map_err(|_| too_short)?;
+        if version != BOOT_INFO_VERSION {
+            return Err(BootInfoError::UnsupportedVersion(version));
+        }
+
+        let bytes = bytes.get(..core::mem::size_of::<Self>()).ok_or(too_short)?;
+        let info = Self::try_ref_from_bytes(bytes).map_err(|err| match err {
+            ConvertError::Alignment(_) => BootInfoError::Misaligned(bytes.as_ptr() as u64),
+            _ => BootInfoError::InvalidValue,
+        })?;
+        info.validate()?;
+        Ok(info)
+    }
+
+    /// The CRC32 of the structure with the checksum taken as zero.
+    pub fn crc32(&self) -> u32 {
+        let (head, tail) = self
+            .as_bytes()
+            .split_at(core::mem::offset_of!(Self, checksum));
+        let mut crc = digest::Crc32::new();
+        crc.update(head);
+        crc.update(&[0; core::mem::size_of::<u32>()]);
+        crc.update(&tail[core::mem::size_of::<u32>()..]);
+        crc.finalize()
+    }
+
+    /// The kernel command line, empty if that is not valid UTF-8.
+    pub fn cmdline_str(&self) -> &str {
+        let len = self
+            .cmdline
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(self.cmdline.len());
+        core::str::from_utf8(&self.cmdline[..len]).unwrap_or_default()
+    }
+
+    /// Sets the checksum, must be called after the last change.
+    pub fn seal(&mut self) {
+        self.checksum = self.crc32();
+    }
+
+    pub fn validate(&self) -> Result<(), BootInfoError> {
+        if self.magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(self.magic));
+        }
+        if self.version != BOOT_INFO_VERSION {
+            return Err(BootInfoError::UnsupportedVersion(self.version));
+        }
+        if self.size as usize != core::mem::size_of::<Self>() {
+            return Err(BootInfoError::SizeMismatch(self.size));
+        }
+        match self.crc32() {
+            crc if crc == self.checksum => Ok(()),
+            crc => Err(BootInfoError::BadChecksum(crc)),
+        }
+    }
+}
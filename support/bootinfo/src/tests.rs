@@ -0,0 +1,184 @@
+#![cfg(test)]
+
+use super::*;
+use core::mem::offset_of;
+use core::mem::size_of;
+
+/// The layout of this version. A change to the layout fails the test, and
+/// [`BOOT_INFO_VERSION`] is to be bumped when updating the snapshot, so
+/// that the kernel refuses the boot info of a loader built before.
+#[test]
+fn layout_snapshot() {
+    assert_eq!((BOOT_INFO_VERSION, size_of::<BootInfo>()), (11, 19312));
+
+    let offsets = [
+        ("magic", offset_of!(BootInfo, magic), 0),
+        ("version", offset_of!(BootInfo, version), 8),
+        ("size", offset_of!(BootInfo, size), 12),
+        ("checksum", offset_of!(BootInfo, checksum), 16),
+        ("kernel", offset_of!(BootInfo, kernel), 24),
+        ("cpus", offset_of!(BootInfo, cpus), 72),
+        ("gic", offset_of!(BootInfo, gic), 14432),
+        ("paging", offset_of!(BootInfo, paging), 14608),
+        ("console", offset_of!(BootInfo, console), 14640),
+        ("efi", offset_of!(BootInfo, efi), 14664),
+        ("memory_map", offset_of!(BootInfo, memory_map), 14696),
+        ("modules", offset_of!(BootInfo, modules), 14712),
+        ("loader", offset_of!(BootInfo, loader), 17408),
+        ("framebuffer", offset_of!(BootInfo, framebuffer), 17424),
+        ("timings", offset_of!(BootInfo, timings), 17488),
+        ("timer", offset_of!(BootInfo, timer), 17568),
+        ("apic", offset_of!(BootInfo, apic), 17584),
+        ("numa", offset_of!(BootInfo, numa), 17632),
+        ("early_heap", offset_of!(BootInfo, early_heap), 18728),
+        ("kernel_stack", offset_of!(BootInfo, kernel_stack), 18752),
+        (
+            "kernel_symbols",
+            offset_of!(BootInfo, kernel_symbols),
+            18784,
+        ),
+        (
+            "framebuffer_console",
+            offset_of!(BootInfo, framebuffer_console),
+            18824,
+        ),
+        ("wall_clock", offset_of!(BootInfo, wall_clock), 18872),
+        ("rtc", offset_of!(BootInfo, rtc), 18896),
+        ("loader_log", offset_of!(BootInfo, loader_log), 18920),
+        ("tpm", offset_of!(BootInfo, tpm), 18960),
+        ("pci", offset_of!(BootInfo, pci), 18992),
+        ("page_bitmap", offset_of!(BootInfo, page_bitmap), 19016),
+        ("cmdline", offset_of!(BootInfo, cmdline), 19056),
+    ];
+    for (field, offset, expected) in offsets {
+        assert_eq!(offset, expected, "offset of {field}");
+    }
+}
+
+#[test]
+fn structure_sizes() {
+    let sizes = [
+        ("LoaderImage", size_of::<LoaderImage>(), 16),
+        ("KernelImage", size_of::<KernelImage>(), 48),
+        ("KernelSymbol", size_of::<KernelSymbol>(), 24),
+        ("KernelSymbols", size_of::<KernelSymbols>(), 40),
+        ("CpuInfo", size_of::<CpuInfo>(), 56),
+        ("Cpus", size_of::<Cpus>(), 14360),
+        ("GicrRange", size_of::<GicrRange>(), 16),
+        ("Gic", size_of::<Gic>(), 176),
+        ("Apic", size_of::<Apic>(), 48),
+        ("Console", size_of::<Console>(), 24),
+        ("Framebuffer", size_of::<Framebuffer>(), 64),
+        ("FramebufferConsole", size_of::<FramebufferConsole>(), 48),
+        ("Timer", size_of::<Timer>(), 16),
+        ("WallClock", size_of::<WallClock>(), 24),
+        ("Rtc", size_of::<Rtc>(), 24),
+        ("Tpm", size_of::<Tpm>(), 32),
+        ("Pci", size_of::<Pci>(), 24),
+        ("BootTimings", size_of::<BootTimings>(), 80),
+        ("Paging", size_of::<Paging>(), 32),
+        ("MemoryRegion", size_of::<MemoryRegion>(), 32),
+        ("MemoryMap", size_of::<MemoryMap>(), 16),
+        ("EarlyHeap", size_of::<EarlyHeap>(), 24),
+        ("PageBitmapStorage", size_of::<PageBitmapStorage>(), 40),
+        ("KernelStack", size_of::<KernelStack>(), 32),
+        ("LoaderLog", size_of::<LoaderLog>(), 40),
+        ("Module", size_of::<Module>(), 336),
+        ("Modules", size_of::<Modules>(), 2696),
+        ("Efi", size_of::<Efi>(), 32),
+        ("NumaMemoryRange", size_of::<NumaMemoryRange>(), 24),
+        ("Numa", size_of::<Numa>(), 1096),
+    ];
+    for (name, size, expected) in sizes {
+        assert_eq!(size, expected, "size of {name}");
+    }
+}
+
+fn new_boot_info() -> Box<BootInfo> {
+    Box::new(BootInfo::default())
+}
+
+/// The bytes of the boot info in a buffer aligned as the structure.
+fn aligned_bytes(boot_info: &BootInfo) -> Vec<u64> {
+    let mut words = vec![0_u64; size_of::<BootInfo>() / size_of::<u64>()];
+    words.as_mut_bytes().copy_from_slice(boot_info.as_bytes());
+    words
+}
+
+#[test]
+fn seal_and_validate() {
+    let mut boot_info = new_boot_info();
+    boot_info.cmdline[..5].copy_from_slice(b"quiet");
+    boot_info.seal();
+    assert_eq!(boot_info.validate(), Ok(()));
+    assert_eq!(boot_info.cmdline_str(), "quiet");
+
+    boot_info.timer.frequency += 1;
+    assert!(matches!(
+        boot_info.validate(),
+        Err(BootInfoError::BadChecksum(_))
+    ));
+    boot_info.seal();
+    assert_eq!(boot_info.validate(), Ok(()));
+}
+
+#[test]
+fn validate_header() {
+    let mut boot_info = new_boot_info();
+    boot_info.magic = 0;
+    boot_info.seal();
+    assert_eq!(boot_info.validate(), Err(BootInfoError::BadMagic(0)));
+
+    let mut boot_info = new_boot_info();
+    boot_info.version = BOOT_INFO_VERSION - 1;
+    boot_info.seal();
+    assert_eq!(
+        boot_info.validate(),
+        Err(BootInfoError::UnsupportedVersion(BOOT_INFO_VERSION - 1))
+    );
+
+    let mut boot_info = new_boot_info();
+    boot_info.size -= 8;
+    boot_info.seal();
+    assert_eq!(
+        boot_info.validate(),
+        Err(BootInfoError::SizeMismatch(boot_info.size))
+    );
+}
+
+#[test]
+fn from_bytes() {
+    let mut boot_info = new_boot_info();
+    boot_info.cmdline[..5].copy_from_slice(b"quiet");
+    boot_info.seal();
+    let bytes = boot_info.as_bytes();
+    assert_eq!(BootInfo::from_bytes(bytes).unwrap().cmdline_str(), "quiet");
+
+    assert_eq!(
+        BootInfo::from_bytes(&bytes[..bytes.len() - 1]).err(),
+        Some(BootInfoError::SizeMismatch(bytes.len() as u32 - 1))
+    );
+    assert_eq!(
+        BootInfo::from_bytes(&bytes[..4]).err(),
+        Some(BootInfoError::SizeMismatch(4))
+    );
+
+    let mut words = aligned_bytes(&boot_info);
+    words.as_mut_bytes()[offset_of!(BootInfo, version)] ^= 1;
+    assert_eq!(
+        BootInfo::from_bytes(words.as_bytes()).err(),
+        Some(BootInfoError::UnsupportedVersion(BOOT_INFO_VERSION ^ 1))
+    );
+}
+
+#[test]
+fn from_bytes_checks_enums() {
+    let mut boot_info = new_boot_info();
+    boot_info.seal();
+    let mut words = aligned_bytes(&boot_info);
+    words.as_mut_bytes()[offset_of!(BootInfo, console) + offset_of!(Console, kind)] = 0xff;
+    assert_eq!(
+        BootInfo::from_bytes(words.as_bytes()).err(),
+        Some(BootInfoError::InvalidValue)
+    );
+}
@@ -0,0 +1,66 @@
+//! SHA-256 with the Armv8 cryptographic extension, the vector registers
+//! hold the state as `ABCD` and `EFGH`, and four words of the message
+//! schedule.
+
+use crate::sha256::BLOCK_SIZE;
+use crate::sha256::K;
+use core::arch::aarch64::*;
+
+/// ID_AA64ISAR0_EL1.SHA2.
+const ISAR0_SHA2_SHIFT: u64 = 12;
+const ISAR0_SHA2_MASK: u64 = 0xf;
+
+pub(crate) fn is_supported() -> bool {
+    let isar0: u64;
+    // SAFETY: reading the ID register has no side effects, Linux emulates
+    // it for the host tests.
+    unsafe {
+        core::arch::asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0, options(nomem, nostack))
+    };
+    (isar0 >> ISAR0_SHA2_SHIFT) & ISAR0_SHA2_MASK != 0
+}
+
+/// # Safety
+///
+/// The processor must have the SHA-256 instructions, see [`is_supported`].
+#[target_feature(enable = "sha2")]
+pub(crate) unsafe fn compress(state: &mut [u32; 8], blocks: &[[u8; BLOCK_SIZE]]) {
+    // SAFETY: the state is eight words.
+    let (mut abcd, mut efgh) =
+        unsafe { (vld1q_u32(state.as_ptr()), vld1q_u32(state.as_ptr().add(4))) };
+
+    for block in blocks {
+        let (abcd_start, efgh_start) = (abcd, efgh);
+
+        let mut w = [vdupq_n_u32(0); 4];
+        for (i, w) in w.iter_mut().enumerate() {
+            // SAFETY: the block is four 16-byte parts. The message is
+            // big-endian.
+            *w = vreinterpretq_u32_u8(vrev32q_u8(unsafe { vld1q_u8(block.as_ptr().add(i * 16)) }));
+        }
+
+        for i in 0..16 {
+            if i >= 4 {
+                w[i % 4] = vsha256su1q_u32(
+                    vsha256su0q_u32(w[i % 4], w[(i + 1) % 4]),
+                    w[(i + 2) % 4],
+                    w[(i + 3) % 4],
+                );
+            }
+            // SAFETY: `K` is 64 words.
+            let wk = vaddq_u32(w[i % 4], unsafe { vld1q_u32(K.as_ptr().add(i * 4)) });
+            let abcd_before = abcd;
+            abcd = vsha256hq_u32(abcd_before, efgh, wk);
+            efgh = vsha256h2q_u32(efgh, abcd_before, wk);
+        }
+
+        abcd = vaddq_u32(abcd, abcd_start);
+        efgh = vaddq_u32(efgh, efgh_start);
+    }
+
+    // SAFETY: the state is eight words.
+    unsafe {
+        vst1q_u32(state.as_mut_ptr(), abcd);
+        vst1q_u32(state.as_mut_ptr().add(4), efgh);
+    }
+}
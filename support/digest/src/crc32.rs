@@ -0,0 +1,99 @@
+//! CRC-32 as in IEEE 802.3, zlib and the UEFI tables: the reflected
+//! polynomial `0xedb88320`, starting from and finished with all ones.
+//!
+//! Example:
+//! ```ignore
+//! let mut crc = digest::Crc32::new();
+//! crc.update(b"1234");
+//! crc.update(b"56789");
+//! assert_eq!(crc.finalize(), 0xcbf43926);
+//! ```
+
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+/// The remainders of the bytes, computed at build time.
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { crc: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = (self.crc >> 8) ^ TABLE[((self.crc ^ byte as u32) & 0xff) as usize];
+        }
+    }
+
+    /// The checksum of the data so far, more may be added after.
+    pub fn value(&self) -> u32 {
+        !self.crc
+    }
+
+    pub fn finalize(self) -> u32 {
+        self.value()
+    }
+}
+
+/// The checksum of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crc32::crc32;
+    use crate::crc32::Crc32;
+
+    #[test]
+    fn check() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        let data: Vec<u8> = (0..1000_u32).map(|i| (i * 7) as u8).collect();
+        let mut crc = Crc32::new();
+        for chunk in data.chunks(13) {
+            crc.update(chunk);
+        }
+        assert_eq!(crc.value(), crc32(&data));
+        assert_eq!(crc.finalize(), crc32(&data));
+    }
+}
@@ -0,0 +1,19 @@
+//! The checksums and the hashes for the loader and the kernel, small and
+//! with no dependencies: [`Crc32`] for catching the corruption, and
+//! [`Sha256`] for verifying the images and for the measurements.
+//!
+//! Both take the data in pieces of any size through `update`.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64_sha;
+pub mod crc32;
+pub mod sha256;
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod x86_64_sha;
+
+pub use crc32::crc32;
+pub use crc32::Crc32;
+pub use sha256::sha256;
+pub use sha256::Sha256;
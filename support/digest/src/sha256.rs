@@ -0,0 +1,283 @@
+//! SHA-256 as in FIPS 180-4.
+//!
+//! The blocks are compressed with the SHA instructions when the processor
+//! has them, and in software otherwise. The x86_64 SHA extensions need SSE,
+//! so the soft-float x86_64 targets such as UEFI always hash in software.
+//!
+//! Example:
+//! ```ignore
+//! let mut hasher = digest::Sha256::new();
+//! hasher.update(b"abc");
+//! let digest = hasher.finalize();
+//! ```
+
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+pub const DIGEST_SIZE: usize = 32;
+pub(crate) const BLOCK_SIZE: usize = 64;
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+pub(crate) const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub type Digest = [u8; DIGEST_SIZE];
+
+/// The software compression of one block.
+pub(crate) fn compress_block(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0_u32; 64];
+    for (i, word) in block.as_chunks::<4>().0.iter().enumerate() {
+        w[i] = u32::from_be_bytes(*word);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+/// Whether the processor has the SHA instructions, checked once.
+static HARDWARE: AtomicU8 = AtomicU8::new(UNKNOWN);
+const UNKNOWN: u8 = 0;
+const SOFTWARE: u8 = 1;
+const ACCELERATED: u8 = 2;
+
+fn accelerated() -> bool {
+    match HARDWARE.load(Ordering::Relaxed) {
+        UNKNOWN => {
+            #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+            let supported = crate::x86_64_sha::is_supported();
+            #[cfg(target_arch = "aarch64")]
+            let supported = crate::aarch64_sha::is_supported();
+            #[cfg(not(any(
+                all(target_arch = "x86_64", target_feature = "sse2"),
+                target_arch = "aarch64"
+            )))]
+            let supported = false;
+
+            HARDWARE.store(
+                if supported { ACCELERATED } else { SOFTWARE },
+                Ordering::Relaxed,
+            );
+            supported
+        }
+        state => state == ACCELERATED,
+    }
+}
+
+fn compress(state: &mut [u32; 8], blocks: &[[u8; BLOCK_SIZE]]) {
+    if accelerated() {
+        // SAFETY: the processor has the instructions.
+        #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+        return unsafe { crate::x86_64_sha::compress(state, blocks) };
+        // SAFETY: the processor has the instructions.
+        #[cfg(target_arch = "aarch64")]
+        return unsafe { crate::aarch64_sha::compress(state, blocks) };
+    }
+    for block in blocks {
+        compress_block(state, block);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    /// The total length of the input in bytes.
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub const fn new() -> Self {
+        Self {
+            state: H0,
+            block: [0; BLOCK_SIZE],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        if self.block_len > 0 {
+            let take = core::cmp::min(BLOCK_SIZE - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < BLOCK_SIZE {
+                return;
+            }
+            compress(&mut self.state, core::slice::from_ref(&self.block));
+            self.block_len = 0;
+        }
+
+        let (blocks, rest) = data.as_chunks::<BLOCK_SIZE>();
+        compress(&mut self.state, blocks);
+
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    pub fn finalize(mut self) -> Digest {
+        let bit_len = self.len.wrapping_mul(8);
+
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+        if self.block_len + 1 > BLOCK_SIZE - 8 {
+            compress(&mut self.state, core::slice::from_ref(&self.block));
+            self.block.fill(0);
+        }
+        self.block[BLOCK_SIZE - 8..].copy_from_slice(&bit_len.to_be_bytes());
+        compress(&mut self.state, core::slice::from_ref(&self.block));
+
+        let mut digest = [0; DIGEST_SIZE];
+        for (bytes, word) in digest.as_chunks_mut::<4>().0.iter_mut().zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+/// The digest of `data`.
+pub fn sha256(data: &[u8]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Parses the digest from 64 hex digits.
+pub fn parse_hex_digest(hex: &str) -> Option<Digest> {
+    if hex.len() != DIGEST_SIZE * 2 {
+        return None;
+    }
+
+    let mut digest = [0; DIGEST_SIZE];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().as_chunks::<2>().0) {
+        *byte = u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sha256::compress;
+    use crate::sha256::compress_block;
+    use crate::sha256::parse_hex_digest;
+    use crate::sha256::sha256;
+    use crate::sha256::Sha256;
+    use crate::sha256::H0;
+
+    fn digest(hex: &str) -> [u8; 32] {
+        parse_hex_digest(hex).unwrap()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            sha256(b""),
+            digest("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+    }
+
+    #[test]
+    fn abc() {
+        assert_eq!(
+            sha256(b"abc"),
+            digest("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+
+    #[test]
+    fn two_blocks() {
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            digest("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        let data = [b'a'; 1000];
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), sha256(&data));
+        assert_eq!(
+            sha256(&data),
+            digest("41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3")
+        );
+    }
+
+    #[test]
+    fn accelerated() {
+        let blocks: Vec<[u8; 64]> = (0..5)
+            .map(|b| core::array::from_fn(|i| (b * 64 + i) as u8 ^ 0x5a))
+            .collect();
+        let mut expected = H0;
+        for block in &blocks {
+            compress_block(&mut expected, block);
+        }
+        let mut state = H0;
+        compress(&mut state, &blocks);
+        assert_eq!(state, expected);
+    }
+
+    #[test]
+    fn bad_hex() {
+        assert_eq!(parse_hex_digest("abc"), None);
+        assert_eq!(
+            parse_hex_digest("zz7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            None
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! SHA-256 with the SHA extensions, the 128-bit registers hold the state
+//! as `ABEF` and `CDGH`, and four words of the message schedule.
+//!
+//! The UEFI target is soft-float and has no SSE registers to use, so
+//! the loader always hashes in software.
+
+use crate::sha256::BLOCK_SIZE;
+use crate::sha256::K;
+use core::arch::x86_64::*;
+
+/// CPUID.07H:EBX.SHA.
+const CPUID_SHA: u32 = 1 << 29;
+/// CPUID.01H:ECX.SSSE3.
+const CPUID_SSSE3: u32 = 1 << 9;
+/// CPUID.01H:ECX.SSE4_1.
+const CPUID_SSE4_1: u32 = 1 << 19;
+
+pub(crate) fn is_supported() -> bool {
+    #[allow(unused_unsafe)]
+    // SAFETY: CPUID is there on all x86_64 processors.
+    let (max_leaf, features, extended) =
+        unsafe { (__cpuid(0).eax, __cpuid(1).ecx, __cpuid_count(7, 0).ebx) };
+    max_leaf >= 7
+        && features & (CPUID_SSSE3 | CPUID_SSE4_1) == CPUID_SSSE3 | CPUID_SSE4_1
+        && extended & CPUID_SHA != 0
+}
+
+/// The next four words of the message schedule from the previous sixteen.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+unsafe fn schedule(w0: __m128i, w1: __m128i, w2: __m128i, w3: __m128i) -> __m128i {
+    let sigma0 = _mm_sha256msg1_epu32(w0, w1);
+    let w7 = _mm_alignr_epi8(w3, w2, 4);
+    _mm_sha256msg2_epu32(_mm_add_epi32(sigma0, w7), w3)
+}
+
+/// # Safety
+///
+/// The processor must have the SHA extensions, see [`is_supported`].
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+pub(crate) unsafe fn compress(state: &mut [u32; 8], blocks: &[[u8; BLOCK_SIZE]]) {
+    // Swaps the bytes of each word, the message is big-endian.
+    let byte_swap = _mm_set_epi64x(
+        0x0c0d_0e0f_0809_0a0b_u64 as i64,
+        0x0405_0607_0001_0203_u64 as i64,
+    );
+
+    // SAFETY: the state is eight words, unaligned loads.
+    let (dcba, hgfe) = unsafe {
+        (
+            _mm_loadu_si128(state.as_ptr().cast()),
+            _mm_loadu_si128(state.as_ptr().add(4).cast()),
+        )
+    };
+    let cdab = _mm_shuffle_epi32(dcba, 0xb1);
+    let efgh = _mm_shuffle_epi32(hgfe, 0x1b);
+    let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+    let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xf0);
+
+    for block in blocks {
+        let (abef_start, cdgh_start) = (abef, cdgh);
+
+        let mut w = [_mm_setzero_si128(); 4];
+        for (i, w) in w.iter_mut().enumerate() {
+            // SAFETY: the block is four 16-byte parts, unaligned loads.
+            let part = unsafe { _mm_loadu_si128(block.as_ptr().add(i * 16).cast()) };
+            *w = _mm_shuffle_epi8(part, byte_swap);
+        }
+
+        for i in 0..16 {
+            if i >= 4 {
+                // SAFETY: the processor has the instructions.
+                w[i % 4] =
+                    unsafe { schedule(w[i % 4], w[(i + 1) % 4], w[(i + 2) % 4], w[(i + 3) % 4]) };
+            }
+            // SAFETY: `K` is 64 words, an unaligned load.
+            let k = unsafe { _mm_loadu_si128(K.as_ptr().add(i * 4).cast()) };
+            let wk = _mm_add_epi32(w[i % 4], k);
+            // Two rounds each, the second pair of the words is in the
+            // low half for the second one.
+            cdgh = _mm_sha256rnds2_epu32(cdgh, abef, wk);
+            abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32(wk, 0x0e));
+        }
+
+        abef = _mm_add_epi32(abef, abef_start);
+        cdgh = _mm_add_epi32(cdgh, cdgh_start);
+    }
+
+    let feba = _mm_shuffle_epi32(abef, 0x1b);
+    let dchg = _mm_shuffle_epi32(cdgh, 0xb1);
+    let dcba = _mm_blend_epi16(feba, dchg, 0xf0);
+    let hgef = _mm_alignr_epi8(dchg, feba, 8);
+    // SAFETY: the state is eight words, unaligned stores.
+    unsafe {
+        _mm_storeu_si128(state.as_mut_ptr().cast(), dcba);
+        _mm_storeu_si128(state.as_mut_ptr().add(4).cast(), hgef);
+    }
+}
@@ -0,0 +1,441 @@
+//! The EL1 exception vectors.
+//!
+//! The vector table has 16 entries `0x80` bytes apart: the synchronous
+//! exceptions, IRQ, FIQ, and SError for each of the current EL with
+//! `SP_EL0`, the current EL with `SP_ELx`, and the lower EL in AArch64 and
+//! in AArch32. Each entry makes room for an [`ExceptionFrame`] on the stack,
+//! and jumps to the common code that saves the rest of the registers and
+//! calls the handler for the kind of the exception. The handler may change
+//! the frame, the registers are restored from it on the return. The SIMD
+//! registers, `FPSR` and `FPCR` of the interrupted code are saved below the
+//! frame, and restored as they were.
+
+use bitfield_struct::bitfield;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::Ordering;
+
+/// The size of the vector table, and its alignment.
+pub const VECTOR_TABLE_SIZE: usize = 0x800;
+/// The distance between the entries.
+pub const VECTOR_ENTRY_SIZE: usize = 0x80;
+
+/// The registers saved on the exception entry.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct ExceptionFrame {
+    pub x: [u64; 31],
+    /// The stack pointer of the EL the exception is taken to, before the
+    /// frame has been pushed.
+    pub sp: u64,
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: u64,
+    pub far: u64,
+}
+
+/// The entry code in `corgos_exception_vectors` relies on the layout.
+const _: () = assert!(core::mem::size_of::<ExceptionFrame>() == 288);
+const _: () = assert!(core::mem::offset_of!(ExceptionFrame, sp) == 248);
+const _: () = assert!(core::mem::offset_of!(ExceptionFrame, elr) == 256);
+const _: () = assert!(core::mem::offset_of!(ExceptionFrame, far) == 280);
+
+impl ExceptionFrame {
+    pub fn syndrome(&self) -> Esr {
+        Esr::from(self.esr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    Synchronous,
+    Irq,
+    Fiq,
+    SError,
+}
+
+/// Where the exception has been taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionOrigin {
+    CurrentElSp0,
+    CurrentElSpx,
+    LowerElAarch64,
+    LowerElAarch32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exception {
+    pub kind: ExceptionKind,
+    pub origin: ExceptionOrigin,
+}
+
+impl Exception {
+    /// The exception for the vector table entry `index`.
+    pub const fn from_index(index: usize) -> Self {
+        let kind = match index % 4 {
+            0 => ExceptionKind::Synchronous,
+            1 => ExceptionKind::Irq,
+            2 => ExceptionKind::Fiq,
+            _ => ExceptionKind::SError,
+        };
+        let origin = match (index / 4) % 4 {
+            0 => ExceptionOrigin::CurrentElSp0,
+            1 => ExceptionOrigin::CurrentElSpx,
+            2 => ExceptionOrigin::LowerElAarch64,
+            _ => ExceptionOrigin::LowerElAarch32,
+        };
+        Self { kind, origin }
+    }
+}
+
+/// The exception classes the early handlers may see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    Unknown,
+    WfxTrap,
+    FpAccess,
+    IllegalState,
+    Svc,
+    Hvc,
+    Smc,
+    SysRegTrap,
+    SveAccess,
+    InstructionAbortLower,
+    InstructionAbort,
+    PcAlignment,
+    DataAbortLower,
+    DataAbort,
+    SpAlignment,
+    FpException,
+    SError,
+    BreakpointLower,
+    Breakpoint,
+    SoftwareStepLower,
+    SoftwareStep,
+    WatchpointLower,
+    Watchpoint,
+    Brk,
+    Other(u8),
+}
+
+impl ExceptionClass {
+    pub const fn from_ec(ec: u8) -> Self {
+        match ec {
+            0x00 => Self::Unknown,
+            0x01 => Self::WfxTrap,
+            0x07 => Self::FpAccess,
+            0x0e => Self::IllegalState,
+            0x15 => Self::Svc,
+            0x16 => Self::Hvc,
+            0x17 => Self::Smc,
+            0x18 => Self::SysRegTrap,
+            0x19 => Self::SveAccess,
+            0x20 => Self::InstructionAbortLower,
+            0x21 => Self::InstructionAbort,
+            0x22 => Self::PcAlignment,
+            0x24 => Self::DataAbortLower,
+            0x25 => Self::DataAbort,
+            0x26 => Self::SpAlignment,
+            0x2c => Self::FpException,
+            0x2f => Self::SError,
+            0x30 => Self::BreakpointLower,
+            0x31 => Self::Breakpoint,
+            0x32 => Self::SoftwareStepLower,
+            0x33 => Self::SoftwareStep,
+            0x34 => Self::WatchpointLower,
+            0x35 => Self::Watchpoint,
+            0x3c => Self::Brk,
+            _ => Self::Other(ec),
+        }
+    }
+}
+
+/// `ESR_EL1`, the syndrome of the exception.
+#[bitfield(u64)]
+pub struct Esr {
+    /// The instruction specific syndrome.
+    #[bits(25)]
+    pub iss: u64,
+    /// The trapped instruction is 32-bit.
+    pub il: bool,
+    #[bits(6)]
+    pub ec: u8,
+    #[bits(24)]
+    pub iss2: u64,
+    #[bits(8)]
+    _mbz0: u64,
+}
+
+impl Esr {
+    pub fn class(&self) -> ExceptionClass {
+        ExceptionClass::from_ec(self.ec())
+    }
+
+    pub fn is_abort(&self) -> bool {
+        matches!(
+            self.class(),
+            ExceptionClass::InstructionAbort
+                | ExceptionClass::InstructionAbortLower
+                | ExceptionClass::DataAbort
+                | ExceptionClass::DataAbortLower
+        )
+    }
+
+    /// The fault status code of an abort, `IFSC` or `DFSC`.
+    pub fn fault_status(&self) -> Option<u8> {
+        self.is_abort().then_some((self.iss() & 0x3f) as u8)
+    }
+
+    /// Whether the data abort has been caused by a write.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self.class(),
+            ExceptionClass::DataAbort | ExceptionClass::DataAbortLower
+        ) && self.iss() & (1 << 6) != 0
+    }
+
+    /// Whether `FAR_EL1` holds the faulting address.
+    pub fn far_valid(&self) -> bool {
+        match self.class() {
+            ExceptionClass::InstructionAbort
+            | ExceptionClass::InstructionAbortLower
+            | ExceptionClass::DataAbort
+            | ExceptionClass::DataAbortLower => self.iss() & (1 << 10) == 0,
+            ExceptionClass::PcAlignment
+            | ExceptionClass::WatchpointLower
+            | ExceptionClass::Watchpoint => true,
+            _ => false,
+        }
+    }
+}
+
+pub type ExceptionHandler = fn(Exception, &mut ExceptionFrame, Esr);
+
+/// The handlers for each kind of the exceptions.
+pub struct ExceptionHandlers {
+    pub synchronous: ExceptionHandler,
+    pub irq: ExceptionHandler,
+    pub fiq: ExceptionHandler,
+    pub serror: ExceptionHandler,
+}
+
+impl ExceptionHandlers {
+    pub fn handler(&self, kind: ExceptionKind) -> ExceptionHandler {
+        match kind {
+            ExceptionKind::Synchronous => self.synchronous,
+            ExceptionKind::Irq => self.irq,
+            ExceptionKind::Fiq => self.fiq,
+            ExceptionKind::SError => self.serror,
+        }
+    }
+}
+
+static HANDLERS: AtomicPtr<ExceptionHandlers> = AtomicPtr::new(core::ptr::null_mut());
+
+#[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+extern "C" fn corgos_exception_dispatch(index: u64, frame: &mut ExceptionFrame) {
+    let exception = Exception::from_index(index as usize);
+    let esr = frame.syndrome();
+
+    // SAFETY: the handlers are static, and are set before the vectors are.
+    match unsafe { HANDLERS.load(Ordering::Acquire).as_ref() } {
+        Some(handlers) => handlers.handler(exception.kind)(exception, frame, esr),
+        None => loop {
+            core::hint::spin_loop();
+        },
+    }
+}
+
+// Each entry saves `x0` and `x1` to have a scratch register for the entry
+// index, the common code saves the rest and the system registers.
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    ".pushsection .text.corgos_exception_vectors, \"ax\"",
+    ".balign 0x800",
+    ".global corgos_exception_vectors",
+    "corgos_exception_vectors:",
+    ".set index, 0",
+    ".rept 16",
+    ".balign 0x80",
+    "sub sp, sp, #288",
+    "stp x0, x1, [sp]",
+    "mov x0, #index",
+    "b corgos_exception_common",
+    ".set index, index + 1",
+    ".endr",
+    "corgos_exception_common:",
+    "stp x2, x3, [sp, #16]",
+    "stp x4, x5, [sp, #32]",
+    "stp x6, x7, [sp, #48]",
+    "stp x8, x9, [sp, #64]",
+    "stp x10, x11, [sp, #80]",
+    "stp x12, x13, [sp, #96]",
+    "stp x14, x15, [sp, #112]",
+    "stp x16, x17, [sp, #128]",
+    "stp x18, x19, [sp, #144]",
+    "stp x20, x21, [sp, #160]",
+    "stp x22, x23, [sp, #176]",
+    "stp x24, x25, [sp, #192]",
+    "stp x26, x27, [sp, #208]",
+    "stp x28, x29, [sp, #224]",
+    "add x2, sp, #288",
+    "stp x30, x2, [sp, #240]",
+    "mrs x3, elr_el1",
+    "mrs x4, spsr_el1",
+    "stp x3, x4, [sp, #256]",
+    "mrs x5, esr_el1",
+    "mrs x6, far_el1",
+    "stp x5, x6, [sp, #272]",
+    "sub sp, sp, #528",
+    "stp q0, q1, [sp, #16]",
+    "stp q2, q3, [sp, #48]",
+    "stp q4, q5, [sp, #80]",
+    "stp q6, q7, [sp, #112]",
+    "stp q8, q9, [sp, #144]",
+    "stp q10, q11, [sp, #176]",
+    "stp q12, q13, [sp, #208]",
+    "stp q14, q15, [sp, #240]",
+    "stp q16, q17, [sp, #272]",
+    "stp q18, q19, [sp, #304]",
+    "stp q20, q21, [sp, #336]",
+    "stp q22, q23, [sp, #368]",
+    "stp q24, q25, [sp, #400]",
+    "stp q26, q27, [sp, #432]",
+    "stp q28, q29, [sp, #464]",
+    "stp q30, q31, [sp, #496]",
+    "mrs x7, fpsr",
+    "mrs x8, fpcr",
+    "stp x7, x8, [sp]",
+    "add x1, sp, #528",
+    "bl {dispatch}",
+    "ldp x7, x8, [sp]",
+    "msr fpsr, x7",
+    "msr fpcr, x8",
+    "ldp q0, q1, [sp, #16]",
+    "ldp q2, q3, [sp, #48]",
+    "ldp q4, q5, [sp, #80]",
+    "ldp q6, q7, [sp, #112]",
+    "ldp q8, q9, [sp, #144]",
+    "ldp q10, q11, [sp, #176]",
+    "ldp q12, q13, [sp, #208]",
+    "ldp q14, q15, [sp, #240]",
+    "ldp q16, q17, [sp, #272]",
+    "ldp q18, q19, [sp, #304]",
+    "ldp q20, q21, [sp, #336]",
+    "ldp q22, q23, [sp, #368]",
+    "ldp q24, q25, [sp, #400]",
+    "ldp q26, q27, [sp, #432]",
+    "ldp q28, q29, [sp, #464]",
+    "ldp q30, q31, [sp, #496]",
+    "add sp, sp, #528",
+    "ldp x3, x4, [sp, #256]",
+    "msr elr_el1, x3",
+    "msr spsr_el1, x4",
+    "ldp x2, x3, [sp, #16]",
+    "ldp x4, x5, [sp, #32]",
+    "ldp x6, x7, [sp, #48]",
+    "ldp x8, x9, [sp, #64]",
+    "ldp x10, x11, [sp, #80]",
+    "ldp x12, x13, [sp, #96]",
+    "ldp x14, x15, [sp, #112]",
+    "ldp x16, x17, [sp, #128]",
+    "ldp x18, x19, [sp, #144]",
+    "ldp x20, x21, [sp, #160]",
+    "ldp x22, x23, [sp, #176]",
+    "ldp x24, x25, [sp, #192]",
+    "ldp x26, x27, [sp, #208]",
+    "ldp x28, x29, [sp, #224]",
+    "ldr x30, [sp, #240]",
+    "ldp x0, x1, [sp]",
+    "add sp, sp, #288",
+    "eret",
+    ".popsection",
+    dispatch = sym corgos_exception_dispatch,
+);
+
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    fn corgos_exception_vectors();
+}
+
+/// The address of the vector table.
+#[cfg(target_arch = "aarch64")]
+pub fn vector_table() -> u64 {
+    corgos_exception_vectors as *const () as u64
+}
+
+/// Points `VBAR_EL1` to the vector table that calls the `handlers`.
+///
+/// # Safety
+///
+/// Runs at EL1, and the firmware or anything else does not rely on its own
+/// vectors any longer.
+#[cfg(target_arch = "aarch64")]
+pub unsafe fn install(handlers: &'static ExceptionHandlers) {
+    HANDLERS.store(
+        handlers as *const ExceptionHandlers as *mut ExceptionHandlers,
+        Ordering::Release,
+    );
+    // SAFETY: the vector table is aligned, and the caller guarantees the
+    // rest.
+    unsafe {
+        core::arch::asm!(
+            "msr vbar_el1, {}",
+            "isb",
+            in(reg) vector_table(),
+            options(nomem, nostack)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aarch64::Esr;
+    use crate::aarch64::Exception;
+    use crate::aarch64::ExceptionClass;
+    use crate::aarch64::ExceptionKind;
+    use crate::aarch64::ExceptionOrigin;
+
+    #[test]
+    fn exception_from_index() {
+        assert_eq!(
+            Exception::from_index(0),
+            Exception {
+                kind: ExceptionKind::Synchronous,
+                origin: ExceptionOrigin::CurrentElSp0
+            }
+        );
+        assert_eq!(
+            Exception::from_index(5),
+            Exception {
+                kind: ExceptionKind::Irq,
+                origin: ExceptionOrigin::CurrentElSpx
+            }
+        );
+        assert_eq!(
+            Exception::from_index(15),
+            Exception {
+                kind: ExceptionKind::SError,
+                origin: ExceptionOrigin::LowerElAarch32
+            }
+        );
+    }
+
+    #[test]
+    fn syndrome() {
+        // A translation fault at level 3 on a write.
+        let esr = Esr::from(0x9600_0047);
+        assert_eq!(esr.class(), ExceptionClass::DataAbort);
+        assert!(esr.il());
+        assert_eq!(esr.fault_status(), Some(0x07));
+        assert!(esr.is_write());
+        assert!(esr.far_valid());
+
+        let esr = Esr::from(0xf200_0001);
+        assert_eq!(esr.class(), ExceptionClass::Brk);
+        assert_eq!(esr.iss(), 1);
+        assert_eq!(esr.fault_status(), None);
+        assert!(!esr.is_write() && !esr.far_valid());
+
+        assert_eq!(Esr::from(0x3f << 26).class(), ExceptionClass::Other(0x3f));
+    }
+}
@@ -0,0 +1,11 @@
+//! The early exception handling shared by the loader and the kernel.
+//!
+//! The handlers here are meant for the time before the kernel has set up
+//! its own: they save the registers into a frame, decode what the
+//! processor reports about the exception, and call a Rust handler. On
+//! x86_64, the descriptor tables the processor needs for that are here, too.
+
+#![no_std]
+
+pub mod aarch64;
+pub mod x86_64;
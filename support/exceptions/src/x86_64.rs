@@ -0,0 +1,697 @@
+//! The x86_64 descriptor tables: the GDT with the flat 64-bit segments and
+//! the TSS, the IDT gates, and the TSS itself with the stacks the processor
+//! switches to.
+//!
+//! The tables are built at run time as the TSS descriptor needs the address
+//! of the TSS, and loaded with [`load_gdt`], [`load_idt`], and [`load_tss`].
+//! The tables must outlive their use by the processor, so those take the
+//! `'static` references.
+//!
+//! The gates of the exceptions and the interrupts may point to the fault
+//! stubs, see [`fault_stub`]. Those save the registers into an
+//! [`ExceptionFrame`] and call the handler set with [`set_fault_handler`],
+//! and resume from the frame if the handler returns.
+
+use bitfield_struct::bitfield;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+/// The number of the IDT gates.
+pub const IDT_ENTRIES: usize = 256;
+/// The architecturally defined exceptions.
+pub const EXCEPTION_COUNT: usize = 32;
+
+/// The exceptions that push an error code.
+pub const fn has_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PrivilegeLevel {
+    Kernel = 0,
+    User = 3,
+}
+
+impl PrivilegeLevel {
+    const fn into_bits(self) -> u8 {
+        self as u8
+    }
+
+    const fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => Self::Kernel,
+            _ => Self::User,
+        }
+    }
+}
+
+/// A code or a data segment descriptor. The base and the limit are ignored
+/// in the 64-bit mode except for FS and GS.
+#[bitfield(u64, default = false)]
+pub struct SegmentDescriptor {
+    #[bits(16)]
+    pub limit_low: u64,
+    #[bits(24)]
+    pub base_low: u64,
+    pub accessed: bool,
+    /// Writable for the data segments, readable for the code ones.
+    pub writable: bool,
+    /// Conforming for the code segments, expand-down for the data ones.
+    pub conforming: bool,
+    pub executable: bool,
+    /// Set for the code and the data segments, clear for the system ones.
+    pub user_segment: bool,
+    #[bits(2)]
+    pub dpl: PrivilegeLevel,
+    pub present: bool,
+    #[bits(4)]
+    pub limit_high: u64,
+    pub available: bool,
+    pub long_mode: bool,
+    pub default_size: bool,
+    /// The limit is in pages.
+    pub granularity: bool,
+    #[bits(8)]
+    pub base_high: u64,
+}
+
+impl SegmentDescriptor {
+    const fn flat(dpl: PrivilegeLevel) -> Self {
+        Self::new()
+            .with_limit_low(0xffff)
+            .with_limit_high(0xf)
+            .with_granularity(true)
+            .with_user_segment(true)
+            .with_present(true)
+            .with_dpl(dpl)
+    }
+
+    const fn code(dpl: PrivilegeLevel) -> Self {
+        Self::flat(dpl)
+            .with_executable(true)
+            .with_writable(true)
+            .with_long_mode(true)
+    }
+
+    const fn data(dpl: PrivilegeLevel) -> Self {
+        Self::flat(dpl).with_writable(true).with_default_size(true)
+    }
+
+    pub const fn kernel_code() -> Self {
+        Self::code(PrivilegeLevel::Kernel)
+    }
+
+    pub const fn kernel_data() -> Self {
+        Self::data(PrivilegeLevel::Kernel)
+    }
+
+    pub const fn user_code() -> Self {
+        Self::code(PrivilegeLevel::User)
+    }
+
+    pub const fn user_data() -> Self {
+        Self::data(PrivilegeLevel::User)
+    }
+}
+
+/// The type of the available 64-bit TSS.
+pub const SYSTEM_TYPE_TSS_AVAILABLE: u8 = 0x9;
+/// The type of the 64-bit TSS that is loaded in the task register.
+pub const SYSTEM_TYPE_TSS_BUSY: u8 = 0xb;
+
+/// The low half of a system segment descriptor, the high half holds the
+/// upper 32 bits of the base.
+#[bitfield(u64, default = false)]
+pub struct SystemDescriptor {
+    #[bits(16)]
+    pub limit_low: u64,
+    #[bits(24)]
+    pub base_low: u64,
+    #[bits(4)]
+    pub system_type: u8,
+    _user_segment: bool,
+    #[bits(2)]
+    pub dpl: PrivilegeLevel,
+    pub present: bool,
+    #[bits(4)]
+    pub limit_high: u64,
+    pub available: bool,
+    #[bits(2)]
+    _mbz0: u64,
+    pub granularity: bool,
+    #[bits(8)]
+    pub base_high: u64,
+}
+
+impl SystemDescriptor {
+    /// The two GDT entries for the TSS.
+    pub fn tss(tss: &'static TaskStateSegment) -> [u64; 2] {
+        let base = tss as *const TaskStateSegment as u64;
+        let limit = core::mem::size_of::<TaskStateSegment>() as u64 - 1;
+        let low = Self::new()
+            .with_limit_low(limit & 0xffff)
+            .with_limit_high(limit >> 16)
+            .with_base_low(base & 0xff_ffff)
+            .with_base_high((base >> 24) & 0xff)
+            .with_system_type(SYSTEM_TYPE_TSS_AVAILABLE)
+            .with_present(true);
+        [low.into(), base >> 32]
+    }
+}
+
+#[bitfield(u16)]
+#[derive(PartialEq, Eq)]
+pub struct SegmentSelector {
+    #[bits(2)]
+    pub rpl: PrivilegeLevel,
+    /// The selector refers to the LDT.
+    pub local: bool,
+    #[bits(13)]
+    pub index: u16,
+}
+
+/// The 64-bit TSS, only the stacks are used.
+#[repr(C, packed(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct TaskStateSegment {
+    reserved0: u32,
+    /// The stacks for the privilege levels `0` to `2`.
+    pub privilege_stacks: [u64; 3],
+    reserved1: u64,
+    /// The interrupt stack table, the gate with `IST` set to `n` switches
+    /// to `interrupt_stacks[n - 1]`.
+    pub interrupt_stacks: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    /// No I/O permission bitmap when past the limit.
+    pub iomap_base: u16,
+}
+
+const _: () = assert!(core::mem::size_of::<TaskStateSegment>() == 104);
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        Self {
+            reserved0: 0,
+            privilege_stacks: [0; 3],
+            reserved1: 0,
+            interrupt_stacks: [0; 7],
+            reserved2: 0,
+            reserved3: 0,
+            iomap_base: core::mem::size_of::<Self>() as u16,
+        }
+    }
+}
+
+impl Default for TaskStateSegment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The GDT of up to `N` entries, the first one is the null descriptor.
+#[repr(C, align(16))]
+#[derive(Debug, Clone)]
+pub struct GlobalDescriptorTable<const N: usize> {
+    entries: [u64; N],
+    len: usize,
+}
+
+impl<const N: usize> GlobalDescriptorTable<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: [0; N],
+            len: 1,
+        }
+    }
+
+    fn push(&mut self, entry: u64) -> u16 {
+        assert!(self.len < N, "The GDT is full");
+        let index = self.len;
+        self.entries[index] = entry;
+        self.len += 1;
+        index as u16
+    }
+
+    pub fn add(&mut self, descriptor: SegmentDescriptor) -> SegmentSelector {
+        let dpl = descriptor.dpl();
+        let index = self.push(descriptor.into());
+        SegmentSelector::new().with_index(index).with_rpl(dpl)
+    }
+
+    /// Takes two entries.
+    pub fn add_tss(&mut self, tss: &'static TaskStateSegment) -> SegmentSelector {
+        let [low, high] = SystemDescriptor::tss(tss);
+        let index = self.push(low);
+        self.push(high);
+        SegmentSelector::new().with_index(index)
+    }
+
+    pub fn entries(&self) -> &[u64] {
+        &self.entries[..self.len]
+    }
+
+    pub fn pointer(&self) -> DescriptorTablePointer {
+        DescriptorTablePointer {
+            limit: (self.len * core::mem::size_of::<u64>() - 1) as u16,
+            base: self.entries.as_ptr() as u64,
+        }
+    }
+}
+
+impl<const N: usize> Default for GlobalDescriptorTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GateType {
+    Interrupt = 0xe,
+    Trap = 0xf,
+}
+
+impl GateType {
+    const fn into_bits(self) -> u8 {
+        self as u8
+    }
+
+    const fn from_bits(bits: u8) -> Self {
+        match bits {
+            0xe => Self::Interrupt,
+            _ => Self::Trap,
+        }
+    }
+}
+
+#[bitfield(u16)]
+#[derive(PartialEq, Eq)]
+pub struct GateOptions {
+    /// The interrupt stack to switch to, `0` for none.
+    #[bits(3)]
+    pub ist: u8,
+    #[bits(5)]
+    _mbz0: u8,
+    #[bits(4, default = GateType::Interrupt)]
+    pub gate_type: GateType,
+    _mbz1: bool,
+    #[bits(2)]
+    pub dpl: PrivilegeLevel,
+    pub present: bool,
+}
+
+/// An IDT gate, the interrupt gates clear `IF` on the entry, the trap
+/// ones do not.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdtGate {
+    offset_low: u16,
+    selector: SegmentSelector,
+    options: GateOptions,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<IdtGate>() == 16);
+
+impl IdtGate {
+    pub const MISSING: Self = Self {
+        offset_low: 0,
+        selector: SegmentSelector::new(),
+        options: GateOptions::new(),
+        offset_mid: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    pub const fn new(handler: u64, selector: SegmentSelector, gate_type: GateType) -> Self {
+        Self {
+            offset_low: handler as u16,
+            selector,
+            options: GateOptions::new()
+                .with_gate_type(gate_type)
+                .with_present(true),
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+
+    pub const fn interrupt(handler: u64, selector: SegmentSelector) -> Self {
+        Self::new(handler, selector, GateType::Interrupt)
+    }
+
+    pub const fn trap(handler: u64, selector: SegmentSelector) -> Self {
+        Self::new(handler, selector, GateType::Trap)
+    }
+
+    /// Switches to the interrupt stack `ist`, `1` to `7`.
+    pub const fn with_ist(mut self, ist: u8) -> Self {
+        self.options = self.options.with_ist(ist);
+        self
+    }
+
+    /// Allows `int n` from the privilege level.
+    pub const fn with_dpl(mut self, dpl: PrivilegeLevel) -> Self {
+        self.options = self.options.with_dpl(dpl);
+        self
+    }
+
+    pub const fn handler(&self) -> u64 {
+        self.offset_low as u64 | (self.offset_mid as u64) << 16 | (self.offset_high as u64) << 32
+    }
+
+    pub const fn selector(&self) -> SegmentSelector {
+        self.selector
+    }
+
+    pub const fn options(&self) -> GateOptions {
+        self.options
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone)]
+pub struct InterruptDescriptorTable {
+    pub gates: [IdtGate; IDT_ENTRIES],
+}
+
+impl InterruptDescriptorTable {
+    pub const fn new() -> Self {
+        Self {
+            gates: [IdtGate::MISSING; IDT_ENTRIES],
+        }
+    }
+
+    pub fn set(&mut self, vector: u8, gate: IdtGate) {
+        self.gates[vector as usize] = gate;
+    }
+
+    pub fn pointer(&self) -> DescriptorTablePointer {
+        DescriptorTablePointer {
+            limit: (core::mem::size_of_val(&self.gates) - 1) as u16,
+            base: self.gates.as_ptr() as u64,
+        }
+    }
+}
+
+impl Default for InterruptDescriptorTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The operand of `lgdt` and `lidt`.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct DescriptorTablePointer {
+    pub limit: u16,
+    pub base: u64,
+}
+
+/// Loads the GDT, and reloads CS with a far return and the data segment
+/// registers.
+///
+/// # Safety
+///
+/// The selectors refer to the code and the data descriptors in the GDT.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn load_gdt<const N: usize>(
+    gdt: &'static GlobalDescriptorTable<N>,
+    code: SegmentSelector,
+    data: SegmentSelector,
+) {
+    let gdtr = gdt.pointer();
+
+    // SAFETY: the GDT is static, and the caller guarantees the selectors.
+    unsafe {
+        core::arch::asm!(
+            "lgdt [{gdtr}]",
+            "push {code}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov ss, {data:x}",
+            "mov fs, {data:x}",
+            "mov gs, {data:x}",
+            gdtr = in(reg) &gdtr,
+            code = in(reg) u16::from(code) as u64,
+            data = in(reg) u16::from(data) as u64,
+            tmp = lateout(reg) _,
+        );
+    }
+}
+
+/// # Safety
+///
+/// The gates point to the handlers that follow the interrupt frame
+/// conventions, with the selectors from the current GDT.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn load_idt(idt: &'static InterruptDescriptorTable) {
+    let idtr = idt.pointer();
+
+    // SAFETY: the IDT is static, and the caller guarantees the gates.
+    unsafe { core::arch::asm!("lidt [{}]", in(reg) &idtr, options(readonly, nostack)) };
+}
+
+/// Loads the task register, that marks the TSS descriptor busy.
+///
+/// # Safety
+///
+/// The selector refers to an available TSS descriptor in the current GDT.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn load_tss(selector: SegmentSelector) {
+    // SAFETY: the caller guarantees the selector.
+    unsafe { core::arch::asm!("ltr {0:x}", in(reg) u16::from(selector), options(nostack)) };
+}
+
+/// The stubs are placed at this stride, see `corgos_fault_stubs`.
+pub const FAULT_STUB_SIZE: u64 = 16;
+
+/// The registers as pushed by the processor and `corgos_fault_common`.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct ExceptionFrame {
+    /// The faulting address of the page faults.
+    pub cr2: u64,
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    /// Zero if the exception does not push an error code.
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// The fault stubs rely on the layout.
+const _: () = assert!(core::mem::size_of::<ExceptionFrame>() == 184);
+const _: () = assert!(core::mem::offset_of!(ExceptionFrame, vector) == 128);
+
+/// Returning resumes at `rip` with the registers from the frame, the
+/// handler may change them to recover from the fault.
+pub type FaultHandler = fn(&mut ExceptionFrame);
+
+static FAULT_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the handler the fault stubs call, must be done before the gates
+/// point to them.
+pub fn set_fault_handler(handler: FaultHandler) {
+    FAULT_HANDLER.store(handler as usize, Ordering::Release);
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+extern "C" fn corgos_fault_dispatch(frame: &mut ExceptionFrame) {
+    match FAULT_HANDLER.load(Ordering::Acquire) {
+        0 => loop {
+            core::hint::spin_loop();
+        },
+        // SAFETY: only `set_fault_handler` stores a non-zero value, and
+        // that is a `FaultHandler`.
+        handler => unsafe { core::mem::transmute::<usize, FaultHandler>(handler)(frame) },
+    }
+}
+
+// One 16-byte stub per vector pushes a zero if the processor has not
+// pushed an error code, then the vector number. The common part keeps the
+// frame address in `rbx`, which the callee preserves, to pop the frame
+// after the handler returns. The handler runs on interrupts too, so the
+// x87 and SSE state of the interrupted code goes below the frame and is
+// restored on the way out.
+#[cfg(target_arch = "x86_64")]
+core::arch::global_asm!(
+    ".pushsection .text",
+    ".balign 16",
+    ".global corgos_fault_stubs",
+    "corgos_fault_stubs:",
+    ".set vector, 0",
+    ".rept 256",
+    ".balign 16",
+    ".if !(vector == 8 || vector == 10 || vector == 11 || vector == 12 || vector == 13 || vector == 14 || vector == 17 || vector == 21 || vector == 29 || vector == 30)",
+    "pushq $0",
+    ".endif",
+    "pushq $vector",
+    "jmp corgos_fault_common",
+    ".set vector, vector + 1",
+    ".endr",
+    "corgos_fault_common:",
+    "pushq %rax",
+    "pushq %rbx",
+    "pushq %rcx",
+    "pushq %rdx",
+    "pushq %rsi",
+    "pushq %rdi",
+    "pushq %rbp",
+    "pushq %r8",
+    "pushq %r9",
+    "pushq %r10",
+    "pushq %r11",
+    "pushq %r12",
+    "pushq %r13",
+    "pushq %r14",
+    "pushq %r15",
+    "movq %cr2, %rax",
+    "pushq %rax",
+    "movq %rsp, %rdi",
+    "movq %rsp, %rbx",
+    "andq $-16, %rsp",
+    "subq $512, %rsp",
+    "fxsave64 (%rsp)",
+    "call {dispatch}",
+    "fxrstor64 (%rsp)",
+    "movq %rbx, %rsp",
+    "addq $8, %rsp",
+    "popq %r15",
+    "popq %r14",
+    "popq %r13",
+    "popq %r12",
+    "popq %r11",
+    "popq %r10",
+    "popq %r9",
+    "popq %r8",
+    "popq %rbp",
+    "popq %rdi",
+    "popq %rsi",
+    "popq %rdx",
+    "popq %rcx",
+    "popq %rbx",
+    "popq %rax",
+    "addq $16, %rsp",
+    "iretq",
+    ".popsection",
+    dispatch = sym corgos_fault_dispatch,
+    options(att_syntax)
+);
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    fn corgos_fault_stubs();
+}
+
+/// The address of the stub for the exception or the interrupt `vector`.
+#[cfg(target_arch = "x86_64")]
+pub fn fault_stub(vector: u8) -> u64 {
+    corgos_fault_stubs as *const () as u64 + vector as u64 * FAULT_STUB_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::x86_64::has_error_code;
+    use crate::x86_64::GlobalDescriptorTable;
+    use crate::x86_64::IdtGate;
+    use crate::x86_64::PrivilegeLevel;
+    use crate::x86_64::SegmentDescriptor;
+    use crate::x86_64::TaskStateSegment;
+    use crate::x86_64::SYSTEM_TYPE_TSS_AVAILABLE;
+
+    static TSS: TaskStateSegment = TaskStateSegment::new();
+
+    #[test]
+    fn segment_descriptors() {
+        assert_eq!(
+            u64::from(SegmentDescriptor::kernel_code()),
+            0x00af_9a00_0000_ffff
+        );
+        assert_eq!(
+            u64::from(SegmentDescriptor::kernel_data()),
+            0x00cf_9200_0000_ffff
+        );
+        assert_eq!(
+            u64::from(SegmentDescriptor::user_code()),
+            0x00af_fa00_0000_ffff
+        );
+        assert_eq!(
+            u64::from(SegmentDescriptor::user_data()),
+            0x00cf_f200_0000_ffff
+        );
+    }
+
+    #[test]
+    fn gdt() {
+        let mut gdt = GlobalDescriptorTable::<7>::new();
+        let code = gdt.add(SegmentDescriptor::kernel_code());
+        let data = gdt.add(SegmentDescriptor::kernel_data());
+        let user_code = gdt.add(SegmentDescriptor::user_code());
+        let tss = gdt.add_tss(&TSS);
+
+        assert_eq!(u16::from(code), 0x08);
+        assert_eq!(u16::from(data), 0x10);
+        assert_eq!(u16::from(user_code), 0x1b);
+        assert_eq!(u16::from(tss), 0x20);
+        assert_eq!(gdt.entries().len(), 6);
+        assert_eq!({ gdt.pointer().limit }, 6 * 8 - 1);
+
+        let base = &TSS as *const TaskStateSegment as u64;
+        let low = gdt.entries()[4];
+        assert_eq!(low & 0xffff, 103);
+        assert_eq!((low >> 16) & 0xff_ffff, base & 0xff_ffff);
+        assert_eq!((low >> 40) & 0xf, SYSTEM_TYPE_TSS_AVAILABLE as u64);
+        assert_eq!((low >> 47) & 1, 1);
+        assert_eq!(low >> 56, (base >> 24) & 0xff);
+        assert_eq!(gdt.entries()[5], base >> 32);
+    }
+
+    #[test]
+    fn idt_gates() {
+        let code = GlobalDescriptorTable::<2>::new().add(SegmentDescriptor::kernel_code());
+        let gate = IdtGate::interrupt(0x1234_5678_9abc_def0, code).with_ist(1);
+        // SAFETY: the gate is 16 bytes of plain data.
+        let raw: u128 = unsafe { core::mem::transmute(gate) };
+        assert_eq!(raw, 0x0000_0000_1234_5678_9abc_8e01_0008_def0);
+        assert_eq!(gate.handler(), 0x1234_5678_9abc_def0);
+
+        let gate = IdtGate::trap(0, code).with_dpl(PrivilegeLevel::User);
+        assert_eq!(u16::from(gate.options()), 0xef00);
+
+        assert!(has_error_code(14) && has_error_code(8) && !has_error_code(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "The GDT is full")]
+    fn gdt_full() {
+        let mut gdt = GlobalDescriptorTable::<2>::new();
+        gdt.add(SegmentDescriptor::kernel_code());
+        gdt.add(SegmentDescriptor::kernel_data());
+    }
+}
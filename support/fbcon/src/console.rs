@@ -0,0 +1,207 @@
+//! Drawing into the shadow buffer, and flushing the changes.
+
+use crate::Framebuffer;
+use crate::Psf2Font;
+use crate::BLACK;
+use crate::LIGHT_GRAY;
+use core::fmt::Write;
+
+const TAB_WIDTH: usize = 8;
+
+/// The changed pixels, the ends are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Rect {
+    fn union(self, other: Rect) -> Rect {
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
+
+pub struct Console<'a> {
+    framebuffer: Framebuffer,
+    shadow: &'a mut [u32],
+    font: Psf2Font<'a>,
+    columns: usize,
+    rows: usize,
+    column: usize,
+    row: usize,
+    foreground: u32,
+    background: u32,
+    dirty: Option<Rect>,
+}
+
+impl<'a> Console<'a> {
+    /// The console on the `framebuffer` drawing into `shadow`, none if the
+    /// shadow buffer is smaller than the framebuffer or not a single
+    /// character fits. The screen is cleared on the first flush.
+    ///
+    /// # Safety
+    ///
+    /// The framebuffer is mapped at its base address for as long as the
+    /// console is used, and nothing else writes to it.
+    pub unsafe fn new(
+        framebuffer: Framebuffer,
+        shadow: &'a mut [u32],
+        font: Psf2Font<'a>,
+    ) -> Option<Self> {
+        let pixels = framebuffer.stride.checked_mul(framebuffer.height)?;
+        if framebuffer.base_addr == 0 || framebuffer.width > framebuffer.stride {
+            return None;
+        }
+        let shadow = shadow.get_mut(..pixels)?;
+        let columns = framebuffer.width / font.width();
+        let rows = framebuffer.height / font.height();
+        if columns == 0 || rows == 0 {
+            return None;
+        }
+
+        let mut console = Self {
+            framebuffer,
+            shadow,
+            font,
+            columns,
+            rows,
+            column: 0,
+            row: 0,
+            foreground: framebuffer.format.encode(LIGHT_GRAY),
+            background: framebuffer.format.encode(BLACK),
+            dirty: None,
+        };
+        console.clear();
+        Some(console)
+    }
+
+    /// The size in characters, as `(columns, rows)`.
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// The colors as `0x00rrggbb`, for the characters written after.
+    pub fn set_colors(&mut self, foreground: u32, background: u32) {
+        self.foreground = self.framebuffer.format.encode(foreground);
+        self.background = self.framebuffer.format.encode(background);
+    }
+
+    pub fn clear(&mut self) {
+        self.shadow.fill(self.background);
+        self.column = 0;
+        self.row = 0;
+        self.mark_dirty(Rect {
+            left: 0,
+            top: 0,
+            right: self.framebuffer.width,
+            bottom: self.framebuffer.height,
+        });
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty = Some(self.dirty.map_or(rect, |dirty| dirty.union(rect)));
+    }
+
+    /// Copies the changed pixels from the shadow buffer, one by one as the
+    /// framebuffer is device memory.
+    pub fn flush(&mut self) {
+        let Some(dirty) = self.dirty.take() else {
+            return;
+        };
+        for y in dirty.top..dirty.bottom {
+            let line = y * self.framebuffer.stride;
+            for x in dirty.left..dirty.right {
+                let pixel = self.framebuffer.base_addr as *mut u32;
+                // SAFETY: the framebuffer is mapped, see `new`, the shadow
+                // buffer has its layout, and the rectangle is inside.
+                unsafe { pixel.add(line + x).write_volatile(self.shadow[line + x]) };
+            }
+        }
+    }
+
+    fn draw(&mut self, c: char) {
+        let (width, height) = (self.font.width(), self.font.height());
+        let bytes_per_row = self.font.bytes_per_row();
+        let x = self.column * width;
+        let y = self.row * height;
+        let glyph = self.font.glyph(c);
+        for (dy, bits) in glyph.chunks_exact(bytes_per_row).enumerate() {
+            let line = (y + dy) * self.framebuffer.stride + x;
+            for dx in 0..width {
+                self.shadow[line + dx] = if bits[dx / 8] & (0x80 >> (dx % 8)) != 0 {
+                    self.foreground
+                } else {
+                    self.background
+                };
+            }
+        }
+        self.mark_dirty(Rect {
+            left: x,
+            top: y,
+            right: x + width,
+            bottom: y + height,
+        });
+    }
+
+    fn scroll(&mut self) {
+        let text_row = self.font.height() * self.framebuffer.stride;
+        let used = self.rows * text_row;
+        self.shadow.copy_within(text_row..used, 0);
+        self.shadow[used - text_row..used].fill(self.background);
+        self.mark_dirty(Rect {
+            left: 0,
+            top: 0,
+            right: self.framebuffer.width,
+            bottom: self.rows * self.font.height(),
+        });
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.column < next.min(self.columns) {
+                    self.put_char(' ');
+                }
+            }
+            '\x08' => self.column = self.column.saturating_sub(1),
+            _ => {
+                if self.column == self.columns {
+                    self.new_line();
+                }
+                self.draw(c);
+                self.column += 1;
+            }
+        }
+    }
+}
+
+impl Write for Console<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            self.put_char(c);
+        }
+        if s.contains('\n') {
+            self.flush();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! The PC Screen Font version 2, as the Linux console fonts.
+//!
+//! Without the Unicode table the glyph index is the character code. With
+//! it, the table is searched for every character, which is fine for the
+//! boot consoles printing a few thousand characters.
+
+pub const PSF2_MAGIC: u32 = 0x864a_b572;
+/// The table mapping the characters to the glyphs follows the glyphs.
+pub const PSF2_HAS_UNICODE_TABLE: u32 = 1 << 0;
+
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_START_SEQUENCE: u8 = 0xfe;
+
+/// The printable ASCII, 8x16 pixels, rasterized from DejaVu Sans Mono
+/// (Bitstream Vera license, see `fonts/LICENSE`). The control characters
+/// have the replacement glyph.
+pub static DEFAULT_FONT: &[u8] = include_bytes!("../fonts/dejavu-sans-mono-8x16.psf");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    BadMagic,
+    /// The glyphs or the header do not fit in the data.
+    Truncated,
+    /// No glyphs, or the glyph size does not match the dimensions.
+    BadHeader,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FontError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(FontError::Truncated)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Psf2Font<'a> {
+    glyphs: &'a [u8],
+    unicode_table: Option<&'a [u8]>,
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Psf2Font<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, FontError> {
+        if read_u32(data, 0)? != PSF2_MAGIC {
+            return Err(FontError::BadMagic);
+        }
+        let header_size = read_u32(data, 8)? as usize;
+        let flags = read_u32(data, 12)?;
+        let glyph_count = read_u32(data, 16)? as usize;
+        let bytes_per_glyph = read_u32(data, 20)? as usize;
+        let height = read_u32(data, 24)? as usize;
+        let width = read_u32(data, 28)? as usize;
+        if header_size < PSF2_HEADER_SIZE
+            || glyph_count == 0
+            || width == 0
+            || bytes_per_glyph != width.div_ceil(8) * height
+        {
+            return Err(FontError::BadHeader);
+        }
+
+        let glyphs_end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or(FontError::Truncated)?;
+        let unicode_table = (flags & PSF2_HAS_UNICODE_TABLE != 0).then(|| &data[glyphs_end..]);
+        Ok(Self {
+            glyphs,
+            unicode_table,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+        })
+    }
+
+    /// [`DEFAULT_FONT`].
+    pub fn default_font() -> Psf2Font<'static> {
+        Psf2Font::parse(DEFAULT_FONT).expect("The default font is valid")
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The glyph rows are padded to the whole bytes, the leftmost pixel is
+    /// the top bit.
+    pub fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    fn index(&self, c: char) -> Option<usize> {
+        let Some(table) = self.unicode_table else {
+            return Some(c as usize).filter(|&index| index < self.glyph_count);
+        };
+
+        let mut glyph = 0;
+        let mut offset = 0;
+        let mut in_sequence = false;
+        while let Some(&b) = table.get(offset) {
+            match b {
+                PSF2_SEPARATOR => {
+                    glyph += 1;
+                    in_sequence = false;
+                    offset += 1;
+                }
+                PSF2_START_SEQUENCE => {
+                    in_sequence = true;
+                    offset += 1;
+                }
+                _ => {
+                    let len = match b.leading_ones() {
+                        0 => 1,
+                        len @ 2..=4 => len as usize,
+                        _ => return None,
+                    };
+                    let bytes = table.get(offset..offset + len)?;
+                    let decoded = core::str::from_utf8(bytes).ok()?.chars().next();
+                    if !in_sequence && decoded == Some(c) {
+                        return Some(glyph).filter(|&index| index < self.glyph_count);
+                    }
+                    offset += len;
+                }
+            }
+        }
+        None
+    }
+
+    /// The glyph 0 for the characters without one.
+    pub fn glyph(&self, c: char) -> &'a [u8] {
+        let index = self.index(c).unwrap_or(0);
+        &self.glyphs[index * self.bytes_per_glyph..(index + 1) * self.bytes_per_glyph]
+    }
+}
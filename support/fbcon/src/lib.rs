@@ -0,0 +1,116 @@
+//! The text console on a linear framebuffer with 32 bits per pixel.
+//!
+//! Reading back the write-combined or uncached framebuffer for scrolling is
+//! slow, so the console draws into a shadow buffer of the framebuffer
+//! layout the caller provides, remembers the rectangle it has changed, and
+//! copies that rectangle out on [`Console::flush`]. Writing through
+//! `core::fmt::Write` flushes at the end of every line.
+//!
+//! The glyphs come from a PSF2 font, [`DEFAULT_FONT`] is embedded.
+//!
+//! Example:
+//! ```ignore
+//! let framebuffer = fbcon::Framebuffer {
+//!     base_addr: phys_base,
+//!     width: 1024,
+//!     height: 768,
+//!     stride: 1024,
+//!     format: fbcon::PixelFormat::Bgr,
+//! };
+//! // SAFETY: the framebuffer is identity-mapped, and the console is the
+//! // only one drawing.
+//! let mut console =
+//!     unsafe { fbcon::Console::new(framebuffer, shadow, Psf2Font::default_font()) }?;
+//! writeln!(console, "Hello")?;
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod console;
+mod font;
+mod tests;
+
+pub use console::Console;
+pub use font::FontError;
+pub use font::Psf2Font;
+pub use font::DEFAULT_FONT;
+pub use font::PSF2_HAS_UNICODE_TABLE;
+pub use font::PSF2_MAGIC;
+
+pub const LIGHT_GRAY: u32 = 0x00c0_c0c0;
+pub const BLACK: u32 = 0x0000_0000;
+
+/// How the colors are laid out in the 32-bit pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red in the lowest byte.
+    Rgb,
+    /// Blue in the lowest byte.
+    Bgr,
+    Bitmask {
+        red: u32,
+        green: u32,
+        blue: u32,
+    },
+}
+
+/// The 8 bits of the color scaled to the width of the mask.
+fn scale(value: u32, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones();
+    let scaled = if bits >= 8 {
+        value << (bits - 8)
+    } else {
+        value >> (8 - bits)
+    };
+    (scaled << shift) & mask
+}
+
+impl PixelFormat {
+    /// The pixel of the color `0x00rrggbb`.
+    pub fn encode(&self, rgb: u32) -> u32 {
+        let (red, green, blue) = ((rgb >> 16) & 0xff, (rgb >> 8) & 0xff, rgb & 0xff);
+        match *self {
+            PixelFormat::Rgb => red | green << 8 | blue << 16,
+            PixelFormat::Bgr => blue | green << 8 | red << 16,
+            PixelFormat::Bitmask {
+                red: red_mask,
+                green: green_mask,
+                blue: blue_mask,
+            } => scale(red, red_mask) | scale(green, green_mask) | scale(blue, blue_mask),
+        }
+    }
+
+    /// The format of the framebuffer the loader has described, none if
+    /// there is no framebuffer or the pixels are not 32 bits.
+    pub fn from_boot_info(framebuffer: &bootinfo::Framebuffer) -> Option<Self> {
+        if framebuffer.bits_per_pixel != 32 {
+            return None;
+        }
+        match framebuffer.pixel_format {
+            bootinfo::PixelFormat::None => None,
+            bootinfo::PixelFormat::Rgb => Some(PixelFormat::Rgb),
+            bootinfo::PixelFormat::Bgr => Some(PixelFormat::Bgr),
+            bootinfo::PixelFormat::Bitmask => Some(PixelFormat::Bitmask {
+                red: framebuffer.red_mask,
+                green: framebuffer.green_mask,
+                blue: framebuffer.blue_mask,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    /// Where the framebuffer is mapped.
+    pub base_addr: u64,
+    /// In pixels.
+    pub width: usize,
+    pub height: usize,
+    /// The number of pixels per scan line, may be more than the width.
+    pub stride: usize,
+    pub format: PixelFormat,
+}
@@ -0,0 +1,234 @@
+#![cfg(test)]
+
+use crate::Console;
+use crate::FontError;
+use crate::Framebuffer;
+use crate::PixelFormat;
+use crate::Psf2Font;
+use crate::DEFAULT_FONT;
+use crate::PSF2_HAS_UNICODE_TABLE;
+use crate::PSF2_MAGIC;
+use core::fmt::Write;
+
+const WIDTH: usize = 8 * 4;
+const HEIGHT: usize = 16 * 2;
+const STRIDE: usize = WIDTH + 8;
+/// Never drawn by the console.
+const SENTINEL: u32 = 0xdead_beef;
+
+fn framebuffer(pixels: &mut [u32]) -> Framebuffer {
+    Framebuffer {
+        base_addr: pixels.as_mut_ptr() as u64,
+        width: WIDTH,
+        height: HEIGHT,
+        stride: STRIDE,
+        format: PixelFormat::Bgr,
+    }
+}
+
+fn new_console<'a>(
+    framebuffer: Framebuffer,
+    shadow: &'a mut [u32],
+    font: Psf2Font<'a>,
+) -> Option<Console<'a>> {
+    // SAFETY: the tests keep the pixels alive while the console is used.
+    unsafe { Console::new(framebuffer, shadow, font) }
+}
+
+/// The pixels of the character cell as the rows of bits.
+fn cell(pixels: &[u32], column: usize, row: usize, foreground: u32) -> Vec<u8> {
+    (0..16)
+        .map(|dy| {
+            let line = (row * 16 + dy) * STRIDE + column * 8;
+            (0..8).fold(0, |bits, dx| {
+                bits | ((pixels[line + dx] == foreground) as u8) << (7 - dx)
+            })
+        })
+        .collect()
+}
+
+fn psf2(flags: u32, glyphs: &[[u8; 2]], table: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for field in [PSF2_MAGIC, 0, 32, flags, glyphs.len() as u32, 2, 2, 8] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    for glyph in glyphs {
+        data.extend_from_slice(glyph);
+    }
+    data.extend_from_slice(table);
+    data
+}
+
+#[test]
+fn test_default_font() {
+    let font = Psf2Font::default_font();
+    assert_eq!(
+        (font.width(), font.height(), font.bytes_per_row()),
+        (8, 16, 1)
+    );
+    assert_eq!(font.glyph('A').len(), 16);
+    assert_ne!(font.glyph('A'), font.glyph('B'));
+    assert_eq!(font.glyph('\u{1}'), font.glyph('\u{263a}'));
+    assert_eq!(font.glyph(' '), &[0; 16]);
+}
+
+#[test]
+fn test_font_errors() {
+    assert_eq!(
+        Psf2Font::parse(&DEFAULT_FONT[..20]).unwrap_err(),
+        FontError::Truncated
+    );
+    assert_eq!(
+        Psf2Font::parse(&DEFAULT_FONT[..DEFAULT_FONT.len() - 1]).unwrap_err(),
+        FontError::Truncated
+    );
+    let mut bad = DEFAULT_FONT.to_vec();
+    bad[0] = 0;
+    assert_eq!(Psf2Font::parse(&bad).unwrap_err(), FontError::BadMagic);
+    let mut bad = DEFAULT_FONT.to_vec();
+    bad[20] = 17;
+    assert_eq!(Psf2Font::parse(&bad).unwrap_err(), FontError::BadHeader);
+}
+
+#[test]
+fn test_unicode_table() {
+    // 'a' and 'é' share the glyph 1, the glyph 2 is for a sequence only,
+    // and the glyph 3 is for '☺'.
+    let mut table = Vec::new();
+    table.push(0xff);
+    table.extend_from_slice("aé".as_bytes());
+    table.push(0xff);
+    table.push(0xfe);
+    table.extend_from_slice("b\u{301}".as_bytes());
+    table.push(0xff);
+    table.extend_from_slice("☺".as_bytes());
+    table.push(0xff);
+    let glyphs = [[0, 0], [1, 1], [2, 2], [3, 3]];
+    let data = psf2(PSF2_HAS_UNICODE_TABLE, &glyphs, &table);
+    let font = Psf2Font::parse(&data).unwrap();
+    assert_eq!(font.glyph('a'), &[1, 1]);
+    assert_eq!(font.glyph('é'), &[1, 1]);
+    assert_eq!(font.glyph('b'), &[0, 0]);
+    assert_eq!(font.glyph('☺'), &[3, 3]);
+    assert_eq!(font.glyph('z'), &[0, 0]);
+
+    let data = psf2(0, &glyphs, &[]);
+    let font = Psf2Font::parse(&data).unwrap();
+    assert_eq!(font.glyph('\u{2}'), &[2, 2]);
+    assert_eq!(font.glyph('a'), &[0, 0]);
+}
+
+#[test]
+fn test_pixel_formats() {
+    assert_eq!(PixelFormat::Rgb.encode(0x11_22_33), 0x33_22_11);
+    assert_eq!(PixelFormat::Bgr.encode(0x11_22_33), 0x11_22_33);
+    let rgb565 = PixelFormat::Bitmask {
+        red: 0xf800,
+        green: 0x07e0,
+        blue: 0x001f,
+    };
+    assert_eq!(rgb565.encode(0xff_ff_ff), 0xffff);
+    assert_eq!(rgb565.encode(0x80_00_00), 0x8000);
+    let rgb10 = PixelFormat::Bitmask {
+        red: 0x3ff << 20,
+        green: 0x3ff << 10,
+        blue: 0x3ff,
+    };
+    assert_eq!(rgb10.encode(0x00_00_ff), 0x3fc);
+}
+
+#[test]
+fn test_boot_info_formats() {
+    let mut framebuffer = bootinfo::Framebuffer {
+        pixel_format: bootinfo::PixelFormat::Bgr,
+        bits_per_pixel: 32,
+        ..Default::default()
+    };
+    assert_eq!(
+        PixelFormat::from_boot_info(&framebuffer),
+        Some(PixelFormat::Bgr)
+    );
+
+    framebuffer.pixel_format = bootinfo::PixelFormat::Bitmask;
+    framebuffer.red_mask = 0xff;
+    framebuffer.green_mask = 0xff00;
+    framebuffer.blue_mask = 0xff_0000;
+    assert_eq!(
+        PixelFormat::from_boot_info(&framebuffer),
+        Some(PixelFormat::Bitmask {
+            red: 0xff,
+            green: 0xff00,
+            blue: 0xff_0000,
+        })
+    );
+
+    framebuffer.bits_per_pixel = 24;
+    assert_eq!(PixelFormat::from_boot_info(&framebuffer), None);
+    framebuffer.bits_per_pixel = 32;
+    framebuffer.pixel_format = bootinfo::PixelFormat::None;
+    assert_eq!(PixelFormat::from_boot_info(&framebuffer), None);
+}
+
+#[test]
+fn test_draw_and_flush() {
+    let mut pixels = vec![SENTINEL; STRIDE * HEIGHT];
+    let mut shadow = vec![0; STRIDE * HEIGHT];
+    let font = Psf2Font::default_font();
+    let mut console = new_console(framebuffer(&mut pixels), &mut shadow, font).unwrap();
+    assert_eq!(console.size(), (4, 2));
+
+    // Nothing reaches the framebuffer before the flush.
+    write!(console, "A").unwrap();
+    assert!(pixels.iter().all(|&p| p == SENTINEL));
+    console.flush();
+    let foreground = PixelFormat::Bgr.encode(crate::LIGHT_GRAY);
+    assert_eq!(cell(&pixels, 0, 0, foreground), font.glyph('A'));
+    // The padding of the scan lines is not touched.
+    assert!((0..HEIGHT).all(|y| pixels[y * STRIDE + WIDTH] == SENTINEL));
+
+    // Only the changed cell is copied out.
+    pixels.fill(SENTINEL);
+    writeln!(console, "B").unwrap();
+    assert_eq!(cell(&pixels, 1, 0, foreground), font.glyph('B'));
+    assert!(pixels[..8].iter().all(|&p| p == SENTINEL));
+}
+
+#[test]
+fn test_wrap_and_scroll() {
+    let mut pixels = vec![0; STRIDE * HEIGHT];
+    let mut shadow = vec![0; STRIDE * HEIGHT];
+    let font = Psf2Font::default_font();
+    let mut console = new_console(framebuffer(&mut pixels), &mut shadow, font).unwrap();
+    let foreground = PixelFormat::Bgr.encode(crate::LIGHT_GRAY);
+
+    write!(console, "abcdE").unwrap();
+    console.flush();
+    assert_eq!(cell(&pixels, 3, 0, foreground), font.glyph('d'));
+    assert_eq!(cell(&pixels, 0, 1, foreground), font.glyph('E'));
+
+    // The tab fills the line, and the first line goes away.
+    write!(console, "\tx").unwrap();
+    console.flush();
+    assert_eq!(cell(&pixels, 0, 0, foreground), font.glyph('E'));
+    assert_eq!(cell(&pixels, 3, 0, foreground), font.glyph(' '));
+    assert_eq!(cell(&pixels, 0, 1, foreground), font.glyph('x'));
+
+    writeln!(console).unwrap();
+    assert_eq!(cell(&pixels, 0, 0, foreground), font.glyph('x'));
+    assert_eq!(cell(&pixels, 0, 1, foreground), font.glyph(' '));
+}
+
+#[test]
+fn test_too_small() {
+    let mut pixels = vec![0; STRIDE * HEIGHT];
+    let mut shadow = vec![0; STRIDE * HEIGHT - 1];
+    let font = Psf2Font::default_font();
+    assert!(new_console(framebuffer(&mut pixels), &mut shadow, font).is_none());
+
+    let mut shadow = vec![0; STRIDE * HEIGHT];
+    let tiny = Framebuffer {
+        height: 15,
+        ..framebuffer(&mut pixels)
+    };
+    assert!(new_console(tiny, &mut shadow, font).is_none());
+}
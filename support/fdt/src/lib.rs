@@ -0,0 +1,1076 @@
+//! The flattened device tree (the DTB), read in place without allocating.
+//!
+//! [`Fdt::new`] checks the header, and walks the structure block once to
+//! make sure the tokens nest, and the names and the values are inside the
+//! blob. Everything after that is an iterator over the blob that stops at
+//! the first surprise rather than returns an error: [`Node::properties`],
+//! [`Node::children`], [`Fdt::nodes`] for all the nodes depth-first, and
+//! [`Fdt::memory_reservations`].
+//!
+//! A node carries what it inherits from its ancestors: the
+//! `#address-cells` and `#size-cells` of its parent that its `reg` is
+//! encoded with, and the nearest `interrupt-parent`. The typed helpers on
+//! top of that are [`Fdt::memory`], [`Fdt::chosen`], [`Fdt::cpus`],
+//! [`Node::reg`], and [`Node::interrupt_parent`].
+//!
+//! Example:
+//! ```ignore
+//! let fdt = fdt::Fdt::new(blob)?;
+//! for region in fdt.memory() {
+//!     log::info!("RAM {:#x}, {:#x} bytes", region.address, region.size);
+//! }
+//! if let Some(uart) = fdt.compatible("arm,pl011").next() {
+//!     let base = uart.reg().and_then(|mut reg| reg.next());
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+/// The blobs older than that have no size of the structure block.
+pub const FDT_MIN_VERSION: u32 = 17;
+/// How deep the nodes may nest.
+pub const MAX_DEPTH: usize = 16;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+const HEADER_SIZE: usize = 40;
+const RESERVATION_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    /// Not a DTB.
+    BadMagic,
+    /// The last compatible version is newer than the one supported.
+    UnsupportedVersion(u32),
+    /// A block or an item points outside of the blob.
+    Truncated,
+    /// An unexpected token at the offset into the structure block.
+    BadToken(usize),
+    /// A name at the offset into its block is not terminated, or not UTF-8.
+    BadName(usize),
+    /// The nodes nest deeper than [`MAX_DEPTH`].
+    TooDeep,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset.checked_add(4)?)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some((read_u32(data, offset)? as u64) << 32 | read_u32(data, offset + 4)? as u64)
+}
+
+/// The big-endian cells, the high ones beyond two are dropped.
+fn read_cells(bytes: &[u8]) -> u64 {
+    bytes
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .fold(0, |acc, &b| acc << 32 | u32::from_be_bytes(b) as u64)
+}
+
+/// The NUL-terminated string at the offset, without the NUL.
+fn read_str(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub total_size: u32,
+    pub off_dt_struct: u32,
+    pub off_dt_strings: u32,
+    pub off_mem_rsvmap: u32,
+    pub version: u32,
+    pub last_comp_version: u32,
+    /// The physical ID of the boot processor.
+    pub boot_cpuid_phys: u32,
+    pub size_dt_strings: u32,
+    pub size_dt_struct: u32,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, FdtError> {
+        let field = |index: usize| read_u32(data, index * 4).ok_or(FdtError::Truncated);
+        if field(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let header = Self {
+            total_size: field(1)?,
+            off_dt_struct: field(2)?,
+            off_dt_strings: field(3)?,
+            off_mem_rsvmap: field(4)?,
+            version: field(5)?,
+            last_comp_version: field(6)?,
+            boot_cpuid_phys: field(7)?,
+            size_dt_strings: field(8)?,
+            size_dt_struct: field(9)?,
+        };
+        if header.last_comp_version > FDT_MIN_VERSION || header.version < FDT_MIN_VERSION {
+            return Err(FdtError::UnsupportedVersion(header.version));
+        }
+        Ok(header)
+    }
+}
+
+/// `#address-cells` and `#size-cells`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cells {
+    pub address: u32,
+    pub size: u32,
+}
+
+impl Cells {
+    /// What the properties default to when absent.
+    pub const DEFAULT: Self = Self {
+        address: 2,
+        size: 1,
+    };
+}
+
+/// A range of the `reg` of a node, or of the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub address: u64,
+    /// `0` if the parent has no `#size-cells`.
+    pub size: u64,
+}
+
+/// An entry of the memory reservation block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReservation {
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+impl<'a> Property<'a> {
+    pub fn as_u32(&self) -> Option<u32> {
+        (self.value.len() == 4).then(|| read_cells(self.value) as u32)
+    }
+
+    /// One or two cells.
+    pub fn as_u64(&self) -> Option<u64> {
+        matches!(self.value.len(), 4 | 8).then(|| read_cells(self.value))
+    }
+
+    /// A single NUL-terminated string.
+    pub fn as_str(&self) -> Option<&'a str> {
+        let (&0, bytes) = self.value.split_last()? else {
+            return None;
+        };
+        if bytes.contains(&0) {
+            return None;
+        }
+        core::str::from_utf8(bytes).ok()
+    }
+
+    /// The NUL-terminated strings, as in `compatible`.
+    pub fn as_str_list(&self) -> StrList<'a> {
+        StrList { value: self.value }
+    }
+
+    /// The value as the big-endian cells.
+    pub fn as_u32_list(&self) -> impl Iterator<Item = u32> + 'a {
+        self.value
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|&b| u32::from_be_bytes(b))
+    }
+}
+
+/// The strings of a string list property, ends at the first one that is
+/// not UTF-8.
+#[derive(Debug, Clone)]
+pub struct StrList<'a> {
+    value: &'a [u8],
+}
+
+impl<'a> Iterator for StrList<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.value.iter().position(|&b| b == 0)?;
+        let s = core::str::from_utf8(&self.value[..len]).ok()?;
+        self.value = &self.value[len + 1..];
+        Some(s)
+    }
+}
+
+/// The ranges of a `reg` property.
+#[derive(Debug, Clone)]
+pub struct Reg<'a> {
+    value: &'a [u8],
+    cells: Cells,
+}
+
+impl Iterator for Reg<'_> {
+    type Item = Region;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address_len = self.cells.address as usize * 4;
+        let entry_len = address_len + self.cells.size as usize * 4;
+        if entry_len == 0 || self.value.len() < entry_len {
+            return None;
+        }
+        let (entry, rest) = self.value.split_at(entry_len);
+        self.value = rest;
+        Some(Region {
+            address: read_cells(&entry[..address_len]),
+            size: read_cells(&entry[address_len..]),
+        })
+    }
+}
+
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Prop(Property<'a>),
+    Nop,
+    End,
+}
+
+/// The parsed device tree, a cheap copy of the references into the blob.
+#[derive(Debug, Clone, Copy)]
+pub struct Fdt<'a> {
+    header: Header,
+    reservations: &'a [u8],
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Checks the header and the structure block, see the crate docs.
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        let header = Header::parse(data)?;
+        let data = data
+            .get(..header.total_size as usize)
+            .ok_or(FdtError::Truncated)?;
+        let block = |offset: u32, size: u32| {
+            let start = offset as usize;
+            start
+                .checked_add(size as usize)
+                .and_then(|end| data.get(start..end))
+                .ok_or(FdtError::Truncated)
+        };
+        let fdt = Self {
+            header,
+            reservations: data
+                .get(header.off_mem_rsvmap as usize..)
+                .ok_or(FdtError::Truncated)?,
+            structure: block(header.off_dt_struct, header.size_dt_struct)?,
+            strings: block(header.off_dt_strings, header.size_dt_strings)?,
+        };
+        fdt.validate()?;
+        Ok(fdt)
+    }
+
+    /// Takes the size of the blob from its header.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` points to the header of a DTB, and the memory of its size
+    /// stays readable and unchanged for `'a`.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Self, FdtError> {
+        // SAFETY: the caller guarantees that there is a header.
+        let header = Header::parse(unsafe { core::slice::from_raw_parts(ptr, HEADER_SIZE) })?;
+        // SAFETY: the caller guarantees the rest.
+        Self::new(unsafe { core::slice::from_raw_parts(ptr, header.total_size as usize) })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn token(&self, offset: usize) -> Result<(Token<'a>, usize), FdtError> {
+        let token = read_u32(self.structure, offset).ok_or(FdtError::Truncated)?;
+        let body = offset + 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = read_str(self.structure, body).ok_or(FdtError::BadName(body))?;
+                Ok((Token::BeginNode(name), align4(body + name.len() + 1)))
+            }
+            FDT_END_NODE => Ok((Token::EndNode, body)),
+            FDT_PROP => {
+                let len = read_u32(self.structure, body).ok_or(FdtError::Truncated)? as usize;
+                let name_offset =
+                    read_u32(self.structure, body + 4).ok_or(FdtError::Truncated)? as usize;
+                let value_start = body + 8;
+                let value = self
+                    .structure
+                    .get(value_start..value_start + len)
+                    .ok_or(FdtError::Truncated)?;
+                let name =
+                    read_str(self.strings, name_offset).ok_or(FdtError::BadName(name_offset))?;
+                Ok((
+                    Token::Prop(Property { name, value }),
+                    align4(value_start + len),
+                ))
+            }
+            FDT_NOP => Ok((Token::Nop, body)),
+            FDT_END => Ok((Token::End, body)),
+            _ => Err(FdtError::BadToken(offset)),
+        }
+    }
+
+    /// One root node, the properties inside the nodes, the nodes balanced,
+    /// and nothing past the end token.
+    fn validate(&self) -> Result<(), FdtError> {
+        let mut offset = 0;
+        let mut depth = 0;
+        let mut seen_root = false;
+        loop {
+            let (token, next) = self.token(offset)?;
+            match token {
+                Token::BeginNode(_) if depth == 0 && seen_root => {
+                    return Err(FdtError::BadToken(offset))
+                }
+                Token::BeginNode(_) => {
+                    seen_root = true;
+                    depth += 1;
+                    if depth > MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                }
+                Token::EndNode | Token::Prop(_) if depth == 0 => {
+                    return Err(FdtError::BadToken(offset))
+                }
+                Token::EndNode => depth -= 1,
+                Token::Prop(_) | Token::Nop => {}
+                Token::End if depth == 0 && seen_root => return Ok(()),
+                Token::End => return Err(FdtError::BadToken(offset)),
+            }
+            offset = next;
+        }
+    }
+
+    /// The offset past the end of the node that begins at `offset`.
+    fn skip_node(&self, mut offset: usize) -> usize {
+        let mut depth = 0;
+        while let Ok((token, next)) = self.token(offset) {
+            offset = next;
+            match token {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode if depth == 1 => break,
+                Token::EndNode => depth -= 1,
+                Token::End => break,
+                Token::Prop(_) | Token::Nop => {}
+            }
+        }
+        offset
+    }
+
+    fn node_at(&self, offset: usize, parent: Inherited) -> Option<Node<'a>> {
+        match self.token(offset).ok()? {
+            (Token::BeginNode(name), body) => Some(Node {
+                fdt: *self,
+                name,
+                body,
+                parent,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn memory_reservations(&self) -> MemoryReservations<'a> {
+        MemoryReservations {
+            data: self.reservations,
+        }
+    }
+
+    pub fn root(&self) -> Node<'a> {
+        let offset = (0..)
+            .step_by(4)
+            .find(|&offset| !matches!(self.token(offset), Ok((Token::Nop, _))))
+            .unwrap_or_default();
+        self.node_at(offset, Inherited::ROOT)
+            .expect("The blob has been validated")
+    }
+
+    /// All the nodes, depth-first.
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            inherited: [Inherited::ROOT; MAX_DEPTH + 1],
+        }
+    }
+
+    /// The node at the absolute path, or at the path from an alias if the
+    /// path does not start with `/`. A path component without a unit
+    /// address matches the node with any.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let path = if path.starts_with('/') {
+            path
+        } else {
+            let (alias, rest) = path.split_at(path.find('/').unwrap_or(path.len()));
+            let aliased = self.find_node("/aliases")?.property(alias)?.as_str()?;
+            return self.find_node(aliased)?.find_descendant(rest);
+        };
+        self.root().find_descendant(path)
+    }
+
+    pub fn find_phandle(&self, phandle: u32) -> Option<Node<'a>> {
+        self.nodes().find(|node| node.phandle() == Some(phandle))
+    }
+
+    /// The enabled nodes compatible with `compatible`.
+    pub fn compatible<'b>(&self, compatible: &'b str) -> impl Iterator<Item = Node<'a>> + 'b
+    where
+        'a: 'b,
+    {
+        self.nodes()
+            .filter(move |node| node.is_compatible(compatible) && node.is_enabled())
+    }
+
+    /// The ranges of the `memory` nodes under the root.
+    pub fn memory(&self) -> impl Iterator<Item = Region> + 'a {
+        self.root()
+            .children()
+            .filter(|node| node.device_type() == Some("memory") || node.unit_name() == "memory")
+            .filter(|node| node.is_enabled())
+            .filter_map(|node| node.reg())
+            .flatten()
+    }
+
+    pub fn chosen(&self) -> Option<Chosen<'a>> {
+        self.find_node("/chosen").map(|node| Chosen { node })
+    }
+
+    /// The enabled `cpu` nodes under `/cpus`, the first range of `reg` is
+    /// the hardware ID of the processor.
+    pub fn cpus(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        self.find_node("/cpus")
+            .into_iter()
+            .flat_map(|cpus| cpus.children())
+            .filter(|node| node.device_type() == Some("cpu") && node.is_enabled())
+    }
+}
+
+/// What a node inherits from its ancestors.
+#[derive(Debug, Clone, Copy)]
+struct Inherited {
+    cells: Cells,
+    interrupt_parent: Option<u32>,
+}
+
+impl Inherited {
+    const ROOT: Self = Self {
+        cells: Cells::DEFAULT,
+        interrupt_parent: None,
+    };
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// The offset of the first token after the name.
+    body: usize,
+    parent: Inherited,
+}
+
+impl<'a> Node<'a> {
+    /// The name with the unit address, empty for the root.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The name without the unit address.
+    pub fn unit_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or_default()
+    }
+
+    pub fn unit_address(&self) -> Option<&'a str> {
+        self.name.split_once('@').map(|(_, address)| address)
+    }
+
+    pub fn properties(&self) -> Properties<'a> {
+        Properties {
+            fdt: self.fdt,
+            offset: self.body,
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    pub fn children(&self) -> Children<'a> {
+        Children {
+            fdt: self.fdt,
+            offset: self.body,
+            inherited: self.inherited(),
+        }
+    }
+
+    /// The child with the name, or with the unit name if `name` has no
+    /// unit address.
+    pub fn child(&self, name: &str) -> Option<Node<'a>> {
+        self.children()
+            .find(|node| node.name == name || (!name.contains('@') && node.unit_name() == name))
+    }
+
+    fn find_descendant(self, path: &str) -> Option<Node<'a>> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self, |node, component| node.child(component))
+    }
+
+    /// What the children inherit.
+    fn inherited(&self) -> Inherited {
+        Inherited {
+            cells: self.cells(),
+            interrupt_parent: self.interrupt_parent_phandle(),
+        }
+    }
+
+    /// The cells of the `reg` of the children.
+    pub fn cells(&self) -> Cells {
+        let cells = |name, default| {
+            self.property(name)
+                .and_then(|property| property.as_u32())
+                .unwrap_or(default)
+        };
+        Cells {
+            address: cells("#address-cells", Cells::DEFAULT.address),
+            size: cells("#size-cells", Cells::DEFAULT.size),
+        }
+    }
+
+    /// The ranges in the cells of the parent.
+    pub fn reg(&self) -> Option<Reg<'a>> {
+        Some(Reg {
+            value: self.property("reg")?.value,
+            cells: self.parent.cells,
+        })
+    }
+
+    pub fn compatible(&self) -> Option<StrList<'a>> {
+        Some(self.property("compatible")?.as_str_list())
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible()
+            .is_some_and(|mut list| list.any(|s| s == compatible))
+    }
+
+    /// No `status`, or `okay`.
+    pub fn is_enabled(&self) -> bool {
+        self.property("status")
+            .and_then(|property| property.as_str())
+            .is_none_or(|status| status == "okay" || status == "ok")
+    }
+
+    pub fn device_type(&self) -> Option<&'a str> {
+        self.property("device_type")?.as_str()
+    }
+
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle")
+            .or_else(|| self.property("linux,phandle"))?
+            .as_u32()
+    }
+
+    fn interrupt_parent_phandle(&self) -> Option<u32> {
+        self.property("interrupt-parent")
+            .and_then(|property| property.as_u32())
+            .or(self.parent.interrupt_parent)
+    }
+
+    /// The controller the `interrupts` of the node go to: its own
+    /// `interrupt-parent`, or the nearest one of the ancestors.
+    pub fn interrupt_parent(&self) -> Option<Node<'a>> {
+        self.fdt.find_phandle(self.interrupt_parent_phandle()?)
+    }
+
+    /// `#interrupt-cells` of an interrupt controller.
+    pub fn interrupt_cells(&self) -> Option<u32> {
+        self.property("#interrupt-cells")?.as_u32()
+    }
+}
+
+pub struct Properties<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = Property<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.fdt.token(self.offset).ok()? {
+                (Token::Nop, next) => self.offset = next,
+                (Token::Prop(property), next) => {
+                    self.offset = next;
+                    return Some(property);
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+pub struct Children<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    inherited: Inherited,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.fdt.token(self.offset).ok()? {
+                (Token::Nop | Token::Prop(_), next) => self.offset = next,
+                (Token::BeginNode(_), _) => {
+                    let node = self.fdt.node_at(self.offset, self.inherited);
+                    self.offset = self.fdt.skip_node(self.offset);
+                    return node;
+                }
+                (Token::EndNode | Token::End, _) => return None,
+            }
+        }
+    }
+}
+
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// What the nodes at each depth inherit.
+    inherited: [Inherited; MAX_DEPTH + 1],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (token, next) = self.fdt.token(self.offset).ok()?;
+            match token {
+                Token::BeginNode(_) => {
+                    let node = self.fdt.node_at(self.offset, self.inherited[self.depth])?;
+                    self.depth += 1;
+                    self.inherited[self.depth] = node.inherited();
+                    self.offset = next;
+                    return Some(node);
+                }
+                Token::EndNode => self.depth = self.depth.checked_sub(1)?,
+                Token::Prop(_) | Token::Nop => {}
+                Token::End => return None,
+            }
+            self.offset = next;
+        }
+    }
+}
+
+pub struct MemoryReservations<'a> {
+    data: &'a [u8],
+}
+
+impl Iterator for MemoryReservations<'_> {
+    type Item = MemoryReservation;
+
+    /// Ends at the zero entry.
+    fn next(&mut self) -> Option<Self::Item> {
+        let reservation = MemoryReservation {
+            address: read_u64(self.data, 0)?,
+            size: read_u64(self.data, 8)?,
+        };
+        if reservation.address == 0 && reservation.size == 0 {
+            return None;
+        }
+        self.data = &self.data[RESERVATION_SIZE..];
+        Some(reservation)
+    }
+}
+
+/// The `/chosen` node.
+#[derive(Debug, Clone, Copy)]
+pub struct Chosen<'a> {
+    pub node: Node<'a>,
+}
+
+impl<'a> Chosen<'a> {
+    pub fn bootargs(&self) -> Option<&'a str> {
+        self.node.property("bootargs")?.as_str()
+    }
+
+    /// The path or the alias of the console, without the options after
+    /// `:`.
+    pub fn stdout_path(&self) -> Option<&'a str> {
+        let path = self.node.property("stdout-path")?.as_str()?;
+        path.split(':').next()
+    }
+
+    /// The initrd the previous stage has loaded, `[start, end)`.
+    pub fn initrd(&self) -> Option<Region> {
+        let start = self.node.property("linux,initrd-start")?.as_u64()?;
+        let end = self.node.property("linux,initrd-end")?.as_u64()?;
+        Some(Region {
+            address: start,
+            size: end.checked_sub(start)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cells;
+    use crate::Fdt;
+    use crate::FdtError;
+    use crate::MemoryReservation;
+    use crate::Region;
+    use crate::FDT_MAGIC;
+
+    /// Assembles a blob the way `dtc` lays it out.
+    #[derive(Default)]
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+        reservations: Vec<(u64, u64)>,
+    }
+
+    impl Builder {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            while !self.structure.len().is_multiple_of(4) {
+                self.structure.push(0);
+            }
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(1);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(2)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(3);
+            self.structure
+                .extend_from_slice(&(value.len() as u32).to_be_bytes());
+            self.structure.extend_from_slice(&name_offset.to_be_bytes());
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn prop_u32(&mut self, name: &str, values: &[u32]) -> &mut Self {
+            let value: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        fn prop_str(&mut self, name: &str, value: &str) -> &mut Self {
+            self.prop(name, format!("{value}\0").as_bytes())
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.token(9);
+            let reservations_offset = 40;
+            let struct_offset = reservations_offset + (self.reservations.len() + 1) * 16;
+            let strings_offset = struct_offset + self.structure.len();
+            let total_size = strings_offset + self.strings.len();
+
+            let mut blob = Vec::new();
+            for field in [
+                FDT_MAGIC,
+                total_size as u32,
+                struct_offset as u32,
+                strings_offset as u32,
+                reservations_offset as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ] {
+                blob.extend_from_slice(&field.to_be_bytes());
+            }
+            for &(address, size) in self.reservations.iter().chain(&[(0, 0)]) {
+                blob.extend_from_slice(&address.to_be_bytes());
+                blob.extend_from_slice(&size.to_be_bytes());
+            }
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    /// A QEMU `virt`-like tree.
+    fn test_blob() -> Vec<u8> {
+        let mut b = Builder {
+            reservations: vec![(0x4000_0000, 0x1000)],
+            ..Default::default()
+        };
+        b.begin("")
+            .prop_u32("#address-cells", &[2])
+            .prop_u32("#size-cells", &[2])
+            .prop_u32("interrupt-parent", &[1])
+            .prop_str("compatible", "linux,dummy-virt");
+        b.begin("aliases")
+            .prop_str("serial0", "/pl011@9000000")
+            .end();
+        b.begin("chosen")
+            .prop_str("bootargs", "log_level=debug")
+            .prop_str("stdout-path", "serial0:115200n8")
+            .prop_u32("linux,initrd-start", &[0x4800_0000])
+            .prop_u32("linux,initrd-end", &[0x4810_0000])
+            .end();
+        b.begin("memory@40000000")
+            .prop_str("device_type", "memory")
+            .prop_u32(
+                "reg",
+                &[0, 0x4000_0000, 0, 0x2000_0000, 1, 0, 0, 0x1000_0000],
+            )
+            .end();
+        b.begin("cpus")
+            .prop_u32("#address-cells", &[1])
+            .prop_u32("#size-cells", &[0]);
+        for (name, id, status) in [("cpu@0", 0, "okay"), ("cpu@1", 1, "disabled")] {
+            b.begin(name)
+                .prop_str("device_type", "cpu")
+                .prop_u32("reg", &[id])
+                .prop_str("status", status)
+                .end();
+        }
+        b.end();
+        b.begin("intc@8000000")
+            .prop("compatible", b"arm,gic-v3\0arm,gic-400\0")
+            .prop_u32("phandle", &[1])
+            .prop_u32("#interrupt-cells", &[3])
+            .prop("interrupt-controller", &[])
+            .end();
+        b.begin("pl011@9000000")
+            .prop_str("compatible", "arm,pl011")
+            .prop_u32("reg", &[0, 0x900_0000, 0, 0x1000])
+            .prop_u32("interrupts", &[0, 1, 4])
+            .end();
+        b.begin("soc")
+            .prop_u32("#address-cells", &[1])
+            .prop_u32("#size-cells", &[1])
+            .prop_u32("interrupt-parent", &[2]);
+        b.begin("uart@1000")
+            .prop_str("compatible", "ns16550a")
+            .prop_u32("reg", &[0x1000, 0x100])
+            .prop_str("status", "disabled")
+            .end();
+        b.begin("intc").prop_u32("phandle", &[2]).end();
+        b.end();
+        b.end();
+        b.finish()
+    }
+
+    #[test]
+    fn header() {
+        let blob = test_blob();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(fdt.header().total_size as usize, blob.len());
+        assert_eq!(fdt.header().version, 17);
+        assert_eq!(
+            fdt.memory_reservations().collect::<Vec<_>>(),
+            [MemoryReservation {
+                address: 0x4000_0000,
+                size: 0x1000
+            }]
+        );
+
+        let mut bad = blob.clone();
+        bad[0] = 0;
+        assert_eq!(Fdt::new(&bad).unwrap_err(), FdtError::BadMagic);
+        assert_eq!(
+            Fdt::new(&blob[..blob.len() - 1]).unwrap_err(),
+            FdtError::Truncated
+        );
+        let mut bad = blob.clone();
+        bad[27] = 18;
+        assert_eq!(
+            Fdt::new(&bad).unwrap_err(),
+            FdtError::UnsupportedVersion(17)
+        );
+    }
+
+    #[test]
+    fn structure_errors() {
+        let unbalanced = Builder::default().begin("").finish();
+        assert!(matches!(Fdt::new(&unbalanced), Err(FdtError::BadToken(_))));
+
+        let two_roots = Builder::default().begin("").end().begin("").end().finish();
+        assert!(matches!(Fdt::new(&two_roots), Err(FdtError::BadToken(_))));
+
+        let mut deep = Builder::default();
+        for _ in 0..17 {
+            deep.begin("n");
+        }
+        for _ in 0..17 {
+            deep.end();
+        }
+        assert_eq!(Fdt::new(&deep.finish()).unwrap_err(), FdtError::TooDeep);
+    }
+
+    #[test]
+    fn nodes_and_properties() {
+        let blob = test_blob();
+        let fdt = Fdt::new(&blob).unwrap();
+        let root = fdt.root();
+        assert_eq!(root.name(), "");
+        assert!(root.is_compatible("linux,dummy-virt"));
+        assert_eq!(
+            root.cells(),
+            Cells {
+                address: 2,
+                size: 2
+            }
+        );
+
+        let names: Vec<_> = fdt.nodes().map(|node| node.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "",
+                "aliases",
+                "chosen",
+                "memory@40000000",
+                "cpus",
+                "cpu@0",
+                "cpu@1",
+                "intc@8000000",
+                "pl011@9000000",
+                "soc",
+                "uart@1000",
+                "intc"
+            ]
+        );
+        assert_eq!(root.children().count(), 7);
+
+        let gic = fdt.find_node("/intc").unwrap();
+        assert_eq!(gic.name(), "intc@8000000");
+        assert_eq!(gic.unit_address(), Some("8000000"));
+        assert_eq!(
+            gic.compatible().unwrap().collect::<Vec<_>>(),
+            ["arm,gic-v3", "arm,gic-400"]
+        );
+        assert_eq!(gic.interrupt_cells(), Some(3));
+        assert_eq!(gic.property("interrupt-controller").unwrap().value, b"");
+        assert!(fdt.find_node("/cpus/cpu@2").is_none());
+        assert_eq!(fdt.find_node("/soc/uart").unwrap().name(), "uart@1000");
+    }
+
+    #[test]
+    fn reg_and_cells() {
+        let blob = test_blob();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(
+            fdt.memory().collect::<Vec<_>>(),
+            [
+                Region {
+                    address: 0x4000_0000,
+                    size: 0x2000_0000
+                },
+                Region {
+                    address: 0x1_0000_0000,
+                    size: 0x1000_0000
+                }
+            ]
+        );
+
+        let uart = fdt.find_node("/soc/uart@1000").unwrap();
+        assert_eq!(
+            uart.reg().unwrap().collect::<Vec<_>>(),
+            [Region {
+                address: 0x1000,
+                size: 0x100
+            }]
+        );
+
+        let cpus: Vec<_> = fdt
+            .cpus()
+            .map(|cpu| cpu.reg().unwrap().next().unwrap())
+            .collect();
+        assert_eq!(
+            cpus,
+            [Region {
+                address: 0,
+                size: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn chosen_and_aliases() {
+        let blob = test_blob();
+        let fdt = Fdt::new(&blob).unwrap();
+        let chosen = fdt.chosen().unwrap();
+        assert_eq!(chosen.bootargs(), Some("log_level=debug"));
+        assert_eq!(chosen.stdout_path(), Some("serial0"));
+        assert_eq!(
+            chosen.initrd(),
+            Some(Region {
+                address: 0x4800_0000,
+                size: 0x10_0000
+            })
+        );
+
+        let console = fdt.find_node(chosen.stdout_path().unwrap()).unwrap();
+        assert_eq!(console.name(), "pl011@9000000");
+        assert_eq!(
+            fdt.compatible("arm,pl011").next().unwrap().name(),
+            console.name()
+        );
+        assert!(fdt.compatible("ns16550a").next().is_none());
+    }
+
+    #[test]
+    fn interrupt_parents() {
+        let blob = test_blob();
+        let fdt = Fdt::new(&blob).unwrap();
+        let pl011 = fdt.find_node("/pl011").unwrap();
+        assert_eq!(pl011.interrupt_parent().unwrap().name(), "intc@8000000");
+        assert_eq!(
+            pl011
+                .property("interrupts")
+                .unwrap()
+                .as_u32_list()
+                .collect::<Vec<_>>(),
+            [0, 1, 4]
+        );
+
+        let uart = fdt.find_node("/soc/uart").unwrap();
+        assert_eq!(uart.interrupt_parent().unwrap().name(), "intc");
+        let from_nodes = fdt.nodes().find(|node| node.name() == "uart@1000").unwrap();
+        assert_eq!(from_nodes.interrupt_parent().unwrap().name(), "intc");
+    }
+}
@@ -0,0 +1,505 @@
+//! The stub side of the GDB remote serial protocol, enough to debug a
+//! single-threaded program stopped in an exception handler: reading and
+//! writing the registers and the memory, the software breakpoints, and
+//! the single-step. Follows "Remote Protocol" in the
+//! [GDB manual](https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html).
+//!
+//! The exception handler hands the registers of the interrupted code to
+//! [`GdbStub::stopped`], that talks to GDB until it says to continue or to
+//! step, and tells how to resume. Setting up the single-step is up to the
+//! caller, as is returning from the exception with the registers.
+//!
+//! The packets are `$<data>#<checksum>`, the checksum is the sum of the
+//! data bytes modulo 256 in two hex digits. Each packet is acknowledged
+//! with `+`, or `-` to ask to send it again, unless GDB has asked for
+//! `QStartNoAckMode`. The stub speaks only when asked, except for the stop
+//! reply after resuming.
+//!
+//! Example:
+//! ```ignore
+//! let mut registers = gdb_stub::X86_64Registers { rip: frame.rip, ..Default::default() };
+//! match stub.stopped(gdb_stub::SIGTRAP, &mut registers, &mut memory) {
+//!     gdb_stub::Resume::Step => frame.rflags |= TRAP_FLAG,
+//!     _ => frame.rflags &= !TRAP_FLAG,
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod registers;
+mod tests;
+
+pub use registers::Aarch64Registers;
+pub use registers::X86_64Registers;
+
+/// The largest packet either way, GDB is told in `qSupported`.
+pub const PACKET_SIZE: usize = 0x400;
+pub const MAX_BREAKPOINTS: usize = 32;
+
+/// The signals in the stop replies, the numbering is GDB's own.
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGFPE: u8 = 8;
+pub const SIGBUS: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+
+/// `EFAULT` and `ENOMEM` in the error replies.
+const ERROR_FAULT: u8 = 0x0e;
+const ERROR_NO_MEMORY: u8 = 0x0c;
+const ERROR_INVALID: u8 = 0x16;
+
+/// The serial line to GDB.
+pub trait Connection {
+    /// Waits for a byte.
+    fn read_byte(&mut self) -> u8;
+    fn write_byte(&mut self, byte: u8);
+}
+
+/// The registers of an architecture, numbered as GDB does, see the
+/// implementations.
+pub trait Registers {
+    /// How many registers the `g` packet has.
+    const COUNT: usize;
+    /// The instruction the software breakpoints are.
+    const BREAKPOINT: &'static [u8];
+
+    /// The size of the register in bytes, `None` if there is no such.
+    fn size(register: usize) -> Option<usize>;
+    fn read(&self, register: usize) -> u64;
+    fn write(&mut self, register: usize, value: u64);
+    fn pc(&self) -> u64;
+    fn set_pc(&mut self, pc: u64);
+}
+
+/// The address that cannot be read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFault {
+    pub addr: u64,
+}
+
+/// The memory of the program. Writing the code must make the processor
+/// see the new instructions.
+pub trait Memory {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), MemoryFault>;
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), MemoryFault>;
+}
+
+/// How the program goes on once GDB has let go of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    /// Stop after one instruction with [`SIGTRAP`].
+    Step,
+    /// GDB has detached, the breakpoints are removed.
+    Detach,
+    /// GDB has asked to kill the program, the breakpoints are removed.
+    Kill,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    addr: u64,
+    /// The bytes the breakpoint instruction has replaced.
+    saved: [u8; 4],
+}
+
+/// The software breakpoints GDB has inserted.
+struct Breakpoints {
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+}
+
+impl Breakpoints {
+    /// Saves the instruction, and writes the breakpoint over it. The error
+    /// is the one for the reply.
+    fn insert<R: Registers>(&mut self, addr: u64, memory: &mut impl Memory) -> Result<(), u8> {
+        // GDB may insert the same one twice, the second time would save
+        // the breakpoint instruction.
+        if self.breakpoints.iter().flatten().any(|b| b.addr == addr) {
+            return Ok(());
+        }
+        let Some(slot) = self.breakpoints.iter_mut().find(|b| b.is_none()) else {
+            return Err(ERROR_NO_MEMORY);
+        };
+        let mut saved = [0; 4];
+        memory
+            .read(addr, &mut saved[..R::BREAKPOINT.len()])
+            .map_err(|_| ERROR_FAULT)?;
+        memory.write(addr, R::BREAKPOINT).map_err(|_| ERROR_FAULT)?;
+        *slot = Some(Breakpoint { addr, saved });
+        Ok(())
+    }
+
+    /// Puts the instruction back, nothing to do if there is no breakpoint.
+    fn remove<R: Registers>(&mut self, addr: u64, memory: &mut impl Memory) -> Result<(), u8> {
+        let Some(slot) = self
+            .breakpoints
+            .iter_mut()
+            .find(|b| b.is_some_and(|b| b.addr == addr))
+        else {
+            return Ok(());
+        };
+        if let Some(breakpoint) = slot.take() {
+            memory
+                .write(addr, &breakpoint.saved[..R::BREAKPOINT.len()])
+                .map_err(|_| ERROR_FAULT)?;
+        }
+        Ok(())
+    }
+
+    fn remove_all<R: Registers>(&mut self, memory: &mut impl Memory) {
+        for breakpoint in self.breakpoints.iter_mut().filter_map(Option::take) {
+            memory
+                .write(breakpoint.addr, &breakpoint.saved[..R::BREAKPOINT.len()])
+                .ok();
+        }
+    }
+}
+
+/// The data of a packet to send.
+struct Reply {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    fn push(&mut self, bytes: &[u8]) {
+        let len = core::cmp::min(bytes.len(), PACKET_SIZE - self.len);
+        self.data[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+    }
+
+    fn push_hex(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.push(&hex_byte(byte));
+        }
+    }
+
+    /// `Exx`
+    fn push_error(&mut self, error: u8) {
+        self.push(b"E");
+        self.push_hex(&[error]);
+    }
+}
+
+fn hex_byte(byte: u8) -> [u8; 2] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    [DIGITS[byte as usize >> 4], DIGITS[byte as usize & 0xf]]
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// A number in hex digits, as the addresses and the lengths are.
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, &digit| {
+        Some(value << 4 | hex_digit(digit)? as u64)
+    })
+}
+
+/// Bytes in hex digits into `buf`, `None` if a digit is not hex, or `buf`
+/// is too small.
+fn parse_hex_bytes<'a>(digits: &[u8], buf: &'a mut [u8]) -> Option<&'a [u8]> {
+    let len = digits.len() / 2;
+    let buf = buf.get_mut(..len)?;
+    for (byte, pair) in buf.iter_mut().zip(digits.chunks_exact(2)) {
+        *byte = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+    }
+    Some(buf)
+}
+
+/// A register value as GDB sends it, the target byte order.
+fn parse_register(digits: &[u8], size: usize) -> Option<u64> {
+    let mut bytes = [0; 8];
+    if digits.len() < size * 2 {
+        return None;
+    }
+    parse_hex_bytes(&digits[..size * 2], &mut bytes[..size])?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..at], &bytes[at + 1..]))
+}
+
+/// `addr,length`
+fn parse_range(args: &[u8]) -> Option<(u64, usize)> {
+    let (addr, len) = split(args, b',')?;
+    Some((parse_hex(addr)?, parse_hex(len)? as usize))
+}
+
+pub struct GdbStub<C: Connection> {
+    connection: C,
+    no_ack: bool,
+    /// GDB waits for a stop reply.
+    running: bool,
+    signal: u8,
+    breakpoints: Breakpoints,
+    packet: [u8; PACKET_SIZE],
+    reply: Reply,
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub const fn new(connection: C) -> Self {
+        Self {
+            connection,
+            no_ack: false,
+            running: false,
+            signal: SIGTRAP,
+            breakpoints: Breakpoints {
+                breakpoints: [None; MAX_BREAKPOINTS],
+            },
+            packet: [0; PACKET_SIZE],
+            reply: Reply {
+                data: [0; PACKET_SIZE],
+                len: 0,
+            },
+        }
+    }
+
+    /// The program has stopped with the signal, serves GDB until it lets
+    /// the program go on. GDB may have changed the registers.
+    pub fn stopped<R: Registers>(
+        &mut self,
+        signal: u8,
+        registers: &mut R,
+        memory: &mut impl Memory,
+    ) -> Resume {
+        self.signal = signal;
+        if self.running {
+            self.running = false;
+            self.reply.len = 0;
+            self.reply.push(b"S");
+            self.reply.push_hex(&[signal]);
+            self.send();
+        }
+
+        loop {
+            let len = self.receive();
+            self.reply.len = 0;
+            match self.handle(len, registers, memory) {
+                // There is no reply to these.
+                Some(resume @ (Resume::Continue | Resume::Step)) => {
+                    self.running = true;
+                    return resume;
+                }
+                Some(Resume::Kill) => return Resume::Kill,
+                Some(Resume::Detach) => {
+                    self.send();
+                    return Resume::Detach;
+                }
+                None => self.send(),
+            }
+            // The reply to this one is the last acknowledged.
+            if &self.packet[..len] == b"QStartNoAckMode" {
+                self.no_ack = true;
+            }
+        }
+    }
+
+    /// Whether the breakpoint instruction at the address is one GDB has
+    /// inserted, and not one compiled in.
+    pub fn is_breakpoint(&self, addr: u64) -> bool {
+        self.breakpoints
+            .breakpoints
+            .iter()
+            .flatten()
+            .any(|b| b.addr == addr)
+    }
+
+    /// Serves the packet in `self.packet`, the reply goes to `self.reply`.
+    fn handle<R: Registers>(
+        &mut self,
+        len: usize,
+        registers: &mut R,
+        memory: &mut impl Memory,
+    ) -> Option<Resume> {
+        let packet = &self.packet[..len];
+        let reply = &mut self.reply;
+        let (&command, args) = packet.split_first()?;
+        match command {
+            b'?' => {
+                reply.push(b"S");
+                reply.push_hex(&[self.signal]);
+            }
+            b'g' => {
+                for register in 0..R::COUNT {
+                    let size = R::size(register).unwrap_or_default();
+                    reply.push_hex(&registers.read(register).to_le_bytes()[..size]);
+                }
+            }
+            b'G' => {
+                let mut digits = args;
+                for register in 0..R::COUNT {
+                    let size = R::size(register).unwrap_or_default();
+                    let Some(value) = parse_register(digits, size) else {
+                        break;
+                    };
+                    registers.write(register, value);
+                    digits = &digits[size * 2..];
+                }
+                reply.push(b"OK");
+            }
+            b'p' => match parse_hex(args).and_then(|r| Some((r, R::size(r as usize)?))) {
+                Some((register, size)) => {
+                    reply.push_hex(&registers.read(register as usize).to_le_bytes()[..size])
+                }
+                None => reply.push_error(ERROR_INVALID),
+            },
+            b'P' => {
+                let written = split(args, b'=').and_then(|(register, value)| {
+                    let register = parse_hex(register)? as usize;
+                    let value = parse_register(value, R::size(register)?)?;
+                    registers.write(register, value);
+                    Some(())
+                });
+                match written {
+                    Some(()) => reply.push(b"OK"),
+                    None => reply.push_error(ERROR_INVALID),
+                }
+            }
+            b'm' => {
+                let Some((addr, len)) = parse_range(args) else {
+                    reply.push_error(ERROR_INVALID);
+                    return None;
+                };
+                let mut buf = [0; PACKET_SIZE / 2];
+                let buf = &mut buf[..core::cmp::min(len, PACKET_SIZE / 2)];
+                match memory.read(addr, buf) {
+                    Ok(()) => reply.push_hex(buf),
+                    Err(_) => reply.push_error(ERROR_FAULT),
+                }
+            }
+            b'M' => {
+                let mut buf = [0; PACKET_SIZE / 2];
+                let data = split(args, b':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let data = parse_hex_bytes(data, &mut buf)?;
+                    Some((addr, data.get(..len)?))
+                });
+                match data.map(|(addr, data)| memory.write(addr, data)) {
+                    Some(Ok(())) => reply.push(b"OK"),
+                    Some(Err(_)) => reply.push_error(ERROR_FAULT),
+                    None => reply.push_error(ERROR_INVALID),
+                }
+            }
+            b'Z' | b'z' => {
+                // Only the software breakpoints, GDB falls back to them
+                // for the empty reply to the other kinds.
+                let args = args.strip_prefix(b"0,")?;
+                let Some((addr, _kind)) = parse_range(args) else {
+                    reply.push_error(ERROR_INVALID);
+                    return None;
+                };
+                let result = if command == b'Z' {
+                    self.breakpoints.insert::<R>(addr, memory)
+                } else {
+                    self.breakpoints.remove::<R>(addr, memory)
+                };
+                match result {
+                    Ok(()) => reply.push(b"OK"),
+                    Err(error) => reply.push_error(error),
+                }
+            }
+            b'c' | b's' => {
+                // The address to resume at is optional.
+                if let Some(pc) = parse_hex(args) {
+                    registers.set_pc(pc);
+                }
+                return Some(if command == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                });
+            }
+            b'D' => {
+                self.breakpoints.remove_all::<R>(memory);
+                reply.push(b"OK");
+                return Some(Resume::Detach);
+            }
+            b'k' => {
+                self.breakpoints.remove_all::<R>(memory);
+                return Some(Resume::Kill);
+            }
+            b'q' if packet.starts_with(b"qSupported") => {
+                reply.push(b"PacketSize=");
+                reply.push_hex(&(PACKET_SIZE as u16).to_be_bytes());
+                reply.push(b";QStartNoAckMode+");
+            }
+            b'q' if packet == b"qAttached" => reply.push(b"1"),
+            b'Q' if packet == b"QStartNoAckMode" => reply.push(b"OK"),
+            // There is one thread, whichever GDB picks.
+            b'H' | b'T' => reply.push(b"OK"),
+            // The empty reply tells GDB the packet is not supported.
+            _ => {}
+        }
+        None
+    }
+
+    /// Waits for a packet with the right checksum, and acknowledges it.
+    /// The interrupt requests in between are ignored, the program is
+    /// stopped already.
+    fn receive(&mut self) -> usize {
+        loop {
+            while self.connection.read_byte() != b'$' {}
+
+            let mut len = 0;
+            let mut sum = 0u8;
+            let mut overflow = false;
+            loop {
+                let byte = self.connection.read_byte();
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                match self.packet.get_mut(len) {
+                    Some(slot) => *slot = byte,
+                    None => overflow = true,
+                }
+                len += 1;
+            }
+            let high = hex_digit(self.connection.read_byte());
+            let low = hex_digit(self.connection.read_byte());
+            let valid = !overflow && high.zip(low).is_some_and(|(h, l)| h << 4 | l == sum);
+
+            if !self.no_ack {
+                self.connection.write_byte(if valid { b'+' } else { b'-' });
+            }
+            if valid {
+                return len;
+            }
+        }
+    }
+
+    /// Sends `self.reply`, again until GDB acknowledges it.
+    fn send(&mut self) {
+        let data = &self.reply.data[..self.reply.len];
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        loop {
+            self.connection.write_byte(b'$');
+            for &byte in data {
+                self.connection.write_byte(byte);
+            }
+            self.connection.write_byte(b'#');
+            for digit in hex_byte(sum) {
+                self.connection.write_byte(digit);
+            }
+
+            if self.no_ack {
+                return;
+            }
+            loop {
+                match self.connection.read_byte() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! The registers GDB expects in the `g` packet when the stub sends no
+//! target description: the general purpose ones, the program counter, and
+//! the flags, in the order of `64bit-core.xml` and `aarch64-core.xml` in
+//! the GDB sources. GDB takes a shorter `g` reply for the full one, the
+//! floating point and the vector registers show as unavailable.
+
+use crate::Registers;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct X86_64Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub eflags: u32,
+    pub cs: u32,
+    pub ss: u32,
+    pub ds: u32,
+    pub es: u32,
+    pub fs: u32,
+    pub gs: u32,
+}
+
+impl Registers for X86_64Registers {
+    const COUNT: usize = 24;
+    /// `int3`
+    const BREAKPOINT: &'static [u8] = &[0xcc];
+
+    fn size(register: usize) -> Option<usize> {
+        match register {
+            0..=16 => Some(8),
+            17..=23 => Some(4),
+            _ => None,
+        }
+    }
+
+    fn read(&self, register: usize) -> u64 {
+        match register {
+            0 => self.rax,
+            1 => self.rbx,
+            2 => self.rcx,
+            3 => self.rdx,
+            4 => self.rsi,
+            5 => self.rdi,
+            6 => self.rbp,
+            7 => self.rsp,
+            8 => self.r8,
+            9 => self.r9,
+            10 => self.r10,
+            11 => self.r11,
+            12 => self.r12,
+            13 => self.r13,
+            14 => self.r14,
+            15 => self.r15,
+            16 => self.rip,
+            17 => self.eflags as u64,
+            18 => self.cs as u64,
+            19 => self.ss as u64,
+            20 => self.ds as u64,
+            21 => self.es as u64,
+            22 => self.fs as u64,
+            23 => self.gs as u64,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, register: usize, value: u64) {
+        match register {
+            0 => self.rax = value,
+            1 => self.rbx = value,
+            2 => self.rcx = value,
+            3 => self.rdx = value,
+            4 => self.rsi = value,
+            5 => self.rdi = value,
+            6 => self.rbp = value,
+            7 => self.rsp = value,
+            8 => self.r8 = value,
+            9 => self.r9 = value,
+            10 => self.r10 = value,
+            11 => self.r11 = value,
+            12 => self.r12 = value,
+            13 => self.r13 = value,
+            14 => self.r14 = value,
+            15 => self.r15 = value,
+            16 => self.rip = value,
+            17 => self.eflags = value as u32,
+            18 => self.cs = value as u32,
+            19 => self.ss = value as u32,
+            20 => self.ds = value as u32,
+            21 => self.es = value as u32,
+            22 => self.fs = value as u32,
+            23 => self.gs = value as u32,
+            _ => {}
+        }
+    }
+
+    fn pc(&self) -> u64 {
+        self.rip
+    }
+
+    fn set_pc(&mut self, pc: u64) {
+        self.rip = pc;
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Aarch64Registers {
+    /// `x29` is the frame pointer, `x30` is the link register.
+    pub x: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub cpsr: u32,
+}
+
+impl Registers for Aarch64Registers {
+    const COUNT: usize = 34;
+    /// `brk #0`, the one GDB writes, too.
+    const BREAKPOINT: &'static [u8] = &[0x00, 0x00, 0x20, 0xd4];
+
+    fn size(register: usize) -> Option<usize> {
+        match register {
+            0..=32 => Some(8),
+            33 => Some(4),
+            _ => None,
+        }
+    }
+
+    fn read(&self, register: usize) -> u64 {
+        match register {
+            0..=30 => self.x[register],
+            31 => self.sp,
+            32 => self.pc,
+            33 => self.cpsr as u64,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, register: usize, value: u64) {
+        match register {
+            0..=30 => self.x[register] = value,
+            31 => self.sp = value,
+            32 => self.pc = value,
+            33 => self.cpsr = value as u32,
+            _ => {}
+        }
+    }
+
+    fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    fn set_pc(&mut self, pc: u64) {
+        self.pc = pc;
+    }
+}
@@ -0,0 +1,259 @@
+#![cfg(test)]
+
+use super::*;
+use std::collections::VecDeque;
+
+/// What GDB sends, and what the stub has sent back.
+#[derive(Default)]
+struct FakeGdb {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl Connection for FakeGdb {
+    fn read_byte(&mut self) -> u8 {
+        self.input.pop_front().expect("the stub waits for more")
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+}
+
+/// The memory at `base`, the rest faults.
+struct FakeMemory {
+    base: u64,
+    bytes: Vec<u8>,
+}
+
+impl FakeMemory {
+    fn range(&self, addr: u64, len: usize) -> Result<std::ops::Range<usize>, MemoryFault> {
+        let start = addr.checked_sub(self.base).ok_or(MemoryFault { addr })? as usize;
+        if start + len > self.bytes.len() {
+            return Err(MemoryFault { addr });
+        }
+        Ok(start..start + len)
+    }
+}
+
+impl Memory for FakeMemory {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), MemoryFault> {
+        let range = self.range(addr, buf.len())?;
+        buf.copy_from_slice(&self.bytes[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), MemoryFault> {
+        let range = self.range(addr, data.len())?;
+        self.bytes[range].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+fn packet(data: &str) -> String {
+    let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${data}#{sum:02x}")
+}
+
+/// A stub with the packets queued, acknowledging each reply.
+fn stub(packets: &[&str]) -> GdbStub<FakeGdb> {
+    let mut gdb = FakeGdb::default();
+    for data in packets {
+        gdb.input.extend(packet(data).bytes());
+        gdb.input.push_back(b'+');
+    }
+    GdbStub::new(gdb)
+}
+
+/// The replies in the order sent, the acknowledgements left out.
+fn replies(stub: &GdbStub<FakeGdb>) -> Vec<String> {
+    let output = String::from_utf8(stub.connection.output.clone()).unwrap();
+    output
+        .split('$')
+        .skip(1)
+        .map(|packet| {
+            let (data, sum) = packet.split_once('#').unwrap();
+            assert_eq!(
+                u8::from_str_radix(&sum[..2], 16).unwrap(),
+                data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b))
+            );
+            data.to_string()
+        })
+        .collect()
+}
+
+fn memory() -> FakeMemory {
+    FakeMemory {
+        base: 0x1000,
+        bytes: (0..0x100).map(|b| b as u8).collect(),
+    }
+}
+
+#[test]
+fn registers() {
+    let mut registers = X86_64Registers {
+        rax: 0x1122_3344_5566_7788,
+        rip: 0xfff0,
+        eflags: 0x202,
+        cs: 0x38,
+        ..Default::default()
+    };
+    let mut stub = stub(&[
+        "qSupported:swbreak+",
+        "?",
+        "g",
+        "p10",
+        "P0=0100000000000000",
+        "p0",
+        "c",
+    ]);
+    assert_eq!(
+        stub.stopped(SIGTRAP, &mut registers, &mut memory()),
+        Resume::Continue
+    );
+
+    let sent = replies(&stub);
+    assert_eq!(sent[0], "PacketSize=0400;QStartNoAckMode+");
+    assert_eq!(sent[1], "S05");
+    let g = &sent[2];
+    assert_eq!(g.len(), (17 * 8 + 7 * 4) * 2);
+    assert!(g.starts_with("8877665544332211"));
+    assert_eq!(&g[16 * 16..17 * 16], "f0ff000000000000");
+    assert_eq!(&g[17 * 16..17 * 16 + 16], "0202000038000000");
+    assert_eq!(sent[3], "f0ff000000000000");
+    assert_eq!(sent[4], "OK");
+    assert_eq!(sent[5], "0100000000000000");
+    assert_eq!(registers.rax, 1);
+    // Each packet is acknowledged, the one to continue has no reply.
+    let output = &stub.connection.output;
+    assert_eq!(output.windows(2).filter(|w| w == b"+$").count(), 6);
+    assert_eq!(output.last(), Some(&b'+'));
+
+    let mut registers = Aarch64Registers::default();
+    let mut g = String::new();
+    for i in 0..33u64 {
+        g.push_str(&hex(&(i << 32 | i).to_le_bytes()));
+    }
+    g.push_str("c5030000");
+    let mut stub = self::stub(&[&format!("G{g}"), "g", "s10"]);
+    assert_eq!(
+        stub.stopped(SIGTRAP, &mut registers, &mut memory()),
+        Resume::Step
+    );
+    assert_eq!(registers.x[30], 30 << 32 | 30);
+    assert_eq!(registers.sp, 31 << 32 | 31);
+    assert_eq!(registers.cpsr, 0x3c5);
+    // The address to step at.
+    assert_eq!(registers.pc, 0x10);
+    assert_eq!(replies(&stub), ["OK", &g]);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[test]
+fn memory_access() {
+    let mut memory = memory();
+    let mut stub = stub(&[
+        "m1010,4",
+        "M1020,2:abcd",
+        "m101f,4",
+        "mff0,4",
+        "M10fe,4:00000000",
+        "Mzz,1:00",
+        "D",
+    ]);
+    assert_eq!(
+        stub.stopped(SIGSEGV, &mut X86_64Registers::default(), &mut memory),
+        Resume::Detach
+    );
+    assert_eq!(
+        replies(&stub),
+        ["10111213", "OK", "1fabcd22", "E0e", "E0e", "E16", "OK"]
+    );
+}
+
+#[test]
+fn breakpoints() {
+    let mut memory = memory();
+    let mut stub = stub(&[
+        "Z0,1010,4",
+        "Z0,1010,4",
+        "Z0,1020,4",
+        "m1010,4",
+        "z0,1020,4",
+        "Z1,1030,4",
+        "Z0,2000,4",
+        "c",
+    ]);
+    let mut registers = Aarch64Registers::default();
+    assert_eq!(
+        stub.stopped(SIGTRAP, &mut registers, &mut memory),
+        Resume::Continue
+    );
+    assert_eq!(
+        replies(&stub),
+        ["OK", "OK", "OK", "000020d4", "OK", "", "E0e"]
+    );
+    assert!(stub.is_breakpoint(0x1010));
+    assert!(!stub.is_breakpoint(0x1020));
+    assert_eq!(memory.bytes[0x20..0x24], [0x20, 0x21, 0x22, 0x23]);
+
+    // The next stop is reported without asking, and killing takes the
+    // breakpoints out.
+    stub.connection.output.clear();
+    stub.connection.input.extend(b"+");
+    stub.connection.input.extend(packet("k").bytes());
+    assert_eq!(
+        stub.stopped(SIGTRAP, &mut registers, &mut memory),
+        Resume::Kill
+    );
+    assert_eq!(replies(&stub), ["S05"]);
+    assert!(!stub.is_breakpoint(0x1010));
+    assert_eq!(memory.bytes[0x10..0x14], [0x10, 0x11, 0x12, 0x13]);
+
+    // No more than there is room for.
+    let mut packets: Vec<String> = (0..=MAX_BREAKPOINTS)
+        .map(|i| format!("Z0,{:x},1", 0x1000 + i))
+        .collect();
+    packets.push("c".into());
+    let packets: Vec<&str> = packets.iter().map(String::as_str).collect();
+    let mut stub = self::stub(&packets);
+    stub.stopped(SIGTRAP, &mut X86_64Registers::default(), &mut memory);
+    assert_eq!(replies(&stub).last().unwrap(), "E0c");
+    assert_eq!(memory.bytes[0], 0xcc);
+}
+
+#[test]
+fn framing() {
+    let mut gdb = FakeGdb::default();
+    // An interrupt request, a packet with a bad checksum, sent again, the
+    // reply asked for again, then no acknowledgements.
+    gdb.input.push_back(0x03);
+    gdb.input.extend(b"$?#00");
+    gdb.input.extend(packet("?").bytes());
+    gdb.input.extend(b"-+");
+    gdb.input.extend(packet("QStartNoAckMode").bytes());
+    gdb.input.push_back(b'+');
+    gdb.input.extend(packet("qAttached").bytes());
+    gdb.input.extend(packet("vMustReplyEmpty").bytes());
+    gdb.input.extend(packet("c").bytes());
+    let mut stub = GdbStub::new(gdb);
+    assert_eq!(
+        stub.stopped(SIGINT, &mut X86_64Registers::default(), &mut memory()),
+        Resume::Continue
+    );
+
+    let output = String::from_utf8(stub.connection.output.clone()).unwrap();
+    assert_eq!(
+        output,
+        format!(
+            "-+{s}{s}+{ok}{attached}{empty}",
+            s = packet("S02"),
+            ok = packet("OK"),
+            attached = packet("1"),
+            empty = packet(""),
+        )
+    );
+}
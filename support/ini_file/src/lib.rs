@@ -11,11 +11,20 @@
 //! brick_density = 1000e10
 //!```
 //!
-//! The users code calls `[Parser::parse()]` until it either returns an error or
+//! The users code calls [`Parser::parse()`] until it either returns an error or
 //! indicates that the parser is at the end of the input returning `Ok(None)`.
 //!
 //! The semantics checks are done by the calling code.
 //!
+//! The values are returned as they are in the input, backslashes and all, and
+//! a quoted value ends at the next quote. A quote not closed on its line is an
+//! error.
+//!
+//! The parser made with [`Parser::with_flags()`] also takes a key by itself,
+//! as on a command line, and returns it with an empty value. Its quoted values
+//! may have `\"` and `\\` in them, and [`unescape()`] copies such a value out
+//! with the escape sequences undone.
+//!
 //! Example:
 //! ```ignore
 //! let mut parser = corg_ini::Parser::new(bytes);
@@ -62,6 +71,7 @@ where
     fn null(self, index: usize) -> bool;
     fn newline(self, index: usize) -> bool;
     fn quote(self, index: usize) -> bool;
+    fn backslash(self, index: usize) -> bool;
 }
 
 impl<'a> Input<&'a [u8]> for &'a [u8] {
@@ -116,6 +126,10 @@ impl<'a> Input<&'a [u8]> for &'a [u8] {
     fn quote(self, index: usize) -> bool {
         self[index] == b'"'
     }
+
+    fn backslash(self, index: usize) -> bool {
+        self[index] == b'\\'
+    }
 }
 
 // TODO: slow and broken for non-ASCII
@@ -171,6 +185,10 @@ impl<'a> Input<&'a str> for &'a str {
     fn quote(self, index: usize) -> bool {
         self.chars().nth(index).unwrap() == '"'
     }
+
+    fn backslash(self, index: usize) -> bool {
+        self.chars().nth(index).unwrap() == '\\'
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -191,6 +209,21 @@ impl Default for Location {
 }
 
 impl Location {
+    /// The line number, from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column in the line, from 1.
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    /// The index into the input.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
     pub fn new_line(&mut self) {
         self.col = 1;
         self.line += 1;
@@ -238,6 +271,7 @@ where
     location: Location,
     input: I,
     input_len: usize,
+    flags: bool,
 }
 
 impl<I> Parser<I>
@@ -249,9 +283,24 @@ where
             location: Location::default(),
             input,
             input_len: input.count(),
+            flags: false,
+        }
+    }
+
+    /// The parser that returns a key without a value as a flag, with an
+    /// empty value, and takes the escaped quotes in the quoted values.
+    pub fn with_flags(input: I) -> Self {
+        Self {
+            flags: true,
+            ..Self::new(input)
         }
     }
 
+    /// Where the next token is looked for.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
     #[inline]
     fn parse_token(&mut self) -> Token {
         let mut tok = Token::EndOfInput(self.location);
@@ -288,12 +337,21 @@ where
                     loc.advance();
                 }
             } else if self.input.quote(loc.pos) {
-                tok = Token::Unknown(Error::UnmatchedQuote(self.location));
+                tok = Token::Unknown(Error::UnmatchedQuote(loc));
                 loc.advance();
 
                 let start_loc = loc;
                 while loc.pos < self.input_len {
-                    // TODO: escaped quotes
+                    if self.flags && self.input.backslash(loc.pos) {
+                        // The escaped character does not end the value,
+                        // `unescape` makes sense of it.
+                        loc.advance();
+                        if loc.pos >= self.input_len || self.input.newline(loc.pos) {
+                            break;
+                        }
+                        loc.advance();
+                        continue;
+                    }
                     if self.input.quote(loc.pos) {
                         tok = Token::Quoted(start_loc, loc);
                         loc.advance();
@@ -304,6 +362,7 @@ where
                     }
                     loc.advance();
                 }
+                break;
             } else if key_value_valid_input(loc.pos) {
                 let start_loc = loc;
 
@@ -335,8 +394,16 @@ where
                     return Err(Error::InvalidKeyName(start_key));
                 }
 
+                let after_key = self.location;
                 let token = self.parse_token();
                 if !matches!(token, Token::Assign(_)) {
+                    if self.flags && !matches!(token, Token::Unknown(_)) {
+                        self.location = after_key;
+                        return Ok(Some(KeyValue {
+                            key: self.input.slice(start_key.pos, end_key.pos),
+                            value: self.input.slice(end_key.pos, end_key.pos),
+                        }));
+                    }
                     return Err(Error::ExpectedAssign(self.location));
                 }
 
@@ -350,16 +417,40 @@ where
                         key: self.input.slice(start_key.pos, end_key.pos),
                         value: self.input.slice(start_value.pos, end_value.pos),
                     })),
+                    Token::Unknown(e) => Err(e),
                     _ => Err(Error::UnexpectedToken(self.location)),
                 }
             }
+            Token::Unknown(e) => Err(e),
             _ => Err(Error::UnexpectedToken(self.location)),
         }
     }
 }
 
+/// Copies the value into the buffer with `\"`, `\\`, `\n`, and `\t` undone,
+/// `None` if it does not fit.
+pub fn unescape<'a>(value: &[u8], buf: &'a mut [u8]) -> Option<&'a [u8]> {
+    let mut len = 0;
+    let mut rest = value;
+    while let Some((&byte, tail)) = rest.split_first() {
+        let (byte, tail) = match (byte, tail) {
+            (b'\\', [b'"', tail @ ..]) => (b'"', tail),
+            (b'\\', [b'\\', tail @ ..]) => (b'\\', tail),
+            (b'\\', [b'n', tail @ ..]) => (b'\n', tail),
+            (b'\\', [b't', tail @ ..]) => (b'\t', tail),
+            _ => (byte, tail),
+        };
+        *buf.get_mut(len)? = byte;
+        len += 1;
+        rest = tail;
+    }
+    Some(&buf[..len])
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::unescape;
+    use crate::Error;
     use crate::KeyValue;
     use crate::Parser;
 
@@ -447,6 +538,47 @@ mod tests {
         assert_eq!(eoi, Ok(None))
     }
 
+    #[test]
+    fn parse_flags() {
+        let input = "nosmp log_level=debug  self_test\nmem = 512M".as_bytes();
+        let mut parser = Parser::with_flags(input);
+        let expected: [(&[u8], &[u8]); 4] = [
+            (b"nosmp", b""),
+            (b"log_level", b"debug"),
+            (b"self_test", b""),
+            (b"mem", b"512M"),
+        ];
+        for (key, value) in expected {
+            assert_eq!(parser.parse(), Ok(Some(KeyValue { key, value })));
+        }
+
+        let eoi = parser.parse();
+        assert_eq!(eoi, Ok(None));
+
+        let mut parser = Parser::new(input);
+        assert!(matches!(parser.parse(), Err(Error::ExpectedAssign(_))));
+    }
+
+    #[test]
+    fn location() {
+        let input = b"foo = bar\n  baz = \"qux\"".as_slice();
+        let mut parser = Parser::new(input);
+        assert_eq!(parser.location().pos(), 0);
+        assert!(parser.parse().unwrap().is_some());
+        let location = parser.location();
+        assert_eq!(
+            (location.line(), location.col(), location.pos()),
+            (1, 10, 9)
+        );
+        assert!(parser.parse().unwrap().is_some());
+        let location = parser.location();
+        assert_eq!(
+            (location.line(), location.col(), location.pos()),
+            (2, 14, 23)
+        );
+        assert_eq!(parser.parse(), Ok(None));
+    }
+
     #[test]
     fn parse_key_values() {
         let input =
@@ -496,4 +628,99 @@ mod tests {
         let eoi = parser.parse();
         assert_eq!(eoi, Ok(None))
     }
+
+    #[test]
+    fn escapes() {
+        let input =
+            b"revision = \"say \\\"woof\\\"\" path = \"\\\\EFI\\corgos\" tabs = \"a\\tb\\nc\""
+                .as_slice();
+        let mut parser = Parser::with_flags(input);
+        let expected: [(&[u8], &[u8], &[u8]); 3] = [
+            (b"revision", b"say \\\"woof\\\"", b"say \"woof\""),
+            (b"path", b"\\\\EFI\\corgos", b"\\EFI\\corgos"),
+            (b"tabs", b"a\\tb\\nc", b"a\tb\nc"),
+        ];
+        for (key, value, unescaped) in expected {
+            assert_eq!(parser.parse(), Ok(Some(KeyValue { key, value })));
+            let mut buf = [0; 16];
+            assert_eq!(unescape(value, &mut buf), Some(unescaped));
+        }
+        assert_eq!(parser.parse(), Ok(None));
+
+        // The unescaped value must fit.
+        assert_eq!(unescape(b"a\\tb", &mut [0; 3]), Some(b"a\tb".as_slice()));
+        assert_eq!(unescape(b"a\\tb", &mut [0; 2]), None);
+
+        let input = "quote = \"escaped \\\" and not closed";
+        let mut parser = Parser::with_flags(input);
+        let Err(Error::UnmatchedQuote(location)) = parser.parse() else {
+            panic!("expected an unmatched quote");
+        };
+        assert_eq!((location.line(), location.col(), location.pos()), (1, 9, 8));
+        let input = "quote = \"trailing \\";
+        let mut parser = Parser::with_flags(input);
+        assert!(matches!(parser.parse(), Err(Error::UnmatchedQuote(_))));
+    }
+
+    #[test]
+    fn backslashes_kept() {
+        // Without the flags, the backslashes are not escapes, and a path
+        // may end with one.
+        let input = "dir = \"\\EFI\\corgos\\\"\nkernel = \"\\EFI\\new\\kernel\"\n";
+        let mut parser = Parser::new(input);
+        assert_eq!(
+            parser.parse(),
+            Ok(Some(KeyValue {
+                key: "dir",
+                value: "\\EFI\\corgos\\"
+            }))
+        );
+        assert_eq!(
+            parser.parse(),
+            Ok(Some(KeyValue {
+                key: "kernel",
+                value: "\\EFI\\new\\kernel"
+            }))
+        );
+        assert_eq!(parser.parse(), Ok(None));
+    }
+
+    #[test]
+    fn unmatched_quote() {
+        // The quote not closed on its line is reported where it is, and the
+        // lines after it are still parsed.
+        let input = "revision = \"not closed\nkernel = \"\\EFI\\kernel\"\n";
+        let mut parser = Parser::new(input);
+        let Err(Error::UnmatchedQuote(location)) = parser.parse() else {
+            panic!("expected an unmatched quote");
+        };
+        assert_eq!(
+            (location.line(), location.col(), location.pos()),
+            (1, 12, 11)
+        );
+        let location = parser.location();
+        assert_eq!(
+            (location.line(), location.col(), location.pos()),
+            (1, 23, 22)
+        );
+        assert_eq!(
+            parser.parse(),
+            Ok(Some(KeyValue {
+                key: "kernel",
+                value: "\\EFI\\kernel"
+            }))
+        );
+        assert_eq!(parser.parse(), Ok(None));
+
+        let input = b"cmdline = \"quiet".as_slice();
+        let mut parser = Parser::new(input);
+        let Err(Error::UnmatchedQuote(location)) = parser.parse() else {
+            panic!("expected an unmatched quote");
+        };
+        assert_eq!(
+            (location.line(), location.col(), location.pos()),
+            (1, 11, 10)
+        );
+        assert_eq!(parser.parse(), Ok(None));
+    }
 }
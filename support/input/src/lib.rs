@@ -0,0 +1,142 @@
+//! Keyboard input for the boot menu and the debug shell: the key events
+//! with the modifiers, whatever the keys come from. The UEFI console
+//! reports the keys already decoded, the serial terminals send the bytes
+//! and the VT100 escape sequences the [`SerialDecoder`] turns into the
+//! same events. A PS/2 scancode backend for the kernel is to follow.
+//!
+//! The [`LineEditor`] edits a line of text with the key events, and echoes
+//! the changes with the backspaces only, so that it works on the UEFI
+//! console and on any terminal alike.
+//!
+//! Example:
+//! ```ignore
+//! let mut decoder = input::SerialDecoder::new();
+//! for &byte in b"\x1b[1;5A" {
+//!     if let Some(event) = decoder.push(byte) {
+//!         assert_eq!(event.key, input::Key::Up);
+//!         assert!(event.modifiers.contains(input::Modifiers::CTRL));
+//!     }
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod line_editor;
+mod serial;
+mod tests;
+
+pub use line_editor::EditResult;
+pub use line_editor::LineEditor;
+pub use serial::SerialDecoder;
+
+use core::ops::BitOr;
+use core::ops::BitOrAssign;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A character, the letter with Ctrl held is reported as the letter.
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Escape,
+    Tab,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// `F1`-`F24`.
+    Function(u8),
+}
+
+/// The modifier keys held down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1 << 0);
+    pub const ALT: Self = Self(1 << 1);
+    pub const CTRL: Self = Self(1 << 2);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    pub const fn new(key: Key) -> Self {
+        Self {
+            key,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    pub const fn with_modifiers(key: Key, modifiers: Modifiers) -> Self {
+        Self { key, modifiers }
+    }
+
+    /// The printable ASCII character typed, `None` for the other keys and
+    /// for the characters with Ctrl or Alt.
+    pub fn printable(&self) -> Option<char> {
+        match self.key {
+            Key::Char(c)
+                if (c.is_ascii_graphic() || c == ' ')
+                    && !self.modifiers.contains(Modifiers::CTRL)
+                    && !self.modifiers.contains(Modifiers::ALT) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// The letter, lowercase, typed with Ctrl and no Alt.
+    pub fn ctrl_letter(&self) -> Option<char> {
+        match self.key {
+            Key::Char(c)
+                if c.is_ascii_alphabetic()
+                    && self.modifiers.contains(Modifiers::CTRL)
+                    && !self.modifiers.contains(Modifiers::ALT) =>
+            {
+                Some(c.to_ascii_lowercase())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> Self {
+        Self::new(key)
+    }
+}
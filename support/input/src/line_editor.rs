@@ -0,0 +1,158 @@
+//! Editing a line of ASCII text in place:
+//!
+//! Keys                 Action
+//! ----------------------------------------------------------------------
+//! printable            insert at the cursor
+//! Left, Right          move the cursor
+//! Home, Ctrl+A         move to the start
+//! End, Ctrl+E          move to the end
+//! Backspace            delete before the cursor
+//! Delete, Ctrl+D       delete at the cursor
+//! Ctrl+U               delete up to the cursor
+//! Ctrl+K               delete from the cursor
+//! Enter                accept
+//! Esc, Ctrl+C          cancel
+//!
+//! The text is the bytes of the buffer up to the first zero, and the bytes
+//! past the text are zeroed as it shrinks, so that a zero-terminated buffer
+//! stays zero-terminated if the editor is given all but its last byte.
+//!
+//! The echo only prints the characters, and moves the cursor back with the
+//! backspaces, the terminal is assumed to be on the line being edited, and
+//! the line is assumed to fit on the screen.
+
+use crate::Key;
+use crate::KeyEvent;
+use core::fmt::Write;
+
+const BACKSPACE: char = '\u{8}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditResult {
+    Editing,
+    Accepted,
+    Cancelled,
+}
+
+pub struct LineEditor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    cursor: usize,
+}
+
+fn write_bytes(echo: &mut impl Write, bytes: &[u8]) {
+    for &byte in bytes {
+        echo.write_char(char::from(byte)).ok();
+    }
+}
+
+fn write_backspaces(echo: &mut impl Write, count: usize) {
+    for _ in 0..count {
+        echo.write_char(BACKSPACE).ok();
+    }
+}
+
+impl<'a> LineEditor<'a> {
+    /// Edits the text in `buf`, the cursor is at its end.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Self {
+            buf,
+            len,
+            cursor: len,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Prints the text, and leaves the cursor where it is in the text.
+    pub fn redraw(&self, echo: &mut impl Write) {
+        write_bytes(echo, &self.buf[..self.len]);
+        write_backspaces(echo, self.len - self.cursor);
+    }
+
+    /// Applies the key, and echoes the change.
+    pub fn handle(&mut self, event: KeyEvent, echo: &mut impl Write) -> EditResult {
+        if let Some(c) = event.printable() {
+            self.insert(c as u8, echo);
+            return EditResult::Editing;
+        }
+
+        match (event.key, event.ctrl_letter()) {
+            (Key::Enter, _) => return EditResult::Accepted,
+            (Key::Escape, _) | (_, Some('c')) => return EditResult::Cancelled,
+            (Key::Left, _) => self.move_to(self.cursor.saturating_sub(1), echo),
+            (Key::Right, _) => self.move_to(core::cmp::min(self.cursor + 1, self.len), echo),
+            (Key::Home, _) | (_, Some('a')) => self.move_to(0, echo),
+            (Key::End, _) | (_, Some('e')) => self.move_to(self.len, echo),
+            (Key::Backspace, _) if self.cursor > 0 => {
+                self.move_to(self.cursor - 1, echo);
+                self.remove(self.cursor..self.cursor + 1, echo);
+            }
+            (Key::Delete, _) | (_, Some('d')) if self.cursor < self.len => {
+                self.remove(self.cursor..self.cursor + 1, echo)
+            }
+            (_, Some('u')) => {
+                let end = self.cursor;
+                self.move_to(0, echo);
+                self.remove(0..end, echo);
+            }
+            (_, Some('k')) => self.remove(self.cursor..self.len, echo),
+            _ => {}
+        }
+        EditResult::Editing
+    }
+
+    fn insert(&mut self, c: u8, echo: &mut impl Write) {
+        if self.len == self.buf.len() {
+            return;
+        }
+        self.buf.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.buf[self.cursor] = c;
+        self.len += 1;
+
+        write_bytes(echo, &self.buf[self.cursor..self.len]);
+        self.cursor += 1;
+        write_backspaces(echo, self.len - self.cursor);
+    }
+
+    /// Removes the bytes at and after the cursor.
+    fn remove(&mut self, range: core::ops::Range<usize>, echo: &mut impl Write) {
+        let removed = range.len();
+        if removed == 0 {
+            return;
+        }
+        self.buf.copy_within(range.end..self.len, range.start);
+        self.buf[self.len - removed..self.len].fill(0);
+        self.len -= removed;
+
+        write_bytes(echo, &self.buf[self.cursor..self.len]);
+        for _ in 0..removed {
+            echo.write_char(' ').ok();
+        }
+        write_backspaces(echo, self.len - self.cursor + removed);
+    }
+
+    fn move_to(&mut self, cursor: usize, echo: &mut impl Write) {
+        if cursor < self.cursor {
+            write_backspaces(echo, self.cursor - cursor);
+        } else {
+            write_bytes(echo, &self.buf[self.cursor..cursor]);
+        }
+        self.cursor = cursor;
+    }
+}
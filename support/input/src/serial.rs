@@ -0,0 +1,226 @@
+//! Decoding the bytes a serial terminal sends into the key events. The
+//! terminals send the control characters for Enter, Backspace, Tab and the
+//! letters with Ctrl, and the VT100/xterm escape sequences for the other
+//! keys:
+//!
+//! Sequence          Keys
+//! ----------------------------------------------------------------------
+//! ESC [ A-D         Up, Down, Right, Left
+//! ESC [ H, ESC [ F  Home, End
+//! ESC [ n ~         Home 1/7, Insert 2, Delete 3, End 4/8, PageUp 5,
+//!                   PageDown 6, F1-F12 11-15, 17-21, 23-24, F13-F20 25-34
+//! ESC [ Z           Shift+Tab
+//! ESC O A-D, H, F   The same keys in the application cursor mode
+//! ESC O P-S         F1-F4
+//! ESC <byte>        The key with Alt
+//!
+//! The modifiers come as the second parameter, `ESC [ 1 ; 5 A` is Ctrl+Up:
+//! the value less one has Shift in bit 0, Alt in bit 1, and Ctrl in bit 2.
+//!
+//! Esc alone is only told from the start of a sequence by the time: the
+//! caller [flushes](SerialDecoder::flush) the decoder when no byte has come
+//! for a while.
+
+use crate::Key;
+use crate::KeyEvent;
+use crate::Modifiers;
+
+const ESC: u8 = 0x1b;
+const MAX_PARAMS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After ESC.
+    Escape,
+    /// After ESC [, the parameters so far.
+    Csi {
+        params: [u16; MAX_PARAMS],
+        index: usize,
+    },
+    /// After ESC O.
+    Ss3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SerialDecoder {
+    state: State,
+    /// The last byte was CR, the LF of CR LF is not another Enter.
+    after_cr: bool,
+}
+
+impl Default for SerialDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The xterm modifier parameter, `0` or `1` for none.
+fn modifiers(param: u16) -> Modifiers {
+    let bits = param.saturating_sub(1);
+    let mut modifiers = Modifiers::NONE;
+    if bits & 1 != 0 {
+        modifiers |= Modifiers::SHIFT;
+    }
+    if bits & 2 != 0 {
+        modifiers |= Modifiers::ALT;
+    }
+    if bits & 4 != 0 {
+        modifiers |= Modifiers::CTRL;
+    }
+    modifiers
+}
+
+/// The key for the final byte shared by CSI and SS3.
+fn cursor_key(byte: u8) -> Option<Key> {
+    match byte {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        b'H' => Some(Key::Home),
+        b'F' => Some(Key::End),
+        b'P'..=b'S' => Some(Key::Function(byte - b'P' + 1)),
+        _ => None,
+    }
+}
+
+/// The key for `ESC [ n ~`.
+fn tilde_key(n: u16) -> Option<Key> {
+    let key = match n {
+        1 | 7 => Key::Home,
+        2 => Key::Insert,
+        3 => Key::Delete,
+        4 | 8 => Key::End,
+        5 => Key::PageUp,
+        6 => Key::PageDown,
+        11..=15 => Key::Function((n - 10) as u8),
+        17..=21 => Key::Function((n - 11) as u8),
+        23..=26 => Key::Function((n - 12) as u8),
+        28 | 29 => Key::Function((n - 13) as u8),
+        31..=34 => Key::Function((n - 14) as u8),
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn csi_key(byte: u8, params: [u16; MAX_PARAMS]) -> Option<KeyEvent> {
+    let key = match byte {
+        b'~' => tilde_key(params[0])?,
+        b'Z' => return Some(KeyEvent::with_modifiers(Key::Tab, Modifiers::SHIFT)),
+        byte => cursor_key(byte)?,
+    };
+    Some(KeyEvent::with_modifiers(key, modifiers(params[1])))
+}
+
+impl SerialDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            after_cr: false,
+        }
+    }
+
+    /// Whether a sequence has started, and the caller is to
+    /// [flush](Self::flush) the decoder if no more bytes come.
+    pub fn is_pending(&self) -> bool {
+        self.state != State::Ground
+    }
+
+    /// Decodes the next byte, `None` if the key is not complete yet or the
+    /// byte is not a key.
+    pub fn push(&mut self, byte: u8) -> Option<KeyEvent> {
+        let after_cr = core::mem::replace(&mut self.after_cr, false);
+
+        match self.state {
+            State::Ground => self.ground(byte, after_cr),
+            State::Escape => match byte {
+                b'[' => {
+                    self.state = State::Csi {
+                        params: [0; MAX_PARAMS],
+                        index: 0,
+                    };
+                    None
+                }
+                b'O' => {
+                    self.state = State::Ss3;
+                    None
+                }
+                // Esc pressed twice.
+                ESC => Some(KeyEvent::new(Key::Escape)),
+                byte => {
+                    self.state = State::Ground;
+                    let mut event = self.ground(byte, false)?;
+                    event.modifiers |= Modifiers::ALT;
+                    Some(event)
+                }
+            },
+            State::Csi {
+                mut params,
+                mut index,
+            } => match byte {
+                b'0'..=b'9' => {
+                    if let Some(param) = params.get_mut(index) {
+                        *param = param
+                            .saturating_mul(10)
+                            .saturating_add((byte - b'0') as u16);
+                    }
+                    self.state = State::Csi { params, index };
+                    None
+                }
+                b';' => {
+                    index += 1;
+                    self.state = State::Csi { params, index };
+                    None
+                }
+                // The other parameter and the intermediate bytes.
+                0x20..=0x3f => None,
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    csi_key(byte, params)
+                }
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+            State::Ss3 => {
+                self.state = State::Ground;
+                cursor_key(byte).map(KeyEvent::new)
+            }
+        }
+    }
+
+    /// Ends the sequence started, after no byte has come for a while. Esc
+    /// alone is the Escape key, the incomplete sequences are dropped.
+    pub fn flush(&mut self) -> Option<KeyEvent> {
+        let state = core::mem::replace(&mut self.state, State::Ground);
+        (state == State::Escape).then_some(KeyEvent::new(Key::Escape))
+    }
+
+    fn ground(&mut self, byte: u8, after_cr: bool) -> Option<KeyEvent> {
+        let key = match byte {
+            ESC => {
+                self.state = State::Escape;
+                return None;
+            }
+            b'\r' => {
+                self.after_cr = true;
+                Key::Enter
+            }
+            b'\n' if after_cr => return None,
+            b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x08 | 0x7f => Key::Backspace,
+            0x01..=0x1a => {
+                return Some(KeyEvent::with_modifiers(
+                    Key::Char(char::from(b'a' + byte - 1)),
+                    Modifiers::CTRL,
+                ))
+            }
+            0x20..=0x7e => Key::Char(char::from(byte)),
+            _ => return None,
+        };
+        Some(KeyEvent::new(key))
+    }
+}
@@ -0,0 +1,208 @@
+#![cfg(test)]
+
+use super::*;
+
+fn decode(bytes: &[u8]) -> Vec<KeyEvent> {
+    let mut decoder = SerialDecoder::new();
+    let mut events: Vec<KeyEvent> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+    events.extend(decoder.flush());
+    events
+}
+
+fn key(key: Key) -> KeyEvent {
+    KeyEvent::new(key)
+}
+
+fn with(key: Key, modifiers: Modifiers) -> KeyEvent {
+    KeyEvent::with_modifiers(key, modifiers)
+}
+
+#[test]
+fn serial_plain_keys() {
+    assert_eq!(
+        decode(b"a Z\r\n\n\t\x7f\x08"),
+        [
+            key(Key::Char('a')),
+            key(Key::Char(' ')),
+            key(Key::Char('Z')),
+            key(Key::Enter),
+            key(Key::Enter),
+            key(Key::Tab),
+            key(Key::Backspace),
+            key(Key::Backspace),
+        ]
+    );
+    assert_eq!(
+        decode(b"\x01\x1a"),
+        [
+            with(Key::Char('a'), Modifiers::CTRL),
+            with(Key::Char('z'), Modifiers::CTRL),
+        ]
+    );
+}
+
+#[test]
+fn serial_escape_sequences() {
+    assert_eq!(
+        decode(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1bOH\x1bOF\x1b[Z"),
+        [
+            key(Key::Up),
+            key(Key::Down),
+            key(Key::Right),
+            key(Key::Left),
+            key(Key::Home),
+            key(Key::End),
+            with(Key::Tab, Modifiers::SHIFT),
+        ]
+    );
+    assert_eq!(
+        decode(b"\x1b[3~\x1b[2~\x1b[5~\x1b[6~\x1b[1~\x1b[4~"),
+        [
+            key(Key::Delete),
+            key(Key::Insert),
+            key(Key::PageUp),
+            key(Key::PageDown),
+            key(Key::Home),
+            key(Key::End),
+        ]
+    );
+    assert_eq!(
+        decode(b"\x1bOP\x1b[15~\x1b[21~\x1b[24~\x1b[34~\x1b[99~"),
+        [
+            key(Key::Function(1)),
+            key(Key::Function(5)),
+            key(Key::Function(10)),
+            key(Key::Function(12)),
+            key(Key::Function(20)),
+        ]
+    );
+}
+
+#[test]
+fn serial_modifiers() {
+    assert_eq!(
+        decode(b"\x1b[1;5A\x1b[1;2D\x1b[3;3~\x1b[1;8H\x1bx"),
+        [
+            with(Key::Up, Modifiers::CTRL),
+            with(Key::Left, Modifiers::SHIFT),
+            with(Key::Delete, Modifiers::ALT),
+            with(
+                Key::Home,
+                Modifiers::SHIFT | Modifiers::ALT | Modifiers::CTRL
+            ),
+            with(Key::Char('x'), Modifiers::ALT),
+        ]
+    );
+}
+
+#[test]
+fn serial_lone_escape() {
+    let mut decoder = SerialDecoder::new();
+    assert_eq!(decoder.push(0x1b), None);
+    assert!(decoder.is_pending());
+    assert_eq!(decoder.flush(), Some(key(Key::Escape)));
+    assert!(!decoder.is_pending());
+    assert_eq!(decoder.flush(), None);
+
+    assert_eq!(decode(b"\x1b\x1b"), [key(Key::Escape), key(Key::Escape)]);
+    // The incomplete sequences are dropped.
+    assert_eq!(decode(b"\x1b[1;5"), []);
+}
+
+#[test]
+fn printable() {
+    assert_eq!(key(Key::Char('a')).printable(), Some('a'));
+    assert_eq!(
+        with(Key::Char('A'), Modifiers::SHIFT).printable(),
+        Some('A')
+    );
+    assert_eq!(with(Key::Char('a'), Modifiers::CTRL).printable(), None);
+    assert_eq!(with(Key::Char('a'), Modifiers::ALT).printable(), None);
+    assert_eq!(key(Key::Char('\u{e9}')).printable(), None);
+    assert_eq!(key(Key::Enter).printable(), None);
+    assert_eq!(
+        with(Key::Char('A'), Modifiers::CTRL).ctrl_letter(),
+        Some('a')
+    );
+}
+
+/// Applies the events, returns the result of the last one and the echo.
+fn edit(buf: &mut [u8], events: &[KeyEvent]) -> (EditResult, String, usize) {
+    let mut editor = LineEditor::new(buf);
+    let mut echo = String::new();
+    let mut result = EditResult::Editing;
+    for &event in events {
+        result = editor.handle(event, &mut echo);
+    }
+    (result, echo, editor.cursor())
+}
+
+fn typed(s: &str) -> Vec<KeyEvent> {
+    s.chars().map(|c| key(Key::Char(c))).collect()
+}
+
+fn text(buf: &[u8]) -> &str {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).unwrap()
+}
+
+#[test]
+fn line_editor_typing() {
+    let mut buf = [0_u8; 8];
+    let mut events = typed("abc");
+    events.push(key(Key::Enter));
+    let (result, echo, cursor) = edit(&mut buf, &events);
+    assert_eq!(result, EditResult::Accepted);
+    assert_eq!(text(&buf), "abc");
+    assert_eq!(echo, "abc");
+    assert_eq!(cursor, 3);
+
+    // The characters past the buffer are dropped.
+    let mut buf = [0_u8; 4];
+    edit(&mut buf, &typed("abcdef"));
+    assert_eq!(text(&buf), "abcd");
+}
+
+#[test]
+fn line_editor_cursor() {
+    let mut buf = *b"acd\0\0\0\0\0";
+    let mut events = vec![key(Key::Left), key(Key::Left)];
+    events.extend(typed("b"));
+    events.push(key(Key::Home));
+    events.push(key(Key::Delete));
+    events.push(key(Key::End));
+    events.push(key(Key::Backspace));
+    let (result, echo, cursor) = edit(&mut buf, &events);
+    assert_eq!(result, EditResult::Editing);
+    assert_eq!(text(&buf), "bc");
+    assert_eq!(buf[2..], [0; 6]);
+    assert_eq!(cursor, 2);
+    assert_eq!(
+        echo,
+        "\u{8}\u{8}bcd\u{8}\u{8}\u{8}\u{8}bcd \u{8}\u{8}\u{8}\u{8}bcd\u{8} \u{8}"
+    );
+}
+
+#[test]
+fn line_editor_kill() {
+    for (ctrl, expected) in [('k', "hello "), ('u', "world")] {
+        let mut buf = *b"hello world\0";
+        let mut events = vec![key(Key::Home)];
+        events.extend([key(Key::Right); 6]);
+        events.push(with(Key::Char(ctrl), Modifiers::CTRL));
+        edit(&mut buf, &events);
+        assert_eq!(text(&buf), expected);
+    }
+}
+
+#[test]
+fn line_editor_cancel() {
+    let mut buf = [0_u8; 8];
+    let mut events = typed("ab");
+    events.push(key(Key::Escape));
+    assert_eq!(edit(&mut buf, &events).0, EditResult::Cancelled);
+
+    let (result, echo, _) = edit(&mut buf, &[with(Key::Char('c'), Modifiers::CTRL)]);
+    assert_eq!(result, EditResult::Cancelled);
+    assert_eq!(echo, "");
+}
@@ -0,0 +1,215 @@
+//! The LZ4 blocks: the sequences of the literals to copy, and the matches
+//! to repeat from the data already written.
+//!
+//! The sequences are decoded byte by byte as the input comes, so a block
+//! may be split anywhere between the pieces of the input.
+
+use crate::Lz4Error;
+
+/// The shortest match, added to the length in the token.
+const MIN_MATCH: usize = 4;
+/// The length in the token continues in the next bytes.
+const LENGTH_MORE: usize = 15;
+
+/// The decompressed data written so far.
+pub(crate) struct Output<'a> {
+    pub(crate) buf: &'a mut [u8],
+    pub(crate) len: usize,
+}
+
+impl Output<'_> {
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Result<(), Lz4Error> {
+        let end = self.end(bytes.len())?;
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn end(&self, len: usize) -> Result<usize, Lz4Error> {
+        self.len
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(Lz4Error::OutputTooSmall)
+    }
+
+    /// Repeats `len` bytes from `offset` back, the match may not reach
+    /// before `window_start`.
+    fn copy_match(
+        &mut self,
+        offset: usize,
+        len: usize,
+        window_start: usize,
+    ) -> Result<(), Lz4Error> {
+        if offset == 0 || offset > self.len - window_start {
+            return Err(Lz4Error::BadOffset);
+        }
+        let end = self.end(len)?;
+        let start = self.len - offset;
+        if offset >= len {
+            self.buf.copy_within(start..start + len, self.len);
+        } else {
+            // The match overlaps what it writes, repeating the last bytes.
+            for i in self.len..end {
+                self.buf[i] = self.buf[i - offset];
+            }
+        }
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Token,
+    LiteralLength,
+    Literals,
+    OffsetLow,
+    OffsetHigh,
+    MatchLength,
+    Match,
+    /// The block has ended with the literals.
+    Done,
+}
+
+/// Where the decoding of a block has stopped.
+#[derive(Debug, Clone)]
+pub(crate) struct Sequences {
+    step: Step,
+    literals: usize,
+    match_len: usize,
+    offset: usize,
+    window_start: usize,
+}
+
+impl Sequences {
+    /// The matches may refer back to `window_start` in the output.
+    pub(crate) fn new(window_start: usize) -> Self {
+        Self {
+            step: Step::Token,
+            literals: 0,
+            match_len: 0,
+            offset: 0,
+            window_start,
+        }
+    }
+
+    /// Whether the block may end here.
+    pub(crate) fn at_end(&self) -> bool {
+        self.step == Step::Done
+    }
+
+    /// Decodes from `input`, taking no more than the `remaining` bytes of
+    /// the block. Returns when either runs out.
+    pub(crate) fn decode(
+        &mut self,
+        input: &mut &[u8],
+        remaining: &mut usize,
+        output: &mut Output,
+    ) -> Result<(), Lz4Error> {
+        loop {
+            match self.step {
+                Step::Literals => {
+                    if self.literals > 0 {
+                        let len = self.literals.min(input.len()).min(*remaining);
+                        if len == 0 {
+                            return Ok(());
+                        }
+                        output.push(&input[..len])?;
+                        *input = &input[len..];
+                        *remaining -= len;
+                        self.literals -= len;
+                        continue;
+                    }
+                    // Only the last sequence has no match.
+                    self.step = if *remaining == 0 {
+                        Step::Done
+                    } else {
+                        Step::OffsetLow
+                    };
+                }
+                Step::Match => {
+                    output.copy_match(
+                        self.offset,
+                        self.match_len + MIN_MATCH,
+                        self.window_start,
+                    )?;
+                    self.step = Step::Token;
+                }
+                Step::Done => {
+                    return if *remaining == 0 {
+                        Ok(())
+                    } else {
+                        Err(Lz4Error::Malformed)
+                    };
+                }
+                step => {
+                    if *remaining == 0 {
+                        return Ok(());
+                    }
+                    let Some((&byte, rest)) = input.split_first() else {
+                        return Ok(());
+                    };
+                    *input = rest;
+                    *remaining -= 1;
+
+                    self.step = match step {
+                        Step::Token => {
+                            self.literals = (byte >> 4) as usize;
+                            self.match_len = (byte & 0xf) as usize;
+                            if self.literals == LENGTH_MORE {
+                                Step::LiteralLength
+                            } else {
+                                Step::Literals
+                            }
+                        }
+                        Step::LiteralLength => {
+                            self.literals = self.literals.saturating_add(byte as usize);
+                            if byte == u8::MAX {
+                                Step::LiteralLength
+                            } else {
+                                Step::Literals
+                            }
+                        }
+                        Step::OffsetLow => {
+                            self.offset = byte as usize;
+                            Step::OffsetHigh
+                        }
+                        Step::OffsetHigh => {
+                            self.offset |= (byte as usize) << 8;
+                            if self.match_len == LENGTH_MORE {
+                                Step::MatchLength
+                            } else {
+                                Step::Match
+                            }
+                        }
+                        Step::MatchLength => {
+                            self.match_len = self.match_len.saturating_add(byte as usize);
+                            if byte == u8::MAX {
+                                Step::MatchLength
+                            } else {
+                                Step::Match
+                            }
+                        }
+                        Step::Literals | Step::Match | Step::Done => unreachable!(),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses the raw block into `output`, and returns the size of the
+/// decompressed data.
+pub fn decompress_block(mut input: &[u8], output: &mut [u8]) -> Result<usize, Lz4Error> {
+    let mut output = Output {
+        buf: output,
+        len: 0,
+    };
+    let mut sequences = Sequences::new(0);
+    let mut remaining = input.len();
+    sequences.decode(&mut input, &mut remaining, &mut output)?;
+    if !sequences.at_end() {
+        return Err(Lz4Error::Malformed);
+    }
+    Ok(output.len)
+}
@@ -0,0 +1,370 @@
+//! The LZ4 frames: the magic, the descriptor, the blocks up to the end
+//! mark, and the checksums. The skippable frames are skipped, and the
+//! frames following each other are decompressed one after another.
+
+use crate::block::Output;
+use crate::block::Sequences;
+use crate::xxh32::xxh32;
+use crate::xxh32::Xxh32;
+use crate::Lz4Error;
+
+pub const FRAME_MAGIC: u32 = 0x184d_2204;
+/// The skippable frames have any of the 16 magics.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+const SKIPPABLE_MAGIC_MASK: u32 = 0xffff_fff0;
+
+const FLG_VERSION_MASK: u8 = 0b1100_0000;
+const FLG_VERSION: u8 = 0b0100_0000;
+const FLG_BLOCK_INDEPENDENT: u8 = 1 << 5;
+const FLG_BLOCK_CHECKSUM: u8 = 1 << 4;
+const FLG_CONTENT_SIZE: u8 = 1 << 3;
+const FLG_CONTENT_CHECKSUM: u8 = 1 << 2;
+const FLG_RESERVED: u8 = 1 << 1;
+const FLG_DICTIONARY_ID: u8 = 1 << 0;
+const BD_RESERVED: u8 = 0b1000_1111;
+const BD_BLOCK_MAX_SHIFT: u8 = 4;
+
+/// The block is stored as is.
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+/// The longest header field: the descriptor with the content size and the
+/// dictionary ID.
+const MAX_FIELD_SIZE: usize = 15;
+
+/// The size of the descriptor from its first byte, the header checksum
+/// included.
+fn descriptor_size(flg: u8) -> usize {
+    let mut size = 3;
+    if flg & FLG_CONTENT_SIZE != 0 {
+        size += 8;
+    }
+    if flg & FLG_DICTIONARY_ID != 0 {
+        size += 4;
+    }
+    size
+}
+
+/// 64 KiB, 256 KiB, 1 MiB, or 4 MiB.
+fn block_max_size(bd: u8) -> Option<usize> {
+    match bd >> BD_BLOCK_MAX_SHIFT {
+        id @ 4..=7 => Some(1 << (8 + 2 * id)),
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    block_independent: bool,
+    block_checksum: bool,
+    content_checksum: bool,
+    content_size: Option<u64>,
+    block_max_size: usize,
+}
+
+impl Descriptor {
+    fn parse(bytes: &[u8]) -> Result<Self, Lz4Error> {
+        let (flg, bd) = (bytes[0], bytes[1]);
+        if flg & FLG_VERSION_MASK != FLG_VERSION || flg & FLG_RESERVED != 0 || bd & BD_RESERVED != 0
+        {
+            return Err(Lz4Error::BadDescriptor);
+        }
+        let block_max_size = block_max_size(bd).ok_or(Lz4Error::BadDescriptor)?;
+        let (fields, checksum) = bytes.split_at(bytes.len() - 1);
+        if (xxh32(fields) >> 8) as u8 != checksum[0] {
+            return Err(Lz4Error::HeaderChecksum);
+        }
+        if flg & FLG_DICTIONARY_ID != 0 {
+            return Err(Lz4Error::Dictionary);
+        }
+
+        Ok(Self {
+            block_independent: flg & FLG_BLOCK_INDEPENDENT != 0,
+            block_checksum: flg & FLG_BLOCK_CHECKSUM != 0,
+            content_checksum: flg & FLG_CONTENT_CHECKSUM != 0,
+            content_size: (flg & FLG_CONTENT_SIZE != 0)
+                .then(|| u64::from_le_bytes(bytes[2..10].try_into().expect("8 bytes"))),
+            block_max_size,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Magic,
+    Descriptor,
+    SkippableSize,
+    Skip(usize),
+    BlockSize,
+    Block {
+        remaining: usize,
+        /// None for the uncompressed block.
+        sequences: Option<Sequences>,
+    },
+    BlockChecksum,
+    ContentChecksum,
+    Failed(Lz4Error),
+}
+
+/// Decompresses the frames fed to it in pieces of any size.
+pub struct FrameDecoder<'a> {
+    output: Output<'a>,
+    state: State,
+    /// The header field being collected.
+    field: [u8; MAX_FIELD_SIZE],
+    field_len: usize,
+    descriptor: Option<Descriptor>,
+    frame_start: usize,
+    block_hash: Xxh32,
+    frames: usize,
+}
+
+impl<'a> FrameDecoder<'a> {
+    pub fn new(output: &'a mut [u8]) -> Self {
+        Self {
+            output: Output {
+                buf: output,
+                len: 0,
+            },
+            state: State::Magic,
+            field: [0; MAX_FIELD_SIZE],
+            field_len: 0,
+            descriptor: None,
+            frame_start: 0,
+            block_hash: Xxh32::new(),
+            frames: 0,
+        }
+    }
+
+    /// The size of the decompressed data so far.
+    pub fn written(&self) -> usize {
+        self.output.len
+    }
+
+    /// The content size the current frame has in the header, if any.
+    pub fn content_size(&self) -> Option<u64> {
+        self.descriptor
+            .and_then(|descriptor| descriptor.content_size)
+    }
+
+    /// Collects the `size` bytes of a header field, true once they are all
+    /// there.
+    fn gather(&mut self, input: &mut &[u8], size: usize) -> bool {
+        let take = (size - self.field_len).min(input.len());
+        self.field[self.field_len..self.field_len + take].copy_from_slice(&input[..take]);
+        self.field_len += take;
+        *input = &input[take..];
+        if self.field_len == size {
+            self.field_len = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Decompresses the next piece of the input. After an error, the
+    /// decoder returns the same error.
+    pub fn feed(&mut self, mut input: &[u8]) -> Result<(), Lz4Error> {
+        if let State::Failed(e) = self.state {
+            return Err(e);
+        }
+        let result = self.run(&mut input);
+        if let Err(e) = result {
+            self.state = State::Failed(e);
+        }
+        result
+    }
+
+    fn run(&mut self, input: &mut &[u8]) -> Result<(), Lz4Error> {
+        while !input.is_empty() {
+            match &mut self.state {
+                State::Magic => {
+                    if !self.gather(input, 4) {
+                        return Ok(());
+                    }
+                    let magic = read_u32(&self.field);
+                    self.state = if magic == FRAME_MAGIC {
+                        State::Descriptor
+                    } else if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+                        State::SkippableSize
+                    } else {
+                        return Err(Lz4Error::BadMagic);
+                    };
+                }
+                State::Descriptor => {
+                    if self.field_len == 0 && !self.gather(input, 1) {
+                        return Ok(());
+                    }
+                    // The first byte stays in the field.
+                    self.field_len = self.field_len.max(1);
+                    let size = descriptor_size(self.field[0]);
+                    if !self.gather(input, size) {
+                        return Ok(());
+                    }
+                    self.descriptor = Some(Descriptor::parse(&self.field[..size])?);
+                    self.frame_start = self.output.len;
+                    self.state = State::BlockSize;
+                }
+                State::SkippableSize => {
+                    if !self.gather(input, 4) {
+                        return Ok(());
+                    }
+                    self.state = State::Skip(read_u32(&self.field) as usize);
+                }
+                State::Skip(remaining) => {
+                    let skip = (*remaining).min(input.len());
+                    *input = &input[skip..];
+                    *remaining -= skip;
+                    if *remaining == 0 {
+                        self.state = State::Magic;
+                    }
+                }
+                State::BlockSize => {
+                    if !self.gather(input, 4) {
+                        return Ok(());
+                    }
+                    let descriptor = self.descriptor.expect("The descriptor is parsed");
+                    let size = read_u32(&self.field);
+                    if size == 0 {
+                        self.state = if descriptor.content_checksum {
+                            State::ContentChecksum
+                        } else {
+                            self.end_frame()?
+                        };
+                        continue;
+                    }
+
+                    let remaining = (size & !BLOCK_UNCOMPRESSED) as usize;
+                    if remaining > descriptor.block_max_size {
+                        return Err(Lz4Error::BlockTooLarge);
+                    }
+                    let window_start = if descriptor.block_independent {
+                        self.output.len
+                    } else {
+                        self.frame_start
+                    };
+                    self.block_hash = Xxh32::new();
+                    self.state = State::Block {
+                        remaining,
+                        sequences: (size & BLOCK_UNCOMPRESSED == 0)
+                            .then(|| Sequences::new(window_start)),
+                    };
+                }
+                State::Block {
+                    remaining,
+                    sequences,
+                } => {
+                    let before = *input;
+                    match sequences {
+                        Some(sequences) => sequences.decode(input, remaining, &mut self.output)?,
+                        None => {
+                            let len = (*remaining).min(input.len());
+                            self.output.push(&input[..len])?;
+                            *input = &input[len..];
+                            *remaining -= len;
+                        }
+                    }
+                    let descriptor = self.descriptor.expect("The descriptor is parsed");
+                    if descriptor.block_checksum {
+                        self.block_hash
+                            .update(&before[..before.len() - input.len()]);
+                    }
+
+                    if *remaining == 0 {
+                        if sequences.as_ref().is_some_and(|s| !s.at_end()) {
+                            return Err(Lz4Error::Malformed);
+                        }
+                        self.state = if descriptor.block_checksum {
+                            State::BlockChecksum
+                        } else {
+                            State::BlockSize
+                        };
+                    }
+                }
+                State::BlockChecksum => {
+                    if !self.gather(input, 4) {
+                        return Ok(());
+                    }
+                    if read_u32(&self.field) != self.block_hash.finalize() {
+                        return Err(Lz4Error::BlockChecksum);
+                    }
+                    self.state = State::BlockSize;
+                }
+                State::ContentChecksum => {
+                    if !self.gather(input, 4) {
+                        return Ok(());
+                    }
+                    let content = &self.output.buf[self.frame_start..self.output.len];
+                    if read_u32(&self.field) != xxh32(content) {
+                        return Err(Lz4Error::ContentChecksum);
+                    }
+                    self.state = self.end_frame()?;
+                }
+                State::Failed(e) => return Err(*e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the size of the frame, and starts looking for the next one.
+    fn end_frame(&mut self) -> Result<State, Lz4Error> {
+        let descriptor = self.descriptor.expect("The descriptor is parsed");
+        if descriptor
+            .content_size
+            .is_some_and(|size| size != (self.output.len - self.frame_start) as u64)
+        {
+            return Err(Lz4Error::ContentSize);
+        }
+        self.frames += 1;
+        Ok(State::Magic)
+    }
+
+    /// The size of the decompressed data, an error unless the input has
+    /// ended right after a frame.
+    pub fn finish(self) -> Result<usize, Lz4Error> {
+        match self.state {
+            State::Failed(e) => Err(e),
+            State::Magic if self.field_len == 0 && self.frames > 0 => Ok(self.output.len),
+            _ => Err(Lz4Error::Truncated),
+        }
+    }
+}
+
+/// Whether the data starts with an LZ4 frame, or a skippable frame.
+pub fn is_frame(data: &[u8]) -> bool {
+    data.len() >= 4 && {
+        let magic = read_u32(data);
+        magic == FRAME_MAGIC || magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC
+    }
+}
+
+/// The content size from the header of the first LZ4 frame, the frame
+/// may not have it. Lets the caller size the output buffer.
+pub fn content_size(mut data: &[u8]) -> Result<Option<u64>, Lz4Error> {
+    loop {
+        let magic = data.get(..4).map(read_u32).ok_or(Lz4Error::Truncated)?;
+        if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+            let size = data.get(4..8).map(read_u32).ok_or(Lz4Error::Truncated)? as usize;
+            data = data.get(8 + size..).ok_or(Lz4Error::Truncated)?;
+            continue;
+        }
+        if magic != FRAME_MAGIC {
+            return Err(Lz4Error::BadMagic);
+        }
+        let flg = *data.get(4).ok_or(Lz4Error::Truncated)?;
+        let descriptor = data
+            .get(4..4 + descriptor_size(flg))
+            .ok_or(Lz4Error::Truncated)?;
+        return Ok(Descriptor::parse(descriptor)?.content_size);
+    }
+}
+
+/// Decompresses all the frames in `input` into `output`, and returns the
+/// size of the decompressed data.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, Lz4Error> {
+    let mut decoder = FrameDecoder::new(output);
+    decoder.feed(input)?;
+    decoder.finish()
+}
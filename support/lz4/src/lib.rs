@@ -0,0 +1,56 @@
+//! LZ4 decompression into a buffer the caller provides, for the compressed
+//! kernel images and boot modules.
+//!
+//! [`decompress_block`] takes a raw block. [`FrameDecoder`] takes the
+//! frames as the `lz4` tool writes them, in pieces of any size as the file
+//! is read, keeping only a few bytes of state between the pieces. The
+//! checksums the frame has are verified, and nothing is written outside
+//! the output buffer whatever the input is.
+//!
+//! Example:
+//! ```ignore
+//! let size = lz4::content_size(compressed)?.ok_or(...)?;
+//! let mut decoder = lz4::FrameDecoder::new(&mut output[..size as usize]);
+//! for chunk in compressed.chunks(0x10_0000) {
+//!     decoder.feed(chunk)?;
+//! }
+//! let len = decoder.finish()?;
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod block;
+mod frame;
+mod tests;
+mod xxh32;
+
+pub use block::decompress_block;
+pub use frame::content_size;
+pub use frame::decompress;
+pub use frame::is_frame;
+pub use frame::FrameDecoder;
+pub use frame::FRAME_MAGIC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lz4Error {
+    /// Neither an LZ4 frame nor a skippable frame.
+    BadMagic,
+    /// The version, the reserved bits, or the block size in the frame
+    /// descriptor.
+    BadDescriptor,
+    /// The frame needs a preset dictionary.
+    Dictionary,
+    HeaderChecksum,
+    BlockTooLarge,
+    BlockChecksum,
+    ContentChecksum,
+    /// The frame has a different size than its header says.
+    ContentSize,
+    /// The match refers to before the start of the data.
+    BadOffset,
+    /// The block ends in the middle of a sequence, or with a match.
+    Malformed,
+    /// The input ends in the middle of a frame.
+    Truncated,
+    OutputTooSmall,
+}
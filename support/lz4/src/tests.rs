@@ -0,0 +1,169 @@
+#![cfg(test)]
+
+use super::*;
+use crate::xxh32::xxh32;
+
+/// The data the fixtures in `testdata` were compressed from by the `lz4`
+/// tool: the default options, `-B4 -BD -BX --content-size`, and
+/// `-B4 --no-frame-crc`.
+fn text() -> Vec<u8> {
+    (0..8000)
+        .flat_map(|i| format!("{:03} corgi pembroke\n", i % 251).into_bytes())
+        .collect()
+}
+
+/// Compressed with `--content-size`, most blocks are stored as is.
+fn random() -> Vec<u8> {
+    let mut seed = 1_u32;
+    (0..20000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 24) as u8
+        })
+        .collect()
+}
+
+const TEXT: &[u8] = include_bytes!("../testdata/text.lz4");
+const TEXT_LINKED: &[u8] = include_bytes!("../testdata/text_linked.lz4");
+const TEXT_NO_CRC: &[u8] = include_bytes!("../testdata/text_nocrc.lz4");
+const RANDOM: &[u8] = include_bytes!("../testdata/random.lz4");
+
+fn decompress_in_chunks(input: &[u8], chunk: usize, capacity: usize) -> Result<Vec<u8>, Lz4Error> {
+    let mut output = vec![0; capacity];
+    let mut decoder = FrameDecoder::new(&mut output);
+    for piece in input.chunks(chunk) {
+        decoder.feed(piece)?;
+    }
+    let len = decoder.finish()?;
+    output.truncate(len);
+    Ok(output)
+}
+
+#[test]
+fn xxh32_vectors() {
+    assert_eq!(xxh32(b""), 0x02cc_5d05);
+    assert_eq!(xxh32(b"abc"), 0x32d1_53ff);
+
+    let data = text();
+    let mut hasher = xxh32::Xxh32::new();
+    for piece in data.chunks(7) {
+        hasher.update(piece);
+    }
+    assert_eq!(hasher.finalize(), xxh32(&data));
+}
+
+#[test]
+fn frames() {
+    let text = text();
+    for input in [TEXT, TEXT_LINKED, TEXT_NO_CRC] {
+        let mut output = vec![0; text.len()];
+        assert_eq!(decompress(input, &mut output), Ok(text.len()));
+        assert_eq!(output, text);
+    }
+
+    let random = random();
+    assert_eq!(content_size(RANDOM), Ok(Some(random.len() as u64)));
+    assert_eq!(
+        decompress_in_chunks(RANDOM, 4096, random.len()).unwrap(),
+        random
+    );
+}
+
+#[test]
+fn streaming() {
+    let text = text();
+    for input in [TEXT, TEXT_LINKED, TEXT_NO_CRC] {
+        for chunk in [1, 3, 17, 1000] {
+            assert_eq!(
+                decompress_in_chunks(input, chunk, text.len()).unwrap(),
+                text
+            );
+        }
+    }
+}
+
+#[test]
+fn skippable_and_concatenated() {
+    assert_eq!(content_size(TEXT), Ok(None));
+    assert_eq!(content_size(TEXT_LINKED), Ok(Some(text().len() as u64)));
+
+    let mut input = vec![0x5a, 0x2a, 0x4d, 0x18, 3, 0, 0, 0, 1, 2, 3];
+    assert!(is_frame(&input));
+    input.extend_from_slice(TEXT_LINKED);
+    input.extend_from_slice(TEXT);
+    assert_eq!(content_size(&input), Ok(Some(text().len() as u64)));
+
+    let twice = [text(), text()].concat();
+    assert_eq!(decompress_in_chunks(&input, 5, twice.len()).unwrap(), twice);
+}
+
+#[test]
+fn blocks() {
+    let mut output = [0; 16];
+    let block = [0x32, b'a', b'b', b'c', 0x03, 0x00, 0x10, b'x'];
+    assert_eq!(decompress_block(&block, &mut output), Ok(10));
+    assert_eq!(&output[..10], b"abcabcabcx");
+
+    assert_eq!(decompress_block(&[0x00], &mut output), Ok(0));
+    assert_eq!(
+        decompress_block(
+            &[0x32, b'a', b'b', b'c', 0x04, 0x00, 0x10, b'x'],
+            &mut output
+        ),
+        Err(Lz4Error::BadOffset)
+    );
+    assert_eq!(
+        decompress_block(&block, &mut output[..9]),
+        Err(Lz4Error::OutputTooSmall)
+    );
+    assert_eq!(
+        decompress_block(&block[..6], &mut output),
+        Err(Lz4Error::Malformed)
+    );
+}
+
+#[test]
+fn errors() {
+    let text = text();
+    let mut output = vec![0; text.len()];
+
+    assert!(!is_frame(b"corgi"));
+    assert_eq!(decompress(b"corgi", &mut output), Err(Lz4Error::BadMagic));
+    assert_eq!(
+        decompress(&TEXT[..TEXT.len() - 1], &mut output),
+        Err(Lz4Error::Truncated)
+    );
+    assert_eq!(decompress(&[], &mut output), Err(Lz4Error::Truncated));
+    assert_eq!(
+        decompress(TEXT, &mut output[..text.len() - 1]),
+        Err(Lz4Error::OutputTooSmall)
+    );
+
+    let mut corrupted = TEXT.to_vec();
+    corrupted[6] ^= 0x01;
+    assert_eq!(
+        decompress(&corrupted, &mut output),
+        Err(Lz4Error::HeaderChecksum)
+    );
+
+    let mut corrupted = TEXT.to_vec();
+    *corrupted.last_mut().unwrap() ^= 0x01;
+    assert_eq!(
+        decompress(&corrupted, &mut output),
+        Err(Lz4Error::ContentChecksum)
+    );
+
+    // The checksum of the first block, after the header and the block.
+    let mut corrupted = TEXT_LINKED.to_vec();
+    let size = u32::from_le_bytes(corrupted[15..19].try_into().unwrap()) & 0x7fff_ffff;
+    corrupted[19 + size as usize] ^= 0x01;
+    assert_eq!(
+        decompress(&corrupted, &mut output),
+        Err(Lz4Error::BlockChecksum)
+    );
+
+    // The decoder keeps the error.
+    let mut decoder = FrameDecoder::new(&mut output);
+    assert_eq!(decoder.feed(b"corgi"), Err(Lz4Error::BadMagic));
+    assert_eq!(decoder.feed(TEXT), Err(Lz4Error::BadMagic));
+}
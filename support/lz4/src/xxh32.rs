@@ -0,0 +1,106 @@
+//! xxHash32, the checksum of the LZ4 frames, with the seed `0`.
+
+const PRIME1: u32 = 0x9e37_79b1;
+const PRIME2: u32 = 0x85eb_ca77;
+const PRIME3: u32 = 0xc2b2_ae3d;
+const PRIME4: u32 = 0x27d4_eb2f;
+const PRIME5: u32 = 0x1656_67b1;
+const STRIPE_SIZE: usize = 16;
+
+fn round(acc: u32, lane: &[u8; 4]) -> u32 {
+    acc.wrapping_add(u32::from_le_bytes(*lane).wrapping_mul(PRIME2))
+        .rotate_left(13)
+        .wrapping_mul(PRIME1)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Xxh32 {
+    acc: [u32; 4],
+    stripe: [u8; STRIPE_SIZE],
+    stripe_len: usize,
+    total: u64,
+}
+
+impl Xxh32 {
+    pub(crate) const fn new() -> Self {
+        Self {
+            acc: [
+                PRIME1.wrapping_add(PRIME2),
+                PRIME2,
+                0,
+                0_u32.wrapping_sub(PRIME1),
+            ],
+            stripe: [0; STRIPE_SIZE],
+            stripe_len: 0,
+            total: 0,
+        }
+    }
+
+    fn consume(acc: &mut [u32; 4], stripe: &[u8; STRIPE_SIZE]) {
+        for (acc, lane) in acc.iter_mut().zip(stripe.as_chunks::<4>().0) {
+            *acc = round(*acc, lane);
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+
+        if self.stripe_len > 0 {
+            let take = (STRIPE_SIZE - self.stripe_len).min(data.len());
+            self.stripe[self.stripe_len..self.stripe_len + take].copy_from_slice(&data[..take]);
+            self.stripe_len += take;
+            data = &data[take..];
+            if self.stripe_len < STRIPE_SIZE {
+                return;
+            }
+            Self::consume(&mut self.acc, &self.stripe);
+            self.stripe_len = 0;
+        }
+
+        let (stripes, rest) = data.as_chunks::<STRIPE_SIZE>();
+        for stripe in stripes {
+            Self::consume(&mut self.acc, stripe);
+        }
+        self.stripe[..rest.len()].copy_from_slice(rest);
+        self.stripe_len = rest.len();
+    }
+
+    pub(crate) fn finalize(&self) -> u32 {
+        let mut hash = if self.total >= STRIPE_SIZE as u64 {
+            let [a, b, c, d] = self.acc;
+            a.rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18))
+        } else {
+            PRIME5
+        };
+        hash = hash.wrapping_add(self.total as u32);
+
+        let (words, bytes) = self.stripe[..self.stripe_len].as_chunks::<4>();
+        for word in words {
+            hash = hash
+                .wrapping_add(u32::from_le_bytes(*word).wrapping_mul(PRIME3))
+                .rotate_left(17)
+                .wrapping_mul(PRIME4);
+        }
+        for &byte in bytes {
+            hash = hash
+                .wrapping_add((byte as u32).wrapping_mul(PRIME5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME1);
+        }
+
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^ (hash >> 16)
+    }
+}
+
+pub(crate) fn xxh32(data: &[u8]) -> u32 {
+    let mut hasher = Xxh32::new();
+    hasher.update(data);
+    hasher.finalize()
+}
@@ -0,0 +1,75 @@
+//! The hexdump lines, the address, the bytes in hex, and the printable
+//! ones as the text:
+//!
+//! ```text
+//! 0x00000000000f5a40: 52 53 44 20 50 54 52 20 6b 42 4f 43 48 53 20 02  RSD PTR kBOCHS .
+//! ```
+//!
+//! The lines are formatted one at a time, so the callers with the consoles
+//! that want `\r\n` print them as they like.
+
+use core::fmt;
+
+/// The bytes in a line.
+pub const LINE_SIZE: usize = 16;
+
+/// A line of up to [`LINE_SIZE`] bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexLine {
+    address: u64,
+    bytes: [u8; LINE_SIZE],
+    len: usize,
+}
+
+impl HexLine {
+    /// The line of the first [`LINE_SIZE`] bytes at most.
+    pub fn new(address: u64, bytes: &[u8]) -> Self {
+        let len = bytes.len().min(LINE_SIZE);
+        let mut line = Self {
+            address,
+            bytes: [0; LINE_SIZE],
+            len,
+        };
+        line.bytes[..len].copy_from_slice(&bytes[..len]);
+        line
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl fmt::Display for HexLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#018x}:", self.address)?;
+        for byte in self.bytes() {
+            write!(f, " {byte:02x}")?;
+        }
+        // The text of the short line lines up with the full ones.
+        for _ in self.len..LINE_SIZE {
+            f.write_str("   ")?;
+        }
+        f.write_str("  ")?;
+        for &byte in self.bytes() {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            };
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+/// The lines of the bytes at the address.
+pub fn hex_lines(address: u64, bytes: &[u8]) -> impl Iterator<Item = HexLine> + '_ {
+    bytes
+        .chunks(LINE_SIZE)
+        .enumerate()
+        .map(move |(i, chunk)| HexLine::new(address + (i * LINE_SIZE) as u64, chunk))
+}
@@ -0,0 +1,157 @@
+//! Looking at the memory by hand: the hexdumps, and reading and searching
+//! the physical memory without touching what is not there.
+//!
+//! A [`Window`] is the physical memory the memory map describes, as seen
+//! at an offset: none where the firmware identity-maps the memory, and the
+//! base of the linear map in the kernel. Every read is checked to be in
+//! the regions of the map first, so a typo in the debug shell reads
+//! nothing rather than faults or pokes a device. The regions of the device
+//! registers are not read as reading a register may change the state of
+//! the device. The regions do not have to be normalized, the firmware map
+//! as it is will do.
+//!
+//! The bytes are read one at a time with the volatile reads, the memory
+//! may change under the reader, and nothing is to be assumed about it.
+//!
+//! Example:
+//! ```ignore
+//! let window = meminspect::Window::new(regions, linear_map_base);
+//! if let Ok(Some(address)) = window.find(0xe0000, 0x20000, b"RSD PTR ") {
+//!     for line in window.hex_lines(address, 36)? {
+//!         println!("{line}");
+//!     }
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod hexdump;
+mod search;
+mod tests;
+
+pub use hexdump::hex_lines;
+pub use hexdump::HexLine;
+pub use hexdump::LINE_SIZE;
+pub use search::find;
+pub use search::parse_pattern;
+pub use search::MAX_PATTERN_SIZE;
+
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+
+/// The bytes searched at a time, the last ones are searched again with the
+/// next chunk for the pattern that straddles the chunks.
+const SEARCH_CHUNK_SIZE: usize = 256;
+
+const _: () = assert!(SEARCH_CHUNK_SIZE > MAX_PATTERN_SIZE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InspectError {
+    /// The address is not in a readable region.
+    NotReadable { address: u64 },
+    /// The range wraps around the address space.
+    Overflow,
+    /// The pattern is empty or longer than [`MAX_PATTERN_SIZE`].
+    BadPattern,
+}
+
+/// The physical memory in the regions, accessed at `address + offset`.
+#[derive(Debug, Clone, Copy)]
+pub struct Window<'a> {
+    regions: &'a [MemoryRegion],
+    offset: u64,
+}
+
+impl<'a> Window<'a> {
+    /// The regions are mapped at the offset, and stay mapped for as long
+    /// as the window is used.
+    pub fn new(regions: &'a [MemoryRegion], offset: u64) -> Self {
+        Self { regions, offset }
+    }
+
+    fn readable_region(&self, address: u64) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| {
+            region.kind != MemoryKind::Mmio && (region.start..region.end()).contains(&address)
+        })
+    }
+
+    /// Checks the range is in the readable regions, the adjacent regions
+    /// may cover it together.
+    pub fn check(&self, address: u64, size: u64) -> Result<(), InspectError> {
+        let end = address.checked_add(size).ok_or(InspectError::Overflow)?;
+        end.checked_add(self.offset).ok_or(InspectError::Overflow)?;
+
+        let mut address = address;
+        while address < end {
+            let region = self
+                .readable_region(address)
+                .ok_or(InspectError::NotReadable { address })?;
+            address = region.end();
+        }
+        Ok(())
+    }
+
+    /// Reads the checked range.
+    fn read_unchecked(&self, address: u64, buf: &mut [u8]) {
+        let ptr = (address + self.offset) as *const u8;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            // SAFETY: the range is in the regions the window maps.
+            *byte = unsafe { ptr.add(i).read_volatile() };
+        }
+    }
+
+    /// Reads the bytes at the address.
+    pub fn read(&self, address: u64, buf: &mut [u8]) -> Result<(), InspectError> {
+        self.check(address, buf.len() as u64)?;
+        self.read_unchecked(address, buf);
+        Ok(())
+    }
+
+    /// The hexdump of the range, read a line at a time.
+    pub fn hex_lines(
+        &self,
+        address: u64,
+        size: u64,
+    ) -> Result<impl Iterator<Item = HexLine> + '_, InspectError> {
+        self.check(address, size)?;
+        let end = address + size;
+        Ok((address..end).step_by(LINE_SIZE).map(move |line_address| {
+            let mut bytes = [0; LINE_SIZE];
+            let len = (end - line_address).min(LINE_SIZE as u64) as usize;
+            self.read_unchecked(line_address, &mut bytes[..len]);
+            HexLine::new(line_address, &bytes[..len])
+        }))
+    }
+
+    /// Where the pattern first is in the range.
+    pub fn find(
+        &self,
+        address: u64,
+        size: u64,
+        pattern: &[u8],
+    ) -> Result<Option<u64>, InspectError> {
+        if pattern.is_empty() || pattern.len() > MAX_PATTERN_SIZE {
+            return Err(InspectError::BadPattern);
+        }
+        self.check(address, size)?;
+
+        let end = address + size;
+        let mut buf = [0; SEARCH_CHUNK_SIZE];
+        // The bytes from the previous chunk at the start of the buffer.
+        let mut kept = 0;
+        let mut next = address;
+        while next < end {
+            let count = ((end - next) as usize).min(buf.len() - kept);
+            self.read_unchecked(next, &mut buf[kept..kept + count]);
+            let filled = kept + count;
+            if let Some(index) = find(&buf[..filled], pattern) {
+                return Ok(Some(next - kept as u64 + index as u64));
+            }
+
+            next += count as u64;
+            kept = (pattern.len() - 1).min(filled);
+            buf.copy_within(filled - kept..filled, 0);
+        }
+        Ok(None)
+    }
+}
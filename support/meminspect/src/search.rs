@@ -0,0 +1,44 @@
+//! The byte pattern search, naive as the patterns are short and the
+//! ranges are searched by hand.
+
+/// The longest pattern to search for.
+pub const MAX_PATTERN_SIZE: usize = 64;
+
+/// Where the pattern first is in the bytes, the empty one is nowhere.
+pub fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+    bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+/// Parses the pattern: the bytes in hex in the memory order after `0x`,
+/// e.g. `0x52534420` for `RSD `, or the text as is.
+pub fn parse_pattern<'a>(s: &str, buf: &'a mut [u8; MAX_PATTERN_SIZE]) -> Option<&'a [u8]> {
+    let len = match s.strip_prefix("0x") {
+        Some(hex) => {
+            if hex.is_empty()
+                || !hex.len().is_multiple_of(2)
+                || hex.len() / 2 > buf.len()
+                || !hex.bytes().all(|c| c.is_ascii_hexdigit())
+            {
+                return None;
+            }
+            for (byte, digits) in buf.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                let digits = core::str::from_utf8(digits).ok()?;
+                *byte = u8::from_str_radix(digits, 16).ok()?;
+            }
+            hex.len() / 2
+        }
+        None => {
+            if s.is_empty() || s.len() > buf.len() {
+                return None;
+            }
+            buf[..s.len()].copy_from_slice(s.as_bytes());
+            s.len()
+        }
+    };
+    Some(&buf[..len])
+}
@@ -0,0 +1,170 @@
+#![cfg(test)]
+
+use super::*;
+
+const PAGE_SIZE: u64 = 0x1000;
+/// Where the test memory is in the made-up physical address space.
+const BASE: u64 = 0x10_0000;
+
+fn region(start: u64, pages: u64, kind: MemoryKind) -> MemoryRegion {
+    MemoryRegion {
+        start,
+        pages,
+        kind,
+        reserved: 0,
+        attributes: 0,
+    }
+}
+
+/// Three pages, the first two in the adjacent regions of the different
+/// kinds, and the last one is the device registers.
+struct Memory {
+    bytes: Vec<u8>,
+    regions: [MemoryRegion; 3],
+}
+
+impl Memory {
+    fn new() -> Self {
+        let bytes = (0..3 * PAGE_SIZE).map(|i| i as u8).collect();
+        Self {
+            bytes,
+            regions: [
+                region(BASE + 2 * PAGE_SIZE, 1, MemoryKind::Mmio),
+                region(BASE + PAGE_SIZE, 1, MemoryKind::AcpiReclaimable),
+                region(BASE, 1, MemoryKind::Usable),
+            ],
+        }
+    }
+
+    fn window(&self) -> Window<'_> {
+        Window::new(
+            &self.regions,
+            (self.bytes.as_ptr() as u64).wrapping_sub(BASE),
+        )
+    }
+}
+
+#[test]
+fn hex_line() {
+    let line = HexLine::new(0xf5a40, b"RSD PTR kBOCHS \x02");
+    assert_eq!(
+        line.to_string(),
+        "0x00000000000f5a40: 52 53 44 20 50 54 52 20 6b 42 4f 43 48 53 20 02  RSD PTR kBOCHS ."
+    );
+
+    let line = HexLine::new(0x10, b"\x00ab");
+    assert_eq!(
+        line.to_string(),
+        format!("0x0000000000000010: 00 61 62{}  .ab", " ".repeat(3 * 13))
+    );
+
+    // The rest does not fit.
+    assert_eq!(HexLine::new(0, &[0; 20]).bytes().len(), LINE_SIZE);
+
+    let lines: Vec<_> = hex_lines(0x100, &[0xaa; 40]).collect();
+    assert_eq!(
+        lines
+            .iter()
+            .map(|l| (l.address(), l.bytes().len()))
+            .collect::<Vec<_>>(),
+        [(0x100, 16), (0x110, 16), (0x120, 8)]
+    );
+}
+
+#[test]
+fn check() {
+    let memory = Memory::new();
+    let window = memory.window();
+
+    assert_eq!(window.check(BASE, PAGE_SIZE), Ok(()));
+    // Across the adjacent regions.
+    assert_eq!(window.check(BASE + 0xff0, 0x20), Ok(()));
+    assert_eq!(window.check(BASE, 2 * PAGE_SIZE), Ok(()));
+    assert_eq!(window.check(BASE, 0), Ok(()));
+
+    // Into the registers, below, and wrapping around.
+    assert_eq!(
+        window.check(BASE + 0x1ff0, 0x20),
+        Err(InspectError::NotReadable {
+            address: BASE + 2 * PAGE_SIZE
+        })
+    );
+    assert_eq!(
+        window.check(BASE - 1, 2),
+        Err(InspectError::NotReadable { address: BASE - 1 })
+    );
+    assert_eq!(window.check(u64::MAX, 2), Err(InspectError::Overflow));
+}
+
+#[test]
+fn read() {
+    let memory = Memory::new();
+    let window = memory.window();
+
+    let mut buf = [0; 4];
+    window.read(BASE + 0xffe, &mut buf).unwrap();
+    assert_eq!(buf, [0xfe, 0xff, 0x00, 0x01]);
+    assert!(window.read(BASE + 0x2000, &mut buf).is_err());
+
+    let lines: Vec<_> = window.hex_lines(BASE + 0x18, 0x20).unwrap().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].address(), BASE + 0x18);
+    assert_eq!(lines[0].bytes(), &memory.bytes[0x18..0x28]);
+    assert_eq!(lines[1].bytes(), &memory.bytes[0x28..0x38]);
+    assert!(window.hex_lines(BASE + 0x1ff0, 0x20).is_err());
+    assert!(matches!(
+        window.hex_lines(u64::MAX - 0xf, 0x20),
+        Err(InspectError::Overflow)
+    ));
+}
+
+#[test]
+fn find_in_window() {
+    let mut memory = Memory::new();
+    // Straddling the search chunks, and the regions.
+    memory.bytes[0xffc..0x1004].copy_from_slice(b"RSD PTR ");
+    memory.bytes[0x1800..0x1804].copy_from_slice(b"FACP");
+    let window = memory.window();
+
+    assert_eq!(
+        window.find(BASE, 2 * PAGE_SIZE, b"RSD PTR "),
+        Ok(Some(BASE + 0xffc))
+    );
+    assert_eq!(
+        window.find(BASE + 0x10, 2 * PAGE_SIZE - 0x10, b"FACP"),
+        Ok(Some(BASE + 0x1800))
+    );
+    // The pattern past the end of the range.
+    assert_eq!(window.find(BASE + 0x1000, 0x803, b"FACP"), Ok(None));
+    assert_eq!(window.find(BASE, 0, b"FACP"), Ok(None));
+
+    assert_eq!(window.find(BASE, 0x10, b""), Err(InspectError::BadPattern));
+    assert_eq!(
+        window.find(BASE, 3 * PAGE_SIZE, b"FACP"),
+        Err(InspectError::NotReadable {
+            address: BASE + 2 * PAGE_SIZE
+        })
+    );
+}
+
+#[test]
+fn find_in_bytes() {
+    assert_eq!(find(b"abcabc", b"ca"), Some(2));
+    assert_eq!(find(b"abc", b"abcd"), None);
+    assert_eq!(find(b"abc", b""), None);
+}
+
+#[test]
+fn parse() {
+    let mut buf = [0; MAX_PATTERN_SIZE];
+    assert_eq!(parse_pattern("0x52534420", &mut buf), Some(&b"RSD "[..]));
+    assert_eq!(parse_pattern("FACP", &mut buf), Some(&b"FACP"[..]));
+    assert_eq!(parse_pattern("0x525", &mut buf), None);
+    assert_eq!(parse_pattern("0x+f", &mut buf), None);
+    assert_eq!(parse_pattern("0x", &mut buf), None);
+    assert_eq!(parse_pattern("", &mut buf), None);
+    assert_eq!(
+        parse_pattern(&"a".repeat(MAX_PATTERN_SIZE + 1), &mut buf),
+        None
+    );
+}
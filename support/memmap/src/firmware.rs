@@ -0,0 +1,147 @@
+//! The firmware memory map entries as the [`MemoryRegion`]s.
+//!
+//! UEFI memory types, UEFI specification 7.2 `EFI_BOOT_SERVICES.AllocatePages()`:
+//!
+//! Type   Name                            Kind
+//! ----------------------------------------------------------------------
+//! 0      EfiReservedMemoryType           Reserved
+//! 1, 2   EfiLoaderCode, EfiLoaderData    LoaderReclaimable
+//! 3, 4   EfiBootServicesCode, ...Data    Usable
+//! 5, 6   EfiRuntimeServicesCode, ...Data Runtime
+//! 7      EfiConventionalMemory           Usable
+//! 8      EfiUnusableMemory               Reserved
+//! 9      EfiACPIReclaimMemory            AcpiReclaimable
+//! 10     EfiACPIMemoryNVS                AcpiNvs
+//! 11, 12 EfiMemoryMappedIO, ...PortSpace Mmio
+//! 13     EfiPalCode                      Reserved
+//! 14     EfiPersistentMemory             Persistent
+//! 15     EfiUnacceptedMemoryType         Reserved, the loader accepts none
+//!
+//! The types from `0x7000_0000` are the OEM ones, from `0x8000_0000` the
+//! OS loader ones, those are up to the loader.
+//!
+//! e820 types, ACPI specification 15.1 `Address Range Types`:
+//!
+//! Type   Name                            Kind
+//! ----------------------------------------------------------------------
+//! 1      AddressRangeMemory              Usable
+//! 2      AddressRangeReserved            Reserved
+//! 3      AddressRangeACPI                AcpiReclaimable
+//! 4      AddressRangeNVS                 AcpiNvs
+//! 5      AddressRangeUnusable            Reserved
+//! 7      AddressRangePersistentMemory    Persistent
+//!
+//! The e820 ranges are in bytes, the usable ones are shrunk to the whole
+//! pages, and the others grown.
+
+use crate::MemMapError;
+use crate::PAGE_SIZE;
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+use bootinfo::MEMORY_ATTRIBUTE_NON_VOLATILE;
+use bootinfo::MEMORY_ATTRIBUTE_UNCACHEABLE;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_BACK;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_COMBINE;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_THROUGH;
+
+/// `EFI_MEMORY_UC` and the rest.
+const EFI_MEMORY_UC: u64 = 0x1;
+const EFI_MEMORY_WC: u64 = 0x2;
+const EFI_MEMORY_WT: u64 = 0x4;
+const EFI_MEMORY_WB: u64 = 0x8;
+const EFI_MEMORY_NV: u64 = 0x8000;
+
+const E820_USABLE: u32 = 1;
+const E820_ACPI: u32 = 3;
+const E820_NVS: u32 = 4;
+const E820_PERSISTENT: u32 = 7;
+
+/// The kind of the standard UEFI memory type, `None` for the OEM and the
+/// OS loader types.
+pub fn uefi_memory_kind(ty: u32) -> Option<MemoryKind> {
+    let kind = match ty {
+        1 | 2 => MemoryKind::LoaderReclaimable,
+        3 | 4 | 7 => MemoryKind::Usable,
+        5 | 6 => MemoryKind::Runtime,
+        9 => MemoryKind::AcpiReclaimable,
+        10 => MemoryKind::AcpiNvs,
+        11 | 12 => MemoryKind::Mmio,
+        14 => MemoryKind::Persistent,
+        0x7000_0000.. => return None,
+        _ => MemoryKind::Reserved,
+    };
+    Some(kind)
+}
+
+/// The `MEMORY_ATTRIBUTE_*` of the UEFI memory attributes.
+pub fn uefi_attributes(attributes: u64) -> u64 {
+    [
+        (EFI_MEMORY_WB, MEMORY_ATTRIBUTE_WRITE_BACK),
+        (EFI_MEMORY_WT, MEMORY_ATTRIBUTE_WRITE_THROUGH),
+        (EFI_MEMORY_WC, MEMORY_ATTRIBUTE_WRITE_COMBINE),
+        (EFI_MEMORY_UC, MEMORY_ATTRIBUTE_UNCACHEABLE),
+        (EFI_MEMORY_NV, MEMORY_ATTRIBUTE_NON_VOLATILE),
+    ]
+    .iter()
+    .filter(|(uefi_attribute, _)| attributes & uefi_attribute != 0)
+    .fold(0, |attributes, (_, attribute)| attributes | attribute)
+}
+
+/// The UEFI memory descriptor, its kind is the one from
+/// [`uefi_memory_kind`] or the loader's.
+pub fn from_uefi(
+    kind: MemoryKind,
+    phys_start: u64,
+    page_count: u64,
+    attributes: u64,
+) -> Result<MemoryRegion, MemMapError> {
+    let overflow = MemMapError::Overflow {
+        start: phys_start,
+        pages: page_count,
+    };
+    page_count
+        .checked_mul(PAGE_SIZE)
+        .and_then(|size| phys_start.checked_add(size))
+        .ok_or(overflow)?;
+
+    Ok(MemoryRegion {
+        start: phys_start,
+        pages: page_count,
+        kind,
+        reserved: 0,
+        attributes: uefi_attributes(attributes),
+    })
+}
+
+/// The e820 entry, `None` if no whole page of the usable memory is in it.
+/// The attributes are not known.
+pub fn from_e820(base: u64, length: u64, ty: u32) -> Option<MemoryRegion> {
+    let kind = match ty {
+        E820_USABLE => MemoryKind::Usable,
+        E820_ACPI => MemoryKind::AcpiReclaimable,
+        E820_NVS => MemoryKind::AcpiNvs,
+        E820_PERSISTENT => MemoryKind::Persistent,
+        // The reserved, the unusable, and the unknown ones.
+        _ => MemoryKind::Reserved,
+    };
+    let end = base.saturating_add(length);
+    let (start, end) = if kind == MemoryKind::Usable {
+        (
+            base.checked_next_multiple_of(PAGE_SIZE)?,
+            end / PAGE_SIZE * PAGE_SIZE,
+        )
+    } else {
+        (
+            base / PAGE_SIZE * PAGE_SIZE,
+            end.checked_next_multiple_of(PAGE_SIZE)
+                .unwrap_or(u64::MAX / PAGE_SIZE * PAGE_SIZE),
+        )
+    };
+    (start < end).then_some(MemoryRegion {
+        start,
+        pages: (end - start) / PAGE_SIZE,
+        kind,
+        reserved: 0,
+        attributes: 0,
+    })
+}
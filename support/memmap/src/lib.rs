@@ -0,0 +1,133 @@
+//! Normalizing the physical memory maps the firmware reports.
+//!
+//! The firmware maps are lists of the typed ranges that are not promised
+//! to be sorted, to be disjoint, or to be split at the type changes only.
+//! The loader reads the UEFI map and, where there is one, the e820 map
+//! into a [`RegionList`] of the [`MemoryRegion`]s, and
+//! [normalizes](RegionList::normalize) it into what [`bootinfo::MemoryMap`]
+//! promises: the regions are sorted by address, do not overlap, and the
+//! adjacent ones of the same kind and attributes are merged. Where two
+//! regions overlap, the [stricter](strictness) kind wins, so the memory
+//! the firmware reports as both free and reserved is not handed out.
+//!
+//! The kernel seeds the page allocator with the [`PageRange`]s of the
+//! usable memory, see [`page_ranges`].
+//!
+//! Example:
+//! ```ignore
+//! let mut list = memmap::RegionList::new(storage);
+//! for entry in uefi_map.entries() {
+//!     let kind = memmap::uefi_memory_kind(entry.ty.0).unwrap_or(MemoryKind::Reserved);
+//!     list.push(memmap::from_uefi(kind, entry.phys_start, entry.page_count, entry.att.bits())?)?;
+//! }
+//! list.normalize()?;
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod firmware;
+mod normalize;
+mod tests;
+
+pub use firmware::from_e820;
+pub use firmware::from_uefi;
+pub use firmware::uefi_attributes;
+pub use firmware::uefi_memory_kind;
+pub use normalize::RegionList;
+
+use bootinfo::MemoryKind;
+use bootinfo::MemoryRegion;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemMapError {
+    /// The regions do not fit into the storage.
+    Full,
+    /// The region does not fit into the physical address space.
+    Overflow { start: u64, pages: u64 },
+}
+
+/// The pages `[start_pfn, end_pfn)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub start_pfn: u64,
+    pub end_pfn: u64,
+}
+
+impl PageRange {
+    pub fn pages(&self) -> u64 {
+        self.end_pfn - self.start_pfn
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start_pfn == self.end_pfn
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start_pfn * PAGE_SIZE
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end_pfn * PAGE_SIZE
+    }
+}
+
+impl From<&MemoryRegion> for PageRange {
+    fn from(region: &MemoryRegion) -> Self {
+        let start_pfn = region.start / PAGE_SIZE;
+        Self {
+            start_pfn,
+            end_pfn: start_pfn + region.pages,
+        }
+    }
+}
+
+/// How strict the kind is about the memory not being used by anything
+/// else, the stricter kind wins where the regions overlap:
+///
+/// 0. Usable
+/// 1. Reclaimable once the kernel is done with it
+/// 2. Allocated by the loader for the kernel
+/// 3. Used by the firmware at runtime
+/// 4. Reserved, the device registers, or the persistent memory
+pub fn strictness(kind: MemoryKind) -> u8 {
+    match kind {
+        MemoryKind::Usable => 0,
+        MemoryKind::LoaderReclaimable | MemoryKind::AcpiReclaimable => 1,
+        MemoryKind::KernelImage
+        | MemoryKind::BootInfo
+        | MemoryKind::PageTables
+        | MemoryKind::PageBitmap
+        | MemoryKind::ApTrampoline
+        | MemoryKind::BootModule
+        | MemoryKind::EarlyHeap
+        | MemoryKind::KernelStack
+        | MemoryKind::KernelSymbols
+        | MemoryKind::FramebufferConsole => 2,
+        MemoryKind::AcpiNvs | MemoryKind::Runtime => 3,
+        MemoryKind::Reserved | MemoryKind::Mmio | MemoryKind::Persistent => 4,
+    }
+}
+
+/// The ranges of the adjacent regions of the kind merged whatever their
+/// attributes are. The regions are [normalized](RegionList::normalize).
+pub fn page_ranges(
+    regions: &[MemoryRegion],
+    kind: MemoryKind,
+) -> impl Iterator<Item = PageRange> + '_ {
+    let mut regions = regions.iter().filter(move |region| region.kind == kind);
+    let mut next = regions.next().map(PageRange::from);
+    core::iter::from_fn(move || {
+        let mut range = next.take()?;
+        for region in regions.by_ref() {
+            let region = PageRange::from(region);
+            if region.start_pfn != range.end_pfn {
+                next = Some(region);
+                break;
+            }
+            range.end_pfn = region.end_pfn;
+        }
+        Some(range)
+    })
+}
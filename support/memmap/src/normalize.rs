@@ -0,0 +1,155 @@
+//! Sorting the regions, resolving the overlaps, and merging the adjacent
+//! regions, all in the storage the caller provides as there is no heap.
+//! The maps have a few hundred entries at most, so the regions are moved
+//! around rather than anything cleverer.
+//!
+//! Where two regions overlap, the overlap goes to the stricter one: the
+//! less strict region is cut short, or loses its start, or is split in
+//! two around the stricter one, the latter takes one more entry.
+
+use crate::strictness;
+use crate::MemMapError;
+use crate::PAGE_SIZE;
+use bootinfo::MemoryRegion;
+
+fn end(region: &MemoryRegion) -> u64 {
+    region.start + region.pages * PAGE_SIZE
+}
+
+fn set_end(region: &mut MemoryRegion, end: u64) {
+    region.pages = (end - region.start) / PAGE_SIZE;
+}
+
+fn same_type(a: &MemoryRegion, b: &MemoryRegion) -> bool {
+    a.kind == b.kind && a.attributes == b.attributes
+}
+
+/// The regions in the storage, the first `len` ones are used.
+pub struct RegionList<'a> {
+    storage: &'a mut [MemoryRegion],
+    len: usize,
+}
+
+impl<'a> RegionList<'a> {
+    pub fn new(storage: &'a mut [MemoryRegion]) -> Self {
+        Self { storage, len: 0 }
+    }
+
+    pub fn as_slice(&self) -> &[MemoryRegion] {
+        &self.storage[..self.len]
+    }
+
+    /// The regions with the lifetime of the storage.
+    pub fn into_slice(self) -> &'a mut [MemoryRegion] {
+        &mut self.storage[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds the region, the empty ones are dropped.
+    pub fn push(&mut self, region: MemoryRegion) -> Result<(), MemMapError> {
+        if region.pages == 0 {
+            return Ok(());
+        }
+        let slot = self.storage.get_mut(self.len).ok_or(MemMapError::Full)?;
+        *slot = region;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.storage.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+    }
+
+    /// Inserts the region after `after` keeping the regions sorted by
+    /// the start.
+    fn insert_sorted(&mut self, after: usize, region: MemoryRegion) -> Result<(), MemMapError> {
+        if self.len == self.storage.len() {
+            return Err(MemMapError::Full);
+        }
+        let index = after
+            + 1
+            + self.storage[after + 1..self.len].partition_point(|r| r.start <= region.start);
+        self.storage.copy_within(index..self.len, index + 1);
+        self.storage[index] = region;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Sorts the regions by address, gives each overlap to the stricter
+    /// region, and merges the adjacent regions of the same kind and
+    /// attributes. Fails if a split does not fit into the storage, the
+    /// regions are sorted but not normalized then.
+    pub fn normalize(&mut self) -> Result<(), MemMapError> {
+        self.storage[..self.len].sort_unstable_by_key(|region| (region.start, region.kind as u32));
+
+        // The regions before `i` do not overlap anything.
+        let mut i = 0;
+        while i + 1 < self.len {
+            let current = self.storage[i];
+            let next = self.storage[i + 1];
+            if next.start >= end(&current) {
+                i += 1;
+                continue;
+            }
+
+            if same_type(&current, &next) {
+                set_end(&mut self.storage[i], end(&current).max(end(&next)));
+                self.remove(i + 1);
+            } else if strictness(current.kind) >= strictness(next.kind) {
+                // The next one loses its start, and may go after the
+                // regions that start before its new start.
+                self.remove(i + 1);
+                if end(&next) > end(&current) {
+                    let mut rest = next;
+                    rest.start = end(&current);
+                    set_end(&mut rest, end(&next));
+                    self.insert_sorted(i, rest)?;
+                }
+            } else {
+                // The current one is cut short, and its tail past the next
+                // one goes after it.
+                let tail = (end(&current) > end(&next)).then(|| {
+                    let mut tail = current;
+                    tail.start = end(&next);
+                    set_end(&mut tail, end(&current));
+                    tail
+                });
+                let after = if next.start == current.start {
+                    self.remove(i);
+                    i
+                } else {
+                    set_end(&mut self.storage[i], next.start);
+                    i + 1
+                };
+                if let Some(tail) = tail {
+                    self.insert_sorted(after, tail)?;
+                }
+            }
+        }
+
+        self.merge_adjacent();
+        Ok(())
+    }
+
+    fn merge_adjacent(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.len {
+            let next = self.storage[i + 1];
+            let current = &mut self.storage[i];
+            if end(current) == next.start && same_type(current, &next) {
+                current.pages += next.pages;
+                self.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
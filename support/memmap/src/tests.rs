@@ -0,0 +1,386 @@
+#![cfg(test)]
+
+use super::*;
+use bootinfo::MEMORY_ATTRIBUTE_NON_VOLATILE;
+use bootinfo::MEMORY_ATTRIBUTE_UNCACHEABLE;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_BACK;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_COMBINE;
+use bootinfo::MEMORY_ATTRIBUTE_WRITE_THROUGH;
+
+const EFI_RESERVED: u32 = 0;
+const EFI_LOADER_CODE: u32 = 1;
+const EFI_LOADER_DATA: u32 = 2;
+const EFI_BOOT_SERVICES_CODE: u32 = 3;
+const EFI_BOOT_SERVICES_DATA: u32 = 4;
+const EFI_RUNTIME_SERVICES_CODE: u32 = 5;
+const EFI_RUNTIME_SERVICES_DATA: u32 = 6;
+const EFI_CONVENTIONAL: u32 = 7;
+const EFI_ACPI_RECLAIM: u32 = 9;
+const EFI_ACPI_NVS: u32 = 10;
+const EFI_MMIO: u32 = 11;
+
+/// UC | WC | WT | WB
+const EFI_CACHEABLE: u64 = 0xf;
+/// UC | RUNTIME
+const EFI_RUNTIME_MMIO: u64 = 0x8000_0000_0000_0001;
+/// UC | WC | WT | WB | RUNTIME
+const EFI_RUNTIME: u64 = 0x8000_0000_0000_000f;
+
+const ALL_CACHEABLE: u64 = MEMORY_ATTRIBUTE_WRITE_BACK
+    | MEMORY_ATTRIBUTE_WRITE_THROUGH
+    | MEMORY_ATTRIBUTE_WRITE_COMBINE
+    | MEMORY_ATTRIBUTE_UNCACHEABLE;
+
+/// The type, the start, the pages, and the attributes as `memmap` in the
+/// UEFI shell prints them. The layout of the map OVMF gives a q35 machine
+/// with 256 MiB, the low memory holes and the ACPI NVS ranges included.
+const OVMF_X86_64: &[(u32, u64, u64, u64)] = &[
+    (EFI_BOOT_SERVICES_CODE, 0x0, 0x1, EFI_CACHEABLE),
+    (EFI_CONVENTIONAL, 0x1000, 0x9f, EFI_CACHEABLE),
+    (EFI_CONVENTIONAL, 0x10_0000, 0x700, EFI_CACHEABLE),
+    (EFI_ACPI_NVS, 0x80_0000, 0x8, EFI_CACHEABLE),
+    (EFI_CONVENTIONAL, 0x80_8000, 0x3, EFI_CACHEABLE),
+    (EFI_ACPI_NVS, 0x80_b000, 0x1, EFI_CACHEABLE),
+    (EFI_CONVENTIONAL, 0x80_c000, 0x4, EFI_CACHEABLE),
+    (EFI_ACPI_NVS, 0x81_0000, 0xf0, EFI_CACHEABLE),
+    (EFI_BOOT_SERVICES_DATA, 0x90_0000, 0x500, EFI_CACHEABLE),
+    (EFI_CONVENTIONAL, 0xe0_0000, 0xd8a8, EFI_CACHEABLE),
+    (EFI_LOADER_CODE, 0xe6a_8000, 0x21, EFI_CACHEABLE),
+    (EFI_BOOT_SERVICES_DATA, 0xe6c_9000, 0x1f0, EFI_CACHEABLE),
+    (EFI_LOADER_DATA, 0xe8b_9000, 0x40, EFI_CACHEABLE),
+    (EFI_BOOT_SERVICES_CODE, 0xe8f_9000, 0xb7, EFI_CACHEABLE),
+    (EFI_RUNTIME_SERVICES_DATA, 0xe9b_0000, 0x100, EFI_RUNTIME),
+    (EFI_RUNTIME_SERVICES_CODE, 0xeab_0000, 0x100, EFI_RUNTIME),
+    (EFI_BOOT_SERVICES_DATA, 0xebb_0000, 0x3a0, EFI_CACHEABLE),
+    (EFI_ACPI_RECLAIM, 0xef5_0000, 0x10, EFI_CACHEABLE),
+    (EFI_ACPI_NVS, 0xef6_0000, 0x20, EFI_CACHEABLE),
+    (EFI_BOOT_SERVICES_DATA, 0xef8_0000, 0x80, EFI_CACHEABLE),
+    (EFI_RESERVED, 0xf00_0000, 0x1000, EFI_CACHEABLE),
+    (EFI_MMIO, 0xe000_0000, 0x1_0000, EFI_RUNTIME_MMIO),
+    (EFI_MMIO, 0xffc0_0000, 0x400, EFI_RUNTIME_MMIO),
+];
+
+/// The base, the length, and the type the SeaBIOS e820 map has for a
+/// machine with 2 GiB: the ranges are not page-aligned.
+const SEABIOS_E820: &[(u64, u64, u32)] = &[
+    (0x0, 0x9_fc00, 1),
+    (0x9_fc00, 0x400, 2),
+    (0xf_0000, 0x1_0000, 2),
+    (0x10_0000, 0x7fee_0000, 1),
+    (0x7ffe_0000, 0x2_0000, 2),
+    (0xfeff_c000, 0x4000, 2),
+    (0xfffc_0000, 0x4_0000, 2),
+];
+
+fn region(kind: MemoryKind, start: u64, pages: u64) -> MemoryRegion {
+    MemoryRegion {
+        start,
+        pages,
+        kind,
+        reserved: 0,
+        attributes: 0,
+    }
+}
+
+fn uefi_regions(map: &[(u32, u64, u64, u64)]) -> Vec<MemoryRegion> {
+    map.iter()
+        .map(|&(ty, start, pages, attributes)| {
+            from_uefi(uefi_memory_kind(ty).unwrap(), start, pages, attributes).unwrap()
+        })
+        .collect()
+}
+
+fn normalize(regions: &[MemoryRegion], capacity: usize) -> Result<Vec<MemoryRegion>, MemMapError> {
+    let mut storage = vec![region(MemoryKind::Reserved, 0, 0); capacity];
+    let mut list = RegionList::new(&mut storage);
+    for region in regions {
+        list.push(*region)?;
+    }
+    list.normalize()?;
+    Ok(list.as_slice().to_vec())
+}
+
+/// Sorted, disjoint, and merged.
+fn check_normalized(regions: &[MemoryRegion]) {
+    for pair in regions.windows(2) {
+        let end = pair[0].start + pair[0].pages * PAGE_SIZE;
+        assert!(end <= pair[1].start, "{pair:x?} overlap");
+        assert!(
+            end < pair[1].start
+                || pair[0].kind != pair[1].kind
+                || pair[0].attributes != pair[1].attributes,
+            "{pair:x?} not merged"
+        );
+    }
+    assert!(regions.iter().all(|region| region.pages != 0));
+}
+
+fn pages_of(regions: &[MemoryRegion], kind: MemoryKind) -> u64 {
+    regions
+        .iter()
+        .filter(|region| region.kind == kind)
+        .map(|region| region.pages)
+        .sum()
+}
+
+#[test]
+fn ovmf_map() {
+    let regions = uefi_regions(OVMF_X86_64);
+    // The firmware sorts the map, the loader must not rely on that.
+    let mut shuffled = regions.clone();
+    shuffled.reverse();
+    shuffled.swap(3, 11);
+
+    let normalized = normalize(&shuffled, regions.len()).unwrap();
+    check_normalized(&normalized);
+    for kind in [
+        MemoryKind::Usable,
+        MemoryKind::LoaderReclaimable,
+        MemoryKind::Runtime,
+        MemoryKind::AcpiReclaimable,
+        MemoryKind::AcpiNvs,
+        MemoryKind::Mmio,
+        MemoryKind::Reserved,
+    ] {
+        assert_eq!(
+            pages_of(&normalized, kind),
+            pages_of(&regions, kind),
+            "{kind:?}"
+        );
+    }
+    assert_eq!(normalized.len(), 20);
+    assert_eq!(normalized, normalize(&regions, regions.len()).unwrap());
+
+    // The boot services memory is usable, and merges with the free memory
+    // around it.
+    let usable: Vec<_> = page_ranges(&normalized, MemoryKind::Usable).collect();
+    assert_eq!(
+        usable,
+        [
+            PageRange {
+                start_pfn: 0x0,
+                end_pfn: 0xa0
+            },
+            PageRange {
+                start_pfn: 0x100,
+                end_pfn: 0x800
+            },
+            PageRange {
+                start_pfn: 0x808,
+                end_pfn: 0x80b
+            },
+            PageRange {
+                start_pfn: 0x80c,
+                end_pfn: 0x810
+            },
+            PageRange {
+                start_pfn: 0x900,
+                end_pfn: 0xe6a8
+            },
+            PageRange {
+                start_pfn: 0xe6c9,
+                end_pfn: 0xe8b9
+            },
+            PageRange {
+                start_pfn: 0xe8f9,
+                end_pfn: 0xe9b0
+            },
+            PageRange {
+                start_pfn: 0xebb0,
+                end_pfn: 0xef50
+            },
+            PageRange {
+                start_pfn: 0xef80,
+                end_pfn: 0xf000
+            },
+        ]
+    );
+    assert_eq!(usable[4].start(), 0x90_0000);
+    assert_eq!(usable[4].end(), 0xe6a_8000);
+    assert_eq!(
+        normalized[0].attributes, ALL_CACHEABLE,
+        "the attributes are translated"
+    );
+}
+
+#[test]
+fn seabios_e820() {
+    let regions: Vec<_> = SEABIOS_E820
+        .iter()
+        .filter_map(|&(base, length, ty)| from_e820(base, length, ty))
+        .collect();
+    let normalized = normalize(&regions, regions.len()).unwrap();
+    check_normalized(&normalized);
+
+    assert_eq!(
+        normalized,
+        [
+            // The usable memory is shrunk to the whole pages, the reserved
+            // grown.
+            region(MemoryKind::Usable, 0x0, 0x9f),
+            region(MemoryKind::Reserved, 0x9_f000, 0x1),
+            region(MemoryKind::Reserved, 0xf_0000, 0x10),
+            region(MemoryKind::Usable, 0x10_0000, 0x7fee0),
+            region(MemoryKind::Reserved, 0x7ffe_0000, 0x20),
+            region(MemoryKind::Reserved, 0xfeff_c000, 0x4),
+            region(MemoryKind::Reserved, 0xfffc_0000, 0x40),
+        ]
+    );
+}
+
+#[test]
+fn e820_rounding() {
+    assert_eq!(from_e820(0x1800, 0x800, 1), None);
+    assert_eq!(
+        from_e820(0x1800, 0x2000, 1),
+        Some(region(MemoryKind::Usable, 0x2000, 1))
+    );
+    assert_eq!(
+        from_e820(0x1800, 0x800, 2),
+        Some(region(MemoryKind::Reserved, 0x1000, 1))
+    );
+    assert_eq!(
+        from_e820(0x1000, 0x1000, 4),
+        Some(region(MemoryKind::AcpiNvs, 0x1000, 1))
+    );
+    assert_eq!(from_e820(0x1000, 0, 2), None);
+    // The last page of the address space cannot be described.
+    assert_eq!(from_e820(u64::MAX - 0xfff, 0x2000, 2), None);
+}
+
+#[test]
+fn overlaps_go_to_the_stricter_kind() {
+    use MemoryKind::*;
+
+    // Reserved in the middle of the usable memory splits it.
+    assert_eq!(
+        normalize(&[region(Usable, 0, 16), region(Reserved, 0x4000, 4)], 3).unwrap(),
+        [
+            region(Usable, 0, 4),
+            region(Reserved, 0x4000, 4),
+            region(Usable, 0x8000, 8),
+        ]
+    );
+    // The usable memory in the reserved one is gone.
+    assert_eq!(
+        normalize(&[region(Reserved, 0, 16), region(Usable, 0x4000, 4)], 2).unwrap(),
+        [region(Reserved, 0, 16)]
+    );
+    // The partial overlaps either way.
+    assert_eq!(
+        normalize(&[region(Usable, 0, 8), region(AcpiNvs, 0x4000, 8)], 2).unwrap(),
+        [region(Usable, 0, 4), region(AcpiNvs, 0x4000, 8)]
+    );
+    assert_eq!(
+        normalize(&[region(AcpiNvs, 0, 8), region(Usable, 0x4000, 8)], 2).unwrap(),
+        [region(AcpiNvs, 0, 8), region(Usable, 0x8000, 4)]
+    );
+    // The same start.
+    assert_eq!(
+        normalize(&[region(Usable, 0, 8), region(KernelImage, 0, 2)], 2).unwrap(),
+        [region(KernelImage, 0, 2), region(Usable, 0x2000, 6)]
+    );
+    // The same kind merges.
+    assert_eq!(
+        normalize(&[region(Usable, 0, 8), region(Usable, 0x4000, 8)], 2).unwrap(),
+        [region(Usable, 0, 12)]
+    );
+    // The rest of the loser goes after the regions it has been pushed past.
+    assert_eq!(
+        normalize(
+            &[
+                region(Mmio, 0, 4),
+                region(Usable, 0x1000, 8),
+                region(AcpiReclaimable, 0x5000, 1),
+            ],
+            4
+        )
+        .unwrap(),
+        [
+            region(Mmio, 0, 4),
+            region(Usable, 0x4000, 1),
+            region(AcpiReclaimable, 0x5000, 1),
+            region(Usable, 0x6000, 3),
+        ]
+    );
+}
+
+#[test]
+fn attributes_keep_regions_apart() {
+    let mut uncached = region(MemoryKind::Usable, 0x4000, 4);
+    uncached.attributes = MEMORY_ATTRIBUTE_UNCACHEABLE;
+    let regions = normalize(&[region(MemoryKind::Usable, 0, 4), uncached], 2).unwrap();
+    assert_eq!(regions.len(), 2);
+
+    // The page ranges do not care.
+    assert_eq!(
+        page_ranges(&regions, MemoryKind::Usable).collect::<Vec<_>>(),
+        [PageRange {
+            start_pfn: 0,
+            end_pfn: 8
+        }]
+    );
+    assert_eq!(page_ranges(&regions, MemoryKind::Reserved).count(), 0);
+}
+
+#[test]
+fn storage_full() {
+    let mut storage = [region(MemoryKind::Reserved, 0, 0); 2];
+    let mut list = RegionList::new(&mut storage);
+    list.push(region(MemoryKind::Usable, 0, 16)).unwrap();
+    // The empty regions take no space.
+    list.push(region(MemoryKind::Mmio, 0x8000, 0)).unwrap();
+    list.push(region(MemoryKind::Reserved, 0x4000, 4)).unwrap();
+    assert_eq!(
+        list.push(region(MemoryKind::Usable, 0x20000, 1)),
+        Err(MemMapError::Full)
+    );
+    assert_eq!(list.len(), 2);
+    // The split needs a third entry.
+    assert_eq!(list.normalize(), Err(MemMapError::Full));
+}
+
+#[test]
+fn uefi_types() {
+    assert_eq!(uefi_memory_kind(EFI_CONVENTIONAL), Some(MemoryKind::Usable));
+    assert_eq!(
+        uefi_memory_kind(EFI_BOOT_SERVICES_DATA),
+        Some(MemoryKind::Usable)
+    );
+    assert_eq!(
+        uefi_memory_kind(EFI_LOADER_DATA),
+        Some(MemoryKind::LoaderReclaimable)
+    );
+    assert_eq!(uefi_memory_kind(8), Some(MemoryKind::Reserved));
+    assert_eq!(uefi_memory_kind(15), Some(MemoryKind::Reserved));
+    assert_eq!(uefi_memory_kind(0x7000_0000), None);
+    assert_eq!(uefi_memory_kind(0x8000_0001), None);
+
+    assert_eq!(uefi_attributes(EFI_CACHEABLE), ALL_CACHEABLE);
+    assert_eq!(
+        uefi_attributes(EFI_RUNTIME_MMIO),
+        MEMORY_ATTRIBUTE_UNCACHEABLE
+    );
+    assert_eq!(
+        uefi_attributes(0x8008),
+        MEMORY_ATTRIBUTE_NON_VOLATILE | MEMORY_ATTRIBUTE_WRITE_BACK
+    );
+
+    assert_eq!(
+        from_uefi(MemoryKind::Usable, u64::MAX - 0xfff, 2, 0),
+        Err(MemMapError::Overflow {
+            start: u64::MAX - 0xfff,
+            pages: 2
+        })
+    );
+}
+
+#[test]
+fn persistent_memory_is_not_usable() {
+    assert_eq!(uefi_memory_kind(14), Some(MemoryKind::Persistent));
+    // Grown to the whole pages as the reserved ranges are.
+    assert_eq!(
+        from_e820(0x1800, 0x800, 7),
+        Some(region(MemoryKind::Persistent, 0x1000, 1))
+    );
+}
@@ -0,0 +1,159 @@
+//! The typed device registers over the volatile reads and writes.
+//!
+//! A register is its offset in the register block, its width, and how it
+//! can be accessed, all in its type:
+//!
+//! ```ignore
+//! const DR: Register<u32, ReadWrite> = Register::new(0x000);
+//! const FR: Register<u32, ReadOnly> = Register::new(0x018);
+//! const ICR: Register<u32, WriteOnly> = Register::new(0x044);
+//!
+//! // SAFETY: the registers are mapped with the device attributes.
+//! let regs = unsafe { RegisterBlock::new(base_addr) };
+//! while regs.read(FR) & FR_TX_FULL != 0 {}
+//! regs.write(DR, byte.into());
+//! ```
+//!
+//! Reading [`ReadOnly`] or [`ReadWrite`] registers and writing
+//! [`WriteOnly`] or [`ReadWrite`] ones compiles, the rest does not, as in
+//! writing to `FR` or reading `ICR` above. The block is a copyable
+//! handle, the drivers are the ones to decide who may write through it.
+//!
+//! The accesses are exactly as wide as the register, and never split or
+//! merged, the devices often care.
+
+#![cfg_attr(not(test), no_std)]
+
+mod tests;
+
+use core::marker::PhantomData;
+
+/// The register can be read.
+pub trait Readable {}
+/// The register can be written.
+pub trait Writable {}
+
+/// Reading has no side effects on the device unless its documentation
+/// says so, e.g. popping a FIFO.
+#[derive(Debug, Clone, Copy)]
+pub enum ReadOnly {}
+#[derive(Debug, Clone, Copy)]
+pub enum WriteOnly {}
+#[derive(Debug, Clone, Copy)]
+pub enum ReadWrite {}
+
+impl Readable for ReadOnly {}
+impl Readable for ReadWrite {}
+impl Writable for WriteOnly {}
+impl Writable for ReadWrite {}
+
+/// The widths the registers can have.
+pub trait Width: Copy {
+    /// # Safety
+    ///
+    /// The address is mapped, and aligned to the width.
+    unsafe fn read(address: u64) -> Self;
+
+    /// # Safety
+    ///
+    /// The address is mapped, and aligned to the width.
+    unsafe fn write(address: u64, value: Self);
+}
+
+macro_rules! impl_width {
+    ($($ty:ty),*) => {
+        $(
+            impl Width for $ty {
+                unsafe fn read(address: u64) -> Self {
+                    // SAFETY: guaranteed by the caller.
+                    unsafe { core::ptr::read_volatile(address as *const $ty) }
+                }
+
+                unsafe fn write(address: u64, value: Self) {
+                    // SAFETY: guaranteed by the caller.
+                    unsafe { core::ptr::write_volatile(address as *mut $ty, value) }
+                }
+            }
+        )*
+    };
+}
+
+impl_width!(u8, u16, u32, u64);
+
+/// The register at `offset` in the block.
+#[derive(Debug)]
+pub struct Register<T: Width, A> {
+    offset: u64,
+    _marker: PhantomData<(T, A)>,
+}
+
+impl<T: Width, A> Clone for Register<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Width, A> Copy for Register<T, A> {}
+
+impl<T: Width, A> Register<T, A> {
+    pub const fn new(offset: u64) -> Self {
+        assert!(
+            offset.is_multiple_of(core::mem::size_of::<T>() as u64),
+            "The register is not aligned"
+        );
+        Self {
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The register `index` widths further, e.g. in an array of them.
+    pub const fn at(self, index: u64) -> Self {
+        Self::new(self.offset + index * core::mem::size_of::<T>() as u64)
+    }
+
+    pub const fn offset(self) -> u64 {
+        self.offset
+    }
+}
+
+/// The registers of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterBlock {
+    base_addr: u64,
+}
+
+impl RegisterBlock {
+    /// # Safety
+    ///
+    /// The registers are mapped at `base_addr` with the device attributes,
+    /// and the block is as large as the largest offset used with it.
+    pub const unsafe fn new(base_addr: u64) -> Self {
+        Self { base_addr }
+    }
+
+    pub const fn base_addr(&self) -> u64 {
+        self.base_addr
+    }
+
+    pub fn read<T: Width, A: Readable>(&self, reg: Register<T, A>) -> T {
+        // SAFETY: the registers are mapped, see `new`, and aligned, see
+        // `Register::new`.
+        unsafe { T::read(self.base_addr + reg.offset) }
+    }
+
+    pub fn write<T: Width, A: Writable>(&self, reg: Register<T, A>, value: T) {
+        // SAFETY: the registers are mapped, see `new`, and aligned, see
+        // `Register::new`.
+        unsafe { T::write(self.base_addr + reg.offset, value) }
+    }
+
+    /// Reads, changes, and writes back the register.
+    pub fn modify<T: Width, A: Readable + Writable>(
+        &self,
+        reg: Register<T, A>,
+        f: impl FnOnce(T) -> T,
+    ) {
+        self.write(reg, f(self.read(reg)))
+    }
+}
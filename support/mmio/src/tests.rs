@@ -0,0 +1,73 @@
+#![cfg(test)]
+
+use super::*;
+
+const DATA: Register<u32, ReadWrite> = Register::new(0x00);
+const STATUS: Register<u8, ReadOnly> = Register::new(0x04);
+const CLEAR: Register<u16, WriteOnly> = Register::new(0x06);
+const COUNTER: Register<u64, ReadWrite> = Register::new(0x08);
+const IDS: Register<u32, ReadOnly> = Register::new(0x10);
+
+#[repr(C, align(8))]
+struct Device([u8; 0x20]);
+
+impl Device {
+    fn new() -> Box<Self> {
+        Box::new(Self([0; 0x20]))
+    }
+
+    fn block(&mut self) -> RegisterBlock {
+        // SAFETY: the memory lives as long as the device.
+        unsafe { RegisterBlock::new(self.0.as_mut_ptr() as u64) }
+    }
+}
+
+#[test]
+fn widths() {
+    let mut device = Device::new();
+    let regs = device.block();
+    regs.write(DATA, 0x1122_3344);
+    regs.write(CLEAR, 0xaabb);
+    regs.write(COUNTER, 0x0102_0304_0506_0708);
+    assert_eq!(
+        device.0[..0x10],
+        [
+            0x44, 0x33, 0x22, 0x11, 0x00, 0x00, 0xbb, 0xaa, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03,
+            0x02, 0x01
+        ]
+    );
+
+    device.0[4] = 0x5a;
+    let regs = device.block();
+    assert_eq!(regs.read(STATUS), 0x5a);
+    assert_eq!(regs.read(DATA), 0x1122_3344);
+    assert_eq!(regs.read(COUNTER), 0x0102_0304_0506_0708);
+}
+
+#[test]
+fn modify() {
+    let mut device = Device::new();
+    let regs = device.block();
+    regs.write(DATA, 0x0f);
+    regs.modify(DATA, |value| value | 0x100);
+    regs.modify(DATA, |value| value & !0x1);
+    assert_eq!(regs.read(DATA), 0x10e);
+}
+
+#[test]
+fn array() {
+    let mut device = Device::new();
+    for (i, id) in device.0[0x10..0x20].chunks_mut(4).enumerate() {
+        id.copy_from_slice(&(0xf0 + i as u32).to_le_bytes());
+    }
+    let regs = device.block();
+    assert_eq!(IDS.at(3).offset(), 0x1c);
+    let ids: Vec<u32> = (0..4).map(|i| regs.read(IDS.at(i))).collect();
+    assert_eq!(ids, [0xf0, 0xf1, 0xf2, 0xf3]);
+}
+
+#[test]
+#[should_panic(expected = "The register is not aligned")]
+fn unaligned() {
+    let _ = Register::<u32, ReadOnly>::new(0x02);
+}
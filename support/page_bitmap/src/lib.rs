@@ -32,11 +32,18 @@
 //! allocators. This implementation uses hierarchical tracking,
 //! bitmaps, and fast bit-searching (in progress). The coarsest
 //! levels are stored first in hopes to be cache-friendly.
+//!
+//! The memory can be partitioned into domains, e.g. the NUMA nodes
+//! the boot loader reports, to allocate the pages local to a node.
+//!
+//! The levels can be kept in one piece of storage, and the bitmap has no
+//! pointers in it, so the boot loader builds it, and the kernel takes it
+//! over at whatever address it maps the storage.
 
 #![cfg_attr(not(test), no_std)]
 
 const PAGE_BITMAP_LEVEL_NUMBER: usize = 8;
-const MAX_MEMORY_SUPPORTED_BYTES: usize = 64 << 30;
+pub const MAX_MEMORY_SUPPORTED_BYTES: usize = 64 << 30;
 const BLOCK_SIZE: usize = 4096;
 
 mod tests;
@@ -45,6 +52,12 @@ mod tests;
 pub enum PageBitMapError {
     AlreadyAllocated,
     NotAllocated,
+    /// The bit of the group at the level does not say whether the group
+    /// of the level below is fully allocated.
+    Inconsistent {
+        level: usize,
+        group: usize,
+    },
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -66,6 +79,13 @@ pub struct MemoryMapEntry {
     allocated: bool,
 }
 
+/// A range of pages, `[start_pfn, end_pfn)`, of a memory domain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PageBitmapDomain {
+    pub start_pfn: usize,
+    pub end_pfn: usize,
+}
+
 fn cttz(_byte: u8) -> u8 {
     // TODO: the instrinsics are unstable, asm or bit twiddling for starters.
     0
@@ -103,6 +123,20 @@ pub const fn page_bitmap_level_size(max_memory: usize) -> [usize; PAGE_BITMAP_LE
     bitmap_size
 }
 
+/// The size of the storage for all the levels in one piece, see
+/// [`PageBitmap::from_storage`].
+pub const fn page_bitmap_storage_size(max_memory: usize) -> usize {
+    let bitmap_size = page_bitmap_level_size(max_memory);
+    let mut size = 0;
+    let mut i = 0;
+    while i < PAGE_BITMAP_LEVEL_NUMBER {
+        // `PageBitmap::new` wants one byte more per level than it uses.
+        size += bitmap_size[i] + 1;
+        i += 1;
+    }
+    size
+}
+
 /// A hierarchical bitmap system to track memory allocation using
 /// 8 hierarchical levels to cover up to 64 GiB of memory with
 /// 4 KiB pages.
@@ -110,6 +144,7 @@ pub const fn page_bitmap_level_size(max_memory: usize) -> [usize; PAGE_BITMAP_LE
 pub struct PageBitmap<'a> {
     levels: [&'a mut [u8]; PAGE_BITMAP_LEVEL_NUMBER],
     max_memory: usize,
+    domains: &'a [PageBitmapDomain],
 }
 
 impl<'a> PageBitmap<'a> {
@@ -142,13 +177,102 @@ impl<'a> PageBitmap<'a> {
         {
             // Initialize the page bitmap
         }
-        Self { max_memory, levels }
+        Self {
+            max_memory,
+            levels,
+            domains: &[],
+        }
+    }
+
+    /// Creates a `PageBitmap` with the levels one after another in the
+    /// storage of [`page_bitmap_storage_size`] bytes, the lowest level
+    /// first. The bitmap starts as the storage has it, all zeroes is all
+    /// free.
+    pub fn from_storage(storage: &'a mut [u8], max_memory: usize) -> Self {
+        let storage_size = page_bitmap_storage_size(max_memory);
+        assert!(
+            storage.len() >= storage_size,
+            "The bitmap storage must be at least {storage_size} bytes of size"
+        );
+
+        let mut storage = storage;
+        let levels = page_bitmap_level_size(max_memory).map(|size| {
+            let (level, rest) = core::mem::take(&mut storage).split_at_mut(size + 1);
+            storage = rest;
+            level
+        });
+        Self::new(levels, max_memory, || None)
+    }
+
+    /// Takes over the bitmap that [`PageBitmap::from_storage`] has
+    /// created, possibly at another address.
+    ///
+    /// # Safety
+    ///
+    /// `storage` points to [`page_bitmap_storage_size`] bytes that hold the
+    /// bitmap for `max_memory`, and nothing else uses them for `'a`.
+    pub unsafe fn from_ptr(storage: *mut u8, max_memory: usize) -> Self {
+        // SAFETY: guaranteed by the caller.
+        let storage = unsafe {
+            core::slice::from_raw_parts_mut(storage, page_bitmap_storage_size(max_memory))
+        };
+        Self::from_storage(storage, max_memory)
     }
 
     pub fn max_memory(&self) -> usize {
         self.max_memory
     }
 
+    /// Partitions the memory into the domains. The domains must not
+    /// overlap, and the pages outside of any belong to no domain.
+    pub fn set_domains(&mut self, domains: &'a [PageBitmapDomain]) {
+        let max_pfn = self.max_memory / BLOCK_SIZE;
+        for (index, domain) in domains.iter().enumerate() {
+            assert!(
+                domain.start_pfn <= domain.end_pfn && domain.end_pfn <= max_pfn,
+                "Domain {index} is outside of the tracked memory"
+            );
+            assert!(
+                domains[..index].iter().all(|other| {
+                    domain.end_pfn <= other.start_pfn || other.end_pfn <= domain.start_pfn
+                }),
+                "Domain {index} overlaps another one"
+            );
+        }
+        self.domains = domains;
+    }
+
+    pub fn domains(&self) -> &[PageBitmapDomain] {
+        self.domains
+    }
+
+    /// The domain the page belongs to.
+    pub fn domain_of(&self, page_number: usize) -> Option<usize> {
+        self.domains
+            .iter()
+            .position(|domain| (domain.start_pfn..domain.end_pfn).contains(&page_number))
+    }
+
+    /// Finds the first free page in the domain.
+    pub fn find_free_page_in_domain(&self, domain: usize) -> Option<usize> {
+        let PageBitmapDomain { start_pfn, end_pfn } = *self.domains.get(domain)?;
+
+        let mut page = start_pfn;
+        while page < end_pfn {
+            // Skip the fully allocated bytes of Level 0 at once
+            if page % 8 == 0 && page + 8 <= end_pfn && self.levels[0][page / 8] == 0xFF {
+                page += 8;
+                continue;
+            }
+            if !self.is_page_allocated(page) {
+                return Some(page);
+            }
+            page += 1;
+        }
+
+        None
+    }
+
     /// Allocates a 4 KiB page, updating all levels accordingly.
     /// TODO: return an error if the page is already allocated.
     pub fn allocate_page(&mut self, page_number: usize) -> Result<(), PageBitMapError> {
@@ -195,37 +319,29 @@ impl<'a> PageBitmap<'a> {
     }
 
     fn update_higher_levels(&mut self, page_number: usize) {
-        let mut current_page = page_number;
-        for level in 1..PAGE_BITMAP_LEVEL_NUMBER - 1 {
-            let group_index = current_page / 8;
-            self.update_level(level, group_index);
-            current_page /= 8;
+        let bitmap_size = page_bitmap_level_size(self.max_memory);
+        let mut group_number = page_number / 8;
+        for (level, &size) in bitmap_size.iter().enumerate().skip(1) {
+            if size == 0 {
+                break;
+            }
+            self.update_level(level, group_number);
+            group_number /= 8;
         }
     }
 
+    /// Marks the group of the lower level units as allocated if all of them
+    /// are, and as free otherwise.
     fn update_level(&mut self, level: usize, group_number: usize) {
-        let start_unit = group_number * 8;
-        let end_unit = start_unit + 8;
-
-        let mut group_free = true;
-
-        for unit in start_unit..end_unit {
-            let byte_index = unit / 8;
-            let bit_index = unit % 8;
-
-            if (self.levels[level + 1][byte_index] & (1 << bit_index)) != 0 {
-                group_free = false;
-                break;
-            }
-        }
+        let group_allocated = self.levels[level - 1][group_number] == 0xFF;
 
         let byte_index = group_number / 8;
         let bit_index = group_number % 8;
 
-        if group_free {
-            self.levels[level][byte_index] &= !(1 << bit_index); // Mark group as free
-        } else {
+        if group_allocated {
             self.levels[level][byte_index] |= 1 << bit_index; // Mark group as allocated
+        } else {
+            self.levels[level][byte_index] &= !(1 << bit_index); // Mark group as free
         }
     }
 
@@ -258,6 +374,32 @@ impl<'a> PageBitmap<'a> {
         (current_group..current_group + 8).find(|&page| !self.is_page_allocated(page))
     }
 
+    /// The number of the allocated pages in `[start_pfn, end_pfn)`.
+    pub fn allocated_pages(&self, start_pfn: usize, end_pfn: usize) -> usize {
+        (start_pfn..end_pfn)
+            .filter(|&page| self.is_page_allocated(page))
+            .count()
+    }
+
+    /// Checks that the bits of the higher levels agree with the levels
+    /// below them.
+    pub fn check(&self) -> Result<(), PageBitMapError> {
+        let bitmap_size = page_bitmap_level_size(self.max_memory);
+        for (level, &size) in bitmap_size.iter().enumerate().skip(1) {
+            if size == 0 {
+                break;
+            }
+            for group in 0..bitmap_size[level - 1] {
+                let group_allocated = self.levels[level - 1][group] == 0xFF;
+                let marked_allocated = self.levels[level][group / 8] & (1 << (group % 8)) != 0;
+                if group_allocated != marked_allocated {
+                    return Err(PageBitMapError::Inconsistent { level, group });
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Is the block allocated?
     pub fn is_block_allocated(_block_size: PageBitMapBlock) -> bool {
         todo!()
@@ -1,6 +1,11 @@
 #![cfg(test)]
 
 use crate::page_bitmap_level_size;
+use crate::page_bitmap_storage_size;
+use crate::PageBitMapError;
+use crate::PageBitmap;
+use crate::PageBitmapDomain;
+use crate::PAGE_BITMAP_LEVEL_NUMBER;
 
 #[test]
 fn test_page_bitmap_size() {
@@ -40,3 +45,134 @@ fn test_page_bitmap_size() {
     let size = page_bitmap_level_size(max_memory);
     assert!(size == [2097152, 262144, 32768, 4096, 512, 64, 8, 1]);
 }
+
+#[test]
+fn test_page_bitmap_domains() {
+    let max_memory = 1 << 20;
+    let sizes = page_bitmap_level_size(max_memory);
+    let mut storage: Vec<Vec<u8>> = sizes.iter().map(|&size| vec![0; size + 1]).collect();
+    let levels: [&mut [u8]; PAGE_BITMAP_LEVEL_NUMBER] = storage
+        .iter_mut()
+        .map(|level| level.as_mut_slice())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    let mut bitmap = PageBitmap::new(levels, max_memory, || None);
+
+    let domains = [
+        PageBitmapDomain {
+            start_pfn: 0,
+            end_pfn: 100,
+        },
+        PageBitmapDomain {
+            start_pfn: 100,
+            end_pfn: 256,
+        },
+    ];
+    bitmap.set_domains(&domains);
+    assert_eq!(bitmap.domain_of(99), Some(0));
+    assert_eq!(bitmap.domain_of(100), Some(1));
+    assert_eq!(bitmap.domain_of(256), None);
+
+    assert_eq!(bitmap.find_free_page_in_domain(1), Some(100));
+    for page in 100..120 {
+        bitmap.allocate_page(page).unwrap();
+    }
+    assert_eq!(bitmap.find_free_page_in_domain(1), Some(120));
+    assert_eq!(bitmap.find_free_page_in_domain(0), Some(0));
+
+    bitmap.free_page(110).unwrap();
+    assert_eq!(bitmap.find_free_page_in_domain(1), Some(110));
+
+    for page in 0..100 {
+        bitmap.allocate_page(page).unwrap();
+    }
+    assert_eq!(bitmap.find_free_page_in_domain(0), None);
+    assert_eq!(bitmap.find_free_page_in_domain(2), None);
+}
+
+#[test]
+#[should_panic(expected = "overlaps")]
+fn test_page_bitmap_overlapping_domains() {
+    let max_memory = 1 << 20;
+    let sizes = page_bitmap_level_size(max_memory);
+    let mut storage: Vec<Vec<u8>> = sizes.iter().map(|&size| vec![0; size + 1]).collect();
+    let levels: [&mut [u8]; PAGE_BITMAP_LEVEL_NUMBER] = storage
+        .iter_mut()
+        .map(|level| level.as_mut_slice())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    let mut bitmap = PageBitmap::new(levels, max_memory, || None);
+
+    let domains = [
+        PageBitmapDomain {
+            start_pfn: 0,
+            end_pfn: 100,
+        },
+        PageBitmapDomain {
+            start_pfn: 50,
+            end_pfn: 150,
+        },
+    ];
+    bitmap.set_domains(&domains);
+}
+
+#[test]
+fn test_page_bitmap_check() {
+    let max_memory = (1 << 20) + 4096;
+    let sizes = page_bitmap_level_size(max_memory);
+    let mut storage: Vec<Vec<u8>> = sizes.iter().map(|&size| vec![0; size + 1]).collect();
+    let levels: [&mut [u8]; PAGE_BITMAP_LEVEL_NUMBER] = storage
+        .iter_mut()
+        .map(|level| level.as_mut_slice())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    let mut bitmap = PageBitmap::new(levels, max_memory, || None);
+    assert!(bitmap.check().is_ok());
+
+    for page in 0..72 {
+        bitmap.allocate_page(page).unwrap();
+    }
+    bitmap.allocate_page(256).unwrap();
+    assert!(bitmap.check().is_ok());
+    assert_eq!(bitmap.allocated_pages(0, 257), 73);
+    assert_eq!(bitmap.allocated_pages(64, 128), 8);
+
+    bitmap.free_page(3).unwrap();
+    assert!(bitmap.check().is_ok());
+    assert_eq!(bitmap.allocated_pages(0, 257), 72);
+
+    bitmap.levels[1][1] ^= 1;
+    assert!(matches!(
+        bitmap.check(),
+        Err(PageBitMapError::Inconsistent { level: 1, group: 8 })
+    ));
+}
+
+#[test]
+fn test_page_bitmap_from_ptr() {
+    let max_memory = (1 << 21) + 4096;
+    assert_eq!(page_bitmap_storage_size(max_memory), 65 + 8 + 1 + 8);
+
+    let mut storage = vec![0; page_bitmap_storage_size(max_memory)];
+    {
+        let mut bitmap = PageBitmap::from_storage(&mut storage, max_memory);
+        for page in 0..64 {
+            bitmap.allocate_page(page).unwrap();
+        }
+        bitmap.allocate_page(512).unwrap();
+    }
+
+    // Somewhere else, as the kernel sees the bitmap the loader has built.
+    let mut moved = storage.clone();
+    // SAFETY: the storage holds the bitmap, and is not used otherwise.
+    let mut bitmap = unsafe { PageBitmap::from_ptr(moved.as_mut_ptr(), max_memory) };
+    assert!(bitmap.check().is_ok());
+    assert_eq!(bitmap.allocated_pages(0, max_memory / 4096), 65);
+    assert!(bitmap.is_page_allocated(512));
+    assert!(!bitmap.is_page_allocated(64));
+    bitmap.free_page(512).unwrap();
+    assert!(bitmap.check().is_ok());
+}
@@ -0,0 +1,205 @@
+//! The VMSAv8-64 translation tables with the 4 KiB granule and 48-bit
+//! virtual addresses. The level `3` tables are the level `0` ones in the
+//! Arm terms.
+
+use crate::BlockSize;
+use crate::EntryFormat;
+use crate::MapError;
+use crate::MapStats;
+use crate::TableAllocator;
+use crate::Tables;
+use bitfield_struct::bitfield;
+
+#[bitfield(u64, default = false)]
+pub struct PageTableEntry {
+    pub valid: bool,
+    pub table: bool, // Use PageBlockEntry if `false`
+    #[bits(10)]
+    _mbz0: u64,
+    #[bits(35)]
+    pub next_table_pfn: u64,
+    #[bits(12)]
+    _mbz1: u64,
+    pub priv_x_never: bool,
+    pub user_x_never: bool,
+    // NoEffect = 0b00,
+    // PrivOnly = 0b01,
+    // ReadOnly = 0b10,
+    // PrivReadOnly = 0b11
+    #[bits(2)]
+    pub access_perm: u64,
+    pub non_secure: bool,
+}
+
+#[bitfield(u64, default = false)]
+pub struct PageBlockEntry {
+    pub valid: bool,
+    pub page: bool,
+    #[bits(3)]
+    pub mair_idx: usize,
+    #[bits(1)]
+    _mbz0: u64,
+    // PrivOnly = 0b00,
+    // ReadWrite = 0b01,
+    // PrivReadOnly = 0b10,
+    // ReadOnly = 0b11
+    #[bits(2)]
+    pub access_perm: u64,
+    // NonShareable = 0b00,
+    // OuterShareable = 0b10,
+    // InnerShareable = 0b11
+    #[bits(2)]
+    pub share_perm: u64,
+    pub accessed: bool,
+    pub not_global: bool,
+    #[bits(35)]
+    pub address_pfn: u64,
+    #[bits(4)]
+    _mbz1: u64,
+    pub dirty: bool,
+    pub contig: bool,
+    pub priv_x_never: bool,
+    pub user_x_never: bool,
+    #[bits(9)]
+    _mbz2: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Shareability {
+    NonShareable = 0b00,
+    OuterShareable = 0b10,
+    InnerShareable = 0b11,
+}
+
+/// What a mapping is for. The memory type is the index of the attribute
+/// in `MAIR_EL1`, the caller has set that up.
+#[derive(Debug, Clone, Copy)]
+pub struct MapAttributes {
+    pub mair_index: u8,
+    pub shareability: Shareability,
+    pub writable: bool,
+    pub executable: bool,
+    /// Accessible from EL0, then executable only there.
+    pub user: bool,
+}
+
+impl MapAttributes {
+    /// The kernel data in the normal memory with the attribute at `mair_index`.
+    pub const fn data(mair_index: u8) -> Self {
+        Self {
+            mair_index,
+            shareability: Shareability::InnerShareable,
+            writable: true,
+            executable: false,
+            user: false,
+        }
+    }
+
+    /// The device registers with the attribute at `mair_index`. The
+    /// shareability is ignored for the device memory.
+    pub const fn device(mair_index: u8) -> Self {
+        Self {
+            mair_index,
+            shareability: Shareability::NonShareable,
+            writable: true,
+            executable: false,
+            user: false,
+        }
+    }
+}
+
+struct Format;
+
+impl EntryFormat for Format {
+    type Attributes = MapAttributes;
+
+    fn is_valid(entry: u64) -> bool {
+        PageTableEntry::from(entry).valid()
+    }
+
+    fn is_table(entry: u64, level: usize) -> bool {
+        level > 0 && PageTableEntry::from(entry).table()
+    }
+
+    fn table_address(entry: u64) -> u64 {
+        PageTableEntry::from(entry).next_table_pfn() << 12
+    }
+
+    fn table_entry(table: u64) -> u64 {
+        PageTableEntry::new()
+            .with_valid(true)
+            .with_table(true)
+            .with_next_table_pfn(table >> 12)
+            .into()
+    }
+
+    fn leaf_entry(phys: u64, level: usize, attributes: MapAttributes) -> u64 {
+        let access_perm = match (attributes.writable, attributes.user) {
+            (true, false) => 0b00,
+            (true, true) => 0b01,
+            (false, false) => 0b10,
+            (false, true) => 0b11,
+        };
+
+        PageBlockEntry::new()
+            .with_valid(true)
+            .with_page(level == 0)
+            .with_mair_idx(attributes.mair_index as usize)
+            .with_access_perm(access_perm)
+            .with_share_perm(attributes.shareability as u64)
+            .with_accessed(true)
+            .with_address_pfn(phys >> 12)
+            .with_priv_x_never(!attributes.executable || attributes.user)
+            .with_user_x_never(!attributes.executable || !attributes.user)
+            .into()
+    }
+}
+
+/// Builds the tables for one translation table base register.
+pub struct PageTableBuilder<'a, A: TableAllocator> {
+    tables: Tables<'a, A>,
+}
+
+impl<'a, A: TableAllocator> PageTableBuilder<'a, A> {
+    /// Allocates the root table.
+    pub fn new(allocator: &'a mut A) -> Result<Self, MapError> {
+        Ok(Self {
+            tables: Tables::new(allocator)?,
+        })
+    }
+
+    /// Adds the mappings to the existing tables.
+    pub fn from_root(root: u64, allocator: &'a mut A) -> Self {
+        Self {
+            tables: Tables::from_root(root, allocator),
+        }
+    }
+
+    /// The largest block to map with, the 1 GiB blocks by default.
+    pub fn with_largest_block(mut self, largest_block: BlockSize) -> Self {
+        self.tables.set_largest_block(largest_block);
+        self
+    }
+
+    /// The physical address of the root table.
+    pub fn root(&self) -> u64 {
+        self.tables.root()
+    }
+
+    pub fn stats(&self) -> MapStats {
+        self.tables.stats()
+    }
+
+    /// Maps `size` bytes at `virt` to `phys`. The addresses and the size
+    /// must be page-aligned.
+    pub fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: u64,
+        attributes: MapAttributes,
+    ) -> Result<(), MapError> {
+        self.tables.map::<Format>(virt, phys, size, attributes)
+    }
+}
@@ -0,0 +1,238 @@
+//! Page table builders for the loader and the kernel.
+//!
+//! The tables use the 4 KiB granule and four levels on both architectures.
+//! A range is mapped with the largest block the alignment of both addresses
+//! and the remaining size allow: 1 GiB, then 2 MiB, then 4 KiB pages at the
+//! edges. The tables come from a [`TableAllocator`]: the loader allocates
+//! them from the firmware, and the kernel from its [`PageBitmap`].
+//!
+//! The levels are numbered from the pages up, the level `0` tables map
+//! 4 KiB pages and the root table is at [`ROOT_LEVEL`].
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod aarch64;
+pub mod x86_64;
+
+mod tests;
+
+use page_bitmap::PageBitmap;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+pub const ENTRIES_PER_TABLE: usize = 512;
+/// The level of the root table.
+pub const ROOT_LEVEL: usize = 3;
+
+pub type Table = [u64; ENTRIES_PER_TABLE];
+
+/// Where the builders get the pages for the tables from.
+pub trait TableAllocator {
+    /// Allocates a zeroed page for a table, and returns its physical address.
+    fn allocate_table(&mut self) -> Option<u64>;
+    /// The table at the physical address, to read and to write the entries.
+    fn table(&mut self, phys: u64) -> &mut Table;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The addresses or the size are not page-aligned.
+    Unaligned,
+    /// The virtual address is mapped already.
+    AlreadyMapped(u64),
+    /// No page for a table.
+    OutOfMemory,
+}
+
+/// The largest block a builder maps with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BlockSize {
+    Page4K = 0,
+    Block2M = 1,
+    Block1G = 2,
+}
+
+/// Statistics to see how large the tables have grown.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MapStats {
+    pub tables: usize,
+    pub pages_1g: usize,
+    pub pages_2m: usize,
+    pub pages_4k: usize,
+}
+
+impl core::ops::AddAssign for MapStats {
+    fn add_assign(&mut self, other: Self) {
+        self.tables += other.tables;
+        self.pages_1g += other.pages_1g;
+        self.pages_2m += other.pages_2m;
+        self.pages_4k += other.pages_4k;
+    }
+}
+
+pub const fn level_size(level: usize) -> u64 {
+    PAGE_SIZE << (9 * level)
+}
+
+pub const fn level_index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) & (ENTRIES_PER_TABLE - 1)
+}
+
+/// How an architecture encodes the entries.
+pub(crate) trait EntryFormat {
+    type Attributes: Copy;
+
+    fn is_valid(entry: u64) -> bool;
+    /// Whether the entry at the level points to a table, not maps a block.
+    fn is_table(entry: u64, level: usize) -> bool;
+    fn table_address(entry: u64) -> u64;
+    fn table_entry(table: u64) -> u64;
+    fn leaf_entry(phys: u64, level: usize, attributes: Self::Attributes) -> u64;
+}
+
+/// The part of the builders that does not depend on the entry format.
+pub(crate) struct Tables<'a, A: TableAllocator> {
+    root: u64,
+    allocator: &'a mut A,
+    largest_block: BlockSize,
+    stats: MapStats,
+}
+
+impl<'a, A: TableAllocator> Tables<'a, A> {
+    pub(crate) fn new(allocator: &'a mut A) -> Result<Self, MapError> {
+        let root = allocator.allocate_table().ok_or(MapError::OutOfMemory)?;
+        let mut tables = Self::from_root(root, allocator);
+        tables.stats.tables += 1;
+        Ok(tables)
+    }
+
+    pub(crate) fn from_root(root: u64, allocator: &'a mut A) -> Self {
+        Self {
+            root,
+            allocator,
+            largest_block: BlockSize::Block1G,
+            stats: MapStats::default(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> u64 {
+        self.root
+    }
+
+    pub(crate) fn stats(&self) -> MapStats {
+        self.stats
+    }
+
+    pub(crate) fn set_largest_block(&mut self, largest_block: BlockSize) {
+        self.largest_block = largest_block;
+    }
+
+    fn map_one<F: EntryFormat>(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        level: usize,
+        attributes: F::Attributes,
+    ) -> Result<(), MapError> {
+        let mut table = self.root;
+        for table_level in (level + 1..=ROOT_LEVEL).rev() {
+            let index = level_index(virt, table_level);
+            let mut entry = self.allocator.table(table)[index];
+            if !F::is_valid(entry) {
+                let next = self
+                    .allocator
+                    .allocate_table()
+                    .ok_or(MapError::OutOfMemory)?;
+                self.stats.tables += 1;
+                entry = F::table_entry(next);
+                self.allocator.table(table)[index] = entry;
+            }
+            if !F::is_table(entry, table_level) {
+                return Err(MapError::AlreadyMapped(virt));
+            }
+            table = F::table_address(entry);
+        }
+
+        let entry = &mut self.allocator.table(table)[level_index(virt, level)];
+        if F::is_valid(*entry) {
+            return Err(MapError::AlreadyMapped(virt));
+        }
+        *entry = F::leaf_entry(phys, level, attributes);
+
+        match level {
+            0 => self.stats.pages_4k += 1,
+            1 => self.stats.pages_2m += 1,
+            _ => self.stats.pages_1g += 1,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn map<F: EntryFormat>(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: u64,
+        attributes: F::Attributes,
+    ) -> Result<(), MapError> {
+        if (virt | phys | size) & (PAGE_SIZE - 1) != 0 {
+            return Err(MapError::Unaligned);
+        }
+
+        let mut offset = 0;
+        while offset < size {
+            let (virt, phys, remaining) = (virt + offset, phys + offset, size - offset);
+            let level = (0..=self.largest_block as usize)
+                .rev()
+                .find(|&level| {
+                    let block = level_size(level);
+                    (virt | phys) & (block - 1) == 0 && remaining >= block
+                })
+                .unwrap_or(0);
+
+            self.map_one::<F>(virt, phys, level, attributes)?;
+            offset += level_size(level);
+        }
+        Ok(())
+    }
+}
+
+/// Allocates the tables from the page bitmap, the page number is the
+/// physical page frame number.
+pub struct BitmapTableAllocator<'a, 'b> {
+    bitmap: &'b mut PageBitmap<'a>,
+    virt_offset: u64,
+    /// The pages below are known to be allocated.
+    next_page: usize,
+}
+
+impl<'a, 'b> BitmapTableAllocator<'a, 'b> {
+    /// # Safety
+    ///
+    /// The memory the bitmap tracks is accessible at `virt_offset` plus its
+    /// physical address, and the free pages are not used by anything else.
+    pub unsafe fn new(bitmap: &'b mut PageBitmap<'a>, virt_offset: u64) -> Self {
+        Self {
+            bitmap,
+            virt_offset,
+            next_page: 0,
+        }
+    }
+}
+
+impl TableAllocator for BitmapTableAllocator<'_, '_> {
+    fn allocate_table(&mut self) -> Option<u64> {
+        let pages = self.bitmap.max_memory() / PAGE_SIZE as usize;
+        let page = (self.next_page..pages).find(|&page| !self.bitmap.is_page_allocated(page))?;
+        self.bitmap.allocate_page(page).ok()?;
+        self.next_page = page + 1;
+
+        let phys = page as u64 * PAGE_SIZE;
+        self.table(phys).fill(0);
+        Some(phys)
+    }
+
+    fn table(&mut self, phys: u64) -> &mut Table {
+        // SAFETY: the memory is accessible at the offset, and the page
+        // has been allocated for a table.
+        unsafe { &mut *((phys + self.virt_offset) as *mut Table) }
+    }
+}
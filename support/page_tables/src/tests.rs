@@ -0,0 +1,206 @@
+#![cfg(test)]
+
+use crate::aarch64;
+use crate::aarch64::PageBlockEntry;
+use crate::aarch64::PageTableBuilder;
+use crate::aarch64::PageTableEntry;
+use crate::level_index;
+use crate::x86_64;
+use crate::BitmapTableAllocator;
+use crate::BlockSize;
+use crate::MapError;
+use crate::Table;
+use crate::TableAllocator;
+use crate::ENTRIES_PER_TABLE;
+use crate::PAGE_SIZE;
+use crate::ROOT_LEVEL;
+use page_bitmap::page_bitmap_level_size;
+use page_bitmap::PageBitmap;
+
+const TABLE_PAGES: usize = 64;
+
+/// The pages for the tables, the physical address `0` is the first one.
+fn with_allocator(f: impl FnOnce(&mut BitmapTableAllocator)) {
+    let max_memory = TABLE_PAGES * PAGE_SIZE as usize;
+    let sizes = page_bitmap_level_size(max_memory);
+    let mut storage: Vec<Vec<u8>> = sizes.iter().map(|&size| vec![0; size + 1]).collect();
+    let levels: [&mut [u8]; 8] = storage
+        .iter_mut()
+        .map(|level| level.as_mut_slice())
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    let mut bitmap = PageBitmap::new(levels, max_memory, || None);
+
+    let mut memory = vec![[0xa5u64; ENTRIES_PER_TABLE]; TABLE_PAGES];
+    // SAFETY: the memory is on the heap, and only the tables use it.
+    let mut allocator =
+        unsafe { BitmapTableAllocator::new(&mut bitmap, memory.as_mut_ptr() as u64) };
+    f(&mut allocator);
+}
+
+/// The leaf entry for `virt` and its level.
+fn lookup(allocator: &mut impl TableAllocator, root: u64, virt: u64) -> Option<(u64, usize)> {
+    let mut table = root;
+    for level in (0..=ROOT_LEVEL).rev() {
+        let entry = allocator.table(table)[level_index(virt, level)];
+        let table_entry = PageTableEntry::from(entry);
+        if !table_entry.valid() {
+            return None;
+        }
+        if level == 0 || !table_entry.table() {
+            return Some((entry, level));
+        }
+        table = table_entry.next_table_pfn() << 12;
+    }
+    None
+}
+
+#[test]
+fn test_aarch64_map() {
+    with_allocator(|allocator| {
+        let mut builder = PageTableBuilder::new(allocator).unwrap();
+        let root = builder.root();
+
+        // A 4 KiB page, a 2 MiB block, and a 1 GiB block.
+        let virt = 0xffff_8000_3fdf_f000;
+        let phys = 0x3fdf_f000;
+        let size = PAGE_SIZE + (2 << 20) + (1 << 30);
+        builder
+            .map(virt, phys, size, aarch64::MapAttributes::data(3))
+            .unwrap();
+        let stats = builder.stats();
+        assert_eq!((stats.pages_4k, stats.pages_2m, stats.pages_1g), (1, 1, 1));
+        assert_eq!(stats.tables, 4);
+
+        assert_eq!(
+            builder.map(virt, phys, PAGE_SIZE, aarch64::MapAttributes::data(3)),
+            Err(MapError::AlreadyMapped(virt))
+        );
+        assert_eq!(
+            builder.map(virt + 1, phys, PAGE_SIZE, aarch64::MapAttributes::data(3)),
+            Err(MapError::Unaligned)
+        );
+
+        let (entry, level) = lookup(allocator, root, virt).unwrap();
+        let entry = PageBlockEntry::from(entry);
+        assert_eq!(level, 0);
+        assert!(entry.page() && entry.accessed() && entry.priv_x_never());
+        assert_eq!(entry.mair_idx(), 3);
+        assert_eq!(entry.share_perm(), 0b11);
+        assert_eq!(entry.address_pfn() << 12, phys);
+
+        let (entry, level) = lookup(allocator, root, virt + PAGE_SIZE).unwrap();
+        assert_eq!(level, 1);
+        assert!(!PageBlockEntry::from(entry).page());
+        let (entry, level) = lookup(allocator, root, virt + PAGE_SIZE + (2 << 20)).unwrap();
+        assert_eq!(level, 2);
+        assert_eq!(PageBlockEntry::from(entry).address_pfn() << 12, 1 << 30);
+        assert!(lookup(allocator, root, virt - PAGE_SIZE).is_none());
+    });
+}
+
+#[test]
+fn test_aarch64_largest_block() {
+    with_allocator(|allocator| {
+        let mut builder = PageTableBuilder::new(allocator)
+            .unwrap()
+            .with_largest_block(BlockSize::Page4K);
+        let code = aarch64::MapAttributes {
+            writable: false,
+            executable: true,
+            ..aarch64::MapAttributes::data(3)
+        };
+        builder.map(0x20_0000, 0x20_0000, 0x4000, code).unwrap();
+        assert_eq!(builder.stats().pages_4k, 4);
+
+        let root = builder.root();
+        let entry = PageBlockEntry::from(lookup(allocator, root, 0x20_3000).unwrap().0);
+        assert_eq!(entry.access_perm(), 0b10);
+        assert!(!entry.priv_x_never() && entry.user_x_never());
+    });
+}
+
+#[test]
+fn test_out_of_memory() {
+    struct NoMemory(Table);
+
+    impl TableAllocator for NoMemory {
+        fn allocate_table(&mut self) -> Option<u64> {
+            None
+        }
+
+        fn table(&mut self, _phys: u64) -> &mut Table {
+            &mut self.0
+        }
+    }
+
+    let mut allocator = NoMemory([0; ENTRIES_PER_TABLE]);
+    assert!(PageTableBuilder::new(&mut allocator).is_err());
+    let mut builder = PageTableBuilder::from_root(0, &mut allocator);
+    assert_eq!(
+        builder.map(0, 0, PAGE_SIZE, aarch64::MapAttributes::data(0)),
+        Err(MapError::OutOfMemory)
+    );
+}
+
+#[test]
+fn test_x86_64_map() {
+    with_allocator(|allocator| {
+        let mut builder = x86_64::PageTableBuilder::new(allocator)
+            .unwrap()
+            .with_largest_block(BlockSize::Block2M);
+        let root = builder.root();
+
+        let virt = 0xffff_8000_001f_f000;
+        let phys = 0x1f_f000;
+        builder
+            .map(
+                virt,
+                phys,
+                PAGE_SIZE + (2 << 20),
+                x86_64::MapAttributes::DATA,
+            )
+            .unwrap();
+        builder
+            .map(
+                0xffff_fe00_0000_0000,
+                0xfee0_0000,
+                PAGE_SIZE,
+                x86_64::MapAttributes::DEVICE,
+            )
+            .unwrap();
+        let stats = builder.stats();
+        assert_eq!((stats.pages_4k, stats.pages_2m, stats.pages_1g), (2, 1, 0));
+
+        let mut walk = |virt: u64| {
+            let mut table = root;
+            for level in (1..=ROOT_LEVEL).rev() {
+                let entry =
+                    x86_64::TableEntry::from(allocator.table(table)[level_index(virt, level)]);
+                assert!(entry.present());
+                if entry.huge() {
+                    return (u64::from(entry), level);
+                }
+                table = entry.next_table_pfn() << 12;
+            }
+            (allocator.table(table)[level_index(virt, 0)], 0)
+        };
+
+        let (entry, level) = walk(virt);
+        let entry = x86_64::PageEntry::from(entry);
+        assert_eq!(level, 0);
+        assert!(entry.writable() && entry.global() && entry.no_execute());
+        assert!(!entry.pat() && !entry.cache_disable() && !entry.write_through());
+        assert_eq!(entry.address_pfn() << 12, phys);
+
+        let (entry, level) = walk(virt + PAGE_SIZE);
+        let entry = x86_64::HugePageEntry::from(entry);
+        assert_eq!(level, 1);
+        assert_eq!(entry.address(), 2 << 20);
+
+        let entry = x86_64::PageEntry::from(walk(0xffff_fe00_0000_0000).0);
+        assert!(entry.cache_disable() && entry.write_through());
+        assert_eq!(entry.address_pfn() << 12, 0xfee0_0000);
+    });
+}
@@ -0,0 +1,243 @@
+//! The 4-level paging tables. The level `3` tables are the PML4 ones, and
+//! the level `0` tables are the page tables.
+//!
+//! The memory type of a mapping is the index of the entry in `IA32_PAT`
+//! made of the `PAT`, `PCD`, and `PWT` bits, the caller has set that up.
+
+use crate::BlockSize;
+use crate::EntryFormat;
+use crate::MapError;
+use crate::MapStats;
+use crate::TableAllocator;
+use crate::Tables;
+use bitfield_struct::bitfield;
+
+/// A PML4E, or a PDPTE or a PDE that points to a table.
+#[bitfield(u64, default = false)]
+pub struct TableEntry {
+    pub present: bool,
+    pub writable: bool,
+    pub user: bool,
+    pub write_through: bool,
+    pub cache_disable: bool,
+    pub accessed: bool,
+    _ignored0: bool,
+    /// Must be clear, the entry maps a page otherwise.
+    pub huge: bool,
+    #[bits(4)]
+    _ignored1: u64,
+    #[bits(40)]
+    pub next_table_pfn: u64,
+    #[bits(11)]
+    _ignored2: u64,
+    pub no_execute: bool,
+}
+
+/// A PDPTE that maps a 1 GiB page, or a PDE that maps a 2 MiB page.
+#[bitfield(u64, default = false)]
+pub struct HugePageEntry {
+    pub present: bool,
+    pub writable: bool,
+    pub user: bool,
+    pub write_through: bool,
+    pub cache_disable: bool,
+    pub accessed: bool,
+    pub dirty: bool,
+    /// Must be set.
+    pub huge: bool,
+    pub global: bool,
+    #[bits(3)]
+    _ignored0: u64,
+    pub pat: bool,
+    /// The address bits from `13` on, the low ones must be zero.
+    #[bits(39)]
+    address_bits: u64,
+    #[bits(7)]
+    _ignored1: u64,
+    #[bits(4)]
+    pub protection_key: u64,
+    pub no_execute: bool,
+}
+
+impl HugePageEntry {
+    pub fn address(&self) -> u64 {
+        self.address_bits() << 13
+    }
+
+    pub fn with_address(self, address: u64) -> Self {
+        self.with_address_bits(address >> 13)
+    }
+}
+
+/// A PTE that maps a 4 KiB page.
+#[bitfield(u64, default = false)]
+pub struct PageEntry {
+    pub present: bool,
+    pub writable: bool,
+    pub user: bool,
+    pub write_through: bool,
+    pub cache_disable: bool,
+    pub accessed: bool,
+    pub dirty: bool,
+    pub pat: bool,
+    pub global: bool,
+    #[bits(3)]
+    _ignored0: u64,
+    #[bits(40)]
+    pub address_pfn: u64,
+    #[bits(7)]
+    _ignored1: u64,
+    #[bits(4)]
+    pub protection_key: u64,
+    pub no_execute: bool,
+}
+
+pub type Pml4Entry = TableEntry;
+/// Either a [`TableEntry`] or a [`HugePageEntry`], see `huge`.
+pub type PdptEntry = TableEntry;
+/// Either a [`TableEntry`] or a [`HugePageEntry`], see `huge`.
+pub type PdEntry = TableEntry;
+pub type PtEntry = PageEntry;
+
+/// The PAT index of the write-back memory with the power-on `IA32_PAT`.
+pub const PAT_INDEX_WRITE_BACK: u8 = 0;
+/// The PAT index of the uncacheable memory with the power-on `IA32_PAT`.
+pub const PAT_INDEX_UNCACHEABLE: u8 = 3;
+
+/// What a mapping is for.
+#[derive(Debug, Clone, Copy)]
+pub struct MapAttributes {
+    /// `PAT`, `PCD`, and `PWT` from the high bit to the low one.
+    pub pat_index: u8,
+    pub writable: bool,
+    pub executable: bool,
+    pub user: bool,
+    /// Survives the CR3 switches with `CR4.PGE`.
+    pub global: bool,
+}
+
+impl MapAttributes {
+    /// The kernel data in the write-back memory.
+    pub const DATA: Self = Self {
+        pat_index: PAT_INDEX_WRITE_BACK,
+        writable: true,
+        executable: false,
+        user: false,
+        global: true,
+    };
+    /// The device registers, not cached.
+    pub const DEVICE: Self = Self {
+        pat_index: PAT_INDEX_UNCACHEABLE,
+        writable: true,
+        executable: false,
+        user: false,
+        global: true,
+    };
+}
+
+struct Format;
+
+impl EntryFormat for Format {
+    type Attributes = MapAttributes;
+
+    fn is_valid(entry: u64) -> bool {
+        TableEntry::from(entry).present()
+    }
+
+    fn is_table(entry: u64, level: usize) -> bool {
+        level > 0 && !TableEntry::from(entry).huge()
+    }
+
+    fn table_address(entry: u64) -> u64 {
+        TableEntry::from(entry).next_table_pfn() << 12
+    }
+
+    /// The permissions are granted in the leaf entries.
+    fn table_entry(table: u64) -> u64 {
+        TableEntry::new()
+            .with_present(true)
+            .with_writable(true)
+            .with_user(true)
+            .with_next_table_pfn(table >> 12)
+            .into()
+    }
+
+    fn leaf_entry(phys: u64, level: usize, attributes: MapAttributes) -> u64 {
+        let pat_index = attributes.pat_index;
+        if level == 0 {
+            PageEntry::new()
+                .with_present(true)
+                .with_writable(attributes.writable)
+                .with_user(attributes.user)
+                .with_write_through(pat_index & 1 != 0)
+                .with_cache_disable(pat_index & 2 != 0)
+                .with_pat(pat_index & 4 != 0)
+                .with_global(attributes.global)
+                .with_address_pfn(phys >> 12)
+                .with_no_execute(!attributes.executable)
+                .into()
+        } else {
+            HugePageEntry::new()
+                .with_present(true)
+                .with_writable(attributes.writable)
+                .with_user(attributes.user)
+                .with_write_through(pat_index & 1 != 0)
+                .with_cache_disable(pat_index & 2 != 0)
+                .with_huge(true)
+                .with_pat(pat_index & 4 != 0)
+                .with_global(attributes.global)
+                .with_address(phys)
+                .with_no_execute(!attributes.executable)
+                .into()
+        }
+    }
+}
+
+/// Builds the tables for CR3.
+pub struct PageTableBuilder<'a, A: TableAllocator> {
+    tables: Tables<'a, A>,
+}
+
+impl<'a, A: TableAllocator> PageTableBuilder<'a, A> {
+    /// Allocates the root table.
+    pub fn new(allocator: &'a mut A) -> Result<Self, MapError> {
+        Ok(Self {
+            tables: Tables::new(allocator)?,
+        })
+    }
+
+    /// Adds the mappings to the existing tables.
+    pub fn from_root(root: u64, allocator: &'a mut A) -> Self {
+        Self {
+            tables: Tables::from_root(root, allocator),
+        }
+    }
+
+    /// The largest page to map with, the 1 GiB pages by default. Not all
+    /// processors support those.
+    pub fn with_largest_block(mut self, largest_block: BlockSize) -> Self {
+        self.tables.set_largest_block(largest_block);
+        self
+    }
+
+    /// The physical address of the root table.
+    pub fn root(&self) -> u64 {
+        self.tables.root()
+    }
+
+    pub fn stats(&self) -> MapStats {
+        self.tables.stats()
+    }
+
+    /// Maps `size` bytes at `virt` to `phys`. The addresses and the size
+    /// must be page-aligned.
+    pub fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        size: u64,
+        attributes: MapAttributes,
+    ) -> Result<(), MapError> {
+        self.tables.map::<Format>(virt, phys, size, attributes)
+    }
+}
@@ -0,0 +1,147 @@
+//! The base address registers.
+//!
+//! Bits   Memory                          I/O
+//! ----------------------------------------------------------------------
+//! 0      0                               1
+//! 1-2    0 for 32-bit, 2 for 64-bit      1: reserved
+//! 3      prefetchable                    2-31: the port
+//! 4-31   the address
+//!
+//! The 64-bit ones take two registers, the high half of the address in the
+//! second. The size is the lowest address bit that stays set when the
+//! register is written with all ones, the bits below are hardwired to zero.
+
+use crate::ConfigAccess;
+use crate::Function;
+use crate::BAR0;
+use crate::COMMAND_IO_SPACE;
+use crate::COMMAND_MEMORY_SPACE;
+
+const BAR_IO: u32 = 1 << 0;
+const BAR_TYPE_MASK: u32 = 3 << 1;
+const BAR_TYPE_64: u32 = 2 << 1;
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+const BAR_MEMORY_MASK: u32 = !0xf;
+const BAR_IO_MASK: u32 = !0x3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarKind {
+    Memory32,
+    Memory64,
+    Io,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bar {
+    /// The register of the BAR, the first one for the 64-bit ones.
+    pub index: u8,
+    pub kind: BarKind,
+    /// The bus address the firmware has assigned, `0` if none.
+    pub address: u64,
+    pub size: u64,
+    pub prefetchable: bool,
+}
+
+impl Bar {
+    /// Decodes the register as read, and as read after writing all ones.
+    /// The high halves are for the 64-bit ones. `None` if the register is
+    /// not implemented.
+    pub fn decode(index: u8, value: u64, sizing: u64) -> Option<Self> {
+        let low = value as u32;
+        let (kind, address, size) = if low & BAR_IO != 0 {
+            let mut mask = sizing as u32 & BAR_IO_MASK;
+            // The upper 16 bits may be hardwired to zero.
+            if mask & 0xffff_0000 == 0 {
+                mask |= 0xffff_0000;
+            }
+            let size = (!mask).wrapping_add(1) as u64;
+            (BarKind::Io, value & BAR_IO_MASK as u64, size)
+        } else if low & BAR_TYPE_MASK == BAR_TYPE_64 {
+            let mask = sizing & !0xf;
+            (BarKind::Memory64, value & !0xf, (!mask).wrapping_add(1))
+        } else {
+            let mask = sizing as u32 & BAR_MEMORY_MASK;
+            let size = (!mask).wrapping_add(1) as u64;
+            (BarKind::Memory32, value & BAR_MEMORY_MASK as u64, size)
+        };
+        // Nothing stays set in the registers that are not implemented.
+        let implemented = match kind {
+            BarKind::Io => sizing as u32 & BAR_IO_MASK != 0,
+            _ => sizing & !0xf != 0,
+        };
+        if !implemented || size == 0 {
+            return None;
+        }
+        Some(Self {
+            index,
+            kind,
+            address,
+            size,
+            prefetchable: kind != BarKind::Io && low & BAR_PREFETCHABLE != 0,
+        })
+    }
+}
+
+/// The implemented BARs of a function, see [`Function::bars`].
+pub struct Bars<'a, A: ConfigAccess> {
+    pub(crate) function: Function<'a, A>,
+    pub(crate) index: u8,
+    pub(crate) count: u8,
+}
+
+impl<A: ConfigAccess> Bars<'_, A> {
+    /// Writes all ones, reads back, and puts the value back.
+    fn size(&self, offset: u16, value: u32) -> u32 {
+        self.function.write_u32(offset, u32::MAX);
+        let sizing = self.function.read_u32(offset);
+        self.function.write_u32(offset, value);
+        sizing
+    }
+
+    fn read(&self, index: u8) -> Option<Bar> {
+        let offset = BAR0 + index as u16 * 4;
+        let low = self.function.read_u32(offset);
+        let is_64 = low & (BAR_IO | BAR_TYPE_MASK) == BAR_TYPE_64;
+        if is_64 && index + 1 >= self.count {
+            // The high half would be past the BARs.
+            return None;
+        }
+
+        // The device must not decode the addresses the sizing writes.
+        let command = self.function.command();
+        self.function
+            .set_command(command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE));
+        let mut value = low as u64;
+        let mut sizing = self.size(offset, low) as u64;
+        if is_64 {
+            let high = self.function.read_u32(offset + 4);
+            value |= (high as u64) << 32;
+            sizing |= (self.size(offset + 4, high) as u64) << 32;
+        }
+        self.function.set_command(command);
+
+        Bar::decode(index, value, sizing)
+    }
+}
+
+impl<A: ConfigAccess> Iterator for Bars<'_, A> {
+    type Item = Bar;
+
+    fn next(&mut self) -> Option<Bar> {
+        while self.index < self.count {
+            let index = self.index;
+            let bar = self.read(index);
+            self.index += match bar {
+                Some(Bar {
+                    kind: BarKind::Memory64,
+                    ..
+                }) => 2,
+                _ => 1,
+            };
+            if bar.is_some() {
+                return bar;
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,38 @@
+//! The names of the class codes, of the classes and the subclasses the
+//! machines here have, as in the PCI Code and ID Assignment Specification.
+
+/// The name of the subclass, or of the class if the subclass is not known.
+pub fn class_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x00, _) => "Unclassified device",
+        (0x01, 0x00) => "SCSI storage controller",
+        (0x01, 0x01) => "IDE interface",
+        (0x01, 0x06) => "SATA controller",
+        (0x01, 0x08) => "Non-Volatile memory controller",
+        (0x01, _) => "Mass storage controller",
+        (0x02, 0x00) => "Ethernet controller",
+        (0x02, _) => "Network controller",
+        (0x03, 0x00) => "VGA compatible controller",
+        (0x03, _) => "Display controller",
+        (0x04, 0x03) => "Audio device",
+        (0x04, _) => "Multimedia controller",
+        (0x05, _) => "Memory controller",
+        (0x06, 0x00) => "Host bridge",
+        (0x06, 0x01) => "ISA bridge",
+        (0x06, 0x04) => "PCI bridge",
+        (0x06, _) => "Bridge",
+        (0x07, 0x00) => "Serial controller",
+        (0x07, _) => "Communication controller",
+        (0x08, _) => "System peripheral",
+        (0x09, _) => "Input device controller",
+        (0x0c, 0x03) => "USB controller",
+        (0x0c, 0x05) => "SMBus",
+        (0x0c, _) => "Serial bus controller",
+        (0x0d, _) => "Wireless controller",
+        (0x10, _) => "Encryption controller",
+        (0x11, _) => "Signal processing controller",
+        (0x12, _) => "Processing accelerators",
+        (0xff, _) => "Unassigned class",
+        _ => "Unknown class",
+    }
+}
@@ -0,0 +1,407 @@
+//! The PCI configuration space over ECAM, the Enhanced Configuration Access
+//! Mechanism of PCI Express, as the ACPI MCFG table describes it.
+//!
+//! Each function of each device on each bus has 4 KiB of the configuration
+//! space in the window, at the offset made of the bus, the device, and the
+//! function numbers:
+//!
+//! Bits   Field
+//! ----------------------------------------------------------------------
+//! 20-27  bus
+//! 15-19  device
+//! 12-14  function
+//! 0-11   register
+//!
+//! [`functions`] walks all the buses of the window rather than following
+//! the bridges, the firmware has numbered the buses already. The reads of
+//! a function that is not there return all ones, so the vendor ID is
+//! `0xffff` then.
+//!
+//! The configuration space is reached through [`ConfigAccess`], [`Ecam`]
+//! for the real thing. A [`Function`] reads its [`Header`], and decodes
+//! its [`Bar`]s, sizing them the usual way: write all ones, read back.
+//!
+//! Example:
+//! ```ignore
+//! // SAFETY: the window is mapped with the device attributes.
+//! let ecam = unsafe { pci::Ecam::new(virt_base, 0, 0, 0xff) };
+//! for function in ecam.functions() {
+//!     log::info!("{function}");
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod bar;
+mod class;
+mod tests;
+
+pub use bar::Bar;
+pub use bar::BarKind;
+pub use bar::Bars;
+pub use class::class_name;
+
+use core::fmt;
+use core::ops::RangeInclusive;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+
+/// The configuration space of one function.
+pub const FUNCTION_CONFIG_SIZE: u64 = 0x1000;
+/// The configuration space of one bus.
+pub const BUS_CONFIG_SIZE: u64 = 0x10_0000;
+
+pub const MAX_DEVICES: u8 = 32;
+pub const MAX_FUNCTIONS: u8 = 8;
+
+/// Nobody is there.
+pub const VENDOR_ID_NONE: u16 = 0xffff;
+
+pub const VENDOR_ID: u16 = 0x00;
+pub const DEVICE_ID: u16 = 0x02;
+pub const COMMAND: u16 = 0x04;
+pub const STATUS: u16 = 0x06;
+pub const REVISION_ID: u16 = 0x08;
+pub const PROG_IF: u16 = 0x09;
+pub const SUBCLASS: u16 = 0x0a;
+pub const CLASS: u16 = 0x0b;
+pub const HEADER_TYPE: u16 = 0x0e;
+pub const BAR0: u16 = 0x10;
+/// Of the bridges.
+pub const SECONDARY_BUS: u16 = 0x19;
+pub const INTERRUPT_LINE: u16 = 0x3c;
+pub const INTERRUPT_PIN: u16 = 0x3d;
+
+pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+pub const HEADER_TYPE_MASK: u8 = 0x7f;
+pub const HEADER_TYPE_MULTIFUNCTION: u8 = 1 << 7;
+/// The endpoints.
+pub const HEADER_TYPE_GENERAL: u8 = 0;
+pub const HEADER_TYPE_PCI_BRIDGE: u8 = 1;
+pub const HEADER_TYPE_CARDBUS_BRIDGE: u8 = 2;
+
+/// Where a function is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Address {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Address {
+    pub fn new(segment: u16, bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+
+    /// The offset of the configuration space in the ECAM window of the
+    /// segment.
+    pub fn ecam_offset(&self) -> u64 {
+        (self.bus as u64) << 20 | (self.device as u64) << 15 | (self.function as u64) << 12
+    }
+}
+
+/// As `lspci` shows it.
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+/// Reads and writes the configuration space in the 32-bit words, the
+/// narrower fields are in them.
+pub trait ConfigAccess {
+    /// The function address of the segment.
+    fn segment(&self) -> u16;
+
+    /// The buses there is the configuration space for.
+    fn buses(&self) -> RangeInclusive<u8>;
+
+    /// Reads the aligned word, all ones if nobody is there.
+    fn read_u32(&self, address: Address, offset: u16) -> u32;
+
+    /// Writes the aligned word.
+    fn write_u32(&self, address: Address, offset: u16, value: u32);
+}
+
+/// The ECAM window of a segment.
+#[derive(Debug, Clone, Copy)]
+pub struct Ecam {
+    /// Where the bus `0` would be, even if `start_bus` is not `0`.
+    regs: RegisterBlock,
+    segment: u16,
+    start_bus: u8,
+    end_bus: u8,
+}
+
+impl Ecam {
+    /// # Safety
+    ///
+    /// The configuration space of the buses `start_bus..=end_bus` is
+    /// mapped with the device attributes at `virt_base` plus the offset
+    /// of the bus, as in the MCFG table.
+    pub unsafe fn new(virt_base: u64, segment: u16, start_bus: u8, end_bus: u8) -> Self {
+        Self {
+            // SAFETY: guaranteed by the caller.
+            regs: unsafe { RegisterBlock::new(virt_base) },
+            segment,
+            start_bus,
+            end_bus,
+        }
+    }
+
+    /// The size of the window mapped from the start bus.
+    pub fn window_size(start_bus: u8, end_bus: u8) -> u64 {
+        (end_bus as u64 + 1).saturating_sub(start_bus as u64) * BUS_CONFIG_SIZE
+    }
+
+    pub fn functions(&self) -> Functions<'_, Self> {
+        functions(self)
+    }
+
+    fn register(&self, address: Address, offset: u16) -> Option<Register<u32, ReadWrite>> {
+        if address.segment != self.segment
+            || !self.buses().contains(&address.bus)
+            || address.device >= MAX_DEVICES
+            || address.function >= MAX_FUNCTIONS
+            || offset as u64 >= FUNCTION_CONFIG_SIZE
+        {
+            return None;
+        }
+        Some(Register::new(address.ecam_offset() + (offset & !3) as u64))
+    }
+}
+
+impl ConfigAccess for Ecam {
+    fn segment(&self) -> u16 {
+        self.segment
+    }
+
+    fn buses(&self) -> RangeInclusive<u8> {
+        self.start_bus..=self.end_bus
+    }
+
+    fn read_u32(&self, address: Address, offset: u16) -> u32 {
+        match self.register(address, offset) {
+            Some(register) => self.regs.read(register),
+            None => u32::MAX,
+        }
+    }
+
+    fn write_u32(&self, address: Address, offset: u16, value: u32) {
+        if let Some(register) = self.register(address, offset) {
+            self.regs.write(register, value);
+        }
+    }
+}
+
+/// The fields all the headers have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub revision: u8,
+    pub prog_if: u8,
+    pub subclass: u8,
+    pub class: u8,
+    /// One of `HEADER_TYPE_*`, without the multifunction bit.
+    pub header_type: u8,
+    pub multifunction: bool,
+}
+
+/// A function that is there.
+pub struct Function<'a, A: ConfigAccess> {
+    access: &'a A,
+    pub address: Address,
+}
+
+impl<A: ConfigAccess> Clone for Function<'_, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A: ConfigAccess> Copy for Function<'_, A> {}
+
+impl<'a, A: ConfigAccess> Function<'a, A> {
+    /// `None` if nobody is there.
+    pub fn new(access: &'a A, address: Address) -> Option<Self> {
+        let function = Self { access, address };
+        (function.read_u16(VENDOR_ID) != VENDOR_ID_NONE).then_some(function)
+    }
+
+    pub fn read_u32(&self, offset: u16) -> u32 {
+        self.access.read_u32(self.address, offset & !3)
+    }
+
+    pub fn read_u16(&self, offset: u16) -> u16 {
+        (self.read_u32(offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn read_u8(&self, offset: u16) -> u8 {
+        (self.read_u32(offset) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn write_u32(&self, offset: u16, value: u32) {
+        self.access.write_u32(self.address, offset & !3, value);
+    }
+
+    /// Writes the half of the word, the other half is written back as
+    /// read. Not for the registers with the write-one-to-clear bits next
+    /// to the field, as the status next to the command.
+    pub fn write_u16(&self, offset: u16, value: u16) {
+        let shift = (offset & 2) * 8;
+        let word = self.read_u32(offset) & !(0xffff << shift) | (value as u32) << shift;
+        self.write_u32(offset, word);
+    }
+
+    pub fn header(&self) -> Header {
+        let header_type = self.read_u8(HEADER_TYPE);
+        Header {
+            vendor_id: self.read_u16(VENDOR_ID),
+            device_id: self.read_u16(DEVICE_ID),
+            revision: self.read_u8(REVISION_ID),
+            prog_if: self.read_u8(PROG_IF),
+            subclass: self.read_u8(SUBCLASS),
+            class: self.read_u8(CLASS),
+            header_type: header_type & HEADER_TYPE_MASK,
+            multifunction: header_type & HEADER_TYPE_MULTIFUNCTION != 0,
+        }
+    }
+
+    pub fn command(&self) -> u16 {
+        self.read_u16(COMMAND)
+    }
+
+    /// The status half of the word is written as zeroes, that leaves its
+    /// write-one-to-clear bits alone.
+    pub fn set_command(&self, command: u16) {
+        self.write_u32(COMMAND, command as u32);
+    }
+
+    /// The interrupt pin, `1` to `4` for `INTA#` to `INTD#`, `None` if
+    /// the function does not use one.
+    pub fn interrupt_pin(&self) -> Option<u8> {
+        match self.read_u8(INTERRUPT_PIN) {
+            0 => None,
+            pin => Some(pin),
+        }
+    }
+
+    /// The bus behind the bridge, `None` for the endpoints.
+    pub fn secondary_bus(&self) -> Option<u8> {
+        (self.read_u8(HEADER_TYPE) & HEADER_TYPE_MASK == HEADER_TYPE_PCI_BRIDGE)
+            .then(|| self.read_u8(SECONDARY_BUS))
+    }
+
+    /// The number of the base address registers of the header type.
+    fn bar_count(&self) -> u8 {
+        match self.read_u8(HEADER_TYPE) & HEADER_TYPE_MASK {
+            HEADER_TYPE_GENERAL => 6,
+            HEADER_TYPE_PCI_BRIDGE => 2,
+            _ => 0,
+        }
+    }
+
+    /// The implemented base address registers, sized with the decoding
+    /// turned off for the while.
+    pub fn bars(&self) -> Bars<'a, A> {
+        Bars {
+            function: *self,
+            index: 0,
+            count: self.bar_count(),
+        }
+    }
+}
+
+/// As `lspci -nn` shows it.
+impl<A: ConfigAccess> fmt::Display for Function<'_, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = self.header();
+        write!(
+            f,
+            "{} {} [{:02x}{:02x}]: [{:04x}:{:04x}] rev {:02x}",
+            self.address,
+            class_name(header.class, header.subclass),
+            header.class,
+            header.subclass,
+            header.vendor_id,
+            header.device_id,
+            header.revision
+        )
+    }
+}
+
+/// The functions there are on the buses, in the order of the addresses.
+pub struct Functions<'a, A: ConfigAccess> {
+    access: &'a A,
+    next: Option<Address>,
+    end_bus: u8,
+    /// Whether the function 0 of the current device is multifunction.
+    multifunction: bool,
+}
+
+/// Walks all the buses, see the crate docs.
+pub fn functions<A: ConfigAccess>(access: &A) -> Functions<'_, A> {
+    let buses = access.buses();
+    Functions {
+        access,
+        next: (!buses.is_empty()).then(|| Address::new(access.segment(), *buses.start(), 0, 0)),
+        end_bus: *buses.end(),
+        multifunction: false,
+    }
+}
+
+impl<A: ConfigAccess> Functions<'_, A> {
+    fn advance(&mut self, address: Address) {
+        self.next = if address.function + 1 < MAX_FUNCTIONS && self.multifunction {
+            Some(Address {
+                function: address.function + 1,
+                ..address
+            })
+        } else if address.device + 1 < MAX_DEVICES {
+            Some(Address {
+                device: address.device + 1,
+                function: 0,
+                ..address
+            })
+        } else if address.bus < self.end_bus {
+            Some(Address::new(address.segment, address.bus + 1, 0, 0))
+        } else {
+            None
+        };
+    }
+}
+
+impl<'a, A: ConfigAccess> Iterator for Functions<'a, A> {
+    type Item = Function<'a, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(address) = self.next {
+            let function = Function::new(self.access, address);
+            if address.function == 0 {
+                // The other functions are only looked at if the function 0
+                // says there are some.
+                self.multifunction = function.is_some_and(|f| f.header().multifunction);
+            }
+            self.advance(address);
+            if function.is_some() {
+                return function;
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,242 @@
+#![cfg(test)]
+
+use super::*;
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+
+/// The configuration space of a few functions. The BARs keep the bits of
+/// their masks only, as the hardware does.
+struct FakeFunction {
+    address: Address,
+    config: [u32; 64],
+    bar_masks: [u32; 6],
+}
+
+struct FakeConfig {
+    functions: RefCell<Vec<FakeFunction>>,
+    buses: RangeInclusive<u8>,
+}
+
+impl FakeConfig {
+    fn new(buses: RangeInclusive<u8>) -> Self {
+        Self {
+            functions: RefCell::new(Vec::new()),
+            buses,
+        }
+    }
+
+    fn add(&self, bus: u8, device: u8, function: u8, words: &[(u16, u32)], bar_masks: [u32; 6]) {
+        let mut config = [0; 64];
+        for &(offset, value) in words {
+            config[offset as usize / 4] = value;
+        }
+        self.functions.borrow_mut().push(FakeFunction {
+            address: Address::new(0, bus, device, function),
+            config,
+            bar_masks,
+        });
+    }
+
+    fn word(&self, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+        self.read_u32(Address::new(0, bus, device, function), offset)
+    }
+}
+
+impl ConfigAccess for FakeConfig {
+    fn segment(&self) -> u16 {
+        0
+    }
+
+    fn buses(&self) -> RangeInclusive<u8> {
+        self.buses.clone()
+    }
+
+    fn read_u32(&self, address: Address, offset: u16) -> u32 {
+        self.functions
+            .borrow()
+            .iter()
+            .find(|f| f.address == address)
+            .map_or(u32::MAX, |f| f.config[offset as usize / 4])
+    }
+
+    fn write_u32(&self, address: Address, offset: u16, value: u32) {
+        let mut functions = self.functions.borrow_mut();
+        let Some(function) = functions.iter_mut().find(|f| f.address == address) else {
+            return;
+        };
+        let word = offset as usize / 4;
+        function.config[word] = match offset {
+            BAR0..=0x24 => {
+                let mask = function.bar_masks[word - BAR0 as usize / 4];
+                value & mask | function.config[word] & !mask
+            }
+            _ => value,
+        };
+    }
+}
+
+const BAR_NONE: [u32; 6] = [0; 6];
+
+/// A host bridge, a multifunction device with a gap in the functions, an
+/// endpoint with the BARs of all kinds, and one on the next bus.
+fn machine() -> FakeConfig {
+    let config = FakeConfig::new(0..=1);
+    config.add(
+        0,
+        0,
+        0,
+        &[(0x00, 0x29c0_8086), (0x08, 0x0600_0002)],
+        BAR_NONE,
+    );
+    config.add(
+        0,
+        0x1f,
+        0,
+        &[
+            (0x00, 0x2918_8086),
+            (0x08, 0x0601_0002),
+            (0x0c, 0x0080_0000),
+        ],
+        BAR_NONE,
+    );
+    config.add(
+        0,
+        0x1f,
+        3,
+        &[(0x00, 0x2930_8086), (0x08, 0x0c05_0002)],
+        BAR_NONE,
+    );
+    config.add(
+        0,
+        2,
+        0,
+        &[
+            (0x00, 0x100e_8086),
+            (0x04, 0x0000_0007),
+            (0x08, 0x0200_0003),
+            // 128 KiB of 32-bit memory.
+            (0x10, 0xfebc_0000),
+            // 64 bytes of I/O with the upper 16 bits hardwired to zero.
+            (0x14, 0x0000_c041),
+            // 16 KiB of 64-bit prefetchable memory above 4 GiB.
+            (0x18, 0x0000_400c),
+            (0x1c, 0x0000_0008),
+            (0x3c, 0x0000_010b),
+        ],
+        [0xfffe_0000, 0x0000_ffc1, 0xffff_c00c, 0xffff_ffff, 0, 0],
+    );
+    config.add(
+        1,
+        0,
+        0,
+        &[(0x00, 0x1041_1af4), (0x08, 0x0100_0001)],
+        BAR_NONE,
+    );
+    config
+}
+
+#[test]
+fn address() {
+    let address = Address::new(0, 0x12, 0x1f, 7);
+    assert_eq!(address.ecam_offset(), 0x12 << 20 | 0x1f << 15 | 7 << 12);
+    assert_eq!(address.to_string(), "0000:12:1f.7");
+    assert_eq!(Ecam::window_size(0, 0xff), 0x1000_0000);
+    assert_eq!(Ecam::window_size(0x10, 0x10), 0x10_0000);
+}
+
+#[test]
+fn enumerate() {
+    let config = machine();
+    let addresses: Vec<String> = functions(&config).map(|f| f.address.to_string()).collect();
+    assert_eq!(
+        addresses,
+        [
+            "0000:00:00.0",
+            "0000:00:02.0",
+            "0000:00:1f.0",
+            "0000:00:1f.3",
+            "0000:01:00.0"
+        ]
+    );
+
+    // The functions other than 0 are not looked at if the device says it
+    // has only one.
+    let config = FakeConfig::new(0..=0);
+    config.add(0, 3, 0, &[(0x00, 0x1234_1af4)], BAR_NONE);
+    config.add(0, 3, 1, &[(0x00, 0x1234_1af4)], BAR_NONE);
+    assert_eq!(functions(&config).count(), 1);
+}
+
+#[test]
+fn header() {
+    let config = machine();
+    let function = Function::new(&config, Address::new(0, 0, 0x1f, 0)).unwrap();
+    assert_eq!(
+        function.header(),
+        Header {
+            vendor_id: 0x8086,
+            device_id: 0x2918,
+            revision: 2,
+            prog_if: 0,
+            subclass: 1,
+            class: 6,
+            header_type: HEADER_TYPE_GENERAL,
+            multifunction: true
+        }
+    );
+    assert_eq!(
+        function.to_string(),
+        "0000:00:1f.0 ISA bridge [0601]: [8086:2918] rev 02"
+    );
+    assert!(Function::new(&config, Address::new(0, 0, 0x1f, 1)).is_none());
+
+    let nic = Function::new(&config, Address::new(0, 0, 2, 0)).unwrap();
+    assert_eq!(nic.interrupt_pin(), Some(1));
+    assert_eq!(nic.read_u8(INTERRUPT_LINE), 0x0b);
+    assert_eq!(nic.secondary_bus(), None);
+    assert_eq!(class_name(0x02, 0x00), "Ethernet controller");
+    assert_eq!(class_name(0x02, 0x80), "Network controller");
+}
+
+#[test]
+fn bars() {
+    let config = machine();
+    let nic = Function::new(&config, Address::new(0, 0, 2, 0)).unwrap();
+    let bars: Vec<Bar> = nic.bars().collect();
+    assert_eq!(
+        bars,
+        [
+            Bar {
+                index: 0,
+                kind: BarKind::Memory32,
+                address: 0xfebc_0000,
+                size: 0x2_0000,
+                prefetchable: false
+            },
+            Bar {
+                index: 1,
+                kind: BarKind::Io,
+                address: 0xc040,
+                size: 0x40,
+                prefetchable: false
+            },
+            Bar {
+                index: 2,
+                kind: BarKind::Memory64,
+                address: 0x8_0000_4000,
+                size: 0x4000,
+                prefetchable: true
+            },
+        ]
+    );
+
+    // The sizing puts everything back.
+    assert_eq!(config.word(0, 2, 0, 0x04), 0x0000_0007);
+    assert_eq!(config.word(0, 2, 0, 0x10), 0xfebc_0000);
+    assert_eq!(config.word(0, 2, 0, 0x14), 0x0000_c041);
+    assert_eq!(config.word(0, 2, 0, 0x18), 0x0000_400c);
+    assert_eq!(config.word(0, 2, 0, 0x1c), 0x0000_0008);
+
+    assert_eq!(Bar::decode(0, 0, 0), None);
+    assert_eq!(Bar::decode(0, 1, 1), None);
+}
@@ -0,0 +1,408 @@
+//! The bare minimum of the PE32+ format to load an image: the headers,
+//! the sections, and the base relocations.
+//!
+//! The image is loaded as the Windows loader would do: the headers and
+//! the sections are copied at their RVAs into a buffer of `SizeOfImage`
+//! bytes, then the `DIR64` base relocations are applied for the difference
+//! between the address the image is going to run at and `ImageBase`.
+
+#![no_std]
+
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+pub const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+pub const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x0000_0080;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+const DOS_MAGIC: &[u8] = b"MZ";
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+/// The offset of `e_lfanew` in the DOS header.
+const DOS_PE_OFFSET: usize = 0x3c;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+/// The offset of the data directories in the PE32+ optional header.
+const DATA_DIRECTORIES_OFFSET: usize = 112;
+const BASE_RELOCATION_DIRECTORY: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeError {
+    /// Not an MZ/PE file.
+    BadMagic,
+    /// Only PE32+ is supported.
+    NotPe32Plus,
+    /// A header or a section points outside of the file or the image.
+    Truncated,
+    /// A relocation type other than `DIR64`.
+    UnsupportedRelocation(u16),
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, PeError> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(PeError::Truncated)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, PeError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(PeError::Truncated)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, PeError> {
+    data.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+        .ok_or(PeError::Truncated)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionHeader {
+    pub name: [u8; 8],
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub characteristics: u32,
+}
+
+impl SectionHeader {
+    fn parse(data: &[u8], offset: usize) -> Result<Self, PeError> {
+        let mut name = [0; 8];
+        name.copy_from_slice(data.get(offset..offset + 8).ok_or(PeError::Truncated)?);
+        Ok(Self {
+            name,
+            virtual_size: read_u32(data, offset + 8)?,
+            virtual_address: read_u32(data, offset + 12)?,
+            size_of_raw_data: read_u32(data, offset + 16)?,
+            pointer_to_raw_data: read_u32(data, offset + 20)?,
+            characteristics: read_u32(data, offset + 36)?,
+        })
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_WRITE != 0
+    }
+
+    /// The bytes copied from the file, the rest is zero-filled. The raw
+    /// data is padded to the file alignment, and might be larger than the
+    /// section.
+    fn file_size(&self) -> u32 {
+        if self.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0 {
+            0
+        } else if self.virtual_size == 0 {
+            self.size_of_raw_data
+        } else {
+            self.size_of_raw_data.min(self.virtual_size)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    pub machine: u16,
+    pub image_base: u64,
+    pub address_of_entry_point: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub section_alignment: u32,
+    base_relocations: Option<(u32, u32)>,
+    section_count: usize,
+    section_table_offset: usize,
+}
+
+impl<'a> PeImage<'a> {
+    /// Checks if the data starts as a PE file.
+    pub fn is_pe(data: &[u8]) -> bool {
+        data.starts_with(DOS_MAGIC)
+    }
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, PeError> {
+        if !Self::is_pe(data) {
+            return Err(PeError::BadMagic);
+        }
+        let pe_offset = read_u32(data, DOS_PE_OFFSET)? as usize;
+        if data.get(pe_offset..pe_offset + PE_SIGNATURE.len()) != Some(PE_SIGNATURE) {
+            return Err(PeError::BadMagic);
+        }
+
+        let coff = pe_offset + PE_SIGNATURE.len();
+        let machine = read_u16(data, coff)?;
+        let section_count = read_u16(data, coff + 2)? as usize;
+        let size_of_optional_header = read_u16(data, coff + 16)? as usize;
+
+        let optional = coff + COFF_HEADER_SIZE;
+        if read_u16(data, optional)? != PE32_PLUS_MAGIC {
+            return Err(PeError::NotPe32Plus);
+        }
+
+        let directory_count = read_u32(data, optional + 108)? as usize;
+        let base_relocations = if directory_count > BASE_RELOCATION_DIRECTORY {
+            let directory = optional + DATA_DIRECTORIES_OFFSET + BASE_RELOCATION_DIRECTORY * 8;
+            let rva = read_u32(data, directory)?;
+            let size = read_u32(data, directory + 4)?;
+            (size != 0).then_some((rva, size))
+        } else {
+            None
+        };
+
+        let image = Self {
+            data,
+            machine,
+            address_of_entry_point: read_u32(data, optional + 16)?,
+            image_base: read_u64(data, optional + 24)?,
+            section_alignment: read_u32(data, optional + 32)?,
+            size_of_image: read_u32(data, optional + 56)?,
+            size_of_headers: read_u32(data, optional + 60)?,
+            base_relocations,
+            section_count,
+            section_table_offset: optional + size_of_optional_header,
+        };
+
+        // Validate the section table once so the iterator does not need to.
+        for section in image.sections() {
+            let section = section?;
+            let file_end = section.pointer_to_raw_data as usize + section.file_size() as usize;
+            let image_end = section.virtual_address as usize
+                + section.virtual_size.max(section.file_size()) as usize;
+            if file_end > data.len() || image_end > image.size_of_image as usize {
+                return Err(PeError::Truncated);
+            }
+        }
+        if image.size_of_headers as usize > data.len().min(image.size_of_image as usize) {
+            return Err(PeError::Truncated);
+        }
+
+        Ok(image)
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = Result<SectionHeader, PeError>> + '_ {
+        (0..self.section_count).map(|i| {
+            SectionHeader::parse(
+                self.data,
+                self.section_table_offset + i * SECTION_HEADER_SIZE,
+            )
+        })
+    }
+
+    /// Copies the headers and the sections into `image` which must be
+    /// at least `size_of_image` bytes and zero-filled.
+    pub fn load(&self, image: &mut [u8]) -> Result<(), PeError> {
+        if image.len() < self.size_of_image as usize {
+            return Err(PeError::Truncated);
+        }
+
+        let headers = self.size_of_headers as usize;
+        image[..headers].copy_from_slice(&self.data[..headers]);
+        for section in self.sections() {
+            let section = section?;
+            let src = section.pointer_to_raw_data as usize;
+            let dst = section.virtual_address as usize;
+            let len = section.file_size() as usize;
+            image[dst..dst + len].copy_from_slice(&self.data[src..src + len]);
+        }
+
+        Ok(())
+    }
+
+    /// Applies the base relocations to the loaded `image` to run at
+    /// `image_base + delta`. Returns the number of the relocations applied.
+    pub fn relocate(&self, image: &mut [u8], delta: u64) -> Result<usize, PeError> {
+        let Some((rva, size)) = self.base_relocations else {
+            return Ok(0);
+        };
+
+        let mut relocations = 0;
+        let mut block = rva as usize;
+        let end = block + size as usize;
+        while block < end {
+            let page_rva = read_u32(image, block)? as usize;
+            let block_size = read_u32(image, block + 4)? as usize;
+            if block_size < 8 {
+                return Err(PeError::Truncated);
+            }
+
+            for entry in (block + 8..block + block_size).step_by(2) {
+                let entry = read_u16(image, entry)?;
+                let offset = page_rva + (entry & 0xfff) as usize;
+                match entry >> 12 {
+                    IMAGE_REL_BASED_ABSOLUTE => {}
+                    IMAGE_REL_BASED_DIR64 => {
+                        let value = read_u64(image, offset)?.wrapping_add(delta);
+                        image[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+                        relocations += 1;
+                    }
+                    ty => return Err(PeError::UnsupportedRelocation(ty)),
+                }
+            }
+
+            block += block_size;
+        }
+
+        Ok(relocations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PeError;
+    use crate::PeImage;
+    use crate::IMAGE_FILE_MACHINE_AMD64;
+    use crate::IMAGE_SCN_MEM_EXECUTE;
+    use crate::IMAGE_SCN_MEM_READ;
+    use crate::IMAGE_SCN_MEM_WRITE;
+
+    const PE_OFFSET: usize = 0x40;
+    const OPTIONAL: usize = PE_OFFSET + 4 + 20;
+    const SECTIONS: usize = OPTIONAL + 240;
+    const IMAGE_BASE: u64 = 0x1_4000_0000;
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn put_section(data: &mut [u8], index: usize, rva: u32, file: u32, size: u32, flags: u32) {
+        let s = SECTIONS + index * 40;
+        put(data, s + 8, &size.to_le_bytes());
+        put(data, s + 12, &rva.to_le_bytes());
+        put(data, s + 16, &size.to_le_bytes());
+        put(data, s + 20, &file.to_le_bytes());
+        put(data, s + 36, &flags.to_le_bytes());
+    }
+
+    /// `.text` at 0x1000 with a pointer to itself at 0x1008 and `.reloc` at
+    /// 0x2000 with one `DIR64` relocation for it.
+    fn test_image() -> [u8; 0x600] {
+        let mut data = [0_u8; 0x600];
+        put(&mut data, 0, b"MZ");
+        put(&mut data, 0x3c, &(PE_OFFSET as u32).to_le_bytes());
+        put(&mut data, PE_OFFSET, b"PE\0\0");
+        put(
+            &mut data,
+            PE_OFFSET + 4,
+            &IMAGE_FILE_MACHINE_AMD64.to_le_bytes(),
+        );
+        put(&mut data, PE_OFFSET + 6, &2_u16.to_le_bytes());
+        put(&mut data, PE_OFFSET + 20, &240_u16.to_le_bytes());
+
+        put(&mut data, OPTIONAL, &0x20b_u16.to_le_bytes());
+        put(&mut data, OPTIONAL + 16, &0x1000_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 24, &IMAGE_BASE.to_le_bytes());
+        put(&mut data, OPTIONAL + 32, &0x1000_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 56, &0x3000_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 60, &0x200_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 108, &16_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 112 + 5 * 8, &0x2000_u32.to_le_bytes());
+        put(&mut data, OPTIONAL + 112 + 5 * 8 + 4, &12_u32.to_le_bytes());
+
+        put_section(
+            &mut data,
+            0,
+            0x1000,
+            0x200,
+            0x200,
+            IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        );
+        put(&mut data, 0x208, &(IMAGE_BASE + 0x1000).to_le_bytes());
+
+        put_section(
+            &mut data,
+            1,
+            0x2000,
+            0x400,
+            0x200,
+            IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE,
+        );
+        put(&mut data, 0x400, &0x1000_u32.to_le_bytes());
+        put(&mut data, 0x404, &12_u32.to_le_bytes());
+        put(&mut data, 0x408, &((10_u16 << 12) | 8).to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn parse_headers() {
+        let data = test_image();
+        let pe = PeImage::parse(&data).unwrap();
+
+        assert_eq!(pe.machine, IMAGE_FILE_MACHINE_AMD64);
+        assert_eq!(pe.image_base, IMAGE_BASE);
+        assert_eq!(pe.address_of_entry_point, 0x1000);
+        assert_eq!(pe.size_of_image, 0x3000);
+
+        let text = pe.sections().next().unwrap().unwrap();
+        assert!(text.is_executable());
+        assert!(!text.is_writable());
+        assert_eq!(pe.sections().count(), 2);
+    }
+
+    #[test]
+    fn load_and_relocate() {
+        let data = test_image();
+        let pe = PeImage::parse(&data).unwrap();
+        let mut image = [0_u8; 0x3000];
+        pe.load(&mut image).unwrap();
+
+        assert_eq!(&image[..2], b"MZ");
+        let delta = 0x20_0000;
+        assert_eq!(pe.relocate(&mut image, delta), Ok(1));
+        assert_eq!(
+            u64::from_le_bytes(image[0x1008..0x1010].try_into().unwrap()),
+            IMAGE_BASE + 0x1000 + delta
+        );
+    }
+
+    #[test]
+    fn relocate_far() {
+        let data = test_image();
+        let pe = PeImage::parse(&data).unwrap();
+
+        // Up into the higher half, all the 64 bits of the pointer change.
+        let mut image = [0_u8; 0x3000];
+        pe.load(&mut image).unwrap();
+        let delta = 0xffff_8000_0000_0000 - IMAGE_BASE;
+        assert_eq!(pe.relocate(&mut image, delta), Ok(1));
+        assert_eq!(
+            u64::from_le_bytes(image[0x1008..0x1010].try_into().unwrap()),
+            0xffff_8000_0000_1000
+        );
+
+        // Down below 4 GiB, the delta wraps around.
+        let mut image = [0_u8; 0x3000];
+        pe.load(&mut image).unwrap();
+        let delta = 0x20_0000_u64.wrapping_sub(IMAGE_BASE);
+        assert_eq!(pe.relocate(&mut image, delta), Ok(1));
+        assert_eq!(
+            u64::from_le_bytes(image[0x1008..0x1010].try_into().unwrap()),
+            0x20_1000
+        );
+    }
+
+    #[test]
+    fn bad_images() {
+        assert_eq!(
+            PeImage::parse(b"\x7fELF").map(|_| ()),
+            Err(PeError::BadMagic)
+        );
+
+        let mut data = test_image();
+        data[OPTIONAL] = 0x0b;
+        data[OPTIONAL + 1] = 0x01;
+        assert_eq!(PeImage::parse(&data).map(|_| ()), Err(PeError::NotPe32Plus));
+
+        let data = test_image();
+        assert_eq!(
+            PeImage::parse(&data[..0x300]).map(|_| ()),
+            Err(PeError::Truncated)
+        );
+    }
+}
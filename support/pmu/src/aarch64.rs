@@ -0,0 +1,82 @@
+//! PMUv3, the cycle counter and event counter 0.
+
+use crate::Counters;
+use crate::Pmu;
+
+/// `ID_AA64DFR0_EL1.PMUVer`, `0xf` is a PMU that is not PMUv3.
+const PMU_VER_SHIFT: u64 = 8;
+const PMU_VER_IMPLEMENTATION_DEFINED: u64 = 0xf;
+/// PMUv3 for Armv8.5, the event counters can be 64 bits wide.
+const PMU_VER_V3P5: u64 = 6;
+
+/// `PMCR_EL0` bits.
+const PMCR_E: u64 = 1 << 0;
+const PMCR_LC: u64 = 1 << 6;
+const PMCR_LP: u64 = 1 << 7;
+const PMCR_N_SHIFT: u64 = 11;
+const PMCR_N_MASK: u64 = 0x1f;
+
+/// Counts at EL2 too, as the loader may run there. The EL1 and EL0
+/// filtering bits are left clear to count there.
+const FILTER_NSH: u64 = 1 << 27;
+const INST_RETIRED: u64 = 0x08;
+
+const CYCLE_COUNTER: u64 = 1 << 31;
+const EVENT_COUNTER_0: u64 = 1 << 0;
+
+fn read() -> Counters {
+    let (cycles, instructions): (u64, u64);
+    // SAFETY: reading the counters has no side effects.
+    unsafe {
+        core::arch::asm!(
+            "isb",
+            "mrs {cycles}, pmccntr_el0",
+            "mrs {instructions}, pmevcntr0_el0",
+            cycles = out(reg) cycles,
+            instructions = out(reg) instructions,
+            options(nomem, nostack)
+        );
+    }
+    Counters {
+        cycles,
+        instructions,
+    }
+}
+
+pub fn enable() -> Option<Pmu> {
+    let dfr0: u64;
+    // SAFETY: reading an ID register has no side effects.
+    unsafe {
+        core::arch::asm!("mrs {}, id_aa64dfr0_el1", out(reg) dfr0, options(nomem, nostack));
+    }
+    let version = (dfr0 >> PMU_VER_SHIFT) & 0xf;
+    if version == 0 || version == PMU_VER_IMPLEMENTATION_DEFINED {
+        return None;
+    }
+
+    let pmcr: u64;
+    // SAFETY: the PMU is there.
+    unsafe { core::arch::asm!("mrs {}, pmcr_el0", out(reg) pmcr, options(nomem, nostack)) };
+    if (pmcr >> PMCR_N_SHIFT) & PMCR_N_MASK == 0 {
+        return None;
+    }
+    let long_events = version >= PMU_VER_V3P5;
+    let pmcr = pmcr | PMCR_E | PMCR_LC | if long_events { PMCR_LP } else { 0 };
+
+    // SAFETY: the counters are not used by anything else.
+    unsafe {
+        core::arch::asm!(
+            "msr pmccfiltr_el0, {filter}",
+            "msr pmevtyper0_el0, {event}",
+            "msr pmcr_el0, {pmcr}",
+            "msr pmcntenset_el0, {counters}",
+            "isb",
+            filter = in(reg) FILTER_NSH,
+            event = in(reg) FILTER_NSH | INST_RETIRED,
+            pmcr = in(reg) pmcr,
+            counters = in(reg) CYCLE_COUNTER | EVENT_COUNTER_0,
+            options(nomem, nostack)
+        );
+    }
+    Some(Pmu::new(read, 64, if long_events { 64 } else { 32 }))
+}
@@ -0,0 +1,203 @@
+//! The performance counters for profiling the boot on the hardware where
+//! there is no profiler to run.
+//!
+//! Two counters are used, the processor cycles and the instructions
+//! retired:
+//!
+//! Architecture Cycles                  Instructions
+//! ----------------------------------------------------------------------
+//! aarch64      `PMCCNTR_EL0`           event counter 0, `INST_RETIRED`
+//! x86_64       fixed counter 1         fixed counter 0
+//!
+//! [`Pmu::enable`] checks the processor has the counters, `PMUVer` on
+//! aarch64 and the architectural performance monitoring CPUID leaf on
+//! x86_64, and starts them counting at all exception levels or rings. The
+//! counters are read with `mrs` and `rdpmc`, cheap enough to bracket short
+//! paths with. The hypervisors often do not expose the counters, and
+//! [`Pmu::enable`] returns `None` then.
+//!
+//! The counters may be narrower than 64 bits, and the differences are
+//! taken modulo their width, so a measured span is to be shorter than a
+//! wrap, seconds at least.
+//!
+//! A [`Scope`] adds what the counters have counted while it has lived to
+//! a [`Hotspot`]:
+//!
+//! ```ignore
+//! static COPY: pmu::Hotspot = pmu::Hotspot::new("copy");
+//!
+//! if let Some(pmu) = pmu::Pmu::enable() {
+//!     {
+//!         let _scope = pmu.scope(&COPY);
+//!         dst.copy_from_slice(src);
+//!     }
+//!     log::info!("{}", COPY.totals());
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+mod tests;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+use core::fmt;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+/// What the counters have counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub cycles: u64,
+    pub instructions: u64,
+}
+
+impl Counters {
+    /// The instructions per cycle, in hundredths.
+    pub fn ipc_percent(&self) -> u64 {
+        (self.instructions * 100)
+            .checked_div(self.cycles)
+            .unwrap_or(0)
+    }
+}
+
+/// The counters the processor has, started.
+#[derive(Debug, Clone, Copy)]
+pub struct Pmu {
+    read: fn() -> Counters,
+    /// The masks of the counter widths.
+    cycles_mask: u64,
+    instructions_mask: u64,
+}
+
+fn width_mask(bits: u32) -> u64 {
+    if bits >= u64::BITS {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+impl Pmu {
+    fn new(read: fn() -> Counters, cycles_bits: u32, instructions_bits: u32) -> Self {
+        Self {
+            read,
+            cycles_mask: width_mask(cycles_bits),
+            instructions_mask: width_mask(instructions_bits),
+        }
+    }
+
+    /// Starts the counters, `None` if there are none to use.
+    pub fn enable() -> Option<Self> {
+        #[cfg(target_arch = "aarch64")]
+        return aarch64::enable();
+        #[cfg(target_arch = "x86_64")]
+        return x86_64::enable();
+    }
+
+    pub fn read(&self) -> Counters {
+        (self.read)()
+    }
+
+    /// What has been counted from `start` to `end`.
+    pub fn elapsed(&self, start: Counters, end: Counters) -> Counters {
+        Counters {
+            cycles: end.cycles.wrapping_sub(start.cycles) & self.cycles_mask,
+            instructions: end.instructions.wrapping_sub(start.instructions)
+                & self.instructions_mask,
+        }
+    }
+
+    /// Runs `f`, and returns what it has taken.
+    pub fn measure<T>(&self, f: impl FnOnce() -> T) -> (T, Counters) {
+        let start = self.read();
+        let result = f();
+        (result, self.elapsed(start, self.read()))
+    }
+
+    /// Counts for `hotspot` until dropped.
+    pub fn scope<'a>(&'a self, hotspot: &'a Hotspot) -> Scope<'a> {
+        Scope {
+            pmu: self,
+            hotspot,
+            start: self.read(),
+        }
+    }
+}
+
+/// The totals of a path measured any number of times, a static usually.
+#[derive(Debug)]
+pub struct Hotspot {
+    name: &'static str,
+    calls: AtomicU64,
+    cycles: AtomicU64,
+    instructions: AtomicU64,
+}
+
+impl Hotspot {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            calls: AtomicU64::new(0),
+            cycles: AtomicU64::new(0),
+            instructions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add(&self, counters: Counters) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.cycles.fetch_add(counters.cycles, Ordering::Relaxed);
+        self.instructions
+            .fetch_add(counters.instructions, Ordering::Relaxed);
+    }
+
+    pub fn totals(&self) -> HotspotTotals {
+        HotspotTotals {
+            name: self.name,
+            calls: self.calls.load(Ordering::Relaxed),
+            counters: Counters {
+                cycles: self.cycles.load(Ordering::Relaxed),
+                instructions: self.instructions.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotspotTotals {
+    pub name: &'static str,
+    pub calls: u64,
+    pub counters: Counters,
+}
+
+impl fmt::Display for HotspotTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ipc = self.counters.ipc_percent();
+        write!(
+            f,
+            "{:<20}{:>6} calls{:>16} cycles{:>16} instructions, IPC {}.{:02}",
+            self.name,
+            self.calls,
+            self.counters.cycles,
+            self.counters.instructions,
+            ipc / 100,
+            ipc % 100
+        )
+    }
+}
+
+/// Adds the counts to the hotspot when dropped.
+pub struct Scope<'a> {
+    pmu: &'a Pmu,
+    hotspot: &'a Hotspot,
+    start: Counters,
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        let end = self.pmu.read();
+        self.hotspot.add(self.pmu.elapsed(self.start, end));
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(test)]
+
+use super::*;
+use std::cell::Cell;
+
+thread_local! {
+    static NOW: Cell<Counters> = const { Cell::new(Counters { cycles: 0, instructions: 0 }) };
+}
+
+fn fake_read() -> Counters {
+    NOW.with(Cell::get)
+}
+
+fn advance(cycles: u64, instructions: u64) {
+    NOW.with(|now| {
+        let counters = now.get();
+        now.set(Counters {
+            cycles: counters.cycles.wrapping_add(cycles),
+            instructions: counters.instructions.wrapping_add(instructions),
+        })
+    });
+}
+
+fn fake_pmu(cycles_bits: u32, instructions_bits: u32) -> Pmu {
+    Pmu::new(fake_read, cycles_bits, instructions_bits)
+}
+
+#[test]
+fn measure() {
+    let pmu = fake_pmu(64, 64);
+    let (value, counters) = pmu.measure(|| {
+        advance(1000, 1500);
+        42
+    });
+    assert_eq!(value, 42);
+    assert_eq!(
+        counters,
+        Counters {
+            cycles: 1000,
+            instructions: 1500
+        }
+    );
+    assert_eq!(counters.ipc_percent(), 150);
+    assert_eq!(Counters::default().ipc_percent(), 0);
+}
+
+#[test]
+fn wrap() {
+    let pmu = fake_pmu(64, 32);
+    let start = Counters {
+        cycles: u64::MAX - 5,
+        instructions: 0xffff_fff0,
+    };
+    let end = Counters {
+        cycles: 10,
+        // The 32-bit counter has wrapped, and the upper bits read as 0.
+        instructions: 0x10,
+    };
+    assert_eq!(
+        pmu.elapsed(start, end),
+        Counters {
+            cycles: 16,
+            instructions: 0x20
+        }
+    );
+
+    let pmu = fake_pmu(48, 48);
+    let start = Counters {
+        cycles: 0xffff_ffff_fff0,
+        instructions: 0,
+    };
+    let end = Counters {
+        cycles: 0x10,
+        instructions: 0,
+    };
+    assert_eq!(pmu.elapsed(start, end).cycles, 0x20);
+}
+
+#[test]
+fn scope() {
+    static HOTSPOT: Hotspot = Hotspot::new("copy");
+
+    let pmu = fake_pmu(64, 64);
+    for _ in 0..3 {
+        let _scope = pmu.scope(&HOTSPOT);
+        advance(200, 100);
+    }
+    // Not counted, outside of the scopes.
+    advance(1000, 1000);
+
+    let totals = HOTSPOT.totals();
+    assert_eq!(
+        totals,
+        HotspotTotals {
+            name: "copy",
+            calls: 3,
+            counters: Counters {
+                cycles: 600,
+                instructions: 300
+            }
+        }
+    );
+    assert_eq!(
+        totals.to_string(),
+        "copy                     3 calls             600 cycles             300 instructions, IPC 0.50"
+    );
+}
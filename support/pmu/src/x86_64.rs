@@ -0,0 +1,68 @@
+//! The architectural fixed counters, read with `rdpmc`.
+
+use crate::Counters;
+use crate::Pmu;
+use core::arch::x86_64::__cpuid;
+
+const ARCH_PERFMON_LEAF: u32 = 0xa;
+
+const IA32_FIXED_CTR_CTRL: u32 = 0x38d;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+/// `INST_RETIRED.ANY` and `CPU_CLK_UNHALTED.CORE`.
+const FIXED_INSTRUCTIONS: u32 = 0;
+const FIXED_CYCLES: u32 = 1;
+/// The ring 0 and ring 3 enable bits of the fixed counters 0 and 1.
+const FIXED_CTR_CTRL_ENABLE: u64 = 0x33;
+const GLOBAL_CTRL_FIXED_SHIFT: u32 = 32;
+/// Selects the fixed counters for `rdpmc`.
+const RDPMC_FIXED: u32 = 1 << 30;
+
+fn rdpmc(counter: u32) -> u64 {
+    let (lo, hi): (u32, u32);
+    // SAFETY: the counter is there, and reading it has no side effects.
+    unsafe {
+        core::arch::asm!("rdpmc", in("ecx") counter, out("eax") lo, out("edx") hi, options(nomem, nostack));
+    }
+    (hi as u64) << 32 | lo as u64
+}
+
+/// # Safety
+///
+/// The MSR is there, and writing it does not break anything.
+unsafe fn wrmsr(msr: u32, value: u64) {
+    // SAFETY: guaranteed by the caller.
+    unsafe {
+        core::arch::asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack));
+    }
+}
+
+fn read() -> Counters {
+    Counters {
+        cycles: rdpmc(RDPMC_FIXED | FIXED_CYCLES),
+        instructions: rdpmc(RDPMC_FIXED | FIXED_INSTRUCTIONS),
+    }
+}
+
+pub fn enable() -> Option<Pmu> {
+    if __cpuid(0).eax < ARCH_PERFMON_LEAF {
+        return None;
+    }
+    let leaf = __cpuid(ARCH_PERFMON_LEAF);
+    let version = leaf.eax & 0xff;
+    let fixed_counters = leaf.edx & 0x1f;
+    let fixed_width = (leaf.edx >> 5) & 0xff;
+    // The fixed counters are enumerated starting with version 2.
+    if version < 2 || fixed_counters <= FIXED_CYCLES || fixed_width == 0 {
+        return None;
+    }
+
+    // SAFETY: the architectural performance monitoring version 2 has the
+    // MSRs, and nothing else uses the fixed counters. The general purpose
+    // counters are left disabled.
+    unsafe {
+        wrmsr(IA32_FIXED_CTR_CTRL, FIXED_CTR_CTRL_ENABLE);
+        wrmsr(IA32_PERF_GLOBAL_CTRL, 0b11 << GLOBAL_CTRL_FIXED_SHIFT);
+    }
+    Some(Pmu::new(read, fixed_width, fixed_width))
+}
@@ -2,8 +2,13 @@
 
 mod pl011;
 mod uart16550;
+mod virtio_console;
 
 pub use pl011::Pl011;
 pub use uart16550::BaudDivisor;
 pub use uart16550::ComPort;
 pub use uart16550::ComPortIo;
+pub use virtio_console::probe as probe_virtio_console;
+pub use virtio_console::VirtioConsole;
+pub use virtio_console::VirtioConsoleError;
+pub use virtio_console::VirtioConsoleQueues;
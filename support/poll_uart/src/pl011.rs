@@ -30,47 +30,36 @@
 //! 0xFF8   UARTPCellID2      RO   0x05         8       UARTPCellID2 Register
 //! 0xFFC   UARTPCellID3      RO   0xB1         8       UARTPCellID3 Register
 
-// TODO: worth replacing with a structure and storing the pointer to it?
-#[derive(Debug, Clone, Copy)]
-#[repr(u16)]
-enum Pl011Register {
-    /// Data Register
-    Dr = 0x000,
-    /// Receive Status Register/Error Clear Register
-    RsrOrEcr = 0x004,
-    /// Flag register
-    Fr = 0x018,
-    /// Integer Baud Rate Register
-    Ibrd = 0x024,
-    /// Fractional Baud Rate Register
-    Fbrd = 0x028,
-    /// Line Control Register
-    LcrHigh = 0x02c,
-    /// Control Register
-    Cr = 0x030,
-    /// Masked Interrupt Status Register
-    Imsc = 0x038,
-    /// Interrupt Clear Register
-    Icr = 0x044,
-    /// DMA Control Register
-    DmaCr = 0x048,
-    /// UARTPeriphID0 Register
-    PeriphID0 = 0xFE0,
-    /// UARTPeriphID1 Register
-    PeriphID1 = 0xFE4,
-    /// UARTPeriphID2 Register
-    PeriphID2 = 0xFE8,
-    /// UARTPeriphID3 Register
-    PeriphID3 = 0xFEC,
-    /// UARTPCellID0 Register
-    PCellID0 = 0xFF0,
-    /// UARTPCellID1 Register
-    PCellID1 = 0xFF4,
-    /// UARTPCellID2 Register
-    PCellID2 = 0xFF8,
-    /// UARTPCellID3 Register
-    PCellID3 = 0xFFC,
-}
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+use mmio::WriteOnly;
+
+/// Data Register
+const DR: Register<u32, ReadWrite> = Register::new(0x000);
+/// Receive Status Register/Error Clear Register
+const RSR_ECR: Register<u32, ReadWrite> = Register::new(0x004);
+/// Flag register
+const FR: Register<u32, ReadOnly> = Register::new(0x018);
+/// Integer Baud Rate Register
+const IBRD: Register<u32, ReadWrite> = Register::new(0x024);
+/// Fractional Baud Rate Register
+const FBRD: Register<u32, ReadWrite> = Register::new(0x028);
+/// Line Control Register
+const LCR_H: Register<u32, ReadWrite> = Register::new(0x02c);
+/// Control Register
+const CR: Register<u32, ReadWrite> = Register::new(0x030);
+/// Interrupt Mask Set/Clear Register
+const IMSC: Register<u32, ReadWrite> = Register::new(0x038);
+/// Interrupt Clear Register
+const ICR: Register<u32, WriteOnly> = Register::new(0x044);
+/// DMA Control Register
+const DMACR: Register<u32, ReadWrite> = Register::new(0x048);
+/// UARTPeriphID0 Register, the 3 others follow.
+const PERIPH_ID0: Register<u32, ReadOnly> = Register::new(0xfe0);
+/// UARTPCellID0 Register, the 3 others follow.
+const PCELL_ID0: Register<u32, ReadOnly> = Register::new(0xff0);
 
 const CR_RX_ENABLE: u32 = 0x200;
 const CR_TX_ENABLE: u32 = 0x100;
@@ -81,96 +70,69 @@ const LCR_H_8BITS: u32 = 0x60;
 const _FR_TX_EMPTY: u32 = 0x080;
 const _FR_RX_FULL: u32 = 0x040;
 const FR_TX_FULL: u32 = 0x020;
-const _FR_RX_EMPTY: u32 = 0x010;
+const FR_RX_EMPTY: u32 = 0x010;
 const FR_BUSY: u32 = 0x008;
 
 /// PL011 UART.
 #[derive(Debug, Clone, Copy)]
 pub struct Pl011 {
-    base_addr: u64,
+    regs: RegisterBlock,
     id: u64,
 }
 
-fn id(pl011: &Pl011) -> u64 {
-    // This can easily be rewritten employing
-    // bare ariphmetic yet the compiler does a very good job
-    // so using the domain abstractions.
-    [
-        Pl011Register::PeriphID0,
-        Pl011Register::PeriphID1,
-        Pl011Register::PeriphID2,
-        Pl011Register::PeriphID3,
-        Pl011Register::PCellID0,
-        Pl011Register::PCellID1,
-        Pl011Register::PCellID2,
-        Pl011Register::PCellID3,
-    ]
-    .iter()
-    .fold(0, |id_running, &r| {
-        id_running.wrapping_shl(8) | (read_register(pl011, r) as u8 as u64)
-    })
+fn id(regs: &RegisterBlock) -> u64 {
+    (0..4)
+        .map(|i| PERIPH_ID0.at(i))
+        .chain((0..4).map(|i| PCELL_ID0.at(i)))
+        .fold(0, |id_running, r| {
+            id_running.wrapping_shl(8) | (regs.read(r) as u8 as u64)
+        })
 }
 
 /// Disables the functional parts of the UART, drains FIFOs,
 /// sets baud rate and enables the UART in the polling mode.
-fn reset_and_init(pl011: &mut Pl011) {
-    pl011.id = id(pl011);
-
+fn reset_and_init(regs: &RegisterBlock) {
     // Mask interrupts (lower 11 bits)
-    write_register(pl011, Pl011Register::Imsc, 0x7ff);
+    regs.write(IMSC, 0x7ff);
     // Clear interrupts (lower 11 bits)
-    write_register(pl011, Pl011Register::Icr, 0x7ff);
+    regs.write(ICR, 0x7ff);
     // Disable DMA on Rx and Tx
-    write_register(pl011, Pl011Register::DmaCr, 0x0);
+    regs.write(DMACR, 0x0);
 
     // Leave Rx and Tx enabled to drain FIFOs.
-    write_register(pl011, Pl011Register::Cr, CR_RX_ENABLE | CR_TX_ENABLE);
-    read_register(pl011, Pl011Register::Cr); // wait
-    read_register(pl011, Pl011Register::Cr); // wait
-    poll_not_busy(pl011);
+    regs.write(CR, CR_RX_ENABLE | CR_TX_ENABLE);
+    regs.read(CR); // wait
+    regs.read(CR); // wait
+    poll_not_busy(regs);
 
     // Disable Rx, Tx, and UART.
-    write_register(pl011, Pl011Register::Cr, 0x00000000);
+    regs.write(CR, 0x00000000);
 
     // Set integer and fractional parts of the baud rate,
     // harcoded for now
-    write_register(pl011, Pl011Register::Fbrd, 0x00000004);
-    write_register(pl011, Pl011Register::Ibrd, 0x00000027);
+    regs.write(FBRD, 0x00000004);
+    regs.write(IBRD, 0x00000027);
     // The UARTLCR_H, UARTIBRD, and UARTFBRD registers form the single 30-bit
     // wide UARTLCR Register that is updated on a single write strobe generated by a
     // UARTLCR_H write
-    write_register(pl011, Pl011Register::LcrHigh, LCR_H_FIFO_EN | LCR_H_8BITS);
+    regs.write(LCR_H, LCR_H_FIFO_EN | LCR_H_8BITS);
 
     // Clear the errors
-    write_register(pl011, Pl011Register::RsrOrEcr, 0);
+    regs.write(RSR_ECR, 0);
 
     // Enable Tx and Rx
-    write_register(pl011, Pl011Register::Cr, CR_RX_ENABLE | CR_TX_ENABLE);
-    read_register(pl011, Pl011Register::Cr); // wait
-    read_register(pl011, Pl011Register::Cr); // wait
-    poll_not_busy(pl011);
+    regs.write(CR, CR_RX_ENABLE | CR_TX_ENABLE);
+    regs.read(CR); // wait
+    regs.read(CR); // wait
+    poll_not_busy(regs);
 
     // Enable UART
-    write_register(
-        pl011,
-        Pl011Register::Cr,
-        CR_RX_ENABLE | CR_TX_ENABLE | CR_UART_ENABLE,
-    );
-    poll_not_busy(pl011);
+    regs.write(CR, CR_RX_ENABLE | CR_TX_ENABLE | CR_UART_ENABLE);
+    poll_not_busy(regs);
 }
 
-fn read_register(pl011: &Pl011, reg: Pl011Register) -> u32 {
-    unsafe { core::ptr::read_volatile((pl011.base_addr + reg as u64) as *const u32) }
-}
-
-fn write_register(pl011: &mut Pl011, reg: Pl011Register, val: u32) {
-    unsafe {
-        core::ptr::write_volatile((pl011.base_addr + reg as u64) as *mut u32, val);
-    }
-}
-
-fn poll_tx_not_full(pl011: &Pl011) {
-    while read_register(pl011, Pl011Register::Fr) & FR_TX_FULL != 0 {
+fn poll_tx_not_full(regs: &RegisterBlock) {
+    while regs.read(FR) & FR_TX_FULL != 0 {
         #[cfg(target_arch = "aarch64")]
         unsafe {
             core::arch::asm!("yield");
@@ -178,8 +140,8 @@ fn poll_tx_not_full(pl011: &Pl011) {
     }
 }
 
-fn poll_not_busy(pl011: &Pl011) {
-    while read_register(pl011, Pl011Register::Fr) & FR_BUSY != 0 {
+fn poll_not_busy(regs: &RegisterBlock) {
+    while regs.read(FR) & FR_BUSY != 0 {
         #[cfg(target_arch = "aarch64")]
         unsafe {
             core::arch::asm!("yield");
@@ -189,14 +151,26 @@ fn poll_not_busy(pl011: &Pl011) {
 
 impl Pl011 {
     pub fn new(base_addr: u64) -> Pl011 {
-        let mut pl011 = Self { base_addr, id: !0 };
-        reset_and_init(&mut pl011);
-        pl011
+        // SAFETY: the callers pass the UART from the firmware tables, that
+        // is mapped.
+        let regs = unsafe { RegisterBlock::new(base_addr) };
+        let id = id(&regs);
+        reset_and_init(&regs);
+        Self { regs, id }
     }
 
     pub fn send_byte(&mut self, byte: u8) {
-        poll_tx_not_full(self);
-        write_register(self, Pl011Register::Dr, byte.into());
+        poll_tx_not_full(&self.regs);
+        self.regs.write(DR, byte.into());
+    }
+
+    /// Returns the received byte if there is one, does not wait.
+    pub fn try_receive_byte(&self) -> Option<u8> {
+        if self.regs.read(FR) & FR_RX_EMPTY == 0 {
+            Some(self.regs.read(DR) as u8)
+        } else {
+            None
+        }
     }
 
     pub fn id(&self) -> u64 {
@@ -297,6 +297,18 @@ fn receive_byte(port: ComPortIo) -> u8 {
     inp8(base_addr)
 }
 
+fn try_receive_byte(port: ComPortIo) -> Option<u8> {
+    let base_addr = port as u16;
+    let lsr = base_addr + 5; // Line Status Register
+
+    // RBF: Receiving buffer full
+    if (inp8(lsr) & 0x1) != 0 {
+        Some(inp8(base_addr))
+    } else {
+        None
+    }
+}
+
 /// Serial portwith 8 bit data, 1 stop bit, and no parity.
 #[derive(Debug, Clone, Copy)]
 pub struct ComPort {
@@ -331,6 +343,15 @@ impl ComPort {
             0xff
         }
     }
+
+    /// Returns the received byte if there is one, does not wait.
+    pub fn try_receive_byte(&self) -> Option<u8> {
+        if self.kind != UartKind::None {
+            try_receive_byte(self.port)
+        } else {
+            None
+        }
+    }
 }
 
 impl core::fmt::Write for ComPort {
@@ -0,0 +1,460 @@
+//! Virtio console driver over the MMIO transport, legacy (version 1) and
+//! modern (version 2).
+//!
+//! Can run in an interrupt-free single thread environment only. Uses the
+//! port 0 queues of the device without the multiport feature: the receive
+//! queue 0 and the transmit queue 1. Each queue has a single buffer in
+//! flight, the writes wait for the device to take the buffer. Follows
+//! [Virtual I/O Device (VIRTIO) Version 1.2](https://docs.oasis-open.org/virtio/virtio/v1.2/virtio-v1.2.html),
+//! 4.2 "Virtio Over MMIO" and 5.3 "Console Device".
+
+//! MMIO registers used:
+//!
+//! Offset  Name                Type  Description
+//! ----------------------------------------------------------------------
+//! 0x000   MagicValue          RO    0x74726976, "virt"
+//! 0x004   Version             RO    1 for legacy, 2 for modern
+//! 0x008   DeviceID            RO    3 for the console
+//! 0x010   DeviceFeatures      RO    32 feature bits selected below
+//! 0x014   DeviceFeaturesSel   WO    Which 32 feature bits to read
+//! 0x020   DriverFeatures      WO    32 feature bits selected below
+//! 0x024   DriverFeaturesSel   WO    Which 32 feature bits to write
+//! 0x028   GuestPageSize       WO    Legacy only
+//! 0x030   QueueSel            WO    The queue the registers below refer to
+//! 0x034   QueueNumMax         RO    The maximum size of the queue
+//! 0x038   QueueNum            WO    The size of the queue
+//! 0x03C   QueueAlign          WO    Legacy only, the used ring alignment
+//! 0x040   QueuePFN            RW    Legacy only, the page of the queue
+//! 0x044   QueueReady          RW    Modern only
+//! 0x050   QueueNotify         WO    The queue with new buffers
+//! 0x070   Status              RW    The device status
+//! 0x080   QueueDescLow/High   WO    Modern only, the descriptor table
+//! 0x090   QueueDriverLow/High WO    Modern only, the available ring
+//! 0x0A0   QueueDeviceLow/High WO    Modern only, the used ring
+
+use core::ptr::addr_of;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::fence;
+use core::sync::atomic::Ordering;
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+use mmio::WriteOnly;
+use sync::Spinlock;
+
+/// "virt"
+const MAGIC_VALUE: Register<u32, ReadOnly> = Register::new(0x000);
+const VERSION: Register<u32, ReadOnly> = Register::new(0x004);
+const DEVICE_ID: Register<u32, ReadOnly> = Register::new(0x008);
+const DEVICE_FEATURES: Register<u32, ReadOnly> = Register::new(0x010);
+const DEVICE_FEATURES_SEL: Register<u32, WriteOnly> = Register::new(0x014);
+const DRIVER_FEATURES: Register<u32, WriteOnly> = Register::new(0x020);
+const DRIVER_FEATURES_SEL: Register<u32, WriteOnly> = Register::new(0x024);
+const GUEST_PAGE_SIZE: Register<u32, WriteOnly> = Register::new(0x028);
+const QUEUE_SEL: Register<u32, WriteOnly> = Register::new(0x030);
+const QUEUE_NUM_MAX: Register<u32, ReadOnly> = Register::new(0x034);
+const QUEUE_NUM: Register<u32, WriteOnly> = Register::new(0x038);
+const QUEUE_ALIGN: Register<u32, WriteOnly> = Register::new(0x03c);
+const QUEUE_PFN: Register<u32, ReadWrite> = Register::new(0x040);
+const QUEUE_READY: Register<u32, ReadWrite> = Register::new(0x044);
+const QUEUE_NOTIFY: Register<u32, WriteOnly> = Register::new(0x050);
+const STATUS: Register<u32, ReadWrite> = Register::new(0x070);
+/// The low halves of the addresses, the high halves follow.
+const QUEUE_DESC_LOW: Register<u32, WriteOnly> = Register::new(0x080);
+const QUEUE_DRIVER_LOW: Register<u32, WriteOnly> = Register::new(0x090);
+const QUEUE_DEVICE_LOW: Register<u32, WriteOnly> = Register::new(0x0a0);
+
+const MAGIC: u32 = 0x7472_6976;
+const VERSION_LEGACY: u32 = 1;
+const VERSION_MODERN: u32 = 2;
+const DEVICE_ID_CONSOLE: u32 = 3;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// VIRTIO_F_VERSION_1, bit 32 of the features.
+const FEATURES_HIGH_VERSION_1: u32 = 1;
+
+const RECEIVE_QUEUE: u32 = 0;
+const TRANSMIT_QUEUE: u32 = 1;
+
+const PAGE_SIZE: u32 = 0x1000;
+/// The split queues must be a power of 2 long, only the descriptor 0 is
+/// ever used.
+const QUEUE_SIZE: usize = 2;
+const DESC_F_WRITE: u16 = 2;
+
+const RECEIVE_BUFFER_SIZE: usize = 64;
+const TRANSMIT_BUFFER_SIZE: usize = 256;
+
+/// How many times the used ring is polled before the device is taken for
+/// gone, and the output is dropped.
+const TRANSMIT_SPIN_LIMIT: usize = 0x100_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioConsoleError {
+    /// No virtio device at the address.
+    NotVirtio,
+    /// The transport version is neither 1 nor 2.
+    UnsupportedVersion(u32),
+    /// A virtio device, but not a console.
+    NotConsole(u32),
+    /// The device has not accepted the features.
+    FeaturesRejected,
+    /// The queue is missing or already in use.
+    QueueUnavailable(u32),
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct AvailableRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct UsedElement {
+    id: u32,
+    len: u32,
+}
+
+/// The legacy transport wants the used ring at the next `QueueAlign`
+/// boundary after the available ring.
+#[derive(Clone, Copy)]
+#[repr(C, align(4096))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElement; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// One split queue laid out as the legacy transport expects, the modern
+/// one takes the parts wherever they are.
+#[derive(Clone, Copy)]
+#[repr(C, align(4096))]
+struct Virtqueue {
+    descriptors: [Descriptor; QUEUE_SIZE],
+    available: AvailableRing,
+    used: UsedRing,
+}
+
+/// The memory the device reads and writes, must be identity-mapped and
+/// live as long as the device is used. The state of the driver is here
+/// too, so that [`VirtioConsole`] can be copied around.
+#[repr(C, align(4096))]
+pub struct VirtioConsoleQueues {
+    receive: Virtqueue,
+    transmit: Virtqueue,
+    receive_buffer: [u8; RECEIVE_BUFFER_SIZE],
+    transmit_buffer: [u8; TRANSMIT_BUFFER_SIZE],
+    /// The used index of the receive queue seen last.
+    receive_used: u16,
+    /// The bytes received and not read yet.
+    receive_pos: usize,
+    receive_len: usize,
+    /// Set once the device has not taken a buffer in time.
+    transmit_stalled: bool,
+}
+
+impl VirtioConsoleQueues {
+    pub const fn new() -> Self {
+        const QUEUE: Virtqueue = Virtqueue {
+            descriptors: [Descriptor {
+                addr: 0,
+                len: 0,
+                flags: 0,
+                next: 0,
+            }; QUEUE_SIZE],
+            available: AvailableRing {
+                flags: 0,
+                idx: 0,
+                ring: [0; QUEUE_SIZE],
+                used_event: 0,
+            },
+            used: UsedRing {
+                flags: 0,
+                idx: 0,
+                ring: [UsedElement { id: 0, len: 0 }; QUEUE_SIZE],
+                avail_event: 0,
+            },
+        };
+
+        Self {
+            receive: QUEUE,
+            transmit: QUEUE,
+            receive_buffer: [0; RECEIVE_BUFFER_SIZE],
+            transmit_buffer: [0; TRANSMIT_BUFFER_SIZE],
+            receive_used: 0,
+            receive_pos: 0,
+            receive_len: 0,
+            transmit_stalled: false,
+        }
+    }
+}
+
+impl Default for VirtioConsoleQueues {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct VirtioConsole {
+    regs: RegisterBlock,
+    queues: &'static Spinlock<VirtioConsoleQueues>,
+}
+
+impl core::fmt::Debug for VirtioConsole {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VirtioConsole")
+            .field("regs", &self.regs)
+            .finish_non_exhaustive()
+    }
+}
+
+fn write_u64(regs: &RegisterBlock, low: Register<u32, WriteOnly>, val: u64) {
+    regs.write(low, val as u32);
+    regs.write(low.at(1), (val >> 32) as u32);
+}
+
+/// Checks the transport and the device ID, returns the transport version.
+///
+/// # Safety
+///
+/// The address must be mapped, and reading the first registers of the
+/// device there must have no side effects, as for the virtio-mmio
+/// transports described in the device tree.
+pub unsafe fn probe(base_addr: u64) -> Result<u32, VirtioConsoleError> {
+    // SAFETY: guaranteed by the caller.
+    let regs = unsafe { RegisterBlock::new(base_addr) };
+    if regs.read(MAGIC_VALUE) != MAGIC {
+        return Err(VirtioConsoleError::NotVirtio);
+    }
+    let version = regs.read(VERSION);
+    if version != VERSION_LEGACY && version != VERSION_MODERN {
+        return Err(VirtioConsoleError::UnsupportedVersion(version));
+    }
+    // Zero is a transport with no device behind it.
+    let device_id = regs.read(DEVICE_ID);
+    if device_id != DEVICE_ID_CONSOLE {
+        return Err(VirtioConsoleError::NotConsole(device_id));
+    }
+
+    Ok(version)
+}
+
+fn set_up_queue(
+    regs: &RegisterBlock,
+    version: u32,
+    index: u32,
+    queue: &Virtqueue,
+) -> Result<(), VirtioConsoleError> {
+    regs.write(QUEUE_SEL, index);
+    let in_use = if version == VERSION_LEGACY {
+        regs.read(QUEUE_PFN)
+    } else {
+        regs.read(QUEUE_READY)
+    };
+    let max = regs.read(QUEUE_NUM_MAX);
+    if in_use != 0 || max < QUEUE_SIZE as u32 {
+        return Err(VirtioConsoleError::QueueUnavailable(index));
+    }
+    regs.write(QUEUE_NUM, QUEUE_SIZE as u32);
+
+    if version == VERSION_LEGACY {
+        let pfn = queue as *const Virtqueue as u64 / PAGE_SIZE as u64;
+        regs.write(QUEUE_ALIGN, PAGE_SIZE);
+        regs.write(QUEUE_PFN, pfn as u32);
+    } else {
+        write_u64(regs, QUEUE_DESC_LOW, addr_of!(queue.descriptors) as u64);
+        write_u64(regs, QUEUE_DRIVER_LOW, addr_of!(queue.available) as u64);
+        write_u64(regs, QUEUE_DEVICE_LOW, addr_of!(queue.used) as u64);
+        regs.write(QUEUE_READY, 1);
+    }
+
+    Ok(())
+}
+
+/// Hands the buffer in the descriptor 0 to the device.
+fn post_buffer(regs: &RegisterBlock, index: u32, queue: &mut Virtqueue) {
+    let idx = queue.available.idx;
+    queue.available.ring[idx as usize % QUEUE_SIZE] = 0;
+    // The descriptor and the ring entry are visible before the index.
+    fence(Ordering::SeqCst);
+    // SAFETY: the index is read by the device, the pointer is valid.
+    unsafe { addr_of_mut!(queue.available.idx).write_volatile(idx.wrapping_add(1)) };
+    fence(Ordering::SeqCst);
+    regs.write(QUEUE_NOTIFY, index);
+}
+
+fn used_idx(queue: &Virtqueue) -> u16 {
+    // SAFETY: the index is written by the device, the pointer is valid.
+    let idx = unsafe { addr_of!(queue.used.idx).read_volatile() };
+    fence(Ordering::SeqCst);
+    idx
+}
+
+impl VirtioConsole {
+    /// Resets the device, negotiates no features besides the modern
+    /// transport, sets up the queues, and posts the receive buffer.
+    ///
+    /// # Safety
+    ///
+    /// As for [`probe`]. The queues must be identity-mapped, and not
+    /// given to another device.
+    pub unsafe fn new(
+        base_addr: u64,
+        queues: &'static Spinlock<VirtioConsoleQueues>,
+    ) -> Result<Self, VirtioConsoleError> {
+        // SAFETY: the caller upholds the contract.
+        let version = unsafe { probe(base_addr)? };
+        // SAFETY: as for `probe`.
+        let regs = unsafe { RegisterBlock::new(base_addr) };
+
+        regs.write(STATUS, 0);
+        regs.write(STATUS, STATUS_ACKNOWLEDGE);
+        let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+        regs.write(STATUS, status);
+
+        if version == VERSION_LEGACY {
+            regs.write(DRIVER_FEATURES_SEL, 0);
+            regs.write(DRIVER_FEATURES, 0);
+            regs.write(GUEST_PAGE_SIZE, PAGE_SIZE);
+        } else {
+            regs.write(DEVICE_FEATURES_SEL, 1);
+            let features_high = regs.read(DEVICE_FEATURES);
+            if features_high & FEATURES_HIGH_VERSION_1 == 0 {
+                regs.write(STATUS, 0);
+                return Err(VirtioConsoleError::FeaturesRejected);
+            }
+            regs.write(DRIVER_FEATURES_SEL, 0);
+            regs.write(DRIVER_FEATURES, 0);
+            regs.write(DRIVER_FEATURES_SEL, 1);
+            regs.write(DRIVER_FEATURES, FEATURES_HIGH_VERSION_1);
+
+            status |= STATUS_FEATURES_OK;
+            regs.write(STATUS, status);
+            if regs.read(STATUS) & STATUS_FEATURES_OK == 0 {
+                regs.write(STATUS, 0);
+                return Err(VirtioConsoleError::FeaturesRejected);
+            }
+        }
+
+        let mut state = queues.lock();
+        *state = VirtioConsoleQueues::new();
+        for (index, queue) in [
+            (RECEIVE_QUEUE, &state.receive),
+            (TRANSMIT_QUEUE, &state.transmit),
+        ] {
+            if let Err(e) = set_up_queue(&regs, version, index, queue) {
+                regs.write(STATUS, 0);
+                return Err(e);
+            }
+        }
+
+        status |= STATUS_DRIVER_OK;
+        regs.write(STATUS, status);
+
+        let state = &mut *state;
+        state.receive.descriptors[0] = Descriptor {
+            addr: state.receive_buffer.as_ptr() as u64,
+            len: RECEIVE_BUFFER_SIZE as u32,
+            flags: DESC_F_WRITE,
+            next: 0,
+        };
+        post_buffer(&regs, RECEIVE_QUEUE, &mut state.receive);
+
+        Ok(Self { regs, queues })
+    }
+
+    /// Sends up to the size of the transmit buffer, and waits for the
+    /// device to take it.
+    fn send_chunk(&mut self, bytes: &[u8]) {
+        let regs = self.regs;
+        let mut guard = self.queues.lock();
+        let queues = &mut *guard;
+        if queues.transmit_stalled {
+            return;
+        }
+
+        queues.transmit_buffer[..bytes.len()].copy_from_slice(bytes);
+        queues.transmit.descriptors[0] = Descriptor {
+            addr: queues.transmit_buffer.as_ptr() as u64,
+            len: bytes.len() as u32,
+            flags: 0,
+            next: 0,
+        };
+        post_buffer(&regs, TRANSMIT_QUEUE, &mut queues.transmit);
+
+        let posted = queues.transmit.available.idx;
+        for _ in 0..TRANSMIT_SPIN_LIMIT {
+            if used_idx(&queues.transmit) == posted {
+                return;
+            }
+            core::hint::spin_loop();
+        }
+        queues.transmit_stalled = true;
+    }
+
+    pub fn send_byte(&mut self, byte: u8) {
+        self.send_chunk(&[byte]);
+    }
+
+    /// Returns the received byte if there is one, does not wait.
+    pub fn try_receive_byte(&mut self) -> Option<u8> {
+        let regs = self.regs;
+        let mut guard = self.queues.lock();
+        let queues = &mut *guard;
+
+        if queues.receive_pos == queues.receive_len {
+            let used = used_idx(&queues.receive);
+            if used == queues.receive_used {
+                return None;
+            }
+            let element = queues.receive.used.ring[queues.receive_used as usize % QUEUE_SIZE];
+            queues.receive_used = used;
+            queues.receive_pos = 0;
+            queues.receive_len = (element.len as usize).min(RECEIVE_BUFFER_SIZE);
+            if queues.receive_len == 0 {
+                post_buffer(&regs, RECEIVE_QUEUE, &mut queues.receive);
+                return None;
+            }
+        }
+
+        let byte = queues.receive_buffer[queues.receive_pos];
+        queues.receive_pos += 1;
+        if queues.receive_pos == queues.receive_len {
+            post_buffer(&regs, RECEIVE_QUEUE, &mut queues.receive);
+        }
+        Some(byte)
+    }
+
+    pub fn base_addr(&self) -> u64 {
+        self.regs.base_addr()
+    }
+}
+
+impl core::fmt::Write for VirtioConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for chunk in s.as_bytes().chunks(TRANSMIT_BUFFER_SIZE) {
+            self.send_chunk(chunk);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! The Power State Coordination Interface, the calls to the firmware to
+//! power the processors and the system on and off.
+//!
+//! The calls follow the SMC Calling Convention and go either through `smc`
+//! or through `hvc`. Which one is the conduit the firmware describes in the
+//! FADT or in the `method` property of the `psci` device tree node.
+//!
+//! See [Reference](https://developer.arm.com/documentation/den0022/latest)
+//! for further details.
+
+#![cfg_attr(not(test), no_std)]
+
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const CPU_ON: u32 = 0xc400_0003;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+
+/// The FADT ARM boot architecture flags.
+pub const ARM_BOOT_ARCH_PSCI_COMPLIANT: u16 = 1 << 0;
+pub const ARM_BOOT_ARCH_PSCI_USE_HVC: u16 = 1 << 1;
+
+/// The instruction the calls trap to the firmware with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conduit {
+    Smc,
+    Hvc,
+}
+
+impl Conduit {
+    /// The conduit from the ARM boot architecture flags of the FADT, if the
+    /// system implements PSCI.
+    pub const fn from_arm_boot_flags(flags: u16) -> Option<Self> {
+        if flags & ARM_BOOT_ARCH_PSCI_COMPLIANT == 0 {
+            None
+        } else if flags & ARM_BOOT_ARCH_PSCI_USE_HVC != 0 {
+            Some(Self::Hvc)
+        } else {
+            Some(Self::Smc)
+        }
+    }
+
+    /// The conduit from the `method` property of the device tree node.
+    pub fn from_dt_method(method: &str) -> Option<Self> {
+        match method.trim_end_matches('\0') {
+            "smc" => Some(Self::Smc),
+            "hvc" => Some(Self::Hvc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    Unknown(i32),
+}
+
+impl Error {
+    pub const fn from_code(code: i32) -> Self {
+        match code {
+            -1 => Self::NotSupported,
+            -2 => Self::InvalidParameters,
+            -3 => Self::Denied,
+            -4 => Self::AlreadyOn,
+            -5 => Self::OnPending,
+            -6 => Self::InternalFailure,
+            -7 => Self::NotPresent,
+            -8 => Self::Disabled,
+            -9 => Self::InvalidAddress,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+/// The status the calls return, `0` on success, and a negative error code
+/// otherwise.
+pub fn check(status: u64) -> Result<(), Error> {
+    match status as i32 {
+        0 => Ok(()),
+        code => Err(Error::from_code(code)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Version {
+    pub const fn from_bits(bits: u32) -> Self {
+        Self {
+            major: (bits >> 16) as u16,
+            minor: bits as u16,
+        }
+    }
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Psci {
+    conduit: Conduit,
+}
+
+impl Psci {
+    pub const fn new(conduit: Conduit) -> Self {
+        Self { conduit }
+    }
+
+    pub const fn conduit(&self) -> Conduit {
+        self.conduit
+    }
+
+    pub fn version(&self) -> Result<Version, Error> {
+        let version = self.call(PSCI_VERSION, 0, 0, 0);
+        if (version as i32) < 0 {
+            return Err(Error::from_code(version as i32));
+        }
+        Ok(Version::from_bits(version as u32))
+    }
+
+    /// Starts the processor with the affinity `target` at `entry` with the
+    /// MMU off, `context_id` is passed in `x0`.
+    pub fn cpu_on(&self, target: u64, entry: u64, context_id: u64) -> Result<(), Error> {
+        check(self.call(CPU_ON, target, entry, context_id))
+    }
+
+    /// Powers the calling processor off, returns only on an error.
+    pub fn cpu_off(&self) -> Error {
+        Error::from_code(self.call(CPU_OFF, 0, 0, 0) as i32)
+    }
+
+    /// Returns only on an error.
+    pub fn system_off(&self) -> Error {
+        Error::from_code(self.call(SYSTEM_OFF, 0, 0, 0) as i32)
+    }
+
+    /// Returns only on an error.
+    pub fn system_reset(&self) -> Error {
+        Error::from_code(self.call(SYSTEM_RESET, 0, 0, 0) as i32)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn call(&self, function: u32, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+        let mut x0 = function as u64;
+        // SAFETY: the firmware implements the calls, and follows the
+        // calling convention.
+        unsafe {
+            match self.conduit {
+                Conduit::Smc => core::arch::asm!(
+                    "smc #0",
+                    inout("x0") x0,
+                    inout("x1") arg0 => _,
+                    inout("x2") arg1 => _,
+                    inout("x3") arg2 => _,
+                    clobber_abi("C"),
+                    options(nostack),
+                ),
+                Conduit::Hvc => core::arch::asm!(
+                    "hvc #0",
+                    inout("x0") x0,
+                    inout("x1") arg0 => _,
+                    inout("x2") arg1 => _,
+                    inout("x3") arg2 => _,
+                    clobber_abi("C"),
+                    options(nostack),
+                ),
+            }
+        }
+        x0
+    }
+
+    #[cfg(not(target_arch = "aarch64"))]
+    fn call(&self, _function: u32, _arg0: u64, _arg1: u64, _arg2: u64) -> u64 {
+        -1_i64 as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check;
+    use crate::Conduit;
+    use crate::Error;
+    use crate::Psci;
+    use crate::Version;
+
+    #[test]
+    fn conduit() {
+        assert_eq!(Conduit::from_arm_boot_flags(0), None);
+        assert_eq!(Conduit::from_arm_boot_flags(0b01), Some(Conduit::Smc));
+        assert_eq!(Conduit::from_arm_boot_flags(0b11), Some(Conduit::Hvc));
+        assert_eq!(Conduit::from_arm_boot_flags(0b10), None);
+
+        assert_eq!(Conduit::from_dt_method("hvc\0"), Some(Conduit::Hvc));
+        assert_eq!(Conduit::from_dt_method("smc"), Some(Conduit::Smc));
+        assert_eq!(Conduit::from_dt_method("spin-table"), None);
+    }
+
+    #[test]
+    fn status() {
+        assert_eq!(check(0), Ok(()));
+        assert_eq!(check(-4_i64 as u64), Err(Error::AlreadyOn));
+        assert_eq!(check(0xffff_fff7), Err(Error::InvalidAddress));
+        assert_eq!(check(-42_i64 as u64), Err(Error::Unknown(-42)));
+
+        let version = Version::from_bits(0x0001_0001);
+        assert_eq!(version, Version { major: 1, minor: 1 });
+        assert_eq!(format!("{version}"), "1.1");
+
+        #[cfg(not(target_arch = "aarch64"))]
+        assert_eq!(Psci::new(Conduit::Smc).version(), Err(Error::NotSupported));
+    }
+}
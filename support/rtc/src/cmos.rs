@@ -0,0 +1,192 @@
+//! The CMOS real-time clock of the PC chipsets, the MC146818 compatible
+//! one behind the index port `0x70` and the data port `0x71`.
+//!
+//! The clock updates the fields once a second, and they are read between
+//! the updates: after the update-in-progress flag clears, and until two
+//! reads in a row agree. The fields are in BCD or binary, and the hour in
+//! the 12- or 24-hour format, as the status register B says. The century
+//! is in the register the ACPI FADT names, if it names one, otherwise the
+//! years are taken to be in 2000-2099.
+
+//! CMOS registers used:
+//!
+//! Index  Name       Description
+//! ----------------------------------------------------------------------
+//! 0x00   Seconds    0-59
+//! 0x02   Minutes    0-59
+//! 0x04   Hours      0-23, or 1-12 with bit 7 set for PM
+//! 0x07   Day        1-31
+//! 0x08   Month      1-12
+//! 0x09   Year       0-99
+//! 0x0A   Status A   Bit 7 is set while the update is in progress
+//! 0x0B   Status B   Bit 1 is set for 24 hours, bit 2 for binary
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::asm;
+
+use crate::DateTime;
+
+const INDEX_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOURS: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const HOURS_PM: u8 = 1 << 7;
+
+/// The year the two-digit years are in without the century register.
+const DEFAULT_CENTURY: u16 = 2000;
+/// The update takes under 2 ms, each read of a register takes about 1 us.
+const MAX_READ_ATTEMPTS: usize = 10_000;
+
+/// The registers as read, not decoded yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct CmosRegisters {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+    /// `0` without the century register.
+    pub century: u8,
+    pub status_b: u8,
+}
+
+#[inline]
+fn outp8(port: u16, val: u8) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SAFETY: the kernel and the loader run at CPL 0, see `Cmos::new`.
+        unsafe {
+            asm!("outb %al, %dx", in("al") val, in("dx") port, options(att_syntax, nostack, nomem));
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        let _ = (port, val);
+    }
+}
+
+#[inline]
+fn inp8(port: u16) -> u8 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let mut val;
+        // SAFETY: the kernel and the loader run at CPL 0, see `Cmos::new`.
+        unsafe {
+            asm!("inb %dx, %al", out("al") val, in("dx") port, options(att_syntax, nostack, nomem));
+        }
+        val
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        let _ = port;
+        0
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xf)
+}
+
+impl CmosRegisters {
+    /// The time the registers hold, `None` if it is not valid.
+    pub fn decode(&self) -> Option<DateTime> {
+        let binary = self.status_b & STATUS_B_BINARY != 0;
+        let field = |value: u8| if binary { value } else { from_bcd(value) };
+
+        let pm = self.hour & HOURS_PM != 0;
+        let mut hour = field(self.hour & !HOURS_PM);
+        if self.status_b & STATUS_B_24_HOURS == 0 {
+            // 12 AM is the midnight, 12 PM is the noon.
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+
+        let century = if self.century != 0 {
+            field(self.century) as u16 * 100
+        } else {
+            DEFAULT_CENTURY
+        };
+
+        let time = DateTime {
+            year: century + field(self.year) as u16,
+            month: field(self.month),
+            day: field(self.day),
+            hour,
+            minute: field(self.minute),
+            second: field(self.second),
+        };
+        time.is_valid().then_some(time)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cmos {
+    /// The index of the century register, `0` if there is none.
+    century_register: u8,
+}
+
+impl Cmos {
+    /// # Safety
+    ///
+    /// The CMOS is at the standard ports, and nothing else uses them while
+    /// the clock is read. `century_register` is from the FADT, `0` if it
+    /// names none.
+    pub unsafe fn new(century_register: u8) -> Self {
+        Self { century_register }
+    }
+
+    fn read_register(&self, index: u8) -> u8 {
+        outp8(INDEX_PORT, index);
+        inp8(DATA_PORT)
+    }
+
+    fn update_in_progress(&self) -> bool {
+        self.read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+    }
+
+    fn read_registers(&self) -> CmosRegisters {
+        CmosRegisters {
+            second: self.read_register(REG_SECONDS),
+            minute: self.read_register(REG_MINUTES),
+            hour: self.read_register(REG_HOURS),
+            day: self.read_register(REG_DAY),
+            month: self.read_register(REG_MONTH),
+            year: self.read_register(REG_YEAR),
+            century: if self.century_register != 0 {
+                self.read_register(self.century_register)
+            } else {
+                0
+            },
+            status_b: self.read_register(REG_STATUS_B),
+        }
+    }
+
+    /// The time of day, `None` if the clock does not settle, or reads as
+    /// an invalid time.
+    pub fn read(&self) -> Option<DateTime> {
+        let mut last = None;
+        for _ in 0..MAX_READ_ATTEMPTS {
+            if self.update_in_progress() {
+                core::hint::spin_loop();
+                continue;
+            }
+            let registers = self.read_registers();
+            if last == Some(registers) {
+                return registers.decode();
+            }
+            last = Some(registers);
+        }
+        None
+    }
+}
@@ -0,0 +1,139 @@
+//! Reading the real-time clock, for the wall-clock time when the firmware
+//! cannot tell it: [`Pl031`] on aarch64, and [`Cmos`], the MC146818 in
+//! the PC chipsets, on x86_64. Only reading is supported, the clock is set
+//! by the firmware setup or the host.
+//!
+//! Both return a [`DateTime`]. The PL031 counts the seconds since the
+//! epoch, UTC by convention. The CMOS has the calendar fields in the
+//! format the firmware has chosen, in the local time on the machines
+//! that also boot Windows.
+//!
+//! Example:
+//! ```ignore
+//! // SAFETY: the registers are mapped with the device attributes.
+//! let rtc = unsafe { rtc::Pl031::new(virt_base) };
+//! log::info!("Time of day {}", rtc.read());
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod cmos;
+mod pl031;
+mod tests;
+
+pub use cmos::Cmos;
+pub use pl031::Pl031;
+
+use core::fmt;
+
+const SECONDS_PER_DAY: u64 = 86400;
+/// The days from 0000-03-01 to 1970-01-01 in the proleptic Gregorian
+/// calendar.
+const DAYS_TO_UNIX_EPOCH: u64 = 719468;
+const DAYS_PER_ERA: u64 = 146097;
+
+/// The calendar time the clocks read, no time zone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// `1970`-`9999`.
+    pub year: u16,
+    /// `1`-`12`.
+    pub month: u8,
+    /// `1`-`31`.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl DateTime {
+    /// The time the seconds since 1970-01-01 00:00:00 are.
+    pub fn from_unix_seconds(seconds: u64) -> Self {
+        let days = seconds / SECONDS_PER_DAY;
+        let seconds = seconds % SECONDS_PER_DAY;
+
+        // The years start in March here, so that the leap day is the last
+        // one of the year.
+        let days = days + DAYS_TO_UNIX_EPOCH;
+        let era = days / DAYS_PER_ERA;
+        let day_of_era = days % DAYS_PER_ERA;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    /// The seconds since 1970-01-01 00:00:00, `None` if the time is not
+    /// [valid](Self::is_valid).
+    pub fn to_unix_seconds(&self) -> Option<u64> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        let year = self.year as u64 - (self.month <= 2) as u64;
+        let month = self.month as u64;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_from_march + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_PER_ERA + day_of_era - DAYS_TO_UNIX_EPOCH;
+
+        Some(
+            days * SECONDS_PER_DAY
+                + self.hour as u64 * 3600
+                + self.minute as u64 * 60
+                + self.second as u64,
+        )
+    }
+
+    /// The fields are in range, and the time is not before the epoch.
+    pub fn is_valid(&self) -> bool {
+        (1970..=9999).contains(&self.year)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+/// `YYYY-MM-DD hh:mm:ss`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
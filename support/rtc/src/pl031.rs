@@ -0,0 +1,81 @@
+//! PL031 real-time clock. Follows
+//! [PrimeCell Real Time Clock (PL031) Technical Reference Manual](https://developer.arm.com/documentation/ddi0224/c/)
+
+//! PL031 Registers used:
+//!
+//! Offset  Name        Type  Reset       Bits  Description
+//! ----------------------------------------------------------------------
+//! 0x000   RTCDR       RO    0x00000000  32    Data Register
+//! 0x00C   RTCCR       RW    0x0         1     Control Register
+//! 0xFE0   RTCPeriphID RO    0x00041031  8x4   Peripheral ID Registers
+//! 0xFF0   RTCPCellID  RO    0xB105F00D  8x4   PrimeCell ID Registers
+
+use crate::DateTime;
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+
+/// Data Register
+const DR: Register<u32, ReadOnly> = Register::new(0x000);
+/// Control Register
+const CR: Register<u32, ReadWrite> = Register::new(0x00c);
+/// Peripheral ID Register 0, the 3 others follow.
+const PERIPH_ID0: Register<u32, ReadOnly> = Register::new(0xfe0);
+/// PrimeCell ID Register 0, the 3 others follow.
+const PCELL_ID0: Register<u32, ReadOnly> = Register::new(0xff0);
+
+/// The counter runs.
+const CR_START: u32 = 1;
+
+/// The part number and the designer, ARM, in the peripheral ID.
+const PERIPH_ID_PART: u32 = 0x0031;
+const PERIPH_ID_DESIGNER: u32 = 0x41;
+const PCELL_ID: u32 = 0xb105_f00d;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pl031 {
+    regs: RegisterBlock,
+}
+
+impl Pl031 {
+    /// # Safety
+    ///
+    /// The registers are mapped at `base_addr` with the device attributes.
+    pub unsafe fn new(base_addr: u64) -> Self {
+        Self {
+            // SAFETY: guaranteed by the caller.
+            regs: unsafe { RegisterBlock::new(base_addr) },
+        }
+    }
+
+    /// The 4 ID registers, each has a byte in the low bits.
+    fn read_id(&self, first: Register<u32, ReadOnly>) -> u32 {
+        (0..4).fold(0, |id, i| {
+            let byte = self.regs.read(first.at(i as u64));
+            id | (byte & 0xff) << (i * 8)
+        })
+    }
+
+    /// Whether the ID registers say this is a PL031.
+    pub fn is_present(&self) -> bool {
+        let periph_id = self.read_id(PERIPH_ID0);
+        self.read_id(PCELL_ID0) == PCELL_ID
+            && periph_id & 0xfff == PERIPH_ID_PART
+            && (periph_id >> 12) & 0xff == PERIPH_ID_DESIGNER
+    }
+
+    /// Whether the counter runs, it does not count otherwise.
+    pub fn is_started(&self) -> bool {
+        self.regs.read(CR) & CR_START != 0
+    }
+
+    /// The seconds since the epoch.
+    pub fn read_seconds(&self) -> u32 {
+        self.regs.read(DR)
+    }
+
+    pub fn read(&self) -> DateTime {
+        DateTime::from_unix_seconds(self.read_seconds().into())
+    }
+}
@@ -0,0 +1,155 @@
+#![cfg(test)]
+
+use super::*;
+use crate::cmos::CmosRegisters;
+
+const BCD_24_HOURS: u8 = 0x02;
+const BINARY_24_HOURS: u8 = 0x06;
+const BCD_12_HOURS: u8 = 0x00;
+
+fn time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    }
+}
+
+#[test]
+fn unix_seconds() {
+    for (seconds, expected) in [
+        (0, time(1970, 1, 1, 0, 0, 0)),
+        (951_782_400, time(2000, 2, 29, 0, 0, 0)),
+        (1_709_251_199, time(2024, 2, 29, 23, 59, 59)),
+        (2_147_483_647, time(2038, 1, 19, 3, 14, 7)),
+        (4_107_542_400, time(2100, 3, 1, 0, 0, 0)),
+        (u32::MAX as u64, time(2106, 2, 7, 6, 28, 15)),
+    ] {
+        assert_eq!(DateTime::from_unix_seconds(seconds), expected, "{seconds}");
+        assert_eq!(expected.to_unix_seconds(), Some(seconds), "{expected}");
+    }
+}
+
+#[test]
+fn unix_seconds_round_trip() {
+    // A bit over a day apart, so all the days and the times move.
+    for seconds in (0..253_402_300_800).step_by(90_061) {
+        let time = DateTime::from_unix_seconds(seconds);
+        assert!(time.is_valid(), "{time:?}");
+        assert_eq!(time.to_unix_seconds(), Some(seconds), "{time}");
+    }
+}
+
+#[test]
+fn validity() {
+    assert!(time(2024, 2, 29, 0, 0, 0).is_valid());
+    assert!(!time(2023, 2, 29, 0, 0, 0).is_valid());
+    assert!(!time(2100, 2, 29, 0, 0, 0).is_valid());
+    assert!(time(2000, 2, 29, 0, 0, 0).is_valid());
+    assert!(!time(2024, 4, 31, 0, 0, 0).is_valid());
+    assert!(!time(2024, 13, 1, 0, 0, 0).is_valid());
+    assert!(!time(2024, 1, 0, 0, 0, 0).is_valid());
+    assert!(!time(2024, 1, 1, 24, 0, 0).is_valid());
+    assert!(!time(1969, 12, 31, 23, 59, 59).is_valid());
+    assert_eq!(time(1969, 12, 31, 23, 59, 59).to_unix_seconds(), None);
+    assert_eq!(DateTime::default().to_unix_seconds(), None);
+}
+
+#[test]
+fn display() {
+    assert_eq!(
+        time(2026, 10, 6, 9, 5, 7).to_string(),
+        "2026-10-06 09:05:07"
+    );
+}
+
+#[test]
+fn cmos_bcd() {
+    let registers = CmosRegisters {
+        second: 0x59,
+        minute: 0x30,
+        hour: 0x23,
+        day: 0x31,
+        month: 0x12,
+        year: 0x25,
+        century: 0,
+        status_b: BCD_24_HOURS,
+    };
+    assert_eq!(registers.decode(), Some(time(2025, 12, 31, 23, 30, 59)));
+
+    let registers = CmosRegisters {
+        century: 0x19,
+        year: 0x99,
+        ..registers
+    };
+    assert_eq!(registers.decode(), Some(time(1999, 12, 31, 23, 30, 59)));
+}
+
+#[test]
+fn cmos_binary() {
+    let registers = CmosRegisters {
+        second: 59,
+        minute: 30,
+        hour: 23,
+        day: 31,
+        month: 12,
+        year: 25,
+        century: 20,
+        status_b: BINARY_24_HOURS,
+    };
+    assert_eq!(registers.decode(), Some(time(2025, 12, 31, 23, 30, 59)));
+}
+
+#[test]
+fn cmos_12_hours() {
+    let at = |hour| {
+        CmosRegisters {
+            second: 0,
+            minute: 0,
+            hour,
+            day: 0x01,
+            month: 0x01,
+            year: 0x26,
+            century: 0,
+            status_b: BCD_12_HOURS,
+        }
+        .decode()
+        .map(|time| time.hour)
+    };
+    assert_eq!(at(0x12), Some(0));
+    assert_eq!(at(0x01), Some(1));
+    assert_eq!(at(0x11), Some(11));
+    assert_eq!(at(0x80 | 0x12), Some(12));
+    assert_eq!(at(0x80 | 0x01), Some(13));
+    assert_eq!(at(0x80 | 0x11), Some(23));
+}
+
+#[test]
+fn cmos_invalid() {
+    let registers = CmosRegisters {
+        second: 0,
+        minute: 0,
+        hour: 0,
+        day: 0x30,
+        month: 0x02,
+        year: 0x26,
+        century: 0,
+        status_b: BCD_24_HOURS,
+    };
+    assert_eq!(registers.decode(), None);
+    // A clock that has lost the power reads as all ones.
+    let registers = CmosRegisters {
+        second: 0xff,
+        minute: 0xff,
+        hour: 0xff,
+        day: 0xff,
+        month: 0xff,
+        year: 0xff,
+        century: 0,
+        status_b: BCD_24_HOURS,
+    };
+    assert_eq!(registers.decode(), None);
+}
@@ -140,7 +140,7 @@ pub mod aarch64 {
 
         pub fn write_dbg_hex(&self, h: u64) {
             let mut hs = [0_u16; 11];
-            hs[0] = u16::from_le_bytes([b'0', b'x']);
+            hs[0] = u16::from_le_bytes(*b"0x");
 
             let hexn = |nibble| match nibble {
                 0..=9 => nibble + b'0',
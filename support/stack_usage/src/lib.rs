@@ -0,0 +1,167 @@
+//! Measuring how much of a stack is used, to size the stacks on the data.
+//!
+//! The unused part of the stack is painted with [`PAINT`] on entry, and
+//! the words that no longer hold it are the ones something has written.
+//! Scanning up from the bottom for the first such word gives the deepest
+//! the stack has ever been, its high-water mark. A frame that reserves the
+//! space and does not write all of it may go unnoticed, so the mark is
+//! the lower bound.
+//!
+//! The lowest word holds [`GUARD`] instead. The panic handlers check it:
+//! the stack that has reached its bottom has overflowed or is about to,
+//! and whatever went wrong may well be the result.
+//!
+//! ```text
+//! bottom                         painted end       stack pointer   top
+//! | GUARD | PAINT PAINT ... PAINT | ... margin ... | the frames    |
+//! ```
+//!
+//! The painting stops [`PAINT_MARGIN`] short of the stack pointer to keep
+//! off the frame of the painting code itself.
+//!
+//! Example:
+//! ```ignore
+//! // SAFETY: the stack is mapped, and nothing below the stack pointer is
+//! // in use.
+//! let stack = unsafe { stack_usage::PaintedStack::paint(bottom, top) };
+//! ...
+//! log::info!("Stack: {}", stack.usage());
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod tests;
+
+use core::fmt;
+
+/// `PAINTED!`
+pub const PAINT: u64 = 0x2144_4554_4e49_4150;
+/// `STKGUARD`
+pub const GUARD: u64 = 0x4452_4155_474b_5453;
+/// Left unpainted below the stack pointer.
+pub const PAINT_MARGIN: u64 = 0x1000;
+
+const WORD_SIZE: u64 = 8;
+
+/// The stack pointer of the function this is inlined into.
+#[inline(always)]
+pub fn stack_pointer() -> u64 {
+    let sp: u64;
+    #[cfg(target_arch = "aarch64")]
+    // SAFETY: reading a register has no side effects.
+    unsafe {
+        core::arch::asm!("mov {}, sp", out(reg) sp, options(nomem, nostack))
+    };
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: reading a register has no side effects.
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) sp, options(nomem, nostack))
+    };
+    sp
+}
+
+/// How deep the stack has been.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The bytes from the top of the stack to the bottom.
+    pub size: u64,
+    /// The bytes from the top of the stack to the high-water mark.
+    pub used: u64,
+}
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = (self.used * 100).checked_div(self.size).unwrap_or(0);
+        write!(
+            f,
+            "{:#x} of {:#x} bytes used, {percent}%",
+            self.used, self.size
+        )
+    }
+}
+
+/// The stack `[bottom, top)` painted up to `painted_end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaintedStack {
+    bottom: u64,
+    painted_end: u64,
+    top: u64,
+}
+
+impl PaintedStack {
+    /// Paints the stack from the bottom up to [`PAINT_MARGIN`] below the
+    /// stack pointer, which is to be in the stack.
+    ///
+    /// # Safety
+    ///
+    /// The stack is mapped, and nothing below the stack pointer is in use.
+    #[inline(never)]
+    pub unsafe fn paint(bottom: u64, top: u64) -> Self {
+        let sp = stack_pointer();
+        assert!((bottom..top).contains(&sp), "Not on the stack");
+        let painted_end = sp.saturating_sub(PAINT_MARGIN).max(bottom);
+        // SAFETY: guaranteed by the caller, and the painting stays off the
+        // frames.
+        unsafe { Self::paint_range(bottom, painted_end, top) }
+    }
+
+    /// Paints `[bottom, painted_end)`.
+    ///
+    /// # Safety
+    ///
+    /// The range is mapped, and not in use.
+    unsafe fn paint_range(bottom: u64, painted_end: u64, top: u64) -> Self {
+        let bottom = bottom.next_multiple_of(WORD_SIZE);
+        let painted_end = (painted_end & !(WORD_SIZE - 1)).max(bottom);
+        let stack = Self {
+            bottom,
+            painted_end,
+            top,
+        };
+        for (i, address) in (bottom..painted_end)
+            .step_by(WORD_SIZE as usize)
+            .enumerate()
+        {
+            let value = if i == 0 { GUARD } else { PAINT };
+            // SAFETY: guaranteed by the caller.
+            unsafe { (address as *mut u64).write_volatile(value) };
+        }
+        stack
+    }
+
+    fn word(&self, address: u64) -> u64 {
+        // SAFETY: the painted part of the stack is mapped.
+        unsafe { (address as *const u64).read_volatile() }
+    }
+
+    pub fn bottom(&self) -> u64 {
+        self.bottom
+    }
+
+    pub fn top(&self) -> u64 {
+        self.top
+    }
+
+    /// Whether the guard at the bottom is there, nothing was painted if
+    /// there was no room.
+    pub fn guard_intact(&self) -> bool {
+        self.painted_end == self.bottom || self.word(self.bottom) == GUARD
+    }
+
+    /// Scans the painted part for the high-water mark. The overwritten
+    /// guard counts as the whole stack used.
+    pub fn usage(&self) -> StackUsage {
+        let high_water_mark = if self.guard_intact() {
+            (self.bottom + WORD_SIZE..self.painted_end)
+                .step_by(WORD_SIZE as usize)
+                .find(|&address| self.word(address) != PAINT)
+                .unwrap_or(self.painted_end)
+        } else {
+            self.bottom
+        };
+        StackUsage {
+            size: self.top - self.bottom,
+            used: self.top - high_water_mark,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(test)]
+
+use super::*;
+
+const WORDS: usize = 64;
+
+struct Stack(Box<[u64; WORDS]>);
+
+impl Stack {
+    fn new() -> Self {
+        Self(Box::new([0; WORDS]))
+    }
+
+    fn bottom(&self) -> u64 {
+        self.0.as_ptr() as u64
+    }
+
+    fn top(&self) -> u64 {
+        self.bottom() + (WORDS as u64) * WORD_SIZE
+    }
+
+    /// Paints all but the top `unpainted` words.
+    fn paint(&mut self, unpainted: usize) -> PaintedStack {
+        let painted_end = self.top() - (unpainted as u64) * WORD_SIZE;
+        // SAFETY: the stack is a live allocation.
+        unsafe { PaintedStack::paint_range(self.bottom(), painted_end, self.top()) }
+    }
+}
+
+#[test]
+fn paint() {
+    let mut stack = Stack::new();
+    let painted = stack.paint(8);
+    assert_eq!(stack.0[0], GUARD);
+    assert!(stack.0[1..WORDS - 8].iter().all(|&word| word == PAINT));
+    assert!(stack.0[WORDS - 8..].iter().all(|&word| word == 0));
+
+    assert!(painted.guard_intact());
+    assert_eq!(
+        painted.usage(),
+        StackUsage {
+            size: 0x200,
+            used: 0x40
+        }
+    );
+}
+
+#[test]
+fn high_water_mark() {
+    let mut stack = Stack::new();
+    let painted = stack.paint(8);
+
+    // The deepest write counts, whatever is above it.
+    stack.0[40] = 0;
+    stack.0[20] = PAINT + 1;
+    assert_eq!(painted.usage().used, 0x200 - 20 * 8);
+    assert_eq!(
+        painted.usage().to_string(),
+        "0x160 of 0x200 bytes used, 68%"
+    );
+}
+
+#[test]
+fn guard() {
+    let mut stack = Stack::new();
+    let painted = stack.paint(8);
+    stack.0[0] = 0;
+    assert!(!painted.guard_intact());
+    assert_eq!(painted.usage().used, 0x200);
+
+    // No room to paint, nothing to check.
+    let mut stack = Stack::new();
+    let painted = stack.paint(WORDS);
+    assert!(painted.guard_intact());
+    assert_eq!(painted.usage().used, 0x200);
+}
+
+#[inline(never)]
+fn use_stack(depth: usize) -> u64 {
+    let buf = [depth as u64; 64];
+    let buf = core::hint::black_box(buf);
+    if depth == 0 {
+        buf[0]
+    } else {
+        use_stack(depth - 1) + buf[depth % 64]
+    }
+}
+
+#[test]
+fn paint_below_stack_pointer() {
+    let sp = stack_pointer();
+    let (bottom, top) = (sp - 0x10000, sp + 0x100);
+    // SAFETY: the test thread stack is much larger than that, and nothing
+    // is below the stack pointer.
+    let painted = unsafe { PaintedStack::paint(bottom, top) };
+    let before = painted.usage();
+    assert!(before.used >= 0x100 + PAINT_MARGIN);
+    assert!(painted.guard_intact());
+
+    core::hint::black_box(use_stack(16));
+    let after = painted.usage();
+    assert!(after.used > before.used, "{before} {after}");
+    assert!(after.used >= 16 * 64 * 8, "{after}");
+    assert!(painted.guard_intact());
+}
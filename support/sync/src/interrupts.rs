@@ -0,0 +1,72 @@
+//! Masking the interrupts on the current processor.
+//!
+//! The host tests run in user mode, where the interrupt flag cannot be
+//! changed, so there the state is not touched.
+
+/// Whether the interrupts were enabled before [`save_and_disable`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InterruptState {
+    #[cfg_attr(
+        any(test, not(any(target_arch = "x86_64", target_arch = "aarch64"))),
+        allow(dead_code)
+    )]
+    enabled: bool,
+}
+
+/// RFLAGS.IF.
+#[cfg(all(target_arch = "x86_64", not(test)))]
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// The DAIF.I bit.
+#[cfg(all(target_arch = "aarch64", not(test)))]
+const DAIF_IRQ: u64 = 1 << 7;
+
+// The asm blocks are not `nomem`: the compiler must not move the accesses
+// under the lock past them.
+
+#[cfg(all(target_arch = "x86_64", not(test)))]
+pub(crate) fn save_and_disable() -> InterruptState {
+    let rflags: u64;
+    // SAFETY: the push is popped right back, and masking the interrupts
+    // is undone by `restore`.
+    unsafe { core::arch::asm!("pushfq", "pop {}", "cli", out(reg) rflags) };
+    InterruptState {
+        enabled: rflags & RFLAGS_IF != 0,
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(test)))]
+pub(crate) fn restore(state: InterruptState) {
+    if state.enabled {
+        // SAFETY: the interrupts were enabled before, so the handlers are
+        // installed.
+        unsafe { core::arch::asm!("sti", options(nostack)) };
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(test)))]
+pub(crate) fn save_and_disable() -> InterruptState {
+    let daif: u64;
+    // SAFETY: masking the IRQs is undone by `restore`.
+    unsafe { core::arch::asm!("mrs {}, daif", "msr daifset, #2", out(reg) daif, options(nostack)) };
+    InterruptState {
+        enabled: daif & DAIF_IRQ == 0,
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(test)))]
+pub(crate) fn restore(state: InterruptState) {
+    if state.enabled {
+        // SAFETY: the IRQs were unmasked before, so the handlers are
+        // installed.
+        unsafe { core::arch::asm!("msr daifclr, #2", options(nostack)) };
+    }
+}
+
+#[cfg(any(test, not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub(crate) fn save_and_disable() -> InterruptState {
+    InterruptState { enabled: false }
+}
+
+#[cfg(any(test, not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub(crate) fn restore(_state: InterruptState) {}
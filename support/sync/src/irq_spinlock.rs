@@ -0,0 +1,106 @@
+//! The spinlock that keeps the interrupts masked while it is held.
+//!
+//! An interrupt handler taking the lock the interrupted code holds on the
+//! same processor would spin forever. The interrupts are masked before the
+//! lock is taken, and the state is restored after it is released, so the
+//! guards nest: only dropping the outermost one unmasks the interrupts.
+
+use crate::interrupts;
+use crate::interrupts::InterruptState;
+use crate::spinlock::RawSpinlock;
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+
+pub struct IrqSpinlock<T: ?Sized> {
+    lock: RawSpinlock,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: the lock hands out one reference to the data at a time.
+unsafe impl<T: ?Sized + Send> Sync for IrqSpinlock<T> {}
+// SAFETY: the lock owns the data.
+unsafe impl<T: ?Sized + Send> Send for IrqSpinlock<T> {}
+
+impl<T> IrqSpinlock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: RawSpinlock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> IrqSpinlock<T> {
+    pub fn lock(&self) -> IrqSpinlockGuard<'_, T> {
+        let state = interrupts::save_and_disable();
+        self.lock.acquire();
+        IrqSpinlockGuard { lock: self, state }
+    }
+
+    /// None if the lock is held, the interrupts are left as they were.
+    pub fn try_lock(&self) -> Option<IrqSpinlockGuard<'_, T>> {
+        let state = interrupts::save_and_disable();
+        if self.lock.try_acquire() {
+            Some(IrqSpinlockGuard { lock: self, state })
+        } else {
+            interrupts::restore(state);
+            None
+        }
+    }
+
+    /// Only a hint, the lock may change right after.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+
+    /// No locking is needed with the exclusive reference.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for IrqSpinlock<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f
+                .debug_struct("IrqSpinlock")
+                .field("data", &&*guard)
+                .finish(),
+            None => f.write_str("IrqSpinlock { <locked> }"),
+        }
+    }
+}
+
+/// Releases the lock, and restores the interrupts when dropped.
+pub struct IrqSpinlockGuard<'a, T: ?Sized> {
+    lock: &'a IrqSpinlock<T>,
+    state: InterruptState,
+}
+
+impl<T: ?Sized> Deref for IrqSpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.lock.release();
+        interrupts::restore(self.state);
+    }
+}
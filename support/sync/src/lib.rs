@@ -0,0 +1,32 @@
+//! The locks and the lazily initialized cells for the loader and the
+//! kernel, which have neither the standard library nor the threads to
+//! park.
+//!
+//! * [`Spinlock`] is the plain test-and-set lock.
+//! * [`IrqSpinlock`] also masks the interrupts on the processor while it is
+//!   held, and restores what was there on unlock, for the data that the
+//!   interrupt handlers touch, too.
+//! * [`TicketLock`] hands the lock out in the order the processors have
+//!   asked for it, so none of them starves under contention.
+//! * [`OnceCell`] is initialized on the first use, and read-only after.
+//!
+//! The locks are not re-entrant, taking a lock twice on the same processor
+//! spins forever. Use `try_lock` where the code may run from an exception
+//! taken under the lock.
+
+#![cfg_attr(not(test), no_std)]
+
+mod interrupts;
+mod irq_spinlock;
+mod once_cell;
+mod spinlock;
+mod tests;
+mod ticket_lock;
+
+pub use irq_spinlock::IrqSpinlock;
+pub use irq_spinlock::IrqSpinlockGuard;
+pub use once_cell::OnceCell;
+pub use spinlock::Spinlock;
+pub use spinlock::SpinlockGuard;
+pub use ticket_lock::TicketLock;
+pub use ticket_lock::TicketLockGuard;
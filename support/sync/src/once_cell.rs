@@ -0,0 +1,114 @@
+//! The cell written once, e.g. the statics set up on the first use.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+const UNINIT: u8 = 0;
+const INITIALIZING: u8 = 1;
+const READY: u8 = 2;
+
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: the value is written once before it is shared, and is only
+// read after.
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+// SAFETY: the cell owns the value.
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn uninit() -> Self {
+        Self {
+            state: AtomicU8::new(UNINIT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// None until the value is there.
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            // SAFETY: the value is written and never changes.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
+    }
+
+    /// Calls `init` if the cell is empty, and waits for the value if
+    /// another processor is in `init`. Calling this from `init` on the same
+    /// cell spins forever.
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        match self.state.compare_exchange(
+            UNINIT,
+            INITIALIZING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                // SAFETY: only the winner of the exchange writes the value,
+                // and nobody reads it before the state is ready.
+                unsafe { (*self.value.get()).write(init()) };
+                self.state.store(READY, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != READY {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+        // SAFETY: the state is ready.
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Gives the value back if the cell has one, or another processor is
+    /// writing it.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(UNINIT, INITIALIZING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        // SAFETY: only the winner of the exchange writes the value.
+        unsafe { (*self.value.get()).write(value) };
+        self.state.store(READY, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::uninit()
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            // SAFETY: the value is written, and dropped only here.
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! The test-and-set spinlock.
+
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+
+/// The lock without the data, shared with [`crate::IrqSpinlock`].
+pub(crate) struct RawSpinlock {
+    locked: AtomicBool,
+}
+
+impl RawSpinlock {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub(crate) fn acquire(&self) {
+        while !self.try_acquire() {
+            // Wait on the plain load not to bounce the cache line.
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    pub(crate) fn release(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Spinlock<T: ?Sized> {
+    lock: RawSpinlock,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: the lock hands out one reference to the data at a time.
+unsafe impl<T: ?Sized + Send> Sync for Spinlock<T> {}
+// SAFETY: the lock owns the data.
+unsafe impl<T: ?Sized + Send> Send for Spinlock<T> {}
+
+impl<T> Spinlock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: RawSpinlock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Spinlock<T> {
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        self.lock.acquire();
+        SpinlockGuard { lock: self }
+    }
+
+    /// None if the lock is held.
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        self.lock
+            .try_acquire()
+            .then_some(SpinlockGuard { lock: self })
+    }
+
+    /// Only a hint, the lock may change right after.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+
+    /// No locking is needed with the exclusive reference.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for Spinlock<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Spinlock").field("data", &&*guard).finish(),
+            None => f.write_str("Spinlock { <locked> }"),
+        }
+    }
+}
+
+impl<T: Default> Default for Spinlock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Releases the lock when dropped.
+pub struct SpinlockGuard<'a, T: ?Sized> {
+    lock: &'a Spinlock<T>,
+}
+
+impl<T: ?Sized> Deref for SpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for SpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.lock.release();
+    }
+}
@@ -0,0 +1,125 @@
+#![cfg(test)]
+
+use crate::IrqSpinlock;
+use crate::OnceCell;
+use crate::Spinlock;
+use crate::TicketLock;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 10_000;
+
+/// Runs `f` on the threads at once.
+fn contend(f: impl Fn() + Sync) {
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(&f);
+        }
+    });
+}
+
+#[test]
+fn test_spinlock() {
+    let lock = Spinlock::new(0);
+    contend(|| {
+        for _ in 0..ROUNDS {
+            *lock.lock() += 1;
+        }
+    });
+    assert_eq!(*lock.lock(), THREADS * ROUNDS);
+
+    let guard = lock.lock();
+    assert!(lock.is_locked());
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+    assert!(!lock.is_locked());
+    *lock.try_lock().unwrap() = 42;
+    assert_eq!(lock.into_inner(), 42);
+}
+
+#[test]
+fn test_irq_spinlock() {
+    let lock = IrqSpinlock::new(Vec::new());
+    contend(|| {
+        for _ in 0..ROUNDS {
+            lock.lock().push(1);
+        }
+    });
+    assert_eq!(lock.lock().len(), THREADS * ROUNDS);
+
+    // The guards of the different locks nest.
+    let other = IrqSpinlock::new(0);
+    let outer = lock.lock();
+    *other.lock() += 1;
+    assert!(lock.try_lock().is_none());
+    drop(outer);
+    assert!(lock.try_lock().is_some());
+    assert_eq!(format!("{other:?}"), "IrqSpinlock { data: 1 }");
+}
+
+#[test]
+fn test_ticket_lock() {
+    let lock = TicketLock::new(0);
+    contend(|| {
+        for _ in 0..ROUNDS {
+            *lock.lock() += 1;
+        }
+    });
+    assert_eq!(*lock.lock(), THREADS * ROUNDS);
+
+    let guard = lock.lock();
+    assert!(lock.is_locked());
+    assert!(lock.try_lock().is_none());
+    assert_eq!(format!("{lock:?}"), "TicketLock { <locked> }");
+    drop(guard);
+    assert!(!lock.is_locked());
+    assert!(lock.try_lock().is_some());
+}
+
+#[test]
+fn test_ticket_lock_wraps() {
+    let lock = TicketLock::new(());
+    lock.next_ticket.store(u32::MAX, Ordering::Relaxed);
+    lock.now_serving.store(u32::MAX, Ordering::Relaxed);
+    drop(lock.lock());
+    drop(lock.lock());
+    assert_eq!(lock.now_serving.load(Ordering::Relaxed), 1);
+    assert!(lock.try_lock().is_some());
+}
+
+#[test]
+fn test_once_cell() {
+    let cell = OnceCell::uninit();
+    let calls = AtomicUsize::new(0);
+    assert!(cell.get().is_none());
+    contend(|| {
+        let value = cell.get_or_init(|| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            String::from("corgi")
+        });
+        assert_eq!(value, "corgi");
+    });
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    assert!(cell.is_initialized());
+    assert_eq!(
+        cell.set(String::from("pembroke")),
+        Err(String::from("pembroke"))
+    );
+    assert_eq!(cell.get().map(String::as_str), Some("corgi"));
+
+    let cell = OnceCell::uninit();
+    assert_eq!(cell.set(1), Ok(()));
+    assert_eq!(*cell.get_or_init(|| 2), 1);
+}
+
+#[test]
+fn test_once_cell_drops() {
+    let value = std::rc::Rc::new(());
+    let cell = OnceCell::uninit();
+    cell.get_or_init(|| value.clone());
+    assert_eq!(std::rc::Rc::strong_count(&value), 2);
+    drop(cell);
+    assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    drop(OnceCell::<std::rc::Rc<()>>::uninit());
+}
@@ -0,0 +1,110 @@
+//! The ticket lock: every waiter takes the next number, and the lock
+//! serves the numbers in order.
+
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+pub struct TicketLock<T: ?Sized> {
+    pub(crate) next_ticket: AtomicU32,
+    pub(crate) now_serving: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: the lock hands out one reference to the data at a time.
+unsafe impl<T: ?Sized + Send> Sync for TicketLock<T> {}
+// SAFETY: the lock owns the data.
+unsafe impl<T: ?Sized + Send> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> TicketLock<T> {
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop();
+        }
+        TicketLockGuard { lock: self }
+    }
+
+    /// None if the lock is held or has waiters.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketLockGuard { lock: self })
+    }
+
+    /// Only a hint, the lock may change right after.
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+
+    /// No locking is needed with the exclusive reference.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for TicketLock<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f
+                .debug_struct("TicketLock")
+                .field("data", &&*guard)
+                .finish(),
+            None => f.write_str("TicketLock { <locked> }"),
+        }
+    }
+}
+
+/// Serves the next ticket when dropped.
+pub struct TicketLockGuard<'a, T: ?Sized> {
+    lock: &'a TicketLock<T>,
+}
+
+impl<T: ?Sized> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only the holder writes the counter.
+        let serving = self.lock.now_serving.load(Ordering::Relaxed);
+        self.lock
+            .now_serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+    }
+}
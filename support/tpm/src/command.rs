@@ -0,0 +1,133 @@
+//! Marshalling the commands and unmarshalling the responses, all the
+//! fields are big-endian.
+//!
+//! Offset Name
+//! ----------------------------------------------------------------------
+//! 0      tag, whether there are sessions
+//! 2      size, of the whole command or response
+//! 6      command code, or response code
+//! 10     handles, sessions, and parameters
+
+use crate::TpmError;
+
+pub const HEADER_SIZE: usize = 10;
+
+pub const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+pub const TPM_ST_SESSIONS: u16 = 0x8002;
+
+pub const TPM_CC_PCR_EXTEND: u32 = 0x0000_0182;
+pub const TPM_CC_GET_RANDOM: u32 = 0x0000_017b;
+
+pub const TPM_RC_SUCCESS: u32 = 0;
+
+/// The password session.
+pub const TPM_RS_PW: u32 = 0x4000_0009;
+pub const TPM_ALG_SHA256: u16 = 0x000b;
+
+/// The largest digest of SHA-512, `TPM2_GetRandom` returns at most that.
+pub const MAX_RANDOM_BYTES: usize = 64;
+
+/// Writes the fields one after another.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+        self
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Sets the size in the header, and returns it.
+    fn finish(&mut self) -> usize {
+        self.buf[2..6].copy_from_slice(&(self.pos as u32).to_be_bytes());
+        self.pos
+    }
+}
+
+fn command(buf: &mut [u8], tag: u16, code: u32) -> Writer<'_> {
+    let mut writer = Writer { buf, pos: 0 };
+    writer.u16(tag).u32(0).u32(code);
+    writer
+}
+
+/// `TPM2_PCR_Extend` of the SHA-256 bank, returns the size.
+pub fn pcr_extend(buf: &mut [u8], pcr: u32, sha256: &[u8; 32]) -> usize {
+    command(buf, TPM_ST_SESSIONS, TPM_CC_PCR_EXTEND)
+        .u32(pcr)
+        // The authorization area: the password session with no nonce, no
+        // attributes, and the empty password.
+        .u32(9)
+        .u32(TPM_RS_PW)
+        .u16(0)
+        .u8(0)
+        .u16(0)
+        // One digest in the list.
+        .u32(1)
+        .u16(TPM_ALG_SHA256)
+        .bytes(sha256)
+        .finish()
+}
+
+/// `TPM2_GetRandom`, returns the size.
+pub fn get_random(buf: &mut [u8], bytes: u16) -> usize {
+    command(buf, TPM_ST_NO_SESSIONS, TPM_CC_GET_RANDOM)
+        .u16(bytes)
+        .finish()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, TpmError> {
+    match bytes.get(offset..offset + 2) {
+        Some(field) => Ok(u16::from_be_bytes([field[0], field[1]])),
+        None => Err(TpmError::BadResponse),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TpmError> {
+    match bytes.get(offset..offset + 4) {
+        Some(field) => Ok(u32::from_be_bytes([field[0], field[1], field[2], field[3]])),
+        None => Err(TpmError::BadResponse),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub tag: u16,
+    pub size: u32,
+    pub code: u32,
+}
+
+impl ResponseHeader {
+    /// Checks the size matches the response.
+    pub fn parse(response: &[u8]) -> Result<Self, TpmError> {
+        let header = Self {
+            tag: read_u16(response, 0)?,
+            size: read_u32(response, 2)?,
+            code: read_u32(response, 6)?,
+        };
+        if header.size as usize != response.len() {
+            return Err(TpmError::BadResponse);
+        }
+        Ok(header)
+    }
+}
+
+/// The random bytes in the parameters of the `TPM2_GetRandom` response.
+pub fn parse_get_random(parameters: &[u8]) -> Result<&[u8], TpmError> {
+    let size = read_u16(parameters, 0)? as usize;
+    parameters.get(2..2 + size).ok_or(TpmError::BadResponse)
+}
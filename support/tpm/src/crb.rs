@@ -0,0 +1,158 @@
+//! The Command Response Buffer interface.
+//!
+//! CRB Registers used, of locality 0:
+//!
+//! Offset  Name              Type  Bits  Description
+//! ----------------------------------------------------------------------
+//! 0x000   LOC_STATE         RO    32    Locality State
+//! 0x008   LOC_CTRL          WO    32    Locality Control
+//! 0x00C   LOC_STS           RO    32    Locality Status
+//! 0x040   CTRL_REQ          RW    32    Control Area Request
+//! 0x044   CTRL_STS          RO    32    Control Area Status
+//! 0x04C   CTRL_START        RW    32    Start
+//! 0x058   CTRL_CMD_SIZE     RW    32    Command Buffer Size
+//! 0x05C   CTRL_CMD_LADDR    RW    32    Command Buffer Address, the low half
+//! 0x060   CTRL_CMD_HADDR    RW    32    Command Buffer Address, the high half
+//! 0x064   CTRL_RSP_SIZE     RW    32    Response Buffer Size
+//! 0x068   CTRL_RSP_ADDR     RW    64    Response Buffer Address
+//!
+//! The command and the response buffers are where the firmware has set
+//! them up, the data buffer at 0x080 usually. Their physical addresses
+//! are translated into the mapping of the registers, and the buffers
+//! outside of it are refused.
+
+use crate::command;
+use crate::poll;
+use crate::Interface;
+use crate::TpmError;
+use mmio::ReadOnly;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+use mmio::WriteOnly;
+
+const LOC_STATE: Register<u32, ReadOnly> = Register::new(0x000);
+const LOC_CTRL: Register<u32, WriteOnly> = Register::new(0x008);
+const LOC_STS: Register<u32, ReadOnly> = Register::new(0x00c);
+const CTRL_REQ: Register<u32, ReadWrite> = Register::new(0x040);
+const CTRL_STS: Register<u32, ReadOnly> = Register::new(0x044);
+const CTRL_START: Register<u32, ReadWrite> = Register::new(0x04c);
+const CTRL_CMD_SIZE: Register<u32, ReadWrite> = Register::new(0x058);
+const CTRL_CMD_LADDR: Register<u32, ReadWrite> = Register::new(0x05c);
+const CTRL_CMD_HADDR: Register<u32, ReadWrite> = Register::new(0x060);
+const CTRL_RSP_SIZE: Register<u32, ReadWrite> = Register::new(0x064);
+const CTRL_RSP_ADDR: Register<u64, ReadWrite> = Register::new(0x068);
+
+const LOC_STATE_REG_VALID: u32 = 1 << 7;
+const LOC_CTRL_REQUEST_ACCESS: u32 = 1 << 0;
+const LOC_CTRL_RELINQUISH: u32 = 1 << 1;
+const LOC_STS_GRANTED: u32 = 1 << 0;
+const CTRL_REQ_CMD_READY: u32 = 1 << 0;
+const CTRL_REQ_GO_IDLE: u32 = 1 << 1;
+const CTRL_STS_FATAL: u32 = 1 << 0;
+const CTRL_START_START: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Crb {
+    regs: RegisterBlock,
+    phys_base: u64,
+    size: u64,
+}
+
+impl Crb {
+    /// # Safety
+    ///
+    /// The `size` bytes of the registers at `phys_base` are mapped at
+    /// `virt_base` with the device attributes.
+    pub unsafe fn new(virt_base: u64, phys_base: u64, size: u64) -> Self {
+        Self {
+            // SAFETY: guaranteed by the caller.
+            regs: unsafe { RegisterBlock::new(virt_base) },
+            phys_base,
+            size,
+        }
+    }
+
+    /// The offset of the buffer in the mapping.
+    fn buffer(&self, phys: u64, size: u32, needed: usize) -> Result<u64, TpmError> {
+        if (size as usize) < needed {
+            return Err(TpmError::CommandTooLarge);
+        }
+        let offset = phys.wrapping_sub(self.phys_base);
+        if phys < self.phys_base || offset + needed as u64 > self.size {
+            return Err(TpmError::CommandTooLarge);
+        }
+        Ok(offset)
+    }
+
+    fn request_locality(&self) -> Result<(), TpmError> {
+        self.regs.write(LOC_CTRL, LOC_CTRL_REQUEST_ACCESS);
+        poll(|| {
+            self.regs.read(LOC_STS) & LOC_STS_GRANTED != 0
+                && self.regs.read(LOC_STATE) & LOC_STATE_REG_VALID != 0
+        })
+        .map_err(|_| TpmError::Locality)
+    }
+
+    fn run(&self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        if self.regs.read(CTRL_STS) & CTRL_STS_FATAL != 0 {
+            return Err(TpmError::Fatal);
+        }
+        self.regs.write(CTRL_REQ, CTRL_REQ_CMD_READY);
+        poll(|| self.regs.read(CTRL_REQ) & CTRL_REQ_CMD_READY == 0)?;
+
+        let command_phys =
+            (self.regs.read(CTRL_CMD_HADDR) as u64) << 32 | self.regs.read(CTRL_CMD_LADDR) as u64;
+        let command_offset =
+            self.buffer(command_phys, self.regs.read(CTRL_CMD_SIZE), command.len())?;
+        for (i, &byte) in command.iter().enumerate() {
+            self.regs.write(
+                Register::<u8, WriteOnly>::new(command_offset + i as u64),
+                byte,
+            );
+        }
+
+        self.regs.write(CTRL_START, CTRL_START_START);
+        poll(|| self.regs.read(CTRL_START) & CTRL_START_START == 0)?;
+        if self.regs.read(CTRL_STS) & CTRL_STS_FATAL != 0 {
+            return Err(TpmError::Fatal);
+        }
+
+        let response_offset = self.buffer(
+            self.regs.read(CTRL_RSP_ADDR),
+            self.regs.read(CTRL_RSP_SIZE),
+            command::HEADER_SIZE,
+        )?;
+        let read = |i: usize| {
+            self.regs
+                .read(Register::<u8, ReadOnly>::new(response_offset + i as u64))
+        };
+        let mut header = [0; command::HEADER_SIZE];
+        for (i, byte) in header.iter_mut().enumerate() {
+            *byte = read(i);
+        }
+        let size = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+        if size < command::HEADER_SIZE || size > response.len() {
+            return Err(TpmError::BadResponse);
+        }
+        self.buffer(
+            self.regs.read(CTRL_RSP_ADDR),
+            self.regs.read(CTRL_RSP_SIZE),
+            size,
+        )?;
+        for (i, byte) in response[..size].iter_mut().enumerate() {
+            *byte = read(i);
+        }
+        Ok(size)
+    }
+}
+
+impl Interface for Crb {
+    fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        self.request_locality()?;
+        let result = self.run(command, response);
+        self.regs.write(CTRL_REQ, CTRL_REQ_GO_IDLE);
+        self.regs.write(LOC_CTRL, LOC_CTRL_RELINQUISH);
+        result
+    }
+}
@@ -0,0 +1,138 @@
+//! TPM 2.0 over the memory-mapped interfaces, for measuring after the
+//! boot services have exited and `EFI_TCG2_PROTOCOL` is gone. Follows
+//! [TCG PC Client Platform TPM Profile Specification for TPM 2.0](https://trustedcomputinggroup.org/resource/pc-client-platform-tpm-profile-ptp-specification/)
+//! for the interfaces, and
+//! [TPM 2.0 Library Part 3: Commands](https://trustedcomputinggroup.org/resource/tpm-library-specification/)
+//! for the commands.
+//!
+//! Two interfaces are supported, both at locality 0:
+//!
+//! - [`Crb`]: the Command Response Buffer, the firmware TPMs of the recent
+//!   processors and QEMU `tpm-crb`,
+//! - [`Tis`]: the FIFO interface, the discrete TPMs and QEMU `tpm-tis`.
+//!
+//! And two commands, `TPM2_PCR_Extend` with a SHA-256 digest, and
+//! `TPM2_GetRandom`, see [`Tpm`]. The firmware has run `TPM2_Startup`, so
+//! the TPM is ready for them. The commands are sent with the password
+//! session and the empty password, as the firmware leaves the PCRs.
+//!
+//! The interfaces poll the status registers a bounded number of times
+//! rather than for the timeouts the specification gives, there is no
+//! clock to tell the time with here.
+//!
+//! Example:
+//! ```ignore
+//! // SAFETY: the registers are mapped with the device attributes.
+//! let mut tpm = tpm::Tpm::new(unsafe { tpm::Crb::new(virt_base, phys_base, size) });
+//! tpm.pcr_extend(12, &digest::sha256(cmdline))?;
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod command;
+mod crb;
+mod tests;
+mod tis;
+
+pub use crb::Crb;
+pub use tis::Tis;
+
+use command::ResponseHeader;
+
+/// The size of the registers of one locality, only the locality 0 is
+/// used.
+pub const LOCALITY_SIZE: u64 = 0x1000;
+/// The TIS base on the PC platforms.
+pub const TIS_PC_BASE: u64 = 0xfed4_0000;
+
+/// The commands and the responses here fit.
+pub const MAX_COMMAND_SIZE: usize = 128;
+
+/// How many times a status register is polled before giving up.
+const MAX_POLLS: usize = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpmError {
+    /// The TPM has not set the expected status bits in time.
+    Timeout,
+    /// The interface does not have the locality 0.
+    Locality,
+    /// The interface reports a fatal error.
+    Fatal,
+    /// The command buffer of the interface is smaller than the command.
+    CommandTooLarge,
+    /// The response does not fit the buffer, or is shorter than it says.
+    BadResponse,
+    /// The response code of the TPM.
+    ResponseCode(u32),
+    /// There is no TPM to send the command to.
+    NotPresent,
+}
+
+/// Polls until `done` returns `true`.
+fn poll(mut done: impl FnMut() -> bool) -> Result<(), TpmError> {
+    for _ in 0..MAX_POLLS {
+        if done() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(TpmError::Timeout)
+}
+
+/// Sends the commands and receives the responses.
+pub trait Interface {
+    /// Runs the command, and returns the size of the response.
+    fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError>;
+}
+
+pub struct Tpm<I: Interface> {
+    interface: I,
+}
+
+impl<I: Interface> Tpm<I> {
+    pub fn new(interface: I) -> Self {
+        Self { interface }
+    }
+
+    fn execute<'a>(
+        &mut self,
+        command: &[u8],
+        response: &'a mut [u8; MAX_COMMAND_SIZE],
+    ) -> Result<&'a [u8], TpmError> {
+        let size = self.interface.execute(command, response)?;
+        let response = &response[..size];
+        let header = ResponseHeader::parse(response)?;
+        if header.code != command::TPM_RC_SUCCESS {
+            return Err(TpmError::ResponseCode(header.code));
+        }
+        Ok(&response[command::HEADER_SIZE..])
+    }
+
+    /// Extends the SHA-256 bank of the PCR with the digest.
+    pub fn pcr_extend(&mut self, pcr: u32, sha256: &[u8; 32]) -> Result<(), TpmError> {
+        let mut command = [0; MAX_COMMAND_SIZE];
+        let size = command::pcr_extend(&mut command, pcr, sha256);
+        self.execute(&command[..size], &mut [0; MAX_COMMAND_SIZE])?;
+        Ok(())
+    }
+
+    /// Fills the buffer with the random bytes from the TPM.
+    pub fn get_random(&mut self, mut bytes: &mut [u8]) -> Result<(), TpmError> {
+        while !bytes.is_empty() {
+            let mut command = [0; MAX_COMMAND_SIZE];
+            let requested = bytes.len().min(command::MAX_RANDOM_BYTES);
+            let size = command::get_random(&mut command, requested as u16);
+            let mut response = [0; MAX_COMMAND_SIZE];
+            let random = command::parse_get_random(self.execute(&command[..size], &mut response)?)?;
+            // The TPM may return fewer bytes than asked.
+            if random.is_empty() || random.len() > requested {
+                return Err(TpmError::BadResponse);
+            }
+            let (head, tail) = bytes.split_at_mut(random.len());
+            head.copy_from_slice(random);
+            bytes = tail;
+        }
+        Ok(())
+    }
+}
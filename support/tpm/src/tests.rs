@@ -0,0 +1,138 @@
+#![cfg(test)]
+
+use super::*;
+
+/// Records the commands, and answers with the canned responses.
+struct FakeTpm {
+    commands: Vec<Vec<u8>>,
+    responses: Vec<Vec<u8>>,
+}
+
+impl FakeTpm {
+    fn new(responses: Vec<Vec<u8>>) -> Self {
+        Self {
+            commands: Vec::new(),
+            responses,
+        }
+    }
+}
+
+impl Interface for FakeTpm {
+    fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        self.commands.push(command.to_vec());
+        let canned = self.responses.remove(0);
+        response[..canned.len()].copy_from_slice(&canned);
+        Ok(canned.len())
+    }
+}
+
+fn response(code: u32, parameters: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&command::TPM_ST_NO_SESSIONS.to_be_bytes());
+    bytes.extend_from_slice(&((command::HEADER_SIZE + parameters.len()) as u32).to_be_bytes());
+    bytes.extend_from_slice(&code.to_be_bytes());
+    bytes.extend_from_slice(parameters);
+    bytes
+}
+
+fn random_response(random: &[u8]) -> Vec<u8> {
+    let mut parameters = (random.len() as u16).to_be_bytes().to_vec();
+    parameters.extend_from_slice(random);
+    response(command::TPM_RC_SUCCESS, &parameters)
+}
+
+#[test]
+fn pcr_extend_command() {
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let size = command::pcr_extend(&mut buf, 12, &[0xab; 32]);
+    let mut expected = vec![
+        0x80, 0x02, // TPM_ST_SESSIONS
+        0x00, 0x00, 0x00, 0x41, // size
+        0x00, 0x00, 0x01, 0x82, // TPM_CC_PCR_Extend
+        0x00, 0x00, 0x00, 0x0c, // PCR 12
+        0x00, 0x00, 0x00, 0x09, // authorization size
+        0x40, 0x00, 0x00, 0x09, // TPM_RS_PW
+        0x00, 0x00, // nonce
+        0x00, // attributes
+        0x00, 0x00, // password
+        0x00, 0x00, 0x00, 0x01, // digest count
+        0x00, 0x0b, // TPM_ALG_SHA256
+    ];
+    expected.extend_from_slice(&[0xab; 32]);
+    assert_eq!(&buf[..size], expected);
+}
+
+#[test]
+fn get_random_command() {
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let size = command::get_random(&mut buf, 16);
+    assert_eq!(
+        &buf[..size],
+        [0x80, 0x01, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x01, 0x7b, 0x00, 0x10]
+    );
+}
+
+#[test]
+fn response_header() {
+    let bytes = response(0x101, &[1, 2]);
+    assert_eq!(
+        command::ResponseHeader::parse(&bytes),
+        Ok(command::ResponseHeader {
+            tag: command::TPM_ST_NO_SESSIONS,
+            size: 12,
+            code: 0x101
+        })
+    );
+    assert_eq!(
+        command::ResponseHeader::parse(&bytes[..11]),
+        Err(TpmError::BadResponse)
+    );
+    assert_eq!(
+        command::ResponseHeader::parse(&bytes[..4]),
+        Err(TpmError::BadResponse)
+    );
+}
+
+#[test]
+fn pcr_extend() {
+    let mut tpm = Tpm::new(FakeTpm::new(vec![
+        response(command::TPM_RC_SUCCESS, &[0, 0, 0, 0, 0, 0, 1, 0, 0]),
+        // TPM_RC_LOCALITY
+        response(0x907, &[]),
+    ]));
+    assert_eq!(tpm.pcr_extend(8, &[0; 32]), Ok(()));
+    assert_eq!(
+        tpm.pcr_extend(8, &[0; 32]),
+        Err(TpmError::ResponseCode(0x907))
+    );
+    assert_eq!(tpm.interface.commands.len(), 2);
+}
+
+#[test]
+fn get_random() {
+    // More than one command takes, and the TPM returns fewer bytes than
+    // asked in the second one.
+    let first: Vec<u8> = (0..64).collect();
+    let mut tpm = Tpm::new(FakeTpm::new(vec![
+        random_response(&first),
+        random_response(&[0xaa; 10]),
+        random_response(&[0xbb; 6]),
+    ]));
+    let mut bytes = [0; 80];
+    assert_eq!(tpm.get_random(&mut bytes), Ok(()));
+    assert_eq!(bytes[..64], first[..]);
+    assert_eq!(bytes[64..74], [0xaa; 10]);
+    assert_eq!(bytes[74..], [0xbb; 6]);
+
+    let requested: Vec<u16> = tpm
+        .interface
+        .commands
+        .iter()
+        .map(|command| u16::from_be_bytes([command[10], command[11]]))
+        .collect();
+    assert_eq!(requested, [64, 16, 6]);
+
+    // Nothing returned would loop forever.
+    let mut tpm = Tpm::new(FakeTpm::new(vec![random_response(&[])]));
+    assert_eq!(tpm.get_random(&mut bytes), Err(TpmError::BadResponse));
+}
@@ -0,0 +1,137 @@
+//! The FIFO interface, TIS in the TPM 1.2 days.
+//!
+//! FIFO Registers used, of locality 0:
+//!
+//! Offset  Name              Type  Bits  Description
+//! ----------------------------------------------------------------------
+//! 0x000   TPM_ACCESS        RW    8     Locality Access
+//! 0x018   TPM_STS           RW    32    Status, the burst count in 8-23
+//! 0x024   TPM_DATA_FIFO     RW    8     Data FIFO
+//!
+//! The command is written to the FIFO in bursts of as many bytes as the
+//! TPM says it takes, and the response is read the same way.
+
+use crate::command;
+use crate::poll;
+use crate::Interface;
+use crate::TpmError;
+use mmio::ReadWrite;
+use mmio::Register;
+use mmio::RegisterBlock;
+
+const TPM_ACCESS: Register<u8, ReadWrite> = Register::new(0x000);
+const TPM_STS: Register<u32, ReadWrite> = Register::new(0x018);
+const TPM_DATA_FIFO: Register<u8, ReadWrite> = Register::new(0x024);
+
+const ACCESS_REQUEST_USE: u8 = 1 << 1;
+const ACCESS_ACTIVE_LOCALITY: u8 = 1 << 5;
+const ACCESS_REG_VALID: u8 = 1 << 7;
+
+const STS_EXPECT: u32 = 1 << 3;
+const STS_DATA_AVAIL: u32 = 1 << 4;
+const STS_GO: u32 = 1 << 5;
+const STS_COMMAND_READY: u32 = 1 << 6;
+const STS_VALID: u32 = 1 << 7;
+const STS_BURST_COUNT_SHIFT: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tis {
+    regs: RegisterBlock,
+}
+
+impl Tis {
+    /// # Safety
+    ///
+    /// The registers are mapped at `virt_base` with the device attributes.
+    pub unsafe fn new(virt_base: u64) -> Self {
+        Self {
+            // SAFETY: guaranteed by the caller.
+            regs: unsafe { RegisterBlock::new(virt_base) },
+        }
+    }
+
+    fn status(&self) -> u32 {
+        self.regs.read(TPM_STS)
+    }
+
+    /// Waits for the TPM to take or to give some bytes.
+    fn burst_count(&self) -> Result<usize, TpmError> {
+        let mut count = 0;
+        poll(|| {
+            count = (self.status() >> STS_BURST_COUNT_SHIFT) & 0xffff;
+            count != 0
+        })?;
+        Ok(count as usize)
+    }
+
+    fn wait_valid(&self, mask: u32, value: u32) -> Result<(), TpmError> {
+        poll(|| {
+            let status = self.status();
+            status & STS_VALID != 0 && status & mask == value
+        })
+    }
+
+    fn request_locality(&self) -> Result<(), TpmError> {
+        self.regs.write(TPM_ACCESS, ACCESS_REQUEST_USE);
+        poll(|| {
+            let access = self.regs.read(TPM_ACCESS);
+            access & (ACCESS_REG_VALID | ACCESS_ACTIVE_LOCALITY)
+                == ACCESS_REG_VALID | ACCESS_ACTIVE_LOCALITY
+        })
+        .map_err(|_| TpmError::Locality)
+    }
+
+    fn read_fifo(&self, bytes: &mut [u8]) -> Result<(), TpmError> {
+        let mut pos = 0;
+        while pos < bytes.len() {
+            self.wait_valid(STS_DATA_AVAIL, STS_DATA_AVAIL)?;
+            let burst = self.burst_count()?.min(bytes.len() - pos);
+            for byte in &mut bytes[pos..pos + burst] {
+                *byte = self.regs.read(TPM_DATA_FIFO);
+            }
+            pos += burst;
+        }
+        Ok(())
+    }
+
+    fn run(&self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        self.regs.write(TPM_STS, STS_COMMAND_READY);
+        poll(|| self.status() & STS_COMMAND_READY != 0)?;
+
+        let mut pos = 0;
+        while pos < command.len() {
+            let burst = self.burst_count()?.min(command.len() - pos);
+            for &byte in &command[pos..pos + burst] {
+                self.regs.write(TPM_DATA_FIFO, byte);
+            }
+            pos += burst;
+            // The TPM expects more until the last byte.
+            let expect = if pos < command.len() { STS_EXPECT } else { 0 };
+            self.wait_valid(STS_EXPECT, expect)?;
+        }
+
+        self.regs.write(TPM_STS, STS_GO);
+        self.wait_valid(STS_DATA_AVAIL, STS_DATA_AVAIL)?;
+
+        let mut header = [0; command::HEADER_SIZE];
+        self.read_fifo(&mut header)?;
+        let size = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+        if size < command::HEADER_SIZE || size > response.len() {
+            return Err(TpmError::BadResponse);
+        }
+        response[..command::HEADER_SIZE].copy_from_slice(&header);
+        self.read_fifo(&mut response[command::HEADER_SIZE..size])?;
+        Ok(size)
+    }
+}
+
+impl Interface for Tis {
+    fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, TpmError> {
+        self.request_locality()?;
+        let result = self.run(command, response);
+        // Back to idle, and let go of the locality.
+        self.regs.write(TPM_STS, STS_COMMAND_READY);
+        self.regs.write(TPM_ACCESS, ACCESS_ACTIVE_LOCALITY);
+        result
+    }
+}
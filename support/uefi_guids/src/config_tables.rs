@@ -0,0 +1,539 @@
+//! Typed access to the configuration tables.
+//!
+//! The firmware publishes the tables as the GUIDs with the untyped
+//! addresses. The accessors here find a table by its GUID, check its
+//! header, and hand out a typed view of it, so the callers do not cast
+//! the addresses themselves. A table that is present yet malformed is
+//! reported as absent.
+
+use crate::EFI_ACPI10_TABLE_GUID;
+use crate::EFI_ACPI20_TABLE_GUID;
+use crate::EFI_DEBUG_IMAGE_INFO_TABLE_GUID;
+use crate::EFI_HOB_LIST_GUID;
+use crate::EFI_MEMORY_ATTRIBUTES_TABLE_GUID;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use uefi::mem::memory_map::MemoryDescriptor;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::table::cfg::ConfigTableEntry;
+use uefi::Handle;
+
+/// `EFI_HOB_TYPE_HANDOFF`, the first HOB in the list.
+pub const HOB_TYPE_HANDOFF: u16 = 0x0001;
+/// `EFI_HOB_TYPE_MEMORY_ALLOCATION`.
+pub const HOB_TYPE_MEMORY_ALLOCATION: u16 = 0x0002;
+/// `EFI_HOB_TYPE_RESOURCE_DESCRIPTOR`.
+pub const HOB_TYPE_RESOURCE_DESCRIPTOR: u16 = 0x0003;
+/// `EFI_HOB_TYPE_GUID_EXTENSION`.
+pub const HOB_TYPE_GUID_EXTENSION: u16 = 0x0004;
+/// `EFI_HOB_TYPE_FV`.
+pub const HOB_TYPE_FV: u16 = 0x0005;
+/// `EFI_HOB_TYPE_CPU`.
+pub const HOB_TYPE_CPU: u16 = 0x0006;
+/// `EFI_HOB_TYPE_UNUSED`.
+pub const HOB_TYPE_UNUSED: u16 = 0xfffe;
+/// `EFI_HOB_TYPE_END_OF_HOB_LIST`.
+pub const HOB_TYPE_END_OF_HOB_LIST: u16 = 0xffff;
+
+/// The newest Memory Attributes Table version known.
+pub const MEMORY_ATTRIBUTES_TABLE_VERSION: u32 = 2;
+
+/// `EFI_DEBUG_IMAGE_INFO_TYPE_NORMAL`.
+pub const IMAGE_INFO_TYPE_NORMAL: u32 = 1;
+
+/// `EFI_HOB_GENERIC_HEADER`, starts every HOB.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HobHeader {
+    pub hob_type: u16,
+    /// The size of the HOB including this header.
+    pub length: u16,
+    pub reserved: u32,
+}
+
+/// The PI hand-off block list the firmware has built before DXE.
+#[derive(Clone, Copy)]
+pub struct HobList<'a> {
+    start: NonNull<HobHeader>,
+    _tables: PhantomData<&'a ()>,
+}
+
+/// A HOB from the list.
+#[derive(Debug, Clone, Copy)]
+pub struct Hob<'a> {
+    pub header: &'a HobHeader,
+    /// The whole HOB, the header included.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> HobList<'a> {
+    /// The HOB list, checks that it starts with the hand-off HOB.
+    ///
+    /// # Safety
+    ///
+    /// `start` points to a HOB list that stays put for `'a`.
+    pub unsafe fn new(start: *const c_void) -> Option<Self> {
+        let start = NonNull::new(start as *mut HobHeader)?;
+        if start
+            .as_ptr()
+            .align_offset(core::mem::align_of::<HobHeader>())
+            != 0
+        {
+            return None;
+        }
+        // SAFETY: the caller guarantees the list is there.
+        let first = unsafe { start.as_ref() };
+        if first.hob_type != HOB_TYPE_HANDOFF
+            || usize::from(first.length) < core::mem::size_of::<HobHeader>()
+        {
+            return None;
+        }
+
+        Some(Self {
+            start,
+            _tables: PhantomData,
+        })
+    }
+
+    /// The HOBs up to the end of the list. The iteration stops early at
+    /// a HOB whose length cannot be right.
+    pub fn iter(&self) -> impl Iterator<Item = Hob<'a>> {
+        let mut next = Some(self.start.as_ptr() as *const u8);
+        core::iter::from_fn(move || {
+            let hob = next?;
+            // SAFETY: the list is valid up to the end HOB, and each HOB
+            // is followed by another one.
+            let header = unsafe { &*(hob as *const HobHeader) };
+            let length = usize::from(header.length);
+            if header.hob_type == HOB_TYPE_END_OF_HOB_LIST
+                || length < core::mem::size_of::<HobHeader>()
+                || !length.is_multiple_of(8)
+            {
+                next = None;
+                return None;
+            }
+
+            // SAFETY: the HOB is `length` bytes long.
+            let bytes = unsafe { core::slice::from_raw_parts(hob, length) };
+            next = Some(hob.wrapping_add(length));
+            Some(Hob { header, bytes })
+        })
+    }
+
+    /// The GUID extension HOBs with the given name, and their data.
+    pub fn guid_extensions(&self, name: uefi::Guid) -> impl Iterator<Item = &'a [u8]> {
+        const GUID_OFFSET: usize = core::mem::size_of::<HobHeader>();
+        const DATA_OFFSET: usize = GUID_OFFSET + core::mem::size_of::<uefi::Guid>();
+
+        self.iter().filter_map(move |hob| {
+            if hob.header.hob_type != HOB_TYPE_GUID_EXTENSION {
+                return None;
+            }
+            let guid: [u8; 16] = hob.bytes.get(GUID_OFFSET..DATA_OFFSET)?.try_into().ok()?;
+            (uefi::Guid::from_bytes(guid) == name).then(|| &hob.bytes[DATA_OFFSET..])
+        })
+    }
+}
+
+/// `EFI_MEMORY_ATTRIBUTES_TABLE` without the descriptors that follow it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryAttributesTableHeader {
+    pub version: u32,
+    pub number_of_entries: u32,
+    pub descriptor_size: u32,
+    pub flags: u32,
+}
+
+/// The protections of the runtime images the firmware asks the OS to use.
+#[derive(Clone, Copy)]
+pub struct MemoryAttributesTable<'a> {
+    header: &'a MemoryAttributesTableHeader,
+}
+
+impl<'a> MemoryAttributesTable<'a> {
+    /// The table, checks the version and the descriptor size.
+    ///
+    /// # Safety
+    ///
+    /// `table` points to a Memory Attributes Table that stays put for `'a`.
+    pub unsafe fn new(table: *const c_void) -> Option<Self> {
+        let table = table as *const MemoryAttributesTableHeader;
+        if table.align_offset(core::mem::align_of::<MemoryDescriptor>()) != 0 {
+            return None;
+        }
+        // SAFETY: the caller guarantees the table is there.
+        let header = unsafe { table.as_ref()? };
+        let descriptor_size = header.descriptor_size as usize;
+        if header.version == 0
+            || header.version > MEMORY_ATTRIBUTES_TABLE_VERSION
+            || descriptor_size < core::mem::size_of::<MemoryDescriptor>()
+            || !descriptor_size.is_multiple_of(core::mem::align_of::<MemoryDescriptor>())
+        {
+            return None;
+        }
+
+        Some(Self { header })
+    }
+
+    pub fn header(&self) -> &'a MemoryAttributesTableHeader {
+        self.header
+    }
+
+    /// The descriptors, they are `descriptor_size` bytes apart.
+    pub fn entries(&self) -> impl Iterator<Item = &'a MemoryDescriptor> {
+        let header: &'a MemoryAttributesTableHeader = self.header;
+        let first = (header as *const MemoryAttributesTableHeader).wrapping_add(1) as *const u8;
+        let stride = header.descriptor_size as usize;
+
+        (0..header.number_of_entries as usize).map(move |i| {
+            // SAFETY: the table is followed by `number_of_entries`
+            // descriptors, and the stride keeps them aligned.
+            unsafe { &*(first.wrapping_add(i * stride) as *const MemoryDescriptor) }
+        })
+    }
+}
+
+/// `EFI_DEBUG_IMAGE_INFO_TABLE_HEADER`.
+#[repr(C)]
+pub struct DebugImageInfoTableHeader {
+    pub update_status: u32,
+    pub table_size: u32,
+    /// An array of pointers to `EFI_DEBUG_IMAGE_INFO` that are `NULL` or
+    /// point to [`DebugImageInfoNormal`].
+    pub table: *mut *mut DebugImageInfoNormal,
+}
+
+/// `EFI_DEBUG_IMAGE_INFO_NORMAL`.
+#[repr(C)]
+pub struct DebugImageInfoNormal {
+    pub image_info_type: u32,
+    pub loaded_image: *const LoadedImage,
+    pub image_handle: Handle,
+}
+
+/// The images the firmware tells the debuggers about.
+pub struct DebugImageInfoTable<'a> {
+    header: NonNull<DebugImageInfoTableHeader>,
+    _tables: PhantomData<&'a ()>,
+}
+
+impl<'a> DebugImageInfoTable<'a> {
+    /// # Safety
+    ///
+    /// `table` points to a Debug Image Info Table header that stays put
+    /// for `'a`.
+    pub unsafe fn new(table: *const c_void) -> Option<Self> {
+        let header = NonNull::new(table as *mut DebugImageInfoTableHeader)?;
+        if header
+            .as_ptr()
+            .align_offset(core::mem::align_of::<DebugImageInfoTableHeader>())
+            != 0
+        {
+            return None;
+        }
+        // SAFETY: the caller guarantees the header is there.
+        let (table_size, table) = unsafe { (header.as_ref().table_size, header.as_ref().table) };
+        if table_size != 0 && table.is_null() {
+            return None;
+        }
+
+        Some(Self {
+            header,
+            _tables: PhantomData,
+        })
+    }
+
+    /// The header, the firmware and the loader update it in place.
+    pub fn header(&self) -> NonNull<DebugImageInfoTableHeader> {
+        self.header
+    }
+
+    /// The images of the normal type.
+    pub fn images(&self) -> impl Iterator<Item = &'a DebugImageInfoNormal> {
+        // SAFETY: the header is valid for `'a`.
+        let header = unsafe { self.header.as_ref() };
+        let table: &'a [*mut DebugImageInfoNormal] = if header.table.is_null() {
+            &[]
+        } else {
+            // SAFETY: the table has `table_size` entries.
+            unsafe { core::slice::from_raw_parts(header.table, header.table_size as usize) }
+        };
+
+        table.iter().filter_map(|&entry| {
+            // SAFETY: the non-NULL entries point to the image infos.
+            let entry = unsafe { entry.as_ref()? };
+            (entry.image_info_type == IMAGE_INFO_TYPE_NORMAL).then_some(entry)
+        })
+    }
+}
+
+/// The ACPI Root System Description Pointer, the fields after `rsdt_address`
+/// are there from the revision 2 on.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3],
+}
+
+impl Rsdp {
+    pub const SIGNATURE: &'static [u8; 8] = b"RSD PTR ";
+    /// The part of the structure the ACPI 1.0 defines.
+    pub const V1_SIZE: usize = 20;
+
+    /// The RSDP, checks the signature and the checksums.
+    ///
+    /// # Safety
+    ///
+    /// `rsdp` points to at least [`Self::V1_SIZE`] bytes, and to the whole
+    /// structure if the revision is 2 or newer, that stay put for `'a`.
+    pub unsafe fn new<'a>(rsdp: *const c_void) -> Option<&'a Self> {
+        let bytes = rsdp as *const u8;
+        if bytes.is_null() {
+            return None;
+        }
+        // SAFETY: the caller guarantees the first part is there.
+        let v1 = unsafe { core::slice::from_raw_parts(bytes, Self::V1_SIZE) };
+        if !v1.starts_with(Self::SIGNATURE) || !checksum_ok(v1) {
+            return None;
+        }
+
+        // SAFETY: the structure is packed, and the caller guarantees the
+        // newer fields are there for the newer revisions.
+        let rsdp = unsafe { &*(bytes as *const Self) };
+        if rsdp.revision >= 2 {
+            let length = rsdp.length as usize;
+            if length < core::mem::size_of::<Self>() {
+                return None;
+            }
+            // SAFETY: the revision 2 structure is `length` bytes long.
+            let all = unsafe { core::slice::from_raw_parts(bytes, length) };
+            if !checksum_ok(all) {
+                return None;
+            }
+        }
+
+        Some(rsdp)
+    }
+
+    /// The XSDT from the revision 2 on, the RSDT before that.
+    pub fn root_table_address(&self) -> u64 {
+        if self.revision >= 2 && self.xsdt_address != 0 {
+            self.xsdt_address
+        } else {
+            u64::from(self.rsdt_address)
+        }
+    }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// The configuration tables the firmware has published.
+#[derive(Clone, Copy)]
+pub struct ConfigTables<'a> {
+    entries: &'a [ConfigTableEntry],
+}
+
+impl<'a> ConfigTables<'a> {
+    /// # Safety
+    ///
+    /// The tables are at the addresses in the entries for `'a`, as they are
+    /// while the boot services are active and the memory is identity-mapped.
+    pub unsafe fn new(entries: &'a [ConfigTableEntry]) -> Self {
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &'a [ConfigTableEntry] {
+        self.entries
+    }
+
+    /// The address of the table with the GUID, `None` if it is absent or
+    /// `NULL`.
+    pub fn find(&self, guid: &uefi::Guid) -> Option<*const c_void> {
+        self.entries
+            .iter()
+            .find(|entry| entry.guid == *guid && !entry.address.is_null())
+            .map(|entry| entry.address)
+    }
+
+    pub fn hob_list(&self) -> Option<HobList<'a>> {
+        // SAFETY: the tables are valid for `'a`.
+        unsafe { HobList::new(self.find(&EFI_HOB_LIST_GUID)?) }
+    }
+
+    pub fn memory_attributes(&self) -> Option<MemoryAttributesTable<'a>> {
+        // SAFETY: the tables are valid for `'a`.
+        unsafe { MemoryAttributesTable::new(self.find(&EFI_MEMORY_ATTRIBUTES_TABLE_GUID)?) }
+    }
+
+    pub fn debug_image_info(&self) -> Option<DebugImageInfoTable<'a>> {
+        // SAFETY: the tables are valid for `'a`.
+        unsafe { DebugImageInfoTable::new(self.find(&EFI_DEBUG_IMAGE_INFO_TABLE_GUID)?) }
+    }
+
+    /// The valid RSDP, the one for ACPI 2.0 is preferred.
+    pub fn rsdp(&self) -> Option<&'a Rsdp> {
+        [EFI_ACPI20_TABLE_GUID, EFI_ACPI10_TABLE_GUID]
+            .iter()
+            .filter_map(|guid| self.find(guid))
+            // SAFETY: the tables are valid for `'a`.
+            .find_map(|rsdp| unsafe { Rsdp::new(rsdp) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config_tables::ConfigTables;
+    use crate::config_tables::MemoryAttributesTableHeader;
+    use crate::config_tables::Rsdp;
+    use crate::config_tables::HOB_TYPE_END_OF_HOB_LIST;
+    use crate::config_tables::HOB_TYPE_GUID_EXTENSION;
+    use crate::config_tables::HOB_TYPE_HANDOFF;
+    use crate::EFI_ACPI10_TABLE_GUID;
+    use crate::EFI_ACPI20_TABLE_GUID;
+    use crate::EFI_HOB_LIST_GUID;
+    use crate::EFI_MEMORY_ATTRIBUTES_TABLE_GUID;
+    use core::ffi::c_void;
+    use uefi::mem::memory_map::MemoryDescriptor;
+    use uefi::table::cfg::ConfigTableEntry;
+
+    fn entry(guid: uefi::Guid, address: *const u64) -> ConfigTableEntry {
+        ConfigTableEntry {
+            guid,
+            address: address as *const c_void,
+        }
+    }
+
+    fn hob(hob_type: u16, length: u16) -> u64 {
+        u64::from(hob_type) | u64::from(length) << 16
+    }
+
+    #[test]
+    fn hob_list() {
+        let name = uefi::guid!("01234567-89ab-cdef-0123-456789abcdef");
+        let name = name.to_bytes();
+        let hobs: [u64; 9] = [
+            hob(HOB_TYPE_HANDOFF, 16),
+            0,
+            hob(HOB_TYPE_GUID_EXTENSION, 32),
+            u64::from_le_bytes(name[..8].try_into().unwrap()),
+            u64::from_le_bytes(name[8..].try_into().unwrap()),
+            0x1122_3344_5566_7788,
+            hob(HOB_TYPE_END_OF_HOB_LIST, 8),
+            hob(HOB_TYPE_HANDOFF, 16),
+            0,
+        ];
+        let entries = [entry(EFI_HOB_LIST_GUID, hobs.as_ptr())];
+        // SAFETY: the tables are on the stack.
+        let tables = unsafe { ConfigTables::new(&entries) };
+
+        let list = tables.hob_list().unwrap();
+        assert_eq!(list.iter().count(), 2);
+        let data: [u8; 8] = list
+            .guid_extensions(uefi::Guid::from_bytes(name))
+            .next()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(u64::from_le_bytes(data), 0x1122_3344_5566_7788);
+
+        // Must start with the hand-off HOB.
+        let entries = [entry(EFI_HOB_LIST_GUID, hobs[2..].as_ptr())];
+        // SAFETY: the tables are on the stack.
+        assert!(unsafe { ConfigTables::new(&entries) }.hob_list().is_none());
+    }
+
+    #[test]
+    fn memory_attributes() {
+        const DESCRIPTOR_WORDS: usize = 6;
+        let mut table = [0u64; 2 + 2 * DESCRIPTOR_WORDS];
+        let header = MemoryAttributesTableHeader {
+            version: 2,
+            number_of_entries: 2,
+            descriptor_size: (DESCRIPTOR_WORDS * 8) as u32,
+            flags: 1,
+        };
+        // SAFETY: the header fits in the first two words.
+        unsafe { (table.as_mut_ptr() as *mut MemoryAttributesTableHeader).write(header) };
+        table[2 + 1] = 0x1000;
+        table[2 + DESCRIPTOR_WORDS + 1] = 0x5000;
+
+        let entries = [entry(EFI_MEMORY_ATTRIBUTES_TABLE_GUID, table.as_ptr())];
+        // SAFETY: the tables are on the stack.
+        let tables = unsafe { ConfigTables::new(&entries) };
+        let attributes = tables.memory_attributes().unwrap();
+        assert_eq!(attributes.header().flags, 1);
+        let starts: [u64; 2] = core::array::from_fn(|i| {
+            attributes
+                .entries()
+                .map(|d: &MemoryDescriptor| d.phys_start)
+                .nth(i)
+                .unwrap()
+        });
+        assert_eq!(starts, [0x1000, 0x5000]);
+
+        // The descriptors are not smaller than the structure.
+        let mut small = table;
+        small[1] = 16;
+        let entries = [entry(EFI_MEMORY_ATTRIBUTES_TABLE_GUID, small.as_ptr())];
+        // SAFETY: the tables are on the stack.
+        let tables = unsafe { ConfigTables::new(&entries) };
+        assert!(tables.memory_attributes().is_none());
+    }
+
+    #[test]
+    fn rsdp() {
+        fn fill(rsdp: &mut [u8; 40], revision: u8) {
+            rsdp.fill(0);
+            rsdp[..8].copy_from_slice(Rsdp::SIGNATURE);
+            rsdp[15] = revision;
+            rsdp[16..20].copy_from_slice(&0x1000u32.to_le_bytes());
+            rsdp[20..24].copy_from_slice(&36u32.to_le_bytes());
+            rsdp[24..32].copy_from_slice(&0x2000u64.to_le_bytes());
+            let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |s, &b| s.wrapping_add(b));
+            rsdp[8] = 0u8.wrapping_sub(sum(&rsdp[..20]));
+            rsdp[32] = 0u8.wrapping_sub(sum(&rsdp[..36]));
+        }
+
+        let (mut v1, mut v2) = ([0u64; 5], [0u64; 5]);
+        // SAFETY: the arrays are 40 bytes.
+        let (b1, b2) = unsafe {
+            (
+                &mut *(v1.as_mut_ptr() as *mut [u8; 40]),
+                &mut *(v2.as_mut_ptr() as *mut [u8; 40]),
+            )
+        };
+        fill(b1, 0);
+        fill(b2, 2);
+
+        let entries = [
+            entry(EFI_ACPI10_TABLE_GUID, v1.as_ptr()),
+            entry(EFI_ACPI20_TABLE_GUID, v2.as_ptr()),
+        ];
+        // SAFETY: the tables are on the stack.
+        let tables = unsafe { ConfigTables::new(&entries) };
+        let rsdp = tables.rsdp().unwrap();
+        assert_eq!(rsdp.revision, 2);
+        assert_eq!(rsdp.root_table_address(), 0x2000);
+
+        // A bad ACPI 2.0 RSDP falls back to the ACPI 1.0 one.
+        b2[32] ^= 1;
+        let rsdp = tables.rsdp().unwrap();
+        assert_eq!(rsdp.revision, 0);
+        assert_eq!(rsdp.root_table_address(), 0x1000);
+
+        b1[0] = b'X';
+        assert!(tables.rsdp().is_none());
+    }
+}
@@ -2,11 +2,38 @@
 
 #![no_std]
 
+pub mod config_tables;
+
+use core::fmt;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
 use uefi::guid;
 
+/// What a table is about, lets group the related tables in the reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuidCategory {
+    /// The ACPI tables.
+    Acpi,
+    /// The SMBIOS entry points.
+    Smbios,
+    /// The PI hand-off blocks.
+    Hob,
+    /// The debugging and the status code support.
+    Debug,
+    /// The memory map and the memory attributes.
+    Memory,
+    /// The other UEFI and PI firmware tables.
+    Firmware,
+    /// Anything else, mostly specific to a firmware vendor.
+    Vendor,
+}
+
 pub struct UefiTableGuidName {
     pub guid: uefi::Guid,
     pub name: &'static str,
+    pub category: GuidCategory,
 }
 
 // Some tables EDK II uses.
@@ -20,8 +47,10 @@ pub const EFI_MEMORY_TYPE_INFORMATION_GUID: uefi::Guid =
     guid!("4c19049f-4137-4dd3-9c10-8b97a83ffdfa");
 pub const EFI_HOB_LIST_GUID: uefi::Guid = guid!("7739f24c-93d7-11d4-9a3a-0090273fc14d");
 pub const EFI_ACPI20_TABLE_GUID: uefi::Guid = guid!("8868e871-e4f1-11d3-bc22-0080c73c8881");
+pub const EFI_DTB_TABLE_GUID: uefi::Guid = guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
 pub const EFI_MEMORY_ATTRIBUTES_TABLE_GUID: uefi::Guid =
     guid!("dcfa911d-26eb-469f-a220-38b7dc461220");
+pub const EFI_RT_PROPERTIES_TABLE_GUID: uefi::Guid = guid!("eb66918a-7eef-402a-842e-931d21c38ae9");
 pub const EFI_ACPI10_TABLE_GUID: uefi::Guid = guid!("eb9d2d30-2d88-11d3-9a16-0090273fc14d");
 pub const EFI_SMBIOS_TABLE_GUID: uefi::Guid = guid!("eb9d2d31-2d88-11d3-9a16-0090273fc14d");
 pub const LZMA_CUSTOM_DECOMPRESS_GUID: uefi::Guid = guid!("ee4e5898-3914-4259-9d6e-dc7bd79403cf");
@@ -29,71 +58,396 @@ pub const EFI_SMBIOS3_TABLE_GUID: uefi::Guid = guid!("f2fd1544-9794-4a2c-992e-e5
 pub const EFI_CRC32_GUIDED_SECTION_EXTRACTION_GUID: uefi::Guid =
     guid!("fc1bcdb0-7d31-49aa-936a-a4600d9dd083");
 
-/// Known UEFI table GUIDs.
+/// Known UEFI table GUIDs, the full table has them with the same names and
+/// categories.
 /// NOTE: Keep sorted by the GUID!
-#[cfg(not(feature = "all_uefi_table_guids"))]
-const UEFI_TABLE_GUIDS: &[UefiTableGuidName] = &[
+#[cfg(any(test, not(feature = "all_uefi_table_guids")))]
+const BUILTIN_UEFI_TABLE_GUIDS: &[UefiTableGuidName] = &[
     UefiTableGuidName {
         guid: guid!("05ad34ba-6f02-4214-952e-4da0398e2bb9"),
         name: "EfiDxeServicesTableGuid",
+        category: GuidCategory::Firmware,
     },
     UefiTableGuidName {
         guid: guid!("060cc026-4c0d-4dda-8f41-595fef00a502"),
         name: "MemoryStatusCodeRecordGuid",
+        category: GuidCategory::Debug,
     },
     UefiTableGuidName {
         guid: guid!("49152e77-1ada-4764-b7a2-7afefed95e8b"),
         name: "EfiDebugImageInfoTableGuid",
+        category: GuidCategory::Debug,
     },
     UefiTableGuidName {
         guid: guid!("4c19049f-4137-4dd3-9c10-8b97a83ffdfa"),
         name: "EfiMemoryTypeInformationGuid",
+        category: GuidCategory::Memory,
     },
     UefiTableGuidName {
         guid: guid!("7739f24c-93d7-11d4-9a3a-0090273fc14d"),
         name: "EfiHobListGuid",
+        category: GuidCategory::Hob,
     },
     UefiTableGuidName {
         guid: guid!("8868e871-e4f1-11d3-bc22-0080c73c8881"),
         name: "EfiAcpi20TableGuid",
+        category: GuidCategory::Acpi,
+    },
+    UefiTableGuidName {
+        guid: guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0"),
+        name: "FdtTableGuid",
+        category: GuidCategory::Firmware,
     },
     UefiTableGuidName {
         guid: guid!("dcfa911d-26eb-469f-a220-38b7dc461220"),
         name: "EfiMemoryAttributesTableGuid",
+        category: GuidCategory::Memory,
+    },
+    UefiTableGuidName {
+        guid: guid!("eb66918a-7eef-402a-842e-931d21c38ae9"),
+        name: "EfiRtPropertiesTable",
+        category: GuidCategory::Firmware,
     },
     UefiTableGuidName {
         guid: guid!("eb9d2d30-2d88-11d3-9a16-0090273fc14d"),
         name: "EfiAcpi10TableGuid",
+        category: GuidCategory::Acpi,
     },
     UefiTableGuidName {
         guid: guid!("eb9d2d31-2d88-11d3-9a16-0090273fc14d"),
         name: "EfiSmbiosTableGuid",
+        category: GuidCategory::Smbios,
     },
     UefiTableGuidName {
         guid: guid!("ee4e5898-3914-4259-9d6e-dc7bd79403cf"),
         name: "LzmaCustomDecompressGuid",
+        category: GuidCategory::Firmware,
     },
     UefiTableGuidName {
         guid: guid!("f2fd1544-9794-4a2c-992e-e5bbcf20e394"),
         name: "EfiSmbios3TableGuid",
+        category: GuidCategory::Smbios,
     },
     UefiTableGuidName {
         guid: guid!("fc1bcdb0-7d31-49aa-936a-a4600d9dd083"),
         name: "EfiCrc32GuidedSectionExtractionGuid",
+        category: GuidCategory::Firmware,
     },
 ];
 
+#[cfg(not(feature = "all_uefi_table_guids"))]
+const UEFI_TABLE_GUIDS: &[UefiTableGuidName] = BUILTIN_UEFI_TABLE_GUIDS;
+
 #[cfg(feature = "all_uefi_table_guids")]
 const UEFI_TABLE_GUIDS: &[UefiTableGuidName] = include!("all_uefi_table_guids.irs");
 
-pub fn get_uefi_table_name(guid: &uefi::Guid) -> &'static str {
-    if let Ok(i) = UEFI_TABLE_GUIDS.binary_search_by_key(guid, |x: &UefiTableGuidName| x.guid) {
-        UEFI_TABLE_GUIDS[i].name
-    } else {
-        "Unknown table GUID"
+/// The first field of the GUID, the table is sorted by it.
+const fn time_low(guid: &uefi::Guid) -> u32 {
+    u32::from_le_bytes(guid.time_low())
+}
+
+/// The full table has thousands of entries and is searched for every
+/// configuration table and protocol. The entries with the same first
+/// byte of the GUID are found through this index, `UEFI_TABLE_INDEX[b]`
+/// is where those with the first byte `b` start, and the next element is
+/// where they end. Also checks the table is sorted.
+#[cfg(feature = "all_uefi_table_guids")]
+const UEFI_TABLE_INDEX: [u16; 257] = {
+    assert!(UEFI_TABLE_GUIDS.len() <= u16::MAX as usize);
+
+    let mut index = [0_u16; 257];
+    let mut first_byte = 0;
+    let mut i = 0;
+    while i < UEFI_TABLE_GUIDS.len() {
+        let key = time_low(&UEFI_TABLE_GUIDS[i].guid);
+        assert!(
+            i == 0 || time_low(&UEFI_TABLE_GUIDS[i - 1].guid) <= key,
+            "UEFI_TABLE_GUIDS must be sorted by the GUID"
+        );
+        while first_byte <= (key >> 24) as usize {
+            index[first_byte] = i as u16;
+            first_byte += 1;
+        }
+        i += 1;
     }
+    while first_byte < index.len() {
+        index[first_byte] = UEFI_TABLE_GUIDS.len() as u16;
+        first_byte += 1;
+    }
+
+    index
+};
+
+/// The entries to search for the GUID.
+#[cfg(feature = "all_uefi_table_guids")]
+fn candidates(guid: &uefi::Guid) -> &'static [UefiTableGuidName] {
+    let first_byte = (time_low(guid) >> 24) as usize;
+    &UEFI_TABLE_GUIDS
+        [UEFI_TABLE_INDEX[first_byte] as usize..UEFI_TABLE_INDEX[first_byte + 1] as usize]
+}
+
+#[cfg(not(feature = "all_uefi_table_guids"))]
+fn candidates(_guid: &uefi::Guid) -> &'static [UefiTableGuidName] {
+    UEFI_TABLE_GUIDS
+}
+
+const EXTRA_NONE: u8 = 0;
+const EXTRA_REGISTERING: u8 = 1;
+const EXTRA_READY: u8 = 2;
+
+/// The registered table is published with the release ordering on the
+/// state after its address and length are stored.
+static EXTRA_STATE: AtomicU8 = AtomicU8::new(EXTRA_NONE);
+static EXTRA_TABLES: AtomicPtr<UefiTableGuidName> = AtomicPtr::new(core::ptr::null_mut());
+static EXTRA_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the GUIDs of the tables the built-in list does not know,
+/// e.g. the vendor ones for a particular board, in any order. The lookups
+/// consult them after the built-in list. Only one list can be registered,
+/// returns `false` if one already has been.
+pub fn register_uefi_tables(tables: &'static [UefiTableGuidName]) -> bool {
+    if EXTRA_STATE
+        .compare_exchange(
+            EXTRA_NONE,
+            EXTRA_REGISTERING,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        return false;
+    }
+
+    EXTRA_TABLES.store(tables.as_ptr().cast_mut(), Ordering::Relaxed);
+    EXTRA_COUNT.store(tables.len(), Ordering::Relaxed);
+    EXTRA_STATE.store(EXTRA_READY, Ordering::Release);
+    true
+}
+
+/// The registered tables, empty if none.
+fn extra_tables() -> &'static [UefiTableGuidName] {
+    if EXTRA_STATE.load(Ordering::Acquire) != EXTRA_READY {
+        return &[];
+    }
+
+    // SAFETY: the pointer and the length come from a `'static` slice, and
+    // have been stored before the state became ready.
+    unsafe {
+        core::slice::from_raw_parts(
+            EXTRA_TABLES.load(Ordering::Relaxed),
+            EXTRA_COUNT.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// The known table with the GUID.
+pub fn get_uefi_table(guid: &uefi::Guid) -> Option<&'static UefiTableGuidName> {
+    // The GUIDs are ordered by the first field only: the next two are
+    // little-endian, and `Ord` of `Guid` compares their bytes.
+    let key = time_low(guid);
+    let candidates = candidates(guid);
+    let start = candidates.partition_point(|x| time_low(&x.guid) < key);
+    candidates[start..]
+        .iter()
+        .take_while(|x| time_low(&x.guid) == key)
+        .find(|x| x.guid == *guid)
+        .or_else(|| extra_tables().iter().find(|x| x.guid == *guid))
+}
+
+pub fn get_uefi_table_name(guid: &uefi::Guid) -> &'static str {
+    get_uefi_table(guid).map_or("Unknown table GUID", |x| x.name)
+}
+
+/// The category of the table, [`GuidCategory::Vendor`] if not known.
+pub fn get_uefi_table_category(guid: &uefi::Guid) -> GuidCategory {
+    get_uefi_table(guid).map_or(GuidCategory::Vendor, |x| x.category)
 }
 
 pub fn get_uefi_known_guids_count() -> usize {
-    UEFI_TABLE_GUIDS.len()
+    UEFI_TABLE_GUIDS.len() + extra_tables().len()
+}
+
+/// The GUID of the table by its name, the case is ignored.
+pub fn find_guid_by_name(name: &str) -> Option<uefi::Guid> {
+    UEFI_TABLE_GUIDS
+        .iter()
+        .chain(extra_tables())
+        .find(|x| x.name.eq_ignore_ascii_case(name))
+        .map(|x| x.guid)
+}
+
+/// Parses `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` in either case, optionally
+/// in braces as the registry and the C initializers have it.
+pub fn parse_guid(s: &str) -> Option<uefi::Guid> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or(s);
+    uefi::Guid::try_parse(s).ok()
+}
+
+/// Writes the first `groups` groups of the GUID given in the UEFI byte
+/// order, where the first three fields are little-endian.
+fn write_guid_groups(bytes: &[u8; 16], groups: usize, out: &mut impl fmt::Write) -> fmt::Result {
+    let time_low = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let time_mid = u16::from_le_bytes([bytes[4], bytes[5]]);
+    let time_high = u16::from_le_bytes([bytes[6], bytes[7]]);
+    write!(out, "{time_low:08x}-{time_mid:04x}-{time_high:04x}")?;
+    if groups > 3 {
+        write!(out, "-{:02x}{:02x}-", bytes[8], bytes[9])?;
+        bytes[10..]
+            .iter()
+            .try_for_each(|b| write!(out, "{b:02x}"))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the GUID given in the UEFI byte order as
+/// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`. Does not need the `uefi` types,
+/// so the code that does not link the `uefi` crate can use it.
+pub fn format_guid_bytes(bytes: &[u8; 16], out: &mut impl fmt::Write) -> fmt::Result {
+    write_guid_groups(bytes, 5, out)
+}
+
+/// Writes the GUID as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+pub fn format_guid(guid: &uefi::Guid, out: &mut impl fmt::Write) -> fmt::Result {
+    format_guid_bytes(&guid.to_bytes(), out)
+}
+
+/// Writes the first three groups of the GUID, `xxxxxxxx-xxxx-xxxx`, that
+/// tell the GUIDs apart in the log lines well enough.
+pub fn format_guid_short(guid: &uefi::Guid, out: &mut impl fmt::Write) -> fmt::Result {
+    write_guid_groups(&guid.to_bytes(), 3, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::find_guid_by_name;
+    use crate::format_guid;
+    use crate::format_guid_bytes;
+    use crate::format_guid_short;
+    use crate::get_uefi_table;
+    use crate::get_uefi_table_category;
+    use crate::get_uefi_table_name;
+    use crate::parse_guid;
+    use crate::register_uefi_tables;
+    use crate::GuidCategory;
+    use crate::UefiTableGuidName;
+    use crate::BUILTIN_UEFI_TABLE_GUIDS;
+    use crate::EFI_ACPI20_TABLE_GUID;
+    use crate::EFI_DXE_SERVICES_TABLE_GUID;
+    use crate::EFI_HOB_LIST_GUID;
+    use crate::EFI_MEMORY_ATTRIBUTES_TABLE_GUID;
+    use crate::EFI_SMBIOS3_TABLE_GUID;
+    use crate::UEFI_TABLE_GUIDS;
+
+    #[test]
+    fn guid_by_name() {
+        assert_eq!(
+            find_guid_by_name("EfiAcpi20TableGuid"),
+            Some(EFI_ACPI20_TABLE_GUID)
+        );
+        assert_eq!(
+            find_guid_by_name("efismbios3tableguid"),
+            Some(EFI_SMBIOS3_TABLE_GUID)
+        );
+        assert_eq!(find_guid_by_name("NoSuchTable"), None);
+    }
+
+    #[test]
+    fn lookup() {
+        for x in UEFI_TABLE_GUIDS {
+            assert_eq!(
+                get_uefi_table(&x.guid).map(|found| found.guid),
+                Some(x.guid)
+            );
+        }
+        assert_eq!(get_uefi_table_name(&EFI_HOB_LIST_GUID), "EfiHobListGuid");
+        assert!(
+            get_uefi_table(&parse_guid("01234567-89ab-cdef-0123-456789abcdef").unwrap()).is_none()
+        );
+    }
+
+    #[test]
+    fn categories() {
+        for (guid, category) in [
+            (EFI_ACPI20_TABLE_GUID, GuidCategory::Acpi),
+            (EFI_SMBIOS3_TABLE_GUID, GuidCategory::Smbios),
+            (EFI_HOB_LIST_GUID, GuidCategory::Hob),
+            (EFI_MEMORY_ATTRIBUTES_TABLE_GUID, GuidCategory::Memory),
+            (EFI_DXE_SERVICES_TABLE_GUID, GuidCategory::Firmware),
+        ] {
+            assert_eq!(get_uefi_table_category(&guid), category, "{guid}");
+        }
+        assert_eq!(
+            get_uefi_table_category(&parse_guid("01234567-89ab-cdef-0123-456789abcdef").unwrap()),
+            GuidCategory::Vendor
+        );
+    }
+
+    #[test]
+    fn builtin_table_agrees() {
+        for x in BUILTIN_UEFI_TABLE_GUIDS {
+            let found = get_uefi_table(&x.guid).unwrap();
+            assert_eq!((found.name, found.category), (x.name, x.category));
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_guid("8868e871-e4f1-11d3-bc22-0080c73c8881"),
+            Some(EFI_ACPI20_TABLE_GUID)
+        );
+        assert_eq!(
+            parse_guid(" {F2FD1544-9794-4A2C-992E-E5BBCF20E394} "),
+            Some(EFI_SMBIOS3_TABLE_GUID)
+        );
+        assert_eq!(parse_guid("8868e871-e4f1-11d3-bc22-0080c73c888"), None);
+        assert_eq!(parse_guid("8868e871e4f111d3bc220080c73c8881"), None);
+        assert_eq!(parse_guid("{8868e871-e4f1-11d3-bc22-0080c73c8881"), None);
+        assert_eq!(parse_guid("g868e871-e4f1-11d3-bc22-0080c73c8881"), None);
+    }
+
+    #[test]
+    fn format() {
+        extern crate std;
+        use std::string::String;
+        use std::string::ToString;
+
+        for x in UEFI_TABLE_GUIDS {
+            let mut s = String::new();
+            format_guid(&x.guid, &mut s).unwrap();
+            assert_eq!(s, x.guid.to_string());
+
+            let mut s = String::new();
+            format_guid_bytes(&x.guid.to_bytes(), &mut s).unwrap();
+            assert_eq!(s, x.guid.to_string());
+        }
+
+        let mut s = String::new();
+        format_guid_short(&EFI_ACPI20_TABLE_GUID, &mut s).unwrap();
+        assert_eq!(s, "8868e871-e4f1-11d3");
+    }
+
+    #[test]
+    fn extra_tables() {
+        static BOARD_TABLES: &[UefiTableGuidName] = &[UefiTableGuidName {
+            guid: uefi::guid!("fedcba98-7654-3210-fedc-ba9876543210"),
+            name: "BoardTestTable",
+            category: GuidCategory::Vendor,
+        }];
+        let guid = BOARD_TABLES[0].guid;
+
+        assert!(register_uefi_tables(BOARD_TABLES));
+        assert!(!register_uefi_tables(&[]));
+        assert_eq!(get_uefi_table_name(&guid), "BoardTestTable");
+        assert_eq!(find_guid_by_name("BoardTestTable"), Some(guid));
+        assert_eq!(
+            get_uefi_table_name(&EFI_ACPI20_TABLE_GUID),
+            "EfiAcpi20TableGuid"
+        );
+    }
 }
@@ -0,0 +1,335 @@
+//! Boots the loader and the kernel under QEMU, and checks what they log.
+//!
+//! The loader and the kernel are built as `run.py` builds them, into their
+//! own target directory so the build does not wait on the one running the
+//! tests. The ESP is a directory QEMU presents as a FAT drive, with the
+//! loader, the kernel, and the configuration. The kernel gets `qemu_exit`
+//! on its command line to end the run once it has initialized, and the
+//! exit code of QEMU says whether the self tests have passed: the kernel
+//! asks `isa-debug-exit` on x86_64, and the semihosting on aarch64.
+//!
+//! The firmware comes from the `edk2-uefi` submodule. The tests need it
+//! and QEMU, so they are ignored unless asked for, and fail when either is
+//! missing then.
+//!
+//! ```sh
+//! cargo test -p qemu-tests -- --ignored --nocapture
+//! ```
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The emulated processors boot slowly, the firmware especially.
+const BOOT_TIMEOUT: Duration = Duration::from_secs(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MEMORY: &str = "1024M";
+const CPUS: &str = "2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::X86_64 => "x86_64",
+            Self::Aarch64 => "aarch64",
+        }
+    }
+
+    fn qemu(self) -> String {
+        format!("qemu-system-{}", self.name())
+    }
+
+    fn machine_args(self) -> &'static [&'static str] {
+        match self {
+            Self::X86_64 => &[
+                "-machine",
+                "q35",
+                "-cpu",
+                "SandyBridge,-tsc-deadline",
+                "-device",
+                "isa-debug-exit",
+            ],
+            Self::Aarch64 => &["-machine", "virt", "-cpu", "cortex-a72", "-semihosting"],
+        }
+    }
+
+    /// The code and the variables templates of the firmware.
+    fn firmware(self) -> [PathBuf; 2] {
+        let edk2 = workspace_root().join("edk2-uefi");
+        match self {
+            Self::X86_64 => [
+                edk2.join("ovmf-x64-4m/OVMF_CODE.fd"),
+                edk2.join("ovmf-x64-4m/OVMF_VARS.fd"),
+            ],
+            Self::Aarch64 => [
+                edk2.join("aarch64/QEMU_EFI-silent-pflash.raw"),
+                edk2.join("aarch64/vars-template-pflash.raw"),
+            ],
+        }
+    }
+
+    fn boot_efi(self) -> &'static str {
+        match self {
+            Self::X86_64 => "bootx64.efi",
+            Self::Aarch64 => "bootaa64.efi",
+        }
+    }
+
+    fn log_device(self) -> &'static str {
+        match self {
+            Self::X86_64 => "com1",
+            Self::Aarch64 => "\"pl011@9000000\"",
+        }
+    }
+
+    /// QEMU exits with `(code << 1) | 1` for `isa-debug-exit`, and with
+    /// the code itself for the semihosting.
+    fn success_code(self) -> i32 {
+        match self {
+            Self::X86_64 => 15,
+            Self::Aarch64 => 0,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .ancestors()
+        .nth(2)
+        .expect("The crate is two levels down the workspace")
+        .to_path_buf()
+}
+
+fn target_dir() -> PathBuf {
+    workspace_root().join("target/qemu-tests")
+}
+
+/// Why the tests cannot run for the architecture, if they cannot.
+pub fn missing_prerequisites(arch: Arch) -> Option<String> {
+    let qemu = arch.qemu();
+    let has_qemu = Command::new(&qemu)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !has_qemu {
+        return Some(format!("no {qemu}"));
+    }
+
+    arch.firmware()
+        .into_iter()
+        .find(|path| !path.exists())
+        .map(|path| {
+            format!(
+                "no firmware {}, is the submodule checked out?",
+                path.display()
+            )
+        })
+}
+
+/// The loader and the kernel built for the architecture.
+pub struct Build {
+    pub loader: PathBuf,
+    pub kernel: PathBuf,
+}
+
+fn cargo_build(args: &[&str]) -> io::Result<()> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let status = Command::new(cargo)
+        .current_dir(workspace_root())
+        .arg("build")
+        .arg("--target-dir")
+        .arg(target_dir())
+        .args(args)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("cargo build {args:?}: {status}")));
+    }
+    Ok(())
+}
+
+/// Builds the debug loader and kernel, as `run.py` does.
+pub fn build(arch: Arch) -> io::Result<Build> {
+    let loader_target = format!("{arch}-unknown-uefi");
+    let kernel_target = format!("{arch}-unknown-linux-gnu");
+    cargo_build(&[
+        "--target",
+        &loader_target,
+        "-p",
+        "boot_loader",
+        "--features",
+        "boot_loader/all_uefi_table_guids",
+    ])?;
+    cargo_build(&[
+        "--target",
+        &kernel_target,
+        "-p",
+        "kernel_start",
+        "--features",
+        "kernel_build",
+    ])?;
+
+    Ok(Build {
+        loader: target_dir()
+            .join(loader_target)
+            .join("debug/boot_loader.efi"),
+        kernel: target_dir().join(kernel_target).join("debug/kernel_start"),
+    })
+}
+
+/// The directory the ESP and the firmware variables of one test are in.
+pub struct Esp {
+    arch: Arch,
+    dir: PathBuf,
+}
+
+impl Esp {
+    /// Lays out the ESP for the test `name`, the loader configuration has
+    /// the logging set up and the `config` lines after that.
+    pub fn new(arch: Arch, name: &str, build: &Build, config: &[&str]) -> io::Result<Self> {
+        let dir = target_dir().join("runs").join(format!("{arch}-{name}"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        let esp = dir.join("esp");
+        fs::create_dir_all(esp.join("efi/boot"))?;
+        fs::copy(&build.loader, esp.join("efi/boot").join(arch.boot_efi()))?;
+        fs::copy(&build.kernel, esp.join("corgos"))?;
+
+        let mut ini = format!(
+            "revision = \"qemu-tests {name}\"\n\
+            log_device = {}\n\
+            log_level = debug\n\
+            gdb_stub = none\n\
+            walk_page_tables = false\n",
+            arch.log_device()
+        );
+        for line in config {
+            ini.push_str(line);
+            ini.push('\n');
+        }
+        fs::write(esp.join(format!("corgos-boot-{arch}.ini")), ini)?;
+
+        // The firmware writes the variables, each test gets its own copy.
+        let [_, vars] = arch.firmware();
+        fs::copy(vars, dir.join("vars.fd"))?;
+
+        Ok(Self { arch, dir })
+    }
+
+    /// Boots QEMU off the ESP, and waits for it to exit. QEMU is killed
+    /// once the time is out.
+    pub fn boot(&self) -> io::Result<Boot> {
+        let arch = self.arch;
+        let [code, _] = arch.firmware();
+        let serial = self.dir.join("serial.log");
+        let mut code_drive = OsString::from("if=pflash,format=raw,readonly=on,file=");
+        code_drive.push(&code);
+        let mut vars_drive = OsString::from("if=pflash,format=raw,file=");
+        vars_drive.push(self.dir.join("vars.fd"));
+        let mut esp_drive = OsString::from("format=raw,file=fat:rw:");
+        esp_drive.push(self.dir.join("esp"));
+        let mut serial_file = OsString::from("file:");
+        serial_file.push(&serial);
+
+        let mut qemu = Command::new(arch.qemu())
+            .args(["-nodefaults", "-nographic", "-no-reboot"])
+            .args(arch.machine_args())
+            .args(["-m", MEMORY, "-smp", CPUS])
+            .arg("-serial")
+            .arg(serial_file)
+            .arg("-drive")
+            .arg(esp_drive)
+            .arg("-drive")
+            .arg(code_drive)
+            .arg("-drive")
+            .arg(vars_drive)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = qemu.try_wait()? {
+                break Some(status);
+            }
+            if start.elapsed() > BOOT_TIMEOUT {
+                qemu.kill()?;
+                qemu.wait()?;
+                break None;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        let log = fs::read(&serial).unwrap_or_default();
+        Ok(Boot {
+            arch,
+            status,
+            serial: String::from_utf8_lossy(&log).into_owned(),
+        })
+    }
+}
+
+/// How the boot has ended.
+pub struct Boot {
+    arch: Arch,
+    /// None if QEMU has been killed after the timeout.
+    pub status: Option<ExitStatus>,
+    pub serial: String,
+}
+
+impl Boot {
+    /// Whether the kernel has asked QEMU to exit with the success code.
+    pub fn succeeded(&self) -> bool {
+        self.status
+            .and_then(|status| status.code())
+            .is_some_and(|code| code == self.arch.success_code())
+    }
+
+    /// Checks that the serial log has the markers in this order, and
+    /// shows the log if it does not.
+    pub fn assert_markers(&self, markers: &[&str]) {
+        let mut rest = self.serial.as_str();
+        for marker in markers {
+            match rest.find(marker) {
+                Some(at) => rest = &rest[at + marker.len()..],
+                None => panic!(
+                    "{}: no '{marker}' in the serial log, in order:\n{}",
+                    self.arch, self.serial
+                ),
+            }
+        }
+    }
+
+    /// Checks that the serial log has none of the markers.
+    pub fn assert_no_markers(&self, markers: &[&str]) {
+        for marker in markers {
+            assert!(
+                !self.serial.contains(marker),
+                "{}: '{marker}' in the serial log:\n{}",
+                self.arch,
+                self.serial
+            );
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use qemu_tests::Arch;
+use qemu_tests::Esp;
+
+/// What the loader and the kernel log on the way, in this order.
+const BOOT_MARKERS: &[&str] = &[
+    "Loading kernel image 'corgos'",
+    "Kernel on ",
+    "Self tests: ",
+    "Kernel initialized, exiting QEMU",
+];
+
+const FAILURE_MARKERS: &[&str] = &["Kernel panic", ": failed, "];
+
+fn boot_with_self_tests(arch: Arch) {
+    if let Some(reason) = qemu_tests::missing_prerequisites(arch) {
+        panic!("Cannot boot {arch}: {reason}");
+    }
+
+    let build = qemu_tests::build(arch).expect("The loader and the kernel build");
+    let esp = Esp::new(
+        arch,
+        "self-tests",
+        &build,
+        &["cmdline = \"self_test qemu_exit\""],
+    )
+    .expect("The ESP is laid out");
+    let boot = esp.boot().expect("QEMU starts");
+
+    boot.assert_markers(BOOT_MARKERS);
+    boot.assert_no_markers(FAILURE_MARKERS);
+    assert!(
+        boot.succeeded(),
+        "{arch}: QEMU has exited with {:?}:\n{}",
+        boot.status,
+        boot.serial
+    );
+}
+
+#[test]
+#[ignore = "needs QEMU and the edk2-uefi submodule, run with --ignored"]
+fn x86_64_boots() {
+    boot_with_self_tests(Arch::X86_64);
+}
+
+#[test]
+#[ignore = "needs QEMU and the edk2-uefi submodule, run with --ignored"]
+fn aarch64_boots() {
+    boot_with_self_tests(Arch::Aarch64);
+}